@@ -0,0 +1,320 @@
+//! Skeletal animation: glTF skins, and the animations that move their joints.
+//!
+//! An [`Animation`] moves nodes by changing their translation, rotation or scale over time, and
+//! blends their meshes' morph targets by changing their weights. Sampling one gives the
+//! [`Pose`] of every node at some moment, from which [`Skeleton::world_matrices`] works out where
+//! every node is in the model, and [`Skin::joint_matrices`] how far each joint has moved the
+//! vertices it carries from where they were bound to it.
+
+use glam::{Mat4, Quat, Vec3};
+
+use crate::transform::Transform;
+
+/// Every node's own transform, relative to its parent, and the weights of its mesh's morph
+/// targets, indexed by glTF node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub transforms: Vec<Transform>,
+    /// Empty for nodes without a mesh, or whose mesh has no morph targets.
+    pub weights: Vec<Vec<f32>>,
+}
+
+/// How the nodes of a model hang together, and how they are posed when nothing moves them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Skeleton {
+    pub rest: Pose,
+    /// Each node's parent, or `None` for the roots.
+    pub parents: Vec<Option<usize>>,
+}
+
+impl Skeleton {
+    /// From each node's transform in `locals`, indexed like the [`rest`](Self::rest) pose's, to
+    /// the model's space, including every parent's transform.
+    ///
+    /// Matrices rather than [`Transform`]s, which can't carry a scale that differs between axes
+    /// through a rotation and so would bend the joints of such a skeleton.
+    pub fn world_matrices(&self, locals: &[Transform]) -> Vec<Mat4> {
+        let mut world = vec![None; locals.len()];
+        for node in 0..locals.len() {
+            self.world_matrix(node, locals, &mut world);
+        }
+        world.into_iter().map(Option::unwrap).collect()
+    }
+
+    /// `node`'s world matrix, working out its parents' first where `world` doesn't have them.
+    fn world_matrix(&self, node: usize, locals: &[Transform], world: &mut [Option<Mat4>]) -> Mat4 {
+        if let Some(matrix) = world[node] {
+            return matrix;
+        }
+        let local = locals[node].matrix();
+        let matrix = match self.parents[node] {
+            Some(parent) => self.world_matrix(parent, locals, world) * local,
+            None => local,
+        };
+        world[node] = Some(matrix);
+        matrix
+    }
+}
+
+/// The joints a skinned mesh's vertices are attached to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skin {
+    /// The node of each joint. A vertex's joint indices index into this.
+    pub joints: Vec<usize>,
+    /// For each joint, from the model's space to the joint's, as it was when the vertices were
+    /// bound to it.
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    /// For each joint, from where a vertex was bound to where the joint carries it, posed by
+    /// the nodes' `world` matrices. The skinned mesh's own node doesn't move it, as glTF says.
+    pub fn joint_matrices<'a>(&'a self, world: &'a [Mat4]) -> impl Iterator<Item = Mat4> + 'a {
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(&joint, inverse_bind)| world[joint] * *inverse_bind)
+    }
+}
+
+/// How a channel's values change between keyframes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Straight from one keyframe to the next; rotations by the shortest arc.
+    Linear,
+    /// Each keyframe's value holds until the next.
+    Step,
+}
+
+/// A channel's value at each keyframe, and which part of its node's transform they are.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    /// The weight of each morph target, at each keyframe.
+    Weights(Vec<Vec<f32>>),
+}
+
+impl Keyframes {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Translation(values) | Self::Scale(values) => values.len(),
+            Self::Rotation(values) => values.len(),
+            Self::Weights(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// One part of one node's transform over time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    /// The glTF node moved.
+    pub node: usize,
+    pub interpolation: Interpolation,
+    /// When each keyframe is, in seconds, in increasing order.
+    pub times: Vec<f32>,
+    /// As many as there are [`times`](Self::times), at least one.
+    pub keyframes: Keyframes,
+}
+
+impl Channel {
+    /// The keyframes either side of `time`, and how far from the first to the second it is.
+    /// Before the first keyframe and after the last, the value is theirs.
+    fn keyframes_at(&self, time: f32) -> (usize, usize, f32) {
+        let next = self.times.partition_point(|&keyframe| keyframe <= time);
+        if next == 0 {
+            return (0, 0, 0.0);
+        }
+        let previous = next - 1;
+        if next == self.times.len() || self.interpolation == Interpolation::Step {
+            return (previous, previous, 0.0);
+        }
+        let (start, end) = (self.times[previous], self.times[next]);
+        (previous, next, (time - start) / (end - start))
+    }
+
+    /// Sets the part of its node's pose the channel moves to its value `time` seconds in.
+    fn apply(&self, time: f32, pose: &mut Pose) {
+        let (from, to, between) = self.keyframes_at(time);
+        let transform = &mut pose.transforms[self.node];
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation = values[from].lerp(values[to], between).to_array();
+            }
+            Keyframes::Rotation(values) => {
+                transform.rotation = values[from].slerp(values[to], between).normalize();
+            }
+            Keyframes::Scale(values) => {
+                transform.scale = values[from].lerp(values[to], between).to_array();
+            }
+            Keyframes::Weights(values) => {
+                pose.weights[self.node] = values[from]
+                    .iter()
+                    .zip(&values[to])
+                    .map(|(from, to)| from + (to - from) * between)
+                    .collect();
+            }
+        }
+    }
+}
+
+/// A glTF animation: channels moving nodes, played together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+}
+
+impl Animation {
+    /// Seconds until the last keyframe of any channel, after which the animation loops.
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max)
+    }
+
+    /// Moves the nodes in `pose` to where the animation has them `time` seconds in, looping
+    /// every [`duration`](Self::duration). What it doesn't animate is left as it is.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        let duration = self.duration();
+        let time = if duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            0.0
+        };
+        for channel in &self.channels {
+            channel.apply(time, pose);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(interpolation: Interpolation, keyframes: Keyframes) -> Channel {
+        Channel {
+            node: 0,
+            interpolation,
+            times: vec![1.0, 2.0, 4.0],
+            keyframes,
+        }
+    }
+
+    fn translations() -> Keyframes {
+        Keyframes::Translation(vec![Vec3::ZERO, Vec3::X, Vec3::new(3.0, 0.0, 0.0)])
+    }
+
+    fn pose(transforms: Vec<Transform>) -> Pose {
+        Pose {
+            weights: vec![Vec::new(); transforms.len()],
+            transforms,
+        }
+    }
+
+    fn translation_at(channel: &Channel, time: f32) -> Vec3 {
+        let mut pose = pose(vec![Transform::IDENTITY]);
+        channel.apply(time, &mut pose);
+        pose.transforms[0].translation.into()
+    }
+
+    #[test]
+    fn linear_channels_interpolate_between_keyframes() {
+        let channel = channel(Interpolation::Linear, translations());
+        assert_eq!(translation_at(&channel, 0.0), Vec3::ZERO);
+        assert_eq!(translation_at(&channel, 1.5), Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(translation_at(&channel, 2.0), Vec3::X);
+        assert_eq!(translation_at(&channel, 3.0), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(translation_at(&channel, 9.0), Vec3::new(3.0, 0.0, 0.0));
+
+        let rotations = Keyframes::Rotation(vec![
+            Quat::IDENTITY,
+            Quat::from_rotation_z(1.0),
+            Quat::from_rotation_z(2.0),
+        ]);
+        let channel = self::channel(Interpolation::Linear, rotations);
+        let mut pose = pose(vec![Transform::IDENTITY]);
+        channel.apply(1.5, &mut pose);
+        assert!(pose.transforms[0]
+            .rotation
+            .abs_diff_eq(Quat::from_rotation_z(0.5), 1e-5));
+
+        let weights = Keyframes::Weights(vec![vec![0.0, 1.0], vec![1.0, 0.0], vec![1.0, 1.0]]);
+        let channel = self::channel(Interpolation::Linear, weights);
+        channel.apply(1.25, &mut pose);
+        assert_eq!(pose.weights[0], [0.25, 0.75]);
+    }
+
+    #[test]
+    fn step_channels_hold_each_keyframe() {
+        let channel = channel(Interpolation::Step, translations());
+        assert_eq!(translation_at(&channel, 0.5), Vec3::ZERO);
+        assert_eq!(translation_at(&channel, 1.9), Vec3::ZERO);
+        assert_eq!(translation_at(&channel, 2.0), Vec3::X);
+        assert_eq!(translation_at(&channel, 3.9), Vec3::X);
+        assert_eq!(translation_at(&channel, 4.0), Vec3::new(3.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn animations_loop_and_leave_other_nodes_alone() {
+        let animation = Animation {
+            name: None,
+            channels: vec![Channel {
+                node: 1,
+                ..channel(Interpolation::Linear, translations())
+            }],
+        };
+        assert_eq!(animation.duration(), 4.0);
+        let still = Transform::from_scale(Vec3::splat(2.0));
+        let mut pose = pose(vec![still; 2]);
+        animation.sample(5.5, &mut pose);
+        assert_eq!(pose.transforms[0], still);
+        assert_eq!(pose.transforms[1].translation, [0.5, 0.0, 0.0]);
+        animation.sample(-2.5, &mut pose);
+        assert_eq!(pose.transforms[1].translation, [0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn joints_move_vertices_from_their_bind_pose() {
+        // A root with a child one unit up, listed child first.
+        let skeleton = Skeleton {
+            rest: pose(vec![
+                Transform::from_translation(Vec3::Y),
+                Transform::from_translation(Vec3::X),
+            ]),
+            parents: vec![Some(1), None],
+        };
+        let skin = Skin {
+            joints: vec![1, 0],
+            inverse_bind_matrices: skeleton
+                .world_matrices(&skeleton.rest.transforms)
+                .iter()
+                .rev()
+                .map(Mat4::inverse)
+                .collect(),
+        };
+        // In the rest pose, nothing moves.
+        let world = skeleton.world_matrices(&skeleton.rest.transforms);
+        assert_eq!(
+            world[0].transform_point3(Vec3::ZERO),
+            Vec3::new(1.0, 1.0, 0.0)
+        );
+        for matrix in skin.joint_matrices(&world) {
+            assert!(matrix.abs_diff_eq(Mat4::IDENTITY, 1e-6));
+        }
+        // Turning the root carries the child's vertices around with it.
+        let mut locals = skeleton.rest.transforms.clone();
+        locals[1].rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let world = skeleton.world_matrices(&locals);
+        let child = skin.joint_matrices(&world).nth(1).unwrap();
+        assert!(child
+            .transform_point3(Vec3::new(1.0, 2.0, 0.0))
+            .abs_diff_eq(Vec3::new(-1.0, 0.0, 0.0), 1e-6));
+    }
+}
@@ -0,0 +1,268 @@
+//! The demo application: owns the event loop and forwards the windows' events to the
+//! [render thread](crate::render_thread), or renders offscreen in headless mode.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use glam::Vec3;
+use vulkano::instance::InstanceExtensions;
+use winit::event::{DeviceEvent, ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoopBuilder;
+use winit::window::{Window, WindowId};
+
+use crate::benchmark::Benchmark;
+use crate::camera::{Camera, FlyCamera, TopDownCamera};
+use crate::context::{create_instance, list_devices, VulkanContext};
+use crate::copy_bench::bench_copy;
+use crate::crash_report;
+use crate::error::RendererError;
+use crate::offscreen::OffscreenTarget;
+use crate::options::Options;
+use crate::render_thread::{RenderEvent, RenderMessage, RenderThread};
+use crate::renderer::DEVICE_EXTENSIONS;
+use crate::safe_mode::{self, Sentinel};
+use crate::scene::{benchmark_nbody, build_scene, FixedTimestep, FrameData};
+use crate::shader_dump;
+use crate::subgroups;
+use crate::window_config::WindowConfig;
+
+/// Key that saves what the window shows to a PNG file in the working directory.
+const SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::P;
+
+/// Size of the image rendered into in headless mode.
+const HEADLESS_EXTENT: [u32; 2] = [1280, 720];
+
+fn initial_camera() -> FlyCamera {
+    FlyCamera::looking_at(Vec3::new(1.5, 1.2, 3.0), Vec3::ZERO)
+}
+
+fn frame_data(camera: &Camera, extent: [u32; 2], time: f32, update_alpha: f32) -> FrameData {
+    FrameData {
+        view: camera.view_matrix(),
+        projection: camera.projection(extent[0] as f32 / extent[1].max(1) as f32),
+        extent,
+        time,
+        update_alpha,
+        ..FrameData::default()
+    }
+}
+
+/// Runs the demo with the given options. Returns in headless mode and for the one-shot modes
+/// (`--list-devices`, `--print-caps`, `--nbody-bench`, `--subgroup-demo` and `--bench-copy`);
+/// windowed mode exits the process when the window is closed. A panic from here on leaves a
+/// crash report behind, see [`crash_report`].
+pub fn run(options: Options) -> Result<(), RendererError> {
+    crash_report::install();
+    if let Some(dir) = &options.dump_shaders {
+        shader_dump::enable(dir.clone());
+    }
+    if options.list_devices {
+        let instance = create_instance(
+            InstanceExtensions::empty(),
+            InstanceExtensions::empty(),
+            options.force_api_version,
+            None,
+        )?;
+        let devices = list_devices(&instance, &DEVICE_EXTENSIONS)?;
+        if devices.is_empty() {
+            println!("No Vulkan devices found");
+        }
+        for device in devices {
+            println!("{device}");
+        }
+        Ok(())
+    } else if options.print_caps {
+        let ctx = VulkanContext::headless(&options.device, options.force_api_version)?;
+        println!("{}", ctx.caps);
+        Ok(())
+    } else if options.nbody_bench {
+        let ctx = VulkanContext::headless(&options.device, options.force_api_version)?;
+        println!("{}", benchmark_nbody(&ctx, options.bodies)?);
+        Ok(())
+    } else if options.subgroup_demo {
+        let ctx = VulkanContext::headless(&options.device, options.force_api_version)?;
+        println!("{}", subgroups::reduce(&ctx)?);
+        Ok(())
+    } else if let Some(bytes) = options.bench_copy {
+        let ctx = VulkanContext::headless(&options.device, options.force_api_version)?;
+        println!("{}", bench_copy(&ctx, bytes)?);
+        Ok(())
+    } else {
+        // Rendering is where a driver can crash on what we ask of it.
+        let mut options = options;
+        let reset = options.reset_safe_mode;
+        let mut sentinel = safe_mode::start(&mut options, reset);
+        if options.headless {
+            let result = run_headless(&options, &mut sentinel);
+            // Returning at all, even with an error, means nothing crashed or hung.
+            if let Some(sentinel) = sentinel {
+                sentinel.disarm();
+            }
+            result
+        } else {
+            run_windowed(&options, sentinel)
+        }
+    }
+}
+
+/// Renders frames into an offscreen image. Without `--frames` a single frame is rendered.
+/// `sentinel` is disarmed once the first one has been; [`run`] disarms it if it's still armed
+/// when this returns.
+///
+/// There's no window to scale to, so the image is simply created at the logical resolution.
+fn run_headless(options: &Options, sentinel: &mut Option<Sentinel>) -> Result<(), RendererError> {
+    let ctx = VulkanContext::headless(&options.device, options.force_api_version)?;
+    let target = OffscreenTarget::new(&ctx, options.render_scale.logical_extent(HEADLESS_EXTENT))?
+        .with_clear_color(options.clear_color);
+    let mut scene = build_scene(
+        options.scene,
+        options.model.as_deref(),
+        &options.scene_options(),
+        &ctx,
+        target.subpass(),
+    )?;
+    let camera = Camera::Fly(initial_camera());
+    let start = Instant::now();
+    let mut timestep = FixedTimestep::new(options.update_rate);
+
+    let mut benchmark = Benchmark::new(options.frames.unwrap_or(1));
+    loop {
+        crash_report::frame_started();
+        let time = start.elapsed().as_secs_f32();
+        let update_alpha = timestep.advance(time, |dt| scene.update(dt));
+        let frame = frame_data(&camera, target.extent(), time, update_alpha);
+        target.draw(&ctx, scene.as_mut(), &frame)?;
+        if let Some(sentinel) = sentinel.take() {
+            sentinel.disarm();
+        }
+        if benchmark.frame_rendered() {
+            break;
+        }
+    }
+
+    if options.frames.is_some() {
+        println!("{}", benchmark.report());
+    }
+    if options.mem_stats {
+        let report = ctx.memory_tracker.report();
+        report.warn_if_near_budget();
+        println!("{report}");
+    }
+    Ok(())
+}
+
+/// Opens the windows and hands them to a [`RenderThread`], then forwards their events to it
+/// until it has finished. The windows outlive the renderer, so they are destroyed only once the
+/// GPU is done with them. The render thread disarms `sentinel` once it has drawn a frame, or
+/// when it ends without having drawn one.
+fn run_windowed(options: &Options, sentinel: Option<Sentinel>) -> ! {
+    let event_loop = EventLoopBuilder::<RenderEvent>::with_user_event().build();
+    let mut config = WindowConfig::new()
+        .transparent(options.transparent)
+        .cursor(options.cursor);
+    if let Some(icon) = &options.window_icon {
+        config = config.icon(icon);
+    }
+    let window = Arc::new(config.build("hi-vulkanos", &event_loop).unwrap());
+    let mut windows = vec![(window, Camera::Fly(initial_camera()))];
+    if options.second_window {
+        let window = Arc::new(config.build("hi-vulkanos - top down", &event_loop).unwrap());
+        windows.push((window, Camera::TopDown(TopDownCamera::default())));
+    }
+
+    // The renderer holds on to the windows too, but until it has let go of one, this keeps the
+    // last reference from being dropped on the render thread.
+    let mut open_windows: HashMap<WindowId, Arc<Window>> = windows
+        .iter()
+        .map(|(window, _)| (window.id(), window.clone()))
+        .collect();
+    let mut render_thread = Some(RenderThread::spawn(
+        windows,
+        options.clone(),
+        sentinel,
+        event_loop.create_proxy(),
+    ));
+
+    event_loop.run(move |event, _, control_flow| {
+        // Frames don't wait for events any more, so there's no need to keep polling.
+        control_flow.set_wait();
+        let Some(thread) = &render_thread else {
+            return;
+        };
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                window_id,
+            } => {
+                // Closing the last window ends the app.
+                if open_windows.len() > 1 {
+                    thread.send(RenderMessage::CloseWindow(window_id));
+                } else {
+                    thread.send(RenderMessage::Shutdown);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                window_id,
+            } => thread.resize(window_id),
+            // Moving to a monitor with a different scale factor changes the window's size in
+            // physical pixels, and not every platform follows up with a `Resized`. Taking the
+            // suggested size keeps the window the same size in logical pixels.
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                window_id,
+            } => {
+                log::info!("Scale factor changed to {scale_factor}");
+                thread.resize(window_id);
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(SCREENSHOT_KEY),
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    },
+                window_id,
+            } => thread.send(RenderMessage::Screenshot(window_id)),
+            Event::WindowEvent { event, window_id } => {
+                let input = matches!(
+                    event,
+                    WindowEvent::Focused(_)
+                        | WindowEvent::KeyboardInput { .. }
+                        | WindowEvent::CursorMoved { .. }
+                        | WindowEvent::CursorLeft { .. }
+                        | WindowEvent::MouseInput { .. }
+                        | WindowEvent::MouseWheel { .. }
+                );
+                if let Some(event) = event.to_static().filter(|_| input) {
+                    thread.send(RenderMessage::WindowEvent(window_id, event));
+                }
+            }
+            // Raw device motion rather than `CursorMoved`, so looking around keeps working when
+            // the cursor is locked in place.
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => thread.send(RenderMessage::MouseMotion(delta)),
+            Event::UserEvent(RenderEvent::Stats { window, title }) => {
+                if let Some(window) = open_windows.get(&window) {
+                    window.set_title(&title);
+                }
+            }
+            Event::UserEvent(RenderEvent::WindowClosed(id)) => {
+                open_windows.remove(&id);
+            }
+            Event::UserEvent(RenderEvent::Exited) => {
+                render_thread.take().unwrap().join();
+                open_windows.clear();
+                control_flow.set_exit();
+            }
+            _ => (),
+        }
+    });
+}
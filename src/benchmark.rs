@@ -0,0 +1,185 @@
+//! Benchmark mode: render a fixed number of frames, then report how long they took and, where
+//! present waits are supported, how long they took to reach the screen.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Counts down the frames left to render and records how long each one took.
+pub struct Benchmark {
+    remaining: u32,
+    start: Instant,
+    last_frame: Instant,
+    frame_times: Vec<Duration>,
+    present_latencies: Vec<Duration>,
+}
+
+impl Benchmark {
+    /// Starts timing a run of `frames` frames.
+    pub fn new(frames: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            remaining: frames,
+            start: now,
+            last_frame: now,
+            frame_times: Vec::with_capacity(frames as usize),
+            present_latencies: Vec::new(),
+        }
+    }
+
+    /// Records that a frame finished. Returns `true` once every requested frame has been
+    /// rendered.
+    pub fn frame_rendered(&mut self) -> bool {
+        let now = Instant::now();
+        self.frame_times.push(now - self.last_frame);
+        self.last_frame = now;
+        self.remaining = self.remaining.saturating_sub(1);
+        self.remaining == 0
+    }
+
+    /// Records how long presents took from being queued to reaching the screen.
+    pub fn presents_seen(&mut self, latencies: impl IntoIterator<Item = Duration>) {
+        self.present_latencies.extend(latencies);
+    }
+
+    /// Summarises the frames recorded so far.
+    pub fn report(&self) -> BenchmarkReport {
+        BenchmarkReport::new(self.last_frame - self.start, &self.frame_times)
+            .with_present_latencies(&self.present_latencies)
+    }
+}
+
+/// Aggregate frame timings of a benchmark run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BenchmarkReport {
+    pub frames: usize,
+    pub total: Duration,
+    pub average: Duration,
+    pub median: Duration,
+    pub p99: Duration,
+    /// Median and 99th percentile of the time from queueing a present to it reaching the
+    /// screen. `None` if no present was seen there, e.g. without present waits.
+    pub present_latency: Option<(Duration, Duration)>,
+}
+
+impl BenchmarkReport {
+    pub fn new(total: Duration, frame_times: &[Duration]) -> Self {
+        let mut sorted = frame_times.to_vec();
+        sorted.sort_unstable();
+
+        let average = if sorted.is_empty() {
+            Duration::ZERO
+        } else {
+            sorted.iter().sum::<Duration>() / sorted.len() as u32
+        };
+
+        Self {
+            frames: sorted.len(),
+            total,
+            average,
+            median: percentile(&sorted, 50.0),
+            p99: percentile(&sorted, 99.0),
+            present_latency: None,
+        }
+    }
+
+    /// Adds the present latencies measured during the run.
+    pub fn with_present_latencies(mut self, latencies: &[Duration]) -> Self {
+        let mut sorted = latencies.to_vec();
+        sorted.sort_unstable();
+        self.present_latency =
+            (!sorted.is_empty()).then(|| (percentile(&sorted, 50.0), percentile(&sorted, 99.0)));
+        self
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(
+            f,
+            "Rendered {} frames in {:.3} s",
+            self.frames,
+            self.total.as_secs_f64()
+        )?;
+        writeln!(f, "  average: {:.3} ms", ms(self.average))?;
+        writeln!(f, "  median:  {:.3} ms", ms(self.median))?;
+        write!(f, "  p99:     {:.3} ms", ms(self.p99))?;
+        if let Some((median, p99)) = self.present_latency {
+            write!(
+                f,
+                "\n  to screen: {:.3} ms median, {:.3} ms p99",
+                ms(median),
+                ms(p99)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Nearest-rank percentile of an already sorted slice.
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&ms| Duration::from_millis(ms)).collect()
+    }
+
+    #[test]
+    fn report_of_unsorted_frames() {
+        let report = BenchmarkReport::new(Duration::from_millis(15), &millis(&[5, 1, 4, 2, 3]));
+        assert_eq!(report.frames, 5);
+        assert_eq!(report.average, Duration::from_millis(3));
+        assert_eq!(report.median, Duration::from_millis(3));
+        assert_eq!(report.p99, Duration::from_millis(5));
+    }
+
+    #[test]
+    fn p99_picks_out_the_slow_tail() {
+        let mut frames = millis(&[10; 99]);
+        frames.push(Duration::from_millis(100));
+        frames.push(Duration::from_millis(200));
+        let report = BenchmarkReport::new(Duration::ZERO, &frames);
+        assert_eq!(report.median, Duration::from_millis(10));
+        assert_eq!(report.p99, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn empty_report_is_all_zero() {
+        let report = BenchmarkReport::new(Duration::ZERO, &[]);
+        assert_eq!(report.frames, 0);
+        assert_eq!(report.median, Duration::ZERO);
+        assert_eq!(report.p99, Duration::ZERO);
+    }
+
+    #[test]
+    fn present_latencies_are_reported_when_seen() {
+        let report = BenchmarkReport::new(Duration::ZERO, &millis(&[16]));
+        assert_eq!(report.present_latency, None);
+        assert!(!report.to_string().contains("to screen"));
+
+        let report = report.with_present_latencies(&millis(&[30, 20, 40]));
+        assert_eq!(
+            report.present_latency,
+            Some((Duration::from_millis(30), Duration::from_millis(40)))
+        );
+        assert!(report.to_string().contains("to screen: 30.000 ms median"));
+    }
+
+    #[test]
+    fn benchmark_finishes_after_requested_frames() {
+        let mut benchmark = Benchmark::new(3);
+        assert!(!benchmark.frame_rendered());
+        assert!(!benchmark.frame_rendered());
+        assert!(benchmark.frame_rendered());
+        assert_eq!(benchmark.report().frames, 3);
+    }
+}
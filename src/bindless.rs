@@ -0,0 +1,214 @@
+//! Bindless textures (`VK_EXT_descriptor_indexing`): every texture goes into one big array of
+//! sampled images, and shaders pick theirs by index, so switching textures between draws is a
+//! push constant rather than a descriptor set bind.
+//!
+//! Without descriptor indexing, a [`TextureTable`] gives each texture a descriptor set of its
+//! own instead, to be bound before drawing with it. Either way textures are referred to by the
+//! ID [`TextureTable::register_texture`] returns, and each is sampled with its own sampler.
+
+use std::sync::Arc;
+
+use vulkano::descriptor_set::allocator::{
+    StandardDescriptorSetAllocator, StandardDescriptorSetAllocatorCreateInfo,
+};
+use vulkano::descriptor_set::layout::{
+    DescriptorBindingFlags, DescriptorSetLayout, DescriptorSetLayoutCreateFlags,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+
+use crate::caps::DeviceLimits;
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::texture::Texture;
+
+/// The most textures a bindless array holds, on devices that allow more.
+pub const MAX_BINDLESS_TEXTURES: u32 = 1024;
+
+/// How many textures a bindless array can hold on a device with `limits`.
+pub fn bindless_capacity(limits: &DeviceLimits) -> u32 {
+    MAX_BINDLESS_TEXTURES.min(limits.max_per_stage_descriptor_update_after_bind_samplers)
+}
+
+/// Turns `binding` of set `set` in a layout derived from shaders into a bindless array of up to
+/// `capacity` textures. The shaders have to declare it as a runtime-sized array.
+///
+/// The array is only as long as the descriptor set it is allocated in says, need not have every
+/// element written and can be written while bound.
+///
+/// # Panics
+///
+/// If the layout has no such binding.
+pub fn make_bindless(
+    layout: &mut PipelineDescriptorSetLayoutCreateInfo,
+    set: usize,
+    binding: u32,
+    capacity: u32,
+) {
+    let set_layout = &mut layout.set_layouts[set];
+    set_layout.flags |= DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL;
+    let binding = set_layout.bindings.get_mut(&binding).unwrap();
+    binding.binding_flags |= DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+        | DescriptorBindingFlags::PARTIALLY_BOUND
+        | DescriptorBindingFlags::UPDATE_AFTER_BIND;
+    binding.descriptor_count = capacity;
+}
+
+enum Sets {
+    /// One set holding every texture, rebuilt as they are registered. Needs its own allocator,
+    /// since update-after-bind sets have to come from pools created for them.
+    Bindless {
+        allocator: Arc<StandardDescriptorSetAllocator>,
+        set: Option<Arc<PersistentDescriptorSet>>,
+    },
+    /// A set per texture, in ID order.
+    PerTexture {
+        allocator: Arc<StandardDescriptorSetAllocator>,
+        sets: Vec<Arc<PersistentDescriptorSet>>,
+    },
+}
+
+/// The textures a pipeline samples, by ID.
+///
+/// Whether it is bindless follows from the descriptor set layout: binding 0 must be either a
+/// bindless array (see [`make_bindless`]) or a single combined image sampler.
+pub struct TextureTable {
+    layout: Arc<DescriptorSetLayout>,
+    /// Each texture's view and sampler, in ID order.
+    textures: Vec<(Arc<ImageView>, Arc<Sampler>)>,
+    sets: Sets,
+}
+
+impl TextureTable {
+    pub fn new(ctx: &VulkanContext, layout: Arc<DescriptorSetLayout>) -> Self {
+        let sets = if layout.variable_descriptor_count() > 0 {
+            Sets::Bindless {
+                allocator: Arc::new(StandardDescriptorSetAllocator::new(
+                    ctx.device.clone(),
+                    StandardDescriptorSetAllocatorCreateInfo {
+                        update_after_bind: true,
+                        ..Default::default()
+                    },
+                )),
+                set: None,
+            }
+        } else {
+            Sets::PerTexture {
+                allocator: ctx.descriptor_set_allocator.clone(),
+                sets: Vec::new(),
+            }
+        };
+        Self {
+            layout,
+            textures: Vec::new(),
+            sets,
+        }
+    }
+
+    /// Whether all textures are in one descriptor set, to be indexed by ID in the shader.
+    pub fn is_bindless(&self) -> bool {
+        matches!(self.sets, Sets::Bindless { .. })
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Adds a texture and returns its ID, which count up from zero.
+    ///
+    /// vulkano can't write to a descriptor set once it has been created, so registering a
+    /// texture in bindless mode replaces the set with a bigger one; frames already recorded keep
+    /// the old set alive until they are done with it.
+    pub fn register_texture(&mut self, texture: &Texture) -> Result<u32, RendererError> {
+        let texture = (texture.view.clone(), texture.sampler.clone());
+        let id = self.textures.len() as u32;
+        match &mut self.sets {
+            Sets::Bindless { allocator, set } => {
+                let capacity = self.layout.variable_descriptor_count();
+                if id == capacity {
+                    return Err(RendererError::InvalidTexture(format!(
+                        "the bindless texture array is full at {capacity} textures"
+                    )));
+                }
+                let textures = self.textures.iter().chain([&texture]).cloned();
+                *set = Some(PersistentDescriptorSet::new_variable(
+                    allocator.as_ref(),
+                    self.layout.clone(),
+                    id + 1,
+                    [WriteDescriptorSet::image_view_sampler_array(0, 0, textures)],
+                    [],
+                )?);
+            }
+            Sets::PerTexture { allocator, sets } => sets.push(PersistentDescriptorSet::new(
+                allocator.as_ref(),
+                self.layout.clone(),
+                [WriteDescriptorSet::image_view_sampler(
+                    0,
+                    texture.0.clone(),
+                    texture.1.clone(),
+                )],
+                [],
+            )?),
+        }
+        self.textures.push(texture);
+        Ok(id)
+    }
+
+    /// The set to bind for drawing with texture `id`: the same one for every texture when
+    /// bindless. `None` if there is no such texture.
+    pub fn descriptor_set(&self, id: u32) -> Option<Arc<PersistentDescriptorSet>> {
+        match &self.sets {
+            Sets::Bindless { set, .. } if id < self.textures.len() as u32 => set.clone(),
+            Sets::Bindless { .. } => None,
+            Sets::PerTexture { sets, .. } => sets.get(id as usize).cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use vulkano::descriptor_set::layout::{
+        DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType,
+    };
+
+    use vulkano::pipeline::layout::PipelineLayoutCreateFlags;
+
+    use super::*;
+
+    #[test]
+    fn bindless_bindings_are_variable_sized_and_updatable_after_bind() {
+        let mut layout = PipelineDescriptorSetLayoutCreateInfo {
+            set_layouts: vec![DescriptorSetLayoutCreateInfo {
+                bindings: BTreeMap::from([(
+                    0,
+                    DescriptorSetLayoutBinding::descriptor_type(
+                        DescriptorType::CombinedImageSampler,
+                    ),
+                )]),
+                ..Default::default()
+            }],
+            push_constant_ranges: Vec::new(),
+            flags: PipelineLayoutCreateFlags::empty(),
+        };
+        make_bindless(&mut layout, 0, 0, 64);
+        let set_layout = &layout.set_layouts[0];
+        assert!(set_layout
+            .flags
+            .contains(DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL));
+        let binding = &set_layout.bindings[&0];
+        assert_eq!(binding.descriptor_count, 64);
+        assert!(binding.binding_flags.contains(
+            DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+                | DescriptorBindingFlags::PARTIALLY_BOUND
+                | DescriptorBindingFlags::UPDATE_AFTER_BIND
+        ));
+    }
+}
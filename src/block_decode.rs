@@ -0,0 +1,760 @@
+//! CPU decoders for the block-compressed formats in [`crate::compressed_texture`], for devices
+//! that can't sample them.
+//!
+//! Every format stores 4x4 texel blocks. Each decoder turns one block into its 16 RGBA8 texels
+//! in row-major order. Interpolated values are rounded, so they can differ from what a GPU
+//! decodes by one step; that's fine for a fallback.
+
+/// The texels of one 4x4 block, row by row.
+pub type Block = [[u8; 4]; 16];
+
+/// BC1 (DXT1): two RGB565 endpoints and 2-bit indices. Punch-through alpha is supported.
+pub fn decode_bc1(block: &[u8; 8]) -> Block {
+    decode_bc1_colors(block, true)
+}
+
+/// BC3 (DXT5): a BC4-style alpha block followed by a BC1 color block.
+pub fn decode_bc3(block: &[u8; 16]) -> Block {
+    let alpha = decode_bc4_alpha(block[..8].try_into().unwrap());
+    let mut texels = decode_bc1_colors(block[8..].try_into().unwrap(), false);
+    for (texel, alpha) in texels.iter_mut().zip(alpha) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let r = (color >> 11) as u8 & 0x1f;
+    let g = (color >> 5) as u8 & 0x3f;
+    let b = color as u8 & 0x1f;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+}
+
+/// `(a * weight_a + b * weight_b) / (weight_a + weight_b)`, per channel and rounded.
+fn mix(a: [u8; 4], b: [u8; 4], weight_a: u32, weight_b: u32) -> [u8; 4] {
+    let total = weight_a + weight_b;
+    std::array::from_fn(|i| {
+        ((a[i] as u32 * weight_a + b[i] as u32 * weight_b + total / 2) / total) as u8
+    })
+}
+
+/// The color half of BC1 to BC3. Only standalone BC1 blocks can use the three-color mode, which
+/// makes the last index transparent black.
+fn decode_bc1_colors(block: &[u8; 8], allow_transparent: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+    let palette = if c0 > c1 || !allow_transparent {
+        [e0, e1, mix(e0, e1, 2, 1), mix(e0, e1, 1, 2)]
+    } else {
+        [e0, e1, mix(e0, e1, 1, 1), [0; 4]]
+    };
+
+    let indices = u32::from_le_bytes(block[4..].try_into().unwrap());
+    std::array::from_fn(|i| palette[(indices >> (2 * i)) as usize & 3])
+}
+
+/// Two 8-bit endpoints and 3-bit indices into six or eight values between them.
+fn decode_bc4_alpha(block: &[u8; 8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i as usize + 1] = ((7 - i) * a0 + i * a1 + 3) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i as usize + 1] = ((5 - i) * a0 + i * a1 + 2) / 5;
+        }
+    }
+
+    let mut bytes = [0; 8];
+    bytes[..6].copy_from_slice(&block[2..]);
+    let indices = u64::from_le_bytes(bytes);
+    std::array::from_fn(|i| palette[(indices >> (3 * i)) as usize & 7] as u8)
+}
+
+/// Reads a BC7 block's fields, least significant bit first.
+struct BitReader {
+    bits: u128,
+}
+
+impl BitReader {
+    fn read(&mut self, count: u32) -> u8 {
+        let value = (self.bits & ((1 << count) - 1)) as u8;
+        self.bits >>= count;
+        value
+    }
+}
+
+/// How a BC7 mode lays out its block.
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    /// One p-bit (an extra low bit shared by all channels) per endpoint.
+    endpoint_p_bits: bool,
+    /// One p-bit per subset, shared by both its endpoints.
+    shared_p_bits: bool,
+    index_bits: u32,
+    /// Modes 4 and 5 have a second set of indices, for alpha or (with the index selection bit
+    /// set) color.
+    secondary_index_bits: u32,
+}
+
+const fn bc7_mode(fields: [u32; 10]) -> Bc7Mode {
+    Bc7Mode {
+        subsets: fields[0] as usize,
+        partition_bits: fields[1],
+        rotation_bits: fields[2],
+        index_selection_bits: fields[3],
+        color_bits: fields[4],
+        alpha_bits: fields[5],
+        endpoint_p_bits: fields[6] == 1,
+        shared_p_bits: fields[7] == 1,
+        index_bits: fields[8],
+        secondary_index_bits: fields[9],
+    }
+}
+
+/// The table from the BC7 specification, one row per mode.
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode([3, 4, 0, 0, 4, 0, 1, 0, 3, 0]),
+    bc7_mode([2, 6, 0, 0, 6, 0, 0, 1, 3, 0]),
+    bc7_mode([3, 6, 0, 0, 5, 0, 0, 0, 2, 0]),
+    bc7_mode([2, 6, 0, 0, 7, 0, 1, 0, 2, 0]),
+    bc7_mode([1, 0, 2, 1, 5, 6, 0, 0, 2, 3]),
+    bc7_mode([1, 0, 2, 0, 7, 8, 0, 0, 2, 2]),
+    bc7_mode([1, 0, 0, 0, 7, 7, 1, 0, 4, 0]),
+    bc7_mode([2, 6, 0, 0, 5, 5, 1, 0, 2, 0]),
+];
+
+const BC7_WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// BC7: up to three subsets with their own endpoint pairs, in one of eight modes.
+pub fn decode_bc7(block: &[u8; 16]) -> Block {
+    // The mode is the number of zero bits before the first one. A block without any is reserved
+    // and decodes to transparent black.
+    if block[0] == 0 {
+        return [[0; 4]; 16];
+    }
+    let mode_index = block[0].trailing_zeros();
+    let mode = &BC7_MODES[mode_index as usize];
+    let mut bits = BitReader {
+        bits: u128::from_le_bytes(*block) >> (mode_index + 1),
+    };
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u8; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    if mode.alpha_bits > 0 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[3] = bits.read(mode.alpha_bits);
+        }
+    }
+
+    let mut p_bits = [0u8; 6];
+    if mode.endpoint_p_bits {
+        for p_bit in &mut p_bits[..endpoint_count] {
+            *p_bit = bits.read(1);
+        }
+    } else if mode.shared_p_bits {
+        for pair in p_bits[..endpoint_count].chunks_mut(2) {
+            pair.fill(bits.read(1));
+        }
+    }
+    let has_p_bits = mode.endpoint_p_bits || mode.shared_p_bits;
+    for (endpoint, p_bit) in endpoints[..endpoint_count].iter_mut().zip(p_bits) {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            let width = if channel == 3 {
+                mode.alpha_bits
+            } else {
+                mode.color_bits
+            };
+            *value = match width {
+                0 => 255,
+                _ if has_p_bits => expand_bits((*value as u32) << 1 | p_bit as u32, width + 1),
+                _ => expand_bits(*value as u32, width),
+            };
+        }
+    }
+
+    let subset_of = |texel: usize| match mode.subsets {
+        1 => 0,
+        2 => BC7_PARTITIONS_2[partition][texel] as usize,
+        _ => BC7_PARTITIONS_3[partition][texel] as usize,
+    };
+    // The first index of each subset (its anchor) has its top bit left out, as it's always 0.
+    let is_anchor = |texel: usize| {
+        texel == 0
+            || match mode.subsets {
+                1 => false,
+                2 => texel == BC7_ANCHORS_2[partition] as usize,
+                _ => {
+                    texel == BC7_ANCHORS_3_SECOND[partition] as usize
+                        || texel == BC7_ANCHORS_3_THIRD[partition] as usize
+                }
+            }
+    };
+
+    let mut indices = [0u8; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        *index = bits.read(mode.index_bits - is_anchor(texel) as u32);
+    }
+    let mut secondary_indices = [0u8; 16];
+    if mode.secondary_index_bits > 0 {
+        for (texel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = bits.read(mode.secondary_index_bits - (texel == 0) as u32);
+        }
+    }
+
+    std::array::from_fn(|texel| {
+        let subset = subset_of(texel);
+        let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+        let primary = (indices[texel], mode.index_bits);
+        let secondary = (secondary_indices[texel], mode.secondary_index_bits);
+        let ((color_index, color_bits), (alpha_index, alpha_bits)) =
+            match (mode.secondary_index_bits, index_selection) {
+                (0, _) => (primary, primary),
+                (_, 0) => (primary, secondary),
+                _ => (secondary, primary),
+            };
+
+        let color_weight = bc7_weight(color_bits, color_index);
+        let alpha_weight = bc7_weight(alpha_bits, alpha_index);
+        let mut texel: [u8; 4] = std::array::from_fn(|channel| {
+            let weight = if channel == 3 {
+                alpha_weight
+            } else {
+                color_weight
+            };
+            (((64 - weight) * e0[channel] as u32 + weight * e1[channel] as u32 + 32) >> 6) as u8
+        });
+        // Modes 4 and 5 can store alpha in one of the color channels instead, to give it the
+        // better precision.
+        if rotation > 0 {
+            texel.swap(3, rotation as usize - 1);
+        }
+        texel
+    })
+}
+
+/// Scales a `width`-bit value up to 8 bits by repeating its top bits below it.
+fn expand_bits(value: u32, width: u32) -> u8 {
+    (value << (8 - width) | value >> (2 * width - 8)) as u8
+}
+
+fn bc7_weight(index_bits: u32, index: u8) -> u32 {
+    match index_bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    }
+}
+
+/// The ETC1/ETC2 intensity modifier tables, as (small, large) pairs.
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// The distances between paint colors in ETC2's T and H modes.
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// ETC2 RGB8, including the T, H and planar modes it adds to ETC1.
+pub fn decode_etc2_rgb(block: &[u8; 8]) -> Block {
+    let bits = u64::from_be_bytes(*block);
+    // The `width` bits ending at bit `high`, counting from the least significant one.
+    let field = |high: u32, width: u32| ((bits >> (high + 1 - width)) & ((1 << width) - 1)) as i32;
+    // The 2-bit texel indices are split over two 16-bit planes, and stored column by column.
+    let selector = |texel: usize| {
+        let j = (texel % 4) * 4 + texel / 4;
+        ((bits >> (16 + j)) & 1) << 1 | (bits >> j) & 1
+    };
+
+    let differential = field(33, 1) == 1;
+    let colors = if differential {
+        let base = [field(63, 5), field(55, 5), field(47, 5)];
+        let delta = [field(58, 3), field(50, 3), field(42, 3)].map(|d| (d << 29) >> 29);
+        let second = [0, 1, 2].map(|i| base[i] + delta[i]);
+        // An overflowing second color selects one of the modes ETC2 added.
+        if !(0..32).contains(&second[0]) {
+            return etc2_t_mode(field, selector);
+        } else if !(0..32).contains(&second[1]) {
+            return etc2_h_mode(field, selector);
+        } else if !(0..32).contains(&second[2]) {
+            return etc2_planar_mode(field);
+        }
+        [
+            base.map(|c| expand_bits(c as u32, 5) as i32),
+            second.map(|c| expand_bits(c as u32, 5) as i32),
+        ]
+    } else {
+        [
+            [field(63, 4), field(55, 4), field(47, 4)].map(|c| c * 17),
+            [field(59, 4), field(51, 4), field(43, 4)].map(|c| c * 17),
+        ]
+    };
+
+    // Two 2x4 subblocks side by side, or 4x2 ones on top of each other if flipped.
+    let flipped = field(32, 1) == 1;
+    let tables = [field(39, 3), field(36, 3)];
+    std::array::from_fn(|texel| {
+        let (x, y) = (texel % 4, texel / 4);
+        let subblock = if flipped { y >= 2 } else { x >= 2 } as usize;
+        let [small, large] = ETC_MODIFIERS[tables[subblock] as usize];
+        let modifier = [small, large, -small, -large][selector(texel) as usize];
+        let [r, g, b] = colors[subblock].map(|c| clamp_u8(c + modifier));
+        [r, g, b, 255]
+    })
+}
+
+/// Paint colors are the first base color and the second one shifted both ways.
+fn etc2_t_mode(field: impl Fn(u32, u32) -> i32, selector: impl Fn(usize) -> u64) -> Block {
+    let first = [field(60, 2) << 2 | field(57, 2), field(55, 4), field(51, 4)].map(|c| c * 17);
+    let second = [field(47, 4), field(43, 4), field(39, 4)].map(|c| c * 17);
+    let distance = ETC_DISTANCES[(field(35, 2) << 1 | field(32, 1)) as usize];
+    let paint = [
+        first,
+        second.map(|c| c + distance),
+        second,
+        second.map(|c| c - distance),
+    ];
+    etc2_paint(paint, selector)
+}
+
+/// Paint colors are both base colors shifted both ways.
+fn etc2_h_mode(field: impl Fn(u32, u32) -> i32, selector: impl Fn(usize) -> u64) -> Block {
+    let first = [
+        field(62, 4),
+        field(58, 3) << 1 | field(52, 1),
+        field(51, 1) << 3 | field(49, 3),
+    ];
+    let second = [field(46, 4), field(42, 4), field(38, 4)];
+    // The last bit of the distance index is implied by the order of the base colors.
+    let packed = |c: [i32; 3]| c[0] << 8 | c[1] << 4 | c[2];
+    let order = (packed(first) >= packed(second)) as i32;
+    let distance = ETC_DISTANCES[(field(34, 1) << 2 | field(32, 1) << 1 | order) as usize];
+    let (first, second) = (first.map(|c| c * 17), second.map(|c| c * 17));
+    let paint = [
+        first.map(|c| c + distance),
+        first.map(|c| c - distance),
+        second.map(|c| c + distance),
+        second.map(|c| c - distance),
+    ];
+    etc2_paint(paint, selector)
+}
+
+fn etc2_paint(paint: [[i32; 3]; 4], selector: impl Fn(usize) -> u64) -> Block {
+    std::array::from_fn(|texel| {
+        let [r, g, b] = paint[selector(texel) as usize].map(clamp_u8);
+        [r, g, b, 255]
+    })
+}
+
+/// A smooth gradient from three colors: at the origin, the right edge and the bottom edge.
+fn etc2_planar_mode(field: impl Fn(u32, u32) -> i32) -> Block {
+    let origin = [
+        expand_bits(field(62, 6) as u32, 6),
+        expand_bits((field(56, 1) << 6 | field(54, 6)) as u32, 7),
+        expand_bits(
+            (field(48, 1) << 5 | field(44, 2) << 3 | field(41, 3)) as u32,
+            6,
+        ),
+    ];
+    let horizontal = [
+        expand_bits((field(38, 5) << 1 | field(32, 1)) as u32, 6),
+        expand_bits(field(31, 7) as u32, 7),
+        expand_bits(field(24, 6) as u32, 6),
+    ];
+    let vertical = [
+        expand_bits(field(18, 6) as u32, 6),
+        expand_bits(field(12, 7) as u32, 7),
+        expand_bits(field(5, 6) as u32, 6),
+    ];
+    std::array::from_fn(|texel| {
+        let (x, y) = ((texel % 4) as i32, (texel / 4) as i32);
+        let [r, g, b] = [0, 1, 2].map(|i| {
+            let (o, h, v) = (origin[i] as i32, horizontal[i] as i32, vertical[i] as i32);
+            clamp_u8((x * (h - o) + y * (v - o) + 4 * o + 2) >> 2)
+        });
+        [r, g, b, 255]
+    })
+}
+
+/// The EAC alpha modifier tables.
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// ETC2 RGBA8: an EAC alpha block followed by an ETC2 RGB block.
+pub fn decode_etc2_rgba(block: &[u8; 16]) -> Block {
+    let alpha = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = (alpha >> 56) as i32;
+    let multiplier = (alpha >> 52) as i32 & 0xf;
+    let modifiers = EAC_MODIFIERS[(alpha >> 48) as usize & 0xf];
+
+    let mut texels = decode_etc2_rgb(block[8..].try_into().unwrap());
+    for (texel, value) in texels.iter_mut().enumerate() {
+        // Column by column, like the color indices.
+        let j = (texel % 4) * 4 + texel / 4;
+        let index = (alpha >> (45 - 3 * j)) as usize & 7;
+        value[3] = clamp_u8(base + modifiers[index] * multiplier);
+    }
+    texels
+}
+
+fn clamp_u8(value: i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+/// Which of the two subsets each texel belongs to, for each of BC7's 64 two-subset partitions.
+#[rustfmt::skip]
+const BC7_PARTITIONS_2: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1],
+    [0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 1, 1, 1, 0, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 1, 1],
+    [0, 0, 0, 1, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1],
+    [0, 0, 0, 0, 1, 0, 0, 0, 1, 1, 1, 0, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 1, 1, 0],
+    [0, 1, 1, 1, 0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0],
+    [0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 1, 0, 0, 0, 1, 1, 0, 0, 1, 1, 1, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 1, 0, 0],
+    [0, 1, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 0, 1],
+    [0, 0, 1, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0],
+    [0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 1, 0, 0],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0],
+    [0, 0, 1, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 1, 0, 0],
+    [0, 0, 0, 1, 0, 1, 1, 1, 1, 1, 1, 0, 1, 0, 0, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0],
+    [0, 1, 1, 1, 0, 0, 0, 1, 1, 0, 0, 0, 1, 1, 1, 0],
+    [0, 0, 1, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 1, 0, 0],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 0, 0, 1, 1, 1, 1],
+    [0, 1, 0, 1, 1, 0, 1, 0, 0, 1, 0, 1, 1, 0, 1, 0],
+    [0, 0, 1, 1, 0, 0, 1, 1, 1, 1, 0, 0, 1, 1, 0, 0],
+    [0, 0, 1, 1, 1, 1, 0, 0, 0, 0, 1, 1, 1, 1, 0, 0],
+    [0, 1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0],
+    [0, 1, 1, 0, 1, 0, 0, 1, 0, 1, 1, 0, 1, 0, 0, 1],
+    [0, 1, 0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1],
+    [0, 1, 1, 1, 0, 0, 1, 1, 1, 1, 0, 0, 1, 1, 1, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 1, 1, 0, 0, 1, 0, 0, 0],
+    [0, 0, 1, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 1, 0, 0],
+    [0, 0, 1, 1, 1, 0, 1, 1, 1, 1, 0, 1, 1, 1, 0, 0],
+    [0, 1, 1, 0, 1, 0, 0, 1, 1, 0, 0, 1, 0, 1, 1, 0],
+    [0, 0, 1, 1, 1, 1, 0, 0, 1, 1, 0, 0, 0, 0, 1, 1],
+    [0, 1, 1, 0, 0, 1, 1, 0, 1, 0, 0, 1, 1, 0, 0, 1],
+    [0, 0, 0, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 0, 0, 1, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 0, 1, 0, 0, 1, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0],
+    [0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 1, 0, 0, 1, 0],
+    [0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 1, 0, 0, 1, 0, 0],
+    [0, 1, 1, 0, 1, 1, 0, 0, 1, 0, 0, 1, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 1, 1, 0, 1, 1, 0, 0, 1, 0, 0, 1],
+    [0, 1, 1, 0, 0, 0, 1, 1, 1, 0, 0, 1, 1, 1, 0, 0],
+    [0, 0, 1, 1, 1, 0, 0, 1, 1, 1, 0, 0, 0, 1, 1, 0],
+    [0, 1, 1, 0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 0, 0, 1],
+    [0, 1, 1, 0, 0, 0, 1, 1, 0, 0, 1, 1, 1, 0, 0, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0, 1, 0, 0, 0, 0, 0, 0, 1],
+    [0, 0, 0, 1, 1, 0, 0, 0, 1, 1, 1, 0, 0, 1, 1, 1],
+    [0, 0, 0, 0, 1, 1, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 0, 0, 0, 1, 0, 1, 1, 1, 0, 1, 1, 1, 0],
+    [0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 1, 1, 0, 1, 1, 1],
+];
+
+/// Which of the three subsets each texel belongs to, for each of BC7's 64 three-subset
+/// partitions.
+#[rustfmt::skip]
+const BC7_PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// The anchor texel of the second subset, for each two-subset partition.
+#[rustfmt::skip]
+const BC7_ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15,
+    2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15,
+    2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2,
+    15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor texel of the second subset, for each three-subset partition.
+#[rustfmt::skip]
+const BC7_ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15,
+    8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10,
+    5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15,
+    15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10,
+    5, 10, 8, 13, 15, 12, 3, 3,
+];
+
+/// The anchor texel of the third subset, for each three-subset partition.
+#[rustfmt::skip]
+const BC7_ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8,
+    15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8,
+    3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10,
+    6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15,
+    15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs fields into a BC7 block, least significant bit first.
+    fn pack_bc7(fields: &[(u128, u32)]) -> [u8; 16] {
+        let (mut bits, mut position) = (0u128, 0);
+        for &(value, width) in fields {
+            bits |= value << position;
+            position += width;
+        }
+        assert_eq!(position, 128);
+        bits.to_le_bytes()
+    }
+
+    #[test]
+    fn bc7_anchors_belong_to_their_subsets() {
+        for partition in 0..64 {
+            let anchor = BC7_ANCHORS_2[partition] as usize;
+            assert_eq!(
+                BC7_PARTITIONS_2[partition][anchor], 1,
+                "partition {partition}"
+            );
+            let second = BC7_ANCHORS_3_SECOND[partition] as usize;
+            let third = BC7_ANCHORS_3_THIRD[partition] as usize;
+            assert_eq!(
+                BC7_PARTITIONS_3[partition][second], 1,
+                "partition {partition}"
+            );
+            assert_eq!(
+                BC7_PARTITIONS_3[partition][third], 2,
+                "partition {partition}"
+            );
+        }
+    }
+
+    #[test]
+    fn bc1_interpolates_and_punches_through() {
+        // Red to blue, first texel 0, second 1, third 2, the rest 3.
+        let four_color = [0x00, 0xf8, 0x1f, 0x00, 0b1110_0100, 0xff, 0xff, 0xff];
+        let texels = decode_bc1(&four_color);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1], [0, 0, 255, 255]);
+        assert_eq!(texels[2], [170, 0, 85, 255]);
+        assert_eq!(texels[3], [85, 0, 170, 255]);
+
+        // Swapping the endpoints selects the three-color mode.
+        let three_color = [0x1f, 0x00, 0x00, 0xf8, 0b1110_0100, 0, 0, 0];
+        let texels = decode_bc1(&three_color);
+        assert_eq!(texels[2], [128, 0, 128, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bc3_alpha_uses_both_palettes() {
+        let mut block = [0u8; 16];
+        block[0] = 255;
+        block[1] = 0;
+        // Texel indices 0, 1, 2, 7.
+        block[2] = 0x88;
+        block[3] = 0x0e;
+        let alpha: Vec<_> = decode_bc3(&block)[..4].iter().map(|t| t[3]).collect();
+        assert_eq!(alpha, [255, 0, 219, 36]);
+
+        block[0] = 0;
+        block[1] = 255;
+        let alpha: Vec<_> = decode_bc3(&block)[..4].iter().map(|t| t[3]).collect();
+        assert_eq!(alpha, [0, 255, 51, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_6_interpolates_with_p_bits() {
+        // Black to white with opaque alpha; the p-bits make the endpoints 0 and 255. The first
+        // texel (the anchor, with a 3-bit index) is 0, the others 15.
+        let mut fields = vec![(1 << 6, 7)];
+        for _ in 0..3 {
+            fields.extend([(0, 7), (0x7f, 7)]);
+        }
+        fields.extend([(0x7f, 7), (0x7f, 7), (0, 1), (1, 1), (0, 3)]);
+        fields.extend([(0xf, 4); 15]);
+        let texels = decode_bc7(&pack_bc7(&fields));
+        assert_eq!(texels[0], [0, 0, 0, 254]);
+        assert_eq!(texels[1], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn bc7_mode_5_rotates_alpha_into_red() {
+        // Solid green with half alpha, rotated so red and alpha swap places.
+        let mut fields = vec![(1 << 5, 6), (1, 2)];
+        fields.extend([(0, 7), (0, 7), (0x7f, 7), (0x7f, 7), (0, 7), (0, 7)]);
+        fields.extend([(0x80, 8), (0x80, 8)]);
+        fields.extend([(0, 1), (0, 2 * 15), (0, 1), (0, 2 * 15)]);
+        let texels = decode_bc7(&pack_bc7(&fields));
+        assert_eq!(texels[5], [128, 255, 0, 0]);
+    }
+
+    #[test]
+    fn reserved_bc7_mode_is_transparent() {
+        assert_eq!(decode_bc7(&[0; 16]), [[0; 4]; 16]);
+    }
+
+    #[test]
+    fn etc2_individual_mode_applies_modifiers() {
+        // Gray base colors 136 and 68, tables 0 and 7, not differential and not flipped. Every texel has index 3 (the
+        // large negative modifier) except the top left one, with index 0.
+        let bits: u64 = 0x8484_8400 | 0b111 << 2;
+        let block = (bits << 32 | 0xfffe_fffe).to_be_bytes();
+        let texels = decode_etc2_rgb(&block);
+        assert_eq!(texels[0], [138, 138, 138, 255]);
+        assert_eq!(texels[1], [128, 128, 128, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn etc2_planar_mode_is_a_gradient() {
+        // Red is 0 at the origin and 255 at the right and bottom edges. A blue base of 31 with a
+        // delta of 1 overflows, which selects planar mode.
+        let differential = 1 << 33;
+        let blue_overflow = 0x1f << 43 | 1 << 40;
+        let horizontal_red = 0x1f << 34 | 1 << 32;
+        let vertical_red = 0x3f << 13;
+        let bits: u64 = differential | blue_overflow | horizontal_red | vertical_red;
+        let texels = decode_etc2_rgb(&bits.to_be_bytes());
+        assert_eq!(texels[0][0], 0);
+        assert_eq!(texels[3][0], 191);
+        assert_eq!(texels[15][0], 255);
+    }
+
+    #[test]
+    fn eac_alpha_scales_modifiers() {
+        let mut block = [0u8; 16];
+        // Base 128, multiplier 2, table 0; first texel index 7 (+14), the rest 0 (-3).
+        block[..8].copy_from_slice(&(0x8020_u64 << 48 | 0b111 << 45).to_be_bytes());
+        let texels = decode_etc2_rgba(&block);
+        assert_eq!(texels[0][3], 156);
+        assert_eq!(texels[1][3], 122);
+    }
+}
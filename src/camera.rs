@@ -0,0 +1,466 @@
+//! Cameras producing the view and projection matrices fed to the scenes.
+
+use glam::{Mat4, Vec3};
+use winit::event::{ElementState, VirtualKeyCode};
+
+/// Pitch is kept just short of straight up/down. At exactly ±90° the forward vector becomes
+/// parallel to the up vector and the view matrix flips.
+const MAX_PITCH: f32 = 89.0_f32 * (std::f32::consts::PI / 180.0);
+
+/// Builds a right-handed perspective projection for Vulkan's clip space.
+///
+/// Vulkan's clip space has Y pointing down and depth in `0..1`, while our world has Y up, so the
+/// Y axis is flipped here rather than in every vertex shader.
+pub fn perspective(fov_y_radians: f32, aspect_ratio: f32, z_near: f32, z_far: f32) -> Mat4 {
+    let mut projection = Mat4::perspective_rh(fov_y_radians, aspect_ratio, z_near, z_far);
+    projection.y_axis.y *= -1.0;
+    projection
+}
+
+/// Builds a right-handed orthographic projection for Vulkan's clip space, showing `half_height`
+/// world units above and below the centre of the view. Y is flipped like in [`perspective`].
+pub fn orthographic(half_height: f32, aspect_ratio: f32, z_near: f32, z_far: f32) -> Mat4 {
+    let half_width = half_height * aspect_ratio;
+    let mut projection = Mat4::orthographic_rh(
+        -half_width,
+        half_width,
+        -half_height,
+        half_height,
+        z_near,
+        z_far,
+    );
+    projection.y_axis.y *= -1.0;
+    projection
+}
+
+/// Vertical field of view of the [`FlyCamera`] and [`OrbitCamera`] projections.
+const FLY_FOV_Y: f32 = 60.0_f32 * (std::f32::consts::PI / 180.0);
+
+/// Radians an [`OrbitCamera`] turns per pixel dragged.
+const ORBIT_SENSITIVITY: f32 = 0.005;
+
+/// How much one scroll wheel step scales an [`OrbitCamera`]'s distance.
+const ZOOM_PER_STEP: f32 = 1.1;
+
+/// [`OrbitCamera`]s never get closer to their target than this, or zooming back out would take
+/// forever.
+const MIN_ORBIT_DISTANCE: f32 = 0.05;
+
+/// The camera a view of the scene is seen through.
+#[derive(Clone, Debug)]
+pub enum Camera {
+    Fly(FlyCamera),
+    TopDown(TopDownCamera),
+    Orbit(OrbitCamera),
+}
+
+impl Camera {
+    pub fn view_matrix(&self) -> Mat4 {
+        match self {
+            Camera::Fly(camera) => camera.view_matrix(),
+            Camera::TopDown(camera) => camera.view_matrix(),
+            Camera::Orbit(camera) => camera.view_matrix(),
+        }
+    }
+
+    pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+        match self {
+            Camera::Fly(_) | Camera::Orbit(_) => perspective(FLY_FOV_Y, aspect_ratio, 0.1, 100.0),
+            Camera::TopDown(camera) => camera.projection(aspect_ratio),
+        }
+    }
+
+    /// Where the camera looks from.
+    pub fn position(&self) -> Vec3 {
+        match self {
+            Camera::Fly(camera) => camera.position,
+            Camera::TopDown(camera) => camera.center + Vec3::Y * camera.height,
+            Camera::Orbit(camera) => camera.position(),
+        }
+    }
+
+    /// Unit vector pointing the way the camera looks.
+    pub fn forward(&self) -> Vec3 {
+        match self {
+            Camera::Fly(camera) => camera.forward(),
+            Camera::TopDown(_) => -Vec3::Y,
+            Camera::Orbit(camera) => camera.forward(),
+        }
+    }
+
+    /// The camera if it can be steered with the keyboard and mouse.
+    pub fn as_fly_mut(&mut self) -> Option<&mut FlyCamera> {
+        match self {
+            Camera::Fly(camera) => Some(camera),
+            Camera::TopDown(_) | Camera::Orbit(_) => None,
+        }
+    }
+
+    /// The camera if it can be dragged around its target.
+    pub fn as_orbit_mut(&mut self) -> Option<&mut OrbitCamera> {
+        match self {
+            Camera::Orbit(camera) => Some(camera),
+            Camera::Fly(_) | Camera::TopDown(_) => None,
+        }
+    }
+
+    /// Switches a fly camera to orbiting and back, looking the same way from the same place.
+    ///
+    /// The orbit's target is the point straight ahead that is closest to the origin, where the
+    /// scenes are, so the turntable spins around something worth looking at.
+    pub fn toggle_orbit(&mut self) {
+        *self = match self {
+            Camera::Fly(camera) => {
+                let to_origin = -camera.position.dot(camera.forward());
+                let distance = if to_origin > MIN_ORBIT_DISTANCE {
+                    to_origin
+                } else {
+                    OrbitCamera::default().distance
+                };
+                Camera::Orbit(OrbitCamera::from_fly(camera, distance))
+            }
+            Camera::Orbit(camera) => {
+                Camera::Fly(FlyCamera::looking_at(camera.position(), camera.target))
+            }
+            Camera::TopDown(_) => return,
+        };
+    }
+
+    /// Moves the camera so the sphere around `center` fits in a view of `aspect_ratio`, keeping
+    /// the direction it looks in.
+    pub fn frame(&mut self, center: Vec3, radius: f32, aspect_ratio: f32) {
+        let half_fov_y = FLY_FOV_Y / 2.0;
+        let half_fov_x = (half_fov_y.tan() * aspect_ratio).atan();
+        let distance = radius / half_fov_y.min(half_fov_x).sin();
+        match self {
+            Camera::Fly(camera) => camera.position = center - camera.forward() * distance,
+            Camera::Orbit(camera) => {
+                camera.target = center;
+                camera.distance = distance.max(MIN_ORBIT_DISTANCE);
+            }
+            Camera::TopDown(camera) => {
+                camera.center = center;
+                camera.half_height = radius / aspect_ratio.min(1.0);
+            }
+        }
+    }
+}
+
+/// A perspective camera on a sphere around a target, looking at it.
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitCamera {
+    pub target: Vec3,
+    pub distance: f32,
+    /// Angle around the target in radians, with 0 on the +Z side.
+    pub yaw: f32,
+    /// Angle above the target's horizon in radians.
+    pub pitch: f32,
+}
+
+impl Default for OrbitCamera {
+    /// Looks at the origin from the front right and a little above.
+    fn default() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            distance: 4.0,
+            yaw: 45.0_f32.to_radians(),
+            pitch: 30.0_f32.to_radians(),
+        }
+    }
+}
+
+impl OrbitCamera {
+    pub fn position(&self) -> Vec3 {
+        let pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        let direction = Vec3::new(
+            self.yaw.sin() * pitch.cos(),
+            pitch.sin(),
+            self.yaw.cos() * pitch.cos(),
+        );
+        self.target + direction * self.distance
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position(), self.target, Vec3::Y)
+    }
+
+    /// Orbits `distance` away from the point `camera` looks at, from where it looks from.
+    pub fn from_fly(camera: &FlyCamera, distance: f32) -> Self {
+        let forward = camera.forward();
+        Self {
+            target: camera.position + forward * distance,
+            distance,
+            yaw: (-forward.x).atan2(-forward.z),
+            pitch: (-forward.y).asin(),
+        }
+    }
+
+    /// Unit vector pointing at the target.
+    pub fn forward(&self) -> Vec3 {
+        (self.target - self.position()).normalize()
+    }
+
+    /// Turns around the target by a cursor movement in pixels, as if dragging the scene.
+    pub fn rotate(&mut self, delta_x: f64, delta_y: f64) {
+        self.yaw -= delta_x as f32 * ORBIT_SENSITIVITY;
+        self.pitch = (self.pitch + delta_y as f32 * ORBIT_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves towards the target by scroll wheel `steps`, or away for negative ones. Each step
+    /// scales the distance by the same factor, so zooming feels the same close up and far away.
+    pub fn zoom(&mut self, steps: f32) {
+        self.distance = (self.distance * ZOOM_PER_STEP.powf(-steps)).max(MIN_ORBIT_DISTANCE);
+    }
+
+    /// Moves the target across the view by a cursor movement in pixels, in a view
+    /// `viewport_height` pixels high, so the point under the cursor stays there.
+    pub fn pan(&mut self, delta_x: f64, delta_y: f64, viewport_height: u32) {
+        let forward = self.forward();
+        let right = forward.cross(Vec3::Y).normalize();
+        let up = right.cross(forward);
+        let units_per_pixel =
+            2.0 * self.distance * (FLY_FOV_Y / 2.0).tan() / viewport_height.max(1) as f32;
+        self.target += (up * delta_y as f32 - right * delta_x as f32) * units_per_pixel;
+    }
+}
+
+/// A fixed orthographic camera looking straight down on the scene, with -Z pointing up the
+/// screen. Handy as a debug overview.
+#[derive(Clone, Copy, Debug)]
+pub struct TopDownCamera {
+    /// The point in the middle of the view.
+    pub center: Vec3,
+    /// How far above `center` the camera sits. Anything higher isn't visible.
+    pub height: f32,
+    /// World units visible above and below the centre.
+    pub half_height: f32,
+}
+
+impl Default for TopDownCamera {
+    /// Shows the area around the origin where the demo scenes are.
+    fn default() -> Self {
+        Self {
+            center: Vec3::ZERO,
+            height: 10.0,
+            half_height: 2.0,
+        }
+    }
+}
+
+impl TopDownCamera {
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.center + Vec3::Y * self.height, self.center, -Vec3::Z)
+    }
+
+    pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+        orthographic(self.half_height, aspect_ratio, 0.0, self.height * 2.0)
+    }
+}
+
+/// Which movement keys are currently held down.
+#[derive(Clone, Copy, Debug, Default)]
+struct MovementKeys {
+    forward: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+}
+
+/// A first-person camera: the mouse turns it, WASD moves it along where it is looking and
+/// Space/Shift move it straight up and down.
+#[derive(Clone, Debug)]
+pub struct FlyCamera {
+    pub position: Vec3,
+    /// Rotation around the world Y axis in radians. Zero looks down +X.
+    pub yaw: f32,
+    /// Rotation above (positive) or below the horizon in radians.
+    pub pitch: f32,
+    /// Movement speed in world units per second.
+    pub speed: f32,
+    /// Radians turned per pixel of mouse movement.
+    pub sensitivity: f32,
+    keys: MovementKeys,
+}
+
+impl FlyCamera {
+    pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
+        Self {
+            position,
+            yaw,
+            pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+            speed: 2.5,
+            sensitivity: 0.002,
+            keys: MovementKeys::default(),
+        }
+    }
+
+    /// Creates a camera at `position` facing `target`.
+    pub fn looking_at(position: Vec3, target: Vec3) -> Self {
+        let direction = (target - position).normalize();
+        let yaw = direction.z.atan2(direction.x);
+        let pitch = direction.y.asin();
+        Self::new(position, yaw, pitch)
+    }
+
+    /// Unit vector pointing where the camera looks.
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.sin(),
+        )
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(Vec3::Y).normalize()
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_to_rh(self.position, self.forward(), Vec3::Y)
+    }
+
+    /// Turns the camera by a raw mouse delta, as reported by `DeviceEvent::MouseMotion`.
+    pub fn process_mouse_motion(&mut self, delta_x: f64, delta_y: f64) {
+        self.yaw += delta_x as f32 * self.sensitivity;
+        // Moving the mouse up gives a negative delta but should make us look up.
+        self.pitch = (self.pitch - delta_y as f32 * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Records a movement key press or release. Returns `false` for keys the camera ignores.
+    pub fn process_key(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        let pressed = state == ElementState::Pressed;
+        let slot = match key {
+            VirtualKeyCode::W => &mut self.keys.forward,
+            VirtualKeyCode::S => &mut self.keys.back,
+            VirtualKeyCode::A => &mut self.keys.left,
+            VirtualKeyCode::D => &mut self.keys.right,
+            VirtualKeyCode::Space => &mut self.keys.up,
+            VirtualKeyCode::LShift => &mut self.keys.down,
+            _ => return false,
+        };
+        *slot = pressed;
+        true
+    }
+
+    /// Stops all movement, e.g. when the window loses focus and we'd miss the key releases.
+    pub fn release_keys(&mut self) {
+        self.keys = MovementKeys::default();
+    }
+
+    /// Moves the camera according to the held keys over `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        let forward = self.forward();
+        let right = self.right();
+        let mut direction = Vec3::ZERO;
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+
+        direction += forward * axis(self.keys.forward, self.keys.back);
+        direction += right * axis(self.keys.right, self.keys.left);
+        direction += Vec3::Y * axis(self.keys.up, self.keys.down);
+
+        if direction != Vec3::ZERO {
+            self.position += direction.normalize() * self.speed * dt;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orbit_camera_keeps_its_target_in_the_middle() {
+        let camera = OrbitCamera {
+            yaw: 0.0,
+            pitch: 0.0,
+            ..OrbitCamera::default()
+        };
+        assert!(camera
+            .position()
+            .abs_diff_eq(Vec3::new(0.0, 0.0, 4.0), 1e-5));
+
+        let camera = OrbitCamera::default();
+        assert!((camera.position().length() - camera.distance).abs() < 1e-5);
+        let view_projection = Camera::Orbit(camera).projection(0.5) * camera.view_matrix();
+        let target = view_projection.project_point3(camera.target);
+        assert!(target.x.abs() < 1e-5 && target.y.abs() < 1e-5);
+    }
+
+    #[test]
+    fn top_down_puts_minus_z_at_the_top_of_the_screen() {
+        let camera = TopDownCamera::default();
+        let view_projection = camera.projection(1.0) * camera.view_matrix();
+
+        let center = view_projection.project_point3(Vec3::ZERO);
+        assert!(center.x.abs() < 1e-5 && center.y.abs() < 1e-5);
+        assert!((0.0..=1.0).contains(&center.z));
+
+        // Vulkan's clip space has -Y at the top.
+        let north = view_projection.project_point3(Vec3::new(0.0, 0.0, -camera.half_height));
+        assert!((north.y + 1.0).abs() < 1e-5);
+        let east = view_projection.project_point3(Vec3::new(camera.half_height, 0.0, 0.0));
+        assert!((east.x - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn toggling_orbit_keeps_the_view() {
+        let fly = FlyCamera::looking_at(Vec3::new(1.5, 1.2, 3.0), Vec3::ZERO);
+        let mut camera = Camera::Fly(fly.clone());
+        camera.toggle_orbit();
+        let orbit = *camera.as_orbit_mut().unwrap();
+        assert!(orbit.position().abs_diff_eq(fly.position, 1e-4));
+        assert!(orbit.forward().abs_diff_eq(fly.forward(), 1e-4));
+        assert!(orbit.target.length() < 1e-4);
+
+        camera.toggle_orbit();
+        assert!(camera.view_matrix().abs_diff_eq(fly.view_matrix(), 1e-4));
+    }
+
+    #[test]
+    fn zooming_scales_the_distance() {
+        let mut camera = OrbitCamera::default();
+        camera.zoom(2.0);
+        assert!((camera.distance - 4.0 / 1.21).abs() < 1e-5);
+        camera.zoom(-2.0);
+        assert!((camera.distance - 4.0).abs() < 1e-5);
+        camera.zoom(1000.0);
+        assert_eq!(camera.distance, MIN_ORBIT_DISTANCE);
+    }
+
+    #[test]
+    fn panning_moves_the_target_across_the_view() {
+        let mut camera = OrbitCamera {
+            yaw: 0.0,
+            pitch: 0.0,
+            ..OrbitCamera::default()
+        };
+        // Dragging right moves the scene right, so the target goes left.
+        camera.pan(100.0, 0.0, 600);
+        assert!(camera.target.x < 0.0);
+        assert!(camera.target.y.abs() < 1e-5 && camera.target.z.abs() < 1e-5);
+    }
+
+    #[test]
+    fn framing_fits_the_sphere_and_keeps_the_direction() {
+        let fly = FlyCamera::looking_at(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO);
+        let mut camera = Camera::Fly(fly.clone());
+        let center = Vec3::new(1.0, 2.0, 3.0);
+        camera.frame(center, 1.0, 2.0);
+        let Camera::Fly(framed) = &camera else {
+            unreachable!()
+        };
+        assert!(framed.forward().abs_diff_eq(fly.forward(), 1e-5));
+        let distance = framed.position.distance(center);
+        assert!((distance - 1.0 / (FLY_FOV_Y / 2.0).sin()).abs() < 1e-4);
+
+        // The top of the sphere touches the top of the view, where -Y is in clip space.
+        let view_projection = camera.projection(2.0) * camera.view_matrix();
+        let half_fov = FLY_FOV_Y / 2.0;
+        let to_tangent = fly.forward() * half_fov.cos() + Vec3::Y * half_fov.sin();
+        let tangent = framed.position + to_tangent * (distance * distance - 1.0).sqrt();
+        assert!((tangent.distance(center) - 1.0).abs() < 1e-4);
+        assert!((view_projection.project_point3(tangent).y + 1.0).abs() < 1e-3);
+    }
+}
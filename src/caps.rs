@@ -0,0 +1,899 @@
+//! What the chosen device can do beyond baseline Vulkan, decided once when the device is created.
+//!
+//! Newer functionality is core in Vulkan 1.2 or 1.3 but also available as an extension on older
+//! drivers. [`DeviceCaps::negotiate`] works out which of the two to use (if either) and what to
+//! enable for it. [`DeviceCaps::query`] adds the limits and format support the renderer cares
+//! about. Everything else looks at the resulting [`DeviceCaps`] rather than asking the physical
+//! device.
+
+use std::fmt;
+
+use vulkano::device::physical::{PhysicalDevice, SubgroupFeatures};
+use vulkano::device::{DeviceExtensions, Features, Properties};
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::SampleCounts;
+use vulkano::shader::ShaderStages;
+use vulkano::{DeviceSize, Version};
+
+use crate::error::RendererError;
+
+/// Formats the crate renders to or samples from, whose support goes in the capabilities.
+const FORMATS: [Format; 15] = [
+    Format::B8G8R8A8_SRGB,
+    Format::R8G8B8A8_SRGB,
+    Format::B8G8R8A8_UNORM,
+    Format::R8G8B8A8_UNORM,
+    Format::R8_UINT,
+    Format::BC1_RGBA_UNORM_BLOCK,
+    Format::BC1_RGBA_SRGB_BLOCK,
+    Format::BC3_UNORM_BLOCK,
+    Format::BC3_SRGB_BLOCK,
+    Format::BC7_UNORM_BLOCK,
+    Format::BC7_SRGB_BLOCK,
+    Format::ETC2_R8G8B8_UNORM_BLOCK,
+    Format::ETC2_R8G8B8_SRGB_BLOCK,
+    Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+    Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+];
+
+/// Depth formats worth checking, roughly in order of preference.
+const DEPTH_FORMATS: [Format; 5] = [
+    Format::D16_UNORM,
+    Format::D32_SFLOAT,
+    Format::X8_D24_UNORM_PACK32,
+    Format::D24_UNORM_S8_UINT,
+    Format::D32_SFLOAT_S8_UINT,
+];
+
+/// The optional functionality the renderer knows how to use, and whether the device has it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceCaps {
+    /// The API version the device is used with: the lowest of what the instance, the device and
+    /// `--force-api-version` allow.
+    pub api_version: Version,
+    /// `VK_KHR_synchronization2`, core in 1.3.
+    pub synchronization2: bool,
+    /// `VK_KHR_dynamic_rendering`, core in 1.3.
+    pub dynamic_rendering: bool,
+    /// `VK_KHR_timeline_semaphore`, core in 1.2 and required by 1.3.
+    pub timeline_semaphores: bool,
+    /// Non-uniform indexing into partially bound, runtime-sized arrays of sampled images, which
+    /// can be updated after being bound. `VK_EXT_descriptor_indexing`, core in 1.2.
+    pub descriptor_indexing: bool,
+    /// Heap budgets can be queried for the memory report (`VK_EXT_memory_budget`).
+    pub memory_budget: bool,
+    /// Presents can be tagged with IDs and waited for, to cap frame latency
+    /// (`VK_KHR_present_id` and `VK_KHR_present_wait`).
+    pub present_wait: bool,
+    /// The device only implements the portability subset (e.g. MoltenVK on macOS), so some
+    /// otherwise core functionality has to be checked for before use.
+    pub portability_subset: bool,
+    /// Lines wider than one pixel can be drawn.
+    pub wide_lines: bool,
+    pub geometry_shaders: bool,
+    /// Triangle fans are core Vulkan, but optional on portability subset devices.
+    pub triangle_fans: bool,
+    pub sampler_anisotropy: bool,
+    /// Wireframe and point polygon modes.
+    pub fill_mode_non_solid: bool,
+    /// Storage images in formats beyond the few every device supports, such as `R8_UINT`.
+    pub storage_image_extended_formats: bool,
+    /// Storage images written without a format qualifier in the shader, so one shader can write
+    /// whatever format the swapchain has.
+    pub storage_write_without_format: bool,
+    /// BC compressed textures.
+    pub texture_compression_bc: bool,
+    /// ETC2 and EAC compressed textures, mostly found on mobile GPUs.
+    pub texture_compression_etc2: bool,
+    /// Indirect draws of several commands at once, each starting at any instance
+    /// (`multiDrawIndirect` and `drawIndirectFirstInstance`), as GPU culling draws.
+    pub multi_draw_indirect: bool,
+    /// One render pass can draw into several layers at once, each shader invocation telling
+    /// them apart by `gl_ViewIndex`. Core in 1.1.
+    pub multiview: bool,
+    /// Each colour attachment of a subpass can blend its own way, as weighted blended
+    /// transparency accumulates and multiplies into two at once.
+    pub independent_blend: bool,
+    /// The queue we render with can write timestamps.
+    pub timestamps: bool,
+    pub limits: DeviceLimits,
+    /// The entries of [`DEPTH_FORMATS`] that can be depth attachments.
+    pub depth_formats: Vec<Format>,
+    /// Optimal-tiling features of the formats in [`FORMATS`] and [`DEPTH_FORMATS`].
+    pub formats: Vec<(Format, FormatFeatures)>,
+}
+
+/// The device limits the renderer checks, copied out of its properties.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeviceLimits {
+    pub max_image_dimension_2d: u32,
+    pub max_sampler_anisotropy: f32,
+    pub max_push_constants_size: u32,
+    pub max_bound_descriptor_sets: u32,
+    pub max_per_stage_descriptor_samplers: u32,
+    /// Zero without descriptor indexing.
+    pub max_per_stage_descriptor_update_after_bind_samplers: u32,
+    pub max_uniform_buffer_range: u32,
+    pub max_storage_buffer_range: u32,
+    pub min_uniform_buffer_offset_alignment: DeviceSize,
+    pub non_coherent_atom_size: DeviceSize,
+    pub max_color_attachments: u32,
+    pub line_width_range: [f32; 2],
+    /// Nanoseconds per timestamp tick.
+    pub timestamp_period: f32,
+    /// Sample counts usable for both colour and depth attachments.
+    pub sample_counts: SampleCounts,
+    /// Invocations per subgroup. Zero below Vulkan 1.1, which is where subgroups were added.
+    pub subgroup_size: u32,
+    /// The subgroup operations shaders can use, in [`subgroup_stages`](Self::subgroup_stages).
+    pub subgroup_operations: SubgroupFeatures,
+    pub subgroup_stages: ShaderStages,
+}
+
+impl DeviceLimits {
+    pub fn from_properties(properties: &Properties) -> Self {
+        Self {
+            max_image_dimension_2d: properties.max_image_dimension2_d,
+            max_sampler_anisotropy: properties.max_sampler_anisotropy,
+            max_push_constants_size: properties.max_push_constants_size,
+            max_bound_descriptor_sets: properties.max_bound_descriptor_sets,
+            max_per_stage_descriptor_samplers: properties.max_per_stage_descriptor_samplers,
+            max_per_stage_descriptor_update_after_bind_samplers: properties
+                .max_per_stage_descriptor_update_after_bind_samplers
+                .unwrap_or(0),
+            max_uniform_buffer_range: properties.max_uniform_buffer_range,
+            max_storage_buffer_range: properties.max_storage_buffer_range,
+            min_uniform_buffer_offset_alignment: properties
+                .min_uniform_buffer_offset_alignment
+                .as_devicesize(),
+            non_coherent_atom_size: properties.non_coherent_atom_size.as_devicesize(),
+            max_color_attachments: properties.max_color_attachments,
+            line_width_range: properties.line_width_range,
+            timestamp_period: properties.timestamp_period,
+            sample_counts: properties.framebuffer_color_sample_counts
+                & properties.framebuffer_depth_sample_counts,
+            subgroup_size: properties.subgroup_size.unwrap_or(0),
+            subgroup_operations: properties.subgroup_supported_operations.unwrap_or_default(),
+            subgroup_stages: properties.subgroup_supported_stages.unwrap_or_default(),
+        }
+    }
+}
+
+/// Extensions and features to enable on the device for a set of [`DeviceCaps`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceSetup {
+    pub caps: DeviceCaps,
+    /// Enable these on top of the ones the caller needs anyway.
+    pub extensions: DeviceExtensions,
+    pub features: Features,
+}
+
+impl DeviceSetup {
+    /// Leaves out what only matters when presenting, for devices created without a swapchain.
+    pub fn without_presentation(mut self) -> Self {
+        self.caps.present_wait = false;
+        self.extensions.khr_present_id = false;
+        self.extensions.khr_present_wait = false;
+        self.features.present_id = false;
+        self.features.present_wait = false;
+        self
+    }
+}
+
+/// The features that make up [`DeviceCaps::descriptor_indexing`].
+const DESCRIPTOR_INDEXING_FEATURES: Features = Features {
+    runtime_descriptor_array: true,
+    descriptor_binding_partially_bound: true,
+    descriptor_binding_variable_descriptor_count: true,
+    descriptor_binding_sampled_image_update_after_bind: true,
+    shader_sampled_image_array_non_uniform_indexing: true,
+    ..Features::empty()
+};
+
+impl DeviceCaps {
+    /// Works out the capabilities of `physical_device` when rendering on `queue_family_index`,
+    /// and what to enable for them. See [`DeviceCaps::negotiate`] for `allow_extensions`.
+    pub fn query(
+        physical_device: &PhysicalDevice,
+        queue_family_index: u32,
+        allow_extensions: bool,
+    ) -> Result<DeviceSetup, RendererError> {
+        let mut setup = Self::negotiate(
+            physical_device.api_version(),
+            physical_device.supported_extensions(),
+            physical_device.supported_features(),
+            allow_extensions,
+        );
+        let caps = &mut setup.caps;
+
+        caps.limits = DeviceLimits::from_properties(physical_device.properties());
+        caps.timestamps = physical_device.queue_family_properties()[queue_family_index as usize]
+            .timestamp_valid_bits
+            .is_some();
+        for format in FORMATS.into_iter().chain(DEPTH_FORMATS) {
+            let features = physical_device
+                .format_properties(format)?
+                .optimal_tiling_features;
+            caps.formats.push((format, features));
+        }
+        caps.depth_formats = DEPTH_FORMATS
+            .into_iter()
+            .filter(|&format| {
+                caps.format_features(format)
+                    .intersects(FormatFeatures::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .collect();
+
+        Ok(setup)
+    }
+
+    /// The optimal-tiling features of `format`. Empty for formats the crate doesn't use.
+    pub fn format_features(&self, format: Format) -> FormatFeatures {
+        self.formats
+            .iter()
+            .find(|&&(f, _)| f == format)
+            .map_or(FormatFeatures::empty(), |&(_, features)| features)
+    }
+
+    /// Whether compute shaders can add up values across a subgroup, with `subgroupAdd` and the
+    /// like. Shaders using it need SPIR-V 1.3, so Vulkan 1.1.
+    pub fn subgroup_arithmetic(&self) -> bool {
+        self.api_version >= Version::V1_1
+            && self
+                .limits
+                .subgroup_stages
+                .intersects(ShaderStages::COMPUTE)
+            && self
+                .limits
+                .subgroup_operations
+                .contains(SubgroupFeatures::BASIC | SubgroupFeatures::ARITHMETIC)
+    }
+
+    /// The widths in pixels lines can be drawn at: the device's `lineWidthRange` with the wide
+    /// lines feature, and only 1 without it.
+    pub fn line_width_range(&self) -> [f32; 2] {
+        if self.wide_lines {
+            self.limits.line_width_range
+        } else {
+            [1.0, 1.0]
+        }
+    }
+
+    /// The enabled functionality on one line, for the startup log.
+    pub fn summary(&self) -> String {
+        let enabled: Vec<_> = self
+            .features()
+            .into_iter()
+            .filter_map(|(name, enabled)| enabled.then_some(name))
+            .collect();
+        format!(
+            "Vulkan {}.{}, enabled: {}",
+            self.api_version.major,
+            self.api_version.minor,
+            enabled.join(", ")
+        )
+    }
+
+    fn features(&self) -> [(&'static str, bool); 20] {
+        [
+            ("synchronization2", self.synchronization2),
+            ("dynamic rendering", self.dynamic_rendering),
+            ("timeline semaphores", self.timeline_semaphores),
+            ("descriptor indexing", self.descriptor_indexing),
+            ("memory budget", self.memory_budget),
+            ("present wait", self.present_wait),
+            ("portability subset", self.portability_subset),
+            ("wide lines", self.wide_lines),
+            ("geometry shaders", self.geometry_shaders),
+            ("triangle fans", self.triangle_fans),
+            ("sampler anisotropy", self.sampler_anisotropy),
+            ("non-solid fill modes", self.fill_mode_non_solid),
+            (
+                "extended storage image formats",
+                self.storage_image_extended_formats,
+            ),
+            (
+                "storage writes without format",
+                self.storage_write_without_format,
+            ),
+            ("BC textures", self.texture_compression_bc),
+            ("ETC2 textures", self.texture_compression_etc2),
+            ("multi-draw indirect", self.multi_draw_indirect),
+            ("multiview", self.multiview),
+            ("independent blend", self.independent_blend),
+            ("timestamps", self.timestamps),
+        ]
+    }
+
+    /// Decides what to use on a device with the given API version, extensions and features, as
+    /// reported by vulkano for that version.
+    ///
+    /// Functionality that isn't core in `api_version` falls back to its extension when
+    /// `allow_extensions` is set. `--force-api-version` turns it off, so the paths for devices
+    /// without the functionality get exercised too.
+    pub fn negotiate(
+        api_version: Version,
+        supported_extensions: &DeviceExtensions,
+        supported_features: &Features,
+        allow_extensions: bool,
+    ) -> DeviceSetup {
+        let mut extensions = DeviceExtensions::empty();
+        let mut features = Features::empty();
+        // Every promoted extension we use needs `VK_KHR_get_physical_device_properties2`, which
+        // is core in 1.1. Below that, stick to the basics.
+        let allow_extensions = allow_extensions && api_version >= Version::V1_1;
+
+        // Whether something core in `core_in` (or provided by an extension, when `extension` is
+        // set) is available, and if so whether the extension has to be enabled for it.
+        let provided = |core_in: Version, extension: bool| {
+            if api_version >= core_in {
+                Some(false)
+            } else if allow_extensions && extension {
+                Some(true)
+            } else {
+                None
+            }
+        };
+
+        let synchronization2 = supported_features.synchronization2
+            && match provided(Version::V1_3, supported_extensions.khr_synchronization2) {
+                Some(via_extension) => {
+                    extensions.khr_synchronization2 = via_extension;
+                    true
+                }
+                None => false,
+            };
+        features.synchronization2 = synchronization2;
+
+        // Below 1.2 the extension also depends on `VK_KHR_depth_stencil_resolve` and
+        // `VK_KHR_create_renderpass2`, which were promoted to 1.2.
+        let dynamic_rendering_extension = supported_extensions.khr_dynamic_rendering
+            && (api_version >= Version::V1_2
+                || supported_extensions.khr_depth_stencil_resolve
+                    && supported_extensions.khr_create_renderpass2);
+        let dynamic_rendering = supported_features.dynamic_rendering
+            && match provided(Version::V1_3, dynamic_rendering_extension) {
+                Some(via_extension) => {
+                    extensions.khr_dynamic_rendering = via_extension;
+                    if via_extension && api_version < Version::V1_2 {
+                        extensions.khr_depth_stencil_resolve = true;
+                        extensions.khr_create_renderpass2 = true;
+                    }
+                    true
+                }
+                None => false,
+            };
+        features.dynamic_rendering = dynamic_rendering;
+
+        let timeline_semaphores = supported_features.timeline_semaphore
+            && match provided(Version::V1_2, supported_extensions.khr_timeline_semaphore) {
+                Some(via_extension) => {
+                    extensions.khr_timeline_semaphore = via_extension;
+                    true
+                }
+                None => false,
+            };
+        features.timeline_semaphore = timeline_semaphores;
+
+        let descriptor_indexing = supported_features.contains(&DESCRIPTOR_INDEXING_FEATURES)
+            && match provided(Version::V1_2, supported_extensions.ext_descriptor_indexing) {
+                Some(via_extension) => {
+                    extensions.ext_descriptor_indexing = via_extension;
+                    true
+                }
+                None => false,
+            };
+        if descriptor_indexing {
+            features = features.union(&DESCRIPTOR_INDEXING_FEATURES);
+        }
+
+        // Only needed to query heap budgets for the memory report, so enable it opportunistically.
+        let memory_budget = supported_extensions.ext_memory_budget && api_version >= Version::V1_1;
+        extensions.ext_memory_budget = memory_budget;
+
+        // Not promoted to core, and only used for frame pacing, so also opportunistic. Waiting
+        // needs the IDs.
+        let present_wait = supported_extensions.khr_present_id
+            && supported_extensions.khr_present_wait
+            && supported_features.present_id
+            && supported_features.present_wait
+            && api_version >= Version::V1_1;
+        extensions.khr_present_id = present_wait;
+        extensions.khr_present_wait = present_wait;
+        features.present_id = present_wait;
+        features.present_wait = present_wait;
+
+        // The spec requires enabling `khr_portability_subset` whenever a device advertises it
+        // (MoltenVK does). vulkano would quietly add it too, but being explicit keeps the decision
+        // visible and lets us know to check the subset's feature flags.
+        let portability_subset = supported_extensions.khr_portability_subset;
+        extensions.khr_portability_subset = portability_subset;
+
+        features.wide_lines = supported_features.wide_lines;
+        features.geometry_shader = supported_features.geometry_shader;
+        // Only exists on portability subset devices, where it's needed for `TriangleFan` topology.
+        features.triangle_fans = supported_features.triangle_fans;
+        features.sampler_anisotropy = supported_features.sampler_anisotropy;
+        features.fill_mode_non_solid = supported_features.fill_mode_non_solid;
+        features.shader_storage_image_extended_formats =
+            supported_features.shader_storage_image_extended_formats;
+        features.shader_storage_image_write_without_format =
+            supported_features.shader_storage_image_write_without_format;
+        features.texture_compression_bc = supported_features.texture_compression_bc;
+        features.texture_compression_etc2 = supported_features.texture_compression_etc2;
+        // Only of use together, so both or neither.
+        let multi_draw_indirect = supported_features.multi_draw_indirect
+            && supported_features.draw_indirect_first_instance;
+        features.multi_draw_indirect = multi_draw_indirect;
+        features.draw_indirect_first_instance = multi_draw_indirect;
+        // `VK_KHR_multiview` is needed below 1.1, where no extension is used anyway.
+        let multiview = supported_features.multiview && api_version >= Version::V1_1;
+        features.multiview = multiview;
+        features.independent_blend = supported_features.independent_blend;
+
+        DeviceSetup {
+            caps: DeviceCaps {
+                api_version,
+                synchronization2,
+                dynamic_rendering,
+                timeline_semaphores,
+                descriptor_indexing,
+                memory_budget,
+                present_wait,
+                portability_subset,
+                wide_lines: features.wide_lines,
+                geometry_shaders: features.geometry_shader,
+                triangle_fans: !portability_subset || features.triangle_fans,
+                sampler_anisotropy: features.sampler_anisotropy,
+                fill_mode_non_solid: features.fill_mode_non_solid,
+                storage_image_extended_formats: features.shader_storage_image_extended_formats,
+                storage_write_without_format: features.shader_storage_image_write_without_format,
+                texture_compression_bc: features.texture_compression_bc,
+                texture_compression_etc2: features.texture_compression_etc2,
+                multi_draw_indirect,
+                multiview,
+                independent_blend: features.independent_blend,
+                // Filled in by `query`, along with the rest below.
+                timestamps: false,
+                limits: DeviceLimits::default(),
+                depth_formats: Vec::new(),
+                formats: Vec::new(),
+            },
+            extensions,
+            features,
+        }
+    }
+}
+
+/// The full capabilities matrix for `--print-caps`. Limits use their Vulkan names so they can be
+/// compared with `vulkaninfo`.
+impl fmt::Display for DeviceCaps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |enabled: bool| if enabled { "yes" } else { "no" };
+
+        write!(
+            f,
+            "Device capabilities (Vulkan {}.{})",
+            self.api_version.major, self.api_version.minor
+        )?;
+        write!(f, "\n\nEnabled features:")?;
+        for (name, enabled) in self.features() {
+            write!(f, "\n  {name:<36} {}", yes_no(enabled))?;
+        }
+
+        let limits = &self.limits;
+        write!(f, "\n\nLimits:")?;
+        let flags = |flags: String| match flags.as_str() {
+            "empty()" => "none".to_owned(),
+            _ => flags,
+        };
+        let rows: [(&str, String); 17] = [
+            (
+                "maxImageDimension2D",
+                limits.max_image_dimension_2d.to_string(),
+            ),
+            (
+                "maxSamplerAnisotropy",
+                limits.max_sampler_anisotropy.to_string(),
+            ),
+            (
+                "maxPushConstantsSize",
+                limits.max_push_constants_size.to_string(),
+            ),
+            (
+                "maxBoundDescriptorSets",
+                limits.max_bound_descriptor_sets.to_string(),
+            ),
+            (
+                "maxPerStageDescriptorSamplers",
+                limits.max_per_stage_descriptor_samplers.to_string(),
+            ),
+            (
+                "maxPerStageDescriptorUpdateAfterBindSamplers",
+                limits
+                    .max_per_stage_descriptor_update_after_bind_samplers
+                    .to_string(),
+            ),
+            (
+                "maxUniformBufferRange",
+                limits.max_uniform_buffer_range.to_string(),
+            ),
+            (
+                "maxStorageBufferRange",
+                limits.max_storage_buffer_range.to_string(),
+            ),
+            (
+                "minUniformBufferOffsetAlignment",
+                limits.min_uniform_buffer_offset_alignment.to_string(),
+            ),
+            (
+                "nonCoherentAtomSize",
+                limits.non_coherent_atom_size.to_string(),
+            ),
+            (
+                "maxColorAttachments",
+                limits.max_color_attachments.to_string(),
+            ),
+            (
+                "lineWidthRange",
+                format!(
+                    "{} - {}",
+                    limits.line_width_range[0], limits.line_width_range[1]
+                ),
+            ),
+            ("timestampPeriod", limits.timestamp_period.to_string()),
+            ("sampleCounts", format_sample_counts(limits.sample_counts)),
+            ("subgroupSize", limits.subgroup_size.to_string()),
+            (
+                "subgroupSupportedOperations",
+                flags(format!("{:?}", limits.subgroup_operations)),
+            ),
+            (
+                "subgroupSupportedStages",
+                flags(format!("{:?}", limits.subgroup_stages)),
+            ),
+        ];
+        for (name, value) in rows {
+            write!(f, "\n  {name:<36} {value}")?;
+        }
+
+        let depth_formats: Vec<_> = self
+            .depth_formats
+            .iter()
+            .map(|f| format!("{f:?}"))
+            .collect();
+        write!(f, "\n\nDepth formats: {}", depth_formats.join(", "))?;
+
+        let columns = [
+            ("sampled", FormatFeatures::SAMPLED_IMAGE),
+            ("linear", FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR),
+            ("color", FormatFeatures::COLOR_ATTACHMENT),
+            ("depth", FormatFeatures::DEPTH_STENCIL_ATTACHMENT),
+            ("blit src", FormatFeatures::BLIT_SRC),
+            ("blit dst", FormatFeatures::BLIT_DST),
+            ("storage", FormatFeatures::STORAGE_IMAGE),
+        ];
+        write!(f, "\n\nFormats (optimal tiling):\n  {:<24}", "")?;
+        for (name, _) in columns {
+            write!(f, " {name:<8}")?;
+        }
+        for &(format, features) in &self.formats {
+            write!(f, "\n  {:<24}", format!("{format:?}"))?;
+            for (_, feature) in columns {
+                write!(f, " {:<8}", yes_no(features.intersects(feature)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists sample counts as e.g. `1, 2, 4, 8`.
+fn format_sample_counts(counts: SampleCounts) -> String {
+    let counts: Vec<_> = counts
+        .into_iter()
+        .map(|count| (count as u32).to_string())
+        .collect();
+    counts.join(", ")
+}
+
+/// Parses a `--force-api-version` value. Only the versions we have paths for are accepted.
+pub fn parse_api_version(value: &str) -> Option<Version> {
+    match value {
+        "1.1" => Some(Version::V1_1),
+        "1.2" => Some(Version::V1_2),
+        "1.3" => Some(Version::V1_3),
+        _ => None,
+    }
+}
+
+/// `width` clamped to `range`, from [`DeviceCaps::line_width_range`], with a warning when it
+/// doesn't fit.
+pub fn clamp_line_width(width: f32, range: [f32; 2]) -> f32 {
+    let [min, max] = range;
+    let clamped = width.clamp(min, max);
+    if clamped != width {
+        if range == [1.0, 1.0] {
+            log::warn!("The device can't draw wide lines, drawing them 1 pixel wide");
+        } else {
+            log::warn!("The device draws lines {min} to {max} pixels wide, drawing them {clamped}");
+        }
+    }
+    clamped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything a modern desktop driver offers, as features and as the old extensions.
+    fn modern_extensions() -> DeviceExtensions {
+        DeviceExtensions {
+            khr_synchronization2: true,
+            khr_dynamic_rendering: true,
+            khr_depth_stencil_resolve: true,
+            khr_create_renderpass2: true,
+            khr_timeline_semaphore: true,
+            ext_descriptor_indexing: true,
+            ext_memory_budget: true,
+            ..DeviceExtensions::empty()
+        }
+    }
+
+    fn modern_features() -> Features {
+        Features {
+            synchronization2: true,
+            dynamic_rendering: true,
+            timeline_semaphore: true,
+            wide_lines: true,
+            geometry_shader: true,
+            multi_draw_indirect: true,
+            draw_indirect_first_instance: true,
+            ..DESCRIPTOR_INDEXING_FEATURES
+        }
+    }
+
+    #[test]
+    fn vulkan_1_3_uses_core_features() {
+        let setup = DeviceCaps::negotiate(
+            Version::V1_3,
+            &modern_extensions(),
+            &modern_features(),
+            true,
+        );
+        assert!(setup.caps.synchronization2 && setup.caps.dynamic_rendering);
+        assert!(setup.caps.timeline_semaphores && setup.caps.descriptor_indexing);
+        assert_eq!(
+            setup.extensions,
+            DeviceExtensions {
+                ext_memory_budget: true,
+                ..DeviceExtensions::empty()
+            }
+        );
+        assert!(setup.features.synchronization2 && setup.features.runtime_descriptor_array);
+        assert!(setup.caps.multi_draw_indirect && setup.features.draw_indirect_first_instance);
+    }
+
+    #[test]
+    fn vulkan_1_2_falls_back_to_extensions_for_1_3_features() {
+        let setup = DeviceCaps::negotiate(
+            Version::V1_2,
+            &modern_extensions(),
+            &modern_features(),
+            true,
+        );
+        assert!(setup.caps.synchronization2 && setup.caps.dynamic_rendering);
+        assert!(setup.extensions.khr_synchronization2 && setup.extensions.khr_dynamic_rendering);
+        // Core in 1.2, so no extension needed.
+        assert!(setup.caps.timeline_semaphores && !setup.extensions.khr_timeline_semaphore);
+        assert!(!setup.extensions.khr_depth_stencil_resolve);
+    }
+
+    #[test]
+    fn vulkan_1_1_enables_dependencies_of_dynamic_rendering() {
+        let setup = DeviceCaps::negotiate(
+            Version::V1_1,
+            &modern_extensions(),
+            &modern_features(),
+            true,
+        );
+        assert!(setup.caps.dynamic_rendering && setup.caps.descriptor_indexing);
+        assert!(setup.extensions.khr_depth_stencil_resolve);
+        assert!(setup.extensions.khr_create_renderpass2);
+        assert!(setup.extensions.ext_descriptor_indexing);
+
+        let mut extensions = modern_extensions();
+        extensions.khr_create_renderpass2 = false;
+        let setup = DeviceCaps::negotiate(Version::V1_1, &extensions, &modern_features(), true);
+        assert!(!setup.caps.dynamic_rendering && !setup.features.dynamic_rendering);
+    }
+
+    #[test]
+    fn forced_version_ignores_extensions() {
+        let setup = DeviceCaps::negotiate(
+            Version::V1_1,
+            &modern_extensions(),
+            &modern_features(),
+            false,
+        );
+        let caps = setup.caps;
+        assert_eq!(caps.api_version, Version::V1_1);
+        assert!(!caps.synchronization2 && !caps.dynamic_rendering);
+        assert!(!caps.timeline_semaphores && !caps.descriptor_indexing);
+        assert!(caps.memory_budget && caps.wide_lines);
+        assert!(!setup.features.synchronization2 && !setup.features.runtime_descriptor_array);
+    }
+
+    #[test]
+    fn missing_features_stay_off() {
+        let setup = DeviceCaps::negotiate(
+            Version::V1_3,
+            &DeviceExtensions::empty(),
+            &Features::empty(),
+            true,
+        );
+        assert_eq!(setup.features, Features::empty());
+        assert!(!setup.caps.synchronization2 && !setup.caps.wide_lines);
+        // Not a portability subset device, so fans are core.
+        assert!(setup.caps.triangle_fans);
+    }
+
+    #[test]
+    fn multi_draw_indirect_needs_first_instances_too() {
+        let features = Features {
+            multi_draw_indirect: true,
+            ..Features::empty()
+        };
+        let setup =
+            DeviceCaps::negotiate(Version::V1_3, &DeviceExtensions::empty(), &features, true);
+        assert!(!setup.caps.multi_draw_indirect && !setup.features.multi_draw_indirect);
+    }
+
+    #[test]
+    fn present_wait_needs_both_extensions_and_a_swapchain() {
+        let features = Features {
+            present_id: true,
+            present_wait: true,
+            ..Features::empty()
+        };
+        let only_wait = DeviceExtensions {
+            khr_present_wait: true,
+            ..DeviceExtensions::empty()
+        };
+        let setup = DeviceCaps::negotiate(Version::V1_3, &only_wait, &features, true);
+        assert!(!setup.caps.present_wait);
+
+        let both = DeviceExtensions {
+            khr_present_id: true,
+            ..only_wait
+        };
+        let setup = DeviceCaps::negotiate(Version::V1_3, &both, &features, true);
+        assert!(setup.caps.present_wait);
+        assert_eq!(setup.extensions, both);
+        assert_eq!(setup.features, features);
+
+        let headless = setup.without_presentation();
+        assert!(!headless.caps.present_wait);
+        assert_eq!(headless.extensions, DeviceExtensions::empty());
+        assert_eq!(headless.features, Features::empty());
+    }
+
+    #[test]
+    fn lines_are_only_wide_with_the_feature() {
+        let mut caps = DeviceCaps::negotiate(
+            Version::V1_3,
+            &DeviceExtensions::empty(),
+            &modern_features(),
+            true,
+        )
+        .caps;
+        caps.limits.line_width_range = [0.5, 8.0];
+        assert_eq!(caps.line_width_range(), [0.5, 8.0]);
+        assert_eq!(clamp_line_width(3.0, caps.line_width_range()), 3.0);
+        assert_eq!(clamp_line_width(16.0, caps.line_width_range()), 8.0);
+
+        caps.wide_lines = false;
+        assert_eq!(caps.line_width_range(), [1.0, 1.0]);
+        assert_eq!(clamp_line_width(3.0, caps.line_width_range()), 1.0);
+    }
+
+    #[test]
+    fn portability_subset_is_enabled_when_advertised() {
+        let extensions = DeviceExtensions {
+            khr_portability_subset: true,
+            ..DeviceExtensions::empty()
+        };
+        let setup = DeviceCaps::negotiate(Version::V1_2, &extensions, &Features::empty(), true);
+        assert!(setup.caps.portability_subset && setup.extensions.khr_portability_subset);
+        assert!(!setup.caps.triangle_fans);
+    }
+
+    #[test]
+    fn sample_counts_and_format_lookup() {
+        assert_eq!(
+            format_sample_counts(
+                SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_4 | SampleCounts::SAMPLE_8
+            ),
+            "1, 4, 8"
+        );
+
+        let mut caps = DeviceCaps::negotiate(
+            Version::V1_3,
+            &DeviceExtensions::empty(),
+            &Features::empty(),
+            true,
+        )
+        .caps;
+        caps.formats = vec![(Format::R8G8B8A8_UNORM, FormatFeatures::BLIT_SRC)];
+        assert_eq!(
+            caps.format_features(Format::R8G8B8A8_UNORM),
+            FormatFeatures::BLIT_SRC
+        );
+        assert_eq!(
+            caps.format_features(Format::D16_UNORM),
+            FormatFeatures::empty()
+        );
+        assert_eq!(caps.summary(), "Vulkan 1.3, enabled: triangle fans");
+    }
+
+    #[test]
+    fn subgroup_arithmetic_needs_compute_and_vulkan_1_1() {
+        let caps = |api_version, operations, stages| {
+            let mut caps = DeviceCaps::negotiate(
+                api_version,
+                &DeviceExtensions::empty(),
+                &Features::empty(),
+                true,
+            )
+            .caps;
+            caps.limits.subgroup_size = 32;
+            caps.limits.subgroup_operations = operations;
+            caps.limits.subgroup_stages = stages;
+            caps
+        };
+        let arithmetic = SubgroupFeatures::BASIC | SubgroupFeatures::ARITHMETIC;
+        assert!(caps(Version::V1_1, arithmetic, ShaderStages::COMPUTE).subgroup_arithmetic());
+        assert!(!caps(
+            Version::V1_1,
+            SubgroupFeatures::BASIC,
+            ShaderStages::COMPUTE
+        )
+        .subgroup_arithmetic());
+        assert!(!caps(Version::V1_1, arithmetic, ShaderStages::FRAGMENT).subgroup_arithmetic());
+        assert!(!caps(Version::V1_0, arithmetic, ShaderStages::COMPUTE).subgroup_arithmetic());
+
+        let printed = caps(Version::V1_1, arithmetic, ShaderStages::COMPUTE).to_string();
+        assert!(printed.contains("subgroupSupportedOperations          BASIC | ARITHMETIC"));
+        let printed = caps(
+            Version::V1_1,
+            SubgroupFeatures::empty(),
+            ShaderStages::empty(),
+        );
+        assert!(printed
+            .to_string()
+            .contains("subgroupSupportedStages              none"));
+    }
+
+    #[test]
+    fn multiview_needs_vulkan_1_1() {
+        let features = Features {
+            multiview: true,
+            ..Features::empty()
+        };
+        let setup =
+            DeviceCaps::negotiate(Version::V1_1, &DeviceExtensions::empty(), &features, true);
+        assert!(setup.caps.multiview);
+        assert_eq!(setup.features, features);
+        let setup =
+            DeviceCaps::negotiate(Version::V1_0, &DeviceExtensions::empty(), &features, true);
+        assert!(!setup.caps.multiview);
+        assert_eq!(setup.features, Features::empty());
+    }
+
+    #[test]
+    fn api_versions_by_name() {
+        assert_eq!(parse_api_version("1.1"), Some(Version::V1_1));
+        assert_eq!(parse_api_version("1.3"), Some(Version::V1_3));
+        assert_eq!(parse_api_version("1.0"), None);
+        assert_eq!(parse_api_version("2"), None);
+    }
+}
@@ -0,0 +1,178 @@
+//! The colour scenes are drawn on top of, for `--clear-color` and `--clear-animate`.
+//!
+//! Colours are given as they should look, i.e. sRGB-encoded. Targets in an sRGB or float format
+//! encode what is written to them (or leave it to the display, for HDR), so they are cleared to
+//! the linear value that comes out as the same colour, while UNORM targets are cleared to the
+//! given values as they are.
+
+use vulkano::format::{Format, NumericFormat};
+
+use crate::scene::CLEAR_COLOR;
+
+/// Seconds `--clear-animate` takes to go once round the hues.
+const HUE_PERIOD: f32 = 6.0;
+
+/// What to clear a target to, possibly changing over time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClearColor {
+    /// RGBA, sRGB-encoded, each from 0 to 1.
+    pub color: [f32; 4],
+    /// Cycle the hue of `color` over time instead of keeping it still. Greys, including the
+    /// default black, cycle through bright, saturated colours instead, so the change shows.
+    pub animate: bool,
+}
+
+impl Default for ClearColor {
+    fn default() -> Self {
+        Self {
+            color: CLEAR_COLOR,
+            animate: false,
+        }
+    }
+}
+
+impl ClearColor {
+    /// The same colour all the time.
+    pub fn fixed(color: [f32; 4]) -> Self {
+        Self {
+            color,
+            animate: false,
+        }
+    }
+
+    /// The clear value to use for a target in `format` `time` seconds in.
+    pub fn value(&self, time: f32, format: Format) -> [f32; 4] {
+        let color = if self.animate {
+            cycle_hue(self.color, time)
+        } else {
+            self.color
+        };
+        match format.numeric_format_color() {
+            Some(NumericFormat::SRGB | NumericFormat::SFLOAT | NumericFormat::UFLOAT) => {
+                let [r, g, b, a] = color;
+                [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+            }
+            _ => color,
+        }
+    }
+}
+
+/// Parses `R,G,B,A` or `R,G,B`, each from 0 to 1, alpha defaulting to opaque.
+pub fn parse_clear_color(value: &str) -> Option<[f32; 4]> {
+    let channels = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let color = match channels[..] {
+        [r, g, b] => [r, g, b, 1.0],
+        [r, g, b, a] => [r, g, b, a],
+        _ => return None,
+    };
+    color
+        .iter()
+        .all(|channel| (0.0..=1.0).contains(channel))
+        .then_some(color)
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// `color` with its hue moved on by how far `time` is through [`HUE_PERIOD`].
+fn cycle_hue([r, g, b, a]: [f32; 4], time: f32) -> [f32; 4] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let (hue, saturation, value) = if chroma == 0.0 {
+        (0.0, 1.0, max.max(0.8))
+    } else {
+        let hue = if max == r {
+            (g - b) / chroma
+        } else if max == g {
+            (b - r) / chroma + 2.0
+        } else {
+            (r - g) / chroma + 4.0
+        } / 6.0;
+        (hue, chroma / max, max)
+    };
+    let hue = (hue + time / HUE_PERIOD).rem_euclid(1.0);
+
+    // Back from HSV, with the hue in sixths of the way round.
+    let sector = hue * 6.0;
+    let chroma = value * saturation;
+    let second = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let [r, g, b] = match sector as u32 {
+        0 => [chroma, second, 0.0],
+        1 => [second, chroma, 0.0],
+        2 => [0.0, chroma, second],
+        3 => [0.0, second, chroma],
+        4 => [second, 0.0, chroma],
+        _ => [chroma, 0.0, second],
+    };
+    let min = value - chroma;
+    [r + min, g + min, b + min, a]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 4], b: [f32; 4]) {
+        assert!(
+            a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-4),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn colours_are_validated() {
+        assert_eq!(
+            parse_clear_color("0.1,0.1,0.12,1"),
+            Some([0.1, 0.1, 0.12, 1.0])
+        );
+        assert_eq!(parse_clear_color("1, 0, 0"), Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_clear_color("1.5,0,0,1"), None);
+        assert_eq!(parse_clear_color("0,0,-0.1,1"), None);
+        assert_eq!(parse_clear_color("0,0"), None);
+        assert_eq!(parse_clear_color("0,0,0,1,1"), None);
+        assert_eq!(parse_clear_color("red"), None);
+    }
+
+    #[test]
+    fn srgb_targets_are_cleared_to_linear_values() {
+        let clear = ClearColor::fixed([0.5, 0.0, 1.0, 0.5]);
+        assert_eq!(
+            clear.value(0.0, Format::B8G8R8A8_UNORM),
+            [0.5, 0.0, 1.0, 0.5]
+        );
+        for format in [Format::B8G8R8A8_SRGB, Format::R16G16B16A16_SFLOAT] {
+            assert_close(clear.value(0.0, format), [0.2140, 0.0, 1.0, 0.5]);
+        }
+    }
+
+    #[test]
+    fn animating_cycles_the_hue() {
+        let clear = ClearColor {
+            color: [1.0, 0.0, 0.0, 1.0],
+            animate: true,
+        };
+        let format = Format::R8G8B8A8_UNORM;
+        assert_close(clear.value(0.0, format), [1.0, 0.0, 0.0, 1.0]);
+        assert_close(clear.value(HUE_PERIOD / 3.0, format), [0.0, 1.0, 0.0, 1.0]);
+        assert_close(
+            clear.value(HUE_PERIOD * 2.0 / 3.0, format),
+            [0.0, 0.0, 1.0, 1.0],
+        );
+        assert_close(clear.value(HUE_PERIOD, format), [1.0, 0.0, 0.0, 1.0]);
+        // Black still visibly changes.
+        let black = ClearColor {
+            animate: true,
+            ..ClearColor::default()
+        };
+        assert_close(black.value(0.0, format), [0.8, 0.0, 0.0, 1.0]);
+    }
+}
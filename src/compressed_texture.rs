@@ -0,0 +1,374 @@
+//! Block-compressed textures loaded from DDS and KTX2 files.
+//!
+//! Only the base level of a 2D texture is read, as [`Texture`] has no mip levels. KTX2 files
+//! must not be supercompressed (Basis Universal or zstd). [`Texture::from_compressed`] uploads
+//! the blocks as they are when the device can sample the format, and decodes them to RGBA8 on
+//! the CPU otherwise.
+//!
+//! [`Texture`]: crate::texture::Texture
+//! [`Texture::from_compressed`]: crate::texture::Texture::from_compressed
+
+use std::path::Path;
+
+use vulkano::format::{Format, FormatFeatures};
+
+use crate::block_decode::{
+    decode_bc1, decode_bc3, decode_bc7, decode_etc2_rgb, decode_etc2_rgba, Block,
+};
+use crate::caps::DeviceCaps;
+use crate::error::RendererError;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const KTX2_MAGIC: &[u8; 12] = b"\xabKTX 20\xbb\r\n\x1a\n";
+
+/// The block-compressed formats that can be loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressedFormat {
+    Bc1,
+    Bc3,
+    Bc7,
+    Etc2Rgb8,
+    Etc2Rgba8,
+}
+
+impl CompressedFormat {
+    /// Bytes per 4x4 block.
+    pub fn block_size(self) -> usize {
+        match self {
+            Self::Bc1 | Self::Etc2Rgb8 => 8,
+            Self::Bc3 | Self::Bc7 | Self::Etc2Rgba8 => 16,
+        }
+    }
+
+    /// The Vulkan format holding the blocks as they are.
+    pub fn vulkan_format(self, srgb: bool) -> Format {
+        match (self, srgb) {
+            (Self::Bc1, false) => Format::BC1_RGBA_UNORM_BLOCK,
+            (Self::Bc1, true) => Format::BC1_RGBA_SRGB_BLOCK,
+            (Self::Bc3, false) => Format::BC3_UNORM_BLOCK,
+            (Self::Bc3, true) => Format::BC3_SRGB_BLOCK,
+            (Self::Bc7, false) => Format::BC7_UNORM_BLOCK,
+            (Self::Bc7, true) => Format::BC7_SRGB_BLOCK,
+            (Self::Etc2Rgb8, false) => Format::ETC2_R8G8B8_UNORM_BLOCK,
+            (Self::Etc2Rgb8, true) => Format::ETC2_R8G8B8_SRGB_BLOCK,
+            (Self::Etc2Rgba8, false) => Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+            (Self::Etc2Rgba8, true) => Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+        }
+    }
+
+    /// Whether the device can sample the format directly. The feature only says the format
+    /// family is there; the format properties have the final word.
+    pub fn is_supported(self, caps: &DeviceCaps, srgb: bool) -> bool {
+        let family = match self {
+            Self::Bc1 | Self::Bc3 | Self::Bc7 => caps.texture_compression_bc,
+            Self::Etc2Rgb8 | Self::Etc2Rgba8 => caps.texture_compression_etc2,
+        };
+        family
+            && caps
+                .format_features(self.vulkan_format(srgb))
+                .contains(FormatFeatures::SAMPLED_IMAGE | FormatFeatures::TRANSFER_DST)
+    }
+
+    fn decode_block(self, block: &[u8]) -> Block {
+        match self {
+            Self::Bc1 => decode_bc1(block.try_into().unwrap()),
+            Self::Bc3 => decode_bc3(block.try_into().unwrap()),
+            Self::Bc7 => decode_bc7(block.try_into().unwrap()),
+            Self::Etc2Rgb8 => decode_etc2_rgb(block.try_into().unwrap()),
+            Self::Etc2Rgba8 => decode_etc2_rgba(block.try_into().unwrap()),
+        }
+    }
+}
+
+/// The base level of a block-compressed 2D texture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressedImage {
+    pub format: CompressedFormat,
+    /// The texels are sRGB-encoded.
+    pub srgb: bool,
+    pub width: u32,
+    pub height: u32,
+    /// The blocks, row by row. Partial blocks at the right and bottom edges are stored whole.
+    pub data: Vec<u8>,
+}
+
+impl CompressedImage {
+    /// Reads a DDS or KTX2 file.
+    pub fn load(path: &Path) -> Result<Self, RendererError> {
+        let bytes = std::fs::read(path).map_err(|err| {
+            RendererError::InvalidTexture(format!("failed to read {}: {err}", path.display()))
+        })?;
+        Self::parse(&bytes)
+    }
+
+    /// Parses the contents of a DDS or KTX2 file, telling them apart by their magic bytes.
+    pub fn parse(bytes: &[u8]) -> Result<Self, RendererError> {
+        if bytes.starts_with(DDS_MAGIC) {
+            Self::parse_dds(bytes)
+        } else if bytes.starts_with(KTX2_MAGIC) {
+            Self::parse_ktx2(bytes)
+        } else {
+            Err(invalid("not a DDS or KTX2 file"))
+        }
+    }
+
+    fn parse_dds(bytes: &[u8]) -> Result<Self, RendererError> {
+        const HEADER_END: usize = 128;
+        const DX10_HEADER_END: usize = HEADER_END + 20;
+
+        let height = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 16)?;
+        let four_cc = bytes
+            .get(84..88)
+            .ok_or_else(|| invalid("truncated DDS header"))?;
+        let (format, srgb, data_start) = match four_cc {
+            b"DXT1" => (CompressedFormat::Bc1, false, HEADER_END),
+            b"DXT5" => (CompressedFormat::Bc3, false, HEADER_END),
+            b"DX10" => {
+                // The DXGI_FORMAT values of the formats we load.
+                let (format, srgb) = match read_u32(bytes, HEADER_END)? {
+                    71 => (CompressedFormat::Bc1, false),
+                    72 => (CompressedFormat::Bc1, true),
+                    77 => (CompressedFormat::Bc3, false),
+                    78 => (CompressedFormat::Bc3, true),
+                    98 => (CompressedFormat::Bc7, false),
+                    99 => (CompressedFormat::Bc7, true),
+                    other => return Err(invalid(format!("unsupported DXGI format {other}"))),
+                };
+                (format, srgb, DX10_HEADER_END)
+            }
+            other => {
+                return Err(invalid(format!(
+                    "unsupported DDS format {:?}",
+                    String::from_utf8_lossy(other)
+                )))
+            }
+        };
+        Self::new(
+            format,
+            srgb,
+            width,
+            height,
+            &bytes[data_start.min(bytes.len())..],
+        )
+    }
+
+    fn parse_ktx2(bytes: &[u8]) -> Result<Self, RendererError> {
+        const LEVEL_INDEX_START: usize = 80;
+
+        // Raw `VkFormat` values.
+        let (format, srgb) = match read_u32(bytes, 12)? {
+            133 => (CompressedFormat::Bc1, false),
+            134 => (CompressedFormat::Bc1, true),
+            137 => (CompressedFormat::Bc3, false),
+            138 => (CompressedFormat::Bc3, true),
+            145 => (CompressedFormat::Bc7, false),
+            146 => (CompressedFormat::Bc7, true),
+            147 => (CompressedFormat::Etc2Rgb8, false),
+            148 => (CompressedFormat::Etc2Rgb8, true),
+            151 => (CompressedFormat::Etc2Rgba8, false),
+            152 => (CompressedFormat::Etc2Rgba8, true),
+            0 => {
+                return Err(invalid(
+                    "KTX2 file without a Vulkan format (Basis Universal?)",
+                ))
+            }
+            other => return Err(invalid(format!("unsupported Vulkan format {other}"))),
+        };
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?;
+        if read_u32(bytes, 28)? > 1 {
+            return Err(invalid("3D textures aren't supported"));
+        }
+        if read_u32(bytes, 44)? != 0 {
+            return Err(invalid("supercompressed KTX2 files aren't supported"));
+        }
+
+        // The level index starts with the base level.
+        let offset = read_u64(bytes, LEVEL_INDEX_START)?;
+        let length = read_u64(bytes, LEVEL_INDEX_START + 8)?;
+        let data = usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(length).ok())
+            .and_then(|(offset, length)| bytes.get(offset..offset.checked_add(length)?))
+            .ok_or_else(|| invalid("base level lies outside the file"))?;
+        Self::new(format, srgb, width, height, data)
+    }
+
+    /// Checks that `data` holds enough blocks for the size, and drops anything after them (such
+    /// as the other mip levels in a DDS file).
+    fn new(
+        format: CompressedFormat,
+        srgb: bool,
+        width: u32,
+        height: u32,
+        data: &[u8],
+    ) -> Result<Self, RendererError> {
+        if width == 0 || height == 0 {
+            return Err(invalid("image is empty"));
+        }
+        let size = blocks(width) * blocks(height) * format.block_size();
+        let data = data.get(..size).ok_or_else(|| {
+            invalid(format!(
+                "{width}x{height} {format:?} image needs {size} bytes, found {}",
+                data.len()
+            ))
+        })?;
+        Ok(Self {
+            format,
+            srgb,
+            width,
+            height,
+            data: data.to_vec(),
+        })
+    }
+
+    /// The Vulkan format holding the blocks as they are.
+    pub fn vulkan_format(&self) -> Format {
+        self.format.vulkan_format(self.srgb)
+    }
+
+    /// Decodes the blocks into tightly packed RGBA8 pixels.
+    pub fn decode_rgba8(&self) -> Vec<u8> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut pixels = vec![0; width * height * 4];
+        let blocks_per_row = blocks(self.width);
+        for (index, block) in self.data.chunks_exact(self.format.block_size()).enumerate() {
+            let (block_x, block_y) = (index % blocks_per_row * 4, index / blocks_per_row * 4);
+            for (texel, color) in self.format.decode_block(block).iter().enumerate() {
+                let (x, y) = (block_x + texel % 4, block_y + texel / 4);
+                if x < width && y < height {
+                    let offset = (y * width + x) * 4;
+                    pixels[offset..offset + 4].copy_from_slice(color);
+                }
+            }
+        }
+        pixels
+    }
+}
+
+/// Number of 4x4 blocks covering `texels` texels.
+fn blocks(texels: u32) -> usize {
+    texels.div_ceil(4) as usize
+}
+
+fn invalid(msg: impl Into<String>) -> RendererError {
+    RendererError::InvalidTexture(msg.into())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, RendererError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("truncated header"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, RendererError> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("truncated header"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BC1 block of solid red.
+    const RED_BC1: [u8; 8] = [0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0];
+
+    fn dds(four_cc: &[u8; 4], dxgi_format: Option<u32>, width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = vec![0; 128];
+        bytes[..4].copy_from_slice(DDS_MAGIC);
+        bytes[4..8].copy_from_slice(&124u32.to_le_bytes());
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[84..88].copy_from_slice(four_cc);
+        if let Some(format) = dxgi_format {
+            bytes.extend(format.to_le_bytes());
+            bytes.extend([0; 16]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_dds_with_and_without_dx10_header() {
+        let mut bytes = dds(b"DXT1", None, 4, 4);
+        bytes.extend(RED_BC1);
+        // A 2x2 mip level after the base one.
+        bytes.extend(RED_BC1);
+        let image = CompressedImage::parse(&bytes).unwrap();
+        assert_eq!(image.format, CompressedFormat::Bc1);
+        assert!(!image.srgb);
+        assert_eq!(image.data, RED_BC1);
+
+        let mut bytes = dds(b"DX10", Some(99), 5, 3);
+        bytes.extend([0x40; 32]);
+        let image = CompressedImage::parse(&bytes).unwrap();
+        assert_eq!(image.vulkan_format(), Format::BC7_SRGB_BLOCK);
+        assert_eq!((image.width, image.height, image.data.len()), (5, 3, 32));
+    }
+
+    #[test]
+    fn parses_ktx2_base_level() {
+        let mut bytes = vec![0; 104];
+        bytes[..12].copy_from_slice(KTX2_MAGIC);
+        bytes[12..16].copy_from_slice(&152u32.to_le_bytes());
+        bytes[20..24].copy_from_slice(&4u32.to_le_bytes());
+        bytes[24..28].copy_from_slice(&4u32.to_le_bytes());
+        bytes[80..88].copy_from_slice(&104u64.to_le_bytes());
+        bytes[88..96].copy_from_slice(&16u64.to_le_bytes());
+        bytes.extend([7; 16]);
+        let image = CompressedImage::parse(&bytes).unwrap();
+        assert_eq!(image.vulkan_format(), Format::ETC2_R8G8B8A8_SRGB_BLOCK);
+        assert_eq!(image.data, [7; 16]);
+
+        bytes[44..48].copy_from_slice(&2u32.to_le_bytes());
+        assert!(CompressedImage::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(CompressedImage::parse(b"PNG").is_err());
+        // Too little data for an 8x8 image.
+        let mut bytes = dds(b"DXT1", None, 8, 8);
+        bytes.extend(RED_BC1);
+        assert!(CompressedImage::parse(&bytes).is_err());
+        assert!(CompressedImage::parse(&dds(b"ATI2", None, 4, 4)).is_err());
+    }
+
+    #[test]
+    fn decoding_crops_partial_blocks() {
+        let image = CompressedImage {
+            format: CompressedFormat::Bc1,
+            srgb: false,
+            width: 5,
+            height: 2,
+            data: [RED_BC1, [0; 8]].concat(),
+        };
+        let pixels = image.decode_rgba8();
+        assert_eq!(pixels.len(), 5 * 2 * 4);
+        assert_eq!(pixels[..4], [255, 0, 0, 255]);
+        // The fifth texel of the first row comes from the second (black) block.
+        assert_eq!(pixels[16..20], [0, 0, 0, 255]);
+        assert_eq!(pixels[20..24], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn support_needs_feature_and_format() {
+        let mut caps = DeviceCaps::negotiate(
+            vulkano::Version::V1_3,
+            &vulkano::device::DeviceExtensions::empty(),
+            &vulkano::device::Features::empty(),
+            true,
+        )
+        .caps;
+        let sampled = FormatFeatures::SAMPLED_IMAGE | FormatFeatures::TRANSFER_DST;
+        caps.formats = vec![(Format::BC7_SRGB_BLOCK, sampled)];
+        assert!(!CompressedFormat::Bc7.is_supported(&caps, true));
+        caps.texture_compression_bc = true;
+        assert!(CompressedFormat::Bc7.is_supported(&caps, true));
+        assert!(!CompressedFormat::Bc7.is_supported(&caps, false));
+        assert!(!CompressedFormat::Etc2Rgb8.is_supported(&caps, true));
+    }
+}
@@ -0,0 +1,260 @@
+//! The most recent log messages, shown over the scene on request.
+//!
+//! [`install`] puts a [`CapturingLogger`] in front of the real logger, which keeps a copy of
+//! every record that gets logged in [`CAPTURED`]. A window's [`ConsoleOverlay`] draws the last
+//! few of them on a dark panel along the top, the newest at the bottom, the older ones fading.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::SecondaryAutoCommandBuffer;
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::text::{TextRenderer, FONT_SIZE};
+
+/// How many messages [`CAPTURED`] holds before dropping the oldest.
+const CAPTURED_LINES: usize = 256;
+
+/// How many messages the overlay shows at once.
+pub const VISIBLE_LINES: usize = 12;
+
+/// Messages are drawn at full strength for this long, then fade over [`FADE_DURATION`] to
+/// [`FADED_ALPHA`], so they stay readable.
+const FADE_AFTER: Duration = Duration::from_secs(5);
+const FADE_DURATION: Duration = Duration::from_secs(5);
+const FADED_ALPHA: f32 = 0.35;
+
+/// Space between the edge of the panel and the text, in logical pixels.
+const PADDING: f32 = 6.0;
+
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.7];
+
+/// The messages logged since [`install`], up to [`CAPTURED_LINES`] of them.
+pub static CAPTURED: LogRing = LogRing::new(CAPTURED_LINES);
+
+/// One line of a logged message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogLine {
+    pub level: Level,
+    pub text: String,
+    pub logged_at: Instant,
+}
+
+/// A fixed number of the most recent log lines.
+pub struct LogRing {
+    lines: Mutex<VecDeque<LogLine>>,
+    capacity: usize,
+}
+
+impl LogRing {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            lines: Mutex::new(VecDeque::new()),
+            capacity,
+        }
+    }
+
+    /// Adds each line of `message`, dropping the oldest lines to make room.
+    pub fn push(&self, level: Level, message: &str) {
+        let logged_at = Instant::now();
+        // A panic while holding the lock can't have left the queue half changed.
+        let mut lines = self.lines.lock().unwrap_or_else(PoisonError::into_inner);
+        for text in message.lines() {
+            if lines.len() == self.capacity {
+                lines.pop_front();
+            }
+            lines.push_back(LogLine {
+                level,
+                text: text.to_owned(),
+                logged_at,
+            });
+        }
+    }
+
+    /// Up to `count` of the most recent lines, oldest first.
+    pub fn recent(&self, count: usize) -> Vec<LogLine> {
+        let lines = self.lines.lock().unwrap_or_else(PoisonError::into_inner);
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+
+    /// Like [`recent`](Self::recent), but gives up rather than waiting for the lock, for callers
+    /// that may be holding it already, like a panic hook.
+    pub fn try_recent(&self, count: usize) -> Option<Vec<LogLine>> {
+        let lines = match self.lines.try_lock() {
+            Ok(lines) => lines,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return None,
+        };
+        Some(
+            lines
+                .iter()
+                .skip(lines.len().saturating_sub(count))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// Passes every record on to another logger, keeping a copy of those it logs.
+pub struct CapturingLogger<L> {
+    inner: L,
+    ring: &'static LogRing,
+}
+
+impl<L: Log> CapturingLogger<L> {
+    pub fn new(inner: L, ring: &'static LogRing) -> Self {
+        Self { inner, ring }
+    }
+}
+
+impl<L: Log> Log for CapturingLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        // Only what the terminal shows too, so `RUST_LOG` filters both alike.
+        if self.inner.enabled(record.metadata()) {
+            self.ring.push(record.level(), &record.args().to_string());
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Makes `inner` the logger, capturing what it logs into [`CAPTURED`]. `max_level` should be
+/// the most verbose level `inner` lets through.
+pub fn install(inner: impl Log + 'static, max_level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(CapturingLogger::new(inner, &CAPTURED)))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// How opaque to draw a line logged `age` ago.
+fn line_alpha(age: Duration) -> f32 {
+    let fading = age.saturating_sub(FADE_AFTER).as_secs_f32() / FADE_DURATION.as_secs_f32();
+    1.0 - (1.0 - FADED_ALPHA) * fading.min(1.0)
+}
+
+fn level_color(level: Level) -> [f32; 3] {
+    match level {
+        Level::Error => [1.0, 0.35, 0.3],
+        Level::Warn => [1.0, 0.8, 0.3],
+        Level::Info => [0.9, 0.9, 0.9],
+        Level::Debug | Level::Trace => [0.6, 0.6, 0.6],
+    }
+}
+
+/// Draws the captured log over a window's scene while shown. Starts out hidden.
+#[derive(Default)]
+pub struct ConsoleOverlay {
+    /// Made the first time the console is shown, so scenes drawn without graphics pipelines
+    /// don't get one for the console either.
+    text: Option<TextRenderer>,
+    visible: bool,
+}
+
+impl ConsoleOverlay {
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Records drawing the most recent lines of [`CAPTURED`] into a target of `extent` pixels
+    /// with `pixels_per_point` of them to a logical pixel, in `subpass`, which must be the same
+    /// every time, with a command buffer from `allocator`. Returns `None` while hidden.
+    ///
+    /// The text is rasterized at that density, again whenever it changes, so it stays sharp and
+    /// the same size on screen whatever the monitor's scale factor or the render scale.
+    pub fn record(
+        &mut self,
+        ctx: &VulkanContext,
+        allocator: &StandardCommandBufferAllocator,
+        subpass: Subpass,
+        extent: [u32; 2],
+        pixels_per_point: f64,
+    ) -> Result<Option<Arc<SecondaryAutoCommandBuffer>>, RendererError> {
+        if !self.visible {
+            return Ok(None);
+        }
+        if self.text.is_none() {
+            self.text = Some(TextRenderer::new(ctx, subpass.clone())?);
+        }
+        let text_renderer = self.text.as_mut().unwrap();
+        // Whole pixels, so a density that barely changes doesn't rasterize the atlas again.
+        let font_size = (f64::from(FONT_SIZE) * pixels_per_point).round().max(1.0) as f32;
+        text_renderer.set_font_size(ctx, font_size)?;
+        let padding = (f64::from(PADDING) * pixels_per_point) as f32;
+        let [cell_width, cell_height] = text_renderer.cell().map(|size| size as f32);
+        let width = extent[0] as f32;
+        let columns = ((width - 2.0 * padding) / cell_width).max(0.0) as usize;
+        text_renderer.queue_rect(
+            [0.0, 0.0],
+            [width, VISIBLE_LINES as f32 * cell_height + 2.0 * padding],
+            PANEL_COLOR,
+        );
+
+        // The newest line sits at the bottom of the panel and pushes the others up.
+        let lines = CAPTURED.recent(VISIBLE_LINES);
+        let first_row = VISIBLE_LINES - lines.len();
+        let now = Instant::now();
+        for (row, line) in (first_row..).zip(&lines) {
+            let [r, g, b] = level_color(line.level);
+            let alpha = line_alpha(now.saturating_duration_since(line.logged_at));
+            let text: String = line.text.chars().take(columns).collect();
+            text_renderer.queue_text(
+                [padding, padding + row as f32 * cell_height],
+                &text,
+                [r, g, b, alpha],
+            );
+        }
+        text_renderer.record(ctx, allocator, subpass, extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_keeps_the_newest_lines() {
+        let ring = LogRing::new(3);
+        ring.push(Level::Info, "one");
+        ring.push(Level::Warn, "two\nthree");
+        ring.push(Level::Error, "four");
+        let texts: Vec<_> = ring.recent(10).into_iter().map(|line| line.text).collect();
+        assert_eq!(texts, ["two", "three", "four"]);
+        let newest = ring.recent(1);
+        assert_eq!(newest.len(), 1);
+        assert_eq!(newest[0].level, Level::Error);
+    }
+
+    #[test]
+    fn old_lines_fade_but_stay_readable() {
+        assert_eq!(line_alpha(Duration::ZERO), 1.0);
+        assert_eq!(line_alpha(FADE_AFTER), 1.0);
+        let halfway = line_alpha(FADE_AFTER + FADE_DURATION / 2);
+        assert!(FADED_ALPHA < halfway && halfway < 1.0);
+        assert!((line_alpha(FADE_AFTER + FADE_DURATION) - FADED_ALPHA).abs() < 1e-6);
+        assert!((line_alpha(Duration::from_secs(3600)) - FADED_ALPHA).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,461 @@
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::device::{
+    Device, DeviceCreateInfo, DeviceExtensions, DeviceOwned, Queue, QueueCreateInfo,
+};
+use vulkano::instance::{Instance, InstanceCreateFlags, InstanceCreateInfo, InstanceExtensions};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::swapchain::Surface;
+use vulkano::sync::{self, GpuFuture};
+use vulkano::{Validated, Version, VulkanError, VulkanLibrary, VulkanObject};
+
+use crate::caps::DeviceCaps;
+use crate::crash_report;
+use crate::device_selection::{
+    next_device, select_device, DeviceCandidate, DeviceListing, DeviceSelection,
+};
+use crate::error::RendererError;
+use crate::memory_report::MemoryTracker;
+use crate::sampler::SamplerCache;
+use crate::validation::{self, LayerFeature};
+
+/// Everything needed to create and submit GPU work, independent of any window.
+///
+/// The windowed app and the headless renderer both build one of these, so the device selection
+/// and allocator setup only exist in one place.
+///
+/// Fields drop in declaration order, so the allocators go before the queue and device, and the
+/// instance goes last. Keep it that way when adding fields. Anything allocated from the context
+/// must be dropped before it, and only once the GPU is done with it.
+pub struct VulkanContext {
+    /// Tallies the memory used by the buffers and images the crate creates.
+    pub memory_tracker: Arc<MemoryTracker>,
+    pub descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    pub command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    /// Samplers shared by every texture sampled the same way.
+    pub samplers: SamplerCache,
+    pub memory_allocator: Arc<StandardMemoryAllocator>,
+    pub queue: Arc<Queue>,
+    pub device: Arc<Device>,
+    pub instance: Arc<Instance>,
+    /// The optional functionality that was enabled on the device. Check this rather than the
+    /// device's properties before relying on anything beyond baseline Vulkan.
+    pub caps: DeviceCaps,
+}
+
+impl VulkanContext {
+    /// Creates a context without any surface, for offscreen rendering and tests.
+    pub fn headless(
+        selection: &DeviceSelection,
+        forced_api_version: Option<Version>,
+    ) -> Result<Self, RendererError> {
+        let instance = create_instance(
+            InstanceExtensions::empty(),
+            InstanceExtensions::empty(),
+            forced_api_version,
+            None,
+        )?;
+        Self::new(
+            instance,
+            DeviceExtensions::empty(),
+            DeviceExtensions::empty(),
+            None,
+            selection,
+            forced_api_version,
+        )
+    }
+
+    /// Picks a physical device supporting `device_extensions` (and able to present to `surface`,
+    /// if given) and creates a logical device with a single graphics queue on it. Those of
+    /// `optional_extensions` the device supports are enabled too.
+    ///
+    /// The device is used with the highest API version both it and the instance support, unless
+    /// `forced_api_version` caps it lower. The instance should have been created by
+    /// [`create_instance`] with the same `forced_api_version`.
+    pub fn new(
+        instance: Arc<Instance>,
+        device_extensions: DeviceExtensions,
+        optional_extensions: DeviceExtensions,
+        surface: Option<&Surface>,
+        selection: &DeviceSelection,
+        forced_api_version: Option<Version>,
+    ) -> Result<Self, RendererError> {
+        let (physical_device, queue_family_index) =
+            select_physical_device(&instance, &device_extensions, surface, selection)?;
+
+        log::info!(
+            "Using device: {} (type: {:?}, driver: {})",
+            physical_device.properties().device_name,
+            physical_device.properties().device_type,
+            physical_device
+                .properties()
+                .driver_name
+                .as_deref()
+                .unwrap_or("unknown"),
+        );
+
+        // vulkano already caps the device's version at the instance's, which is where a forced
+        // version was applied.
+        let mut setup = DeviceCaps::query(
+            &physical_device,
+            queue_family_index,
+            forced_api_version.is_none(),
+        )?;
+        if !device_extensions.khr_swapchain {
+            setup = setup.without_presentation();
+        }
+        let caps = setup.caps;
+        log::info!("Capabilities: {}", caps.summary());
+        crash_report::set_device(&physical_device, &caps);
+        if caps.portability_subset {
+            log::info!("Device only implements the Vulkan portability subset");
+        }
+
+        let enabled_extensions = setup
+            .extensions
+            .union(&device_extensions)
+            .union(&optional_extensions.intersection(physical_device.supported_extensions()));
+        let enabled_features = setup.features;
+        let memory_tracker = Arc::new(MemoryTracker::new(
+            physical_device.clone(),
+            caps.memory_budget,
+        ));
+
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                enabled_extensions,
+                enabled_features,
+                // provide the desired queue family by index.
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )?;
+
+        let queue = queues.next().unwrap();
+
+        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+        // Scenes always draw into secondary command buffers, so one-off renders need a few.
+        let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
+            device.clone(),
+            StandardCommandBufferAllocatorCreateInfo {
+                secondary_buffer_count: 4,
+                ..Default::default()
+            },
+        ));
+        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+            device.clone(),
+            Default::default(),
+        ));
+        let samplers = SamplerCache::new(device.clone(), &caps);
+
+        let ctx = Self {
+            memory_tracker,
+            descriptor_set_allocator,
+            command_buffer_allocator,
+            samplers,
+            memory_allocator,
+            queue,
+            device,
+            instance,
+            caps,
+        };
+        if ctx.debug_names_enabled() {
+            if let Err(err) = name_device(&ctx.device, "device") {
+                log::warn!("Failed to name the device: {err}");
+            }
+            ctx.name_object(&ctx.queue, "graphics queue");
+        }
+        Ok(ctx)
+    }
+
+    /// Whether [`name_object`](Self::name_object) does anything, i.e. `VK_EXT_debug_utils` is
+    /// enabled on the instance.
+    pub fn debug_names_enabled(&self) -> bool {
+        self.instance.enabled_extensions().ext_debug_utils
+    }
+
+    /// Names `object` in validation messages and captures (e.g. in RenderDoc), which otherwise
+    /// only show its handle. Does nothing without `VK_EXT_debug_utils`, which is only enabled in
+    /// debug builds.
+    pub fn name_object<T: VulkanObject + DeviceOwned>(&self, object: &T, name: &str) {
+        if !self.debug_names_enabled() {
+            return;
+        }
+        // A name is only a debugging aid, so failing to set one isn't worth failing over.
+        if let Err(err) = self.device.set_debug_utils_object_name(object, Some(name)) {
+            log::warn!("Failed to name `{name}`: {err}");
+        }
+    }
+
+    /// Records a one-off command buffer, submits it and blocks until the GPU has finished it.
+    ///
+    /// Only meant for setup work like uploads and readbacks, never for per-frame rendering.
+    pub fn submit_and_wait(
+        &self,
+        record: impl FnOnce(
+            &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        ) -> Result<(), RendererError>,
+    ) -> Result<(), RendererError> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.as_ref(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        record(&mut builder)?;
+        let command_buffer = builder.build()?;
+
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?
+            .wait(None)?;
+
+        Ok(())
+    }
+}
+
+/// vulkano only names objects that are owned by a device, which the device itself isn't.
+fn name_device(device: &Device, name: &str) -> Result<(), VulkanError> {
+    use ash::vk::Handle;
+
+    let name = std::ffi::CString::new(name).unwrap();
+    let info = ash::vk::DebugUtilsObjectNameInfoEXT {
+        object_type: ash::vk::ObjectType::DEVICE,
+        object_handle: device.handle().as_raw(),
+        p_object_name: name.as_ptr(),
+        ..Default::default()
+    };
+    // SAFETY: the caller checked that `VK_EXT_debug_utils` is enabled on the instance, so the
+    // function is loaded. The name outlives the call.
+    unsafe {
+        (device
+            .instance()
+            .fns()
+            .ext_debug_utils
+            .set_debug_utils_object_name_ext)(device.handle(), &info)
+        .result()
+        .map_err(VulkanError::from)
+    }
+}
+
+/// How many times loading the Vulkan library and creating the instance are each tried before
+/// giving up. Right after a driver install, or early in a remote session, the loader can take a
+/// moment to become usable.
+const STARTUP_ATTEMPTS: u32 = 4;
+
+/// How long to wait before trying again the first time, doubling before each try after that.
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Loads the Vulkan library and creates an instance with `enabled_extensions` enabled, along
+/// with those of `optional_extensions` the library supports. Either is tried again a few times,
+/// with a growing delay, before the error is returned.
+///
+/// The instance supports the highest API version the library does, or at most
+/// `forced_api_version` if given. Debug builds also enable `VK_EXT_debug_utils` where the
+/// library has it, so objects can be given names with [`VulkanContext::name_object`]. With
+/// `layer_feature` the validation layer is enabled too, see [`validation::enable`].
+pub fn create_instance(
+    mut enabled_extensions: InstanceExtensions,
+    optional_extensions: InstanceExtensions,
+    forced_api_version: Option<Version>,
+    layer_feature: Option<LayerFeature>,
+) -> Result<Arc<Instance>, RendererError> {
+    let library = retry(
+        "load the Vulkan library",
+        VulkanLibrary::new,
+        |_| true,
+        thread::sleep,
+    )
+    .map_err(RendererError::NoVulkanLibrary)?;
+    enabled_extensions |= library
+        .supported_extensions()
+        .intersection(&optional_extensions);
+    if cfg!(debug_assertions) && library.supported_extensions().ext_debug_utils {
+        enabled_extensions.ext_debug_utils = true;
+    }
+
+    let mut create_info = InstanceCreateInfo {
+        flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+        enabled_extensions,
+        // vulkano defaults to the newest version it knows about, which the instance then
+        // lowers to what the library supports.
+        max_api_version: forced_api_version,
+        ..InstanceCreateInfo::default()
+    };
+    if let Some(feature) = layer_feature {
+        validation::enable(&library, &mut create_info, feature);
+    }
+    // The create info isn't `Clone`, so each attempt gets a copy of what was set above.
+    let copy = || InstanceCreateInfo {
+        flags: create_info.flags,
+        enabled_layers: create_info.enabled_layers.clone(),
+        enabled_extensions: create_info.enabled_extensions,
+        enabled_validation_features: create_info.enabled_validation_features.clone(),
+        max_api_version: create_info.max_api_version,
+        ..InstanceCreateInfo::default()
+    };
+    retry(
+        "create a Vulkan instance",
+        || Instance::new(library.clone(), copy()),
+        // Anything else is about what was asked for, which won't change by asking again.
+        |err| {
+            matches!(
+                err,
+                Validated::Error(
+                    VulkanError::InitializationFailed | VulkanError::IncompatibleDriver
+                )
+            )
+        },
+        thread::sleep,
+    )
+    .map_err(RendererError::NoInstance)
+}
+
+/// Calls `attempt` until it succeeds, at most [`STARTUP_ATTEMPTS`] times, sleeping with `sleep`
+/// for [`FIRST_RETRY_DELAY`] and then twice as long each time between them. Stops at the first
+/// error `transient` says trying again won't fix.
+fn retry<T, E: fmt::Display>(
+    what: &str,
+    mut attempt: impl FnMut() -> Result<T, E>,
+    transient: impl Fn(&E) -> bool,
+    mut sleep: impl FnMut(Duration),
+) -> Result<T, E> {
+    let mut delay = FIRST_RETRY_DELAY;
+    for tries in 1.. {
+        match attempt() {
+            Err(err) if tries < STARTUP_ATTEMPTS && transient(&err) => {
+                log::warn!("Could not {what}, trying again in {delay:?}: {err}");
+                sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    unreachable!()
+}
+
+/// Picks the physical device we want to render with according to `selection`, along with the
+/// queue family to use on it. See [`select_device`] for the policy.
+pub fn select_physical_device(
+    instance: &Arc<Instance>,
+    device_extensions: &DeviceExtensions,
+    surface: Option<&Surface>,
+    selection: &DeviceSelection,
+) -> Result<(Arc<PhysicalDevice>, u32), RendererError> {
+    let (physical_devices, candidates) = candidates(instance, device_extensions, surface)?;
+    let selected = select_device(&candidates, selection)?;
+    if selected.is_software_renderer() {
+        log::warn!(
+            "`{}` is a software renderer. Everything will work, but expect single-digit frame rates.",
+            selected.name
+        );
+    }
+    Ok((
+        physical_devices[selected.index].clone(),
+        selected.queue_families[0],
+    ))
+}
+
+/// The position in the device list of the next device after `current` that supports
+/// `device_extensions` and can present to `surface`, for cycling through them. `None` if there
+/// is no other.
+pub fn next_physical_device(
+    instance: &Arc<Instance>,
+    device_extensions: &DeviceExtensions,
+    surface: &Surface,
+    current: &PhysicalDevice,
+) -> Result<Option<usize>, RendererError> {
+    let (physical_devices, candidates) = candidates(instance, device_extensions, Some(surface))?;
+    let Some(current) = physical_devices
+        .iter()
+        .position(|p| p.handle() == current.handle())
+    else {
+        return Ok(None);
+    };
+    Ok(next_device(&candidates, current).map(|c| c.index))
+}
+
+/// Describes every physical device for `--list-devices`, in the order `--gpu` numbers them.
+/// `device_extensions` are the ones the renderer requires.
+pub fn list_devices(
+    instance: &Arc<Instance>,
+    device_extensions: &DeviceExtensions,
+) -> Result<Vec<DeviceListing>, RendererError> {
+    Ok(instance
+        .enumerate_physical_devices()?
+        .enumerate()
+        .map(|(index, p)| DeviceListing::from_physical_device(index, &p, device_extensions))
+        .collect())
+}
+
+/// Every physical device, along with its description for the selection policy.
+fn candidates(
+    instance: &Arc<Instance>,
+    device_extensions: &DeviceExtensions,
+    surface: Option<&Surface>,
+) -> Result<(Vec<Arc<PhysicalDevice>>, Vec<DeviceCandidate>), RendererError> {
+    let physical_devices: Vec<_> = instance.enumerate_physical_devices()?.collect();
+    let candidates = physical_devices
+        .iter()
+        .enumerate()
+        .map(|(index, p)| {
+            DeviceCandidate::from_physical_device(index, p, device_extensions, surface)
+        })
+        .collect();
+    Ok((physical_devices, candidates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_retries_transient_errors_with_a_growing_delay() {
+        let mut slept = Vec::new();
+        let mut tries = 0;
+        let result = retry(
+            "succeed",
+            || {
+                tries += 1;
+                if tries < 3 {
+                    Err("not yet")
+                } else {
+                    Ok(tries)
+                }
+            },
+            |_| true,
+            |delay| slept.push(delay),
+        );
+        assert_eq!(result, Ok(3));
+        assert_eq!(slept, [FIRST_RETRY_DELAY, FIRST_RETRY_DELAY * 2]);
+
+        // Gives up after the last attempt, or at once on errors retrying won't fix.
+        let mut tries = 0;
+        let result: Result<(), _> = retry(
+            "fail",
+            || {
+                tries += 1;
+                Err(tries)
+            },
+            |_| true,
+            |_| {},
+        );
+        assert_eq!(result, Err(STARTUP_ATTEMPTS));
+        let result: Result<(), _> = retry("fail", || Err("invalid"), |_| false, |_| {});
+        assert_eq!(result, Err("invalid"));
+    }
+}
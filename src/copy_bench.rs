@@ -0,0 +1,278 @@
+//! Timing buffer-to-buffer copies, to see how fast the device moves data into its own memory
+//! compared with memory the CPU can see, for `--bench-copy`.
+//!
+//! Both copies start from the same host-visible buffer, like the staging buffers uploads go
+//! through. One lands in device-local memory, as a staged upload does, the other in host-visible
+//! memory, where a buffer the CPU writes directly would live. Each copy is submitted on its own
+//! [`ITERATIONS`] times, with timestamps written around it where the queue supports them and
+//! timed on the CPU otherwise.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::CopyBufferInfo;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType};
+use vulkano::sync::PipelineStage;
+use vulkano::DeviceSize;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+
+/// How many times each copy is submitted.
+pub const ITERATIONS: u32 = 10;
+
+/// Where a copy lands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyTarget {
+    /// Memory only the device sees, or at least prefers, as for staged uploads.
+    DeviceLocal,
+    /// Memory the CPU can map, as for buffers it writes directly.
+    HostVisible,
+}
+
+impl fmt::Display for CopyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CopyTarget::DeviceLocal => "device-local",
+            CopyTarget::HostVisible => "host-visible",
+        })
+    }
+}
+
+/// How the copies were timed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyTimer {
+    /// With timestamps written before and after each copy, so only the copy itself counts.
+    Timestamps,
+    /// From submitting each copy to the fence signalling, where the queue can't write
+    /// timestamps. Includes the submission, so small copies look slower than they are.
+    Cpu,
+}
+
+/// The copies to one target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CopyTimes {
+    pub target: CopyTarget,
+    /// One per iteration.
+    pub times: Vec<Duration>,
+}
+
+impl CopyTimes {
+    /// The fastest copy's.
+    pub fn best_throughput(&self, bytes: DeviceSize) -> f64 {
+        self.times
+            .iter()
+            .map(|&time| throughput(bytes, time))
+            .fold(0.0, f64::max)
+    }
+
+    /// Of all the copies together.
+    pub fn average_throughput(&self, bytes: DeviceSize) -> f64 {
+        throughput(
+            bytes * self.times.len() as DeviceSize,
+            self.times.iter().sum(),
+        )
+    }
+}
+
+/// What [`bench_copy`] found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CopyBenchReport {
+    /// Copied each time.
+    pub bytes: DeviceSize,
+    pub timer: CopyTimer,
+    pub targets: Vec<CopyTimes>,
+}
+
+impl fmt::Display for CopyBenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Copy benchmark: {} bytes per copy, {} copies each, timed {}",
+            self.bytes,
+            ITERATIONS,
+            match self.timer {
+                CopyTimer::Timestamps => "with GPU timestamps",
+                CopyTimer::Cpu => "on the CPU, submissions included",
+            }
+        )?;
+        for target in &self.targets {
+            write!(
+                f,
+                "\n  {:<13} {:.2} GB/s at best, {:.2} GB/s on average",
+                format!("{}:", target.target),
+                target.best_throughput(self.bytes),
+                target.average_throughput(self.bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Copies `bytes` from a host-visible buffer into device-local and host-visible ones
+/// [`ITERATIONS`] times each, timing every copy.
+pub fn bench_copy(
+    ctx: &VulkanContext,
+    bytes: DeviceSize,
+) -> Result<CopyBenchReport, RendererError> {
+    let buffer = |usage, memory_type_filter, label| {
+        let buffer = Buffer::new_slice::<u8>(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter,
+                ..Default::default()
+            },
+            bytes,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Staging, buffer.buffer());
+        ctx.name_object(buffer.buffer(), label);
+        Ok::<_, RendererError>(buffer)
+    };
+    let source = buffer(
+        BufferUsage::TRANSFER_SRC,
+        MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+        "copy benchmark source",
+    )?;
+    for (index, byte) in source.write()?.iter_mut().enumerate() {
+        *byte = index as u8;
+    }
+
+    let timestamps = ctx
+        .caps
+        .timestamps
+        .then(|| {
+            QueryPool::new(
+                ctx.device.clone(),
+                QueryPoolCreateInfo {
+                    query_count: 2,
+                    ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                },
+            )
+        })
+        .transpose()?;
+    let timer = match timestamps {
+        Some(_) => CopyTimer::Timestamps,
+        None => {
+            log::warn!("The queue can't write timestamps, so timing copies on the CPU");
+            CopyTimer::Cpu
+        }
+    };
+
+    let targets = [
+        (
+            CopyTarget::DeviceLocal,
+            MemoryTypeFilter::PREFER_DEVICE,
+            "copy benchmark device-local target",
+        ),
+        (
+            CopyTarget::HostVisible,
+            MemoryTypeFilter::PREFER_HOST | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            "copy benchmark host-visible target",
+        ),
+    ]
+    .into_iter()
+    .map(|(target, memory_type_filter, label)| {
+        let destination = buffer(BufferUsage::TRANSFER_DST, memory_type_filter, label)?;
+        let times = (0..ITERATIONS)
+            .map(|_| time_copy(ctx, &source, &destination, timestamps.as_ref()))
+            .collect::<Result<_, _>>()?;
+        // Where the CPU can see what arrived, make sure it all did.
+        if target == CopyTarget::HostVisible && *destination.read()? != *source.read()? {
+            log::error!("The {target} copy doesn't match its source");
+        }
+        Ok(CopyTimes { target, times })
+    })
+    .collect::<Result<_, RendererError>>()?;
+    Ok(CopyBenchReport {
+        bytes,
+        timer,
+        targets,
+    })
+}
+
+/// Copies `source` into `destination` in a submission of its own and waits for it, returning
+/// how long the copy took by `timestamps` or, without them, the CPU.
+fn time_copy(
+    ctx: &VulkanContext,
+    source: &Subbuffer<[u8]>,
+    destination: &Subbuffer<[u8]>,
+    timestamps: Option<&Arc<QueryPool>>,
+) -> Result<Duration, RendererError> {
+    let start = Instant::now();
+    ctx.submit_and_wait(|builder| {
+        if let Some(pool) = timestamps {
+            // SAFETY: the queries are reset before they are written, and only read once the
+            // submission has finished.
+            unsafe {
+                builder
+                    .reset_query_pool(pool.clone(), 0..2)?
+                    .write_timestamp(pool.clone(), 0, PipelineStage::TopOfPipe)?;
+            }
+        }
+        builder.copy_buffer(CopyBufferInfo::buffers(source.clone(), destination.clone()))?;
+        if let Some(pool) = timestamps {
+            // SAFETY: as above.
+            unsafe {
+                builder.write_timestamp(pool.clone(), 1, PipelineStage::BottomOfPipe)?;
+            }
+        }
+        Ok(())
+    })?;
+    let cpu_time = start.elapsed();
+
+    let Some(pool) = timestamps else {
+        return Ok(cpu_time);
+    };
+    let mut ticks = [0u64; 2];
+    pool.get_results(0..2, &mut ticks, QueryResultFlags::WAIT)?;
+    let nanos = ticks[1].wrapping_sub(ticks[0]) as f64 * ctx.caps.limits.timestamp_period as f64;
+    Ok(Duration::from_nanos(nanos as u64))
+}
+
+/// In GB/s, with a GB of 10⁹ bytes like the hardware vendors quote.
+fn throughput(bytes: DeviceSize, time: Duration) -> f64 {
+    if time.is_zero() {
+        return 0.0;
+    }
+    bytes as f64 / time.as_secs_f64() / 1e9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_give_the_best_and_average_throughput() {
+        let report = CopyBenchReport {
+            bytes: 1_000_000_000,
+            timer: CopyTimer::Timestamps,
+            targets: vec![
+                CopyTimes {
+                    target: CopyTarget::DeviceLocal,
+                    times: vec![Duration::from_millis(100), Duration::from_millis(400)],
+                },
+                CopyTimes {
+                    target: CopyTarget::HostVisible,
+                    times: vec![Duration::from_millis(500); 2],
+                },
+            ],
+        };
+        assert_eq!(
+            report.to_string(),
+            "Copy benchmark: 1000000000 bytes per copy, 10 copies each, timed with GPU \
+             timestamps\n  \
+             device-local: 10.00 GB/s at best, 4.00 GB/s on average\n  \
+             host-visible: 2.00 GB/s at best, 2.00 GB/s on average"
+        );
+        assert_eq!(throughput(1024, Duration::ZERO), 0.0);
+    }
+}
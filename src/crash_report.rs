@@ -0,0 +1,241 @@
+//! Writing down what the renderer was doing when the program panics, to `crash-report.txt`.
+//!
+//! The renderer notes its device, swapchains and scene here as it sets them up, and counts the
+//! frames it starts. The hook [`install`] adds writes those out along with the recent log lines
+//! from [`CAPTURED`], the panic message and a backtrace, then lets the previous hook run.
+//!
+//! The hook does no GPU work, so it still works when the GPU hangs. That also means the report
+//! can't include the last frame presented: no copy of it is kept on the CPU, and reading one
+//! back from the swapchain would take a copy on the GPU.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError, TryLockError};
+use std::{panic, thread};
+
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::swapchain::Swapchain;
+
+use crate::caps::DeviceCaps;
+use crate::console::{LogLine, CAPTURED};
+
+/// Where the report is written, relative to the working directory.
+pub const REPORT_PATH: &str = "crash-report.txt";
+
+/// How many of the most recent log lines go into the report.
+const LOG_LINES: usize = 200;
+
+/// What the renderer noted about itself, as it goes into the report.
+#[derive(Debug, Default)]
+struct Notes {
+    device: Option<String>,
+    caps: Option<String>,
+    /// By window index.
+    swapchains: BTreeMap<usize, String>,
+    scene: Option<String>,
+}
+
+static NOTES: Mutex<Notes> = Mutex::new(Notes {
+    device: None,
+    caps: None,
+    swapchains: BTreeMap::new(),
+    scene: None,
+});
+
+/// The frames started so far, the last of them being the one in progress.
+static FRAMES: AtomicU64 = AtomicU64::new(0);
+
+fn update_notes(update: impl FnOnce(&mut Notes)) {
+    update(&mut NOTES.lock().unwrap_or_else(PoisonError::into_inner));
+}
+
+/// Notes the device rendered with and what was enabled on it.
+pub fn set_device(physical_device: &PhysicalDevice, caps: &DeviceCaps) {
+    let properties = physical_device.properties();
+    let device = format!(
+        "{} ({:?}), Vulkan {}, driver {} {}",
+        properties.device_name,
+        properties.device_type,
+        physical_device.api_version(),
+        properties.driver_name.as_deref().unwrap_or("unknown"),
+        properties.driver_info.as_deref().unwrap_or(""),
+    );
+    update_notes(|notes| {
+        notes.device = Some(device.trim_end().to_owned());
+        notes.caps = Some(caps.summary());
+    });
+}
+
+/// Notes the swapchain window `window_index` presents with, replacing the one before.
+pub fn set_swapchain(window_index: usize, swapchain: &Swapchain) {
+    let [width, height] = swapchain.image_extent();
+    let description = format!(
+        "{width}x{height}, {:?}, {:?}, {:?}, {} images",
+        swapchain.image_format(),
+        swapchain.image_color_space(),
+        swapchain.present_mode(),
+        swapchain.image_count(),
+    );
+    update_notes(|notes| {
+        notes.swapchains.insert(window_index, description);
+    });
+}
+
+/// Forgets window `window_index`'s swapchain, once the window is gone.
+pub fn clear_swapchain(window_index: usize) {
+    update_notes(|notes| {
+        notes.swapchains.remove(&window_index);
+    });
+}
+
+/// Notes the scene being drawn.
+pub fn set_scene(name: &str) {
+    update_notes(|notes| notes.scene = Some(name.to_owned()));
+}
+
+/// Counts a frame starting.
+pub fn frame_started() {
+    FRAMES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Writes a report on every panic, before the previous hook prints the panic as usual.
+pub fn install() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let thread = thread::current();
+        let panic = format!("thread '{}' {info}", thread.name().unwrap_or("<unnamed>"));
+        match write_report(Path::new(REPORT_PATH), &panic) {
+            Ok(()) => eprintln!("Wrote a crash report to {REPORT_PATH}"),
+            Err(err) => eprintln!("Failed to write a crash report to {REPORT_PATH}: {err}"),
+        }
+        previous(info);
+    }));
+}
+
+fn write_report(path: &Path, panic: &str) -> std::io::Result<()> {
+    // The panic may have happened while holding either lock, so neither is waited for.
+    let notes = match NOTES.try_lock() {
+        Ok(notes) => Some(notes),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    };
+    let log = CAPTURED.try_recent(LOG_LINES);
+    let backtrace = std::backtrace::Backtrace::force_capture();
+    let report = format_report(
+        notes.as_deref(),
+        FRAMES.load(Ordering::Relaxed),
+        log.as_deref(),
+        panic,
+        &backtrace,
+    );
+    fs::write(path, report)
+}
+
+fn format_report(
+    notes: Option<&Notes>,
+    frames: u64,
+    log: Option<&[LogLine]>,
+    panic: &str,
+    backtrace: &dyn fmt::Display,
+) -> String {
+    const UNKNOWN: &str = "unknown";
+    let mut report = String::new();
+    // Writing to a string can't fail.
+    let _ = writeln!(report, "hi-vulkanos crash report\n\nPanic: {panic}\n");
+    match notes {
+        Some(notes) => {
+            let _ = writeln!(
+                report,
+                "Device: {}\nCapabilities: {}\nScene: {}",
+                notes.device.as_deref().unwrap_or(UNKNOWN),
+                notes.caps.as_deref().unwrap_or(UNKNOWN),
+                notes.scene.as_deref().unwrap_or(UNKNOWN),
+            );
+            if notes.swapchains.is_empty() {
+                let _ = writeln!(report, "Swapchains: none");
+            }
+            for (window_index, swapchain) in &notes.swapchains {
+                let _ = writeln!(report, "Swapchain of window {window_index}: {swapchain}");
+            }
+        }
+        None => {
+            let _ = writeln!(report, "The renderer's state was locked while panicking");
+        }
+    }
+    match frames {
+        0 => {
+            let _ = writeln!(report, "Frame: none started yet");
+        }
+        frames => {
+            let _ = writeln!(report, "Frame: {frames}");
+        }
+    }
+
+    let _ = writeln!(report, "\nLast {LOG_LINES} log lines:");
+    match log {
+        Some(lines) => {
+            for line in lines {
+                let _ = writeln!(report, "  {:<5} {}", line.level, line.text);
+            }
+        }
+        None => {
+            let _ = writeln!(report, "  The log was locked while panicking");
+        }
+    }
+    let _ = write!(report, "\nBacktrace:\n{backtrace}");
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn the_report_has_every_section() {
+        let notes = Notes {
+            device: Some("llvmpipe (Cpu)".to_owned()),
+            caps: Some("dynamic rendering".to_owned()),
+            swapchains: BTreeMap::from([(0, "1280x720, B8G8R8A8_SRGB".to_owned())]),
+            scene: Some("cube".to_owned()),
+        };
+        let log = [LogLine {
+            level: Level::Warn,
+            text: "about to go wrong".to_owned(),
+            logged_at: Instant::now(),
+        }];
+        let report = format_report(
+            Some(&notes),
+            42,
+            Some(&log),
+            "thread 'render' panicked at src/lib.rs:1:1:\ninjected",
+            &"frames go here",
+        );
+        for expected in [
+            "Panic: thread 'render' panicked at src/lib.rs:1:1:\ninjected",
+            "Device: llvmpipe (Cpu)",
+            "Capabilities: dynamic rendering",
+            "Scene: cube",
+            "Swapchain of window 0: 1280x720, B8G8R8A8_SRGB",
+            "Frame: 42",
+            "  WARN  about to go wrong",
+            "Backtrace:\nframes go here",
+        ] {
+            assert!(report.contains(expected), "no `{expected}` in:\n{report}");
+        }
+    }
+
+    #[test]
+    fn locked_state_is_left_out() {
+        let report = format_report(None, 0, None, "panicked", &"");
+        assert!(report.contains("state was locked"));
+        assert!(report.contains("log was locked"));
+        assert!(report.contains("Frame: none started yet"));
+    }
+}
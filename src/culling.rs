@@ -0,0 +1,92 @@
+//! Skipping the draws of objects the camera can't see, by testing their bounding spheres
+//! against the view frustum on the CPU.
+
+use std::ops::AddAssign;
+
+use glam::{Mat4, Vec3, Vec4};
+
+/// The volume a view-projection matrix shows, as six planes facing inwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// Each plane as `(normal, distance)`, with a normalized normal, so that points inside have
+    /// `normal.dot(point) + distance >= 0`.
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// The frustum of `view_projection`, for Vulkan's clip space where depth runs from 0 to 1.
+    /// Works for orthographic projections too.
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        // Gribb and Hartmann: a clip space point is inside when -w <= x <= w, -w <= y <= w and
+        // 0 <= z <= w, and each of those is a plane in terms of the matrix's rows.
+        let [x, y, z, w] = [0, 1, 2, 3].map(|row| view_projection.row(row));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncate().length();
+            // A far plane at infinity has no normal, and nothing is beyond it.
+            if length > f32::EPSILON {
+                plane / length
+            } else {
+                Vec4::new(0.0, 0.0, 0.0, 1.0)
+            }
+        });
+        Self { planes }
+    }
+
+    /// Whether any part of the sphere may be inside. Spheres just outside near a corner count
+    /// as inside, which only costs a draw that turns out to be empty.
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+}
+
+/// How many nodes were drawn and how many were skipped for being outside the view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+impl AddAssign for CullStats {
+    fn add_assign(&mut self, other: Self) {
+        self.drawn += other.drawn;
+        self.culled += other.culled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{orthographic, perspective};
+
+    fn looking_down_negative_z(projection: Mat4) -> Frustum {
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        Frustum::from_view_projection(projection * view)
+    }
+
+    #[test]
+    fn spheres_in_front_of_the_camera_are_inside() {
+        let frustum = looking_down_negative_z(perspective(1.0, 1.5, 0.1, 100.0));
+        assert!(frustum.intersects_sphere(Vec3::new(0.0, 0.0, -10.0), 1.0));
+        // Behind the camera, beyond the far plane, and far off to each side.
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, 10.0), 1.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, -200.0), 1.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(50.0, 0.0, -10.0), 1.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, -50.0, -10.0), 1.0));
+    }
+
+    #[test]
+    fn spheres_overlapping_an_edge_are_inside() {
+        let frustum = looking_down_negative_z(orthographic(5.0, 1.0, 0.1, 100.0));
+        assert!(frustum.intersects_sphere(Vec3::new(5.5, 0.0, -10.0), 1.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(6.5, 0.0, -10.0), 1.0));
+    }
+
+    #[test]
+    fn an_infinite_far_plane_culls_nothing_far_away() {
+        let projection = Mat4::perspective_infinite_rh(1.0, 1.0, 0.1);
+        let frustum = looking_down_negative_z(projection);
+        assert!(frustum.intersects_sphere(Vec3::new(0.0, 0.0, -1.0e6), 1.0));
+    }
+}
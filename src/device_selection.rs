@@ -0,0 +1,655 @@
+//! Choosing which physical device to render with.
+//!
+//! The policy works on plain [`DeviceCandidate`] values rather than on `PhysicalDevice`s so it
+//! can be tested without a GPU. The runtime path only has to describe each real device as a
+//! candidate.
+
+use std::cmp::Reverse;
+use std::fmt;
+
+use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
+use vulkano::device::{DeviceExtensions, QueueFlags};
+use vulkano::memory::MemoryHeapFlags;
+use vulkano::swapchain::Surface;
+use vulkano::Version;
+
+/// What we need to know about a physical device to decide whether to use it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceCandidate {
+    /// Position in the instance's device list, as used by `--gpu`.
+    pub index: usize,
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+    /// Whether every device extension the renderer needs is supported.
+    pub supports_extensions: bool,
+    /// Queue families that support graphics and, when rendering to a surface, can present to it.
+    pub queue_families: Vec<u32>,
+    /// Total size of the device-local memory heaps in bytes.
+    pub memory_size: u64,
+}
+
+impl DeviceCandidate {
+    /// Describes a real device. When `surface` is given, only queue families that can present to
+    /// it are listed.
+    pub fn from_physical_device(
+        index: usize,
+        physical_device: &PhysicalDevice,
+        device_extensions: &DeviceExtensions,
+        surface: Option<&Surface>,
+    ) -> Self {
+        let properties = physical_device.properties();
+
+        let queue_families = physical_device
+            .queue_family_properties()
+            .iter()
+            .enumerate()
+            .filter(|(_, family)| family.queue_flags.contains(QueueFlags::GRAPHICS))
+            .map(|(i, _)| i as u32)
+            .filter(|&i| {
+                surface.is_none_or(|surface| {
+                    physical_device.surface_support(i, surface).unwrap_or(false)
+                })
+            })
+            .collect();
+
+        let memory_size = physical_device
+            .memory_properties()
+            .memory_heaps
+            .iter()
+            .filter(|heap| heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        Self {
+            index,
+            name: properties.device_name.clone(),
+            device_type: properties.device_type,
+            supports_extensions: physical_device
+                .supported_extensions()
+                .contains(device_extensions),
+            queue_families,
+            memory_size,
+        }
+    }
+
+    /// Whether the renderer could run on this device at all.
+    pub fn is_suitable(&self) -> bool {
+        self.supports_extensions && !self.queue_families.is_empty()
+    }
+
+    /// Whether this is a CPU implementation, which works but is far too slow for real use.
+    pub fn is_software_renderer(&self) -> bool {
+        let name = self.name.to_lowercase();
+        self.device_type == PhysicalDeviceType::Cpu
+            || SOFTWARE_RENDERER_NAMES
+                .iter()
+                .any(|software| name.contains(software))
+    }
+}
+
+/// A device as `--list-devices` prints it: its description for the selection policy, and the
+/// versions that tell its driver apart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceListing {
+    pub candidate: DeviceCandidate,
+    /// The highest Vulkan version the device supports.
+    pub api_version: Version,
+    /// Only reported from Vulkan 1.2 on, or with `VK_KHR_driver_properties`.
+    pub driver_name: Option<String>,
+    pub vendor_id: u32,
+    /// Packed however the vendor likes, see [`driver_version`].
+    pub driver_version: u32,
+}
+
+impl DeviceListing {
+    /// Describes a real device, as [`DeviceCandidate::from_physical_device`] does without a
+    /// surface.
+    pub fn from_physical_device(
+        index: usize,
+        physical_device: &PhysicalDevice,
+        device_extensions: &DeviceExtensions,
+    ) -> Self {
+        let properties = physical_device.properties();
+        Self {
+            candidate: DeviceCandidate::from_physical_device(
+                index,
+                physical_device,
+                device_extensions,
+                None,
+            ),
+            api_version: properties.api_version,
+            driver_name: properties.driver_name.clone(),
+            vendor_id: properties.vendor_id,
+            driver_version: properties.driver_version,
+        }
+    }
+}
+
+impl fmt::Display for DeviceListing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let candidate = &self.candidate;
+        write!(
+            f,
+            "{}: {} ({:?}), Vulkan {}, driver ",
+            candidate.index, candidate.name, candidate.device_type, self.api_version
+        )?;
+        if let Some(name) = &self.driver_name {
+            write!(f, "{name} ")?;
+        }
+        write!(f, "{}", driver_version(self.vendor_id, self.driver_version))?;
+        if !candidate.is_suitable() {
+            write!(f, ", lacks a required extension or a graphics queue")?;
+        } else if candidate.is_software_renderer() {
+            write!(f, ", software renderer")?;
+        }
+        Ok(())
+    }
+}
+
+/// A driver version as its vendor numbers it. Vulkan only says how API versions are packed;
+/// NVIDIA packs driver versions its own way, and so does Intel on Windows. Everyone else packs
+/// them like API versions.
+pub fn driver_version(vendor_id: u32, version: u32) -> String {
+    const NVIDIA: u32 = 0x10de;
+    const INTEL: u32 = 0x8086;
+    match vendor_id {
+        NVIDIA => format!(
+            "{}.{}.{}.{}",
+            version >> 22,
+            (version >> 14) & 0xff,
+            (version >> 6) & 0xff,
+            version & 0x3f
+        ),
+        INTEL if cfg!(windows) => format!("{}.{}", version >> 14, version & 0x3fff),
+        _ => Version::from(version).to_string(),
+    }
+}
+
+/// Lowercase name fragments of software implementations that don't always report themselves as
+/// `PhysicalDeviceType::Cpu`.
+const SOFTWARE_RENDERER_NAMES: [&str; 4] = ["llvmpipe", "lavapipe", "swiftshader", "softpipe"];
+
+/// How the user wants the physical device to be picked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceSelection {
+    pub preference: DevicePreference,
+    /// Allow falling back to a software renderer when a hardware device exists but can't be used.
+    pub allow_software_renderer: bool,
+    /// Rank integrated GPUs above discrete ones when picking automatically, as safe mode does in
+    /// case the discrete GPU's driver is what crashed.
+    pub prefer_integrated: bool,
+}
+
+/// Which device the user asked for on the command line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum DevicePreference {
+    /// Pick the best suitable device.
+    #[default]
+    Auto,
+    /// The device at this position in the device list (`--gpu`).
+    Index(usize),
+    /// The best suitable device whose name contains this text, ignoring case (`--gpu-name`).
+    Name(String),
+}
+
+/// Why no device could be selected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SelectionError {
+    /// None of the devices can run the renderer.
+    NoSuitableDevice,
+    /// `--gpu` named an index past the end of the device list.
+    IndexOutOfRange { index: usize, count: usize },
+    /// No device name contains the `--gpu-name` text.
+    NoNameMatch(String),
+    /// The requested device exists but lacks a required extension or a usable queue family.
+    Unsuitable(String),
+    /// Only a software renderer is usable even though there is a hardware device, and
+    /// `--allow-software-renderer` wasn't given.
+    SoftwareRendererNotAllowed { software: String, skipped: String },
+}
+
+impl fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuitableDevice => write!(f, "no suitable physical device could be found"),
+            Self::IndexOutOfRange { index, count } => write!(
+                f,
+                "there is no device with index {index}, only {count} device(s) available"
+            ),
+            Self::NoNameMatch(name) => write!(f, "no device name contains `{name}`"),
+            Self::Unsuitable(name) => write!(
+                f,
+                "device `{name}` lacks a required extension or a usable queue family"
+            ),
+            Self::SoftwareRendererNotAllowed { software, skipped } => write!(
+                f,
+                "only the software renderer `{software}` is usable because `{skipped}` lacks a \
+                 required extension or a usable queue family; pass --allow-software-renderer to \
+                 run on it anyway"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SelectionError {}
+
+/// Lower is better. Discrete GPUs are preferred over integrated ones unless `prefer_integrated`,
+/// and anything beats a CPU implementation.
+fn type_rank(device_type: PhysicalDeviceType, prefer_integrated: bool) -> u32 {
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu => u32::from(prefer_integrated),
+        PhysicalDeviceType::IntegratedGpu => u32::from(!prefer_integrated),
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Cpu => 3,
+        PhysicalDeviceType::Other => 4,
+        _ => 5,
+    }
+}
+
+/// Picks the best suitable candidate: by device type, then by more device-local memory, then by
+/// whichever was listed first. Software renderers are only considered when nothing else is
+/// suitable.
+fn best<'a>(
+    candidates: impl IntoIterator<Item = &'a DeviceCandidate>,
+    prefer_integrated: bool,
+) -> Option<&'a DeviceCandidate> {
+    let suitable: Vec<_> = candidates.into_iter().filter(|c| c.is_suitable()).collect();
+    let rank = |c: &&DeviceCandidate| {
+        (
+            type_rank(c.device_type, prefer_integrated),
+            Reverse(c.memory_size),
+            c.index,
+        )
+    };
+
+    let hardware = suitable
+        .iter()
+        .copied()
+        .filter(|c| !c.is_software_renderer())
+        .min_by_key(rank);
+    hardware.or_else(|| suitable.iter().copied().min_by_key(rank))
+}
+
+/// Applies the selection policy to `candidates`.
+///
+/// Picking a software renderer automatically while a hardware device was skipped for being
+/// unsuitable is an error unless `allow_software_renderer` is set, since that almost always means
+/// something is misconfigured. Asking for a software renderer explicitly is always fine.
+pub fn select_device<'a>(
+    candidates: &'a [DeviceCandidate],
+    selection: &DeviceSelection,
+) -> Result<&'a DeviceCandidate, SelectionError> {
+    match &selection.preference {
+        DevicePreference::Auto => {
+            let selected = best(candidates, selection.prefer_integrated)
+                .ok_or(SelectionError::NoSuitableDevice)?;
+            if selected.is_software_renderer() && !selection.allow_software_renderer {
+                if let Some(skipped) = candidates.iter().find(|c| !c.is_software_renderer()) {
+                    return Err(SelectionError::SoftwareRendererNotAllowed {
+                        software: selected.name.clone(),
+                        skipped: skipped.name.clone(),
+                    });
+                }
+            }
+            Ok(selected)
+        }
+        DevicePreference::Index(index) => {
+            let candidate = candidates.iter().find(|c| c.index == *index).ok_or(
+                SelectionError::IndexOutOfRange {
+                    index: *index,
+                    count: candidates.len(),
+                },
+            )?;
+            if candidate.is_suitable() {
+                Ok(candidate)
+            } else {
+                Err(SelectionError::Unsuitable(candidate.name.clone()))
+            }
+        }
+        DevicePreference::Name(name) => {
+            let needle = name.to_lowercase();
+            let matching: Vec<_> = candidates
+                .iter()
+                .filter(|c| c.name.to_lowercase().contains(&needle))
+                .collect();
+            match matching.first() {
+                None => Err(SelectionError::NoNameMatch(name.clone())),
+                Some(first) => best(matching.iter().copied(), selection.prefer_integrated)
+                    .ok_or_else(|| SelectionError::Unsuitable(first.name.clone())),
+            }
+        }
+    }
+}
+
+/// The first suitable candidate after the one at `current` in device-list order, wrapping around,
+/// for cycling through the devices. `None` if no other device is suitable.
+pub fn next_device(candidates: &[DeviceCandidate], current: usize) -> Option<&DeviceCandidate> {
+    candidates
+        .iter()
+        .filter(|c| c.is_suitable() && c.index != current)
+        .min_by_key(|c| (c.index < current, c.index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    fn device(index: usize, name: &str, device_type: PhysicalDeviceType) -> DeviceCandidate {
+        DeviceCandidate {
+            index,
+            name: name.to_owned(),
+            device_type,
+            supports_extensions: true,
+            queue_families: vec![0],
+            memory_size: GIB,
+        }
+    }
+
+    fn with_memory(mut candidate: DeviceCandidate, memory_size: u64) -> DeviceCandidate {
+        candidate.memory_size = memory_size;
+        candidate
+    }
+
+    fn without_extensions(mut candidate: DeviceCandidate) -> DeviceCandidate {
+        candidate.supports_extensions = false;
+        candidate
+    }
+
+    fn without_queues(mut candidate: DeviceCandidate) -> DeviceCandidate {
+        candidate.queue_families.clear();
+        candidate
+    }
+
+    use PhysicalDeviceType::{Cpu, DiscreteGpu, IntegratedGpu, Other, VirtualGpu};
+
+    #[test]
+    fn listings_show_the_driver_version_as_its_vendor_packs_it() {
+        let listing = DeviceListing {
+            candidate: device(
+                1,
+                "NVIDIA GeForce RTX 3080",
+                PhysicalDeviceType::DiscreteGpu,
+            ),
+            api_version: Version::V1_3,
+            driver_name: Some("NVIDIA".to_owned()),
+            vendor_id: 0x10de,
+            // 535.113.1.0
+            driver_version: (535 << 22) | (113 << 14) | (1 << 6),
+        };
+        assert_eq!(
+            listing.to_string(),
+            "1: NVIDIA GeForce RTX 3080 (DiscreteGpu), Vulkan 1.3.0, driver NVIDIA 535.113.1.0"
+        );
+
+        let listing = DeviceListing {
+            candidate: without_extensions(device(0, "llvmpipe", PhysicalDeviceType::Cpu)),
+            api_version: Version::V1_3,
+            driver_name: None,
+            vendor_id: 0x10005,
+            driver_version: (23 << 22) | (2 << 12),
+        };
+        assert_eq!(
+            listing.to_string(),
+            "0: llvmpipe (Cpu), Vulkan 1.3.0, driver 23.2.0, lacks a required extension or a \
+             graphics queue"
+        );
+    }
+
+    #[test]
+    fn software_renderers_are_recognised() {
+        assert!(device(0, "some cpu device", Cpu).is_software_renderer());
+        assert!(device(0, "llvmpipe (LLVM 15.0.7, 256 bits)", Other).is_software_renderer());
+        assert!(!device(0, "GeForce RTX 3080", DiscreteGpu).is_software_renderer());
+    }
+
+    #[test]
+    fn selection_policy() {
+        struct Case {
+            name: &'static str,
+            candidates: Vec<DeviceCandidate>,
+            preference: DevicePreference,
+            allow_software_renderer: bool,
+            expected: Result<&'static str, SelectionError>,
+        }
+
+        let cases = [
+            Case {
+                name: "discrete beats integrated",
+                candidates: vec![
+                    device(0, "Intel UHD", IntegratedGpu),
+                    device(1, "GeForce RTX", DiscreteGpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("GeForce RTX"),
+            },
+            Case {
+                name: "integrated beats virtual and cpu",
+                candidates: vec![
+                    device(0, "llvmpipe", Cpu),
+                    device(1, "virtio", VirtualGpu),
+                    device(2, "Intel UHD", IntegratedGpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("Intel UHD"),
+            },
+            Case {
+                name: "llvmpipe only",
+                candidates: vec![device(0, "llvmpipe (LLVM 15.0.7, 256 bits)", Cpu)],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("llvmpipe (LLVM 15.0.7, 256 bits)"),
+            },
+            Case {
+                name: "tie on type goes to more memory",
+                candidates: vec![
+                    with_memory(device(0, "small", DiscreteGpu), 4 * GIB),
+                    with_memory(device(1, "big", DiscreteGpu), 8 * GIB),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("big"),
+            },
+            Case {
+                name: "full tie goes to first listed",
+                candidates: vec![
+                    device(0, "first", DiscreteGpu),
+                    device(1, "second", DiscreteGpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("first"),
+            },
+            Case {
+                name: "missing extensions are skipped",
+                candidates: vec![
+                    without_extensions(device(0, "GeForce RTX", DiscreteGpu)),
+                    device(1, "Intel UHD", IntegratedGpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("Intel UHD"),
+            },
+            Case {
+                name: "devices that can't present are skipped",
+                candidates: vec![
+                    without_queues(device(0, "headless compute card", DiscreteGpu)),
+                    device(1, "Intel UHD", IntegratedGpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("Intel UHD"),
+            },
+            Case {
+                name: "falling back to software needs opt-in",
+                candidates: vec![
+                    without_queues(device(0, "GeForce RTX", DiscreteGpu)),
+                    device(1, "llvmpipe", Cpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Err(SelectionError::SoftwareRendererNotAllowed {
+                    software: "llvmpipe".to_owned(),
+                    skipped: "GeForce RTX".to_owned(),
+                }),
+            },
+            Case {
+                name: "falling back to software with opt-in",
+                candidates: vec![
+                    without_queues(device(0, "GeForce RTX", DiscreteGpu)),
+                    device(1, "llvmpipe", Cpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: true,
+                expected: Ok("llvmpipe"),
+            },
+            Case {
+                name: "software renderers named but not typed as cpu rank last",
+                candidates: vec![
+                    device(0, "SwiftShader Device (Subzero)", IntegratedGpu),
+                    device(1, "virtio", VirtualGpu),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Ok("virtio"),
+            },
+            Case {
+                name: "no devices at all",
+                candidates: vec![],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Err(SelectionError::NoSuitableDevice),
+            },
+            Case {
+                name: "no suitable devices",
+                candidates: vec![
+                    without_extensions(device(0, "a", DiscreteGpu)),
+                    without_queues(device(1, "b", IntegratedGpu)),
+                ],
+                preference: DevicePreference::Auto,
+                allow_software_renderer: false,
+                expected: Err(SelectionError::NoSuitableDevice),
+            },
+            Case {
+                name: "index overrides ranking",
+                candidates: vec![
+                    device(0, "GeForce RTX", DiscreteGpu),
+                    device(1, "llvmpipe", Cpu),
+                ],
+                preference: DevicePreference::Index(1),
+                allow_software_renderer: false,
+                expected: Ok("llvmpipe"),
+            },
+            Case {
+                name: "index out of range",
+                candidates: vec![device(0, "GeForce RTX", DiscreteGpu)],
+                preference: DevicePreference::Index(3),
+                allow_software_renderer: false,
+                expected: Err(SelectionError::IndexOutOfRange { index: 3, count: 1 }),
+            },
+            Case {
+                name: "index of an unsuitable device",
+                candidates: vec![
+                    device(0, "Intel UHD", IntegratedGpu),
+                    without_queues(device(1, "GeForce RTX", DiscreteGpu)),
+                ],
+                preference: DevicePreference::Index(1),
+                allow_software_renderer: false,
+                expected: Err(SelectionError::Unsuitable("GeForce RTX".to_owned())),
+            },
+            Case {
+                name: "name matches case-insensitively",
+                candidates: vec![
+                    device(0, "GeForce RTX 3080", DiscreteGpu),
+                    device(1, "AMD Radeon Graphics", IntegratedGpu),
+                ],
+                preference: DevicePreference::Name("radeon".to_owned()),
+                allow_software_renderer: false,
+                expected: Ok("AMD Radeon Graphics"),
+            },
+            Case {
+                name: "several name matches are ranked",
+                candidates: vec![
+                    device(0, "AMD Radeon Graphics", IntegratedGpu),
+                    device(1, "AMD Radeon RX 7900", DiscreteGpu),
+                    device(2, "GeForce RTX 3080", DiscreteGpu),
+                ],
+                preference: DevicePreference::Name("AMD".to_owned()),
+                allow_software_renderer: false,
+                expected: Ok("AMD Radeon RX 7900"),
+            },
+            Case {
+                name: "name skips unsuitable matches",
+                candidates: vec![
+                    without_extensions(device(0, "AMD Radeon RX 7900", DiscreteGpu)),
+                    device(1, "AMD Radeon Graphics", IntegratedGpu),
+                ],
+                preference: DevicePreference::Name("amd".to_owned()),
+                allow_software_renderer: false,
+                expected: Ok("AMD Radeon Graphics"),
+            },
+            Case {
+                name: "no name match",
+                candidates: vec![device(0, "GeForce RTX 3080", DiscreteGpu)],
+                preference: DevicePreference::Name("arc".to_owned()),
+                allow_software_renderer: false,
+                expected: Err(SelectionError::NoNameMatch("arc".to_owned())),
+            },
+            Case {
+                name: "only unsuitable name matches",
+                candidates: vec![
+                    without_queues(device(0, "llvmpipe", Cpu)),
+                    device(1, "GeForce RTX 3080", DiscreteGpu),
+                ],
+                preference: DevicePreference::Name("llvm".to_owned()),
+                allow_software_renderer: false,
+                expected: Err(SelectionError::Unsuitable("llvmpipe".to_owned())),
+            },
+        ];
+
+        for case in cases {
+            let selection = DeviceSelection {
+                preference: case.preference,
+                allow_software_renderer: case.allow_software_renderer,
+                prefer_integrated: false,
+            };
+            let selected = select_device(&case.candidates, &selection).map(|c| c.name.as_str());
+            assert_eq!(selected, case.expected, "case: {}", case.name);
+        }
+    }
+
+    #[test]
+    fn integrated_gpus_can_be_preferred() {
+        let candidates = [
+            device(0, "GeForce RTX", DiscreteGpu),
+            device(1, "Intel UHD", IntegratedGpu),
+            device(2, "llvmpipe", Cpu),
+        ];
+        let selection = DeviceSelection {
+            prefer_integrated: true,
+            ..Default::default()
+        };
+        let selected = select_device(&candidates, &selection).map(|c| c.name.as_str());
+        assert_eq!(selected, Ok("Intel UHD"));
+        let selected = select_device(&candidates[..1], &selection).map(|c| c.name.as_str());
+        assert_eq!(selected, Ok("GeForce RTX"));
+    }
+
+    #[test]
+    fn cycling_skips_unsuitable_devices_and_wraps_around() {
+        let candidates = [
+            device(0, "GeForce RTX", DiscreteGpu),
+            without_queues(device(1, "Headless GPU", DiscreteGpu)),
+            device(2, "Intel UHD", IntegratedGpu),
+            device(3, "llvmpipe", Cpu),
+        ];
+        let next = |current| next_device(&candidates, current).map(|c| c.index);
+        assert_eq!(next(0), Some(2));
+        assert_eq!(next(2), Some(3));
+        assert_eq!(next(3), Some(0));
+        assert_eq!(next_device(&candidates[..2], 0), None);
+    }
+}
@@ -0,0 +1,219 @@
+//! Keeps a window's recorded scene draws around while the scene says they stay the same, and
+//! records those of large scenes on several threads.
+//!
+//! Each view of a window records its draws into secondary command buffers per frame slot, since
+//! the draws bind that slot's descriptor sets. As long as the scene's
+//! [`draw_revision`](crate::scene::Scene::draw_revision) and the view's scissor don't change, the
+//! same secondaries are executed again the next time the slot comes round, so only `prepare` runs
+//! every frame.
+//!
+//! Scenes with enough [nodes](crate::scene::Scene::node_count) have them split into a chunk per
+//! thread, each recorded into a secondary of its own and executed in order.
+
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::Arc;
+use std::thread;
+
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::{CommandBufferUsage, SecondaryAutoCommandBuffer};
+use vulkano::pipeline::graphics::viewport::Scissor;
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::options::Options;
+use crate::scene::{record_draws, FrameData, Scene, FRAME_SLOTS};
+use crate::stereo::record_stereo_draws;
+
+/// Fewer nodes than this per thread aren't worth a thread: spawning it and executing another
+/// secondary costs more than recording them takes.
+pub const MIN_NODES_PER_THREAD: usize = 256;
+
+/// What a set of recorded draws was recorded for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct DrawKey {
+    revision: u64,
+    scissor: Scissor,
+}
+
+/// The draws recorded for one slot, in the order to execute them.
+type Draws = Vec<Arc<SecondaryAutoCommandBuffer>>;
+
+/// The recorded draws of one window, by frame slot.
+///
+/// The draws come from allocators of their own rather than the per-slot pools of
+/// [`frame_commands`](crate::frame_commands), which are reset every frame while these live on.
+/// Each recording thread gets its own allocator.
+pub struct DrawCache {
+    allocators: Vec<StandardCommandBufferAllocator>,
+    queue_family_index: u32,
+    draws: Vec<Option<(DrawKey, Draws)>>,
+    /// Record every frame even where the scene allows reuse, to compare against.
+    disabled: bool,
+}
+
+impl DrawCache {
+    pub fn new(ctx: &VulkanContext, options: &Options) -> Self {
+        let threads = options
+            .record_threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get));
+        let allocators = (0..threads.max(1))
+            .map(|_| {
+                StandardCommandBufferAllocator::new(
+                    ctx.device.clone(),
+                    StandardCommandBufferAllocatorCreateInfo {
+                        primary_buffer_count: 0,
+                        secondary_buffer_count: FRAME_SLOTS,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        Self {
+            allocators,
+            queue_family_index: ctx.queue.queue_family_index(),
+            draws: vec![None; FRAME_SLOTS],
+            disabled: options.record_every_frame,
+        }
+    }
+
+    /// The draws of `scene` for `frame` and `scissor`: the ones recorded for the slot before if
+    /// they still apply, otherwise recorded now.
+    ///
+    /// The previous frame in the slot must have finished, as a secondary command buffer can't be
+    /// executed again while a submission of it is still pending.
+    pub fn draws(
+        &mut self,
+        subpass: Subpass,
+        scene: &dyn Scene,
+        frame: &FrameData,
+        scissor: Scissor,
+    ) -> Result<Draws, RendererError> {
+        let key = scene
+            .draw_revision()
+            .filter(|_| !self.disabled)
+            .map(|revision| DrawKey { revision, scissor });
+        if let (Some(key), Some((cached_key, draws))) = (key, &self.draws[frame.frame_in_flight]) {
+            if key == *cached_key {
+                return Ok(draws.clone());
+            }
+        }
+
+        let usage = if key.is_some() {
+            CommandBufferUsage::MultipleSubmit
+        } else {
+            CommandBufferUsage::OneTimeSubmit
+        };
+        let record = |allocator, nodes| {
+            record_draws(
+                allocator,
+                self.queue_family_index,
+                usage,
+                subpass.clone(),
+                scene,
+                frame,
+                scissor,
+                nodes,
+            )
+        };
+        let chunks = chunks(scene.node_count(), self.allocators.len());
+        let draws = if chunks.len() <= 1 {
+            vec![record(&self.allocators[0], None)?]
+        } else {
+            thread::scope(|scope| {
+                let recorders: Vec<_> = chunks
+                    .into_iter()
+                    .zip(&self.allocators)
+                    .map(|(nodes, allocator)| scope.spawn(move || record(allocator, Some(nodes))))
+                    .collect();
+                recorders
+                    .into_iter()
+                    .map(|recorder| {
+                        recorder
+                            .join()
+                            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                    })
+                    .collect::<Result<Draws, _>>()
+            })?
+        };
+        self.draws[frame.frame_in_flight] = key.map(|key| (key, draws.clone()));
+        Ok(draws)
+    }
+
+    /// The draws of `scene` for both eyes of `frame` at once, for `subpass` of a multiview
+    /// render pass. These are recorded anew every frame.
+    pub fn stereo_draws(
+        &self,
+        subpass: Subpass,
+        scene: &dyn Scene,
+        frame: &FrameData,
+    ) -> Result<Arc<SecondaryAutoCommandBuffer>, RendererError> {
+        record_stereo_draws(
+            &self.allocators[0],
+            self.queue_family_index,
+            subpass,
+            scene,
+            frame,
+        )
+    }
+
+    /// Forgets every recorded draw, e.g. because the render pass they were recorded for has
+    /// been replaced.
+    pub fn clear(&mut self) {
+        self.draws.fill(None);
+    }
+}
+
+/// Splits `node_count` nodes into contiguous chunks of about the same size, one per thread for
+/// up to `threads` threads that each get at least [`MIN_NODES_PER_THREAD`]. A single chunk means
+/// the scene is best recorded on one thread.
+fn chunks(node_count: usize, threads: usize) -> Vec<Range<usize>> {
+    let threads = threads.min(node_count / MIN_NODES_PER_THREAD).max(1);
+    let (size, remainder) = (node_count / threads, node_count % threads);
+    let mut start = 0;
+    (0..threads)
+        .map(|chunk| {
+            // The first chunks take one node of the remainder each.
+            let end = start + size + usize::from(chunk < remainder);
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_scenes_are_one_chunk() {
+        assert_eq!(chunks(0, 8), vec![0..0]);
+        assert_eq!(chunks(MIN_NODES_PER_THREAD * 2 - 1, 8).len(), 1);
+        assert_eq!(chunks(100_000, 1), vec![0..100_000]);
+    }
+
+    #[test]
+    fn chunks_cover_every_node_in_order() {
+        let node_count = MIN_NODES_PER_THREAD * 3 + 2;
+        let chunks = chunks(node_count, 8);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].start, 0);
+        assert_eq!(chunks.last().unwrap().end, node_count);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        let sizes: Vec<_> = chunks.iter().map(ExactSizeIterator::len).collect();
+        assert_eq!(
+            sizes,
+            [
+                MIN_NODES_PER_THREAD + 1,
+                MIN_NODES_PER_THREAD + 1,
+                MIN_NODES_PER_THREAD
+            ]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Vertex data the CPU rewrites every frame, for geometry generated or animated in code.
+//!
+//! A [`DynamicMesh`] keeps its vertices on the CPU and copies them into a ring of host-visible
+//! vertex buffers, one per frame slot, after each [`update`](DynamicMesh::update). A slot is only
+//! written once the frame that last drew from it has finished, so the CPU never writes what the
+//! GPU might still be reading, however many frames are in flight. A slot's buffer grows when the
+//! mesh has more vertices than it holds and is kept otherwise.
+
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::DeviceSize;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::{MemoryCategory, MemoryTracker};
+use crate::scene::{FrameData, FRAME_SLOTS};
+
+/// Vertices a slot's buffer holds at least, so small meshes that grow a little don't
+/// reallocate every time.
+const MIN_CAPACITY: DeviceSize = 64;
+
+/// Vertices rewritten on the CPU every frame and drawn from a ring of vertex buffers.
+pub struct DynamicMesh<V: BufferContents> {
+    vertices: Vec<V>,
+    /// Each frame slot's buffer, made the first time the slot is updated.
+    ring: Vec<Option<Subbuffer<[V]>>>,
+    /// For growing the ring's buffers from [`Scene::prepare`](crate::scene::Scene::prepare).
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    memory_tracker: Arc<MemoryTracker>,
+}
+
+impl<V: BufferContents + Copy> DynamicMesh<V> {
+    /// A mesh starting out as `vertices`. Nothing is allocated until the first update.
+    pub fn new(ctx: &VulkanContext, vertices: Vec<V>) -> Self {
+        Self {
+            vertices,
+            ring: (0..FRAME_SLOTS).map(|_| None).collect(),
+            memory_allocator: ctx.memory_allocator.clone(),
+            memory_tracker: ctx.memory_tracker.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Changes how many vertices there are, filling any new ones with `value`. Takes effect in
+    /// each slot as it is next updated, growing its buffer if need be.
+    pub fn resize(&mut self, len: usize, value: V) {
+        self.vertices.resize(len, value);
+    }
+
+    /// Lets `update` change the vertices, then copies them into `frame`'s buffer. The vertices
+    /// are as the last update left them, whichever slot it was for. Only call this from
+    /// [`Scene::prepare`](crate::scene::Scene::prepare) or wherever else the slot's last frame is
+    /// known to have finished.
+    pub fn update(
+        &mut self,
+        frame: &FrameData,
+        update: impl FnOnce(&mut [V]),
+    ) -> Result<(), RendererError> {
+        update(&mut self.vertices);
+        let len = self.vertices.len() as DeviceSize;
+        let slot = &mut self.ring[frame.frame_in_flight];
+        let capacity = slot.as_ref().map(|buffer| buffer.len());
+        if let Some(capacity) = grown_capacity(capacity, len) {
+            let buffer = Buffer::new_slice::<V>(
+                self.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::VERTEX_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                capacity,
+            )?;
+            self.memory_tracker
+                .track_buffer(MemoryCategory::Vertex, buffer.buffer());
+            *slot = Some(buffer);
+        }
+        if let Some(buffer) = slot {
+            buffer.write()?[..self.vertices.len()].copy_from_slice(&self.vertices);
+        }
+        Ok(())
+    }
+
+    /// The vertices `frame`'s last update wrote, or `None` if its slot hasn't been updated yet
+    /// or there were none.
+    pub fn vertex_buffer(&self, frame: &FrameData) -> Option<Subbuffer<[V]>> {
+        let buffer = self.ring[frame.frame_in_flight].as_ref()?;
+        let len = (self.vertices.len() as DeviceSize).min(buffer.len());
+        (len > 0).then(|| buffer.clone().slice(..len))
+    }
+}
+
+/// How many vertices a slot's buffer of `capacity`, or none yet, must grow to for `len` of them,
+/// or `None` if it holds them already. Buffers double, so a mesh growing a vertex at a time
+/// doesn't reallocate every frame.
+fn grown_capacity(capacity: Option<DeviceSize>, len: DeviceSize) -> Option<DeviceSize> {
+    match capacity {
+        Some(capacity) if capacity >= len => None,
+        _ => Some(len.next_power_of_two().max(MIN_CAPACITY)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_only_grow_when_the_mesh_outgrows_them() {
+        assert_eq!(grown_capacity(None, 0), Some(MIN_CAPACITY));
+        assert_eq!(grown_capacity(None, 64 * 64), Some(4096));
+        assert_eq!(grown_capacity(Some(4096), 4096), None);
+        assert_eq!(grown_capacity(Some(4096), 10), None);
+        assert_eq!(grown_capacity(Some(4096), 4097), Some(8192));
+    }
+}
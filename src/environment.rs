@@ -0,0 +1,1050 @@
+//! Image-based lighting from an HDR environment map, for `--environment`.
+//!
+//! An equirectangular Radiance `.hdr` file is turned, once at startup and on the GPU, into what
+//! models are lit with: the environment as a cubemap, which is drawn as the sky too; copies of
+//! it prefiltered by GGX importance sampling, a mip level per roughness, for specular light; an
+//! irradiance cubemap for diffuse light; and the split-sum BRDF table the prefiltered light is
+//! scaled and biased by.
+//!
+//! What the preprocessing makes is written to a file in the cache directory named after a hash
+//! of the `.hdr` file's contents, and read back from it instead whenever the same file is used
+//! again.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BufferImageCopy, ClearColorImageInfo, CopyBufferToImageInfo,
+    CopyImageToBufferInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::{ClearColorValue, Format};
+use vulkano::image::sampler::{Sampler, SamplerAddressMode};
+use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
+use vulkano::image::{
+    Image, ImageCreateFlags, ImageCreateInfo, ImageSubresourceLayers, ImageSubresourceRange,
+    ImageType, ImageUsage,
+};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+use vulkano::DeviceSize;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::sampler::SamplerConfig;
+use crate::scene::build_compute_pipeline;
+use crate::texture::Texture;
+
+/// Format of every image the preprocessing makes, which the shaders' `rgba16f` qualifiers
+/// match. Storage images of it are supported everywhere.
+const ENVIRONMENT_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Bytes per texel of [`ENVIRONMENT_FORMAT`].
+const TEXEL_BYTES: DeviceSize = 8;
+
+/// Texels each workgroup handles along each axis; matches `local_size_x` and `local_size_y` in
+/// the shaders.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The size, mip levels and layers of one of the images of an [`Environment`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ImageSpec {
+    size: u32,
+    levels: u32,
+    /// Six for cubemaps, one for the BRDF table.
+    layers: u32,
+}
+
+impl ImageSpec {
+    fn level_size(self, level: u32) -> u32 {
+        (self.size >> level).max(1)
+    }
+
+    fn level_bytes(self, level: u32) -> DeviceSize {
+        let size = self.level_size(level) as DeviceSize;
+        size * size * self.layers as DeviceSize * TEXEL_BYTES
+    }
+
+    fn bytes(self) -> DeviceSize {
+        (0..self.levels).map(|level| self.level_bytes(level)).sum()
+    }
+}
+
+/// The environment as a cubemap, with a full chain of mips for the prefiltering to sample.
+const CUBE: ImageSpec = ImageSpec {
+    size: 512,
+    levels: 512_u32.ilog2() + 1,
+    layers: 6,
+};
+
+/// From mirrors in the first level to a roughness of one in the last.
+const PREFILTERED: ImageSpec = ImageSpec {
+    size: 128,
+    levels: 5,
+    layers: 6,
+};
+
+/// Diffuse light changes slowly with the normal, so a small cubemap holds it.
+const IRRADIANCE: ImageSpec = ImageSpec {
+    size: 32,
+    levels: 1,
+    layers: 6,
+};
+
+const BRDF_LUT: ImageSpec = ImageSpec {
+    size: 128,
+    levels: 1,
+    layers: 1,
+};
+
+/// The images of an environment cache file, in the order they are stored.
+const CACHED_IMAGES: [ImageSpec; 4] = [CUBE, PREFILTERED, IRRADIANCE, BRDF_LUT];
+
+/// What every cache file starts with.
+const CACHE_MAGIC: &[u8; 8] = b"HVKIBL\0\0";
+
+/// Changes whenever the preprocessing does, so older cache files are made again rather than
+/// read.
+const CACHE_VERSION: u32 = 1;
+
+mod equirect_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            // Radiance's shared-exponent texels, as they are in the file.
+            layout(set = 0, binding = 0) uniform sampler2D equirect;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray cube;
+
+            const float PI = 3.14159265;
+            // The most a half float holds.
+            const float HALF_MAX = 65504.0;
+
+            // The direction through `uv`, from 0 to 1 across and down, of cube face `face`, as
+            // Vulkan lays the faces out.
+            vec3 cube_direction(uint face, vec2 uv) {
+                vec2 p = uv * 2.0 - 1.0;
+                switch (face) {
+                    case 0: return normalize(vec3(1.0, -p.y, -p.x));
+                    case 1: return normalize(vec3(-1.0, -p.y, p.x));
+                    case 2: return normalize(vec3(p.x, 1.0, p.y));
+                    case 3: return normalize(vec3(p.x, -1.0, -p.y));
+                    case 4: return normalize(vec3(p.x, -p.y, 1.0));
+                    default: return normalize(vec3(-p.x, -p.y, -1.0));
+                }
+            }
+
+            // The radiance of texel `p`, wrapping around the sides and clamped at the poles.
+            vec3 texel(ivec2 p) {
+                ivec2 size = textureSize(equirect, 0);
+                p = ivec2((p.x % size.x + size.x) % size.x, clamp(p.y, 0, size.y - 1));
+                vec4 rgbe = texelFetch(equirect, p, 0) * 255.0;
+                if (rgbe.a == 0.0) {
+                    return vec3(0.0);
+                }
+                // Mantissas in 256ths, from the middle of each step, and an exponent biased by 128.
+                return (rgbe.rgb + 0.5) / 256.0 * exp2(rgbe.a - 128.0);
+            }
+
+            void main() {
+                uvec3 id = gl_GlobalInvocationID;
+                ivec2 size = imageSize(cube).xy;
+                if (any(greaterThanEqual(ivec2(id.xy), size))) {
+                    return;
+                }
+                vec3 d = cube_direction(id.z, (vec2(id.xy) + 0.5) / vec2(size));
+                vec2 uv = vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+                // Filtered by hand, since mixing shared exponents doesn't mix what they encode.
+                vec2 p = uv * vec2(textureSize(equirect, 0)) - 0.5;
+                ivec2 i = ivec2(floor(p));
+                vec2 f = p - vec2(i);
+                vec3 color = mix(
+                    mix(texel(i), texel(i + ivec2(1, 0)), f.x),
+                    mix(texel(i + ivec2(0, 1)), texel(i + ivec2(1, 1)), f.x),
+                    f.y
+                );
+                imageStore(cube, ivec3(id), vec4(min(color, HALF_MAX), 1.0));
+            }
+        "
+    }
+}
+
+mod downsample_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, rgba16f) uniform readonly image2DArray source;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray target;
+
+            // Each texel of the next mip level is the average of the four it covers.
+            void main() {
+                ivec3 id = ivec3(gl_GlobalInvocationID);
+                if (any(greaterThanEqual(id.xy, imageSize(target).xy))) {
+                    return;
+                }
+                ivec3 p = ivec3(id.xy * 2, id.z);
+                vec4 sum = imageLoad(source, p)
+                    + imageLoad(source, p + ivec3(1, 0, 0))
+                    + imageLoad(source, p + ivec3(0, 1, 0))
+                    + imageLoad(source, p + ivec3(1, 1, 0));
+                imageStore(target, id, sum / 4.0);
+            }
+        "
+    }
+}
+
+mod prefilter_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0) uniform samplerCube environment;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray prefiltered;
+
+            layout(push_constant) uniform Prefilter {
+                float roughness;
+            } prefilter;
+
+            const float PI = 3.14159265;
+            const uint SAMPLES = 1024;
+
+            // As in `equirect_cs`.
+            vec3 cube_direction(uint face, vec2 uv) {
+                vec2 p = uv * 2.0 - 1.0;
+                switch (face) {
+                    case 0: return normalize(vec3(1.0, -p.y, -p.x));
+                    case 1: return normalize(vec3(-1.0, -p.y, p.x));
+                    case 2: return normalize(vec3(p.x, 1.0, p.y));
+                    case 3: return normalize(vec3(p.x, -1.0, -p.y));
+                    case 4: return normalize(vec3(p.x, -p.y, 1.0));
+                    default: return normalize(vec3(-p.x, -p.y, -1.0));
+                }
+            }
+
+            // The `i`th of the Hammersley set's points, spread evenly over the unit square.
+            vec2 hammersley(uint i) {
+                return vec2(float(i) / float(SAMPLES), float(bitfieldReverse(i)) * 2.3283064e-10);
+            }
+
+            // A microfacet normal around `normal`, drawn from GGX's distribution for `alpha` by
+            // the point `xi`.
+            vec3 importance_sample_ggx(vec2 xi, vec3 normal, float alpha) {
+                float phi = 2.0 * PI * xi.x;
+                float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+                float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+                vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+                vec3 tangent = normalize(cross(up, normal));
+                vec3 bitangent = cross(normal, tangent);
+                return normalize(
+                    (tangent * cos(phi) + bitangent * sin(phi)) * sin_theta + normal * cos_theta
+                );
+            }
+
+            void main() {
+                uvec3 id = gl_GlobalInvocationID;
+                ivec2 size = imageSize(prefiltered).xy;
+                if (any(greaterThanEqual(ivec2(id.xy), size))) {
+                    return;
+                }
+                // Seen head on, as the split sum assumes: the view and reflection are the normal.
+                vec3 normal = cube_direction(id.z, (vec2(id.xy) + 0.5) / vec2(size));
+                if (prefilter.roughness == 0.0) {
+                    imageStore(prefiltered, ivec3(id), textureLod(environment, normal, 0.0));
+                    return;
+                }
+                float alpha = prefilter.roughness * prefilter.roughness;
+                float alpha2 = alpha * alpha;
+                float texel_solid_angle = 4.0 * PI / (6.0 * pow(float(textureSize(environment, 0).x), 2.0));
+                vec3 sum = vec3(0.0);
+                float weight = 0.0;
+                for (uint i = 0; i < SAMPLES; i++) {
+                    vec3 half_vector = importance_sample_ggx(hammersley(i), normal, alpha);
+                    float n_dot_h = max(dot(normal, half_vector), 0.0);
+                    vec3 light = 2.0 * n_dot_h * half_vector - normal;
+                    float n_dot_l = dot(normal, light);
+                    if (n_dot_l <= 0.0) {
+                        continue;
+                    }
+                    // The fewer samples land around a direction, the more of the environment each
+                    // stands for, so the blurrier the mip level it's read from, lest a few bright
+                    // texels sparkle.
+                    float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+                    float pdf = alpha2 / (PI * d * d) / 4.0;
+                    float sample_solid_angle = 1.0 / (float(SAMPLES) * pdf + 1e-4);
+                    float lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+                    sum += textureLod(environment, light, lod).rgb * n_dot_l;
+                    weight += n_dot_l;
+                }
+                imageStore(prefiltered, ivec3(id), vec4(sum / max(weight, 1e-4), 1.0));
+            }
+        "
+    }
+}
+
+mod irradiance_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0) uniform samplerCube environment;
+            layout(set = 0, binding = 1, rgba16f) uniform writeonly image2DArray irradiance;
+
+            const float PI = 3.14159265;
+            const uint SAMPLES = 1024;
+
+            // As in `prefilter_cs`.
+            vec3 cube_direction(uint face, vec2 uv) {
+                vec2 p = uv * 2.0 - 1.0;
+                switch (face) {
+                    case 0: return normalize(vec3(1.0, -p.y, -p.x));
+                    case 1: return normalize(vec3(-1.0, -p.y, p.x));
+                    case 2: return normalize(vec3(p.x, 1.0, p.y));
+                    case 3: return normalize(vec3(p.x, -1.0, -p.y));
+                    case 4: return normalize(vec3(p.x, -p.y, 1.0));
+                    default: return normalize(vec3(-p.x, -p.y, -1.0));
+                }
+            }
+
+            vec2 hammersley(uint i) {
+                return vec2(float(i) / float(SAMPLES), float(bitfieldReverse(i)) * 2.3283064e-10);
+            }
+
+            // The light arriving from around each normal, weighted by the cosine of its angle
+            // and over pi: what a white Lambertian surface facing that way reflects. Drawn with
+            // as many samples towards each direction as its cosine, which the average then
+            // weights them by.
+            void main() {
+                uvec3 id = gl_GlobalInvocationID;
+                ivec2 size = imageSize(irradiance).xy;
+                if (any(greaterThanEqual(ivec2(id.xy), size))) {
+                    return;
+                }
+                vec3 normal = cube_direction(id.z, (vec2(id.xy) + 0.5) / vec2(size));
+                vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+                vec3 tangent = normalize(cross(up, normal));
+                vec3 bitangent = cross(normal, tangent);
+                float texel_solid_angle = 4.0 * PI / (6.0 * pow(float(textureSize(environment, 0).x), 2.0));
+                vec3 sum = vec3(0.0);
+                for (uint i = 0; i < SAMPLES; i++) {
+                    vec2 xi = hammersley(i);
+                    float phi = 2.0 * PI * xi.x;
+                    float cos_theta = sqrt(1.0 - xi.y);
+                    float sin_theta = sqrt(xi.y);
+                    vec3 light = (tangent * cos(phi) + bitangent * sin(phi)) * sin_theta
+                        + normal * cos_theta;
+                    // As in `prefilter_cs`, for the cosine's density of samples.
+                    float pdf = max(cos_theta, 1e-3) / PI;
+                    float lod = max(0.5 * log2(1.0 / (float(SAMPLES) * pdf * texel_solid_angle)) + 1.0, 0.0);
+                    sum += textureLod(environment, light, lod).rgb;
+                }
+                imageStore(irradiance, ivec3(id), vec4(sum / float(SAMPLES), 1.0));
+            }
+        "
+    }
+}
+
+mod brdf_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, rgba16f) uniform writeonly image2D lut;
+
+            const float PI = 3.14159265;
+            const uint SAMPLES = 512;
+
+            // As in `prefilter_cs`.
+            vec2 hammersley(uint i) {
+                return vec2(float(i) / float(SAMPLES), float(bitfieldReverse(i)) * 2.3283064e-10);
+            }
+
+            vec3 importance_sample_ggx(vec2 xi, float alpha) {
+                float phi = 2.0 * PI * xi.x;
+                float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+                float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+                return vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+            }
+
+            // Smith's shadowing or masking by Schlick's fit of GGX's, as the model shader has it.
+            float geometry(float n_dot_x, float k) {
+                return n_dot_x / (n_dot_x * (1.0 - k) + k);
+            }
+
+            // For the cosine of the view angle across and the roughness down, the scale in red
+            // and bias in green that turn the reflectance at normal incidence into how much of
+            // the prefiltered light the Cook-Torrance BRDF reflects.
+            void main() {
+                ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(lut);
+                if (any(greaterThanEqual(id, size))) {
+                    return;
+                }
+                vec2 uv = (vec2(id) + 0.5) / vec2(size);
+                float n_dot_v = uv.x;
+                float alpha = max(uv.y * uv.y, 1e-3);
+                float k = alpha / 2.0;
+                // With the normal along z.
+                vec3 view = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+                vec2 sum = vec2(0.0);
+                for (uint i = 0; i < SAMPLES; i++) {
+                    vec3 half_vector = importance_sample_ggx(hammersley(i), alpha);
+                    float v_dot_h = max(dot(view, half_vector), 0.0);
+                    vec3 light = 2.0 * v_dot_h * half_vector - view;
+                    float n_dot_l = light.z;
+                    if (n_dot_l <= 0.0) {
+                        continue;
+                    }
+                    float n_dot_h = max(half_vector.z, 1e-4);
+                    float visibility = geometry(n_dot_v, k) * geometry(n_dot_l, k) * v_dot_h
+                        / (n_dot_h * n_dot_v);
+                    float fresnel = pow(1.0 - v_dot_h, 5.0);
+                    sum += vec2(1.0 - fresnel, fresnel) * visibility;
+                }
+                imageStore(lut, id, vec4(sum / float(SAMPLES), 0.0, 1.0));
+            }
+        "
+    }
+}
+
+/// The images a model is lit with, from an environment map or evenly from all around.
+pub struct Environment {
+    /// The environment itself, with a full chain of mips, to draw as the sky.
+    pub cube: Arc<ImageView>,
+    /// The environment as reflected by ever rougher surfaces, from mirrors in the first mip
+    /// level to a roughness of one in the last.
+    pub prefiltered: Arc<ImageView>,
+    /// The light a white Lambertian surface facing each way reflects.
+    pub irradiance: Arc<ImageView>,
+    /// The split-sum table, for the cosine of the view angle across and the roughness down.
+    pub brdf_lut: Arc<ImageView>,
+    /// Linearly filtered between texels and mip levels, and clamped to the edge.
+    pub sampler: Arc<Sampler>,
+}
+
+impl Environment {
+    /// Preprocesses the equirectangular `.hdr` file at `path`, or reads what that made before
+    /// from the cache.
+    pub fn load(ctx: &VulkanContext, path: &Path) -> Result<Self, RendererError> {
+        let invalid = |err: String| {
+            RendererError::InvalidTexture(format!("environment {}: {err}", path.display()))
+        };
+        let bytes = fs::read(path).map_err(|err| invalid(err.to_string()))?;
+        let cache_path = cache_dir().join(format!("{:016x}.ibl", fnv1a(&bytes)));
+        let images = [
+            create_image(ctx, CUBE, "environment cubemap")?,
+            create_image(ctx, PREFILTERED, "environment prefiltered")?,
+            create_image(ctx, IRRADIANCE, "environment irradiance")?,
+            create_image(ctx, BRDF_LUT, "environment BRDF table")?,
+        ];
+
+        let cached = fs::read(&cache_path).ok();
+        match cached.as_deref().and_then(cached_images) {
+            Some(data) => {
+                upload(ctx, &images, data)?;
+                log::info!(
+                    "Read the preprocessed environment {} from {}",
+                    path.display(),
+                    cache_path.display()
+                );
+            }
+            None => {
+                let start = Instant::now();
+                let (size, rgbe) = parse_hdr(&bytes).map_err(invalid)?;
+                preprocess(ctx, &images, size, &rgbe)?;
+                log::info!(
+                    "Preprocessed the environment {} in {:.2?}",
+                    path.display(),
+                    start.elapsed()
+                );
+                let mut file = cache_header();
+                file.extend(read_back(ctx, &images)?);
+                let written = fs::create_dir_all(cache_path.parent().unwrap())
+                    .and_then(|()| fs::write(&cache_path, file));
+                if let Err(err) = written {
+                    log::warn!(
+                        "Could not cache the environment in {}: {err}",
+                        cache_path.display()
+                    );
+                }
+            }
+        }
+        Self::from_images(ctx, images)
+    }
+
+    /// An environment of `radiance` in every direction, lighting models evenly where there is
+    /// no environment map.
+    pub fn uniform(ctx: &VulkanContext, radiance: f32) -> Result<Self, RendererError> {
+        let texel = ImageSpec {
+            size: 1,
+            levels: 1,
+            layers: 6,
+        };
+        let images = [
+            create_image(ctx, texel, "uniform environment cubemap")?,
+            create_image(ctx, texel, "uniform environment prefiltered")?,
+            create_image(ctx, texel, "uniform environment irradiance")?,
+            create_image(ctx, BRDF_LUT, "environment BRDF table")?,
+        ];
+        let brdf_pipeline = build_compute_pipeline(ctx, brdf_cs::load(ctx.device.clone())?)?;
+        ctx.name_object(&brdf_pipeline, "environment BRDF pipeline");
+        ctx.submit_and_wait(|builder| {
+            for image in &images[..3] {
+                builder.clear_color_image(ClearColorImageInfo {
+                    clear_value: ClearColorValue::Float([radiance, radiance, radiance, 1.0]),
+                    ..ClearColorImageInfo::image(image.clone())
+                })?;
+            }
+            record_brdf_lut(ctx, builder, &brdf_pipeline, &images[3])
+        })?;
+        Self::from_images(ctx, images)
+    }
+
+    fn from_images(
+        ctx: &VulkanContext,
+        [cube, prefiltered, irradiance, brdf_lut]: [Arc<Image>; 4],
+    ) -> Result<Self, RendererError> {
+        Ok(Self {
+            cube: cube_view(&cube)?,
+            prefiltered: cube_view(&prefiltered)?,
+            irradiance: cube_view(&irradiance)?,
+            brdf_lut: ImageView::new_default(brdf_lut)?,
+            sampler: ctx
+                .samplers
+                .get(SamplerConfig::default().with_address_mode(SamplerAddressMode::ClampToEdge))?,
+        })
+    }
+}
+
+/// Makes an image to `spec`, written by the shaders or the cache and sampled by models.
+fn create_image(
+    ctx: &VulkanContext,
+    spec: ImageSpec,
+    label: &str,
+) -> Result<Arc<Image>, RendererError> {
+    let image = Image::new(
+        ctx.memory_allocator.clone(),
+        ImageCreateInfo {
+            flags: if spec.layers == 6 {
+                ImageCreateFlags::CUBE_COMPATIBLE
+            } else {
+                ImageCreateFlags::empty()
+            },
+            image_type: ImageType::Dim2d,
+            format: ENVIRONMENT_FORMAT,
+            extent: [spec.size, spec.size, 1],
+            mip_levels: spec.levels,
+            array_layers: spec.layers,
+            usage: ImageUsage::STORAGE
+                | ImageUsage::SAMPLED
+                | ImageUsage::TRANSFER_SRC
+                | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+    ctx.memory_tracker
+        .track_image(MemoryCategory::Texture, &image);
+    ctx.name_object(&image, label);
+    Ok(image)
+}
+
+/// All of `image`, sampled as a cubemap.
+fn cube_view(image: &Arc<Image>) -> Result<Arc<ImageView>, RendererError> {
+    Ok(ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type: ImageViewType::Cube,
+            ..ImageViewCreateInfo::from_image(image)
+        },
+    )?)
+}
+
+/// Mip level `level` of `image`, every layer, for a shader to write.
+fn level_view(image: &Arc<Image>, level: u32) -> Result<Arc<ImageView>, RendererError> {
+    Ok(ImageView::new(
+        image.clone(),
+        ImageViewCreateInfo {
+            view_type: if image.array_layers() == 1 {
+                ImageViewType::Dim2d
+            } else {
+                ImageViewType::Dim2dArray
+            },
+            subresource_range: ImageSubresourceRange {
+                mip_levels: level..level + 1,
+                ..image.subresource_range()
+            },
+            ..ImageViewCreateInfo::from_image(image)
+        },
+    )?)
+}
+
+/// Runs `pipeline` with `set` over every texel of a `size` by `size` image of `layers` layers.
+fn dispatch(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: &Arc<ComputePipeline>,
+    set: Arc<PersistentDescriptorSet>,
+    size: u32,
+    layers: u32,
+) -> Result<(), RendererError> {
+    let groups = size.div_ceil(WORKGROUP_SIZE);
+    builder
+        .bind_pipeline_compute(pipeline.clone())?
+        .bind_descriptor_sets(
+            PipelineBindPoint::Compute,
+            pipeline.layout().clone(),
+            0,
+            set,
+        )?
+        .dispatch([groups, groups, layers])?;
+    Ok(())
+}
+
+/// A set binding `writes` at set 0 of `pipeline`.
+fn descriptor_set(
+    ctx: &VulkanContext,
+    pipeline: &ComputePipeline,
+    writes: impl IntoIterator<Item = WriteDescriptorSet>,
+) -> Result<Arc<PersistentDescriptorSet>, RendererError> {
+    Ok(PersistentDescriptorSet::new(
+        ctx.descriptor_set_allocator.as_ref(),
+        pipeline.layout().set_layouts()[0].clone(),
+        writes,
+        [],
+    )?)
+}
+
+/// Records filling `lut`, a [`BRDF_LUT`] image, with the split-sum table.
+fn record_brdf_lut(
+    ctx: &VulkanContext,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    pipeline: &Arc<ComputePipeline>,
+    lut: &Arc<Image>,
+) -> Result<(), RendererError> {
+    let set = descriptor_set(
+        ctx,
+        pipeline,
+        [WriteDescriptorSet::image_view(0, level_view(lut, 0)?)],
+    )?;
+    dispatch(builder, pipeline, set, BRDF_LUT.size, 1)
+}
+
+/// Fills the [`CACHED_IMAGES`] from the equirectangular environment of `size`, in Radiance's
+/// shared-exponent texels.
+fn preprocess(
+    ctx: &VulkanContext,
+    [cube, prefiltered, irradiance, brdf_lut]: &[Arc<Image>; 4],
+    [width, height]: [u32; 2],
+    rgbe: &[u8],
+) -> Result<(), RendererError> {
+    let source = Texture::from_rgba8(ctx, width, height, rgbe, SamplerConfig::nearest_clamped())?;
+    ctx.name_object(&source.image, "environment equirect");
+    let pipeline = |module, name: &str| -> Result<_, RendererError> {
+        let pipeline = build_compute_pipeline(ctx, module)?;
+        ctx.name_object(&pipeline, &format!("environment {name} pipeline"));
+        Ok(pipeline)
+    };
+    let equirect_pipeline = pipeline(equirect_cs::load(ctx.device.clone())?, "equirect")?;
+    let downsample_pipeline = pipeline(downsample_cs::load(ctx.device.clone())?, "downsample")?;
+    let prefilter_pipeline = pipeline(prefilter_cs::load(ctx.device.clone())?, "prefilter")?;
+    let irradiance_pipeline = pipeline(irradiance_cs::load(ctx.device.clone())?, "irradiance")?;
+    let brdf_pipeline = pipeline(brdf_cs::load(ctx.device.clone())?, "BRDF")?;
+    let sampler = ctx
+        .samplers
+        .get(SamplerConfig::default().with_address_mode(SamplerAddressMode::ClampToEdge))?;
+    let cube_sampled = cube_view(cube)?;
+    let environment =
+        || WriteDescriptorSet::image_view_sampler(0, cube_sampled.clone(), sampler.clone());
+
+    ctx.submit_and_wait(|builder| {
+        let set = descriptor_set(
+            ctx,
+            &equirect_pipeline,
+            [
+                WriteDescriptorSet::image_view_sampler(
+                    0,
+                    source.view.clone(),
+                    source.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view(1, level_view(cube, 0)?),
+            ],
+        )?;
+        dispatch(builder, &equirect_pipeline, set, CUBE.size, 6)?;
+        for level in 1..CUBE.levels {
+            let set = descriptor_set(
+                ctx,
+                &downsample_pipeline,
+                [
+                    WriteDescriptorSet::image_view(0, level_view(cube, level - 1)?),
+                    WriteDescriptorSet::image_view(1, level_view(cube, level)?),
+                ],
+            )?;
+            dispatch(
+                builder,
+                &downsample_pipeline,
+                set,
+                CUBE.level_size(level),
+                6,
+            )?;
+        }
+
+        for level in 0..PREFILTERED.levels {
+            let set = descriptor_set(
+                ctx,
+                &prefilter_pipeline,
+                [
+                    environment(),
+                    WriteDescriptorSet::image_view(1, level_view(prefiltered, level)?),
+                ],
+            )?;
+            builder.push_constants(
+                prefilter_pipeline.layout().clone(),
+                0,
+                prefilter_cs::Prefilter {
+                    roughness: level as f32 / (PREFILTERED.levels - 1) as f32,
+                },
+            )?;
+            dispatch(
+                builder,
+                &prefilter_pipeline,
+                set,
+                PREFILTERED.level_size(level),
+                6,
+            )?;
+        }
+
+        let set = descriptor_set(
+            ctx,
+            &irradiance_pipeline,
+            [
+                environment(),
+                WriteDescriptorSet::image_view(1, level_view(irradiance, 0)?),
+            ],
+        )?;
+        dispatch(builder, &irradiance_pipeline, set, IRRADIANCE.size, 6)?;
+        record_brdf_lut(ctx, builder, &brdf_pipeline, brdf_lut)
+    })
+}
+
+/// The copies between each mip level of `image`, to `spec`, and a buffer holding them one after
+/// the other from `offset`.
+fn level_copies(
+    image: &Image,
+    spec: ImageSpec,
+    offset: DeviceSize,
+) -> impl Iterator<Item = BufferImageCopy> + '_ {
+    (0..spec.levels).scan(offset, move |offset, level| {
+        let size = spec.level_size(level);
+        let copy = BufferImageCopy {
+            buffer_offset: *offset,
+            image_subresource: ImageSubresourceLayers {
+                mip_level: level,
+                ..image.subresource_layers()
+            },
+            image_extent: [size, size, 1],
+            ..Default::default()
+        };
+        *offset += spec.level_bytes(level);
+        Some(copy)
+    })
+}
+
+/// Where each of the [`CACHED_IMAGES`] starts in a cache file's data.
+fn cached_offsets() -> impl Iterator<Item = (ImageSpec, DeviceSize)> {
+    CACHED_IMAGES.into_iter().scan(0, |offset, spec| {
+        let start = *offset;
+        *offset += spec.bytes();
+        Some((spec, start))
+    })
+}
+
+/// Copies `data`, a cache file's after its header, into `images`.
+fn upload(ctx: &VulkanContext, images: &[Arc<Image>; 4], data: &[u8]) -> Result<(), RendererError> {
+    let staging_buffer = Buffer::from_iter(
+        ctx.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data.iter().copied(),
+    )?;
+    ctx.submit_and_wait(|builder| {
+        for (image, (spec, offset)) in images.iter().zip(cached_offsets()) {
+            builder.copy_buffer_to_image(CopyBufferToImageInfo {
+                regions: level_copies(image, spec, offset).collect(),
+                ..CopyBufferToImageInfo::buffer_image(staging_buffer.clone(), image.clone())
+            })?;
+        }
+        Ok(())
+    })
+}
+
+/// Copies `images` back from the GPU, for a cache file's data.
+fn read_back(ctx: &VulkanContext, images: &[Arc<Image>; 4]) -> Result<Vec<u8>, RendererError> {
+    let buffer = Buffer::new_slice::<u8>(
+        ctx.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        cached_offsets().map(|(spec, _)| spec.bytes()).sum(),
+    )?;
+    ctx.submit_and_wait(|builder| {
+        for (image, (spec, offset)) in images.iter().zip(cached_offsets()) {
+            builder.copy_image_to_buffer(CopyImageToBufferInfo {
+                regions: level_copies(image, spec, offset).collect(),
+                ..CopyImageToBufferInfo::image_buffer(image.clone(), buffer.clone())
+            })?;
+        }
+        Ok(())
+    })?;
+    let data = buffer.read()?.to_vec();
+    Ok(data)
+}
+
+/// What a cache file starts with: the magic, the version and the size, mip levels and layers of
+/// each of the [`CACHED_IMAGES`].
+fn cache_header() -> Vec<u8> {
+    let mut header = CACHE_MAGIC.to_vec();
+    header.extend(CACHE_VERSION.to_le_bytes());
+    for spec in CACHED_IMAGES {
+        for value in [spec.size, spec.levels, spec.layers] {
+            header.extend(value.to_le_bytes());
+        }
+    }
+    header
+}
+
+/// The images' data in a cache file, or `None` if it was written by another version or is cut
+/// short.
+fn cached_images(file: &[u8]) -> Option<&[u8]> {
+    let data = file.strip_prefix(cache_header().as_slice())?;
+    let expected: DeviceSize = CACHED_IMAGES.iter().map(|spec| spec.bytes()).sum();
+    (data.len() as DeviceSize == expected).then_some(data)
+}
+
+/// Where preprocessed environments are cached: the platform's cache directory, or the temporary
+/// directory where there's none.
+fn cache_dir() -> PathBuf {
+    let var = |name| {
+        env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    var("XDG_CACHE_HOME")
+        .or_else(|| var("LOCALAPPDATA"))
+        .or_else(|| var("HOME").map(|home| home.join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("hi-vulkanos")
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, the same on every platform and in every build, unlike
+/// the standard library's hashers.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Decodes a Radiance `.hdr` file into its size and its texels, top row first, each four bytes:
+/// red, green and blue mantissas sharing the exponent in the fourth. Scanlines may be flat or
+/// run-length encoded either way Radiance does.
+fn parse_hdr(bytes: &[u8]) -> Result<([u32; 2], Vec<u8>), String> {
+    if !bytes.starts_with(b"#?") {
+        return Err("not a Radiance HDR file".to_owned());
+    }
+    let mut lines = bytes.split(|&byte| byte == b'\n');
+    let mut header_len = 0;
+    for line in lines.by_ref() {
+        header_len += line.len() + 1;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix(b"FORMAT=") {
+            if format != b"32-bit_rle_rgbe" {
+                return Err(format!(
+                    "{} pixels aren't supported, only 32-bit_rle_rgbe",
+                    String::from_utf8_lossy(format)
+                ));
+            }
+        }
+    }
+    let resolution = lines.next().ok_or("no resolution")?;
+    header_len += resolution.len() + 1;
+    let resolution = String::from_utf8_lossy(resolution);
+    let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (width.parse::<u32>(), height.parse::<u32>()),
+        _ => {
+            return Err(format!(
+                "only top-to-bottom, left-to-right images are supported, not {resolution}"
+            ))
+        }
+    };
+    let (Ok(width), Ok(height)) = (width, height) else {
+        return Err(format!("invalid resolution {resolution}"));
+    };
+    if width == 0 || height == 0 {
+        return Err(format!("invalid resolution {resolution}"));
+    }
+
+    let mut data = bytes.get(header_len..).ok_or("no pixels")?;
+    let mut texels = Vec::with_capacity(width as usize * height as usize * 4);
+    let truncated = || "the pixels end early".to_owned();
+    for _ in 0..height {
+        let row = texels.len();
+        texels.resize(row + width as usize * 4, 0);
+        let scanline = &mut texels[row..];
+        let rle = (8..=0x7fff).contains(&width)
+            && data.len() >= 4
+            && data[..2] == [2, 2]
+            && u16::from_be_bytes([data[2], data[3]]) as u32 == width;
+        if rle {
+            // Each channel of the scanline in turn, in runs of one repeated byte or literals.
+            data = &data[4..];
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < width as usize {
+                    let (&count, rest) = data.split_first().ok_or_else(truncated)?;
+                    let (run, literal) = if count > 128 {
+                        (count as usize - 128, false)
+                    } else {
+                        (count as usize, true)
+                    };
+                    if run == 0 || x + run > width as usize {
+                        return Err("a run overruns its scanline".to_owned());
+                    }
+                    let taken = if literal { run } else { 1 };
+                    let values = rest.get(..taken).ok_or_else(truncated)?;
+                    for i in 0..run {
+                        scanline[(x + i) * 4 + channel] = values[if literal { i } else { 0 }];
+                    }
+                    data = &rest[taken..];
+                    x += run;
+                }
+            }
+        } else {
+            // Flat texels, where a texel of three ones repeats the one before it, more times for
+            // every such texel in a row.
+            let mut x = 0;
+            let mut shift = 0;
+            while x < width as usize {
+                let texel = data.get(..4).ok_or_else(truncated)?;
+                data = &data[4..];
+                if texel[..3] == [1, 1, 1] && x > 0 {
+                    let repeats = (texel[3] as usize) << shift;
+                    if x + repeats > width as usize {
+                        return Err("a run overruns its scanline".to_owned());
+                    }
+                    let previous: [u8; 4] = scanline[(x - 1) * 4..x * 4].try_into().unwrap();
+                    for i in 0..repeats {
+                        scanline[(x + i) * 4..(x + i + 1) * 4].copy_from_slice(&previous);
+                    }
+                    x += repeats;
+                    shift += 8;
+                } else {
+                    scanline[x * 4..(x + 1) * 4].copy_from_slice(texel);
+                    x += 1;
+                    shift = 0;
+                }
+            }
+        }
+    }
+    Ok(([width, height], texels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+        let mut file =
+            format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {height} +X {width}\n").into_bytes();
+        file.extend(pixels);
+        file
+    }
+
+    #[test]
+    fn hdr_files_decode_flat_and_run_length_encoded() {
+        // Flat, with the last texel repeated twice the old way.
+        let flat = hdr(4, 1, &[10, 20, 30, 128, 40, 50, 60, 129, 1, 1, 1, 2]);
+        let (size, texels) = parse_hdr(&flat).unwrap();
+        assert_eq!(size, [4, 1]);
+        assert_eq!(
+            texels,
+            [10, 20, 30, 128, 40, 50, 60, 129, 40, 50, 60, 129, 40, 50, 60, 129]
+        );
+
+        // Eight texels, red as a run, green and blue as literals and the exponent in two runs.
+        let mut scanline = vec![2, 2, 0, 8];
+        scanline.extend([128 + 8, 7]);
+        scanline.extend([8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        scanline.extend([8, 9, 9, 9, 9, 9, 9, 9, 9]);
+        scanline.extend([128 + 3, 130, 128 + 5, 131]);
+        let (size, texels) = parse_hdr(&hdr(8, 1, &scanline)).unwrap();
+        assert_eq!(size, [8, 1]);
+        let expected: Vec<u8> = (0..8)
+            .flat_map(|x| [7, x, 9, if x < 3 { 130 } else { 131 }])
+            .collect();
+        assert_eq!(texels, expected);
+
+        assert!(parse_hdr(&hdr(8, 1, &scanline[..10])).is_err());
+        assert!(parse_hdr(b"P6\n1 1\n255\n").is_err());
+        let bottom_up = b"#?RADIANCE\n\n+Y 1 +X 1\n\x01\x02\x03\x80";
+        assert!(parse_hdr(bottom_up).is_err());
+    }
+
+    #[test]
+    fn cache_files_are_only_read_back_as_this_version_wrote_them() {
+        // FNV-1a's published values, which the file names must keep to.
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        let bytes: DeviceSize = CACHED_IMAGES.iter().map(|spec| spec.bytes()).sum();
+        let mut file = cache_header();
+        file.resize(file.len() + bytes as usize, 7);
+        assert_eq!(cached_images(&file).map(<[u8]>::len), Some(bytes as usize));
+        assert_eq!(cached_images(&file[..file.len() - 1]), None);
+        file[CACHE_MAGIC.len()] ^= 1;
+        assert_eq!(cached_images(&file), None);
+
+        // Each level in turn, the cubemap's down to a single texel.
+        assert_eq!(CUBE.levels, 10);
+        assert_eq!(CUBE.level_bytes(CUBE.levels - 1), 6 * TEXEL_BYTES);
+        let offsets: Vec<_> = cached_offsets().map(|(_, offset)| offset).collect();
+        assert_eq!(offsets[1], CUBE.bytes());
+        assert_eq!(
+            offsets[3],
+            CUBE.bytes() + PREFILTERED.bytes() + IRRADIANCE.bytes()
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::fmt;
+
+use vulkano::buffer::AllocateBufferError;
+use vulkano::command_buffer::CommandBufferExecError;
+use vulkano::image::AllocateImageError;
+use vulkano::memory::allocator::MemoryAllocatorError;
+use vulkano::pipeline::layout::IntoPipelineLayoutCreateInfoError;
+use vulkano::sync::HostAccessError;
+use vulkano::{LoadingError, Validated, ValidationError, VulkanError};
+
+use crate::device_selection::SelectionError;
+
+/// Errors that can occur while setting up or driving the renderer.
+#[derive(Debug)]
+pub enum RendererError {
+    /// The Vulkan loader library could not be found or opened.
+    NoVulkanLibrary(LoadingError),
+    /// The loader was found but refused to create an instance (for example no ICD installed).
+    NoInstance(Validated<VulkanError>),
+    /// No physical device satisfied the renderer's requirements.
+    NoSuitableDevice,
+    /// The device asked for with `--gpu`/`--gpu-name` doesn't exist or can't be used, or only a
+    /// software renderer is left and it wasn't allowed.
+    RequestedDevice(SelectionError),
+    /// Raw vertex data or a hand-written vertex layout doesn't add up.
+    InvalidVertexData(String),
+    /// A texture file couldn't be parsed or uses a format the crate can't load.
+    InvalidTexture(String),
+    /// A model file couldn't be read or uses features the crate can't draw.
+    InvalidModel(String),
+    /// Precompiled shader code isn't SPIR-V the device can use. Holds the reason.
+    InvalidShader(String),
+    /// A uniform struct isn't laid out the way the shader's `std140` block reads it. Holds the
+    /// reason.
+    UniformLayout(String),
+    /// An upload doesn't fit into the staging ring. Holds the reason.
+    StagingRingFull(String),
+    /// Another window can't be drawn into alongside the existing ones. Holds the reason.
+    IncompatibleWindow(String),
+    /// A framebuffer's attachments don't match its render pass. Holds the reason.
+    InvalidAttachments(String),
+    /// A frame's passes can't be put in an order that works. Holds the reason.
+    FrameGraph(String),
+    /// A screenshot couldn't be taken or written. Holds the reason.
+    Screenshot(String),
+    /// The device can't run the scene asked for. Holds the reason.
+    UnsupportedScene(String),
+    /// Any other error reported by vulkano while creating or using Vulkan objects.
+    Vulkan(Box<dyn Error + Send + Sync>),
+}
+
+impl RendererError {
+    /// Returns `true` if the error means Vulkan simply isn't usable on this machine, as opposed
+    /// to something going wrong with a working Vulkan setup.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self,
+            Self::NoVulkanLibrary(_) | Self::NoInstance(_) | Self::NoSuitableDevice
+        )
+    }
+
+    /// Returns `true` if the device was lost, after which nothing created from it works and
+    /// the only way on is a new device.
+    pub fn is_device_lost(&self) -> bool {
+        let Self::Vulkan(err) = self else {
+            return false;
+        };
+        matches!(err.downcast_ref(), Some(VulkanError::DeviceLost))
+            || matches!(
+                err.downcast_ref(),
+                Some(Validated::Error(VulkanError::DeviceLost))
+            )
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoVulkanLibrary(err) => write!(
+                f,
+                "no local Vulkan library/DLL found: {err}. Install the Vulkan runtime, which \
+                 comes with your GPU's driver or the Vulkan SDK, and check that the driver's ICD \
+                 is registered with the loader (`vulkaninfo` lists what it finds)"
+            ),
+            Self::NoInstance(err) => write!(f, "failed to create a Vulkan instance: {err}"),
+            Self::NoSuitableDevice => write!(f, "no suitable physical device could be found"),
+            Self::RequestedDevice(err) => write!(f, "{err}"),
+            Self::InvalidVertexData(msg) => write!(f, "invalid vertex data: {msg}"),
+            Self::InvalidTexture(msg) => write!(f, "invalid texture: {msg}"),
+            Self::InvalidModel(msg) => write!(f, "invalid model: {msg}"),
+            Self::InvalidShader(msg) => write!(f, "invalid shader: {msg}"),
+            Self::UniformLayout(msg) => write!(f, "uniform layout doesn't match std140: {msg}"),
+            Self::StagingRingFull(msg) => write!(f, "the staging ring is full: {msg}"),
+            Self::IncompatibleWindow(msg) => write!(f, "can't render to the window: {msg}"),
+            Self::InvalidAttachments(msg) => write!(f, "invalid framebuffer attachments: {msg}"),
+            Self::FrameGraph(msg) => write!(f, "invalid frame graph: {msg}"),
+            Self::Screenshot(msg) => write!(f, "can't save a screenshot: {msg}"),
+            Self::UnsupportedScene(msg) => write!(f, "can't draw the scene: {msg}"),
+            Self::Vulkan(err) => write!(f, "vulkan error: {err}"),
+        }
+    }
+}
+
+impl Error for RendererError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::NoVulkanLibrary(err) => Some(err),
+            Self::NoInstance(err) => Some(err),
+            Self::NoSuitableDevice
+            | Self::InvalidVertexData(_)
+            | Self::InvalidTexture(_)
+            | Self::InvalidModel(_)
+            | Self::InvalidShader(_)
+            | Self::UniformLayout(_)
+            | Self::StagingRingFull(_)
+            | Self::IncompatibleWindow(_)
+            | Self::InvalidAttachments(_)
+            | Self::FrameGraph(_)
+            | Self::Screenshot(_)
+            | Self::UnsupportedScene(_) => None,
+            Self::RequestedDevice(err) => Some(err),
+            Self::Vulkan(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+impl From<SelectionError> for RendererError {
+    fn from(err: SelectionError) -> Self {
+        match err {
+            SelectionError::NoSuitableDevice => Self::NoSuitableDevice,
+            err => Self::RequestedDevice(err),
+        }
+    }
+}
+
+// Most vulkano calls return their own error type. Funnel them all into `RendererError::Vulkan`
+// so `?` works everywhere in the renderer.
+macro_rules! impl_from_vulkano_error {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl From<$ty> for RendererError {
+                fn from(err: $ty) -> Self {
+                    Self::Vulkan(Box::new(err))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_vulkano_error!(
+    Validated<VulkanError>,
+    Box<ValidationError>,
+    Validated<AllocateBufferError>,
+    Validated<AllocateImageError>,
+    CommandBufferExecError,
+    IntoPipelineLayoutCreateInfoError,
+    HostAccessError,
+    MemoryAllocatorError,
+    VulkanError,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_loss_is_recognized_however_it_was_wrapped() {
+        assert!(RendererError::from(VulkanError::DeviceLost).is_device_lost());
+        assert!(RendererError::from(Validated::Error(VulkanError::DeviceLost)).is_device_lost());
+        assert!(!RendererError::from(VulkanError::OutOfDate).is_device_lost());
+        assert!(!RendererError::NoSuitableDevice.is_device_lost());
+    }
+}
@@ -0,0 +1,110 @@
+//! Exclusive fullscreen on Windows (`VK_EXT_full_screen_exclusive`), behind
+//! `--exclusive-fullscreen`.
+//!
+//! A borderless fullscreen window is still composited by DWM, which can cost a frame of latency.
+//! With application-controlled exclusivity, the swapchain takes its monitor over while the window
+//! is fullscreen and frames are flipped straight to the display. Windows can take exclusivity
+//! away again at any time, e.g. on alt-tab, after which the swapchain is recreated without it.
+
+use std::ffi::c_void;
+
+use vulkano::swapchain::{FullScreenExclusive, Swapchain, SwapchainCreateInfo, Win32Monitor};
+use winit::platform::windows::MonitorHandleExtWindows;
+use winit::window::Window;
+
+use crate::context::VulkanContext;
+use crate::options::Options;
+
+/// Whether a window wants exclusivity, and whether it lost it.
+pub struct ExclusiveFullscreen {
+    /// The window is fullscreen, so its swapchain should hold exclusivity.
+    wanted: bool,
+    /// Acquiring failed or exclusivity was taken away, so the swapchain does without until the
+    /// window next goes fullscreen.
+    lost: bool,
+}
+
+impl ExclusiveFullscreen {
+    /// `None` unless `--exclusive-fullscreen` was given and the device has the extension.
+    pub fn new(ctx: &VulkanContext, options: &Options) -> Option<Self> {
+        if !options.exclusive_fullscreen {
+            return None;
+        }
+        if !ctx.device.enabled_extensions().ext_full_screen_exclusive {
+            log::warn!("Exclusive fullscreen isn't supported, so fullscreen stays borderless");
+            return None;
+        }
+        Some(Self {
+            wanted: false,
+            lost: false,
+        })
+    }
+
+    /// Sets `create_info` up for exclusivity on the monitor `window` is on, or to do without it
+    /// once it has been lost.
+    pub fn configure(&self, create_info: &mut SwapchainCreateInfo, window: &Window) {
+        match window.current_monitor().filter(|_| !self.lost) {
+            Some(monitor) => {
+                create_info.full_screen_exclusive = FullScreenExclusive::ApplicationControlled;
+                // SAFETY: winit got the handle from Win32, and it stays valid for as long as the
+                // monitor is connected.
+                create_info.win32_monitor =
+                    Some(unsafe { Win32Monitor::new(monitor.hmonitor() as *const c_void) });
+            }
+            None => {
+                create_info.full_screen_exclusive = FullScreenExclusive::Disallowed;
+                create_info.win32_monitor = None;
+            }
+        }
+    }
+
+    /// To be called as the window enters or leaves fullscreen. Exclusivity is given up right
+    /// away when leaving, while the window is still fullscreen, and taken by [`Self::update`]
+    /// once it is.
+    ///
+    /// Returns `true` if the swapchain has to be recreated, to try again after losing it.
+    pub fn set_fullscreen(&mut self, swapchain: &Swapchain, fullscreen: bool) -> bool {
+        if !fullscreen && swapchain.is_full_screen_exclusive() {
+            match swapchain.release_full_screen_exclusive_mode() {
+                Ok(()) => log::info!("Released exclusive fullscreen"),
+                Err(err) => log::warn!("Failed to release exclusive fullscreen: {err}"),
+            }
+        }
+        self.wanted = fullscreen;
+        if fullscreen && self.lost {
+            self.lost = false;
+            return true;
+        }
+        false
+    }
+
+    /// Acquires exclusivity if the window wants it but the swapchain doesn't hold it yet.
+    ///
+    /// Returns `false` if that failed and the swapchain has to be recreated without it.
+    pub fn update(&mut self, swapchain: &Swapchain) -> bool {
+        if !self.wanted
+            || swapchain.full_screen_exclusive() != FullScreenExclusive::ApplicationControlled
+            || swapchain.is_full_screen_exclusive()
+        {
+            return true;
+        }
+        match swapchain.acquire_full_screen_exclusive_mode() {
+            Ok(()) => {
+                log::info!("Acquired exclusive fullscreen");
+                true
+            }
+            Err(err) => {
+                log::warn!("Failed to acquire exclusive fullscreen, staying borderless: {err}");
+                self.lost = true;
+                false
+            }
+        }
+    }
+
+    /// To be called when presenting returns `FullScreenExclusiveModeLost`. The swapchain then
+    /// has to be recreated, and does without exclusivity.
+    pub fn mode_lost(&mut self) {
+        log::warn!("Lost exclusive fullscreen, continuing borderless");
+        self.lost = true;
+    }
+}
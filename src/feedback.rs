@@ -0,0 +1,231 @@
+//! Offscreen targets that sample what they held the frame before, for trails, accumulation and
+//! other effects that build up over time.
+//!
+//! A [`FeedbackTarget`] has an image for each frame slot. A view's frames take its slots in
+//! turn, so with [`FRAMES_IN_FLIGHT`] at two each view ping-pongs between a pair of images: every
+//! frame renders into one while sampling the other, which the previous frame rendered, and the
+//! two swap roles the next frame. Nothing is loaded when the pass begins, so whatever of the
+//! previous contents should carry over is sampled from the other image by the shader.
+//!
+//! The pass leaves its image ready to be sampled, both by the view's own draws later in the frame
+//! and by the next frame's pass. Any other transitions are inserted by the command buffer.
+
+use std::sync::Arc;
+
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, ClearColorImageInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+    SubpassBeginInfo, SubpassContents,
+};
+use vulkano::descriptor_set::layout::DescriptorSetLayout;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::{ClearColorValue, Format};
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageLayout, ImageType, ImageUsage};
+use vulkano::memory::allocator::AllocationCreateInfo;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::scene::{FrameData, FRAMES_IN_FLIGHT, FRAME_SLOTS};
+
+/// Format of feedback images. Floating point, so contents fading a little every frame fade all
+/// the way out instead of getting stuck at the last few 8-bit steps.
+pub const FEEDBACK_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// A colour image per frame slot, each rendered into while the previous frame's is sampled.
+pub struct FeedbackTarget {
+    extent: [u32; 2],
+    render_pass: Arc<RenderPass>,
+    /// One per frame slot, each rendering into the image of the same index.
+    framebuffers: Vec<Arc<Framebuffer>>,
+    views: Vec<Arc<ImageView>>,
+}
+
+impl FeedbackTarget {
+    /// Creates the images, cleared to transparent black so the first frame has something to
+    /// sample, and names them after `label`.
+    pub fn new(ctx: &VulkanContext, extent: [u32; 2], label: &str) -> Result<Self, RendererError> {
+        let render_pass = vulkano::single_pass_renderpass!(
+            ctx.device.clone(),
+            attachments: {
+                color: {
+                    format: FEEDBACK_FORMAT,
+                    samples: 1,
+                    // Every pixel is written, from the other image and whatever is drawn on top.
+                    load_op: DontCare,
+                    store_op: Store,
+                    initial_layout: ImageLayout::Undefined,
+                    final_layout: ImageLayout::ShaderReadOnlyOptimal,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        )?;
+
+        let images = (0..FRAME_SLOTS)
+            .map(|slot| {
+                let image = Image::new(
+                    ctx.memory_allocator.clone(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        format: FEEDBACK_FORMAT,
+                        extent: [extent[0], extent[1], 1],
+                        usage: ImageUsage::COLOR_ATTACHMENT
+                            | ImageUsage::SAMPLED
+                            | ImageUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )?;
+                ctx.memory_tracker
+                    .track_image(MemoryCategory::RenderTarget, &image);
+                ctx.name_object(&image, &format!("{label} {slot}"));
+                Ok(image)
+            })
+            .collect::<Result<Vec<_>, RendererError>>()?;
+        ctx.submit_and_wait(|builder| {
+            for image in &images {
+                builder.clear_color_image(ClearColorImageInfo {
+                    clear_value: ClearColorValue::Float([0.0; 4]),
+                    ..ClearColorImageInfo::image(image.clone())
+                })?;
+            }
+            Ok(())
+        })?;
+
+        let views = images
+            .into_iter()
+            .map(ImageView::new_default)
+            .collect::<Result<Vec<_>, _>>()?;
+        let framebuffers = views
+            .iter()
+            .map(|view| {
+                Framebuffer::new(
+                    render_pass.clone(),
+                    FramebufferCreateInfo {
+                        attachments: vec![view.clone()],
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            extent,
+            render_pass,
+            framebuffers,
+            views,
+        })
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// The subpass pipelines drawing into the target must be built for. It has no depth
+    /// attachment.
+    pub fn subpass(&self) -> Subpass {
+        Subpass::from(self.render_pass.clone(), 0).unwrap()
+    }
+
+    /// The image `frame` renders into.
+    pub fn current(&self, frame: &FrameData) -> Arc<ImageView> {
+        self.views[frame.frame_in_flight].clone()
+    }
+
+    /// The image the previous frame of `frame`'s view rendered into, for `frame` to sample.
+    pub fn previous(&self, frame: &FrameData) -> Arc<ImageView> {
+        self.views[frame.previous_frame_in_flight()].clone()
+    }
+
+    /// A descriptor set per frame slot, binding that slot's [`current`](Self::current) image
+    /// with `sampler` at binding 0 of `layout`, to show what was rendered.
+    pub fn current_sets(
+        &self,
+        ctx: &VulkanContext,
+        layout: &Arc<DescriptorSetLayout>,
+        sampler: &Arc<Sampler>,
+    ) -> Result<Vec<Arc<PersistentDescriptorSet>>, RendererError> {
+        self.sets(ctx, layout, sampler, |frame| self.current(frame))
+    }
+
+    /// Like [`current_sets`](Self::current_sets), but binding each slot's
+    /// [`previous`](Self::previous) image, for the pass rendering into the current one.
+    pub fn previous_sets(
+        &self,
+        ctx: &VulkanContext,
+        layout: &Arc<DescriptorSetLayout>,
+        sampler: &Arc<Sampler>,
+    ) -> Result<Vec<Arc<PersistentDescriptorSet>>, RendererError> {
+        self.sets(ctx, layout, sampler, |frame| self.previous(frame))
+    }
+
+    fn sets(
+        &self,
+        ctx: &VulkanContext,
+        layout: &Arc<DescriptorSetLayout>,
+        sampler: &Arc<Sampler>,
+        view: impl Fn(&FrameData) -> Arc<ImageView>,
+    ) -> Result<Vec<Arc<PersistentDescriptorSet>>, RendererError> {
+        (0..FRAME_SLOTS)
+            .map(|frame_in_flight| {
+                let frame = FrameData {
+                    frame_in_flight,
+                    ..Default::default()
+                };
+                Ok(PersistentDescriptorSet::new(
+                    ctx.descriptor_set_allocator.as_ref(),
+                    layout.clone(),
+                    [WriteDescriptorSet::image_view_sampler(
+                        0,
+                        view(&frame),
+                        sampler.clone(),
+                    )],
+                    [],
+                )?)
+            })
+            .collect()
+    }
+
+    /// Begins the pass rendering into `frame`'s image, with the viewport and scissor covering
+    /// all of it. The caller draws and ends the pass.
+    pub fn begin(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: self.extent.map(|length| length as f32),
+            depth_range: 0.0..=1.0,
+        };
+        let scissor = Scissor {
+            offset: [0, 0],
+            extent: self.extent,
+        };
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![None],
+                    ..RenderPassBeginInfo::framebuffer(
+                        self.framebuffers[frame.frame_in_flight].clone(),
+                    )
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(0, [viewport].into_iter().collect())?
+            .set_scissor(0, [scissor].into_iter().collect())?;
+        Ok(())
+    }
+}
+
+/// Each view's frames alternate between its slots, so a frame's previous image is always one of
+/// its own view's and never the one it renders into.
+const _: () = assert!(FRAMES_IN_FLIGHT >= 2);
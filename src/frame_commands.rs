@@ -0,0 +1,201 @@
+//! Command buffers for the frames in flight, allocated from a command pool per frame-in-flight
+//! slot.
+//!
+//! One allocator shared by every frame fills up its pool, then has to fall back on another one
+//! whenever command buffers of earlier frames are still pending. Giving each slot its own
+//! [`StandardCommandBufferAllocator`] and resetting it once the slot's fence has signalled
+//! instead recycles the same few command buffers every frame. That only works if no command
+//! buffer outlives the frame it was recorded for, which [`FrameCommandPools::reset`] checks.
+//!
+//! There are [`FRAMES_IN_FLIGHT`] slots whatever the swapchain's image count: a window with
+//! more images than that still only has that many frames recorded ahead, and one with fewer
+//! leaves the spare slots' pools idle. A slot's pool holds the primary command buffer its frame
+//! records and the secondaries drawn over the scene. Resetting keeps the pool's memory, so a
+//! steady frame allocates nothing new after the first round of slots.
+
+use vulkano::command_buffer::allocator::{
+    StandardCommandBufferAllocator, StandardCommandBufferAllocatorCreateInfo,
+};
+use vulkano::command_buffer::pool::CommandPoolResetFlags;
+use vulkano::command_buffer::CommandBufferLevel;
+
+use crate::context::VulkanContext;
+use crate::scene::FRAMES_IN_FLIGHT;
+
+/// How many primary command buffers each slot's pool holds. A frame records one, so this leaves
+/// room for passes that want their own before a frame would need a second pool.
+pub const PRIMARY_BUFFERS_PER_SLOT: usize = 4;
+
+/// How many secondary command buffers each slot's pool holds. A frame records up to three, the
+/// minimap's clear and overlay and the console, and the scene's own come from the
+/// [`DrawCache`](crate::draw_cache::DrawCache) as they outlive the frame.
+pub const SECONDARY_BUFFERS_PER_SLOT: usize = 4;
+
+/// The per-slot command buffer allocators of one window.
+///
+/// vulkano keeps an allocator's pools per thread, so all frames have to be recorded on the same
+/// thread for its pool to be the one that gets reset.
+pub struct FrameCommandPools {
+    queue_family_index: u32,
+    allocators: Vec<StandardCommandBufferAllocator>,
+    bookkeeping: Bookkeeping,
+}
+
+impl FrameCommandPools {
+    pub fn new(ctx: &VulkanContext) -> Self {
+        let allocators = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                StandardCommandBufferAllocator::new(
+                    ctx.device.clone(),
+                    StandardCommandBufferAllocatorCreateInfo {
+                        primary_buffer_count: PRIMARY_BUFFERS_PER_SLOT,
+                        secondary_buffer_count: SECONDARY_BUFFERS_PER_SLOT,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        Self {
+            queue_family_index: ctx.queue.queue_family_index(),
+            allocators,
+            bookkeeping: Bookkeeping::new([PRIMARY_BUFFERS_PER_SLOT, SECONDARY_BUFFERS_PER_SLOT]),
+        }
+    }
+
+    /// The allocator to record one primary command buffer for `slot` with, which is counted in
+    /// the context's command pool stats.
+    pub fn allocator(
+        &mut self,
+        ctx: &VulkanContext,
+        slot: usize,
+    ) -> &StandardCommandBufferAllocator {
+        self.allocator_for(ctx, slot, CommandBufferLevel::Primary)
+    }
+
+    /// Like [`allocator`](Self::allocator), but for one secondary command buffer, which must
+    /// not outlive the frame either.
+    pub fn secondary_allocator(
+        &mut self,
+        ctx: &VulkanContext,
+        slot: usize,
+    ) -> &StandardCommandBufferAllocator {
+        self.allocator_for(ctx, slot, CommandBufferLevel::Secondary)
+    }
+
+    fn allocator_for(
+        &mut self,
+        ctx: &VulkanContext,
+        slot: usize,
+        level: CommandBufferLevel,
+    ) -> &StandardCommandBufferAllocator {
+        let new_pool = self.bookkeeping.allocate(slot, level);
+        ctx.memory_tracker.count_command_pools(|stats| {
+            stats.command_buffers += 1;
+            stats.pools += new_pool as u64;
+        });
+        &self.allocators[slot]
+    }
+
+    /// Recycles every command buffer allocated for `slot`. Call once the fence of the slot's last
+    /// frame has signalled and that frame's future has been cleaned up.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if a command buffer of the slot is still alive. Release builds count it
+    /// in the stats and carry on with a fresh pool when the old one runs out.
+    pub fn reset(&mut self, ctx: &VulkanContext, slot: usize) {
+        let result = self.allocators[slot]
+            .try_reset_pool(self.queue_family_index, CommandPoolResetFlags::empty());
+        let reset = result.is_ok();
+        if reset {
+            self.bookkeeping.reset(slot);
+        }
+        ctx.memory_tracker.count_command_pools(|stats| {
+            stats.resets += reset as u64;
+            stats.resets_in_use += !reset as u64;
+        });
+        debug_assert!(
+            reset,
+            "a command buffer outlived frame-in-flight slot {slot}: {:?}",
+            result.unwrap_err()
+        );
+    }
+}
+
+/// Works out from the allocations and resets when vulkano has to take another pool for a slot:
+/// on a slot's first allocation, and whenever its pool has no command buffers of the level
+/// asked for left.
+struct Bookkeeping {
+    /// Primary and secondary command buffers each pool holds.
+    capacity: [usize; 2],
+    /// Primary and secondary command buffers allocated from each slot's current pool since it
+    /// was last reset, `None` before the slot's first.
+    in_pool: Vec<Option<[usize; 2]>>,
+}
+
+impl Bookkeeping {
+    fn new(capacity: [usize; 2]) -> Self {
+        Self {
+            capacity,
+            in_pool: vec![None; FRAMES_IN_FLIGHT],
+        }
+    }
+
+    /// Counts an allocation of `level` for `slot` and returns whether it needs another pool.
+    fn allocate(&mut self, slot: usize, level: CommandBufferLevel) -> bool {
+        let level = match level {
+            CommandBufferLevel::Primary => 0,
+            CommandBufferLevel::Secondary => 1,
+        };
+        let new_pool =
+            self.in_pool[slot].is_none_or(|counts| counts[level] == self.capacity[level]);
+        let counts = match &mut self.in_pool[slot] {
+            Some(counts) if !new_pool => counts,
+            in_pool => in_pool.insert([0; 2]),
+        };
+        counts[level] += 1;
+        new_pool
+    }
+
+    fn reset(&mut self, slot: usize) {
+        if let Some(counts) = &mut self.in_pool[slot] {
+            *counts = [0; 2];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_state_frames_reuse_their_slots_pool() {
+        let mut bookkeeping = Bookkeeping::new([2, 2]);
+        let new_pools: Vec<_> = (0..10)
+            .map(|frame| {
+                let slot = frame % FRAMES_IN_FLIGHT;
+                bookkeeping.reset(slot);
+                let primary = bookkeeping.allocate(slot, CommandBufferLevel::Primary);
+                let secondary = bookkeeping.allocate(slot, CommandBufferLevel::Secondary);
+                primary || secondary
+            })
+            .collect();
+        let first_frames = FRAMES_IN_FLIGHT;
+        assert!(new_pools[..first_frames].iter().all(|&new_pool| new_pool));
+        assert!(new_pools[first_frames..].iter().all(|&new_pool| !new_pool));
+    }
+
+    #[test]
+    fn a_full_pool_needs_another() {
+        let mut bookkeeping = Bookkeeping::new([2, 1]);
+        assert!(bookkeeping.allocate(0, CommandBufferLevel::Primary));
+        assert!(!bookkeeping.allocate(0, CommandBufferLevel::Primary));
+        assert!(bookkeeping.allocate(0, CommandBufferLevel::Primary));
+        bookkeeping.reset(0);
+        assert!(!bookkeeping.allocate(0, CommandBufferLevel::Primary));
+        // Secondaries run out separately, and the new pool has room for both again.
+        assert!(!bookkeeping.allocate(0, CommandBufferLevel::Secondary));
+        assert!(bookkeeping.allocate(0, CommandBufferLevel::Secondary));
+        assert!(!bookkeeping.allocate(0, CommandBufferLevel::Primary));
+    }
+}
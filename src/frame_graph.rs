@@ -0,0 +1,472 @@
+//! Ordering a frame's passes by the images they use, and pooling the images only a frame needs.
+//!
+//! Each [`Pass`] declares the images it reads and writes and how it accesses them. The
+//! [`FrameGraph`] runs every pass that writes an image before any pass that reads it, and
+//! otherwise keeps the order the passes were added in. Images are either imported, like the
+//! swapchain image, or transient: described by format and size, and only given memory from a
+//! [`TransientPool`] for the stretch of the frame between their first and last use. Transients
+//! whose stretches don't overlap share one image.
+//!
+//! Layout transitions and barriers between passes are left to the auto command buffer, which
+//! inserts them from the commands actually recorded. A disabled pass records nothing, so it
+//! leaves no transitions behind either.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer};
+use vulkano::format::Format;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::AllocationCreateInfo;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+
+type Builder = AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>;
+type RecordFn<'a> = Box<dyn FnOnce(&mut Builder, &PassImages) -> Result<(), RendererError> + 'a>;
+
+/// Refers to an image of one [`FrameGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+/// How a pass uses an image, which decides the layout it needs the image in and, for transient
+/// images, the usage they are created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageAccess {
+    ColorAttachment,
+    Sampled,
+    TransferSrc,
+    TransferDst,
+    /// Read or written from a compute shader, in the `General` layout.
+    Storage,
+}
+
+impl ImageAccess {
+    fn usage(self) -> ImageUsage {
+        match self {
+            ImageAccess::ColorAttachment => ImageUsage::COLOR_ATTACHMENT,
+            ImageAccess::Sampled => ImageUsage::SAMPLED,
+            ImageAccess::TransferSrc => ImageUsage::TRANSFER_SRC,
+            ImageAccess::TransferDst => ImageUsage::TRANSFER_DST,
+            ImageAccess::Storage => ImageUsage::STORAGE,
+        }
+    }
+}
+
+/// What a transient image has to be.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TransientDesc {
+    pub format: Format,
+    pub extent: [u32; 2],
+}
+
+/// Transient images can stand in for each other if they match in this.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct TransientKey {
+    desc: TransientDesc,
+    usage: ImageUsage,
+}
+
+enum GraphImage {
+    Imported(Arc<Image>),
+    Transient(TransientDesc),
+}
+
+/// One step of a frame, recorded into the frame's command buffer when the graph gets to it.
+pub struct Pass<'a> {
+    name: &'static str,
+    reads: Vec<(ImageId, ImageAccess)>,
+    writes: Vec<(ImageId, ImageAccess)>,
+    enabled: bool,
+    record: RecordFn<'a>,
+}
+
+impl<'a> Pass<'a> {
+    /// A pass that records with `record`, given the images of the graph.
+    pub fn new(
+        name: &'static str,
+        record: impl FnOnce(&mut Builder, &PassImages) -> Result<(), RendererError> + 'a,
+    ) -> Self {
+        Self {
+            name,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            enabled: true,
+            record: Box::new(record),
+        }
+    }
+
+    pub fn reads(mut self, image: ImageId, access: ImageAccess) -> Self {
+        self.reads.push((image, access));
+        self
+    }
+
+    pub fn writes(mut self, image: ImageId, access: ImageAccess) -> Self {
+        self.writes.push((image, access));
+        self
+    }
+
+    /// Skips the pass when `enabled` is false, as if it had never been added.
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    fn accesses(&self) -> impl Iterator<Item = &(ImageId, ImageAccess)> {
+        self.reads.iter().chain(&self.writes)
+    }
+}
+
+/// The images a pass is recorded with.
+pub struct PassImages {
+    images: Vec<Option<Arc<Image>>>,
+}
+
+impl PassImages {
+    /// The image behind `id`. Panics if no pass that runs uses it, as only those get one.
+    pub fn image(&self, id: ImageId) -> &Arc<Image> {
+        self.images[id.0]
+            .as_ref()
+            .expect("the image is used by a pass that runs")
+    }
+}
+
+/// The passes of one frame and the images they use.
+#[derive(Default)]
+pub struct FrameGraph<'a> {
+    images: Vec<(&'static str, GraphImage)>,
+    passes: Vec<Pass<'a>>,
+}
+
+/// The order the enabled passes run in, and which pooled image each transient gets.
+#[derive(Debug, PartialEq, Eq)]
+struct Schedule {
+    passes: Vec<usize>,
+    /// By image; `None` for imported images and transients no pass that runs uses.
+    slots: Vec<Option<(TransientKey, usize)>>,
+}
+
+impl<'a> FrameGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an image that lives outside the graph, named `name` in errors.
+    pub fn import(&mut self, name: &'static str, image: Arc<Image>) -> ImageId {
+        self.images.push((name, GraphImage::Imported(image)));
+        ImageId(self.images.len() - 1)
+    }
+
+    /// Adds an image that only lives for as long as the passes using it. Its contents are
+    /// undefined until a pass writes it.
+    pub fn transient(&mut self, name: &'static str, desc: TransientDesc) -> ImageId {
+        self.images.push((name, GraphImage::Transient(desc)));
+        ImageId(self.images.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, pass: Pass<'a>) {
+        self.passes.push(pass);
+    }
+
+    /// Records the enabled passes into `builder` in order, with transient images from `pool`.
+    pub fn execute(
+        self,
+        ctx: &VulkanContext,
+        pool: &mut TransientPool,
+        builder: &mut Builder,
+    ) -> Result<(), RendererError> {
+        let schedule = self.schedule()?;
+        let names: Vec<_> = self.images.iter().map(|(name, _)| *name).collect();
+        let transients = pool.assign(ctx, &schedule.slots, &names)?;
+        let images = PassImages {
+            images: self
+                .images
+                .into_iter()
+                .zip(transients)
+                .map(|((_, image), transient)| match image {
+                    GraphImage::Imported(image) => Some(image),
+                    GraphImage::Transient(_) => transient,
+                })
+                .collect(),
+        };
+
+        let mut passes: Vec<_> = self.passes.into_iter().map(Some).collect();
+        for index in schedule.passes {
+            let pass = passes[index].take().unwrap();
+            (pass.record)(builder, &images)?;
+        }
+        Ok(())
+    }
+
+    fn schedule(&self) -> Result<Schedule, RendererError> {
+        let enabled: Vec<usize> = (0..self.passes.len())
+            .filter(|&index| self.passes[index].enabled)
+            .collect();
+        let mut writers = vec![Vec::new(); self.images.len()];
+        for &index in &enabled {
+            for (image, _) in &self.passes[index].writes {
+                writers[image.0].push(index);
+            }
+        }
+
+        // Every writer of an image runs before its readers; writers of the same image run in the
+        // order they were added.
+        let mut dependencies: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); self.passes.len()];
+        for &index in &enabled {
+            let pass = &self.passes[index];
+            for (image, _) in &pass.reads {
+                let image_writers = &writers[image.0];
+                if image_writers.is_empty() {
+                    if let (name, GraphImage::Transient(_)) = &self.images[image.0] {
+                        return Err(RendererError::FrameGraph(format!(
+                            "`{}` reads `{name}`, which no pass writes",
+                            pass.name
+                        )));
+                    }
+                }
+                dependencies[index].extend(image_writers.iter().filter(|&&w| w != index));
+            }
+            for (image, _) in &pass.writes {
+                dependencies[index].extend(writers[image.0].iter().filter(|&&w| w < index));
+            }
+        }
+
+        // Kahn's algorithm, taking the earliest added of the passes that are ready each time.
+        let mut order = Vec::with_capacity(enabled.len());
+        let mut ready: BTreeSet<usize> = enabled
+            .iter()
+            .copied()
+            .filter(|&index| dependencies[index].is_empty())
+            .collect();
+        while let Some(index) = ready.pop_first() {
+            order.push(index);
+            for &other in &enabled {
+                if dependencies[other].remove(&index) && dependencies[other].is_empty() {
+                    ready.insert(other);
+                }
+            }
+        }
+        if order.len() != enabled.len() {
+            let stuck: Vec<_> = enabled
+                .iter()
+                .filter(|index| !order.contains(index))
+                .map(|&index| self.passes[index].name)
+                .collect();
+            return Err(RendererError::FrameGraph(format!(
+                "the passes {stuck:?} depend on each other"
+            )));
+        }
+
+        // The span of the order each transient is used in, and the usage it needs there.
+        let mut uses: Vec<Option<(usize, usize, ImageUsage)>> = vec![None; self.images.len()];
+        for (position, &index) in order.iter().enumerate() {
+            for &(image, access) in self.passes[index].accesses() {
+                let GraphImage::Transient(_) = self.images[image.0].1 else {
+                    continue;
+                };
+                let span = uses[image.0].get_or_insert((position, position, ImageUsage::empty()));
+                span.1 = position;
+                span.2 |= access.usage();
+            }
+        }
+        let mut transients: Vec<_> = uses
+            .iter()
+            .enumerate()
+            .filter_map(|(image, span)| {
+                span.map(|(first, last, usage)| (image, first, last, usage))
+            })
+            .collect();
+        transients.sort_by_key(|&(_, first, _, _)| first);
+
+        // Hand each transient the first pooled image of its kind that is free by then.
+        let mut slots = vec![None; self.images.len()];
+        let mut busy_until: HashMap<TransientKey, Vec<usize>> = HashMap::new();
+        for (image, first, last, usage) in transients {
+            let GraphImage::Transient(desc) = self.images[image].1 else {
+                unreachable!();
+            };
+            let key = TransientKey { desc, usage };
+            let pooled = busy_until.entry(key).or_default();
+            let slot = match pooled.iter().position(|&until| until < first) {
+                Some(slot) => slot,
+                None => {
+                    pooled.push(0);
+                    pooled.len() - 1
+                }
+            };
+            pooled[slot] = last;
+            slots[image] = Some((key, slot));
+        }
+
+        Ok(Schedule {
+            passes: order,
+            slots,
+        })
+    }
+}
+
+/// The images transients are given, kept from one frame to the next.
+///
+/// Frames are submitted to a single queue in order, so frames in flight can share one the same
+/// way they share the depth buffer.
+#[derive(Default)]
+pub struct TransientPool {
+    images: HashMap<TransientKey, Vec<Arc<Image>>>,
+}
+
+impl TransientPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The pooled image for each slot of `slots`, creating those the pool doesn't have yet and
+    /// dropping those no slot needs any more. `names` name new images in captures.
+    fn assign(
+        &mut self,
+        ctx: &VulkanContext,
+        slots: &[Option<(TransientKey, usize)>],
+        names: &[&str],
+    ) -> Result<Vec<Option<Arc<Image>>>, RendererError> {
+        let mut needed: HashMap<TransientKey, usize> = HashMap::new();
+        for &(key, slot) in slots.iter().flatten() {
+            let count = needed.entry(key).or_default();
+            *count = (*count).max(slot + 1);
+        }
+        self.images.retain(|key, _| needed.contains_key(key));
+        for (key, images) in &mut self.images {
+            images.truncate(needed[key]);
+        }
+
+        slots
+            .iter()
+            .zip(names)
+            .map(|(slot, name)| {
+                let Some((key, slot)) = *slot else {
+                    return Ok(None);
+                };
+                let images = self.images.entry(key).or_default();
+                while images.len() <= slot {
+                    let image = create_transient(ctx, key)?;
+                    ctx.name_object(&image, &format!("transient {name}"));
+                    images.push(image);
+                }
+                Ok(Some(images[slot].clone()))
+            })
+            .collect()
+    }
+}
+
+fn create_transient(ctx: &VulkanContext, key: TransientKey) -> Result<Arc<Image>, RendererError> {
+    let image = Image::new(
+        ctx.memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: key.desc.format,
+            extent: [key.desc.extent[0], key.desc.extent[1], 1],
+            usage: key.usage,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )?;
+    ctx.memory_tracker
+        .track_image(MemoryCategory::RenderTarget, &image);
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESC: TransientDesc = TransientDesc {
+        format: Format::R8G8B8A8_UNORM,
+        extent: [64, 64],
+    };
+
+    fn pass(name: &'static str) -> Pass<'static> {
+        Pass::new(name, |_, _| Ok(()))
+    }
+
+    fn pass_names(graph: &FrameGraph, schedule: &Schedule) -> Vec<&'static str> {
+        schedule
+            .passes
+            .iter()
+            .map(|&index| graph.passes[index].name)
+            .collect()
+    }
+
+    #[test]
+    fn writers_run_before_readers() {
+        use ImageAccess::*;
+        let mut graph = FrameGraph::new();
+        let scene = graph.transient("scene", DESC);
+        let blurred = graph.transient("blurred", DESC);
+        graph.add_pass(
+            pass("composite")
+                .reads(blurred, Sampled)
+                .reads(scene, Sampled),
+        );
+        graph.add_pass(
+            pass("blur")
+                .reads(scene, Sampled)
+                .writes(blurred, ColorAttachment),
+        );
+        graph.add_pass(pass("scene").writes(scene, ColorAttachment));
+        let schedule = graph.schedule().unwrap();
+        assert_eq!(
+            pass_names(&graph, &schedule),
+            ["scene", "blur", "composite"]
+        );
+    }
+
+    #[test]
+    fn disabled_passes_are_skipped() {
+        use ImageAccess::*;
+        let mut graph = FrameGraph::new();
+        let scene = graph.transient("scene", DESC);
+        let bloom = graph.transient("bloom", DESC);
+        graph.add_pass(pass("scene").writes(scene, ColorAttachment));
+        graph.add_pass(
+            pass("bloom")
+                .reads(scene, Sampled)
+                .writes(bloom, ColorAttachment)
+                .enabled(false),
+        );
+        graph.add_pass(pass("present").reads(scene, TransferSrc));
+        let schedule = graph.schedule().unwrap();
+        assert_eq!(pass_names(&graph, &schedule), ["scene", "present"]);
+        // Nothing that runs uses the bloom target, so it gets no memory.
+        assert_eq!(schedule.slots[bloom.0], None);
+
+        // Reading what only a disabled pass writes is a mistake.
+        graph.add_pass(pass("composite").reads(bloom, Sampled));
+        assert!(graph.schedule().is_err());
+    }
+
+    #[test]
+    fn transients_used_at_different_times_share_an_image() {
+        use ImageAccess::*;
+        let mut graph = FrameGraph::new();
+        let [a, b, c] = ["a", "b", "c"].map(|name| graph.transient(name, DESC));
+        graph.add_pass(pass("a").writes(a, ColorAttachment));
+        graph.add_pass(pass("b").reads(a, Sampled).writes(b, ColorAttachment));
+        graph.add_pass(pass("c").reads(b, Sampled).writes(c, ColorAttachment));
+        graph.add_pass(pass("present").reads(c, TransferSrc));
+        let schedule = graph.schedule().unwrap();
+        let slot = |image: ImageId| schedule.slots[image.0].map(|(_, slot)| slot);
+        // `b` is read while `c` is written, but `a` is done with by then.
+        assert_eq!(slot(a), Some(0));
+        assert_eq!(slot(b), Some(1));
+        assert_eq!(slot(c), Some(0));
+    }
+
+    #[test]
+    fn cycles_are_caught() {
+        use ImageAccess::*;
+        let mut graph = FrameGraph::new();
+        let [a, b] = ["a", "b"].map(|name| graph.transient(name, DESC));
+        graph.add_pass(pass("one").reads(a, Sampled).writes(b, ColorAttachment));
+        graph.add_pass(pass("two").reads(b, Sampled).writes(a, ColorAttachment));
+        assert!(graph.schedule().is_err());
+    }
+}
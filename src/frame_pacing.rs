@@ -0,0 +1,124 @@
+//! Present IDs and waits (`VK_KHR_present_id` and `VK_KHR_present_wait`), for measuring how long
+//! frames take to reach the screen and capping how many can be queued for it.
+//!
+//! Without the extensions, the frame fences are all the pacing there is: they bound the frames
+//! being rendered, but not the ones waiting to be displayed.
+
+use std::collections::VecDeque;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use vulkano::swapchain::{self, Swapchain};
+use vulkano::{Validated, VulkanError};
+
+use crate::error::RendererError;
+
+/// How long to block on a single present before giving up on it. Some compositors hold on to
+/// the presents of hidden windows indefinitely.
+const PRESENT_WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Presents that haven't been seen on screen are forgotten beyond this many.
+const MAX_PENDING_PRESENTS: usize = 8;
+
+/// Tags one window's presents with increasing IDs and waits for them.
+pub struct FramePacer {
+    /// The device supports present waits.
+    enabled: bool,
+    frame_latency: Option<u32>,
+    /// IDs only have to increase per swapchain, but never restarting them is simpler.
+    next_id: u64,
+    /// Presents not yet known to be on screen, oldest first, with when they were queued.
+    pending: VecDeque<(u64, Instant)>,
+    latency: Option<Duration>,
+    /// Every latency measured since the last [`take_latencies`](Self::take_latencies).
+    latencies: Vec<Duration>,
+}
+
+impl FramePacer {
+    /// Does nothing unless `enabled`. With a `frame_latency`, at most that many presents are
+    /// left queued when the next frame starts.
+    pub fn new(enabled: bool, frame_latency: Option<u32>) -> Self {
+        Self {
+            enabled,
+            frame_latency,
+            next_id: 1,
+            pending: VecDeque::new(),
+            latency: None,
+            latencies: Vec::new(),
+        }
+    }
+
+    /// How long the most recent present seen on screen took to get there, from being queued. It
+    /// is only checked for when a frame starts, so this errs on the long side.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// The latencies of the presents seen on screen since the last call, for collecting
+    /// statistics over a run.
+    pub fn take_latencies(&mut self) -> Vec<Duration> {
+        std::mem::take(&mut self.latencies)
+    }
+
+    /// The ID to tag the next present with, if presents are tagged at all.
+    pub fn next_present_id(&self) -> Option<NonZeroU64> {
+        self.enabled.then(|| NonZeroU64::new(self.next_id).unwrap())
+    }
+
+    /// Records that the present tagged with [`next_present_id`](Self::next_present_id) was
+    /// queued.
+    pub fn presented(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.pending.len() == MAX_PENDING_PRESENTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((self.next_id, Instant::now()));
+        self.next_id += 1;
+    }
+
+    /// Forgets the presents to a swapchain that was just replaced, which can't be waited for.
+    pub fn swapchain_recreated(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Checks which presents have reached the screen and, with a frame latency set, blocks until
+    /// few enough are left queued. Call before acquiring the next image.
+    pub fn wait(&mut self, swapchain: &Arc<Swapchain>) -> Result<(), RendererError> {
+        while let Some(&(id, queued)) = self.pending.front() {
+            let must_wait = self
+                .frame_latency
+                .is_some_and(|latency| self.pending.len() >= latency as usize);
+            let timeout = if must_wait {
+                PRESENT_WAIT_TIMEOUT
+            } else {
+                Duration::ZERO
+            };
+            match swapchain::wait_for_present(swapchain.clone(), id, Some(timeout))
+                .map_err(Validated::unwrap)
+            {
+                Ok(_) => {
+                    let latency = queued.elapsed();
+                    self.latency = Some(latency);
+                    self.latencies.push(latency);
+                    self.pending.pop_front();
+                }
+                // Not on screen yet. If we were waiting for it, it may never be, so carry on
+                // without.
+                Err(VulkanError::Timeout) if must_wait => {
+                    self.pending.pop_front();
+                }
+                Err(VulkanError::Timeout) => break,
+                // The swapchain is about to be recreated anyway.
+                Err(VulkanError::OutOfDate) => {
+                    self.pending.clear();
+                    break;
+                }
+                Err(e) => return Err(Validated::Error(e).into()),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+//! Culling nodes against the view frustum on the GPU, for scenes too large to cull on the CPU.
+//!
+//! A compute shader tests each node's bounding sphere and, for those in view, bumps the instance
+//! count of the indirect draw command the node is drawn with and appends the node's index to
+//! that command's range of the visible list. The draws then read both: each command draws its
+//! visible nodes as instances, and the vertex shader looks up which node an instance is in the
+//! visible list at `gl_InstanceIndex`.
+//!
+//! Every frame slot has its own commands and visible list, reset from a template before each
+//! dispatch. The command buffer puts the barriers between the reset, the dispatch and the draws.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CopyBufferInfo, DrawIndexedIndirectCommand, PrimaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout, PipelineShaderStageCreateInfo,
+};
+
+use crate::context::VulkanContext;
+use crate::culling::CullStats;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::scene::{FrameData, FRAME_SLOTS};
+use crate::shader_dump;
+
+/// Nodes culled by each workgroup; matches `local_size_x` in the shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+mod cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            struct CullNode {
+                vec4 sphere;
+                uint command;
+            };
+
+            struct DrawCommand {
+                uint index_count;
+                uint instance_count;
+                uint first_index;
+                int vertex_offset;
+                uint first_instance;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Nodes {
+                CullNode nodes[];
+            };
+            layout(set = 0, binding = 1) buffer Commands {
+                DrawCommand commands[];
+            };
+            layout(set = 0, binding = 2) writeonly buffer Visible {
+                uint visible[];
+            };
+
+            layout(push_constant) uniform Cull {
+                mat4 view_projection;
+                uint node_count;
+            } cull;
+
+            // Like `Frustum::from_view_projection`: -w <= x, y <= w and 0 <= z <= w in clip space.
+            bool intersects_frustum(vec3 center, float radius) {
+                mat4 m = transpose(cull.view_projection);
+                vec4 planes[6] = vec4[6](
+                    m[3] + m[0], m[3] - m[0], m[3] + m[1], m[3] - m[1], m[2], m[3] - m[2]
+                );
+                for (int i = 0; i < 6; i++) {
+                    float len = length(planes[i].xyz);
+                    // A far plane at infinity has no normal, and nothing is beyond it.
+                    if (len > 1e-6 && dot(planes[i].xyz, center) + planes[i].w < -radius * len) {
+                        return false;
+                    }
+                }
+                return true;
+            }
+
+            void main() {
+                uint node = gl_GlobalInvocationID.x;
+                if (node >= cull.node_count) {
+                    return;
+                }
+                CullNode cull_node = nodes[node];
+                if (intersects_frustum(cull_node.sphere.xyz, cull_node.sphere.w)) {
+                    uint slot = atomicAdd(commands[cull_node.command].instance_count, 1);
+                    visible[commands[cull_node.command].first_instance + slot] = node;
+                }
+            }
+        "
+    }
+}
+
+/// A node as the culling shader sees it: its world space bounding sphere and the draw command
+/// it is an instance of.
+#[derive(BufferContents, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct CullNode {
+    /// The centre, then the radius.
+    pub sphere: [f32; 4],
+    pub command: u32,
+    /// The array stride in the shader's std430 layout is a multiple of the `vec4`'s alignment.
+    _padding: [u32; 3],
+}
+
+impl CullNode {
+    pub fn new(center: Vec3, radius: f32, command: u32) -> Self {
+        Self {
+            sphere: center.extend(radius).to_array(),
+            command,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Draw commands for the `(indices, instance count)` of each draw, with no instances to draw
+/// yet. Each command's instances start where the previous command's end, so every node drawn
+/// with it fits in its range of the visible list.
+pub fn indirect_commands(
+    draws: impl IntoIterator<Item = (Range<u32>, u32)>,
+) -> Vec<DrawIndexedIndirectCommand> {
+    let mut first_instance = 0;
+    draws
+        .into_iter()
+        .map(|(indices, instances)| {
+            let command = DrawIndexedIndirectCommand {
+                index_count: indices.len() as u32,
+                instance_count: 0,
+                first_index: indices.start,
+                vertex_offset: 0,
+                first_instance,
+            };
+            first_instance += instances;
+            command
+        })
+        .collect()
+}
+
+/// The culling shader and the buffers it fills for each frame slot.
+pub struct GpuCuller {
+    pipeline: Arc<ComputePipeline>,
+    node_count: u32,
+    /// The commands with instance counts of zero, copied over a slot's before culling.
+    template: Subbuffer<[DrawIndexedIndirectCommand]>,
+    commands: Vec<Subbuffer<[DrawIndexedIndirectCommand]>>,
+    visible: Vec<Subbuffer<[u32]>>,
+    descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+}
+
+impl GpuCuller {
+    /// Sets up culling `nodes` into `commands`, as made by [`indirect_commands`].
+    ///
+    /// # Panics
+    ///
+    /// If `nodes` or `commands` is empty, as buffers can't be.
+    pub fn new(
+        ctx: &VulkanContext,
+        nodes: &[CullNode],
+        commands: &[DrawIndexedIndirectCommand],
+    ) -> Result<Self, RendererError> {
+        assert!(!nodes.is_empty() && !commands.is_empty());
+        let cs = cs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        shader_dump::entry_point(&cs);
+        let stage = PipelineShaderStageCreateInfo::new(cs);
+        let layout = PipelineLayout::new(
+            ctx.device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(ctx.device.clone())?,
+        )?;
+        let pipeline = ComputePipeline::new(
+            ctx.device.clone(),
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )?;
+        ctx.name_object(&pipeline, "culling pipeline");
+
+        let device_local = || AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let cull_nodes = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            device_local(),
+            nodes.iter().copied(),
+        )?;
+        let template = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            device_local(),
+            commands.iter().copied(),
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Storage, cull_nodes.buffer());
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Storage, template.buffer());
+
+        let mut culler = Self {
+            pipeline,
+            node_count: nodes.len() as u32,
+            template,
+            commands: Vec::with_capacity(FRAME_SLOTS),
+            visible: Vec::with_capacity(FRAME_SLOTS),
+            descriptor_sets: Vec::with_capacity(FRAME_SLOTS),
+        };
+        for _ in 0..FRAME_SLOTS {
+            // Read back on the CPU for the culling stats, once the slot's frame is done. Starts
+            // out as the template, so nothing has been drawn before the first frame.
+            let commands = Buffer::from_iter(
+                ctx.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::INDIRECT_BUFFER
+                        | BufferUsage::STORAGE_BUFFER
+                        | BufferUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                    ..Default::default()
+                },
+                commands.iter().copied(),
+            )?;
+            let visible = Buffer::new_slice::<u32>(
+                ctx.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+                nodes.len() as u64,
+            )?;
+            ctx.memory_tracker
+                .track_buffer(MemoryCategory::Storage, commands.buffer());
+            ctx.memory_tracker
+                .track_buffer(MemoryCategory::Storage, visible.buffer());
+            culler.descriptor_sets.push(PersistentDescriptorSet::new(
+                ctx.descriptor_set_allocator.as_ref(),
+                culler.pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::buffer(0, cull_nodes.clone()),
+                    WriteDescriptorSet::buffer(1, commands.clone()),
+                    WriteDescriptorSet::buffer(2, visible.clone()),
+                ],
+                [],
+            )?);
+            culler.commands.push(commands);
+            culler.visible.push(visible);
+        }
+        Ok(culler)
+    }
+
+    /// Records culling the nodes against `frame`'s view into its slot's commands and visible
+    /// list. Must come before the render pass drawing with them.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let slot = frame.frame_in_flight;
+        let view_projection: Mat4 = frame.projection * frame.view;
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                self.template.clone(),
+                self.commands[slot].clone(),
+            ))?
+            .bind_pipeline_compute(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_sets[slot].clone(),
+            )?
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                cs::Cull {
+                    view_projection: view_projection.to_cols_array_2d(),
+                    node_count: self.node_count,
+                },
+            )?
+            .dispatch([self.node_count.div_ceil(WORKGROUP_SIZE), 1, 1])?;
+        Ok(())
+    }
+
+    /// The draw commands culling for `frame` fills in.
+    pub fn commands(&self, frame: &FrameData) -> &Subbuffer<[DrawIndexedIndirectCommand]> {
+        &self.commands[frame.frame_in_flight]
+    }
+
+    /// The indices of the nodes in view, by command, for each frame slot.
+    pub fn visible_lists(&self) -> &[Subbuffer<[u32]>] {
+        &self.visible
+    }
+
+    /// How many nodes the previous frame in `frame`'s slot drew, which the GPU has finished.
+    /// `None` if the commands can't be read yet.
+    pub fn stats(&self, frame: &FrameData) -> Option<CullStats> {
+        let commands = self.commands[frame.frame_in_flight].read().ok()?;
+        let drawn: u32 = commands.iter().map(|command| command.instance_count).sum();
+        Some(CullStats {
+            drawn: drawn as usize,
+            culled: self.node_count.saturating_sub(drawn) as usize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_leave_room_for_all_their_instances() {
+        let commands = indirect_commands([(0..36, 3), (36..42, 1), (42..48, 2)]);
+        let firsts: Vec<_> = commands.iter().map(|c| c.first_instance).collect();
+        assert_eq!(firsts, [0, 3, 4]);
+        assert_eq!(commands[1].index_count, 6);
+        assert_eq!(commands[1].first_index, 36);
+        assert!(commands.iter().all(|command| command.instance_count == 0));
+    }
+
+    #[test]
+    fn cull_nodes_match_the_shader_layout() {
+        assert_eq!(std::mem::size_of::<CullNode>(), 32);
+        let node = CullNode::new(Vec3::new(1.0, 2.0, 3.0), 4.0, 7);
+        assert_eq!(node.sphere, [1.0, 2.0, 3.0, 4.0]);
+    }
+}
@@ -0,0 +1,97 @@
+//! Index buffers in the narrowest index type that can address the vertices they index.
+//!
+//! Indices are built as `u32` on the CPU, whatever their size. Meshes with few enough vertices
+//! are uploaded as `u16`, halving the memory their indices take.
+
+use vulkano::buffer::{
+    Buffer, BufferContents, BufferCreateInfo, BufferUsage, IndexBuffer, IndexType, Subbuffer,
+};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+
+/// The index type to draw `vertex_count` vertices with. `0xFFFF` is left unused, since it means
+/// a primitive restart when restarts are enabled.
+pub fn index_type_for(vertex_count: usize) -> IndexType {
+    if vertex_count <= usize::from(u16::MAX) {
+        IndexType::U16
+    } else {
+        IndexType::U32
+    }
+}
+
+/// Uploads `indices` into a `u16` index buffer if they index no more vertices than
+/// [`index_type_for`] allows for `vertex_count`, otherwise into a `u32` one.
+///
+/// # Panics
+///
+/// If an index is `vertex_count` or more.
+pub fn create_index_buffer(
+    ctx: &VulkanContext,
+    indices: &[u32],
+    vertex_count: usize,
+) -> Result<IndexBuffer, RendererError> {
+    assert!(
+        indices.iter().all(|&index| (index as usize) < vertex_count),
+        "an index is out of bounds for {vertex_count} vertices"
+    );
+    Ok(match index_type_for(vertex_count) {
+        IndexType::U16 => {
+            let narrow: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+            IndexBuffer::U16(create_index_buffer_u16(ctx, &narrow)?)
+        }
+        _ => IndexBuffer::U32(create_index_buffer_u32(ctx, indices)?),
+    })
+}
+
+pub fn create_index_buffer_u16(
+    ctx: &VulkanContext,
+    indices: &[u16],
+) -> Result<Subbuffer<[u16]>, RendererError> {
+    upload(ctx, indices)
+}
+
+pub fn create_index_buffer_u32(
+    ctx: &VulkanContext,
+    indices: &[u32],
+) -> Result<Subbuffer<[u32]>, RendererError> {
+    upload(ctx, indices)
+}
+
+fn upload<T: BufferContents + Copy>(
+    ctx: &VulkanContext,
+    indices: &[T],
+) -> Result<Subbuffer<[T]>, RendererError> {
+    let buffer = Buffer::from_iter(
+        ctx.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::INDEX_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        indices.iter().copied(),
+    )?;
+    ctx.memory_tracker
+        .track_buffer(MemoryCategory::Index, buffer.buffer());
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_meshes_use_u16_indices() {
+        assert_eq!(index_type_for(3), IndexType::U16);
+        assert_eq!(index_type_for(usize::from(u16::MAX)), IndexType::U16);
+        // Index 0xFFFF would be needed, which is kept for primitive restarts.
+        assert_eq!(index_type_for(usize::from(u16::MAX) + 1), IndexType::U32);
+        assert_eq!(index_type_for(1 << 20), IndexType::U32);
+    }
+}
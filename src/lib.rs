@@ -0,0 +1,61 @@
+//! Building blocks for the hi-vulkanos demos: device setup, scenes and offscreen rendering.
+
+pub mod animation;
+pub mod app;
+pub mod benchmark;
+pub mod bindless;
+pub mod block_decode;
+pub mod camera;
+pub mod caps;
+pub mod clear_color;
+pub mod compressed_texture;
+pub mod console;
+pub mod context;
+pub mod copy_bench;
+pub mod crash_report;
+pub mod culling;
+pub mod device_selection;
+pub mod draw_cache;
+pub mod dynamic_mesh;
+pub mod environment;
+pub mod error;
+#[cfg(windows)]
+pub mod exclusive_fullscreen;
+pub mod feedback;
+pub mod frame_commands;
+pub mod frame_graph;
+pub mod frame_pacing;
+pub mod gpu_culling;
+pub mod index_buffer;
+pub mod loader;
+pub mod memory_report;
+pub mod mesh;
+pub mod minimap;
+pub mod model;
+pub mod offscreen;
+pub mod options;
+pub mod picking;
+pub mod render_pass;
+pub mod render_thread;
+pub mod renderdoc;
+pub mod renderer;
+pub mod safe_mode;
+pub mod sampler;
+pub mod scene;
+pub mod screenshot;
+pub mod shader;
+pub mod shader_dump;
+pub mod sprite;
+pub mod staging;
+pub mod std140;
+pub mod stereo;
+pub mod subgroups;
+pub mod surface_config;
+pub mod text;
+pub mod texture;
+pub mod transform;
+pub mod upscale;
+pub mod validation;
+pub mod vertex_input;
+pub mod window_config;
+pub mod window_context;
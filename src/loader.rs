@@ -0,0 +1,139 @@
+//! Loading assets on worker threads, so reading and decoding large files doesn't hold up
+//! rendering.
+//!
+//! [`Loader::load`] queues a job, which a worker takes as soon as it is free, and
+//! [`Loader::finished`] hands back the results that are ready without waiting for the rest.
+//! Only the CPU side runs on the workers. Uploading what they produce needs the device's queue,
+//! so that is left to whoever takes the result, between frames.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::RendererError;
+
+type Job<T> = Box<dyn FnOnce() -> Result<T, RendererError> + Send>;
+
+/// A job that has run, successfully or not.
+#[derive(Debug)]
+pub struct Loaded<T> {
+    /// What the job was queued as, e.g. the path of the file it loads.
+    pub name: String,
+    pub result: Result<T, RendererError>,
+    /// How long the job took once a worker had started it.
+    pub elapsed: Duration,
+}
+
+/// Worker threads running loading jobs, in the order they are queued.
+///
+/// Dropping the loader doesn't wait for the workers: each finishes its current job, if it has
+/// one, and ends, and its result goes nowhere.
+pub struct Loader<T> {
+    jobs: Sender<(String, Job<T>)>,
+    results: Receiver<Loaded<T>>,
+    /// Jobs queued whose results haven't been handed back yet.
+    pending: usize,
+}
+
+impl<T: Send + 'static> Loader<T> {
+    /// Starts `threads` workers, at least one.
+    pub fn new(threads: usize) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<(String, Job<T>)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for index in 0..threads.max(1) {
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            thread::Builder::new()
+                .name(format!("loader {index}"))
+                .spawn(move || loop {
+                    // Hold the lock only while waiting, so the others can take jobs as this one
+                    // runs its own.
+                    let next = job_receiver
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .recv();
+                    let Ok((name, job)) = next else {
+                        return;
+                    };
+                    let start = Instant::now();
+                    let result = job();
+                    let loaded = Loaded {
+                        name,
+                        result,
+                        elapsed: start.elapsed(),
+                    };
+                    if result_sender.send(loaded).is_err() {
+                        return;
+                    }
+                })
+                .expect("failed to spawn a loader thread");
+        }
+        Self {
+            jobs,
+            results,
+            pending: 0,
+        }
+    }
+
+    /// Queues `job`, known as `name` in its [`Loaded`].
+    pub fn load(
+        &mut self,
+        name: impl Into<String>,
+        job: impl FnOnce() -> Result<T, RendererError> + Send + 'static,
+    ) {
+        // The workers only stop once the loader is gone, so they are still there to send to.
+        let _ = self.jobs.send((name.into(), Box::new(job)));
+        self.pending += 1;
+    }
+
+    /// The jobs that have finished since the last call, in the order they finished.
+    pub fn finished(&mut self) -> Vec<Loaded<T>> {
+        let finished: Vec<_> = self.results.try_iter().collect();
+        self.pending -= finished.len();
+        finished
+    }
+
+    /// How many jobs are queued or running, or finished but not yet handed back.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Everything `loader` has pending, waiting up to a few seconds for it.
+    fn wait_for_all<T: Send + 'static>(loader: &mut Loader<T>) -> Vec<Loaded<T>> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut loaded = Vec::new();
+        while loader.pending() > 0 {
+            assert!(Instant::now() < deadline, "the jobs never finished");
+            loaded.extend(loader.finished());
+            thread::sleep(Duration::from_millis(1));
+        }
+        loaded
+    }
+
+    #[test]
+    fn jobs_run_in_the_background() {
+        let mut loader = Loader::new(2);
+        assert!(loader.finished().is_empty());
+        for n in 0..4 {
+            loader.load(format!("job {n}"), move || Ok(n * 10));
+        }
+        loader.load("failing job", || {
+            Err(RendererError::InvalidModel("not a model".to_owned()))
+        });
+        assert_eq!(loader.pending(), 5);
+
+        let mut loaded = wait_for_all(&mut loader);
+        loaded.sort_by(|a, b| a.name.cmp(&b.name));
+        let names: Vec<_> = loaded.iter().map(|loaded| loaded.name.as_str()).collect();
+        assert_eq!(names, ["failing job", "job 0", "job 1", "job 2", "job 3"]);
+        assert!(loaded[0].result.is_err());
+        assert_eq!(loaded[3].result.as_ref().ok(), Some(&20));
+    }
+}
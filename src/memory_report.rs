@@ -0,0 +1,352 @@
+//! Bookkeeping of how much GPU memory the renderer's own resources use.
+//!
+//! vulkano's allocator doesn't expose statistics, so the crate's helpers register the buffers and
+//! images they create with a [`MemoryTracker`]. The tracker only holds weak references, so
+//! resources drop out of the report as soon as they are freed.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+use vulkano::buffer::Buffer;
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::image::Image;
+use vulkano::{DeviceSize, Version, VulkanObject};
+
+/// Heaps using more than this fraction of their budget get a warning.
+const BUDGET_WARNING_THRESHOLD: f64 = 0.9;
+
+/// What a tracked resource is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryCategory {
+    Vertex,
+    Index,
+    Uniform,
+    /// Buffers shaders write, like indirect draw commands.
+    Storage,
+    Texture,
+    RenderTarget,
+    Staging,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 7] = [
+        MemoryCategory::Vertex,
+        MemoryCategory::Index,
+        MemoryCategory::Uniform,
+        MemoryCategory::Storage,
+        MemoryCategory::Texture,
+        MemoryCategory::RenderTarget,
+        MemoryCategory::Staging,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCategory::Vertex => "vertex",
+            MemoryCategory::Index => "index",
+            MemoryCategory::Uniform => "uniform",
+            MemoryCategory::Storage => "storage",
+            MemoryCategory::Texture => "texture",
+            MemoryCategory::RenderTarget => "render targets",
+            MemoryCategory::Staging => "staging",
+        }
+    }
+}
+
+struct TrackedResource {
+    category: MemoryCategory,
+    size: DeviceSize,
+    /// Address of the resource, to avoid counting it twice.
+    address: usize,
+    resource: Weak<dyn Any + Send + Sync>,
+}
+
+/// Keeps a tally of the live buffers and images the renderer allocated, by category.
+pub struct MemoryTracker {
+    physical_device: Arc<PhysicalDevice>,
+    /// Whether `VK_EXT_memory_budget` was enabled, so heap budgets can be queried.
+    memory_budget: bool,
+    resources: Mutex<Vec<TrackedResource>>,
+    command_pools: Mutex<CommandPoolStats>,
+}
+
+impl MemoryTracker {
+    pub fn new(physical_device: Arc<PhysicalDevice>, memory_budget: bool) -> Self {
+        Self {
+            physical_device,
+            memory_budget,
+            resources: Mutex::new(Vec::new()),
+            command_pools: Mutex::new(CommandPoolStats::default()),
+        }
+    }
+
+    /// Counts `buffer` under `category` for as long as it is alive. Tracking the same buffer again
+    /// (e.g. another suballocation out of it) does nothing.
+    pub fn track_buffer(&self, category: MemoryCategory, buffer: &Arc<Buffer>) {
+        self.track(category, buffer.size(), buffer.clone());
+    }
+
+    /// Counts `image` under `category` for as long as it is alive.
+    pub fn track_image(&self, category: MemoryCategory, image: &Arc<Image>) {
+        let size = image
+            .memory_requirements()
+            .iter()
+            .map(|requirements| requirements.layout.size())
+            .sum();
+        self.track(category, size, image.clone());
+    }
+
+    fn track<T: Any + Send + Sync>(
+        &self,
+        category: MemoryCategory,
+        size: DeviceSize,
+        resource: Arc<T>,
+    ) {
+        let address = Arc::as_ptr(&resource) as usize;
+        let mut resources = self.resources.lock().unwrap();
+        if resources.iter().any(|tracked| tracked.address == address) {
+            return;
+        }
+        let resource: Arc<dyn Any + Send + Sync> = resource;
+        resources.push(TrackedResource {
+            category,
+            size,
+            address,
+            resource: Arc::downgrade(&resource),
+        });
+    }
+
+    /// Updates the command pool stats, for the allocators in
+    /// [`frame_commands`](crate::frame_commands) to keep up to date.
+    pub fn count_command_pools(&self, update: impl FnOnce(&mut CommandPoolStats)) {
+        update(&mut self.command_pools.lock().unwrap());
+    }
+
+    /// Sums up the live resources and, when the device supports it, the driver's heap budgets.
+    pub fn report(&self) -> MemoryReport {
+        let mut resources = self.resources.lock().unwrap();
+        resources.retain(|tracked| tracked.resource.strong_count() > 0);
+
+        let mut bytes = [0; MemoryCategory::ALL.len()];
+        for tracked in resources.iter() {
+            bytes[tracked.category as usize] += tracked.size;
+        }
+
+        MemoryReport {
+            bytes,
+            command_pools: *self.command_pools.lock().unwrap(),
+            heaps: self
+                .memory_budget
+                .then(|| query_heap_budgets(&self.physical_device))
+                .flatten()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// How the per-frame command pools have been used since startup, over all windows.
+///
+/// In steady state every frame resets its slot's pool and reuses its command buffers, so only
+/// `command_buffers` and `resets` keep growing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommandPoolStats {
+    /// Command buffers allocated for frames.
+    pub command_buffers: u64,
+    /// Pools the allocators needed: one per slot to begin with, and another whenever a slot's
+    /// pool was full. vulkano may hand out one it kept from before rather than create it.
+    pub pools: u64,
+    /// Times a slot's pool was reset for reuse.
+    pub resets: u64,
+    /// Times a slot's pool couldn't be reset because one of its command buffers was still alive.
+    pub resets_in_use: u64,
+}
+
+/// Budget and current usage of one memory heap, as reported by `VK_EXT_memory_budget`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeapBudget {
+    pub heap_index: u32,
+    /// How much this process can use before the driver may start failing or evicting.
+    pub budget: DeviceSize,
+    /// How much this process currently uses, including allocations we don't track.
+    pub usage: DeviceSize,
+}
+
+impl HeapBudget {
+    pub fn is_near_budget(&self) -> bool {
+        self.budget > 0 && self.usage as f64 > self.budget as f64 * BUDGET_WARNING_THRESHOLD
+    }
+}
+
+/// A snapshot of the renderer's memory use.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryReport {
+    /// Bytes in live resources, indexed by [`MemoryCategory`].
+    pub bytes: [DeviceSize; MemoryCategory::ALL.len()],
+    pub command_pools: CommandPoolStats,
+    /// Heap budgets. Empty when `VK_EXT_memory_budget` isn't available.
+    pub heaps: Vec<HeapBudget>,
+}
+
+impl MemoryReport {
+    pub fn bytes(&self, category: MemoryCategory) -> DeviceSize {
+        self.bytes[category as usize]
+    }
+
+    pub fn total(&self) -> DeviceSize {
+        self.bytes.iter().sum()
+    }
+
+    /// A compact single line for the stats overlay.
+    pub fn summary(&self) -> String {
+        let categories: Vec<_> = MemoryCategory::ALL
+            .into_iter()
+            .map(|category| format!("{} {}", category.name(), format_bytes(self.bytes(category))))
+            .collect();
+        format!(
+            "mem {} ({})",
+            format_bytes(self.total()),
+            categories.join(", ")
+        )
+    }
+
+    /// Logs a warning for every heap that is close to its budget.
+    pub fn warn_if_near_budget(&self) {
+        for heap in self.heaps.iter().filter(|heap| heap.is_near_budget()) {
+            log::warn!(
+                "Memory heap {} uses {} of its {} budget",
+                heap.heap_index,
+                format_bytes(heap.usage),
+                format_bytes(heap.budget),
+            );
+        }
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GPU memory allocated by the renderer:")?;
+        for category in MemoryCategory::ALL {
+            write!(
+                f,
+                "\n  {:<15} {:>10}",
+                category.name(),
+                format_bytes(self.bytes(category))
+            )?;
+        }
+        write!(f, "\n  {:<15} {:>10}", "total", format_bytes(self.total()))?;
+        let pools = &self.command_pools;
+        if pools.command_buffers > 0 {
+            write!(
+                f,
+                "\n  command pools: {} for {} command buffers, {} resets",
+                pools.pools, pools.command_buffers, pools.resets
+            )?;
+            if pools.resets_in_use > 0 {
+                write!(f, " ({} failed while in use)", pools.resets_in_use)?;
+            }
+        }
+        for heap in &self.heaps {
+            write!(
+                f,
+                "\n  heap {}: {} used of {} budget",
+                heap.heap_index,
+                format_bytes(heap.usage),
+                format_bytes(heap.budget),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`.
+pub fn format_bytes(bytes: DeviceSize) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Asks the driver for per-heap budgets and usage. Returns `None` if the device doesn't support
+/// `VK_EXT_memory_budget` or the instance can't make the query.
+pub fn query_heap_budgets(physical_device: &PhysicalDevice) -> Option<Vec<HeapBudget>> {
+    let instance = physical_device.instance();
+    if !physical_device.supported_extensions().ext_memory_budget
+        || instance.api_version() < Version::V1_1
+    {
+        return None;
+    }
+
+    // vulkano doesn't wrap this query, so chain the budget struct onto the properties ourselves.
+    let mut budget = ash::vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let mut properties = ash::vk::PhysicalDeviceMemoryProperties2 {
+        p_next: &mut budget as *mut _ as *mut std::ffi::c_void,
+        ..Default::default()
+    };
+    // SAFETY: the instance is Vulkan 1.1, so the function is loaded, and the device supports
+    // `VK_EXT_memory_budget`, so chaining its struct is valid. Both structs outlive the call.
+    unsafe {
+        (instance.fns().v1_1.get_physical_device_memory_properties2)(
+            physical_device.handle(),
+            &mut properties,
+        );
+    }
+
+    let heap_count = properties.memory_properties.memory_heap_count as usize;
+    Some(
+        (0..heap_count)
+            .map(|i| HeapBudget {
+                heap_index: i as u32,
+                budget: budget.heap_budget[i],
+                usage: budget.heap_usage[i],
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_bytes_picks_a_unit() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(8 << 20), "8.0 MiB");
+    }
+
+    #[test]
+    fn report_totals_and_lookups() {
+        let mut report = MemoryReport::default();
+        report.bytes[MemoryCategory::Texture as usize] = 4096;
+        report.bytes[MemoryCategory::Vertex as usize] = 1024;
+        assert_eq!(report.bytes(MemoryCategory::Texture), 4096);
+        assert_eq!(report.total(), 5120);
+        assert!(report.summary().starts_with("mem 5.0 KiB"));
+    }
+
+    #[test]
+    fn near_budget_threshold() {
+        let heap = |usage| HeapBudget {
+            heap_index: 0,
+            budget: 1000,
+            usage,
+        };
+        assert!(!heap(900).is_near_budget());
+        assert!(heap(901).is_near_budget());
+        assert!(!HeapBudget {
+            heap_index: 0,
+            budget: 0,
+            usage: 0
+        }
+        .is_near_budget());
+    }
+}
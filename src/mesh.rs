@@ -0,0 +1,120 @@
+//! CPU-side helpers for mesh data, for geometry that comes from files rather than code.
+
+use glam::Vec3;
+
+/// The normal given to vertices no triangle with any area touches, which have no direction of
+/// their own.
+const FALLBACK_NORMAL: [f32; 3] = [0.0, 1.0, 0.0];
+
+/// Each triangle of an indexed triangle list, as its three corners' positions. A trailing
+/// incomplete triangle is ignored.
+fn triangles<'a>(
+    positions: &'a [[f32; 3]],
+    indices: &'a [u32],
+) -> impl Iterator<Item = [Vec3; 3]> + 'a {
+    indices
+        .chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|corner| Vec3::from(positions[triangle[corner] as usize])))
+}
+
+/// The normal of a counter-clockwise triangle, with a length of twice its area.
+fn area_weighted_normal([a, b, c]: [Vec3; 3]) -> Vec3 {
+    (b - a).cross(c - a)
+}
+
+fn normalize_or_fallback(normal: Vec3) -> [f32; 3] {
+    normal
+        .try_normalize()
+        .map_or(FALLBACK_NORMAL, |normal| normal.to_array())
+}
+
+/// One normal per vertex, averaging the normals of the triangles sharing it weighted by their
+/// area, so small slivers don't skew the result. Triangles face the side their corners are
+/// counter-clockwise on.
+///
+/// # Panics
+///
+/// If an index is out of bounds for `positions`.
+pub fn compute_smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut sums = vec![Vec3::ZERO; positions.len()];
+    for (triangle, corners) in indices.chunks_exact(3).zip(triangles(positions, indices)) {
+        let normal = area_weighted_normal(corners);
+        for &index in triangle {
+            sums[index as usize] += normal;
+        }
+    }
+    sums.into_iter().map(normalize_or_fallback).collect()
+}
+
+/// One normal per index, each its triangle's own, for faceted shading. Vertices shared between
+/// triangles can't have a single flat normal, so the mesh has to be drawn unindexed, with
+/// positions gathered in index order to match.
+///
+/// # Panics
+///
+/// If an index is out of bounds for `positions`.
+pub fn compute_flat_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    triangles(positions, indices)
+        .flat_map(|corners| [normalize_or_fallback(area_weighted_normal(corners)); 3])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        let distance = Vec3::from(actual).distance(Vec3::from(expected));
+        assert!(distance < 1e-5, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn a_flat_quad_has_normals_facing_its_front() {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let indices = [0, 1, 2, 0, 2, 3];
+        for normal in compute_smooth_normals(&positions, &indices) {
+            assert_close(normal, [0.0, 0.0, 1.0]);
+        }
+        let flat = compute_flat_normals(&positions, &indices);
+        assert_eq!(flat.len(), indices.len());
+        for normal in flat {
+            assert_close(normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_are_weighted_by_area() {
+        // A big triangle facing +Z and a small one facing +X, sharing the vertex at the origin.
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0],
+            [0.0, 2.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ];
+        let indices = [0, 1, 2, 0, 3, 4];
+        let normals = compute_smooth_normals(&positions, &indices);
+        let expected = Vec3::new(1.0, 0.0, 4.0).normalize().to_array();
+        assert_close(normals[0], expected);
+        assert_close(normals[1], [0.0, 0.0, 1.0]);
+        assert_close(normals[4], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn vertices_without_area_get_the_fallback_normal() {
+        let positions = [[0.0; 3], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [5.0; 3]];
+        let indices = [0, 1, 2];
+        for normal in compute_smooth_normals(&positions, &indices) {
+            assert_eq!(normal, FALLBACK_NORMAL);
+        }
+        assert_eq!(
+            compute_flat_normals(&positions, &indices),
+            [FALLBACK_NORMAL; 3]
+        );
+    }
+}
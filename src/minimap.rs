@@ -0,0 +1,326 @@
+//! A small top-down view of the scene in a corner of the window, following the camera.
+//!
+//! The minimap is one more view of the scene, drawn in the same render pass after the others,
+//! with its viewport and scissor set to a rectangle in the corner. It looks straight down through
+//! a [`TopDownCamera`] above wherever the window's camera is, so the scene moves under it as the
+//! camera flies around. The rectangle's colour and depth are cleared first, so nothing of the
+//! main view shows through, and afterwards a border and a marker for the camera are drawn over
+//! it, before the console.
+
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3, Vec4Swizzles};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, ClearAttachment, ClearRect, CommandBufferInheritanceInfo,
+    CommandBufferUsage, SecondaryAutoCommandBuffer,
+};
+use vulkano::format::ClearColorValue;
+use vulkano::pipeline::graphics::viewport::Scissor;
+use vulkano::render_pass::Subpass;
+
+use crate::camera::TopDownCamera;
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::scene::FrameData;
+use crate::text::TextRenderer;
+
+/// Logical pixels between the minimap and the edges of the window.
+const MARGIN: f64 = 16.0;
+
+/// How wide the border around the minimap is, in logical pixels.
+const BORDER_WIDTH: f64 = 2.0;
+
+/// How big the camera's marker is, in logical pixels.
+const MARKER_SIZE: f64 = 8.0;
+
+/// World units the minimap shows above and below the camera.
+const HALF_HEIGHT: f32 = 4.0;
+
+/// How far above the ground the minimap looks down from. Anything higher isn't visible.
+const VIEW_HEIGHT: f32 = 50.0;
+
+/// What the minimap's rectangle is cleared to before the scene is drawn into it.
+const BACKGROUND_COLOR: [f32; 4] = [0.02, 0.02, 0.03, 1.0];
+const BORDER_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 0.9];
+const MARKER_COLOR: [f32; 4] = [1.0, 0.3, 0.2, 1.0];
+
+/// The corner of the window the minimap sits in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MinimapCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    /// Clear of the console, which covers the top of the window.
+    #[default]
+    BottomRight,
+}
+
+impl MinimapCorner {
+    /// The names `--minimap-corner` takes, in the order the help lists them.
+    pub const NAMES: [(&'static str, MinimapCorner); 4] = [
+        ("top-left", MinimapCorner::TopLeft),
+        ("top-right", MinimapCorner::TopRight),
+        ("bottom-left", MinimapCorner::BottomLeft),
+        ("bottom-right", MinimapCorner::BottomRight),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .into_iter()
+            .find_map(|(corner_name, corner)| (corner_name == name).then_some(corner))
+    }
+}
+
+/// Where the minimap goes and how big it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinimapConfig {
+    /// Shown from the start, rather than after pressing M.
+    pub visible: bool,
+    pub corner: MinimapCorner,
+    /// The length of the minimap's sides in logical pixels, so it looks the same size on any
+    /// monitor.
+    pub size: f64,
+}
+
+impl Default for MinimapConfig {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            corner: MinimapCorner::default(),
+            size: 200.0,
+        }
+    }
+}
+
+impl MinimapConfig {
+    /// The minimap's rectangle in a target of `extent` pixels with `pixels_per_point` of them
+    /// per logical pixel, or `None` if the target is too small to fit it with its margin.
+    pub fn area(&self, extent: [u32; 2], pixels_per_point: f64) -> Option<Scissor> {
+        let size = (self.size * pixels_per_point).round() as u32;
+        let margin = (MARGIN * pixels_per_point).round() as u32;
+        if size == 0 || extent.iter().any(|&length| length < size + 2 * margin) {
+            return None;
+        }
+        let (left, top) = match self.corner {
+            MinimapCorner::TopLeft => (true, true),
+            MinimapCorner::TopRight => (false, true),
+            MinimapCorner::BottomLeft => (true, false),
+            MinimapCorner::BottomRight => (false, false),
+        };
+        let along = |start: bool, length: u32| {
+            if start {
+                margin
+            } else {
+                length - margin - size
+            }
+        };
+        Some(Scissor {
+            offset: [along(left, extent[0]), along(top, extent[1])],
+            extent: [size, size],
+        })
+    }
+}
+
+/// The camera the minimap is seen through, straight above `position` and with -Z up the
+/// minimap like the window's top-down view.
+pub fn minimap_camera(position: Vec3) -> TopDownCamera {
+    TopDownCamera {
+        center: Vec3::new(position.x, 0.0, position.z),
+        height: VIEW_HEIGHT,
+        half_height: HALF_HEIGHT,
+    }
+}
+
+/// A window's minimap, toggled with M. Starts out as `--minimap` says.
+pub struct Minimap {
+    config: MinimapConfig,
+    visible: bool,
+    /// Made the first time the minimap is drawn, like the console's.
+    overlay: Option<TextRenderer>,
+}
+
+impl Minimap {
+    pub fn new(config: MinimapConfig) -> Self {
+        Self {
+            config,
+            visible: config.visible,
+            overlay: None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Where the minimap goes in a target of `extent` pixels, see [`MinimapConfig::area`], or
+    /// `None` while hidden.
+    pub fn area(&self, extent: [u32; 2], pixels_per_point: f64) -> Option<Scissor> {
+        self.visible
+            .then(|| self.config.area(extent, pixels_per_point))
+            .flatten()
+    }
+
+    /// Records clearing `area`'s colour and depth in `subpass` with a command buffer from
+    /// `allocator`, to be executed before the minimap's view is drawn there.
+    pub fn record_clear(
+        &self,
+        ctx: &VulkanContext,
+        allocator: &StandardCommandBufferAllocator,
+        subpass: Subpass,
+        area: Scissor,
+    ) -> Result<Arc<SecondaryAutoCommandBuffer>, RendererError> {
+        let mut builder = AutoCommandBufferBuilder::secondary(
+            allocator,
+            ctx.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+            CommandBufferInheritanceInfo {
+                render_pass: Some(subpass.into()),
+                ..Default::default()
+            },
+        )?;
+        builder.clear_attachments(
+            [
+                ClearAttachment::Color {
+                    color_attachment: 0,
+                    clear_value: ClearColorValue::Float(BACKGROUND_COLOR),
+                },
+                ClearAttachment::Depth(1.0),
+            ]
+            .into_iter()
+            .collect(),
+            [ClearRect {
+                offset: area.offset,
+                extent: area.extent,
+                array_layers: 0..1,
+            }]
+            .into_iter()
+            .collect(),
+        )?;
+        Ok(builder.build()?)
+    }
+
+    /// Records drawing the border around `area` and a marker where the camera at `position`
+    /// looking along `forward` is, as seen in `frame`, the minimap's view. Drawn into a target
+    /// of `extent` pixels in `subpass`, which must be the same every time, with a command buffer
+    /// from `allocator`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_overlay(
+        &mut self,
+        ctx: &VulkanContext,
+        allocator: &StandardCommandBufferAllocator,
+        subpass: Subpass,
+        extent: [u32; 2],
+        area: Scissor,
+        frame: &FrameData,
+        position: Vec3,
+        forward: Vec3,
+        pixels_per_point: f64,
+    ) -> Result<Option<Arc<SecondaryAutoCommandBuffer>>, RendererError> {
+        if self.overlay.is_none() {
+            self.overlay = Some(TextRenderer::new(ctx, subpass.clone())?);
+        }
+        let overlay = self.overlay.as_mut().unwrap();
+        let [left, top] = area.offset.map(|offset| offset as f32);
+        let [right, bottom] = [left + area.extent[0] as f32, top + area.extent[1] as f32];
+        let border = (BORDER_WIDTH * pixels_per_point) as f32;
+        overlay.queue_rect(
+            [left - border, top - border],
+            [right + border, top],
+            BORDER_COLOR,
+        );
+        overlay.queue_rect(
+            [left - border, bottom],
+            [right + border, bottom + border],
+            BORDER_COLOR,
+        );
+        overlay.queue_rect([left - border, top], [left, bottom], BORDER_COLOR);
+        overlay.queue_rect([right, top], [right + border, bottom], BORDER_COLOR);
+
+        let [x, y] = marker_position(frame, area, position);
+        let half = (MARKER_SIZE * pixels_per_point) as f32 / 2.0;
+        overlay.queue_rect([x - half, y - half], [x + half, y + half], MARKER_COLOR);
+        // A smaller square ahead of the marker shows which way the camera looks, unless it
+        // looks straight up or down. The minimap's x follows the world's, its y the world's z.
+        let heading = Vec2::new(forward.x, forward.z).normalize_or_zero() * half * 2.0;
+        if heading != Vec2::ZERO {
+            let [x, y] = [x + heading.x, y + heading.y];
+            let half = half / 2.0;
+            overlay.queue_rect([x - half, y - half], [x + half, y + half], MARKER_COLOR);
+        }
+        overlay.record(ctx, allocator, subpass, extent)
+    }
+}
+
+/// Where `position` appears in `area`, drawn with `frame`'s matrices, in the target's pixels.
+fn marker_position(frame: &FrameData, area: Scissor, position: Vec3) -> [f32; 2] {
+    let clip = frame.projection * frame.view * position.extend(1.0);
+    let ndc = clip.xy() / clip.w;
+    [0, 1].map(|axis| area.offset[axis] as f32 + (ndc[axis] + 1.0) / 2.0 * area.extent[axis] as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimaps_sit_in_their_corner_at_the_monitors_density() {
+        let config = MinimapConfig {
+            visible: true,
+            corner: MinimapCorner::BottomRight,
+            size: 100.0,
+        };
+        assert_eq!(
+            config.area([800, 600], 1.0),
+            Some(Scissor {
+                offset: [684, 484],
+                extent: [100, 100],
+            })
+        );
+        // Twice the pixels per logical pixel, twice the size and margin.
+        let top_left = MinimapConfig {
+            corner: MinimapCorner::TopLeft,
+            ..config
+        };
+        assert_eq!(
+            top_left.area([800, 600], 2.0),
+            Some(Scissor {
+                offset: [32, 32],
+                extent: [200, 200],
+            })
+        );
+        assert_eq!(config.area([120, 600], 1.0), None);
+        assert_eq!(
+            MinimapCorner::from_name("top-right"),
+            Some(MinimapCorner::TopRight)
+        );
+    }
+
+    #[test]
+    fn the_marker_follows_the_camera_to_the_middle_of_the_minimap() {
+        let position = Vec3::new(3.0, 1.5, -2.0);
+        let camera = minimap_camera(position);
+        let area = Scissor {
+            offset: [600, 400],
+            extent: [200, 200],
+        };
+        let frame = FrameData {
+            view: camera.view_matrix(),
+            projection: camera.projection(1.0),
+            ..FrameData::default()
+        };
+        let [x, y] = marker_position(&frame, area, position);
+        assert!((x - 700.0).abs() < 1e-3 && (y - 500.0).abs() < 1e-3);
+        // -Z is up the minimap.
+        let [_, y] = marker_position(&frame, area, position - Vec3::Z);
+        assert!(y < 500.0);
+    }
+}
@@ -0,0 +1,1151 @@
+//! Importing glTF models into plain CPU-side data, ready to be uploaded.
+//!
+//! Every primitive of every mesh is appended to one shared vertex and index arena, so a model
+//! needs only one vertex and one index buffer however many meshes it has. Buffers and images
+//! can be embedded (in a `.glb` or as data URIs) or in files next to the `.gltf`.
+//!
+//! Skins, morph targets and animations are imported along with every node, so that meshes can be
+//! posed by the [`animation`](crate::animation) module.
+
+use std::ops::Range;
+use std::path::Path;
+
+use glam::{Mat4, Quat, Vec3};
+use vulkano::buffer::BufferContents;
+use vulkano::image::sampler::{Filter, SamplerAddressMode, SamplerMipmapMode};
+use vulkano::pipeline::graphics::vertex_input::Vertex;
+
+use crate::animation::{Animation, Channel, Interpolation, Keyframes, Pose, Skeleton, Skin};
+use crate::error::RendererError;
+use crate::mesh::compute_smooth_normals;
+use crate::picking::Aabb;
+use crate::sampler::SamplerConfig;
+use crate::scene::{AlphaMode, PipelineVariant};
+use crate::transform::Transform;
+
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct ModelVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+    /// Indices into the joints of the skin of the node the mesh is placed by, zero for meshes
+    /// without one.
+    #[format(R32G32B32A32_UINT)]
+    pub joints: [u32; 4],
+    /// How much each of [`joints`](Self::joints) moves the vertex, adding up to one, or all zero
+    /// for meshes without skins.
+    #[format(R32G32B32A32_SFLOAT)]
+    pub weights: [f32; 4],
+}
+
+/// The most morph targets a primitive can blend between. Targets past these are left out, with a
+/// warning.
+pub const MAX_MORPH_TARGETS: usize = 8;
+
+/// How far a morph target at full weight moves a vertex and turns its normal. Padded to four
+/// components, as storage buffers lay out arrays of `vec3`.
+#[derive(BufferContents, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct MorphDelta {
+    pub position: [f32; 4],
+    pub normal: [f32; 4],
+}
+
+/// Where a primitive's morph targets are in [`Model::morph_deltas`]: each target's deltas in
+/// turn, one for each of the primitive's vertices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MorphTargets {
+    pub first_delta: u32,
+    /// The first of the primitive's vertices in [`Model::vertices`], whose deltas come first.
+    pub first_vertex: u32,
+    pub vertex_count: u32,
+    /// At most [`MAX_MORPH_TARGETS`].
+    pub count: u32,
+}
+
+/// The parts of a glTF material we draw with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelMaterial {
+    pub base_color_factor: [f32; 4],
+    /// Index into [`Model::images`]. The colour is multiplied by the factor.
+    pub base_color_texture: Option<usize>,
+    /// How the base colour texture is filtered and wrapped.
+    pub base_color_sampler: SamplerConfig,
+    /// From 0 for a dielectric to 1 for a metal, whose highlights take its base colour.
+    pub metallic_factor: f32,
+    /// From 0 for a mirror to 1 for a surface without highlights.
+    pub roughness_factor: f32,
+    /// Index into [`Model::images`], whose green channel multiplies the roughness factor and
+    /// blue channel the metallic factor.
+    pub metallic_roughness_texture: Option<usize>,
+    pub metallic_roughness_sampler: SamplerConfig,
+    /// Index into [`Model::images`] of a tangent space normal map.
+    pub normal_texture: Option<usize>,
+    pub normal_sampler: SamplerConfig,
+    /// How far the normal map's X and Y tilt the normal.
+    pub normal_scale: f32,
+    /// The linear colour of the light the surface gives off itself, black for none.
+    pub emissive_factor: [f32; 3],
+    /// Index into [`Model::images`]. The emitted colour is multiplied by the factor.
+    pub emissive_texture: Option<usize>,
+    pub emissive_sampler: SamplerConfig,
+    pub alpha_mode: AlphaMode,
+    /// Alpha under which [`AlphaMode::Mask`] materials are discarded.
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl ModelMaterial {
+    /// The pipeline state drawing with the material needs.
+    pub fn pipeline_variant(&self) -> PipelineVariant {
+        PipelineVariant {
+            alpha_mode: self.alpha_mode,
+            double_sided: self.double_sided,
+        }
+    }
+}
+
+impl Default for ModelMaterial {
+    /// What glTF says primitives without a material look like: plain white, and as its
+    /// defaults for the rest.
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            base_color_sampler: SamplerConfig::default(),
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            metallic_roughness_sampler: SamplerConfig::default(),
+            normal_texture: None,
+            normal_sampler: SamplerConfig::default(),
+            normal_scale: 1.0,
+            emissive_factor: [0.0; 3],
+            emissive_texture: None,
+            emissive_sampler: SamplerConfig::default(),
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+/// Tightly packed RGBA8 pixels: in sRGB for base colour textures, linear for the rest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// A triangle list in the model's arena.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Primitive {
+    /// The range of [`Model::indices`] making up the triangles. The indices already point at the
+    /// primitive's own vertices in [`Model::vertices`].
+    pub indices: Range<u32>,
+    /// Index into [`Model::materials`], or `None` for the default material.
+    pub material: Option<usize>,
+    pub bounds: Aabb,
+    /// One more than the highest joint index its vertices use, or zero if they have none.
+    pub joint_count: u32,
+    pub morph_targets: Option<MorphTargets>,
+}
+
+/// A mesh placed in the scene by a node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshInstance {
+    /// Index into [`Model::meshes`].
+    pub mesh: usize,
+    /// The glTF node placing it, e.g. to look up its morph target weights in a [`Pose`].
+    pub node: usize,
+    /// From the mesh's space to the model's, including every parent node's transform.
+    pub transform: Transform,
+    /// Index into [`Model::skins`]. Skinned meshes are placed by their joints, not by
+    /// `transform`.
+    pub skin: Option<usize>,
+}
+
+/// A glTF document's default scene, flattened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    /// Every primitive's morph targets, see [`MorphTargets`].
+    pub morph_deltas: Vec<MorphDelta>,
+    pub primitives: Vec<Primitive>,
+    /// Each mesh's primitives, as indices into [`primitives`](Self::primitives).
+    pub meshes: Vec<Vec<usize>>,
+    pub materials: Vec<ModelMaterial>,
+    pub images: Vec<ModelImage>,
+    pub instances: Vec<MeshInstance>,
+    /// Every node of the document, not only the default scene's, as animations refer to them.
+    pub skeleton: Skeleton,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<Animation>,
+}
+
+impl Model {
+    /// Loads a `.gltf` or `.glb` file, with any buffers and images it refers to.
+    pub fn load(path: &Path) -> Result<Self, RendererError> {
+        let (document, buffers, images) = gltf::import(path).map_err(|err| {
+            RendererError::InvalidModel(format!("can't load {}: {err}", path.display()))
+        })?;
+        Self::from_gltf(&document, &buffers, &images)
+    }
+
+    /// Loads a model from the contents of a `.glb` file or a `.gltf` whose buffers and images
+    /// are all embedded.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, RendererError> {
+        let (document, buffers, images) = gltf::import_slice(bytes)
+            .map_err(|err| RendererError::InvalidModel(err.to_string()))?;
+        Self::from_gltf(&document, &buffers, &images)
+    }
+
+    fn from_gltf(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        images: &[gltf::image::Data],
+    ) -> Result<Self, RendererError> {
+        let mut model = Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            morph_deltas: Vec::new(),
+            primitives: Vec::new(),
+            meshes: Vec::new(),
+            materials: document.materials().map(import_material).collect(),
+            images: images.iter().map(import_image).collect::<Result<_, _>>()?,
+            instances: Vec::new(),
+            skeleton: import_skeleton(document),
+            skins: document
+                .skins()
+                .map(|skin| import_skin(&skin, buffers))
+                .collect::<Result<_, _>>()?,
+            animations: document
+                .animations()
+                .map(|animation| import_animation(&animation, buffers))
+                .collect::<Result<_, _>>()?,
+        };
+
+        for mesh in document.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    log::warn!(
+                        "Skipping a primitive of mesh {} drawn as {:?}, only triangle lists are \
+                         supported",
+                        mesh.index(),
+                        primitive.mode()
+                    );
+                    continue;
+                }
+                primitives.push(model.primitives.len());
+                let primitive = model.import_primitive(&primitive, buffers)?;
+                model.primitives.push(primitive);
+            }
+            model.meshes.push(primitives);
+        }
+
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next());
+        for node in scene.iter().flat_map(|scene| scene.nodes()) {
+            model.place(&node, Transform::IDENTITY);
+        }
+        for instance in &model.instances {
+            let Some(skin) = instance.skin else {
+                continue;
+            };
+            let joints = model.skins[skin].joints.len();
+            for &primitive in &model.meshes[instance.mesh] {
+                if model.primitives[primitive].joint_count as usize > joints {
+                    return Err(RendererError::InvalidModel(format!(
+                        "mesh {} uses more joints than skin {skin} has",
+                        instance.mesh
+                    )));
+                }
+            }
+        }
+        Ok(model)
+    }
+
+    /// Appends a primitive's vertices and indices to the arena.
+    fn import_primitive(
+        &mut self,
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Primitive, RendererError> {
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &**data));
+        let positions: Vec<[f32; 3]> = reader
+            .read_positions()
+            .ok_or_else(|| {
+                RendererError::InvalidModel("a primitive has no vertex positions".to_owned())
+            })?
+            .collect();
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(&index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+            return Err(RendererError::InvalidModel(format!(
+                "index {index} is out of bounds for a primitive with {} vertices",
+                positions.len()
+            )));
+        }
+        let normals: Vec<[f32; 3]> = match reader.read_normals() {
+            Some(normals) => normals.collect(),
+            None => compute_smooth_normals(&positions, &indices),
+        };
+        let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+            Some(uvs) => uvs.into_f32().collect(),
+            None => vec![[0.0; 2]; positions.len()],
+        };
+        let joints: Vec<[u32; 4]> = match reader.read_joints(0) {
+            Some(joints) => joints
+                .into_u16()
+                .map(|joints| joints.map(u32::from))
+                .collect(),
+            None => vec![[0; 4]; positions.len()],
+        };
+        let weights: Vec<[f32; 4]> = match reader.read_weights(0) {
+            Some(weights) => weights.into_f32().collect(),
+            None => vec![[0.0; 4]; positions.len()],
+        };
+        if normals.len() != positions.len()
+            || uvs.len() != positions.len()
+            || joints.len() != positions.len()
+            || weights.len() != positions.len()
+        {
+            return Err(RendererError::InvalidModel(
+                "a primitive's vertex attributes have different lengths".to_owned(),
+            ));
+        }
+
+        let bounds = Aabb::from_points(positions.iter().map(|&p| Vec3::from(p)))
+            .ok_or_else(|| RendererError::InvalidModel("a primitive has no vertices".to_owned()))?;
+        let morph_targets = self.import_morph_targets(&reader, positions.len())?;
+        // Joints without any weight don't move the vertex, so needn't exist.
+        let joint_count = joints
+            .iter()
+            .zip(&weights)
+            .flat_map(|(joints, weights)| {
+                joints
+                    .iter()
+                    .zip(weights)
+                    .filter(|(_, &weight)| weight != 0.0)
+                    .map(|(&joint, _)| joint + 1)
+            })
+            .max()
+            .unwrap_or(0);
+        let base_vertex = self.vertices.len() as u32;
+        self.vertices.extend(
+            positions
+                .into_iter()
+                .zip(normals)
+                .zip(uvs)
+                .zip(joints.into_iter().zip(weights))
+                .map(
+                    |(((position, normal), uv), (joints, weights))| ModelVertex {
+                        position,
+                        normal,
+                        uv,
+                        joints,
+                        weights,
+                    },
+                ),
+        );
+        let first_index = self.indices.len() as u32;
+        self.indices
+            .extend(indices.into_iter().map(|index| base_vertex + index));
+        Ok(Primitive {
+            indices: first_index..self.indices.len() as u32,
+            material: primitive.material().index(),
+            bounds,
+            joint_count,
+            morph_targets,
+        })
+    }
+
+    /// Appends the deltas of a primitive's morph targets, up to [`MAX_MORPH_TARGETS`] of them, to
+    /// the arena. Its vertices must be the next `vertex_count` to be appended.
+    fn import_morph_targets<'a, 's, F>(
+        &mut self,
+        reader: &gltf::mesh::Reader<'a, 's, F>,
+        vertex_count: usize,
+    ) -> Result<Option<MorphTargets>, RendererError>
+    where
+        F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+    {
+        let targets = reader.read_morph_targets();
+        if targets.len() == 0 {
+            return Ok(None);
+        }
+        if targets.len() > MAX_MORPH_TARGETS {
+            log::warn!(
+                "A primitive has {} morph targets, only the first {MAX_MORPH_TARGETS} are used",
+                targets.len()
+            );
+        }
+        let first_delta = self.morph_deltas.len() as u32;
+        let count = targets.len().min(MAX_MORPH_TARGETS);
+        for (positions, normals, _) in targets.take(count) {
+            let mut deltas = vec![MorphDelta::default(); vertex_count];
+            let mut lengths = [vertex_count; 2];
+            if let Some(positions) = positions {
+                lengths[0] = positions.len();
+                for (delta, [x, y, z]) in deltas.iter_mut().zip(positions) {
+                    delta.position = [x, y, z, 0.0];
+                }
+            }
+            if let Some(normals) = normals {
+                lengths[1] = normals.len();
+                for (delta, [x, y, z]) in deltas.iter_mut().zip(normals) {
+                    delta.normal = [x, y, z, 0.0];
+                }
+            }
+            if lengths != [vertex_count; 2] {
+                return Err(RendererError::InvalidModel(
+                    "a morph target's deltas don't match its primitive's vertices".to_owned(),
+                ));
+            }
+            self.morph_deltas.extend(deltas);
+        }
+        Ok(Some(MorphTargets {
+            first_delta,
+            first_vertex: self.vertices.len() as u32,
+            vertex_count: vertex_count as u32,
+            count: count as u32,
+        }))
+    }
+
+    /// Instances the meshes of `node` and its descendants, `parent` being the transform of the
+    /// node's parent.
+    fn place(&mut self, node: &gltf::Node, parent: Transform) {
+        let transform = parent * import_transform(node);
+        if let Some(mesh) = node.mesh() {
+            self.instances.push(MeshInstance {
+                mesh: mesh.index(),
+                node: node.index(),
+                transform,
+                skin: node.skin().map(|skin| skin.index()),
+            });
+        }
+        for child in node.children() {
+            self.place(&child, transform);
+        }
+    }
+
+    /// A box around every instance, or `None` if nothing is placed.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.instances
+            .iter()
+            .flat_map(|instance| {
+                self.meshes[instance.mesh].iter().map(|&primitive| {
+                    self.primitives[primitive]
+                        .bounds
+                        .transformed(instance.transform.matrix())
+                })
+            })
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+fn import_transform(node: &gltf::Node) -> Transform {
+    let (translation, rotation, scale) = node.transform().decomposed();
+    Transform {
+        translation,
+        rotation: Quat::from_array(rotation),
+        scale,
+    }
+}
+
+fn import_skeleton(document: &gltf::Document) -> Skeleton {
+    let mut parents = vec![None; document.nodes().len()];
+    for node in document.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+    // Nodes can override their mesh's default weights, and targets without either start at
+    // none.
+    let weights = document.nodes().map(|node| {
+        let Some(mesh) = node.mesh() else {
+            return Vec::new();
+        };
+        let targets = mesh
+            .primitives()
+            .map(|primitive| primitive.morph_targets().len())
+            .max()
+            .unwrap_or(0);
+        let mut weights = node
+            .weights()
+            .or(mesh.weights())
+            .unwrap_or_default()
+            .to_vec();
+        weights.resize(targets, 0.0);
+        weights
+    });
+    Skeleton {
+        rest: Pose {
+            transforms: document
+                .nodes()
+                .map(|node| import_transform(&node))
+                .collect(),
+            weights: weights.collect(),
+        },
+        parents,
+    }
+}
+
+/// Skins without inverse bind matrices were bound where their joints are at rest.
+fn import_skin(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> Result<Skin, RendererError> {
+    let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+    let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(|data| &**data));
+    let inverse_bind_matrices: Vec<Mat4> = match reader.read_inverse_bind_matrices() {
+        Some(matrices) => matrices
+            .map(|matrix| Mat4::from_cols_array_2d(&matrix))
+            .collect(),
+        None => vec![Mat4::IDENTITY; joints.len()],
+    };
+    if inverse_bind_matrices.len() < joints.len() {
+        return Err(RendererError::InvalidModel(format!(
+            "skin {} has {} joints but {} inverse bind matrices",
+            skin.index(),
+            joints.len(),
+            inverse_bind_matrices.len()
+        )));
+    }
+    Ok(Skin {
+        joints,
+        inverse_bind_matrices,
+    })
+}
+
+/// Cubic spline channels are interpolated linearly between their keyframes, ignoring the
+/// tangents.
+fn import_animation(
+    animation: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Animation, RendererError> {
+    use gltf::animation::util::ReadOutputs;
+
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let node = channel.target().node().index();
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::CubicSpline => {
+                log::warn!(
+                    "Animation {:?} has a cubic spline channel, which is played linearly",
+                    animation.name()
+                );
+                Interpolation::Linear
+            }
+        };
+        // Cubic spline keyframes come as an in-tangent, the value and an out-tangent.
+        let values = |index: usize| {
+            channel.sampler().interpolation() != gltf::animation::Interpolation::CubicSpline
+                || index % 3 == 1
+        };
+        let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &**data));
+        let times: Vec<f32> = reader.read_inputs().into_iter().flatten().collect();
+        let keyframes = match reader.read_outputs() {
+            Some(ReadOutputs::Translations(translations)) => Keyframes::Translation(
+                translations
+                    .enumerate()
+                    .filter(|&(index, _)| values(index))
+                    .map(|(_, translation)| Vec3::from(translation))
+                    .collect(),
+            ),
+            Some(ReadOutputs::Rotations(rotations)) => Keyframes::Rotation(
+                rotations
+                    .into_f32()
+                    .enumerate()
+                    .filter(|&(index, _)| values(index))
+                    .map(|(_, rotation)| Quat::from_array(rotation).normalize())
+                    .collect(),
+            ),
+            Some(ReadOutputs::Scales(scales)) => Keyframes::Scale(
+                scales
+                    .enumerate()
+                    .filter(|&(index, _)| values(index))
+                    .map(|(_, scale)| Vec3::from(scale))
+                    .collect(),
+            ),
+            Some(ReadOutputs::MorphTargetWeights(weights)) => {
+                // Every keyframe's weights for all the targets come one after another.
+                let weights: Vec<f32> = weights.into_f32().collect();
+                let per_time = if values(0) { 1 } else { 3 };
+                let targets = (weights.len() / (times.len() * per_time).max(1)).max(1);
+                Keyframes::Weights(
+                    weights
+                        .chunks(targets)
+                        .enumerate()
+                        .filter(|&(index, _)| values(index))
+                        .map(|(_, weights)| weights.to_vec())
+                        .collect(),
+                )
+            }
+            None => Keyframes::Translation(Vec::new()),
+        };
+        if times.is_empty() || keyframes.len() != times.len() {
+            return Err(RendererError::InvalidModel(format!(
+                "a channel of animation {} has {} keyframe times but {} values",
+                animation.index(),
+                times.len(),
+                keyframes.len()
+            )));
+        }
+        channels.push(Channel {
+            node,
+            interpolation,
+            times,
+            keyframes,
+        });
+    }
+    Ok(Animation {
+        name: animation.name().map(str::to_owned),
+        channels,
+    })
+}
+
+fn import_material(material: gltf::Material) -> ModelMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    let normal = material.normal_texture();
+    let tex_coords = [
+        (
+            "base colour",
+            pbr.base_color_texture().map(|info| info.tex_coord()),
+        ),
+        (
+            "metallic-roughness",
+            pbr.metallic_roughness_texture()
+                .map(|info| info.tex_coord()),
+        ),
+        (
+            "normal map",
+            normal.as_ref().map(|normal| normal.tex_coord()),
+        ),
+        (
+            "emissive texture",
+            material.emissive_texture().map(|info| info.tex_coord()),
+        ),
+    ];
+    for (texture, tex_coord) in tex_coords {
+        if tex_coord.is_some_and(|tex_coord| tex_coord != 0) {
+            log::warn!(
+                "Material {:?} samples its {texture} with a second set of texture coordinates, \
+                 which isn't supported",
+                material.name()
+            );
+        }
+    }
+    let image = |texture: Option<&gltf::Texture>| texture.map(|texture| texture.source().index());
+    let sampler = |texture: Option<&gltf::Texture>| {
+        texture.map_or_else(SamplerConfig::default, |texture| {
+            import_sampler(&texture.sampler())
+        })
+    };
+    let base_color = pbr.base_color_texture().map(|info| info.texture());
+    let metallic_roughness = pbr.metallic_roughness_texture().map(|info| info.texture());
+    let normal_map = normal.as_ref().map(|normal| normal.texture());
+    let emissive = material.emissive_texture().map(|info| info.texture());
+    ModelMaterial {
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: image(base_color.as_ref()),
+        base_color_sampler: sampler(base_color.as_ref()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: image(metallic_roughness.as_ref()),
+        metallic_roughness_sampler: sampler(metallic_roughness.as_ref()),
+        normal_texture: image(normal_map.as_ref()),
+        normal_sampler: sampler(normal_map.as_ref()),
+        normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: image(emissive.as_ref()),
+        emissive_sampler: sampler(emissive.as_ref()),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided(),
+    }
+}
+
+/// glTF leaves filtering up to the renderer where the sampler doesn't say, and wraps by
+/// repeating. Its wrap modes only cover U and V; W doesn't matter for 2D textures.
+fn import_sampler(sampler: &gltf::texture::Sampler) -> SamplerConfig {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let defaults = SamplerConfig::default();
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        None => (defaults.min_filter, defaults.mipmap_mode),
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (Filter::Nearest, SamplerMipmapMode::Nearest)
+        }
+        Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => {
+            (Filter::Linear, SamplerMipmapMode::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (Filter::Nearest, SamplerMipmapMode::Linear),
+        Some(MinFilter::LinearMipmapLinear) => (Filter::Linear, SamplerMipmapMode::Linear),
+    };
+    let mag_filter = match sampler.mag_filter() {
+        None => defaults.mag_filter,
+        Some(MagFilter::Nearest) => Filter::Nearest,
+        Some(MagFilter::Linear) => Filter::Linear,
+    };
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => SamplerAddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => SamplerAddressMode::MirroredRepeat,
+        WrappingMode::Repeat => SamplerAddressMode::Repeat,
+    };
+    SamplerConfig {
+        min_filter,
+        mag_filter,
+        mipmap_mode,
+        address_mode: [
+            address_mode(sampler.wrap_s()),
+            address_mode(sampler.wrap_t()),
+            SamplerAddressMode::Repeat,
+        ],
+        ..defaults
+    }
+}
+
+/// Expands the 8-bit formats to RGBA8.
+fn import_image(image: &gltf::image::Data) -> Result<ModelImage, RendererError> {
+    use gltf::image::Format;
+
+    let channels = match image.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+        format => {
+            return Err(RendererError::InvalidModel(format!(
+                "{format:?} images aren't supported"
+            )))
+        }
+    };
+    Ok(ModelImage {
+        width: image.width,
+        height: image.height,
+        pixels: image
+            .pixels
+            .chunks_exact(channels)
+            .flat_map(|pixel| match *pixel {
+                [luminance] => [luminance, luminance, luminance, 255],
+                [luminance, alpha] => [luminance, luminance, luminance, alpha],
+                [r, g, b] => [r, g, b, 255],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wraps glTF JSON and a binary buffer into a `.glb` file.
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let pad = |data: &[u8], with: u8| {
+            let mut data = data.to_vec();
+            data.resize(data.len().next_multiple_of(4), with);
+            data
+        };
+        let json = pad(json.as_bytes(), b' ');
+        let bin = pad(bin, 0);
+        let mut file = Vec::new();
+        file.extend_from_slice(b"glTF");
+        file.extend_from_slice(&2u32.to_le_bytes());
+        file.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        for (kind, chunk) in [(b"JSON", &json), (b"BIN\0", &bin)] {
+            file.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            file.extend_from_slice(kind);
+            file.extend_from_slice(chunk);
+        }
+        file
+    }
+
+    /// A right triangle in the XY plane, without normals, placed twice: once by a translated
+    /// node and once by its scaled child.
+    fn triangle_model() -> Model {
+        let mut bin = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2] {
+            bin.extend_from_slice(&index.to_le_bytes());
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [
+                    {{ "mesh": 0, "translation": [5, 0, 0], "children": [1] }},
+                    {{ "mesh": 0, "scale": [2, 2, 2] }}
+                ],
+                "meshes": [{{
+                    "primitives": [{{
+                        "attributes": {{ "POSITION": 0 }},
+                        "indices": 1,
+                        "material": 0
+                    }}]
+                }}],
+                "materials": [{{
+                    "pbrMetallicRoughness": {{
+                        "baseColorFactor": [1, 0, 0, 1],
+                        "metallicFactor": 0,
+                        "roughnessFactor": 0.5
+                    }},
+                    "alphaMode": "MASK",
+                    "doubleSided": true
+                }}],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0]
+                    }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+                ],
+                "buffers": [{{ "byteLength": {} }}]
+            }}"#,
+            bin.len()
+        );
+        Model::from_slice(&glb(&json, &bin)).unwrap()
+    }
+
+    #[test]
+    fn primitives_are_imported_into_the_arena() {
+        let model = triangle_model();
+        assert_eq!(model.vertices.len(), 3);
+        assert_eq!(model.indices, [0, 1, 2]);
+        assert_eq!(model.meshes, [vec![0]]);
+        assert_eq!(model.primitives[0].indices, 0..3);
+        assert_eq!(model.primitives[0].material, Some(0));
+        assert_eq!(model.materials[0].base_color_factor, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(model.materials[0].metallic_factor, 0.0);
+        assert_eq!(model.materials[0].roughness_factor, 0.5);
+        assert_eq!(
+            model.materials[0].pipeline_variant(),
+            PipelineVariant {
+                alpha_mode: AlphaMode::Mask,
+                double_sided: true,
+            }
+        );
+        assert_eq!(model.materials[0].alpha_cutoff, 0.5);
+        // Missing normals are computed, missing texture coordinates are zero.
+        for vertex in &model.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+            assert_eq!(vertex.uv, [0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn node_transforms_include_their_parents() {
+        let model = triangle_model();
+        assert_eq!(model.instances.len(), 2);
+        let point = Vec3::new(1.0, 0.0, 0.0);
+        assert_eq!(
+            model.instances[0].transform.transform_point(point),
+            Vec3::new(6.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            model.instances[1].transform.transform_point(point),
+            Vec3::new(7.0, 0.0, 0.0)
+        );
+        let bounds = model.bounds().unwrap();
+        assert_eq!(bounds.min, Vec3::new(5.0, 0.0, 0.0));
+        assert_eq!(bounds.max, Vec3::new(7.0, 2.0, 0.0));
+    }
+
+    /// The triangle again, skinned to a two joint arm whose elbow a step animation turns.
+    fn skinned_model() -> Model {
+        let mut bin = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        for joint in [0u16, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0] {
+            bin.extend_from_slice(&joint.to_le_bytes());
+        }
+        for weight in [
+            1.0f32, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.0, 0.0,
+        ] {
+            bin.extend_from_slice(&weight.to_le_bytes());
+        }
+        for time in [0.0f32, 1.0] {
+            bin.extend_from_slice(&time.to_le_bytes());
+        }
+        let half_turn = Quat::from_rotation_z(std::f32::consts::PI);
+        for value in Quat::IDENTITY
+            .to_array()
+            .into_iter()
+            .chain(half_turn.to_array())
+        {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0, 1] }}],
+                "nodes": [
+                    {{ "mesh": 0, "skin": 0 }},
+                    {{ "children": [2] }},
+                    {{ "translation": [0, 1, 0] }}
+                ],
+                "skins": [{{ "joints": [1, 2] }}],
+                "meshes": [{{
+                    "primitives": [{{
+                        "attributes": {{ "POSITION": 0, "JOINTS_0": 1, "WEIGHTS_0": 2 }}
+                    }}]
+                }}],
+                "animations": [{{
+                    "name": "wave",
+                    "samplers": [{{ "input": 3, "output": 4, "interpolation": "STEP" }}],
+                    "channels": [{{ "sampler": 0, "target": {{ "node": 2, "path": "rotation" }} }}]
+                }}],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0]
+                    }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "VEC4" }},
+                    {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4" }},
+                    {{
+                        "bufferView": 3, "componentType": 5126, "count": 2, "type": "SCALAR",
+                        "min": [0], "max": [1]
+                    }},
+                    {{ "bufferView": 4, "componentType": 5126, "count": 2, "type": "VEC4" }}
+                ],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 24 }},
+                    {{ "buffer": 0, "byteOffset": 60, "byteLength": 48 }},
+                    {{ "buffer": 0, "byteOffset": 108, "byteLength": 8 }},
+                    {{ "buffer": 0, "byteOffset": 116, "byteLength": 32 }}
+                ],
+                "buffers": [{{ "byteLength": {} }}]
+            }}"#,
+            bin.len()
+        );
+        Model::from_slice(&glb(&json, &bin)).unwrap()
+    }
+
+    #[test]
+    fn skins_and_animations_are_imported() {
+        let model = skinned_model();
+        assert_eq!(model.vertices[1].joints, [1, 0, 0, 0]);
+        assert_eq!(model.vertices[2].weights, [0.5, 0.5, 0.0, 0.0]);
+        assert_eq!(model.primitives[0].joint_count, 2);
+        assert_eq!(model.instances[0].skin, Some(0));
+        assert_eq!(model.skeleton.parents, [None, None, Some(1)]);
+        assert_eq!(model.skins[0].joints, [1, 2]);
+        // Without inverse bind matrices, the vertices were bound to the joints where they are.
+        assert_eq!(model.skins[0].inverse_bind_matrices, [Mat4::IDENTITY; 2]);
+
+        let animation = &model.animations[0];
+        assert_eq!(animation.name.as_deref(), Some("wave"));
+        assert_eq!(animation.duration(), 1.0);
+        let channel = &animation.channels[0];
+        assert_eq!(channel.node, 2);
+        assert_eq!(channel.interpolation, Interpolation::Step);
+        assert_eq!(channel.times, [0.0, 1.0]);
+        let mut pose = model.skeleton.rest.clone();
+        animation.sample(0.5, &mut pose);
+        assert_eq!(pose.transforms[2].rotation, Quat::IDENTITY);
+        assert_eq!(pose.transforms[2].translation, [0.0, 1.0, 0.0]);
+    }
+
+    /// The triangle again, with two morph targets raising its corners, whose weights an
+    /// animation swaps.
+    fn morphed_model() -> Model {
+        let mut bin = Vec::new();
+        for value in [
+            0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, // the triangle
+            0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, // the first target raises vertex 1
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, // the second raises vertex 2
+            0.0, 1.0, // keyframe times
+            1.0, 0.0, 0.0, 1.0, // keyframe weights
+        ] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [{{ "mesh": 0 }}],
+                "meshes": [{{
+                    "primitives": [{{
+                        "attributes": {{ "POSITION": 0 }},
+                        "targets": [{{ "POSITION": 1 }}, {{ "POSITION": 2 }}]
+                    }}],
+                    "weights": [0.25, 0.0]
+                }}],
+                "animations": [{{
+                    "samplers": [{{ "input": 3, "output": 4 }}],
+                    "channels": [{{ "sampler": 0, "target": {{ "node": 0, "path": "weights" }} }}]
+                }}],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [1, 1, 0]
+                    }},
+                    {{
+                        "bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [0, 0, 1]
+                    }},
+                    {{
+                        "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [0, 0, 2]
+                    }},
+                    {{
+                        "bufferView": 3, "componentType": 5126, "count": 2, "type": "SCALAR",
+                        "min": [0], "max": [1]
+                    }},
+                    {{ "bufferView": 4, "componentType": 5126, "count": 4, "type": "SCALAR" }}
+                ],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 72, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 108, "byteLength": 8 }},
+                    {{ "buffer": 0, "byteOffset": 116, "byteLength": 16 }}
+                ],
+                "buffers": [{{ "byteLength": {} }}]
+            }}"#,
+            bin.len()
+        );
+        Model::from_slice(&glb(&json, &bin)).unwrap()
+    }
+
+    #[test]
+    fn morph_targets_and_their_weights_are_imported() {
+        let model = morphed_model();
+        assert_eq!(
+            model.primitives[0].morph_targets,
+            Some(MorphTargets {
+                first_delta: 0,
+                first_vertex: 0,
+                vertex_count: 3,
+                count: 2,
+            })
+        );
+        // Each target's deltas in turn, without normals.
+        assert_eq!(model.morph_deltas.len(), 6);
+        assert_eq!(model.morph_deltas[1].position, [0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(model.morph_deltas[5].position, [0.0, 0.0, 2.0, 0.0]);
+        assert_eq!(model.morph_deltas[5].normal, [0.0; 4]);
+        assert_eq!(model.instances[0].node, 0);
+        assert_eq!(model.skeleton.rest.weights, [vec![0.25, 0.0]]);
+
+        let mut pose = model.skeleton.rest.clone();
+        model.animations[0].sample(0.25, &mut pose);
+        assert_eq!(pose.weights[0], [0.75, 0.25]);
+    }
+
+    #[test]
+    fn materials_keep_their_metallic_roughness_and_normal_textures() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "uri": "base.png" }, { "uri": "orm.png" }, { "uri": "normal.png" }],
+            "samplers": [{ "wrapS": 33071 }],
+            "textures": [{ "source": 0 }, { "source": 1, "sampler": 0 }, { "source": 2 }],
+            "materials": [
+                {
+                    "pbrMetallicRoughness": { "metallicRoughnessTexture": { "index": 1 } },
+                    "normalTexture": { "index": 2, "scale": 0.5 }
+                },
+                {}
+            ]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let materials: Vec<_> = gltf.materials().map(import_material).collect();
+        assert_eq!(materials[0].base_color_texture, None);
+        assert_eq!(materials[0].metallic_roughness_texture, Some(1));
+        assert_eq!(
+            materials[0].metallic_roughness_sampler.address_mode[0],
+            SamplerAddressMode::ClampToEdge
+        );
+        assert_eq!(materials[0].normal_texture, Some(2));
+        assert_eq!(materials[0].normal_scale, 0.5);
+        assert_eq!(materials[1], ModelMaterial::default());
+    }
+
+    #[test]
+    fn materials_keep_their_emissive_factor_and_texture() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "uri": "glow.png" }],
+            "textures": [{ "source": 0 }],
+            "materials": [
+                { "emissiveFactor": [1.0, 0.5, 0.0], "emissiveTexture": { "index": 0 } },
+                { "pbrMetallicRoughness": { "baseColorFactor": [1.0, 1.0, 1.0, 1.0] } }
+            ]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let materials: Vec<_> = gltf.materials().map(import_material).collect();
+        assert_eq!(materials[0].emissive_factor, [1.0, 0.5, 0.0]);
+        assert_eq!(materials[0].emissive_texture, Some(0));
+        // White, but giving off no light of its own.
+        assert_eq!(materials[1].emissive_factor, [0.0; 3]);
+        assert_eq!(materials[1].emissive_texture, None);
+    }
+
+    #[test]
+    fn samplers_map_to_vulkan_filters_and_wrapping() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "samplers": [
+                { "magFilter": 9728, "minFilter": 9986, "wrapS": 33071, "wrapT": 33648 },
+                {}
+            ]
+        }"#;
+        let gltf = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let samplers: Vec<_> = gltf.samplers().map(|s| import_sampler(&s)).collect();
+        assert_eq!(
+            samplers[0],
+            SamplerConfig {
+                min_filter: Filter::Nearest,
+                mag_filter: Filter::Nearest,
+                mipmap_mode: SamplerMipmapMode::Linear,
+                address_mode: [
+                    SamplerAddressMode::ClampToEdge,
+                    SamplerAddressMode::MirroredRepeat,
+                    SamplerAddressMode::Repeat,
+                ],
+                anisotropy: None,
+            }
+        );
+        assert_eq!(samplers[1], SamplerConfig::default());
+    }
+
+    #[test]
+    fn images_are_expanded_to_rgba() {
+        let image = gltf::image::Data {
+            pixels: vec![10, 20, 30, 40, 50, 60],
+            format: gltf::image::Format::R8G8B8,
+            width: 2,
+            height: 1,
+        };
+        assert_eq!(
+            import_image(&image).unwrap().pixels,
+            [10, 20, 30, 255, 40, 50, 60, 255]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{
+    CommandBufferUsage, CopyImageToBufferInfo, RenderPassBeginInfo, SubpassBeginInfo,
+    SubpassContents, SubpassEndInfo,
+};
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::viewport::Scissor;
+use vulkano::render_pass::{Framebuffer, RenderPass, Subpass};
+
+use crate::clear_color::ClearColor;
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::render_pass::{clear_values, create_framebuffer, create_render_pass, SharedAttachments};
+use crate::scene::{record_draws, FrameData, Scene};
+use crate::upscale::{record_upscale, UpscaleFilter};
+
+/// Format of offscreen targets. Deliberately UNORM rather than SRGB so the bytes read back are
+/// exactly what the shaders wrote.
+pub const OFFSCREEN_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// A colour image (plus depth buffer) that scenes can be rendered into and read back from,
+/// without any window.
+pub struct OffscreenTarget {
+    extent: [u32; 2],
+    image: Arc<Image>,
+    render_pass: Arc<RenderPass>,
+    framebuffer: Arc<Framebuffer>,
+    readback_buffer: Subbuffer<[u8]>,
+    clear_color: ClearColor,
+    /// Whether [`Scene::blit_source`]s can be blitted to the image.
+    blit_supported: bool,
+}
+
+impl OffscreenTarget {
+    pub fn new(ctx: &VulkanContext, extent: [u32; 2]) -> Result<Self, RendererError> {
+        // Single-sampled, so the images read back match the references exactly on every device.
+        let render_pass =
+            create_render_pass(ctx.device.clone(), OFFSCREEN_FORMAT, SampleCount::Sample1)?;
+
+        let image = Image::new(
+            ctx.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: OFFSCREEN_FORMAT,
+                extent: [extent[0], extent[1], 1],
+                usage: ImageUsage::COLOR_ATTACHMENT
+                    | ImageUsage::TRANSFER_SRC
+                    | ImageUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+
+        let shared = SharedAttachments::new(ctx, &render_pass, extent, "offscreen")?;
+        ctx.memory_tracker
+            .track_image(MemoryCategory::RenderTarget, &image);
+        ctx.name_object(&image, "offscreen colour target");
+
+        let framebuffer = create_framebuffer(
+            render_pass.clone(),
+            ImageView::new_default(image.clone())?,
+            &shared,
+        )?;
+
+        let readback_buffer = Buffer::new_slice::<u8>(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (extent[0] * extent[1] * 4) as u64,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::RenderTarget, readback_buffer.buffer());
+
+        Ok(Self {
+            extent,
+            image,
+            render_pass,
+            framebuffer,
+            readback_buffer,
+            clear_color: ClearColor::default(),
+            blit_supported: ctx
+                .caps
+                .format_features(OFFSCREEN_FORMAT)
+                .intersects(FormatFeatures::BLIT_DST),
+        })
+    }
+
+    /// Clears to `clear_color` rather than [`CLEAR_COLOR`](crate::scene::CLEAR_COLOR).
+    pub fn with_clear_color(mut self, clear_color: ClearColor) -> Self {
+        self.clear_color = clear_color;
+        self
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// The subpass scenes must build their pipelines against to be drawn into this target.
+    pub fn subpass(&self) -> Subpass {
+        Subpass::from(self.render_pass.clone(), 0).unwrap()
+    }
+
+    /// Draws `scene`, waits for the GPU and returns the image as tightly packed RGBA8 rows.
+    pub fn render(
+        &self,
+        ctx: &VulkanContext,
+        scene: &mut dyn Scene,
+        frame: &FrameData,
+    ) -> Result<Vec<u8>, RendererError> {
+        self.submit(ctx, scene, frame, true)?;
+        let pixels = self.readback_buffer.read()?.to_vec();
+        Ok(pixels)
+    }
+
+    /// Draws `scene` and waits for the GPU, without reading the image back.
+    pub fn draw(
+        &self,
+        ctx: &VulkanContext,
+        scene: &mut dyn Scene,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        self.submit(ctx, scene, frame, false)
+    }
+
+    fn submit(
+        &self,
+        ctx: &VulkanContext,
+        scene: &mut dyn Scene,
+        frame: &FrameData,
+        read_back: bool,
+    ) -> Result<(), RendererError> {
+        // Every submission is waited for, so the GPU is never still using the scene's resources.
+        scene.prepare(frame)?;
+
+        let clear_color = self.clear_color.value(frame.time, OFFSCREEN_FORMAT);
+        // Like windows, the target gets what a compute scene computed blitted over it instead of
+        // the scene's draws, where it can be blitted to.
+        let blit_source = scene.blit_source().filter(|_| self.blit_supported);
+        let scene: &dyn Scene = scene;
+
+        ctx.submit_and_wait(|builder| {
+            scene.draw_offscreen(builder, frame)?;
+            match blit_source {
+                Some(source) => record_upscale(
+                    builder,
+                    source,
+                    self.image.clone(),
+                    UpscaleFilter::Nearest,
+                    clear_color,
+                )?,
+                None => {
+                    let draws = record_draws(
+                        &ctx.command_buffer_allocator,
+                        ctx.queue.queue_family_index(),
+                        CommandBufferUsage::OneTimeSubmit,
+                        Subpass::from(self.render_pass.clone(), 0).unwrap(),
+                        scene,
+                        frame,
+                        Scissor {
+                            offset: [0, 0],
+                            extent: self.extent,
+                        },
+                        None,
+                    )?;
+                    builder
+                        .begin_render_pass(
+                            RenderPassBeginInfo {
+                                clear_values: clear_values(&self.render_pass, clear_color),
+                                ..RenderPassBeginInfo::framebuffer(self.framebuffer.clone())
+                            },
+                            SubpassBeginInfo {
+                                contents: SubpassContents::SecondaryCommandBuffers,
+                                ..Default::default()
+                            },
+                        )?
+                        .execute_commands(draws)?
+                        .end_render_pass(SubpassEndInfo::default())?;
+                }
+            }
+            if read_back {
+                builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                    self.image.clone(),
+                    self.readback_buffer.clone(),
+                ))?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,998 @@
+//! Command-line options for the demo binary.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use vulkano::image::SampleCount;
+use vulkano::swapchain::PresentMode;
+use vulkano::Version;
+
+use crate::caps::parse_api_version;
+use crate::clear_color::{parse_clear_color, ClearColor};
+use crate::device_selection::{DevicePreference, DeviceSelection};
+use crate::minimap::{MinimapConfig, MinimapCorner};
+use crate::scene::{LightsConfig, SceneKind, SceneOptions, DEFAULT_BODIES, DEFAULT_UPDATE_RATE};
+use crate::upscale::{RenderScale, UpscaleFilter, MAX_RENDER_SCALE, MIN_RENDER_SCALE};
+use crate::validation::LayerFeature;
+use crate::window_config::CursorStyle;
+
+const USAGE: &str = "\
+Usage: hi-vulkanos [OPTIONS]
+
+Options:
+      --scene <NAME>     Scene to draw: triangle, textured_quad, cube (default), plasma,
+                         monitor, texture_grid, life, nbody, terrain, sprites, flag,
+                         trails, lights or transparency
+      --bodies <N>       Simulate N bodies in the nbody scene and benchmark (default 16384)
+      --lights <N>       Light the lights scene with N point lights (default 512)
+      --light-tile-size <PIXELS>
+                         Bin the lights scene's lights into tiles this many pixels square
+                         (default 16)
+      --max-lights-per-tile <N>
+                         List at most N lights per tile (default 128), warning about tiles
+                         reached by more
+      --naive-lights     Start out lighting every pixel of the lights scene with every light,
+                         rather than its tile's, to compare frame times with --frames
+      --model <PATH>     Draw a glTF model (.gltf or .glb) instead of a built-in scene
+      --skybox <DIR>     Draw a cubemap skybox behind the scene, from px.png, nx.png, py.png,
+                         ny.png, pz.png and nz.png in DIR
+      --environment <PATH>
+                         Light models with the equirectangular Radiance .hdr environment map
+                         in this file, drawn behind the scene without --skybox. Preprocessed
+                         once and cached by the file's hash
+      --frames <N>       Render exactly N frames, print timing statistics and exit
+      --capture-frame <N>
+                         Capture frame N with RenderDoc, when running under it
+      --headless         Render offscreen without opening a window
+      --second-window    Also open a window with a top-down orthographic view of the scene
+      --transparent      Let the desktop show through wherever nothing is drawn, where the
+                         compositor supports it
+      --window-icon <PATH>
+                         Give the windows the icon in this PNG file, where the platform shows
+                         one
+      --cursor <NAME>    The cursor over the windows: default, hidden, crosshair, hand, arrow,
+                         move or text
+      --minimap          Start with the minimap, a top-down view around the camera, shown. K
+                         shows and hides it
+      --minimap-corner <CORNER>
+                         top-left, top-right, bottom-left or bottom-right (default) corner of
+                         the window for the minimap
+      --minimap-size <N> Make the minimap N logical pixels square (default 200)
+      --clear-color <R,G,B[,A]>
+                         Draw the scene on this colour, as it should look, each channel from 0
+                         to 1 (default 0,0,0,1). Not for transparent windows
+      --clear-animate    Cycle the clear colour's hue over time, to see that frames are drawn
+      --hdr              Present in HDR where the display supports it, as linear extended sRGB
+                         in a 16-bit float format
+      --exclusive-fullscreen
+                         Windows only: take the monitor over exclusively in fullscreen (F11),
+                         where VK_EXT_full_screen_exclusive is supported
+      --present-mode <MODE>
+                         fifo (default), fifo_relaxed, mailbox or immediate. Falls back to fifo
+                         when the surface doesn't support the mode
+      --frame-latency <N>
+                         Wait for presents so at most N frames are queued for display, where
+                         VK_KHR_present_wait is supported: 1 waits for the previous frame to
+                         reach the screen before starting the next
+      --swapchain-images <N>
+                         Ask for N swapchain images (default 2), within what the surface allows
+      --compute-present  Let scenes computed in compute shaders, like life, write straight into
+                         the swapchain images, where the surface and its format allow storage
+                         images
+      --stereo           Draw the scene once per eye, side by side, in one pass with multiview
+                         where the device and scene support it
+      --reset-safe-mode  Start normally again after safe mode, which a launch that crashed or
+                         hung before drawing its first frame leaves the next ones in
+      --allow-software-renderer
+                         Run on a software renderer like llvmpipe even when a hardware device
+                         exists but can't be used
+      --render-scale <F> Render at F times the window size (0.125 to 2) and scale to the window
+      --logical-size <WxH>
+                         Render at a fixed size and scale it to the window, letterboxed
+      --upscale-filter <FILTER>
+                         nearest (default) or linear filtering when scaling to the window
+      --msaa <N>         Multisample the windows with N samples (1, 2, 4 or 8, default 1), or
+                         the most the device supports below that
+      --record-threads <N>
+                         Record the draws of large scenes on up to N threads (default: one per
+                         core)
+      --load-threads <N> Load --model on N threads in the background (default 1), drawing the
+                         built-in scene until it is ready, or before the first frame with 0 or
+                         --frames
+      --record-every-frame
+                         Record the scene's draws anew every frame rather than reusing them
+                         while they stay the same, to compare the record time
+      --gpu-culling      Cull the nodes of models in a compute shader and draw them indirectly,
+                         where the device supports it, rather than culling on the CPU
+      --vertex-pulling   Fetch the vertices of models from a storage buffer by index in the
+                         vertex shader, rather than binding them as a vertex buffer
+      --depth-prepass    Start out drawing the opaque surfaces of models with a depth pre-pass,
+                         shading only what is nearest, to compare frame times with --frames. Z
+                         switches it on and off
+      --update-rate <HZ> Update scenes HZ times a second, whatever the frame rate (default 60)
+      --shader-printf    Log what shaders print with debugPrintfEXT, through the validation
+                         layer, which slows every shader down a lot. Windowed only
+      --dump-shaders <DIR>
+                         Log what each shader declares as pipelines are built with it, and
+                         write it into DIR, with the SPIR-V of shaders loaded from .spv files
+      --gpu-validation   Check what shaders access on the GPU, through the validation layer,
+                         which slows every shader down a lot. Windowed only, and not together
+                         with --shader-printf
+      --stats            Show stats in the title bar, refreshed every second: frames per
+                         second, milliseconds to screen and recording, how many nodes were
+                         drawn and culled, the draw calls and pipeline, material and buffer
+                         binds they took, and the swapchain image count and present mode
+      --mem-stats        Add GPU memory use to the --stats title bar and print a report on
+                         exit. M logs the report at any time, with or without this
+      --print-caps       Print the device's features, limits and format support, then exit
+      --list-devices     Print every device with its --gpu index, type, Vulkan and driver
+                         versions and whether it can be used, then exit
+      --nbody-bench      Time the nbody simulation's steps without drawing them, print how many
+                         interactions between bodies it computes per second, then exit
+      --subgroup-demo    Add up a buffer in compute shaders, with subgroupAdd where supported
+                         and in shared memory, print both sums next to the CPU's, then exit
+      --bench-copy <BYTES>
+                         Time copying BYTES (or with a K, M or G suffix, KiB, MiB or GiB) into
+                         device-local and host-visible buffers, print the GB/s of each, then
+                         exit
+      --force-api-version <VERSION>
+                         Use at most Vulkan 1.1, 1.2 or 1.3 and no extensions standing in for
+                         newer core features, to test the fallbacks
+      --gpu <INDEX>      Use the device at this position in the device list
+      --gpu-name <NAME>  Use the best device whose name contains NAME (ignoring case)
+  -h, --help             Print this help
+
+Set RUST_LOG (e.g. RUST_LOG=debug) to change how much is logged.";
+
+/// Settings picked on the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// The scene to draw.
+    pub scene: SceneKind,
+    /// A glTF model to draw instead of the scene.
+    pub model: Option<PathBuf>,
+    /// A directory holding the six faces of a skybox to draw behind the scene.
+    pub skybox: Option<PathBuf>,
+    /// An equirectangular `.hdr` environment map to light models with.
+    pub environment: Option<PathBuf>,
+    /// Number of frames to render before exiting. `None` runs until the window is closed.
+    pub frames: Option<u32>,
+    /// The frame to capture with RenderDoc, counting from 1.
+    pub capture_frame: Option<u32>,
+    /// Render into an offscreen image instead of a window.
+    pub headless: bool,
+    /// Open a second window showing the scene from above.
+    pub second_window: bool,
+    /// Make the windows transparent where nothing is drawn.
+    pub transparent: bool,
+    /// A PNG file to use as the windows' icon.
+    pub window_icon: Option<PathBuf>,
+    /// The cursor shown over the windows while it isn't captured.
+    pub cursor: CursorStyle,
+    /// Where the windows' minimaps go and whether they start out shown.
+    pub minimap: MinimapConfig,
+    /// What the scene is drawn on top of.
+    pub clear_color: ClearColor,
+    /// Present in an HDR colour space where the surface offers one.
+    pub hdr: bool,
+    /// Hold exclusive fullscreen while a window is fullscreen.
+    #[cfg(windows)]
+    pub exclusive_fullscreen: bool,
+    /// Which physical device to render with.
+    pub device: DeviceSelection,
+    /// Show frame, culling, batching and swapchain statistics in the title bar while running.
+    pub stats: bool,
+    /// Add memory statistics to the title bar while running and print them on exit. Implies
+    /// `stats`.
+    pub mem_stats: bool,
+    /// The present mode to ask the swapchain for.
+    pub present_mode: PresentMode,
+    /// The most presents that may be queued before the next frame waits for the oldest to reach
+    /// the screen. `None` leaves pacing to the frame fences.
+    pub frame_latency: Option<u32>,
+    /// How many swapchain images to ask for. `None` asks for two, or the surface's minimum.
+    pub swapchain_images: Option<u32>,
+    /// Give the swapchain images storage usage where possible, for scenes that compute the
+    /// whole frame to write into.
+    pub compute_present: bool,
+    /// Draw the scene for a left and a right eye, side by side.
+    pub stereo: bool,
+    /// The resolution to render the scene at, relative to the window or fixed.
+    pub render_scale: RenderScale,
+    /// How the scene is filtered when scaled to the window.
+    pub upscale_filter: UpscaleFilter,
+    /// The samples per pixel to draw into windows with. Headless rendering is never
+    /// multisampled, so its output stays the same on every device.
+    pub msaa: SampleCount,
+    /// Caps the Vulkan API version, to exercise the paths for older drivers.
+    pub force_api_version: Option<Version>,
+    /// Print the capabilities matrix instead of rendering.
+    pub print_caps: bool,
+    /// List the physical devices instead of rendering, without creating one.
+    pub list_devices: bool,
+    /// How many bodies the nbody scene and benchmark simulate.
+    pub bodies: u32,
+    /// How many lights the lights scene has, and how it bins them.
+    pub lights: LightsConfig,
+    /// Benchmark the nbody simulation instead of rendering.
+    pub nbody_bench: bool,
+    /// Run the subgroup reduction demo instead of rendering.
+    pub subgroup_demo: bool,
+    /// Benchmark copies of this many bytes instead of rendering.
+    pub bench_copy: Option<u64>,
+    /// Re-record the scene's draws every frame even when they could be reused.
+    pub record_every_frame: bool,
+    /// Cull models on the GPU instead of the CPU.
+    pub gpu_culling: bool,
+    /// Pull models' vertices from a storage buffer instead of binding a vertex buffer.
+    pub vertex_pulling: bool,
+    /// Draw models' opaque surfaces' depth before shading them.
+    pub depth_prepass: bool,
+    /// How many times a second scenes are updated.
+    pub update_rate: f32,
+    /// Run under the validation layer, to log what shaders print or validate them on the GPU.
+    pub layer_feature: Option<LayerFeature>,
+    /// A directory to write what every shader declares into, see
+    /// [`shader_dump`](crate::shader_dump).
+    pub dump_shaders: Option<PathBuf>,
+    /// The most threads to record a scene's draws on. `None` uses one per core.
+    pub record_threads: Option<usize>,
+    /// Leave safe mode, see [`safe_mode`](crate::safe_mode).
+    pub reset_safe_mode: bool,
+    /// How many threads load `model` in the background. With none, it is loaded before the first
+    /// frame instead; see [`load_in_background`](Self::load_in_background).
+    pub load_threads: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: SceneKind::Cube,
+            model: None,
+            skybox: None,
+            environment: None,
+            frames: None,
+            capture_frame: None,
+            headless: false,
+            second_window: false,
+            transparent: false,
+            window_icon: None,
+            cursor: CursorStyle::default(),
+            minimap: MinimapConfig::default(),
+            clear_color: ClearColor::default(),
+            hdr: false,
+            #[cfg(windows)]
+            exclusive_fullscreen: false,
+            device: DeviceSelection::default(),
+            stats: false,
+            mem_stats: false,
+            present_mode: PresentMode::Fifo,
+            frame_latency: None,
+            swapchain_images: None,
+            compute_present: false,
+            stereo: false,
+            render_scale: RenderScale::default(),
+            upscale_filter: UpscaleFilter::default(),
+            msaa: SampleCount::Sample1,
+            force_api_version: None,
+            print_caps: false,
+            list_devices: false,
+            bodies: DEFAULT_BODIES,
+            lights: LightsConfig::default(),
+            nbody_bench: false,
+            subgroup_demo: false,
+            bench_copy: None,
+            record_every_frame: false,
+            gpu_culling: false,
+            vertex_pulling: false,
+            depth_prepass: false,
+            update_rate: DEFAULT_UPDATE_RATE,
+            layer_feature: None,
+            dump_shaders: None,
+            record_threads: None,
+            reset_safe_mode: false,
+            load_threads: 1,
+        }
+    }
+}
+
+/// Why the command line couldn't be turned into [`Options`].
+#[derive(Debug, PartialEq)]
+pub enum OptionsError {
+    /// `--help` was passed. Not really an error, but it stops the program all the same.
+    Help,
+    /// The arguments were malformed. Holds a message for the user.
+    Invalid(String),
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Help => write!(f, "{USAGE}"),
+            Self::Invalid(msg) => write!(f, "{msg}\n\n{USAGE}"),
+        }
+    }
+}
+
+impl Options {
+    /// Parses the process's arguments.
+    pub fn from_env() -> Result<Self, OptionsError> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parses `args`, which should not include the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, OptionsError> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        let mut render_scale_given = false;
+
+        while let Some(arg) = args.next() {
+            // Accept both `--flag value` and `--flag=value`.
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag.to_owned(), Some(value)),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || -> Result<String, OptionsError> {
+                inline_value
+                    .map(str::to_owned)
+                    .or_else(|| args.next())
+                    .ok_or_else(|| OptionsError::Invalid(format!("{flag} needs a value")))
+            };
+
+            match flag.as_str() {
+                "--scene" => {
+                    let value = value()?;
+                    options.scene = SceneKind::from_name(&value)
+                        .ok_or_else(|| OptionsError::Invalid(format!("unknown scene `{value}`")))?;
+                }
+                "--model" => options.model = Some(PathBuf::from(value()?)),
+                "--skybox" => options.skybox = Some(PathBuf::from(value()?)),
+                "--environment" => options.environment = Some(PathBuf::from(value()?)),
+                "--window-icon" => options.window_icon = Some(PathBuf::from(value()?)),
+                "--dump-shaders" => options.dump_shaders = Some(PathBuf::from(value()?)),
+                "--cursor" => {
+                    let value = value()?;
+                    options.cursor = CursorStyle::from_name(&value).ok_or_else(|| {
+                        OptionsError::Invalid(format!("unknown cursor `{value}`"))
+                    })?;
+                }
+                "--minimap" => options.minimap.visible = true,
+                "--minimap-corner" => {
+                    let value = value()?;
+                    options.minimap.corner = MinimapCorner::from_name(&value).ok_or_else(|| {
+                        OptionsError::Invalid(format!("unknown minimap corner `{value}`"))
+                    })?;
+                }
+                "--minimap-size" => {
+                    let value = value()?;
+                    options.minimap.size = value
+                        .parse()
+                        .ok()
+                        .filter(|&size: &f64| size >= 1.0 && size.is_finite())
+                        .ok_or_else(|| {
+                            OptionsError::Invalid(format!(
+                                "--minimap-size expects a size of at least 1, got `{value}`"
+                            ))
+                        })?;
+                }
+                "--update-rate" => {
+                    let value = value()?;
+                    options.update_rate = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|&rate| rate > 0.0 && rate.is_finite())
+                        .ok_or_else(|| {
+                            OptionsError::Invalid(format!(
+                                "--update-rate expects a positive number, got `{value}`"
+                            ))
+                        })?;
+                }
+                "--frames" => {
+                    let value = value()?;
+                    let frames = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--frames expects a positive number, got `{value}`"
+                        ))
+                    })?;
+                    options.frames = Some(frames);
+                }
+                "--capture-frame" => {
+                    let value = value()?;
+                    let frame = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--capture-frame expects a positive number, got `{value}`"
+                        ))
+                    })?;
+                    options.capture_frame = Some(frame);
+                }
+                "--frame-latency" => {
+                    let value = value()?;
+                    let latency = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--frame-latency expects a positive number, got `{value}`"
+                        ))
+                    })?;
+                    options.frame_latency = Some(latency);
+                }
+                "--swapchain-images" => {
+                    let value = value()?;
+                    let count = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--swapchain-images expects a positive number, got `{value}`"
+                        ))
+                    })?;
+                    options.swapchain_images = Some(count);
+                }
+                "--bodies" => {
+                    let value = value()?;
+                    options.bodies = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--bodies expects a positive number, got `{value}`"
+                        ))
+                    })?;
+                }
+                "--lights" => {
+                    let value = value()?;
+                    options.lights.count =
+                        value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            OptionsError::Invalid(format!(
+                                "--lights expects a positive number, got `{value}`"
+                            ))
+                        })?;
+                }
+                "--light-tile-size" => {
+                    let value = value()?;
+                    options.lights.tile_size =
+                        value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            OptionsError::Invalid(format!(
+                                "--light-tile-size expects a positive number of pixels, got \
+                                 `{value}`"
+                            ))
+                        })?;
+                }
+                "--max-lights-per-tile" => {
+                    let value = value()?;
+                    options.lights.max_per_tile =
+                        value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                            OptionsError::Invalid(format!(
+                                "--max-lights-per-tile expects a positive number, got `{value}`"
+                            ))
+                        })?;
+                }
+                "--naive-lights" => options.lights.naive = true,
+                "--record-threads" => {
+                    let value = value()?;
+                    let threads = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--record-threads expects a positive number, got `{value}`"
+                        ))
+                    })?;
+                    options.record_threads = Some(threads);
+                }
+                "--load-threads" => {
+                    let value = value()?;
+                    options.load_threads = value.parse().map_err(|_| {
+                        OptionsError::Invalid(format!(
+                            "--load-threads expects a number, got `{value}`"
+                        ))
+                    })?;
+                }
+                "--msaa" => {
+                    let value = value()?;
+                    options.msaa = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|n| [1, 2, 4, 8].contains(n))
+                        .and_then(|n| SampleCount::try_from(n).ok())
+                        .ok_or_else(|| {
+                            OptionsError::Invalid(format!(
+                                "--msaa expects 1, 2, 4 or 8 samples, got `{value}`"
+                            ))
+                        })?;
+                }
+                "--headless" => options.headless = true,
+                "--second-window" => options.second_window = true,
+                "--transparent" => options.transparent = true,
+                "--clear-color" => {
+                    let value = value()?;
+                    options.clear_color.color = parse_clear_color(&value).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--clear-color expects R,G,B or R,G,B,A, each from 0 to 1, got \
+                             `{value}`"
+                        ))
+                    })?;
+                }
+                "--clear-animate" => options.clear_color.animate = true,
+                "--hdr" => options.hdr = true,
+                #[cfg(windows)]
+                "--exclusive-fullscreen" => options.exclusive_fullscreen = true,
+                #[cfg(not(windows))]
+                "--exclusive-fullscreen" => {
+                    return Err(OptionsError::Invalid(
+                        "--exclusive-fullscreen is only supported on Windows".to_owned(),
+                    ))
+                }
+                "--stats" => options.stats = true,
+                "--mem-stats" => options.mem_stats = true,
+                "--print-caps" => options.print_caps = true,
+                "--list-devices" => options.list_devices = true,
+                "--nbody-bench" => options.nbody_bench = true,
+                "--subgroup-demo" => options.subgroup_demo = true,
+                "--bench-copy" => {
+                    let value = value()?;
+                    options.bench_copy = Some(parse_bytes(&value).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--bench-copy expects a positive number of bytes, like 4096 or 64M, \
+                             got `{value}`"
+                        ))
+                    })?);
+                }
+                "--record-every-frame" => options.record_every_frame = true,
+                "--compute-present" => options.compute_present = true,
+                "--stereo" => options.stereo = true,
+                "--reset-safe-mode" => options.reset_safe_mode = true,
+                "--gpu-culling" => options.gpu_culling = true,
+                "--vertex-pulling" => options.vertex_pulling = true,
+                "--depth-prepass" => options.depth_prepass = true,
+                "--shader-printf" | "--gpu-validation" => {
+                    if options.layer_feature.is_some() {
+                        return Err(OptionsError::Invalid(
+                            "only one of --shader-printf and --gpu-validation may be given"
+                                .to_owned(),
+                        ));
+                    }
+                    options.layer_feature = Some(if flag == "--shader-printf" {
+                        LayerFeature::ShaderPrintf
+                    } else {
+                        LayerFeature::GpuAssisted
+                    });
+                }
+                "--allow-software-renderer" => options.device.allow_software_renderer = true,
+                "--present-mode" => {
+                    let value = value()?;
+                    options.present_mode = match value.as_str() {
+                        "fifo" => PresentMode::Fifo,
+                        "fifo_relaxed" => PresentMode::FifoRelaxed,
+                        "mailbox" => PresentMode::Mailbox,
+                        "immediate" => PresentMode::Immediate,
+                        _ => {
+                            return Err(OptionsError::Invalid(format!(
+                                "unknown present mode `{value}`"
+                            )))
+                        }
+                    };
+                }
+                "--render-scale" | "--logical-size" => {
+                    if render_scale_given {
+                        return Err(OptionsError::Invalid(
+                            "only one of --render-scale and --logical-size may be given".to_owned(),
+                        ));
+                    }
+                    render_scale_given = true;
+                    let value = value()?;
+                    options.render_scale = if flag == "--render-scale" {
+                        let scale = value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|scale| (MIN_RENDER_SCALE..=MAX_RENDER_SCALE).contains(scale))
+                            .ok_or_else(|| {
+                                OptionsError::Invalid(format!(
+                                    "--render-scale expects a number from {MIN_RENDER_SCALE} to \
+                                     {MAX_RENDER_SCALE}, got `{value}`"
+                                ))
+                            })?;
+                        RenderScale::Relative(scale)
+                    } else {
+                        let size = parse_size(&value).ok_or_else(|| {
+                            OptionsError::Invalid(format!(
+                                "--logical-size expects WIDTHxHEIGHT, got `{value}`"
+                            ))
+                        })?;
+                        RenderScale::Fixed(size)
+                    };
+                }
+                "--upscale-filter" => {
+                    let value = value()?;
+                    options.upscale_filter = UpscaleFilter::from_name(&value).ok_or_else(|| {
+                        OptionsError::Invalid(format!("unknown upscale filter `{value}`"))
+                    })?;
+                }
+                "--force-api-version" => {
+                    let value = value()?;
+                    let version = parse_api_version(&value).ok_or_else(|| {
+                        OptionsError::Invalid(format!(
+                            "--force-api-version expects 1.1, 1.2 or 1.3, got `{value}`"
+                        ))
+                    })?;
+                    options.force_api_version = Some(version);
+                }
+                "--gpu" | "--gpu-name" => {
+                    if options.device.preference != DevicePreference::Auto {
+                        return Err(OptionsError::Invalid(
+                            "only one of --gpu and --gpu-name may be given".to_owned(),
+                        ));
+                    }
+                    let value = value()?;
+                    options.device.preference = if flag == "--gpu" {
+                        let index = value.parse().map_err(|_| {
+                            OptionsError::Invalid(format!(
+                                "--gpu expects a device index, got `{value}`"
+                            ))
+                        })?;
+                        DevicePreference::Index(index)
+                    } else {
+                        DevicePreference::Name(value)
+                    };
+                }
+                "-h" | "--help" => return Err(OptionsError::Help),
+                _ => return Err(OptionsError::Invalid(format!("unknown argument `{arg}`"))),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Whether `model` is loaded on `load_threads` threads while the built-in scene stands in for
+    /// it. Benchmarks always load it first, so they time the model rather than the stand-in.
+    pub fn load_in_background(&self) -> bool {
+        self.model.is_some() && self.load_threads > 0 && self.frames.is_none()
+    }
+
+    /// What the scene is built with.
+    pub fn scene_options(&self) -> SceneOptions<'_> {
+        SceneOptions {
+            skybox: self.skybox.as_deref(),
+            environment: self.environment.as_deref(),
+            gpu_culling: self.gpu_culling,
+            vertex_pulling: self.vertex_pulling,
+            depth_prepass: self.depth_prepass,
+            bodies: self.bodies,
+            lights: self.lights,
+        }
+    }
+}
+
+/// Parses a non-zero size like `640x360`.
+fn parse_size(value: &str) -> Option<[u32; 2]> {
+    let (width, height) = value.split_once('x')?;
+    let size = [width.parse().ok()?, height.parse().ok()?];
+    size.iter().all(|&n| n > 0).then_some(size)
+}
+
+/// Parses a non-zero number of bytes, optionally with a `K`, `M` or `G` suffix for KiB, MiB or
+/// GiB.
+fn parse_bytes(value: &str) -> Option<u64> {
+    let (digits, unit) = match value.char_indices().last()? {
+        (at, 'K' | 'k') => (&value[..at], 1 << 10),
+        (at, 'M' | 'm') => (&value[..at], 1 << 20),
+        (at, 'G' | 'g') => (&value[..at], 1 << 30),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(unit)
+        .filter(|&bytes| bytes > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, OptionsError> {
+        Options::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_gives_defaults() {
+        assert_eq!(parse(&[]), Ok(Options::default()));
+    }
+
+    #[test]
+    fn frames_accepts_separate_and_inline_values() {
+        assert_eq!(parse(&["--frames", "10"]).unwrap().frames, Some(10));
+        assert_eq!(parse(&["--frames=10"]).unwrap().frames, Some(10));
+    }
+
+    #[test]
+    fn capture_frame_counts_from_one() {
+        assert_eq!(
+            parse(&["--capture-frame", "500"]).unwrap().capture_frame,
+            Some(500)
+        );
+        assert!(matches!(
+            parse(&["--capture-frame", "0"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn frames_rejects_zero_and_garbage() {
+        assert!(matches!(
+            parse(&["--frames", "0"]),
+            Err(OptionsError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse(&["--frames", "ten"]),
+            Err(OptionsError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse(&["--frames"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn only_one_layer_feature_may_be_given() {
+        assert_eq!(
+            parse(&["--gpu-validation"]).unwrap().layer_feature,
+            Some(LayerFeature::GpuAssisted)
+        );
+        assert!(matches!(
+            parse(&["--shader-printf", "--gpu-validation"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn gpu_flags_pick_a_device_preference() {
+        assert_eq!(
+            parse(&["--gpu", "1"]).unwrap().device.preference,
+            DevicePreference::Index(1)
+        );
+        assert_eq!(
+            parse(&["--gpu-name", "radeon"]).unwrap().device.preference,
+            DevicePreference::Name("radeon".to_owned())
+        );
+        assert!(matches!(
+            parse(&["--gpu", "first"]),
+            Err(OptionsError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse(&["--gpu", "0", "--gpu-name", "radeon"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn frame_latency_must_be_positive() {
+        assert_eq!(
+            parse(&["--frame-latency", "1"]).unwrap().frame_latency,
+            Some(1)
+        );
+        assert!(matches!(
+            parse(&["--frame-latency", "0"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn bodies_must_be_positive() {
+        assert_eq!(parse(&["--bodies", "1024"]).unwrap().bodies, 1024);
+        assert!(matches!(
+            parse(&["--bodies", "0"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn lights_are_configurable() {
+        let lights = parse(&[
+            "--scene",
+            "lights",
+            "--lights=1024",
+            "--light-tile-size",
+            "32",
+            "--max-lights-per-tile",
+            "64",
+            "--naive-lights",
+        ])
+        .unwrap()
+        .lights;
+        assert_eq!(
+            lights,
+            LightsConfig {
+                count: 1024,
+                tile_size: 32,
+                max_per_tile: 64,
+                naive: true,
+            }
+        );
+        for flag in ["--lights", "--light-tile-size", "--max-lights-per-tile"] {
+            assert!(matches!(parse(&[flag, "0"]), Err(OptionsError::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn load_threads_can_be_zero() {
+        assert_eq!(Options::default().load_threads, 1);
+        assert_eq!(parse(&["--load-threads", "0"]).unwrap().load_threads, 0);
+        assert!(matches!(
+            parse(&["--load-threads", "-1"]),
+            Err(OptionsError::Invalid(_))
+        ));
+        assert!(parse(&["--model", "a.glb"]).unwrap().load_in_background());
+        assert!(!parse(&["--model", "a.glb", "--frames", "100"])
+            .unwrap()
+            .load_in_background());
+    }
+
+    #[test]
+    fn record_threads_must_be_positive() {
+        assert_eq!(
+            parse(&["--record-threads", "4"]).unwrap().record_threads,
+            Some(4)
+        );
+        assert!(matches!(
+            parse(&["--record-threads", "0"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn msaa_takes_power_of_two_sample_counts() {
+        assert_eq!(parse(&["--msaa", "4"]).unwrap().msaa, SampleCount::Sample4);
+        assert_eq!(parse(&[]).unwrap().msaa, SampleCount::Sample1);
+        for invalid in ["3", "16", "x"] {
+            assert!(matches!(
+                parse(&["--msaa", invalid]),
+                Err(OptionsError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn swapchain_images_must_be_positive() {
+        assert_eq!(
+            parse(&["--swapchain-images", "3"])
+                .unwrap()
+                .swapchain_images,
+            Some(3)
+        );
+        assert!(matches!(
+            parse(&["--swapchain-images", "0"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn present_mode_by_name() {
+        assert_eq!(
+            parse(&["--present-mode", "immediate"])
+                .unwrap()
+                .present_mode,
+            PresentMode::Immediate
+        );
+        assert!(matches!(
+            parse(&["--present-mode", "vsync"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn render_scale_and_logical_size() {
+        assert_eq!(
+            parse(&["--render-scale", "0.5"]).unwrap().render_scale,
+            RenderScale::Relative(0.5)
+        );
+        assert_eq!(
+            parse(&["--logical-size=640x360"]).unwrap().render_scale,
+            RenderScale::Fixed([640, 360])
+        );
+        for args in [
+            &["--render-scale", "0"][..],
+            &["--render-scale", "3"],
+            &["--logical-size", "640"],
+            &["--logical-size", "0x360"],
+            &["--render-scale", "0.5", "--logical-size", "640x360"],
+        ] {
+            assert!(
+                matches!(parse(args), Err(OptionsError::Invalid(_))),
+                "{args:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn clear_color_and_animation() {
+        let options = parse(&["--clear-color", "0.1,0.1,0.12,1", "--clear-animate"]).unwrap();
+        assert_eq!(
+            options.clear_color,
+            ClearColor {
+                color: [0.1, 0.1, 0.12, 1.0],
+                animate: true,
+            }
+        );
+        assert!(matches!(
+            parse(&["--clear-color", "0.1,0.1,1.2"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn upscale_filter_by_name() {
+        assert_eq!(
+            parse(&["--upscale-filter", "linear"])
+                .unwrap()
+                .upscale_filter,
+            UpscaleFilter::Linear
+        );
+        assert!(matches!(
+            parse(&["--upscale-filter", "bicubic"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn cursor_by_name() {
+        assert_eq!(
+            parse(&["--cursor", "hidden"]).unwrap().cursor,
+            CursorStyle::Hidden
+        );
+        assert!(matches!(
+            parse(&["--cursor", "pointer"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn minimap_layout() {
+        let options = parse(&["--minimap-corner", "top-left", "--minimap-size=120"]).unwrap();
+        assert_eq!(options.minimap.corner, MinimapCorner::TopLeft);
+        assert_eq!(options.minimap.size, 120.0);
+        assert!(!options.minimap.visible);
+        assert!(matches!(
+            parse(&["--minimap-size", "0"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn copy_benchmark_sizes() {
+        assert_eq!(
+            parse(&["--bench-copy", "4096"]).unwrap().bench_copy,
+            Some(4096)
+        );
+        assert_eq!(
+            parse(&["--bench-copy=64M"]).unwrap().bench_copy,
+            Some(64 << 20)
+        );
+        for size in ["0", "G", "1.5G", "-1"] {
+            assert!(matches!(
+                parse(&["--bench-copy", size]),
+                Err(OptionsError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn force_api_version() {
+        assert_eq!(
+            parse(&["--force-api-version", "1.1"])
+                .unwrap()
+                .force_api_version,
+            Some(Version::V1_1)
+        );
+        assert!(matches!(
+            parse(&["--force-api-version", "1.4"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn scene_by_name() {
+        assert_eq!(
+            parse(&["--scene", "plasma"]).unwrap().scene,
+            SceneKind::Plasma
+        );
+        assert!(matches!(
+            parse(&["--scene", "teapot"]),
+            Err(OptionsError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        assert!(matches!(parse(&["--fast"]), Err(OptionsError::Invalid(_))));
+    }
+}
@@ -0,0 +1,183 @@
+//! Finding what is under the cursor by casting a ray into the scene and intersecting it with the
+//! objects' bounding boxes on the CPU.
+
+use glam::{Mat4, Vec3};
+
+/// A half-line in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Normalized.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// The ray through the centre of `pixel` of a view `extent` pixels in size, drawn with
+    /// `view` and `projection`. Pixels count from the top left, like window coordinates do.
+    ///
+    /// The ray starts on the near plane, so it works for orthographic projections too.
+    pub fn through_pixel(pixel: [f32; 2], extent: [u32; 2], view: Mat4, projection: Mat4) -> Self {
+        // Our projections flip Y, so clip space Y points down just like pixel rows do.
+        let ndc_x = (pixel[0] + 0.5) / extent[0].max(1) as f32 * 2.0 - 1.0;
+        let ndc_y = (pixel[1] + 0.5) / extent[1].max(1) as f32 * 2.0 - 1.0;
+        let to_world = (projection * view).inverse();
+        let near = to_world.project_point3(Vec3::new(ndc_x, ndc_y, 0.0));
+        let far = to_world.project_point3(Vec3::new(ndc_x, ndc_y, 1.0));
+        Self {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+}
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// The box around `points`, or `None` if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        points.into_iter().fold(None, |aabb, point| {
+            Some(match aabb {
+                None => Self {
+                    min: point,
+                    max: point,
+                },
+                Some(Self { min, max }) => Self {
+                    min: min.min(point),
+                    max: max.max(point),
+                },
+            })
+        })
+    }
+
+    /// The smallest box containing both.
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// The centre and radius of the sphere through the corners, for framing the box.
+    pub fn bounding_sphere(&self) -> (Vec3, f32) {
+        (
+            (self.min + self.max) / 2.0,
+            (self.max - self.min).length() / 2.0,
+        )
+    }
+
+    /// The axis-aligned box around this one after `transform`.
+    pub fn transformed(&self, transform: Mat4) -> Self {
+        let corners = (0..8).map(|corner| {
+            let pick = |bit: usize, axis: usize| {
+                if corner & bit == 0 {
+                    self.min[axis]
+                } else {
+                    self.max[axis]
+                }
+            };
+            transform.transform_point3(Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2)))
+        });
+        Self::from_points(corners).unwrap()
+    }
+
+    /// How far along `ray` it first enters the box, or `None` if it misses. A ray starting
+    /// inside the box hits it at distance zero.
+    pub fn intersect(&self, ray: &Ray) -> Option<f32> {
+        // The slab method: intersect the ranges in which the ray is between each pair of planes.
+        // Dividing by a zero direction gives infinities, which work out as long as the origin
+        // isn't exactly on one of the planes.
+        let inverse = ray.direction.recip();
+        let to_min = (self.min - ray.origin) * inverse;
+        let to_max = (self.max - ray.origin) * inverse;
+        let enter = to_min.min(to_max).max_element().max(0.0);
+        let exit = to_min.max(to_max).min_element();
+        (enter <= exit).then_some(enter)
+    }
+}
+
+/// The index of the box `ray` hits first, if any.
+pub fn closest_hit(ray: &Ray, boxes: impl IntoIterator<Item = Aabb>) -> Option<usize> {
+    boxes
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, aabb)| aabb.intersect(ray).map(|distance| (index, distance)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{orthographic, perspective};
+
+    fn unit_box(center: Vec3) -> Aabb {
+        Aabb {
+            min: center - 0.5,
+            max: center + 0.5,
+        }
+    }
+
+    #[test]
+    fn the_centre_pixel_looks_where_the_camera_does() {
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+        let projection = perspective(1.0, 4.0 / 3.0, 0.1, 100.0);
+        // An odd extent has a pixel whose centre is the centre of the view.
+        let ray = Ray::through_pixel([320.0, 240.0], [641, 481], view, projection);
+        assert!(ray.direction.distance(-Vec3::Z) < 1e-4);
+        assert!((ray.origin - Vec3::new(0.0, 0.0, 4.9)).length() < 1e-3);
+    }
+
+    #[test]
+    fn pixel_rows_count_down() {
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 5.0), Vec3::ZERO, Vec3::Y);
+        let projection = orthographic(2.0, 1.0, 0.0, 10.0);
+        let top_left = Ray::through_pixel([0.0, 0.0], [100, 100], view, projection);
+        assert!(top_left.origin.x < -1.9 && top_left.origin.y > 1.9);
+        assert!(top_left.direction.distance(-Vec3::Z) < 1e-4);
+    }
+
+    #[test]
+    fn rays_hit_boxes_in_front_of_them() {
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 5.0),
+            direction: -Vec3::Z,
+        };
+        assert_eq!(unit_box(Vec3::ZERO).intersect(&ray), Some(4.5));
+        assert_eq!(unit_box(Vec3::new(2.0, 0.0, 0.0)).intersect(&ray), None);
+        assert_eq!(unit_box(Vec3::new(0.0, 0.0, 6.0)).intersect(&ray), None);
+        assert_eq!(
+            unit_box(Vec3::new(0.0, 0.0, 5.2)).intersect(&ray),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn the_closest_box_wins() {
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 5.0),
+            direction: -Vec3::Z,
+        };
+        let boxes = [
+            unit_box(Vec3::new(0.0, 0.0, -3.0)),
+            unit_box(Vec3::new(3.0, 0.0, 0.0)),
+            unit_box(Vec3::new(0.0, 0.0, 1.0)),
+        ];
+        assert_eq!(closest_hit(&ray, boxes), Some(2));
+        assert_eq!(closest_hit(&ray, [boxes[1]]), None);
+    }
+
+    #[test]
+    fn transformed_boxes_contain_the_transformed_corners() {
+        let aabb = unit_box(Vec3::ZERO).transformed(
+            Mat4::from_translation(Vec3::X) * Mat4::from_rotation_y(45.0_f32.to_radians()),
+        );
+        let half_diagonal = 0.5 * 2.0_f32.sqrt();
+        assert!((aabb.max - Vec3::new(1.0 + half_diagonal, 0.5, half_diagonal)).length() < 1e-5);
+        assert!((aabb.min - Vec3::new(1.0 - half_diagonal, -0.5, -half_diagonal)).length() < 1e-5);
+    }
+}
@@ -0,0 +1,281 @@
+//! The render pass shared by every target we draw into, plus the depth buffer and, when
+//! multisampling, the colour image its framebuffers share.
+//!
+//! Nothing here or in the renderer tracks image layouts. The command buffers record through
+//! vulkano's `AutoCommandBufferBuilder`, which knows the layout each image is left in, by this
+//! pass, a compute frame or a screenshot copy, and inserts only the barriers the next use needs.
+
+use std::sync::Arc;
+
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::view::ImageView;
+use vulkano::image::{
+    Image, ImageAspects, ImageCreateInfo, ImageType, ImageUsage, SampleCount, SampleCounts,
+};
+use vulkano::memory::allocator::AllocationCreateInfo;
+use vulkano::render_pass::{AttachmentLoadOp, Framebuffer, FramebufferCreateInfo, RenderPass};
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+
+/// `D16_UNORM` is the one depth format every implementation must support as an attachment.
+pub const DEPTH_FORMAT: Format = Format::D16_UNORM;
+
+/// Creates a single-subpass render pass with one colour attachment of `color_format` and a
+/// depth attachment, both cleared at the start of the pass.
+///
+/// With more than one sample, the scene is drawn into a multisampled colour attachment and
+/// depth attachment of `samples` samples each, and the colour is resolved into the first
+/// attachment, the only one that is stored. Either way, that first attachment is the image the
+/// framebuffer is created for.
+pub fn create_render_pass(
+    device: Arc<Device>,
+    color_format: Format,
+    samples: SampleCount,
+) -> Result<Arc<RenderPass>, RendererError> {
+    let render_pass = if samples == SampleCount::Sample1 {
+        vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: color_format,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    // Nothing reads depth after the pass, so let the driver throw it away.
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {depth},
+            },
+        )?
+    } else {
+        vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    format: color_format,
+                    samples: 1,
+                    // Every pixel is written by the resolve.
+                    load_op: DontCare,
+                    store_op: Store,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: samples as u32,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+                // Only needed until it's resolved, so it never has to leave tile memory.
+                msaa_color: {
+                    format: color_format,
+                    samples: samples as u32,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [msaa_color],
+                color_resolve: [color],
+                depth_stencil: {depth},
+            },
+        )?
+    };
+    Ok(render_pass)
+}
+
+/// The most samples up to `requested` that the device supports for colour and depth attachments
+/// alike. Every device supports a single sample.
+pub fn supported_samples(requested: SampleCount, supported: SampleCounts) -> SampleCount {
+    [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .filter(|&samples| samples as u32 <= requested as u32)
+    .find(|&samples| supported.contains_enum(samples))
+    .unwrap_or(SampleCount::Sample1)
+}
+
+/// How many samples `render_pass` draws with.
+pub fn render_pass_samples(render_pass: &RenderPass) -> SampleCount {
+    render_pass
+        .attachments()
+        .iter()
+        .map(|attachment| attachment.samples)
+        .max_by_key(|&samples| samples as u32)
+        .unwrap_or(SampleCount::Sample1)
+}
+
+/// What to clear the attachments of `render_pass` to at the start of the pass: colour
+/// attachments to `color` and depth to the far plane.
+pub fn clear_values(render_pass: &RenderPass, color: [f32; 4]) -> Vec<Option<ClearValue>> {
+    render_pass
+        .attachments()
+        .iter()
+        .map(|attachment| {
+            (attachment.load_op == AttachmentLoadOp::Clear).then(|| {
+                if attachment.format.aspects().intersects(ImageAspects::DEPTH) {
+                    1.0.into()
+                } else {
+                    color.into()
+                }
+            })
+        })
+        .collect()
+}
+
+/// The attachments every framebuffer of a size shares: the depth buffer and, when
+/// multisampling, the colour image that is resolved from. Frames are submitted to a single queue
+/// in order, so they never use them at the same time.
+#[derive(Clone)]
+pub struct SharedAttachments {
+    depth: Arc<ImageView>,
+    msaa_color: Option<Arc<ImageView>>,
+}
+
+impl SharedAttachments {
+    /// Creates transient attachments of `extent` with as many samples as `render_pass` draws
+    /// with, named after `label` in captures.
+    pub fn new(
+        ctx: &VulkanContext,
+        render_pass: &RenderPass,
+        extent: [u32; 2],
+        label: &str,
+    ) -> Result<Self, RendererError> {
+        let samples = render_pass_samples(render_pass);
+        let transient_image = |format, usage| {
+            let image = Image::new(
+                ctx.memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent: [extent[0], extent[1], 1],
+                    samples,
+                    usage: usage | ImageUsage::TRANSIENT_ATTACHMENT,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )?;
+            ctx.memory_tracker
+                .track_image(MemoryCategory::RenderTarget, &image);
+            Ok::<_, RendererError>(image)
+        };
+
+        let depth = transient_image(DEPTH_FORMAT, ImageUsage::DEPTH_STENCIL_ATTACHMENT)?;
+        ctx.name_object(&depth, &format!("{label} depth buffer"));
+        let msaa_color = if samples == SampleCount::Sample1 {
+            None
+        } else {
+            let color_format = render_pass.attachments()[0].format;
+            let image = transient_image(color_format, ImageUsage::COLOR_ATTACHMENT)?;
+            ctx.name_object(&image, &format!("{label} multisampled colour"));
+            Some(ImageView::new_default(image)?)
+        };
+        Ok(Self {
+            depth: ImageView::new_default(depth)?,
+            msaa_color,
+        })
+    }
+}
+
+/// Creates a framebuffer for `render_pass` that draws into (or resolves into) `color`, using
+/// `shared` for the rest.
+///
+/// Fails if any attachment's sample count differs from what the render pass declares for it,
+/// which would otherwise only show up as a validation error when the framebuffer is created.
+pub fn create_framebuffer(
+    render_pass: Arc<RenderPass>,
+    color: Arc<ImageView>,
+    shared: &SharedAttachments,
+) -> Result<Arc<Framebuffer>, RendererError> {
+    let attachments: Vec<_> = [color, shared.depth.clone()]
+        .into_iter()
+        .chain(shared.msaa_color.clone())
+        .collect();
+    let samples: Vec<_> = attachments
+        .iter()
+        .map(|view| view.image().samples())
+        .collect();
+    let expected: Vec<_> = render_pass
+        .attachments()
+        .iter()
+        .map(|attachment| attachment.samples)
+        .collect();
+    check_samples(&expected, &samples)?;
+    Ok(Framebuffer::new(
+        render_pass,
+        FramebufferCreateInfo {
+            attachments,
+            ..Default::default()
+        },
+    )?)
+}
+
+fn check_samples(expected: &[SampleCount], actual: &[SampleCount]) -> Result<(), RendererError> {
+    if expected.len() != actual.len() {
+        return Err(RendererError::InvalidAttachments(format!(
+            "the render pass has {} attachments, but {} were given",
+            expected.len(),
+            actual.len()
+        )));
+    }
+    match expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+    {
+        Some(index) => Err(RendererError::InvalidAttachments(format!(
+            "attachment {index} has {} samples where the render pass expects {}",
+            actual[index] as u32, expected[index] as u32
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_fall_back_to_what_is_supported() {
+        let supported = SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_2 | SampleCounts::SAMPLE_4;
+        assert_eq!(
+            supported_samples(SampleCount::Sample4, supported),
+            SampleCount::Sample4
+        );
+        assert_eq!(
+            supported_samples(SampleCount::Sample8, supported),
+            SampleCount::Sample4
+        );
+        assert_eq!(
+            supported_samples(SampleCount::Sample1, supported),
+            SampleCount::Sample1
+        );
+        assert_eq!(
+            supported_samples(SampleCount::Sample8, SampleCounts::SAMPLE_1),
+            SampleCount::Sample1
+        );
+    }
+
+    #[test]
+    fn mismatched_samples_are_caught() {
+        use SampleCount::{Sample1, Sample4};
+        assert!(check_samples(&[Sample1, Sample4, Sample4], &[Sample1, Sample4, Sample4]).is_ok());
+        assert!(check_samples(&[Sample1, Sample4, Sample4], &[Sample1, Sample1, Sample4]).is_err());
+        assert!(check_samples(&[Sample1, Sample1], &[Sample1, Sample1, Sample4]).is_err());
+    }
+}
@@ -0,0 +1,734 @@
+//! The render thread, which owns the renderer and draws frames as fast as the swapchains let it,
+//! while the event loop on the main thread only forwards what happens to the windows.
+//!
+//! Keeping the two apart means rendering carries on while the event loop is stuck, e.g. in the
+//! modal loop Windows runs for as long as a window's title bar is being dragged. The event loop
+//! sends [`RenderMessage`]s over a channel, picked up between frames, and gets [`RenderEvent`]s
+//! back as user events. Windows are created and destroyed on the main thread, so the render
+//! thread reports when it has let go of one.
+//!
+//! Resizes are the exception to the event loop never waiting: some platforms only resize
+//! smoothly if the new size is drawn before the event returns, so [`RenderThread::resize`] waits
+//! for that frame, for a little while at most.
+
+use std::ops::ControlFlow;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use winit::dpi::PhysicalPosition;
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+use winit::event_loop::EventLoopProxy;
+use winit::window::{CursorGrabMode, Window, WindowId};
+
+use crate::benchmark::Benchmark;
+use crate::camera::{Camera, OrbitCamera};
+use crate::crash_report;
+use crate::options::Options;
+use crate::renderdoc::FrameCapture;
+use crate::renderer::Renderer;
+use crate::safe_mode::Sentinel;
+use crate::scene::FrameData;
+use crate::screenshot::screenshot_path;
+use crate::upscale::RenderScale;
+use crate::window_config::CursorStyle;
+use crate::window_context::WindowContext;
+
+/// Key that toggles capturing the mouse for looking around.
+const CURSOR_GRAB_KEY: VirtualKeyCode = VirtualKeyCode::G;
+
+/// Key that splits the window in two, with a fixed orbit camera on the right.
+const SPLIT_SCREEN_KEY: VirtualKeyCode = VirtualKeyCode::V;
+
+/// Key that switches the camera between flying and orbiting.
+const ORBIT_KEY: VirtualKeyCode = VirtualKeyCode::C;
+
+/// Key that moves the camera back until the whole scene is in view.
+const FRAME_SCENE_KEY: VirtualKeyCode = VirtualKeyCode::F;
+
+/// A left click that moves the cursor further than this many pixels is a drag, not a selection.
+const CLICK_SLOP: f64 = 4.0;
+
+/// Scroll distance reported in pixels that counts as one wheel step.
+const PIXELS_PER_SCROLL_STEP: f64 = 40.0;
+
+/// Key that toggles borderless fullscreen, exclusive with `--exclusive-fullscreen` on Windows.
+const FULLSCREEN_KEY: VirtualKeyCode = VirtualKeyCode::F11;
+
+/// Key that moves rendering to the next GPU.
+const NEXT_DEVICE_KEY: VirtualKeyCode = VirtualKeyCode::Tab;
+
+/// Keys that halve and double the render scale.
+const RENDER_SCALE_DOWN_KEY: VirtualKeyCode = VirtualKeyCode::Minus;
+const RENDER_SCALE_UP_KEY: VirtualKeyCode = VirtualKeyCode::Equals;
+
+/// Key that shows and hides the most recent log messages over the scene.
+const CONSOLE_KEY: VirtualKeyCode = VirtualKeyCode::Grave;
+
+/// Captures the next frame with RenderDoc, when running under it.
+const CAPTURE_KEY: VirtualKeyCode = VirtualKeyCode::Home;
+
+/// Logs how much memory the renderer's resources use right now, by category.
+const MEMORY_REPORT_KEY: VirtualKeyCode = VirtualKeyCode::M;
+
+/// Key that shows and hides the minimap in the corner.
+const MINIMAP_KEY: VirtualKeyCode = VirtualKeyCode::K;
+
+/// How many times a session rebuilds the renderer after losing the device before giving up.
+/// A device that keeps getting lost is more likely hitting a bug than a driver reset.
+const MAX_DEVICE_RECOVERIES: u32 = 3;
+
+/// How often the stats in the title bar are refreshed.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The longest the event loop waits for a frame at a window's new size. Past that, the resize
+/// goes on without one rather than feeling sluggish.
+const RESIZE_FRAME_BUDGET: Duration = Duration::from_millis(50);
+
+/// How long to wait for a message when no window could be drawn into, e.g. because they're all
+/// minimized, rather than trying again straight away.
+const IDLE_WAIT: Duration = Duration::from_millis(10);
+
+/// What the event loop tells the render thread.
+#[derive(Debug)]
+pub enum RenderMessage {
+    /// Input to a window, or it gaining or losing focus.
+    WindowEvent(WindowId, WindowEvent<'static>),
+    /// The mouse moved by this much, as reported by the device rather than the cursor, so looking
+    /// around keeps working while the cursor is locked in place.
+    MouseMotion((f64, f64)),
+    /// The window's size in physical pixels changed, so its swapchain has to be recreated.
+    /// `drawn` is signalled once the next frame has been drawn.
+    Resized {
+        window: WindowId,
+        drawn: SyncSender<()>,
+    },
+    /// Save the next frame shown in the window to a PNG file.
+    Screenshot(WindowId),
+    /// Stop drawing into the window. Answered with [`RenderEvent::WindowClosed`].
+    CloseWindow(WindowId),
+    /// Stop rendering altogether. Answered with [`RenderEvent::Exited`].
+    Shutdown,
+}
+
+/// What the render thread tells the event loop.
+#[derive(Debug)]
+pub enum RenderEvent {
+    /// New text for the window's title bar: frame rate, timings and memory use.
+    Stats { window: WindowId, title: String },
+    /// The renderer has let go of the window, so it can be destroyed.
+    WindowClosed(WindowId),
+    /// The render thread is done and the GPU idle, so every window can be destroyed. Sent however
+    /// the thread ends, panics included.
+    Exited,
+}
+
+/// The event loop's handle on the render thread.
+pub struct RenderThread {
+    messages: Sender<RenderMessage>,
+    thread: JoinHandle<()>,
+}
+
+impl RenderThread {
+    /// Starts rendering into `windows`, the first of which picks the device, each through its
+    /// camera. `sentinel` is disarmed once a frame has been drawn, when the thread ends without
+    /// having drawn one, or when the renderer can't be created.
+    ///
+    /// # Panics
+    ///
+    /// The render thread panics if the renderer can't be created or a frame can't be drawn. The
+    /// panic is passed on by [`join`](Self::join).
+    pub fn spawn(
+        windows: Vec<(Arc<Window>, Camera)>,
+        options: Options,
+        mut sentinel: Option<Sentinel>,
+        events: EventLoopProxy<RenderEvent>,
+    ) -> Self {
+        let (messages, receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("render".to_owned())
+            .spawn(move || {
+                let _exited = ExitNotifier(events.clone());
+                let mut windows = windows.into_iter();
+                let (window, camera) = windows.next().expect("no window to render into");
+                let mut renderer = Renderer::new(window, camera, options.scene, &options)
+                    .inspect_err(|_| clean_failure(&mut sentinel))
+                    .expect("Failed to create renderer");
+                for (window, camera) in windows {
+                    renderer
+                        .add_window(window, camera)
+                        .inspect_err(|_| clean_failure(&mut sentinel))
+                        .expect("Failed to open the second window");
+                }
+                RenderLoop::new(renderer, &options, sentinel, events).run(receiver);
+            })
+            .expect("Failed to start the render thread");
+        Self { messages, thread }
+    }
+
+    /// Passes `message` on. Messages sent after the thread has ended are dropped; it has already
+    /// sent [`RenderEvent::Exited`] then.
+    pub fn send(&self, message: RenderMessage) {
+        let _ = self.messages.send(message);
+    }
+
+    /// Has the window's swapchain recreated at its new size, and waits until a frame has been
+    /// drawn into it or [`RESIZE_FRAME_BUDGET`] is up.
+    pub fn resize(&self, window: WindowId) {
+        let (drawn, wait) = mpsc::sync_channel(1);
+        self.send(RenderMessage::Resized { window, drawn });
+        // Timing out only means the new size shows up a frame later.
+        let _ = wait.recv_timeout(RESIZE_FRAME_BUDGET);
+    }
+
+    /// Waits for the thread to end, which it does by itself after sending
+    /// [`RenderEvent::Exited`], and carries on its panic if it panicked.
+    pub fn join(self) {
+        drop(self.messages);
+        if let Err(panic) = self.thread.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+/// Disarms `sentinel` before the render thread gives up on an error Vulkan returned: failing
+/// that way isn't the crash or hang safe mode is for.
+fn clean_failure(sentinel: &mut Option<Sentinel>) {
+    if let Some(sentinel) = sentinel.take() {
+        sentinel.disarm();
+    }
+}
+
+/// Sends [`RenderEvent::Exited`] when the render thread ends, normally or by unwinding.
+struct ExitNotifier(EventLoopProxy<RenderEvent>);
+
+impl Drop for ExitNotifier {
+    fn drop(&mut self) {
+        // The event loop only goes away after receiving this, so it's there to receive it.
+        let _ = self.0.send_event(RenderEvent::Exited);
+    }
+}
+
+/// Shows frame rate and, with `--mem-stats`, memory use in the title bar, standing in for an
+/// on-screen overlay.
+struct StatsLine {
+    frames: u32,
+    since: Instant,
+    /// Whether memory use is shown too, and reported on exit.
+    memory: bool,
+}
+
+impl StatsLine {
+    fn new(memory: bool) -> Self {
+        Self {
+            frames: 0,
+            since: Instant::now(),
+            memory,
+        }
+    }
+
+    /// Counts a frame, and every [`STATS_INTERVAL`] returns a new title for the window to show
+    /// the stats in.
+    fn frame_rendered(&mut self, renderer: &Renderer) -> Option<(WindowId, String)> {
+        self.frames += 1;
+        let elapsed = self.since.elapsed();
+        if elapsed < STATS_INTERVAL {
+            return None;
+        }
+
+        let memory = self.memory.then(|| {
+            let report = renderer.context().memory_tracker.report();
+            report.warn_if_near_budget();
+            format!(" - {}", report.summary())
+        });
+        let fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.frames = 0;
+        self.since = Instant::now();
+
+        // The stats are the same for every window, so they only go in the first one's title.
+        let window = renderer.windows().next()?;
+        let latency = window
+            .present_latency()
+            .map(|latency| format!(" - {:.1} ms to screen", latency.as_secs_f64() * 1000.0))
+            .unwrap_or_default();
+        let culling = window
+            .cull_stats()
+            .map(|stats| format!(" - {} drawn, {} culled", stats.drawn, stats.culled))
+            .unwrap_or_default();
+        let batching = window
+            .batch_stats()
+            .map(|stats| {
+                format!(
+                    " - {} draws, {} pipeline, {} material and {} buffer binds",
+                    stats.draws, stats.pipeline_binds, stats.material_binds, stats.buffer_binds
+                )
+            })
+            .unwrap_or_default();
+        let title = format!(
+            "hi-vulkanos - {fps:.0} fps{latency} - {:.3} ms recording{culling}{batching} - {} images, {:?}{}",
+            window.record_time().as_secs_f64() * 1000.0,
+            window.swapchain_image_count(),
+            window.present_mode(),
+            memory.unwrap_or_default()
+        );
+        Some((window.window().id(), title))
+    }
+}
+
+/// A mouse button held down over a window.
+struct MouseDrag {
+    window: WindowId,
+    button: MouseButton,
+    /// How far the cursor has travelled since the button went down, in pixels.
+    moved: f64,
+    /// Whether the drag paints onto the scene rather than moving the camera.
+    paints: bool,
+}
+
+/// Orbits or pans the window's camera by a cursor movement, if it is an orbit camera.
+fn drag_camera(window: &mut WindowContext, button: MouseButton, delta: (f64, f64)) {
+    let height = window.window().inner_size().height;
+    let Some(camera) = window.camera.as_orbit_mut() else {
+        return;
+    };
+    match button {
+        MouseButton::Left => camera.rotate(delta.0, delta.1),
+        MouseButton::Middle => camera.pan(delta.0, delta.1, height),
+        _ => (),
+    }
+}
+
+/// Selects what is under the cursor, or in the middle of the window while the cursor is captured
+/// and hidden.
+fn select_at_cursor(
+    renderer: &mut Renderer,
+    id: WindowId,
+    cursor: Option<PhysicalPosition<f64>>,
+    captured: bool,
+) {
+    let Some(window) = renderer.window(id) else {
+        return;
+    };
+    let size = window.window().inner_size();
+    let position = if captured {
+        PhysicalPosition::new(size.width as f64 / 2.0, size.height as f64 / 2.0)
+    } else if let Some(cursor) = cursor {
+        cursor
+    } else {
+        return;
+    };
+    match renderer.select_at(id, position) {
+        Some(object) => log::info!("Selected object {object}"),
+        None => log::info!("Selection cleared"),
+    }
+}
+
+/// Hides the cursor and locks it to the window, or gives it back looking like `cursor`.
+fn set_cursor_captured(window: &Window, captured: bool, cursor: CursorStyle) {
+    if captured {
+        // `Locked` keeps the cursor in place, which is what we want, but only some platforms
+        // support it. `Confined` at least stops it leaving the window.
+        let grabbed = window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+        if let Err(err) = grabbed {
+            log::warn!("Could not grab the cursor: {err}");
+        }
+    } else {
+        let _ = window.set_cursor_grab(CursorGrabMode::None);
+    }
+    window.set_cursor_visible(!captured && cursor.is_visible());
+}
+
+/// The render thread's state: the renderer, and what the input has done to it so far.
+struct RenderLoop {
+    renderer: Renderer,
+    benchmark: Option<Benchmark>,
+    stats_line: Option<StatsLine>,
+    capture: FrameCapture,
+    events: EventLoopProxy<RenderEvent>,
+    /// The window that has grabbed the cursor, if any. Mouse motion steers its camera.
+    captured_window: Option<WindowId>,
+    /// What the cursor looks like while it isn't captured.
+    cursor_style: CursorStyle,
+    /// Where the cursor is, in physical pixels, and over which window.
+    cursor: Option<(WindowId, PhysicalPosition<f64>)>,
+    drag: Option<MouseDrag>,
+    /// Who to tell that the next frame has been drawn, after resizes.
+    resizes_waiting: Vec<SyncSender<()>>,
+    /// How many times the renderer has been rebuilt after losing the device.
+    device_recoveries: u32,
+    /// Disarmed after the first frame, or on exit if there wasn't one, see
+    /// [`safe_mode`](crate::safe_mode).
+    sentinel: Option<Sentinel>,
+    start: Instant,
+    last_frame: Instant,
+}
+
+impl RenderLoop {
+    fn new(
+        renderer: Renderer,
+        options: &Options,
+        sentinel: Option<Sentinel>,
+        events: EventLoopProxy<RenderEvent>,
+    ) -> Self {
+        let start = Instant::now();
+        Self {
+            renderer,
+            benchmark: options.frames.map(Benchmark::new),
+            stats_line: (options.stats || options.mem_stats)
+                .then(|| StatsLine::new(options.mem_stats)),
+            capture: FrameCapture::new(options.capture_frame),
+            events,
+            captured_window: None,
+            cursor_style: options.cursor,
+            cursor: None,
+            drag: None,
+            resizes_waiting: Vec::new(),
+            device_recoveries: 0,
+            sentinel,
+            start,
+            last_frame: start,
+        }
+    }
+
+    /// Draws frames until told to stop, the last window is closed or the benchmark is over, then
+    /// drops the renderer, which waits for the GPU to finish every frame in flight.
+    fn run(mut self, messages: Receiver<RenderMessage>) {
+        'frames: loop {
+            loop {
+                let message = match messages.try_recv() {
+                    Ok(message) => message,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break 'frames,
+                };
+                if self.handle(message).is_break() {
+                    break 'frames;
+                }
+            }
+
+            match self.render_frame() {
+                ControlFlow::Break(()) => break,
+                ControlFlow::Continue(true) => (),
+                ControlFlow::Continue(false) => match messages.recv_timeout(IDLE_WAIT) {
+                    Ok(message) => {
+                        if self.handle(message).is_break() {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+            }
+        }
+
+        // Closing the window before the first frame is drawn is no reason to start in safe mode.
+        if let Some(sentinel) = self.sentinel.take() {
+            sentinel.disarm();
+        }
+        if self.stats_line.as_ref().is_some_and(|stats| stats.memory) {
+            let report = self.renderer.context().memory_tracker.report();
+            report.warn_if_near_budget();
+            println!("{report}");
+        }
+    }
+
+    fn handle(&mut self, message: RenderMessage) -> ControlFlow<()> {
+        match message {
+            RenderMessage::WindowEvent(id, event) => self.window_event(id, event),
+            RenderMessage::MouseMotion(delta) => {
+                let camera = self
+                    .captured_window
+                    .and_then(|id| self.renderer.window_mut(id))
+                    .and_then(|window| window.camera.as_fly_mut());
+                if let Some(camera) = camera {
+                    camera.process_mouse_motion(delta.0, delta.1);
+                }
+            }
+            // Every message is handled before the next frame, so a burst of resizes only
+            // recreates the swapchain once, at the latest size.
+            RenderMessage::Resized { window, drawn } => {
+                if let Some(window) = self.renderer.window_mut(window) {
+                    window.resize();
+                }
+                self.resizes_waiting.push(drawn);
+            }
+            RenderMessage::Screenshot(id) => {
+                if let Some(window) = self.renderer.window_mut(id) {
+                    window.request_screenshot(screenshot_path());
+                }
+            }
+            RenderMessage::CloseWindow(id) => {
+                if self.captured_window == Some(id) {
+                    self.captured_window = None;
+                }
+                let windows_left = self.renderer.remove_window(id);
+                let _ = self.events.send_event(RenderEvent::WindowClosed(id));
+                if windows_left == 0 {
+                    return ControlFlow::Break(());
+                }
+            }
+            RenderMessage::Shutdown => return ControlFlow::Break(()),
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn window_event(&mut self, id: WindowId, event: WindowEvent<'static>) {
+        match event {
+            WindowEvent::Focused(false) => {
+                let Some(window) = self.renderer.window_mut(id) else {
+                    return;
+                };
+                // We won't see key releases while unfocused, so don't keep flying.
+                if let Some(camera) = window.camera.as_fly_mut() {
+                    camera.release_keys();
+                }
+                if self.captured_window == Some(id) {
+                    self.captured_window = None;
+                    set_cursor_captured(window.window(), false, self.cursor_style);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => self.key(id, key, state),
+            WindowEvent::CursorMoved { position, .. } => {
+                let last = self
+                    .cursor
+                    .filter(|(window, _)| *window == id)
+                    .map(|(_, last)| last);
+                let drag = self.drag.as_mut().filter(|drag| drag.window == id);
+                if let (Some(last), Some(drag)) = (last, drag) {
+                    let delta = (position.x - last.x, position.y - last.y);
+                    drag.moved += delta.0.hypot(delta.1);
+                    if drag.paints {
+                        self.renderer.paint(id, last, position);
+                    } else if let Some(window) = self.renderer.window_mut(id) {
+                        drag_camera(window, drag.button, delta);
+                    }
+                }
+                self.cursor = Some((id, position));
+            }
+            WindowEvent::CursorLeft { .. }
+                if self.cursor.is_some_and(|(window, _)| window == id) =>
+            {
+                self.cursor = None
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => {
+                    let position = self
+                        .cursor
+                        .filter(|(window, _)| *window == id)
+                        .map(|(_, position)| position);
+                    // A captured cursor isn't over anything in particular, so it doesn't paint.
+                    let paints = button == MouseButton::Left
+                        && self.captured_window != Some(id)
+                        && position
+                            .is_some_and(|position| self.renderer.paint(id, position, position));
+                    self.drag = Some(MouseDrag {
+                        window: id,
+                        button,
+                        moved: 0.0,
+                        paints,
+                    })
+                }
+                ElementState::Released => {
+                    let Some(released) = self.drag.take_if(|drag| drag.button == button) else {
+                        return;
+                    };
+                    if button == MouseButton::Left
+                        && released.moved < CLICK_SLOP
+                        && !released.paints
+                    {
+                        let position = self
+                            .cursor
+                            .filter(|(window, _)| *window == id)
+                            .map(|(_, position)| position);
+                        let captured = self.captured_window == Some(id);
+                        select_at_cursor(&mut self.renderer, id, position, captured);
+                    }
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, lines) => lines,
+                    MouseScrollDelta::PixelDelta(pixels) => {
+                        (pixels.y / PIXELS_PER_SCROLL_STEP) as f32
+                    }
+                };
+                let camera = self
+                    .renderer
+                    .window_mut(id)
+                    .and_then(|window| window.camera.as_orbit_mut());
+                if let Some(camera) = camera {
+                    camera.zoom(steps);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn key(&mut self, id: WindowId, key: VirtualKeyCode, state: ElementState) {
+        let pressed = state == ElementState::Pressed;
+        if key == NEXT_DEVICE_KEY && pressed {
+            // Rebuilding everything takes a while, so the next frame's delta would be huge.
+            match self.renderer.switch_to_next_device() {
+                Ok(true) => self.last_frame = Instant::now(),
+                Ok(false) => log::info!("There is no other device to switch to"),
+                Err(err) => panic!("Failed to switch devices: {err}"),
+            }
+            return;
+        }
+        if key == CONSOLE_KEY && pressed {
+            self.renderer.toggle_console();
+            return;
+        }
+        if key == CAPTURE_KEY && pressed {
+            self.capture.request();
+            return;
+        }
+        if key == MEMORY_REPORT_KEY && pressed {
+            let report = self.renderer.context().memory_tracker.report();
+            report.warn_if_near_budget();
+            log::info!("{report}");
+            return;
+        }
+        if pressed && self.renderer.scene_key_pressed(key) {
+            return;
+        }
+        let scene_bounds = self.renderer.scene_bounds();
+        let Some(window) = self.renderer.window_mut(id) else {
+            return;
+        };
+        if key == CURSOR_GRAB_KEY && pressed {
+            let captured = self.captured_window != Some(id);
+            self.captured_window = captured.then_some(id);
+            set_cursor_captured(window.window(), captured, self.cursor_style);
+        } else if key == FULLSCREEN_KEY && pressed {
+            window.toggle_fullscreen();
+        } else if key == ORBIT_KEY && pressed {
+            window.camera.toggle_orbit();
+        } else if key == FRAME_SCENE_KEY && pressed {
+            if let Some(bounds) = scene_bounds {
+                let size = window.window().inner_size();
+                let (center, radius) = bounds.bounding_sphere();
+                let aspect_ratio = size.width as f32 / size.height.max(1) as f32;
+                window.camera.frame(center, radius, aspect_ratio);
+            }
+        } else if key == SPLIT_SCREEN_KEY && pressed {
+            window.split_camera = match window.split_camera {
+                Some(_) => None,
+                None => Some(Camera::Orbit(OrbitCamera::default())),
+            };
+        } else if key == MINIMAP_KEY && pressed {
+            window.minimap.toggle();
+        } else if (key == RENDER_SCALE_DOWN_KEY || key == RENDER_SCALE_UP_KEY) && pressed {
+            let factor = if key == RENDER_SCALE_UP_KEY { 2.0 } else { 0.5 };
+            let render_scale = window.render_scale().scaled_by(factor);
+            if let RenderScale::Relative(scale) = render_scale {
+                log::info!("Render scale: {scale}");
+            }
+            window.set_render_scale(render_scale);
+        } else if let Some(camera) = window.camera.as_fly_mut() {
+            camera.process_key(key, state);
+        }
+    }
+
+    /// Rebuilds the renderer on a new device after the old one was lost. Panics once the session
+    /// has lost [`MAX_DEVICE_RECOVERIES`] devices already, or if the rebuild fails.
+    fn recover_from_device_loss(&mut self) {
+        if self.device_recoveries == MAX_DEVICE_RECOVERIES {
+            panic!("Lost the device again after {MAX_DEVICE_RECOVERIES} recoveries, giving up");
+        }
+        self.device_recoveries += 1;
+        log::error!(
+            "Lost the device, rebuilding the renderer ({} of {MAX_DEVICE_RECOVERIES} recoveries)",
+            self.device_recoveries
+        );
+        if let Err(err) = self.renderer.recover_from_device_loss() {
+            panic!("Failed to recover from losing the device: {err}");
+        }
+        let device = self.renderer.context().device.physical_device();
+        log::info!(
+            "Rebuilt the renderer on {}",
+            device.properties().device_name
+        );
+        // Like switching devices, rebuilding takes long enough to make the next delta huge.
+        self.last_frame = Instant::now();
+    }
+
+    /// Draws a frame into every window. Continues with whether any window was drawn into, or
+    /// breaks once the benchmark is over.
+    fn render_frame(&mut self) -> ControlFlow<(), bool> {
+        let now = Instant::now();
+        let dt = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        // The scene is updated once for all the windows. Every window gets its own acquire and
+        // present, one after the other. The windows add their cameras to the frame data.
+        self.renderer.finish_loads();
+        let time = self.start.elapsed().as_secs_f32();
+        let frame = FrameData {
+            time,
+            update_alpha: self.renderer.update(time),
+            ..FrameData::default()
+        };
+        let mut rendered = false;
+        crash_report::frame_started();
+        self.capture.frame_starting();
+        for id in self.renderer.window_ids() {
+            let window = self.renderer.window_mut(id).unwrap();
+            if let Some(camera) = window.camera.as_fly_mut() {
+                camera.update(dt);
+            }
+            match self.renderer.render(id, &frame) {
+                Ok(window_rendered) => rendered |= window_rendered,
+                Err(err) if err.is_device_lost() => {
+                    self.recover_from_device_loss();
+                    break;
+                }
+                Err(err) => panic!("Failed to render frame: {err}"),
+            }
+            let latencies = self
+                .renderer
+                .window_mut(id)
+                .unwrap()
+                .take_present_latencies();
+            if let Some(benchmark) = &mut self.benchmark {
+                benchmark.presents_seen(latencies);
+            }
+        }
+        self.capture.frame_finished(rendered);
+        if let Some(sentinel) = self.sentinel.take_if(|_| rendered) {
+            sentinel.disarm();
+        }
+        for drawn in self.resizes_waiting.drain(..) {
+            let _ = drawn.send(());
+        }
+
+        let stats = self
+            .stats_line
+            .as_mut()
+            .filter(|_| rendered)
+            .and_then(|stats_line| stats_line.frame_rendered(&self.renderer));
+        if let Some((window, title)) = stats {
+            let _ = self.events.send_event(RenderEvent::Stats { window, title });
+        }
+
+        if let Some(benchmark) = &mut self.benchmark {
+            if rendered && benchmark.frame_rendered() {
+                println!("{}", benchmark.report());
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(rendered)
+    }
+}
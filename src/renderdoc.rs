@@ -0,0 +1,273 @@
+//! Triggering RenderDoc captures from inside the program, for catching exactly the frame
+//! something goes wrong in.
+//!
+//! RenderDoc's in-application API is looked up in the library RenderDoc injects into programs
+//! it launches. The library is never loaded otherwise, so without RenderDoc every call is a
+//! no-op.
+
+use std::ffi::{c_char, c_int, c_void};
+use std::path::PathBuf;
+use std::ptr;
+
+/// `eRENDERDOC_API_Version_1_1_2`, the oldest version with everything used here.
+const API_VERSION: c_int = 10102;
+
+type GetApiFn = unsafe extern "C" fn(version: c_int, api: *mut *mut c_void) -> c_int;
+type Unused = Option<unsafe extern "C" fn()>;
+
+/// The start of `RENDERDOC_API_1_1_2` from `renderdoc_app.h`, up to the last entry used.
+#[repr(C)]
+struct Api {
+    get_api_version: Unused,
+    set_capture_option_u32: Unused,
+    set_capture_option_f32: Unused,
+    get_capture_option_u32: Unused,
+    get_capture_option_f32: Unused,
+    set_focus_toggle_keys: Unused,
+    set_capture_keys: Unused,
+    get_overlay_bits: Unused,
+    mask_overlay_bits: Unused,
+    remove_hooks: Unused,
+    unload_crash_handler: Unused,
+    set_capture_file_path_template: Unused,
+    get_capture_file_path_template: Unused,
+    get_num_captures: unsafe extern "C" fn() -> u32,
+    get_capture: unsafe extern "C" fn(
+        index: u32,
+        filename: *mut c_char,
+        path_length: *mut u32,
+        timestamp: *mut u64,
+    ) -> u32,
+    trigger_capture: Unused,
+    is_target_control_connected: Unused,
+    launch_replay_ui: Unused,
+    set_active_window: Unused,
+    start_frame_capture: unsafe extern "C" fn(device: *mut c_void, window: *mut c_void),
+    is_frame_capturing: Unused,
+    end_frame_capture: unsafe extern "C" fn(device: *mut c_void, window: *mut c_void) -> u32,
+}
+
+/// The RenderDoc instance the process runs under, if any.
+pub struct RenderDoc {
+    api: *const Api,
+    /// Keeps the library loaded for as long as `api` points into it.
+    _library: Option<libloading::Library>,
+}
+
+impl RenderDoc {
+    /// Connects to RenderDoc if it injected itself into the process.
+    pub fn detect() -> Self {
+        let absent = Self {
+            api: ptr::null(),
+            _library: None,
+        };
+        let Some(library) = injected_library() else {
+            return absent;
+        };
+        let mut api: *mut c_void = ptr::null_mut();
+        // SAFETY: `RENDERDOC_GetAPI` has this signature in every version of RenderDoc, and fills
+        // in `api` with a table laid out like `Api` when it supports the version asked for.
+        let supported = unsafe {
+            library
+                .get::<GetApiFn>(b"RENDERDOC_GetAPI\0")
+                .map(|get_api| get_api(API_VERSION, &mut api) == 1)
+        };
+        match supported {
+            Ok(true) if !api.is_null() => Self {
+                api: api.cast(),
+                _library: Some(library),
+            },
+            _ => {
+                log::warn!("RenderDoc is loaded, but doesn't support API version 1.1.2");
+                absent
+            }
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        !self.api.is_null()
+    }
+
+    fn api(&self) -> Option<&Api> {
+        // SAFETY: the table lives as long as the library, which `self` keeps loaded.
+        unsafe { self.api.as_ref() }
+    }
+
+    /// Starts capturing everything any device submits and presents.
+    pub fn start_frame_capture(&self) {
+        if let Some(api) = self.api() {
+            // SAFETY: null device and window handles capture from whichever are active.
+            unsafe { (api.start_frame_capture)(ptr::null_mut(), ptr::null_mut()) };
+        }
+    }
+
+    /// Ends the capture started by [`start_frame_capture`](Self::start_frame_capture) and
+    /// returns where RenderDoc saved it.
+    pub fn end_frame_capture(&self) -> Option<PathBuf> {
+        let api = self.api()?;
+        // SAFETY: matches the call starting the capture.
+        let captured = unsafe { (api.end_frame_capture)(ptr::null_mut(), ptr::null_mut()) };
+        if captured != 1 {
+            return None;
+        }
+        // SAFETY: RenderDoc first reports the path's length, including the terminating nul,
+        // then writes that many bytes into the buffer.
+        unsafe {
+            let index = (api.get_num_captures)().checked_sub(1)?;
+            let mut length = 0;
+            if (api.get_capture)(index, ptr::null_mut(), &mut length, ptr::null_mut()) != 1 {
+                return None;
+            }
+            let mut path = vec![0u8; length as usize];
+            (api.get_capture)(
+                index,
+                path.as_mut_ptr().cast(),
+                &mut length,
+                ptr::null_mut(),
+            );
+            path.truncate(
+                path.iter()
+                    .position(|&byte| byte == 0)
+                    .unwrap_or(path.len()),
+            );
+            Some(PathBuf::from(String::from_utf8_lossy(&path).into_owned()))
+        }
+    }
+}
+
+/// The RenderDoc library, only if it is already loaded into the process.
+#[cfg(unix)]
+fn injected_library() -> Option<libloading::Library> {
+    use libloading::os::unix::{Library, RTLD_NOW};
+    // SAFETY: with `RTLD_NOLOAD` nothing new is loaded, so no initialization code runs.
+    unsafe { Library::open(Some("librenderdoc.so"), RTLD_NOW | libc::RTLD_NOLOAD) }
+        .ok()
+        .map(Into::into)
+}
+
+#[cfg(windows)]
+fn injected_library() -> Option<libloading::Library> {
+    libloading::os::windows::Library::open_already_loaded("renderdoc.dll")
+        .ok()
+        .map(Into::into)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn injected_library() -> Option<libloading::Library> {
+    None
+}
+
+/// Decides which frames to capture: the one `--capture-frame` asks for and the next one after
+/// each request from the capture key.
+pub struct FrameCapture {
+    renderdoc: RenderDoc,
+    /// The frame to capture, counting from 1, until it has been.
+    frame: Option<u64>,
+    capture_next: bool,
+    capturing: bool,
+    frames_drawn: u64,
+}
+
+impl FrameCapture {
+    /// Looks for RenderDoc and, if found, arranges to capture frame `frame`.
+    pub fn new(frame: Option<u32>) -> Self {
+        let renderdoc = RenderDoc::detect();
+        if renderdoc.is_available() {
+            log::info!("Running under RenderDoc, press Home to capture the next frame");
+        } else if frame.is_some() {
+            log::warn!("Not running under RenderDoc, so no frame will be captured");
+        }
+        Self::with_renderdoc(renderdoc, frame)
+    }
+
+    fn with_renderdoc(renderdoc: RenderDoc, frame: Option<u32>) -> Self {
+        Self {
+            renderdoc,
+            frame: frame.map(u64::from),
+            capture_next: false,
+            capturing: false,
+            frames_drawn: 0,
+        }
+    }
+
+    /// Captures the next frame drawn.
+    pub fn request(&mut self) {
+        if !self.renderdoc.is_available() {
+            log::info!("Not running under RenderDoc, so there's nothing to capture with");
+        }
+        self.capture_next = true;
+    }
+
+    /// Starts a capture if the frame about to be drawn is to be captured.
+    pub fn frame_starting(&mut self) {
+        let due = self.capture_next || self.frame == Some(self.frames_drawn + 1);
+        if due && !self.capturing {
+            self.renderdoc.start_frame_capture();
+            self.capturing = true;
+        }
+    }
+
+    /// Ends the capture once a frame has actually been drawn into it, so it isn't empty when
+    /// nothing could be drawn, e.g. while minimized.
+    pub fn frame_finished(&mut self, drawn: bool) {
+        if !drawn {
+            return;
+        }
+        self.frames_drawn += 1;
+        if !self.capturing {
+            return;
+        }
+        self.capturing = false;
+        self.capture_next = false;
+        if self.frame.is_some_and(|frame| frame <= self.frames_drawn) {
+            self.frame = None;
+        }
+        if let Some(path) = self.renderdoc.end_frame_capture() {
+            log::info!("Captured frame {} to {}", self.frames_drawn, path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn without_renderdoc(frame: Option<u32>) -> FrameCapture {
+        let renderdoc = RenderDoc {
+            api: ptr::null(),
+            _library: None,
+        };
+        FrameCapture::with_renderdoc(renderdoc, frame)
+    }
+
+    #[test]
+    fn the_requested_frame_is_captured_once() {
+        let mut capture = without_renderdoc(Some(3));
+        let mut captured = Vec::new();
+        for drawn in [true, true, false, true, true, true] {
+            capture.frame_starting();
+            if capture.capturing && drawn {
+                captured.push(capture.frames_drawn + 1);
+            }
+            capture.frame_finished(drawn);
+        }
+        assert_eq!(captured, [3]);
+        assert!(!capture.capturing);
+    }
+
+    #[test]
+    fn a_request_captures_the_next_frame_drawn() {
+        let mut capture = without_renderdoc(None);
+        capture.frame_starting();
+        capture.frame_finished(true);
+        capture.request();
+        capture.frame_starting();
+        assert!(capture.capturing);
+        // Nothing drawn, so the capture stays open for the next frame.
+        capture.frame_finished(false);
+        assert!(capture.capturing);
+        capture.frame_starting();
+        capture.frame_finished(true);
+        assert!(!capture.capturing && !capture.capture_next);
+    }
+}
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::device::DeviceExtensions;
+use vulkano::instance::debug::DebugUtilsMessenger;
+use vulkano::instance::{Instance, InstanceExtensions};
+use vulkano::render_pass::{RenderPass, Subpass};
+use vulkano::swapchain::Surface;
+use winit::dpi::PhysicalPosition;
+use winit::event::VirtualKeyCode;
+use winit::window::{Window, WindowId};
+
+use crate::camera::Camera;
+use crate::console::ConsoleOverlay;
+use crate::context::{create_instance, next_physical_device, VulkanContext};
+use crate::device_selection::DevicePreference;
+use crate::error::RendererError;
+use crate::loader::Loader;
+use crate::model::Model;
+use crate::options::Options;
+use crate::picking::Aabb;
+use crate::render_pass::{create_render_pass, supported_samples};
+use crate::scene::{
+    build_loaded_model_scene, build_scene, FixedTimestep, FrameData, Scene, SceneKind, MAX_WINDOWS,
+};
+use crate::surface_config::SurfaceConfig;
+use crate::upscale::RenderScale;
+use crate::validation::{self, LayerFeature};
+use crate::window_context::WindowContext;
+
+/// The extensions every device we draw with needs.
+pub(crate) const DEVICE_EXTENSIONS: DeviceExtensions = DeviceExtensions {
+    khr_swapchain: true,
+    ..DeviceExtensions::empty()
+};
+
+/// Everything tied to the device rather than to a window.
+type DeviceParts = (
+    VulkanContext,
+    Arc<RenderPass>,
+    Box<dyn Scene>,
+    ConsoleOverlay,
+);
+
+/// What a window keeps when its context is rebuilt for another device.
+struct WindowState {
+    window: Arc<Window>,
+    surface: Arc<Surface>,
+    camera: Camera,
+    split_camera: Option<Camera>,
+    minimap_visible: bool,
+    render_scale: RenderScale,
+}
+
+/// Stands in for the scene between dropping the old device's and building the new device's.
+struct NoScene;
+
+impl Scene for NoScene {
+    fn draw(
+        &self,
+        _builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        _frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        Ok(())
+    }
+}
+
+/// Draws one scene into any number of windows, one frame at a time.
+///
+/// The device, the scene's pipelines and its meshes and textures are shared; everything tied to
+/// a window lives in its [`WindowContext`].
+///
+/// Rust drops fields in declaration order, and the order below is deliberate: the windows (and
+/// their per-frame state) first, then the scene and the overlay and the render pass they were
+/// built for, then the loader, then the context (and with it the allocators and device), and the
+/// validation messenger last. New fields must be slotted in accordingly. [`Drop`] waits for the
+/// GPU to go idle before any of them are freed.
+pub struct Renderer {
+    windows: HashMap<WindowId, WindowContext>,
+    scene: Box<dyn Scene>,
+    /// When the scene is due its next update.
+    timestep: FixedTimestep,
+    /// The log, drawn over every window's scene while shown.
+    console: ConsoleOverlay,
+    render_pass: Arc<RenderPass>,
+    /// Which scene to build again when switching devices.
+    scene_kind: SceneKind,
+    options: Options,
+    /// Loads `--model` in the background while the built-in scene stands in for it, unless
+    /// `--load-threads 0` loads it up front instead.
+    loader: Option<Loader<Model>>,
+    ctx: VulkanContext,
+    /// Logs what the validation layer reports with `--shader-printf` or `--gpu-validation`.
+    /// Goes after the device, so messages about destroying it are logged too.
+    _validation: Option<DebugUtilsMessenger>,
+}
+
+impl Renderer {
+    /// Creates the device and the scene, choosing a device that can present to `window`, which
+    /// becomes the first window drawn into.
+    pub fn new(
+        window: Arc<Window>,
+        camera: Camera,
+        scene_kind: SceneKind,
+        options: &Options,
+    ) -> Result<Self, RendererError> {
+        let required_extensions = InstanceExtensions {
+            // `VK_EXT_full_screen_exclusive` extends the surface capabilities query from this one.
+            #[cfg(windows)]
+            khr_get_surface_capabilities2: options.exclusive_fullscreen,
+            ..Surface::required_extensions(window.as_ref())
+        };
+        // Adds the HDR colour spaces to those surfaces report.
+        let optional_extensions = InstanceExtensions {
+            ext_swapchain_colorspace: options.hdr,
+            ..InstanceExtensions::empty()
+        };
+        let instance = create_instance(
+            required_extensions,
+            optional_extensions,
+            options.force_api_version,
+            options.layer_feature,
+        )?;
+        let validation = validation::is_enabled(&instance)
+            .then(|| validation::create_messenger(&instance))
+            .transpose()?;
+        let surface = Surface::from_window(instance.clone(), window.clone())?;
+        let (ctx, render_pass, scene, console) =
+            Self::create_device(instance, &surface, scene_kind, options)?;
+
+        let first_window =
+            WindowContext::new(&ctx, &render_pass, window, surface, camera, 0, options)?;
+        let windows = HashMap::from([(first_window.window().id(), first_window)]);
+
+        let loader = options
+            .load_in_background()
+            .then(|| Loader::new(options.load_threads));
+        let mut renderer = Self {
+            windows,
+            scene,
+            timestep: FixedTimestep::new(options.update_rate),
+            console,
+            render_pass,
+            scene_kind,
+            options: options.clone(),
+            loader,
+            ctx,
+            _validation: validation,
+        };
+        renderer.start_loading_model();
+        Ok(renderer)
+    }
+
+    /// Queues `--model` on the loader, if there is one and it isn't loading it already.
+    fn start_loading_model(&mut self) {
+        let (Some(loader), Some(path)) = (&mut self.loader, &self.options.model) else {
+            return;
+        };
+        if loader.pending() == 0 {
+            log::info!("Loading {} in the background", path.display());
+            let path = path.clone();
+            loader.load(path.display().to_string(), move || Model::load(&path));
+        }
+    }
+
+    /// Swaps in the model the loader has finished loading, if it has. Call between frames: the
+    /// model's buffers and textures are uploaded here, on the device's queue. A model that
+    /// failed to load is logged and the built-in scene kept.
+    pub fn finish_loads(&mut self) {
+        let Some(loader) = &mut self.loader else {
+            return;
+        };
+        for loaded in loader.finished() {
+            let model = match loaded.result {
+                Ok(model) => model,
+                Err(err) => {
+                    log::error!("Failed to load {}: {err}", loaded.name);
+                    continue;
+                }
+            };
+            log::info!(
+                "Loaded {} in {:.2?}: {} vertices, {} triangles, {} instances",
+                loaded.name,
+                loaded.elapsed,
+                model.vertices.len(),
+                model.indices.len() / 3,
+                model.instances.len()
+            );
+            let subpass = Subpass::from(self.render_pass.clone(), 0).unwrap();
+            let path = self.options.model.as_deref().unwrap();
+            match build_loaded_model_scene(
+                path,
+                &model,
+                &self.options.scene_options(),
+                &self.ctx,
+                subpass,
+            ) {
+                Ok(scene) => {
+                    // The frames in flight may still be drawing the old scene.
+                    self.wait_idle();
+                    for window in self.windows.values_mut() {
+                        window.scene_replaced();
+                    }
+                    self.scene = scene;
+                }
+                Err(err) => log::error!("Failed to upload {}: {err}", loaded.name),
+            }
+        }
+    }
+
+    /// Creates the device, choosing one that can present to `surface`, and the scene and console
+    /// overlay on it.
+    fn create_device(
+        instance: Arc<Instance>,
+        surface: &Surface,
+        scene: SceneKind,
+        options: &Options,
+    ) -> Result<DeviceParts, RendererError> {
+        let optional_extensions = DeviceExtensions {
+            // Shaders calling `debugPrintfEXT` need it before Vulkan 1.3.
+            khr_shader_non_semantic_info: options.layer_feature == Some(LayerFeature::ShaderPrintf),
+            #[cfg(windows)]
+            ext_full_screen_exclusive: options.exclusive_fullscreen,
+            ..DeviceExtensions::empty()
+        };
+        let ctx = VulkanContext::new(
+            instance,
+            DEVICE_EXTENSIONS,
+            optional_extensions,
+            Some(surface),
+            &options.device,
+            options.force_api_version,
+        )?;
+        if options.layer_feature == Some(LayerFeature::GpuAssisted)
+            && validation::is_enabled(&ctx.instance)
+        {
+            validation::check_gpu_assisted_features(ctx.device.physical_device());
+        }
+
+        // Every window has to use the first one's format, so the scene's pipelines can draw into
+        // all of them.
+        let surface_config = SurfaceConfig::query(ctx.device.physical_device(), surface)?;
+        let hdr_format = surface_config
+            .choose_hdr_format()
+            .filter(|_| options.hdr && ctx.instance.enabled_extensions().ext_swapchain_colorspace);
+        if options.hdr && hdr_format.is_none() {
+            log::warn!("The display doesn't support HDR in extended linear sRGB, using SDR");
+        }
+        let (format, color_space) = hdr_format.unwrap_or_else(|| surface_config.choose_format());
+        log::info!("Swapchain format: {format:?}, {color_space:?}");
+        let samples = supported_samples(options.msaa, ctx.caps.limits.sample_counts);
+        if samples != options.msaa {
+            log::warn!(
+                "The device can't draw with {} samples, using {}",
+                options.msaa as u32,
+                samples as u32
+            );
+        }
+        let render_pass = create_render_pass(ctx.device.clone(), format, samples)?;
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        // With a loader, the built-in scene is drawn until the model is ready.
+        let model = options
+            .model
+            .as_deref()
+            .filter(|_| !options.load_in_background());
+        let scene = build_scene(
+            scene,
+            model,
+            &options.scene_options(),
+            &ctx,
+            subpass.clone(),
+        )?;
+        let console = ConsoleOverlay::default();
+        Ok((ctx, render_pass, scene, console))
+    }
+
+    /// Moves to the next device that can present to the first window, wrapping around, for
+    /// comparing drivers without restarting. Returns `false` if there is no other device.
+    ///
+    /// Once the GPU is idle, the windows' swapchains and the scene are dropped before the new
+    /// device is created; the old device itself goes as soon as the new one replaces it. The
+    /// windows keep their cameras and render scales, but the scene starts afresh. The console
+    /// overlay stays shown or hidden.
+    pub fn switch_to_next_device(&mut self) -> Result<bool, RendererError> {
+        let Some(first_surface) = self.windows().next().map(|w| w.surface().clone()) else {
+            return Ok(false);
+        };
+        let Some(index) = next_physical_device(
+            &self.ctx.instance,
+            &DEVICE_EXTENSIONS,
+            &first_surface,
+            self.ctx.device.physical_device(),
+        )?
+        else {
+            return Ok(false);
+        };
+
+        self.wait_idle();
+        self.options.device.preference = DevicePreference::Index(index);
+        self.rebuild(&first_surface)?;
+        Ok(true)
+    }
+
+    /// Rebuilds everything on a new device after the current one was lost, e.g. to a driver
+    /// crash or reset or a GPU hang. The device is picked the same way it was at startup.
+    ///
+    /// Like [`switch_to_next_device`](Self::switch_to_next_device), the windows keep their
+    /// cameras and render scales but the scene starts afresh. The frames that were in flight
+    /// are abandoned rather than waited for.
+    pub fn recover_from_device_loss(&mut self) -> Result<(), RendererError> {
+        let Some(first_surface) = self.windows().next().map(|w| w.surface().clone()) else {
+            return Ok(());
+        };
+        for window in self.windows.values_mut() {
+            window.abandon_frames();
+        }
+        self.rebuild(&first_surface)
+    }
+
+    /// Drops the windows' contexts, the scene and the device, then creates them all again with
+    /// the current options. The GPU must be idle, or lost.
+    fn rebuild(&mut self, first_surface: &Arc<Surface>) -> Result<(), RendererError> {
+        let states: Vec<_> = self
+            .windows()
+            .map(|w| WindowState {
+                window: w.window().clone(),
+                surface: w.surface().clone(),
+                camera: w.camera.clone(),
+                split_camera: w.split_camera.clone(),
+                minimap_visible: w.minimap.is_visible(),
+                render_scale: w.render_scale(),
+            })
+            .collect();
+        self.windows.clear();
+        self.scene = Box::new(NoScene);
+
+        let (ctx, render_pass, scene, mut console) = Self::create_device(
+            self.ctx.instance.clone(),
+            first_surface,
+            self.scene_kind,
+            &self.options,
+        )?;
+        console.set_visible(self.console.is_visible());
+        self.console = console;
+        self.render_pass = render_pass;
+        self.ctx = ctx;
+        self.scene = scene;
+
+        for (window_index, state) in states.into_iter().enumerate() {
+            let mut context = WindowContext::new(
+                &self.ctx,
+                &self.render_pass,
+                state.window,
+                state.surface,
+                state.camera,
+                window_index,
+                &self.options,
+            )?;
+            context.split_camera = state.split_camera;
+            context.minimap.set_visible(state.minimap_visible);
+            if context.render_scale() != state.render_scale {
+                context.set_render_scale(state.render_scale);
+            }
+            self.windows.insert(context.window().id(), context);
+        }
+        self.start_loading_model();
+        Ok(())
+    }
+
+    /// Blocks until the GPU has finished everything queued, presentation included.
+    fn wait_idle(&self) {
+        // The frame fences only cover rendering, not presentation, so wait for the whole queue.
+        // An error means the device is lost, and there's nothing useful to do about that here.
+        if let Err(err) = self.ctx.queue.with(|mut queue| queue.wait_idle()) {
+            log::error!("Failed to wait for the GPU: {err}");
+        }
+    }
+
+    pub fn context(&self) -> &VulkanContext {
+        &self.ctx
+    }
+
+    /// Starts drawing the scene into another window as well, viewed through `camera`.
+    pub fn add_window(&mut self, window: Arc<Window>, camera: Camera) -> Result<(), RendererError> {
+        let window_index = (0..MAX_WINDOWS)
+            .find(|&index| self.windows.values().all(|w| w.window_index() != index))
+            .ok_or_else(|| {
+                RendererError::IncompatibleWindow(format!(
+                    "at most {MAX_WINDOWS} windows are supported"
+                ))
+            })?;
+        let surface = Surface::from_window(self.ctx.instance.clone(), window.clone())?;
+        let context = WindowContext::new(
+            &self.ctx,
+            &self.render_pass,
+            window,
+            surface,
+            camera,
+            window_index,
+            &self.options,
+        )?;
+        self.windows.insert(context.window().id(), context);
+        Ok(())
+    }
+
+    /// Stops drawing into a window and destroys everything that belonged to it, once the GPU is
+    /// done with it. Returns the number of windows left.
+    pub fn remove_window(&mut self, id: WindowId) -> usize {
+        self.windows.remove(&id);
+        self.windows.len()
+    }
+
+    pub fn window(&self, id: WindowId) -> Option<&WindowContext> {
+        self.windows.get(&id)
+    }
+
+    pub fn window_mut(&mut self, id: WindowId) -> Option<&mut WindowContext> {
+        self.windows.get_mut(&id)
+    }
+
+    /// All windows, the first one first.
+    pub fn windows(&self) -> impl Iterator<Item = &WindowContext> {
+        let mut windows: Vec<_> = self.windows.values().collect();
+        windows.sort_by_key(|w| w.window_index());
+        windows.into_iter()
+    }
+
+    /// IDs of all windows, the first one first.
+    pub fn window_ids(&self) -> Vec<WindowId> {
+        self.windows().map(|w| w.window().id()).collect()
+    }
+
+    /// Selects the object under `cursor` in the window `id`, or clears the selection if there is
+    /// none. Returns the scene's index of the selected object.
+    pub fn select_at(&mut self, id: WindowId, cursor: PhysicalPosition<f64>) -> Option<usize> {
+        let selection = self
+            .windows
+            .get(&id)
+            .and_then(|window| window.pick_ray(cursor))
+            .and_then(|ray| self.scene.pick(&ray));
+        self.scene.set_selection(selection);
+        selection
+    }
+
+    /// Paints a mouse stroke from `from` to `to` in the window `id` onto the scene. Returns
+    /// whether the scene can be painted on; see [`Scene::paint`].
+    pub fn paint(
+        &mut self,
+        id: WindowId,
+        from: PhysicalPosition<f64>,
+        to: PhysicalPosition<f64>,
+    ) -> bool {
+        let Some(window) = self.windows.get(&id) else {
+            return false;
+        };
+        let size = window.window().inner_size();
+        self.scene.paint(
+            [from.x as f32, from.y as f32],
+            [to.x as f32, to.y as f32],
+            [size.width, size.height],
+        )
+    }
+
+    /// Passes a key press on to the scene. Returns whether the scene used the key.
+    pub fn scene_key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        self.scene.key_pressed(key)
+    }
+
+    /// Shows or hides the log over the scene in every window.
+    pub fn toggle_console(&mut self) {
+        self.console.toggle();
+    }
+
+    /// A box around everything in the scene, if it knows.
+    pub fn scene_bounds(&self) -> Option<Aabb> {
+        self.scene.bounds()
+    }
+
+    /// Updates the scene as many times as are due by the frame at `time`, in seconds since the
+    /// app started. Returns the frame's [`update_alpha`](FrameData::update_alpha).
+    pub fn update(&mut self, time: f32) -> f32 {
+        let scene = &mut self.scene;
+        self.timestep.advance(time, |dt| scene.update(dt))
+    }
+
+    /// Seconds between the scene's updates.
+    pub fn timestep(&self) -> f32 {
+        self.timestep.timestep()
+    }
+
+    /// Draws the scene into the window `id`, through each of its cameras, and queues it for
+    /// presentation. Only the time and update alpha are taken from `frame`; see
+    /// [`WindowContext`] for the details.
+    ///
+    /// Returns `false` if no frame was drawn, e.g. because the window is minimized, its swapchain
+    /// had to be recreated first or it doesn't exist (any more).
+    pub fn render(&mut self, id: WindowId, frame: &FrameData) -> Result<bool, RendererError> {
+        match self.windows.get_mut(&id) {
+            Some(window) => window.render(
+                &self.ctx,
+                &self.render_pass,
+                self.scene.as_mut(),
+                &mut self.console,
+                frame,
+            ),
+            None => Ok(false),
+        }
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.wait_idle();
+    }
+}
@@ -0,0 +1,152 @@
+//! Starting in a minimal configuration after a launch that never got to draw a frame, so a
+//! driver that crashes on something we ask of it doesn't keep the app from starting at all.
+//!
+//! Before setting anything up, [`start`] writes a [`Sentinel`] file, which the first frame drawn
+//! removes again, as does exiting or failing with an error before then. Finding it at launch
+//! means the last run crashed or hung without drawing a frame, so that run and every one after
+//! it starts with the [`minimal`] options instead, until `--reset-safe-mode` removes the file.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use vulkano::image::SampleCount;
+use vulkano::swapchain::PresentMode;
+use vulkano::Version;
+
+use crate::device_selection::DevicePreference;
+use crate::options::Options;
+use crate::upscale::RenderScale;
+
+/// Where the sentinel is written, relative to the working directory.
+pub const SENTINEL_PATH: &str = "safe-mode.sentinel";
+
+/// A file saying a launch hasn't drawn its first frame yet.
+#[derive(Debug)]
+pub struct Sentinel {
+    path: PathBuf,
+}
+
+impl Sentinel {
+    fn arm(path: &Path) -> io::Result<Self> {
+        fs::write(
+            path,
+            "hi-vulkanos writes this file while starting up and removes it once a frame has been \
+             drawn. If it is still here at launch, the app starts in safe mode.\n",
+        )?;
+        Ok(Self {
+            path: path.to_owned(),
+        })
+    }
+
+    /// Removes the file, now that a frame has been drawn or the app is exiting without crashing.
+    pub fn disarm(self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("Could not remove {}: {err}", self.path.display());
+        }
+    }
+}
+
+/// Decides how this launch starts, with the sentinel at [`SENTINEL_PATH`]; see [`start_at`].
+pub fn start(options: &mut Options, reset: bool) -> Option<Sentinel> {
+    start_at(Path::new(SENTINEL_PATH), options, reset)
+}
+
+/// Removes the sentinel at `path` first if `reset`. If one is still there, switches `options`
+/// to the [`minimal`] ones and leaves it in place. Otherwise writes one, to be disarmed after
+/// the first frame.
+pub fn start_at(path: &Path, options: &mut Options, reset: bool) -> Option<Sentinel> {
+    if reset {
+        match fs::remove_file(path) {
+            Ok(()) => log::info!("Left safe mode"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => log::warn!("Could not remove {}: {err}", path.display()),
+        }
+    }
+    if path.exists() {
+        log::warn!(
+            "The last launch didn't get to draw a frame, so starting in safe mode without \
+             validation, with FIFO presents and the integrated GPU where there is one. Pass \
+             --reset-safe-mode to start normally again"
+        );
+        *options = minimal(options);
+        return None;
+    }
+    Sentinel::arm(path)
+        .inspect_err(|err| log::warn!("Could not write {}: {err}", path.display()))
+        .ok()
+}
+
+/// `options` with everything optional that could crash a driver turned off: no validation
+/// layer, FIFO presents with the default swapchain, no HDR, multisampling, GPU culling or other
+/// extras, the device picked automatically but preferring an integrated GPU, and no extensions
+/// standing in for newer core features. What the app is asked to draw, and how many frames of
+/// it, stays the same.
+pub fn minimal(options: &Options) -> Options {
+    let mut minimal = Options {
+        capture_frame: None,
+        transparent: false,
+        hdr: false,
+        present_mode: PresentMode::Fifo,
+        frame_latency: None,
+        swapchain_images: None,
+        compute_present: false,
+        stereo: false,
+        render_scale: RenderScale::default(),
+        msaa: SampleCount::Sample1,
+        force_api_version: Some(Version::V1_1),
+        gpu_culling: false,
+        layer_feature: None,
+        ..options.clone()
+    };
+    #[cfg(windows)]
+    {
+        minimal.exclusive_fullscreen = false;
+    }
+    minimal.device.preference = DevicePreference::Auto;
+    minimal.device.prefer_integrated = true;
+    minimal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::LayerFeature;
+
+    #[test]
+    fn a_launch_that_never_drew_starts_the_next_in_safe_mode() {
+        let path =
+            std::env::temp_dir().join(format!("hi-vulkanos-safe-mode-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let requested = Options {
+            present_mode: PresentMode::Mailbox,
+            layer_feature: Some(LayerFeature::GpuAssisted),
+            frames: Some(10),
+            ..Options::default()
+        };
+
+        // A launch that draws a frame leaves nothing behind.
+        let mut options = requested.clone();
+        start_at(&path, &mut options, false).unwrap().disarm();
+        assert_eq!(options, requested);
+        assert!(!path.exists());
+
+        // One that doesn't makes the next start minimal, and the one after that too.
+        let mut options = requested.clone();
+        // Never disarmed, as after a crash.
+        start_at(&path, &mut options, false).unwrap();
+        for _ in 0..2 {
+            let mut options = requested.clone();
+            assert!(start_at(&path, &mut options, false).is_none());
+            assert_eq!(options.present_mode, PresentMode::Fifo);
+            assert_eq!(options.layer_feature, None);
+            assert!(options.device.prefer_integrated);
+            assert_eq!(options.frames, Some(10));
+        }
+
+        // Until it is reset.
+        let mut options = requested.clone();
+        start_at(&path, &mut options, true).unwrap().disarm();
+        assert_eq!(options, requested);
+    }
+}
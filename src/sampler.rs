@@ -0,0 +1,170 @@
+//! How textures are sampled, chosen per texture and shared between those that sample alike.
+//!
+//! Devices only allow so many samplers (`maxSamplerAllocationCount` can be as low as 4000), so
+//! rather than every texture creating its own, [`SamplerCache::get`] hands out one [`Sampler`]
+//! per distinct [`SamplerConfig`].
+
+use std::sync::{Arc, Mutex};
+
+use vulkano::device::Device;
+use vulkano::image::sampler::{
+    Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE,
+};
+
+use crate::caps::DeviceCaps;
+use crate::error::RendererError;
+
+/// Filtering and wrapping for a texture. The default is linear filtering, repeating in every
+/// direction, without anisotropy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplerConfig {
+    pub min_filter: Filter,
+    pub mag_filter: Filter,
+    pub mipmap_mode: SamplerMipmapMode,
+    /// For the U, V and W coordinates.
+    pub address_mode: [SamplerAddressMode; 3],
+    /// The most samples anisotropic filtering takes, or `None` to not filter anisotropically.
+    /// Clamped to what the device supports, and dropped where it doesn't support it at all.
+    pub anisotropy: Option<f32>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            min_filter: Filter::Linear,
+            mag_filter: Filter::Linear,
+            mipmap_mode: SamplerMipmapMode::Linear,
+            address_mode: [SamplerAddressMode::Repeat; 3],
+            anisotropy: None,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// Nearest filtering, clamped to the edge: for pixel art and anything that must stay crisp.
+    pub fn nearest_clamped() -> Self {
+        Self {
+            min_filter: Filter::Nearest,
+            mag_filter: Filter::Nearest,
+            mipmap_mode: SamplerMipmapMode::Nearest,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            anisotropy: None,
+        }
+    }
+
+    /// Uses `filter` for both minification and magnification.
+    pub fn with_filter(self, filter: Filter) -> Self {
+        Self {
+            min_filter: filter,
+            mag_filter: filter,
+            ..self
+        }
+    }
+
+    /// Uses `address_mode` in every direction.
+    pub fn with_address_mode(self, address_mode: SamplerAddressMode) -> Self {
+        Self {
+            address_mode: [address_mode; 3],
+            ..self
+        }
+    }
+
+    pub fn with_anisotropy(self, anisotropy: f32) -> Self {
+        Self {
+            anisotropy: Some(anisotropy),
+            ..self
+        }
+    }
+
+    /// The config as the device can actually sample it, given the most anisotropy it supports
+    /// (`None` without the feature).
+    fn supported(self, max_anisotropy: Option<f32>) -> Self {
+        let anisotropy = self
+            .anisotropy
+            .zip(max_anisotropy)
+            .map(|(anisotropy, max)| anisotropy.clamp(1.0, max))
+            // A single sample is what sampling without anisotropy does anyway.
+            .filter(|&anisotropy| anisotropy > 1.0);
+        Self { anisotropy, ..self }
+    }
+
+    fn create_info(&self) -> SamplerCreateInfo {
+        SamplerCreateInfo {
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_mode: self.mipmap_mode,
+            address_mode: self.address_mode,
+            anisotropy: self.anisotropy,
+            // Every mip level, for the images that have more than one.
+            lod: 0.0..=LOD_CLAMP_NONE,
+            ..Default::default()
+        }
+    }
+}
+
+/// The samplers created so far, one per distinct config.
+pub struct SamplerCache {
+    device: Arc<Device>,
+    max_anisotropy: Option<f32>,
+    /// Looked up linearly: a renderer only ever needs a handful.
+    samplers: Mutex<Vec<(SamplerConfig, Arc<Sampler>)>>,
+}
+
+impl SamplerCache {
+    pub fn new(device: Arc<Device>, caps: &DeviceCaps) -> Self {
+        Self {
+            device,
+            max_anisotropy: caps
+                .sampler_anisotropy
+                .then_some(caps.limits.max_sampler_anisotropy),
+            samplers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A sampler for `config`, the same one every time for the same config.
+    pub fn get(&self, config: SamplerConfig) -> Result<Arc<Sampler>, RendererError> {
+        let config = config.supported(self.max_anisotropy);
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some((_, sampler)) = samplers.iter().find(|(cached, _)| *cached == config) {
+            return Ok(sampler.clone());
+        }
+        let sampler = Sampler::new(self.device.clone(), config.create_info())?;
+        samplers.push((config, sampler.clone()));
+        Ok(sampler)
+    }
+
+    /// How many distinct samplers have been created.
+    pub fn len(&self) -> usize {
+        self.samplers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anisotropy_is_clamped_to_the_device() {
+        let config = SamplerConfig::default().with_anisotropy(16.0);
+        assert_eq!(config.supported(Some(8.0)).anisotropy, Some(8.0));
+        assert_eq!(config.supported(Some(16.0)).anisotropy, Some(16.0));
+        assert_eq!(config.supported(None).anisotropy, None);
+        let single = SamplerConfig::default().with_anisotropy(0.5);
+        assert_eq!(single.supported(Some(16.0)).anisotropy, None);
+    }
+
+    #[test]
+    fn builders_set_every_direction() {
+        let config = SamplerConfig::default()
+            .with_filter(Filter::Nearest)
+            .with_address_mode(SamplerAddressMode::MirroredRepeat);
+        assert_eq!(config.min_filter, Filter::Nearest);
+        assert_eq!(config.mag_filter, Filter::Nearest);
+        assert_eq!(config.address_mode, [SamplerAddressMode::MirroredRepeat; 3]);
+        assert_eq!(config.mipmap_mode, SamplerMipmapMode::Linear);
+    }
+}
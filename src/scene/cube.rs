@@ -0,0 +1,479 @@
+use glam::{Mat4, Vec3};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::render_pass::Subpass;
+use winit::event::VirtualKeyCode;
+
+use crate::caps::clamp_line_width;
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::picking::{closest_hit, Aabb, Ray};
+use crate::scene::{
+    build_line_pipeline, build_pipeline, FrameData, FrameUniforms, Material, Materials, MvpUniform,
+    Node, Scene,
+};
+use crate::std140::std140_layout;
+use crate::stereo::create_multiview_render_pass;
+
+#[derive(BufferContents, Vertex, Debug, PartialEq)]
+#[repr(C)]
+pub struct ColoredVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub color: [f32; 3],
+}
+
+pub(super) mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 color;
+
+            layout(location = 0) out vec3 v_color;
+
+            layout(set = 0, binding = 0) uniform Mvp {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+                float time;
+            } mvp;
+
+            void main() {
+                v_color = color;
+                gl_Position = mvp.projection * mvp.view * mvp.model * vec4(position, 1.0);
+            }
+        "
+    }
+}
+
+/// Like [`vs`], but for both eyes at once in a multiview render pass.
+mod stereo_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+            #extension GL_EXT_multiview : require
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 color;
+
+            layout(location = 0) out vec3 v_color;
+
+            // The left eye's view first.
+            layout(set = 0, binding = 0) uniform Stereo {
+                mat4 model;
+                mat4 view[2];
+                mat4 projection;
+            } stereo;
+
+            void main() {
+                v_color = color;
+                gl_Position = stereo.projection * stereo.view[gl_ViewIndex] * stereo.model
+                    * vec4(position, 1.0);
+            }
+        "
+    }
+}
+
+std140_layout!(stereo_vs::Stereo {
+    model: [[f32; 4]; 4] => Mat4,
+    view: [[[f32; 4]; 4]; 2] => Array(&Mat4, 2),
+    projection: [[f32; 4]; 4] => Mat4,
+});
+
+fn stereo_uniform(model: Mat4, frame: &FrameData, views: [Mat4; 2]) -> stereo_vs::Stereo {
+    stereo_vs::Stereo {
+        model: model.to_cols_array_2d(),
+        view: views.map(|view| view.to_cols_array_2d()),
+        projection: frame.projection.to_cols_array_2d(),
+    }
+}
+
+pub(super) mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            // Mixed into the colour by its alpha, to highlight the cube.
+            layout(push_constant) uniform Highlight {
+                vec4 tint;
+            } highlight;
+
+            void main() {
+                f_color = vec4(mix(v_color, highlight.tint.rgb, highlight.tint.a), 1.0);
+            }
+        "
+    }
+}
+
+/// The six faces of a unit cube centred on the origin, each with its own colour.
+pub(super) fn cube_vertices() -> Vec<ColoredVertex> {
+    // Each face is a colour and its four corners, listed counter-clockwise when looking at the
+    // face from outside the cube.
+    let faces: [([f32; 3], [[f32; 3]; 4]); 6] = [
+        // +X
+        (
+            [1.0, 0.2, 0.2],
+            [
+                [0.5, -0.5, 0.5],
+                [0.5, -0.5, -0.5],
+                [0.5, 0.5, -0.5],
+                [0.5, 0.5, 0.5],
+            ],
+        ),
+        // -X
+        (
+            [0.2, 1.0, 1.0],
+            [
+                [-0.5, -0.5, -0.5],
+                [-0.5, -0.5, 0.5],
+                [-0.5, 0.5, 0.5],
+                [-0.5, 0.5, -0.5],
+            ],
+        ),
+        // +Y
+        (
+            [0.2, 1.0, 0.2],
+            [
+                [-0.5, 0.5, 0.5],
+                [0.5, 0.5, 0.5],
+                [0.5, 0.5, -0.5],
+                [-0.5, 0.5, -0.5],
+            ],
+        ),
+        // -Y
+        (
+            [1.0, 0.2, 1.0],
+            [
+                [-0.5, -0.5, -0.5],
+                [0.5, -0.5, -0.5],
+                [0.5, -0.5, 0.5],
+                [-0.5, -0.5, 0.5],
+            ],
+        ),
+        // +Z
+        (
+            [0.2, 0.2, 1.0],
+            [
+                [-0.5, -0.5, 0.5],
+                [0.5, -0.5, 0.5],
+                [0.5, 0.5, 0.5],
+                [-0.5, 0.5, 0.5],
+            ],
+        ),
+        // -Z
+        (
+            [1.0, 1.0, 0.2],
+            [
+                [0.5, -0.5, -0.5],
+                [-0.5, -0.5, -0.5],
+                [-0.5, 0.5, -0.5],
+                [0.5, 0.5, -0.5],
+            ],
+        ),
+    ];
+
+    faces
+        .iter()
+        .flat_map(|(color, corners)| {
+            [0, 1, 2, 0, 2, 3].map(|i| ColoredVertex {
+                position: corners[i],
+                color: *color,
+            })
+        })
+        .collect()
+}
+
+/// The twelve edges of the cube, as a line list, a little outside it so its faces don't hide
+/// them.
+fn outline_vertices() -> Vec<ColoredVertex> {
+    let corner = |index: u32| {
+        [0, 1, 2].map(|axis| {
+            if index >> axis & 1 == 1 {
+                OUTLINE_EXTENT
+            } else {
+                -OUTLINE_EXTENT
+            }
+        })
+    };
+    // From each corner along each axis it can go further along.
+    (0..8u32)
+        .flat_map(|from| (0..3).map(move |axis| (from, from | 1 << axis)))
+        .filter(|(from, to)| from != to)
+        .flat_map(|(from, to)| {
+            [from, to].map(|index| ColoredVertex {
+                position: corner(index),
+                color: [1.0; 3],
+            })
+        })
+        .collect()
+}
+
+/// What a selected cube is tinted with.
+const SELECTION_TINT: [f32; 4] = [1.0, 1.0, 1.0, 0.5];
+
+/// What a selected cube's outline is tinted with, all of it.
+const OUTLINE_TINT: [f32; 4] = [1.0; 4];
+
+/// How far the outline is from the cube's centre along each axis.
+const OUTLINE_EXTENT: f32 = 0.505;
+
+/// How many pixels wide the outline is at first, if the device can draw it that wide. A single
+/// pixel is hard to see on high-DPI displays.
+const OUTLINE_WIDTH: f32 = 2.0;
+
+/// Keys that make the outline a pixel thinner and thicker, within what the device can draw.
+const THINNER_OUTLINE_KEY: VirtualKeyCode = VirtualKeyCode::LBracket;
+const THICKER_OUTLINE_KEY: VirtualKeyCode = VirtualKeyCode::RBracket;
+
+/// The push constant leaving the cube's colours as they are.
+pub(super) const NO_HIGHLIGHT: fs::Highlight = fs::Highlight { tint: [0.0; 4] };
+
+/// A vertex-coloured cube viewed through the camera, for trying out 3D navigation. Clicking it
+/// selects it, tinting it and outlining its edges with lines whose width [`THINNER_OUTLINE_KEY`]
+/// and [`THICKER_OUTLINE_KEY`] change. Drawn for both eyes at once, it isn't outlined.
+pub struct CubeScene {
+    materials: Materials,
+    cube: Node,
+    outline: Node,
+    /// In pixels, within `line_width_range`.
+    outline_width: f32,
+    line_width_range: [f32; 2],
+    /// Changes whenever the selection or the outline's width does, which the draws push and set.
+    revision: u64,
+    uniforms: FrameUniforms<MvpUniform>,
+    /// For drawing both eyes at once, where the device has multiview.
+    stereo: Option<(Material, FrameUniforms<stereo_vs::Stereo>)>,
+    bounds: Aabb,
+    selected: bool,
+}
+
+impl CubeScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let vertices = cube_vertices();
+        let bounds = Aabb::from_points(vertices.iter().map(|v| Vec3::from(v.position))).unwrap();
+        let vertex_buffer = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vertices,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Vertex, vertex_buffer.buffer());
+        ctx.name_object(vertex_buffer.buffer(), "cube vertices");
+
+        let outline_buffer = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            outline_vertices(),
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Vertex, outline_buffer.buffer());
+        ctx.name_object(outline_buffer.buffer(), "cube outline vertices");
+
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let vertex_input_state =
+            ColoredVertex::per_vertex().definition(&vs.info().input_interface)?;
+
+        let stereo = if ctx.caps.multiview {
+            let stereo_vs = stereo_vs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap();
+            // Compatible with every window's multiview render pass, which is all the pipeline
+            // needs.
+            let render_pass = create_multiview_render_pass(
+                ctx.device.clone(),
+                subpass.render_pass().attachments()[0].format,
+            )?;
+            let pipeline = build_pipeline(
+                ctx.device.clone(),
+                stereo_vs,
+                fs.clone(),
+                vertex_input_state.clone(),
+                Subpass::from(render_pass, 0).unwrap(),
+            )?;
+            ctx.name_object(&pipeline, "cube stereo pipeline");
+            let uniforms = FrameUniforms::new(
+                ctx,
+                &pipeline,
+                0,
+                stereo_uniform(Mat4::IDENTITY, &FrameData::default(), [Mat4::IDENTITY; 2]),
+            )?;
+            Some((Material::new(pipeline), uniforms))
+        } else {
+            None
+        };
+
+        let outline_pipeline = build_line_pipeline(
+            ctx.device.clone(),
+            vs.clone(),
+            fs.clone(),
+            vertex_input_state.clone(),
+            subpass.clone(),
+        )?;
+        ctx.name_object(&outline_pipeline, "cube outline pipeline");
+        let pipeline = build_pipeline(ctx.device.clone(), vs, fs, vertex_input_state, subpass)?;
+        ctx.name_object(&pipeline, "cube pipeline");
+
+        let uniforms = FrameUniforms::new(
+            ctx,
+            &pipeline,
+            0,
+            MvpUniform::new(Mat4::IDENTITY, &FrameData::default()),
+        )?;
+        let mut materials = Materials::new();
+        let cube = Node::new(materials.add(Material::new(pipeline)), vertex_buffer);
+        let outline = Node::new(
+            materials.add(Material::new(outline_pipeline)),
+            outline_buffer,
+        );
+        let line_width_range = ctx.caps.line_width_range();
+
+        Ok(Self {
+            materials,
+            cube,
+            outline,
+            outline_width: clamp_line_width(OUTLINE_WIDTH, line_width_range),
+            line_width_range,
+            revision: 0,
+            uniforms,
+            stereo,
+            bounds,
+            selected: false,
+        })
+    }
+}
+
+impl CubeScene {
+    fn highlight(&self) -> fs::Highlight {
+        if self.selected {
+            fs::Highlight {
+                tint: SELECTION_TINT,
+            }
+        } else {
+            NO_HIGHLIGHT
+        }
+    }
+}
+
+impl Scene for CubeScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        self.uniforms
+            .write(frame, MvpUniform::new(Mat4::IDENTITY, frame))
+    }
+
+    fn prepare_stereo(
+        &mut self,
+        frame: &FrameData,
+        views: [Mat4; 2],
+    ) -> Result<bool, RendererError> {
+        let Some((_, uniforms)) = &self.stereo else {
+            return Ok(false);
+        };
+        uniforms.write(frame, stereo_uniform(Mat4::IDENTITY, frame, views))?;
+        Ok(true)
+    }
+
+    fn pick(&self, ray: &Ray) -> Option<usize> {
+        closest_hit(ray, [self.bounds])
+    }
+
+    fn set_selection(&mut self, selection: Option<usize>) {
+        if self.selected != selection.is_some() {
+            self.selected = selection.is_some();
+            self.revision += 1;
+        }
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        let step = match key {
+            THINNER_OUTLINE_KEY => -1.0,
+            THICKER_OUTLINE_KEY => 1.0,
+            _ => return false,
+        };
+        let width = clamp_line_width(self.outline_width + step, self.line_width_range);
+        if width != self.outline_width {
+            self.outline_width = width;
+            self.revision += 1;
+        }
+        log::info!(
+            "Outlining the selected cube {} pixels wide",
+            self.outline_width
+        );
+        true
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    /// The highlight is a push constant and the outline's width is set when recording, so
+    /// selecting the cube or changing the width changes the draws.
+    fn draw_revision(&self) -> Option<u64> {
+        Some(self.revision)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let material = &self.materials[self.cube.material];
+        material.bind(builder, frame)?;
+        material.bind_sets(builder, 0, self.uniforms.descriptor_set(frame))?;
+        material.push_constants(builder, self.highlight())?;
+        self.cube.draw(builder)?;
+        if !self.selected {
+            return Ok(());
+        }
+        let outline = &self.materials[self.outline.material];
+        outline.bind(builder, frame)?;
+        outline.bind_sets(builder, 0, self.uniforms.descriptor_set(frame))?;
+        outline.push_constants(builder, fs::Highlight { tint: OUTLINE_TINT })?;
+        builder.set_line_width(self.outline_width)?;
+        self.outline.draw(builder)
+    }
+
+    /// The same single draw as [`draw`](Self::draw), which the render pass runs once per eye.
+    fn draw_stereo(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let Some((material, uniforms)) = &self.stereo else {
+            return Ok(());
+        };
+        material.bind(builder, frame)?;
+        material.bind_sets(builder, 0, uniforms.descriptor_set(frame))?;
+        material.push_constants(builder, self.highlight())?;
+        self.cube.draw(builder)
+    }
+}
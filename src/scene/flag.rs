@@ -0,0 +1,247 @@
+//! A flag waving in the wind, deformed on the CPU every frame.
+//!
+//! The flag is a grid of [`GRID_SIZE`] by [`GRID_SIZE`] vertices whose positions and normals are
+//! recomputed each frame from a sine wave travelling away from the pole, and drawn from a
+//! [`DynamicMesh`]. Only the vertices change, so the indices are uploaded once.
+
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use vulkano::buffer::{BufferContents, IndexBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::dynamic_mesh::DynamicMesh;
+use crate::error::RendererError;
+use crate::index_buffer::create_index_buffer;
+use crate::picking::Aabb;
+use crate::scene::terrain::grid_indices;
+use crate::scene::{build_pipeline, FrameData, FrameUniforms, MvpUniform, Scene};
+
+/// Vertices along each side of the flag.
+pub const GRID_SIZE: u32 = 64;
+
+/// How wide and tall the flag is, centred on the origin with the pole along its left edge.
+const WIDTH: f32 = 2.0;
+const HEIGHT: f32 = 1.3;
+
+/// How far the free edge of the flag swings either way. The edge at the pole stays put.
+const AMPLITUDE: f32 = 0.15;
+
+/// Radians of wave per unit across the flag.
+const WAVENUMBER: f32 = 5.0;
+
+/// Radians per second the wave moves on by.
+const ANGULAR_SPEED: f32 = 4.0;
+
+/// How much further along the wave is at the top of the flag than at the bottom, so it doesn't
+/// ripple in straight lines, in radians per unit up.
+const SKEW: f32 = 0.8;
+
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct FlagVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    pub normal: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+
+            layout(set = 0, binding = 0) uniform Mvp {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+                float time;
+            } mvp;
+
+            void main() {
+                v_normal = mat3(mvp.model) * normal;
+                v_uv = uv;
+                gl_Position = mvp.projection * mvp.view * mvp.model * vec4(position, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            const vec3 SUN = normalize(vec3(0.3, 0.6, 0.8));
+
+            void main() {
+                // Three stripes, top to bottom.
+                vec3 colors[3] = vec3[](
+                    vec3(0.85, 0.15, 0.15),
+                    vec3(0.95, 0.95, 0.92),
+                    vec3(0.15, 0.25, 0.7)
+                );
+                vec3 color = colors[min(int(v_uv.y * 3.0), 2)];
+                // The back of the flag is lit as its front.
+                vec3 normal = normalize(gl_FrontFacing ? v_normal : -v_normal);
+                float light = 0.3 + 0.7 * max(dot(normal, SUN), 0.0);
+                f_color = vec4(color * light, 1.0);
+            }
+        "
+    }
+}
+
+/// A flag waving on a [`DynamicMesh`] rewritten every frame.
+pub struct FlagScene {
+    pipeline: Arc<GraphicsPipeline>,
+    uniforms: FrameUniforms<MvpUniform>,
+    mesh: DynamicMesh<FlagVertex>,
+    indices: IndexBuffer,
+    index_count: u32,
+}
+
+impl FlagScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let vertex_input_state = FlagVertex::per_vertex().definition(&vs.info().input_interface)?;
+        let pipeline = build_pipeline(ctx.device.clone(), vs, fs, vertex_input_state, subpass)?;
+        ctx.name_object(&pipeline, "flag pipeline");
+        let uniforms = FrameUniforms::new(
+            ctx,
+            &pipeline,
+            0,
+            MvpUniform::new(Mat4::IDENTITY, &FrameData::default()),
+        )?;
+
+        let mut vertices = flat_flag(GRID_SIZE);
+        wave(&mut vertices, 0.0);
+        let grid = grid_indices(GRID_SIZE);
+        let indices = create_index_buffer(ctx, &grid, vertices.len())?;
+        Ok(Self {
+            pipeline,
+            uniforms,
+            mesh: DynamicMesh::new(ctx, vertices),
+            indices,
+            index_count: grid.len() as u32,
+        })
+    }
+}
+
+impl Scene for FlagScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        self.mesh
+            .update(frame, |vertices| wave(vertices, frame.time))?;
+        self.uniforms
+            .write(frame, MvpUniform::new(Mat4::IDENTITY, frame))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: Vec3::new(-WIDTH / 2.0, -HEIGHT / 2.0, -AMPLITUDE),
+            max: Vec3::new(WIDTH / 2.0, HEIGHT / 2.0, AMPLITUDE),
+        })
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let Some(vertices) = self.mesh.vertex_buffer(frame) else {
+            return Ok(());
+        };
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                self.uniforms.descriptor_set(frame),
+            )?
+            .bind_vertex_buffers(0, vertices)?
+            .bind_index_buffer(self.indices.clone())?
+            .draw_indexed(self.index_count, 1, 0, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// A flat flag of `size` by `size` vertices facing +Z, numbered row by row from the top left,
+/// with its uvs from 0 to 1 across and down.
+fn flat_flag(size: u32) -> Vec<FlagVertex> {
+    let last = size.saturating_sub(1).max(1) as f32;
+    (0..size * size)
+        .map(|index| {
+            let uv = [(index % size) as f32 / last, (index / size) as f32 / last];
+            FlagVertex {
+                position: [(uv[0] - 0.5) * WIDTH, (0.5 - uv[1]) * HEIGHT, 0.0],
+                normal: [0.0, 0.0, 1.0],
+                uv,
+            }
+        })
+        .collect()
+}
+
+/// Moves each of `vertices` to where the wave has it at `time` seconds and turns its normal to
+/// match. Only the depth changes, growing from nothing at the pole to [`AMPLITUDE`] at the free
+/// edge.
+fn wave(vertices: &mut [FlagVertex], time: f32) {
+    for vertex in vertices {
+        let [x, y, _] = vertex.position;
+        let along = vertex.uv[0];
+        let phase = WAVENUMBER * x + SKEW * y - ANGULAR_SPEED * time;
+        let (sin, cos) = phase.sin_cos();
+        vertex.position[2] = AMPLITUDE * along * sin;
+        // The slopes of the surface across and up, for a normal at right angles to both.
+        let dz_dx = AMPLITUDE * (sin / WIDTH + along * WAVENUMBER * cos);
+        let dz_dy = AMPLITUDE * along * SKEW * cos;
+        vertex.normal = Vec3::new(-dz_dx, -dz_dy, 1.0).normalize().to_array();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_flag_waves_from_its_pole() {
+        let mut vertices = flat_flag(GRID_SIZE);
+        assert_eq!(vertices.len() as u32, GRID_SIZE * GRID_SIZE);
+        for time in [0.0, 0.4, 2.5] {
+            wave(&mut vertices, time);
+            for vertex in &vertices {
+                assert!(vertex.position[2].abs() <= AMPLITUDE * vertex.uv[0] + 1e-6);
+                assert!((Vec3::from(vertex.normal).length() - 1.0).abs() < 1e-5);
+            }
+            // The pole's edge stays flat, facing +Z.
+            assert_eq!(vertices[0].position[2], 0.0);
+            assert!(Vec3::from(vertices[0].normal).z > 0.9);
+        }
+        // The wave travels, moving the free edge.
+        let edge = (GRID_SIZE - 1) as usize;
+        wave(&mut vertices, 0.0);
+        let before = vertices[edge].position[2];
+        wave(&mut vertices, 0.3);
+        assert_ne!(vertices[edge].position[2], before);
+    }
+}
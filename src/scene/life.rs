@@ -0,0 +1,904 @@
+//! Conway's Game of Life, computed entirely in compute shaders and blitted to the screen, that
+//! can be drawn on with the mouse.
+//!
+//! The board is two `R8_UINT` storage images with a cell for every [`CELL_SIZE`] pixels of the
+//! view, each generation read from one and written to the other, [`STEPS_PER_SECOND`] of them.
+//! A live cell holds [`LIVE`], and a dead one half of what it held the generation before, which
+//! leaves fading trails. A second shader colours the newest generation into an RGBA8 image.
+//! Where the swapchain images are storage images, with `--compute-present`, a third shader
+//! writes that image straight into the one presented, letterboxed. Otherwise, where the target
+//! can be blitted to, the image is blitted over it, so either way no graphics pipeline is ever
+//! made. Failing both, a fullscreen triangle samples it, through a pipeline made the first time
+//! it is needed.
+//!
+//! When the view changes size, the board is made again to fit and the cells the old and new
+//! boards share are copied over. Painting, clearing and filling at random are uploads into the
+//! board, recorded before the frame's generations are stepped.
+//!
+//! [`PAUSE_KEY`] pauses and resumes, [`STEP_KEY`] steps one generation, [`CLEAR_KEY`] clears the
+//! board and [`RANDOMIZE_KEY`] fills it at random again.
+
+use std::mem;
+use std::sync::{Arc, OnceLock};
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BufferImageCopy, ClearColorImageInfo, CopyBufferToImageInfo,
+    CopyImageInfo, ImageCopy, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::format::{ClearColorValue, Format, FormatFeatures, NumericFormat};
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::{ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+use winit::event::VirtualKeyCode;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::{MemoryCategory, MemoryTracker};
+use crate::sampler::SamplerConfig;
+use crate::scene::step_clock::StepClock;
+use crate::scene::{build_compute_pipeline, build_pipeline, FrameData, Scene};
+use crate::upscale::letterbox;
+
+/// Pixels of the view each cell takes up, across and down.
+const CELL_SIZE: u32 = 6;
+
+/// Generations stepped per second while running.
+const STEPS_PER_SECOND: f32 = 15.0;
+
+/// The most generations one frame steps. After a longer hitch the board skips the rest rather
+/// than catching up.
+const MAX_STEPS_PER_FRAME: u32 = 4;
+
+/// Cells each workgroup handles along each axis; matches `local_size_x` and `local_size_y` in
+/// the shaders.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The board's format, which the shaders' `r8ui` qualifiers match.
+const CELL_FORMAT: Format = Format::R8_UINT;
+
+/// The coloured board's format, which the colour shader's `rgba8` qualifier matches.
+const COLOR_FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// What a live cell holds; `LIVE` in the shaders.
+const LIVE: u8 = 255;
+
+/// Key that pauses and resumes stepping.
+const PAUSE_KEY: VirtualKeyCode = VirtualKeyCode::Space;
+
+/// Key that steps one generation, mostly of use while paused.
+const STEP_KEY: VirtualKeyCode = VirtualKeyCode::N;
+
+/// Key that kills every cell.
+const CLEAR_KEY: VirtualKeyCode = VirtualKeyCode::Back;
+
+/// Key that brings a random quarter of the cells to life, and kills the rest.
+const RANDOMIZE_KEY: VirtualKeyCode = VirtualKeyCode::R;
+
+mod step_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, r8ui) uniform readonly uimage2D previous;
+            layout(set = 0, binding = 1, r8ui) uniform writeonly uimage2D next;
+
+            const uint LIVE = 255;
+
+            bool alive(ivec2 cell) {
+                ivec2 size = imageSize(previous);
+                return imageLoad(previous, (cell + size) % size).r == LIVE;
+            }
+
+            void main() {
+                ivec2 cell = ivec2(gl_GlobalInvocationID.xy);
+                if (any(greaterThanEqual(cell, imageSize(previous)))) {
+                    return;
+                }
+                int neighbours = 0;
+                for (int y = -1; y <= 1; y++) {
+                    for (int x = -1; x <= 1; x++) {
+                        if ((x != 0 || y != 0) && alive(cell + ivec2(x, y))) {
+                            neighbours++;
+                        }
+                    }
+                }
+                uint value = imageLoad(previous, cell).r;
+                bool lives = neighbours == 3 || (neighbours == 2 && value == LIVE);
+                imageStore(next, cell, uvec4(lives ? LIVE : value / 2));
+            }
+        "
+    }
+}
+
+mod color_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, r8ui) uniform readonly uimage2D cells;
+            layout(set = 0, binding = 1, rgba8) uniform writeonly image2D colors;
+
+            const uint LIVE = 255;
+
+            void main() {
+                ivec2 cell = ivec2(gl_GlobalInvocationID.xy);
+                if (any(greaterThanEqual(cell, imageSize(cells)))) {
+                    return;
+                }
+                uint value = imageLoad(cells, cell).r;
+                // Live cells are white. Dead ones fade out from blue, red first.
+                float trail = float(value) / float(LIVE / 2);
+                vec3 color = value == LIVE
+                    ? vec3(1.0)
+                    : vec3(0.5, 0.85, 0.95) * vec3(pow(trail, 3.0), pow(trail, 1.5), trail);
+                imageStore(colors, cell, vec4(color, 1.0));
+            }
+        "
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec2 v_uv;
+
+            void main() {
+                v_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(v_uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D colors;
+
+            void main() {
+                f_color = texture(colors, v_uv);
+            }
+        "
+    }
+}
+
+mod present_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, rgba8) uniform readonly image2D colors;
+            // Whatever format the swapchain has, so without a format qualifier.
+            layout(set = 0, binding = 1) uniform writeonly image2D target;
+
+            layout(push_constant) uniform Letterbox {
+                // Already encoded for the target.
+                vec4 clear_color;
+                // Where the board goes on the target.
+                ivec2 offset;
+                ivec2 extent;
+                // Set for UNORM targets, which store what they're given rather than encoding
+                // it as sRGB formats do.
+                uint encode_srgb;
+            } letterbox;
+
+            vec3 linear_to_srgb(vec3 color) {
+                return mix(
+                    color * 12.92,
+                    1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055,
+                    greaterThan(color, vec3(0.0031308))
+                );
+            }
+
+            void main() {
+                ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+                if (any(greaterThanEqual(pixel, imageSize(target)))) {
+                    return;
+                }
+                ivec2 inside = pixel - letterbox.offset;
+                vec4 color = letterbox.clear_color;
+                if (all(greaterThanEqual(inside, ivec2(0))) && all(lessThan(inside, letterbox.extent))) {
+                    // The cell under the pixel's centre, as a nearest blit picks it.
+                    ivec2 cell = (inside * 2 + 1) * imageSize(colors) / (letterbox.extent * 2);
+                    color = imageLoad(colors, cell);
+                    if (letterbox.encode_srgb != 0) {
+                        color.rgb = linear_to_srgb(color.rgb);
+                    }
+                }
+                imageStore(target, pixel, color);
+            }
+        "
+    }
+}
+
+/// What making boards and the fallback pipeline takes, kept from the context.
+struct Parts {
+    device: Arc<Device>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    memory_tracker: Arc<MemoryTracker>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    /// Whether objects can be named, as in [`VulkanContext::name_object`].
+    debug_names: bool,
+    subpass: Subpass,
+    sampler: Arc<Sampler>,
+    /// A single live cell, copied into every cell painted.
+    live: Subbuffer<[u8]>,
+}
+
+impl Parts {
+    fn name_image(&self, image: &Arc<Image>, name: &str) {
+        if !self.debug_names {
+            return;
+        }
+        if let Err(err) = self
+            .device
+            .set_debug_utils_object_name(&**image, Some(name))
+        {
+            log::warn!("Failed to name `{name}`: {err}");
+        }
+    }
+
+    fn create_image(
+        &self,
+        format: Format,
+        usage: ImageUsage,
+        [width, height]: [u32; 2],
+        name: &str,
+    ) -> Result<Arc<Image>, RendererError> {
+        let image = Image::new(
+            self.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [width, height, 1],
+                usage,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+        self.memory_tracker
+            .track_image(MemoryCategory::RenderTarget, &image);
+        self.name_image(&image, name);
+        Ok(image)
+    }
+
+    /// A staging buffer for the cells of a `size` board, a random quarter of them alive.
+    fn random_cells(&self, size: [u32; 2], seed: u32) -> Result<Subbuffer<[u8]>, RendererError> {
+        Ok(Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            random_cells(size, seed),
+        )?)
+    }
+}
+
+/// The images of a board of one size, and the sets using them.
+struct Board {
+    size: [u32; 2],
+    /// The two generations in flight.
+    cells: [Arc<Image>; 2],
+    /// The newest generation, coloured.
+    colors: Arc<Image>,
+    colors_view: Arc<ImageView>,
+    /// The set for stepping into `cells[i]`, which reads the other image.
+    step_sets: [Arc<PersistentDescriptorSet>; 2],
+    /// The set for colouring `cells[i]`.
+    color_sets: [Arc<PersistentDescriptorSet>; 2],
+    /// Samples `colors` for the fallback, made along with it.
+    fallback_set: OnceLock<Arc<PersistentDescriptorSet>>,
+}
+
+impl Board {
+    fn new(
+        parts: &Parts,
+        step_pipeline: &ComputePipeline,
+        color_pipeline: &ComputePipeline,
+        size: [u32; 2],
+    ) -> Result<Self, RendererError> {
+        let cell_usage = ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC | ImageUsage::TRANSFER_DST;
+        let cells = [
+            parts.create_image(CELL_FORMAT, cell_usage, size, "life cells 0")?,
+            parts.create_image(CELL_FORMAT, cell_usage, size, "life cells 1")?,
+        ];
+        let colors = parts.create_image(
+            COLOR_FORMAT,
+            ImageUsage::STORAGE | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC,
+            size,
+            "life colours",
+        )?;
+
+        let cell_views = [
+            ImageView::new_default(cells[0].clone())?,
+            ImageView::new_default(cells[1].clone())?,
+        ];
+        let colors_view = ImageView::new_default(colors.clone())?;
+        let set = |pipeline: &ComputePipeline, read: usize, written: WriteDescriptorSet| {
+            PersistentDescriptorSet::new(
+                parts.descriptor_set_allocator.as_ref(),
+                pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::image_view(0, cell_views[read].clone()),
+                    written,
+                ],
+                [],
+            )
+        };
+        let step_set = |next: usize| {
+            let written = WriteDescriptorSet::image_view(1, cell_views[next].clone());
+            set(step_pipeline, 1 - next, written)
+        };
+        let color_set = |cells: usize| {
+            let written = WriteDescriptorSet::image_view(1, colors_view.clone());
+            set(color_pipeline, cells, written)
+        };
+        Ok(Self {
+            size,
+            cells,
+            colors,
+            step_sets: [step_set(0)?, step_set(1)?],
+            color_sets: [color_set(0)?, color_set(1)?],
+            colors_view,
+            fallback_set: OnceLock::new(),
+        })
+    }
+
+    fn workgroups(&self) -> [u32; 3] {
+        [
+            self.size[0].div_ceil(WORKGROUP_SIZE),
+            self.size[1].div_ceil(WORKGROUP_SIZE),
+            1,
+        ]
+    }
+}
+
+/// Sets every cell of the board at once.
+enum Fill {
+    Clear,
+    /// Copies in a cell per byte, row by row.
+    Cells(Subbuffer<[u8]>),
+}
+
+/// What the next frame records into the board before colouring it, worked out in `prepare`.
+#[derive(Default)]
+struct Updates {
+    /// The frame slot recording it, that of the first view of the frame.
+    frame_in_flight: usize,
+    /// Applied to the generation stepped from first.
+    fill: Option<Fill>,
+    /// The board this one replaced and which of its images was newest, to copy the cells both
+    /// have from, after filling.
+    resized_from: Option<(Board, usize)>,
+    /// Cells to bring to life, after copying.
+    painted: Vec<[u32; 2]>,
+    /// Which of the board's images the first generation is stepped from.
+    stepped_from: usize,
+    steps: u32,
+}
+
+/// A Game of Life filling the view, run at a fixed rate and painted on with the mouse.
+pub struct LifeScene {
+    step_pipeline: Arc<ComputePipeline>,
+    color_pipeline: Arc<ComputePipeline>,
+    /// Writes the coloured board into swapchain images, where the device can write storage
+    /// images without knowing their format.
+    present_pipeline: Option<Arc<ComputePipeline>>,
+    parts: Parts,
+    /// Made to fit the first view drawn, and made again whenever that changes size.
+    board: Option<Board>,
+    /// Which of the board's images holds the newest generation.
+    current: usize,
+    clock: StepClock,
+    paused: bool,
+    /// Generations asked for with [`STEP_KEY`] since the last frame.
+    steps_requested: u32,
+    clear_requested: bool,
+    randomize_requested: bool,
+    /// How many times the board was filled at random, to vary the cells each time.
+    randomized: u32,
+    /// Cells painted since the last frame.
+    painted: Vec<[u32; 2]>,
+    updates: Updates,
+    /// Drawing the coloured board with a graphics pipeline, for targets it can't be blitted to.
+    fallback_pipeline: OnceLock<Arc<GraphicsPipeline>>,
+}
+
+impl LifeScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        if !ctx.caps.storage_image_extended_formats {
+            return Err(RendererError::UnsupportedScene(format!(
+                "life needs {CELL_FORMAT:?} storage images, an extended storage image format"
+            )));
+        }
+        for (format, needed) in [
+            (
+                CELL_FORMAT,
+                FormatFeatures::STORAGE_IMAGE
+                    | FormatFeatures::TRANSFER_SRC
+                    | FormatFeatures::TRANSFER_DST,
+            ),
+            (
+                COLOR_FORMAT,
+                FormatFeatures::STORAGE_IMAGE
+                    | FormatFeatures::BLIT_SRC
+                    | FormatFeatures::SAMPLED_IMAGE,
+            ),
+        ] {
+            let missing = needed.difference(ctx.caps.format_features(format));
+            if !missing.is_empty() {
+                return Err(RendererError::UnsupportedScene(format!(
+                    "life needs {format:?} images to support {missing:?}"
+                )));
+            }
+        }
+
+        let step_pipeline = build_compute_pipeline(ctx, step_cs::load(ctx.device.clone())?)?;
+        ctx.name_object(&step_pipeline, "life step pipeline");
+        let color_pipeline = build_compute_pipeline(ctx, color_cs::load(ctx.device.clone())?)?;
+        ctx.name_object(&color_pipeline, "life colour pipeline");
+        let present_pipeline = if ctx.caps.storage_write_without_format {
+            let pipeline = build_compute_pipeline(ctx, present_cs::load(ctx.device.clone())?)?;
+            ctx.name_object(&pipeline, "life present pipeline");
+            Some(pipeline)
+        } else {
+            None
+        };
+
+        let live = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            [LIVE],
+        )?;
+
+        Ok(Self {
+            step_pipeline,
+            color_pipeline,
+            present_pipeline,
+            parts: Parts {
+                device: ctx.device.clone(),
+                memory_allocator: ctx.memory_allocator.clone(),
+                memory_tracker: ctx.memory_tracker.clone(),
+                descriptor_set_allocator: ctx.descriptor_set_allocator.clone(),
+                debug_names: ctx.debug_names_enabled(),
+                subpass,
+                sampler: ctx.samplers.get(SamplerConfig::nearest_clamped())?,
+                live,
+            },
+            board: None,
+            current: 0,
+            clock: StepClock::new(STEPS_PER_SECOND, MAX_STEPS_PER_FRAME),
+            paused: false,
+            steps_requested: 0,
+            clear_requested: false,
+            randomize_requested: false,
+            randomized: 0,
+            painted: Vec::new(),
+            updates: Updates::default(),
+            fallback_pipeline: OnceLock::new(),
+        })
+    }
+
+    fn fallback_pipeline(&self) -> Result<&Arc<GraphicsPipeline>, RendererError> {
+        if let Some(pipeline) = self.fallback_pipeline.get() {
+            return Ok(pipeline);
+        }
+        log::info!("Drawing life with a graphics pipeline, as it can't be blitted this frame");
+        let parts = &self.parts;
+        let vs = vs::load(parts.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(parts.device.clone())?.entry_point("main").unwrap();
+        let pipeline = build_pipeline(
+            parts.device.clone(),
+            vs,
+            fs,
+            VertexInputState::new(),
+            parts.subpass.clone(),
+        )?;
+        Ok(self.fallback_pipeline.get_or_init(|| pipeline))
+    }
+
+    /// Makes the board fit `frame`, returning how to fill it and what to copy into it.
+    fn update_board(&mut self, frame: &FrameData) -> Result<Updates, RendererError> {
+        let size = board_size(frame.extent);
+        let mut fill = None;
+        let mut resized_from = None;
+        if self.board.as_ref().map(|board| board.size) != Some(size) {
+            let board = Board::new(&self.parts, &self.step_pipeline, &self.color_pipeline, size)?;
+            log::info!("Life runs on a {}x{} board", size[0], size[1]);
+            match self.board.replace(board) {
+                // The new images start out undefined, so the cells the old board doesn't cover
+                // are cleared.
+                Some(old) => {
+                    fill = Some(Fill::Clear);
+                    resized_from = Some((old, self.current));
+                }
+                None => self.randomize_requested = true,
+            }
+            self.current = 0;
+        }
+        // Either of these replaces every cell, so nothing is copied over.
+        if mem::take(&mut self.randomize_requested) {
+            self.clear_requested = false;
+            self.randomized += 1;
+            fill = Some(Fill::Cells(self.parts.random_cells(size, self.randomized)?));
+            resized_from = None;
+        } else if mem::take(&mut self.clear_requested) {
+            fill = Some(Fill::Clear);
+            resized_from = None;
+        }
+        Ok(Updates {
+            fill,
+            resized_from,
+            ..Updates::default()
+        })
+    }
+}
+
+impl Scene for LifeScene {
+    /// Only the first view of a frame moves the board on, so every view shows the same
+    /// generation.
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        if self.clock.time() == Some(frame.time) {
+            // The same slot at the same time is another frame rather than another view, and
+            // shows the board as it is.
+            if frame.frame_in_flight == self.updates.frame_in_flight {
+                self.updates = Updates {
+                    frame_in_flight: frame.frame_in_flight,
+                    stepped_from: self.current,
+                    ..Updates::default()
+                };
+            }
+            return Ok(());
+        }
+        let steps =
+            self.clock.steps(frame.time, !self.paused) + mem::take(&mut self.steps_requested);
+        let board_updates = self.update_board(frame)?;
+        let size = board_size(frame.extent);
+        let mut painted = mem::take(&mut self.painted);
+        painted.retain(|&[x, y]| x < size[0] && y < size[1]);
+
+        let stepped_from = self.current;
+        self.current = (self.current + steps as usize) % 2;
+        self.updates = Updates {
+            frame_in_flight: frame.frame_in_flight,
+            painted,
+            stepped_from,
+            steps,
+            ..board_updates
+        };
+        Ok(())
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let updates = &self.updates;
+        let Some(board) = self.board.as_ref() else {
+            return Ok(());
+        };
+        if frame.frame_in_flight != updates.frame_in_flight {
+            return Ok(());
+        }
+
+        let start = &board.cells[updates.stepped_from];
+        match &updates.fill {
+            Some(Fill::Clear) => {
+                builder.clear_color_image(ClearColorImageInfo {
+                    clear_value: ClearColorValue::Uint([0; 4]),
+                    ..ClearColorImageInfo::image(start.clone())
+                })?;
+            }
+            Some(Fill::Cells(cells)) => {
+                builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                    cells.clone(),
+                    start.clone(),
+                ))?;
+            }
+            None => {}
+        }
+        if let Some((old, old_current)) = &updates.resized_from {
+            let old_cells = &old.cells[*old_current];
+            let shared = [
+                old.size[0].min(board.size[0]),
+                old.size[1].min(board.size[1]),
+                1,
+            ];
+            builder.copy_image(CopyImageInfo {
+                regions: [ImageCopy {
+                    src_subresource: old_cells.subresource_layers(),
+                    dst_subresource: start.subresource_layers(),
+                    extent: shared,
+                    ..Default::default()
+                }]
+                .into(),
+                ..CopyImageInfo::images(old_cells.clone(), start.clone())
+            })?;
+        }
+        if !updates.painted.is_empty() {
+            builder.copy_buffer_to_image(CopyBufferToImageInfo {
+                regions: updates
+                    .painted
+                    .iter()
+                    .map(|&[x, y]| BufferImageCopy {
+                        image_subresource: start.subresource_layers(),
+                        image_offset: [x, y, 0],
+                        image_extent: [1, 1, 1],
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..CopyBufferToImageInfo::buffer_image(self.parts.live.clone(), start.clone())
+            })?;
+        }
+
+        builder.bind_pipeline_compute(self.step_pipeline.clone())?;
+        for step in 1..=updates.steps as usize {
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.step_pipeline.layout().clone(),
+                    0,
+                    board.step_sets[(updates.stepped_from + step) % 2].clone(),
+                )?
+                .dispatch(board.workgroups())?;
+        }
+        builder
+            .bind_pipeline_compute(self.color_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.color_pipeline.layout().clone(),
+                0,
+                board.color_sets[self.current].clone(),
+            )?
+            .dispatch(board.workgroups())?;
+        Ok(())
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            PAUSE_KEY => {
+                self.paused = !self.paused;
+                log::info!("Life {}", if self.paused { "paused" } else { "running" });
+            }
+            STEP_KEY => self.steps_requested += 1,
+            CLEAR_KEY => {
+                self.clear_requested = true;
+                self.randomize_requested = false;
+            }
+            RANDOMIZE_KEY => {
+                self.randomize_requested = true;
+                self.clear_requested = false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn paint(&mut self, from: [f32; 2], to: [f32; 2], target_extent: [u32; 2]) -> bool {
+        if let Some(board) = &self.board {
+            self.painted
+                .extend(stroke_cells(from, to, target_extent, board.size));
+        }
+        true
+    }
+
+    fn blit_source(&self) -> Option<Arc<Image>> {
+        self.board.as_ref().map(|board| board.colors.clone())
+    }
+
+    fn computes_frame(&self) -> bool {
+        self.present_pipeline.is_some() && self.board.is_some()
+    }
+
+    fn compute_frame(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        target: Arc<ImageView>,
+        clear_color: [f32; 4],
+    ) -> Result<(), RendererError> {
+        let (Some(pipeline), Some(board)) = (&self.present_pipeline, &self.board) else {
+            return Ok(());
+        };
+        let image = target.image().clone();
+        let target_extent = [image.extent()[0], image.extent()[1]];
+        let (offset, extent) = letterbox(board.size, target_extent);
+        let set = PersistentDescriptorSet::new(
+            self.parts.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view(0, board.colors_view.clone()),
+                WriteDescriptorSet::image_view(1, target),
+            ],
+            [],
+        )?;
+        let letterbox = present_cs::Letterbox {
+            clear_color,
+            offset: offset.map(|n| n as i32),
+            extent: extent.map(|n| n as i32),
+            encode_srgb: (image.format().numeric_format_color() == Some(NumericFormat::UNORM))
+                .into(),
+        };
+        builder
+            .bind_pipeline_compute(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )?
+            .push_constants(pipeline.layout().clone(), 0, letterbox)?
+            .dispatch([
+                target_extent[0].div_ceil(WORKGROUP_SIZE),
+                target_extent[1].div_ceil(WORKGROUP_SIZE),
+                1,
+            ])?;
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        _frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let Some(board) = self.board.as_ref() else {
+            return Ok(());
+        };
+        let pipeline = self.fallback_pipeline()?;
+        let set = match board.fallback_set.get() {
+            Some(set) => set.clone(),
+            None => {
+                let set = PersistentDescriptorSet::new(
+                    self.parts.descriptor_set_allocator.as_ref(),
+                    pipeline.layout().set_layouts()[0].clone(),
+                    [WriteDescriptorSet::image_view_sampler(
+                        0,
+                        ImageView::new_default(board.colors.clone())?,
+                        self.parts.sampler.clone(),
+                    )],
+                    [],
+                )?;
+                board.fallback_set.get_or_init(|| set).clone()
+            }
+        };
+        builder
+            .bind_pipeline_graphics(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )?
+            .draw(3, 1, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// The board fitting a view of `extent` pixels, at least one cell in each direction.
+fn board_size(extent: [u32; 2]) -> [u32; 2] {
+    extent.map(|pixels| (pixels / CELL_SIZE).max(1))
+}
+
+/// The cells of a `board_size` board under a stroke from `from` to `to`, in pixels on a target
+/// of `target_extent` the board is letterboxed into, in order and each once. Those off the
+/// board are left out.
+fn stroke_cells(
+    from: [f32; 2],
+    to: [f32; 2],
+    target_extent: [u32; 2],
+    board_size: [u32; 2],
+) -> Vec<[u32; 2]> {
+    let (offset, extent) = letterbox(board_size, target_extent);
+    let to_cells = |position: [f32; 2]| {
+        [0, 1].map(|axis| {
+            (position[axis] - offset[axis] as f32) * board_size[axis] as f32
+                / extent[axis].max(1) as f32
+        })
+    };
+    let (from, to) = (to_cells(from), to_cells(to));
+    // At least one point per cell crossed, so the stroke has no gaps.
+    let points = (to[0] - from[0]).abs().max((to[1] - from[1]).abs()).ceil() as u32;
+    let mut cells: Vec<[u32; 2]> = Vec::new();
+    for point in 0..=points {
+        let t = if points == 0 {
+            0.0
+        } else {
+            point as f32 / points as f32
+        };
+        let [x, y] = [0, 1].map(|axis| from[axis] + (to[axis] - from[axis]) * t);
+        if x < 0.0 || y < 0.0 || x >= board_size[0] as f32 || y >= board_size[1] as f32 {
+            continue;
+        }
+        let cell = [x as u32, y as u32];
+        if !cells.contains(&cell) {
+            cells.push(cell);
+        }
+    }
+    cells
+}
+
+/// A quarter of the cells alive, one byte each, always the same for the same `size` and `seed`.
+fn random_cells([width, height]: [u32; 2], seed: u32) -> Vec<u8> {
+    // xorshift32, which is plenty for scattering cells. Its state must never be zero.
+    let mut state = (0x9e37_79b9_u32 ^ seed.wrapping_mul(0x85eb_ca6b)).max(1);
+    (0..width * height)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            if state >> 30 == 0 {
+                LIVE
+            } else {
+                0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strokes_map_to_cells_through_the_letterbox() {
+        // A 20x10 board letterboxed into 300x100 pixels sits at x 50 to 250, 10 pixels a cell.
+        let board = [20, 10];
+        let target = [300, 100];
+        assert_eq!(
+            stroke_cells([55.0, 5.0], [55.0, 5.0], target, board),
+            [[0, 0]]
+        );
+        assert_eq!(
+            stroke_cells([245.0, 95.0], [245.0, 95.0], target, board),
+            [[19, 9]]
+        );
+        // On the bars, off the board.
+        assert!(stroke_cells([10.0, 50.0], [10.0, 50.0], target, board).is_empty());
+        // A quick stroke still paints every cell it crosses.
+        assert_eq!(
+            stroke_cells([55.0, 25.0], [95.0, 25.0], target, board),
+            [[0, 2], [1, 2], [2, 2], [3, 2], [4, 2]]
+        );
+    }
+}
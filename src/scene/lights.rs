@@ -0,0 +1,884 @@
+//! Hundreds of coloured point lights circling over a floor of pillars, lit with tiled forward
+//! shading.
+//!
+//! Before the scene is drawn, a compute pass splits the view into square tiles and bins the
+//! lights into them: each workgroup builds the four planes bounding its tile's part of the view
+//! and lists the lights whose spheres reach inside all four. The fragment shader then goes
+//! through only its tile's list, rather than every light in the scene, which is what makes
+//! hundreds of lights affordable. A tile lists at most [`LightsConfig::max_per_tile`] lights;
+//! the rest are dropped, and the tiles that had to drop any are counted so a warning can say so.
+//!
+//! [`HEATMAP_KEY`] shows how many lights reach each tile, and [`LIGHTING_KEY`] switches to going
+//! through every light for every pixel, for comparing the two.
+
+use std::mem;
+use std::sync::Arc;
+
+use glam::Vec3;
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::{ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+use vulkano::DeviceSize;
+use winit::event::VirtualKeyCode;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::{MemoryCategory, MemoryTracker};
+use crate::picking::Aabb;
+use crate::scene::{
+    build_compute_pipeline, build_pipeline, FrameData, FrameUniforms, Scene, FRAME_SLOTS,
+};
+use crate::std140::std140_layout;
+
+/// Lights in the scene unless `--lights` says otherwise.
+pub const DEFAULT_LIGHTS: u32 = 512;
+
+/// Width and height of a tile in pixels unless `--light-tile-size` says otherwise.
+pub const DEFAULT_TILE_SIZE: u32 = 16;
+
+/// The most lights a tile lists unless `--max-lights-per-tile` says otherwise.
+pub const DEFAULT_MAX_LIGHTS_PER_TILE: u32 = 128;
+
+/// Half the width of the square floor, centred on the origin. Matches the vertex shader.
+const FLOOR_HALF_SIZE: f32 = 6.0;
+
+/// Height of the top of the floor. Matches the vertex shader.
+const FLOOR_TOP: f32 = -0.5;
+
+/// How tall the pillars standing on the floor are. Matches the vertex shader.
+const PILLAR_HEIGHT: f32 = 1.5;
+
+/// Pillars along each side of the grid on the floor. Matches the vertex shader.
+const PILLARS_ACROSS: u32 = 7;
+
+/// Vertices of one box, six faces of two triangles each.
+const BOX_VERTICES: u32 = 36;
+
+/// Furthest from the middle of the floor a light circles.
+const ORBIT_RADIUS: f32 = 5.5;
+
+/// How far each light's light reaches, from the smallest to the largest.
+const REACH: [f32; 2] = [0.6, 1.2];
+
+/// How high above the floor the lights circle, from the lowest to the highest.
+const HEIGHT: [f32; 2] = [0.15, 1.2];
+
+/// Key that shows and hides how many lights reach each tile.
+const HEATMAP_KEY: VirtualKeyCode = VirtualKeyCode::H;
+
+/// Key that switches between [`Lighting`]s.
+const LIGHTING_KEY: VirtualKeyCode = VirtualKeyCode::L;
+
+/// How many lights the lights scene has, and how they are binned into tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightsConfig {
+    pub count: u32,
+    /// Width and height of a tile in pixels.
+    pub tile_size: u32,
+    /// The most lights one tile lists. Any more reaching the tile don't light it.
+    pub max_per_tile: u32,
+    /// Start out lighting every pixel with every light, rather than with its tile's.
+    pub naive: bool,
+}
+
+impl Default for LightsConfig {
+    fn default() -> Self {
+        Self {
+            count: DEFAULT_LIGHTS,
+            tile_size: DEFAULT_TILE_SIZE,
+            max_per_tile: DEFAULT_MAX_LIGHTS_PER_TILE,
+            naive: false,
+        }
+    }
+}
+
+impl LightsConfig {
+    /// How many tiles across and down cover a view of `extent` pixels, the last ones sticking
+    /// out past its edges where the tiles don't fit exactly. Always at least one each way.
+    pub fn tiles(&self, extent: [u32; 2]) -> [u32; 2] {
+        extent.map(|length| length.div_ceil(self.tile_size).max(1))
+    }
+
+    /// How many `u32`s a tile's list takes: its count, then its lights.
+    fn tile_stride(&self) -> u32 {
+        self.max_per_tile + 1
+    }
+}
+
+/// Which lights each pixel is lit by, numbered as the fragment shader reads them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lighting {
+    /// Those its tile lists.
+    Tiled = 0,
+    /// Every light in the scene, to compare against.
+    EveryLight = 1,
+}
+
+impl Lighting {
+    fn toggled(self) -> Self {
+        match self {
+            Self::Tiled => Self::EveryLight,
+            Self::EveryLight => Self::Tiled,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Tiled => "its tile's lights",
+            Self::EveryLight => "every light",
+        }
+    }
+}
+
+/// A light as the shaders' `PointLight` lays it out.
+#[derive(BufferContents, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+struct PointLight {
+    /// Where the light is, and how far it reaches in `w`.
+    position_reach: [f32; 4],
+    /// Colour and brightness, `w` unused.
+    color: [f32; 4],
+}
+
+mod cull_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            // A workgroup to each tile, its invocations testing the lights between them.
+            layout(local_size_x = 64) in;
+
+            struct PointLight {
+                vec4 position_reach;
+                vec4 color;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Lights {
+                PointLight lights[];
+            };
+            // For each tile, how many lights reach it, then the first of them, up to
+            // `max_lights_per_tile`.
+            layout(set = 0, binding = 1) writeonly buffer Tiles {
+                uint tiles[];
+            };
+            layout(set = 0, binding = 2) buffer Overflow {
+                uint overflowed_tiles;
+            };
+
+            layout(push_constant) uniform Cull {
+                mat4 inverse_view_projection;
+                uvec2 extent;
+                uvec2 tiles;
+                uint tile_size;
+                uint max_lights_per_tile;
+                uint light_count;
+            } cull;
+
+            shared uint reaching;
+            // Facing into the tile, each with its distance from the origin in `w`.
+            shared vec4 planes[4];
+
+            // The point at `pixel` of the view, at `depth`, in world space.
+            vec3 unproject(vec2 pixel, float depth) {
+                vec2 ndc = pixel / vec2(cull.extent) * 2.0 - 1.0;
+                vec4 world = cull.inverse_view_projection * vec4(ndc, depth, 1.0);
+                return world.xyz / world.w;
+            }
+
+            void main() {
+                uvec2 tile = gl_WorkGroupID.xy;
+                uint base = (tile.y * cull.tiles.x + tile.x) * (cull.max_lights_per_tile + 1);
+                if (gl_LocalInvocationIndex == 0) {
+                    reaching = 0;
+                    vec2 low = vec2(tile * cull.tile_size);
+                    vec2 high = min(low + float(cull.tile_size), vec2(cull.extent));
+                    vec2 corners[4] = vec2[](low, vec2(high.x, low.y), high, vec2(low.x, high.y));
+                    vec3 inside = unproject((low + high) * 0.5, 0.5);
+                    // Each side's plane goes through the lines from the near to the far plane
+                    // at its two corners, which works for orthographic views as well.
+                    for (int i = 0; i < 4; i++) {
+                        vec3 near = unproject(corners[i], 0.0);
+                        vec3 along = unproject(corners[(i + 1) % 4], 0.0) - near;
+                        vec3 away = unproject(corners[i], 1.0) - near;
+                        vec3 normal = normalize(cross(along, away));
+                        float distance = dot(normal, near);
+                        if (dot(normal, inside) < distance) {
+                            normal = -normal;
+                            distance = -distance;
+                        }
+                        planes[i] = vec4(normal, distance);
+                    }
+                }
+                barrier();
+
+                for (uint i = gl_LocalInvocationIndex; i < cull.light_count; i += gl_WorkGroupSize.x) {
+                    vec4 light = lights[i].position_reach;
+                    bool inside = true;
+                    for (int side = 0; side < 4; side++) {
+                        inside = inside && dot(planes[side].xyz, light.xyz) - planes[side].w >= -light.w;
+                    }
+                    if (inside) {
+                        uint slot = atomicAdd(reaching, 1);
+                        if (slot < cull.max_lights_per_tile) {
+                            tiles[base + 1 + slot] = i;
+                        }
+                    }
+                }
+                barrier();
+
+                if (gl_LocalInvocationIndex == 0) {
+                    tiles[base] = reaching;
+                    if (reaching > cull.max_lights_per_tile) {
+                        atomicAdd(overflowed_tiles, 1);
+                    }
+                }
+            }
+        "
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec3 v_position;
+            layout(location = 1) out vec3 v_normal;
+            layout(location = 2) out vec3 v_albedo;
+            layout(location = 3) out vec4 v_clip;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view_projection;
+                uvec2 extent;
+                uint tile_size;
+                uint max_lights_per_tile;
+                uint light_count;
+                uint lighting;
+                uint heatmap;
+            } frame;
+
+            const float FLOOR_HALF_SIZE = 6.0;
+            const float FLOOR_TOP = -0.5;
+            const float FLOOR_THICKNESS = 0.1;
+            const float PILLAR_HEIGHT = 1.5;
+            const float PILLAR_HALF_WIDTH = 0.15;
+            const float PILLAR_SPACING = 1.6;
+            const uint PILLARS_ACROSS = 7;
+
+            const vec3 FACES[6] = vec3[](
+                vec3(1.0, 0.0, 0.0), vec3(-1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0),
+                vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0)
+            );
+            const vec2 CORNERS[6] = vec2[](
+                vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+                vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+            );
+
+            // Instance 0 is the floor, and the rest the pillars standing on it, each a box made
+            // from the vertex index so no vertex buffer is needed.
+            void main() {
+                vec3 normal = FACES[gl_VertexIndex / 6];
+                vec2 corner = CORNERS[gl_VertexIndex % 6];
+                // A corner of that face of the cube from -1 to 1 on every axis.
+                vec3 unit = normal + corner.x * normal.zxy + corner.y * normal.yzx;
+
+                vec3 center;
+                vec3 half_size;
+                if (gl_InstanceIndex == 0) {
+                    center = vec3(0.0, FLOOR_TOP - FLOOR_THICKNESS * 0.5, 0.0);
+                    half_size = vec3(FLOOR_HALF_SIZE, FLOOR_THICKNESS * 0.5, FLOOR_HALF_SIZE);
+                    v_albedo = vec3(0.55);
+                } else {
+                    uint pillar = uint(gl_InstanceIndex) - 1;
+                    vec2 cell = vec2(pillar % PILLARS_ACROSS, pillar / PILLARS_ACROSS)
+                        - float(PILLARS_ACROSS - 1) * 0.5;
+                    center = vec3(cell.x, 0.0, cell.y) * PILLAR_SPACING;
+                    center.y = FLOOR_TOP + PILLAR_HEIGHT * 0.5;
+                    half_size = vec3(PILLAR_HALF_WIDTH, PILLAR_HEIGHT * 0.5, PILLAR_HALF_WIDTH);
+                    v_albedo = vec3(0.8, 0.78, 0.74);
+                }
+                v_position = center + unit * half_size;
+                v_normal = normal;
+                v_clip = frame.view_projection * vec4(v_position, 1.0);
+                gl_Position = v_clip;
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_position;
+            layout(location = 1) in vec3 v_normal;
+            layout(location = 2) in vec3 v_albedo;
+            layout(location = 3) in vec4 v_clip;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view_projection;
+                uvec2 extent;
+                uint tile_size;
+                uint max_lights_per_tile;
+                uint light_count;
+                uint lighting;
+                uint heatmap;
+            } frame;
+
+            struct PointLight {
+                vec4 position_reach;
+                vec4 color;
+            };
+
+            layout(set = 1, binding = 0) readonly buffer Lights {
+                PointLight lights[];
+            };
+            layout(set = 1, binding = 1) readonly buffer Tiles {
+                uint tiles[];
+            };
+
+            const uint EVERY_LIGHT = 1;
+            const vec3 AMBIENT = vec3(0.02);
+
+            // What `light` adds to a surface at `v_position` facing `normal`. The light falls
+            // off with the square of the distance, smoothly reaching nothing at its reach so
+            // the tiles it wasn't binned into don't show.
+            vec3 lit_by(PointLight light, vec3 normal) {
+                vec3 to_light = light.position_reach.xyz - v_position;
+                float distance = length(to_light);
+                float reached = distance / light.position_reach.w;
+                float window = clamp(1.0 - reached * reached * reached * reached, 0.0, 1.0);
+                float falloff = window * window / (distance * distance + 1.0);
+                return light.color.rgb * falloff * max(dot(normal, to_light / distance), 0.0);
+            }
+
+            // From blue for no lights through green and yellow to red for a full tile.
+            vec3 heat(float full) {
+                return clamp(1.5 - abs(4.0 * full - vec3(3.0, 2.0, 1.0)), 0.0, 1.0);
+            }
+
+            void main() {
+                vec3 normal = normalize(v_normal);
+                // The tile the fragment is in, counting from the corner of the view rather than
+                // of the whole target, which may hold other views too.
+                vec2 pixel = (v_clip.xy / v_clip.w * 0.5 + 0.5) * vec2(frame.extent);
+                uvec2 tiles = (frame.extent + frame.tile_size - 1) / frame.tile_size;
+                uvec2 tile = min(uvec2(max(pixel, 0.0)) / frame.tile_size, tiles - 1);
+                uint base = (tile.y * tiles.x + tile.x) * (frame.max_lights_per_tile + 1);
+
+                vec3 radiance = vec3(0.0);
+                if (frame.lighting == EVERY_LIGHT) {
+                    for (uint i = 0; i < frame.light_count; i++) {
+                        radiance += lit_by(lights[i], normal);
+                    }
+                } else {
+                    uint count = min(tiles[base], frame.max_lights_per_tile);
+                    for (uint i = 0; i < count; i++) {
+                        radiance += lit_by(lights[tiles[base + 1 + i]], normal);
+                    }
+                }
+                vec3 color = v_albedo * (AMBIENT + radiance);
+
+                if (frame.heatmap != 0) {
+                    uint reaching = tiles[base];
+                    // Tiles that dropped lights are white.
+                    vec3 shown = reaching > frame.max_lights_per_tile
+                        ? vec3(1.0)
+                        : heat(float(reaching) / float(frame.max_lights_per_tile));
+                    color = mix(color, shown, 0.6);
+                }
+                f_color = vec4(color, 1.0);
+            }
+        "
+    }
+}
+
+std140_layout!(vs::Frame {
+    view_projection: [[f32; 4]; 4] => Mat4,
+    extent: [u32; 2] => Vec2,
+    tile_size: u32 => Scalar,
+    max_lights_per_tile: u32 => Scalar,
+    light_count: u32 => Scalar,
+    lighting: u32 => Scalar,
+    heatmap: u32 => Scalar,
+});
+
+/// One light's way round the floor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Orbit {
+    radius: f32,
+    /// Where it starts, in radians round from +X.
+    angle: f32,
+    /// Radians per second, either way round.
+    speed: f32,
+    height: f32,
+    reach: f32,
+    color: [f32; 3],
+}
+
+impl Orbit {
+    /// The light where it is `time` seconds in, bobbing a little up and down as it goes round.
+    fn light(&self, time: f32) -> PointLight {
+        let angle = self.angle + self.speed * time;
+        let (sin, cos) = angle.sin_cos();
+        let bob = 0.1 * (2.0 * angle).sin();
+        PointLight {
+            position_reach: [
+                self.radius * cos,
+                FLOOR_TOP + self.height + bob,
+                self.radius * sin,
+                self.reach,
+            ],
+            color: [self.color[0], self.color[1], self.color[2], 0.0],
+        }
+    }
+}
+
+/// `count` lights' orbits, spread evenly over the floor in every colour. Always the same for the
+/// same `count`.
+fn orbits(count: u32) -> Vec<Orbit> {
+    // xorshift32, which is plenty for scattering lights. Its state must never be zero.
+    let mut state = 0x9e37_79b9_u32;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    };
+    let lerp = |[from, to]: [f32; 2], t: f32| from + (to - from) * t;
+    (0..count)
+        .map(|_| {
+            let radius = ORBIT_RADIUS * random().sqrt();
+            let angle = std::f32::consts::TAU * random();
+            // Slower further out, so the outer lights don't race round.
+            let direction = if random() < 0.5 { -1.0 } else { 1.0 };
+            let speed = direction * (0.2 + 0.4 * random()) / (0.5 + radius);
+            let height = lerp(HEIGHT, random());
+            let reach = lerp(REACH, random());
+            let hue = random();
+            let color = [0.0, 1.0 / 3.0, 2.0 / 3.0]
+                .map(|offset| 2.0 * (0.5 + 0.5 * (std::f32::consts::TAU * (hue + offset)).cos()));
+            Orbit {
+                radius,
+                angle,
+                speed,
+                height,
+                reach,
+                color,
+            }
+        })
+        .collect()
+}
+
+/// The sets binding a slot's tile lists.
+struct TileLists {
+    /// How many tiles the buffer has room for, perhaps more than the slot's frame uses.
+    capacity: u32,
+    cull_set: Arc<PersistentDescriptorSet>,
+    draw_set: Arc<PersistentDescriptorSet>,
+}
+
+/// What one frame slot bins and draws its lights with.
+struct Slot {
+    lights: Subbuffer<[PointLight]>,
+    /// How many tiles the slot's last cull pass dropped lights from, read back and cleared once
+    /// the GPU is done with it.
+    overflow: Subbuffer<u32>,
+    /// Made in `prepare`, once the view's size is known, and remade when it outgrows them.
+    tiles: Option<TileLists>,
+    /// Whether the slot's frame bins its lights, which it needn't when every pixel goes through
+    /// every light and the heatmap is hidden.
+    culled: bool,
+}
+
+/// Point lights over a floor of pillars, each pixel lit by those binned into its tile.
+pub struct LightsScene {
+    config: LightsConfig,
+    orbits: Vec<Orbit>,
+    cull_pipeline: Arc<ComputePipeline>,
+    pipeline: Arc<GraphicsPipeline>,
+    uniforms: FrameUniforms<vs::Frame>,
+    slots: Vec<Slot>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    memory_tracker: Arc<MemoryTracker>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    max_storage_buffer_range: DeviceSize,
+    lighting: Lighting,
+    heatmap: bool,
+    /// Tiles that dropped lights, over every frame so far.
+    overflowed_tiles: u64,
+}
+
+impl LightsScene {
+    pub fn new(
+        ctx: &VulkanContext,
+        subpass: Subpass,
+        config: LightsConfig,
+    ) -> Result<Self, RendererError> {
+        let config = LightsConfig {
+            count: config.count.max(1),
+            tile_size: config.tile_size.max(1),
+            max_per_tile: config.max_per_tile.max(1),
+            ..config
+        };
+        let max_storage_buffer_range = DeviceSize::from(ctx.caps.limits.max_storage_buffer_range);
+        let size = DeviceSize::from(config.count) * mem::size_of::<PointLight>() as DeviceSize;
+        if size > max_storage_buffer_range {
+            return Err(RendererError::UnsupportedScene(format!(
+                "{} lights take {size} bytes, more than the device's storage buffers hold",
+                config.count
+            )));
+        }
+
+        let cull_pipeline = build_compute_pipeline(ctx, cull_cs::load(ctx.device.clone())?)?;
+        ctx.name_object(&cull_pipeline, "lights cull pipeline");
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let pipeline =
+            build_pipeline(ctx.device.clone(), vs, fs, VertexInputState::new(), subpass)?;
+        ctx.name_object(&pipeline, "lights pipeline");
+        let lighting = if config.naive {
+            Lighting::EveryLight
+        } else {
+            Lighting::Tiled
+        };
+        let uniforms = FrameUniforms::new(
+            ctx,
+            &pipeline,
+            0,
+            frame_uniform(&FrameData::default(), &config, lighting, false),
+        )?;
+
+        let orbits = orbits(config.count);
+        let slots = (0..FRAME_SLOTS)
+            .map(|slot| {
+                let lights = Buffer::from_iter(
+                    ctx.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    orbits.iter().map(|orbit| orbit.light(0.0)),
+                )?;
+                ctx.memory_tracker
+                    .track_buffer(MemoryCategory::Storage, lights.buffer());
+                ctx.name_object(lights.buffer(), &format!("lights {slot}"));
+                let overflow = Buffer::from_data(
+                    ctx.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                            | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                        ..Default::default()
+                    },
+                    0u32,
+                )?;
+                ctx.memory_tracker
+                    .track_buffer(MemoryCategory::Storage, overflow.buffer());
+                Ok(Slot {
+                    lights,
+                    overflow,
+                    tiles: None,
+                    culled: false,
+                })
+            })
+            .collect::<Result<Vec<_>, RendererError>>()?;
+
+        log::info!(
+            "Lighting {} lights in tiles of {size}x{size} pixels, each listing at most {}",
+            config.count,
+            config.max_per_tile,
+            size = config.tile_size,
+        );
+        Ok(Self {
+            config,
+            orbits,
+            cull_pipeline,
+            pipeline,
+            uniforms,
+            slots,
+            memory_allocator: ctx.memory_allocator.clone(),
+            memory_tracker: ctx.memory_tracker.clone(),
+            descriptor_set_allocator: ctx.descriptor_set_allocator.clone(),
+            max_storage_buffer_range,
+            lighting,
+            heatmap: false,
+            overflowed_tiles: 0,
+        })
+    }
+
+    /// Adds up the tiles `slot`'s last cull pass dropped lights from, warning the first time any
+    /// did, and clears the count for the next.
+    fn count_overflows(&mut self, slot: usize) -> Result<(), RendererError> {
+        let overflowed = mem::take(&mut *self.slots[slot].overflow.write()?);
+        if overflowed == 0 {
+            return Ok(());
+        }
+        if self.overflowed_tiles == 0 {
+            log::warn!(
+                "{overflowed} tiles are reached by more than {} lights, and are lit by only that \
+                 many; --max-lights-per-tile raises the limit",
+                self.config.max_per_tile
+            );
+        }
+        self.overflowed_tiles += u64::from(overflowed);
+        Ok(())
+    }
+
+    /// Makes sure `slot` has room for the lists of `tiles` tiles.
+    fn reserve_tiles(&mut self, slot: usize, tiles: u32) -> Result<(), RendererError> {
+        if self.slots[slot]
+            .tiles
+            .as_ref()
+            .is_some_and(|lists| lists.capacity >= tiles)
+        {
+            return Ok(());
+        }
+        let len = DeviceSize::from(tiles) * DeviceSize::from(self.config.tile_stride());
+        let size = len * mem::size_of::<u32>() as DeviceSize;
+        if size > self.max_storage_buffer_range {
+            return Err(RendererError::UnsupportedScene(format!(
+                "{tiles} tiles of up to {} lights take {size} bytes, more than the device's \
+                 storage buffers hold",
+                self.config.max_per_tile
+            )));
+        }
+        let buffer = Buffer::new_slice::<u32>(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            len,
+        )?;
+        self.memory_tracker
+            .track_buffer(MemoryCategory::Storage, buffer.buffer());
+
+        let Slot {
+            lights, overflow, ..
+        } = &self.slots[slot];
+        let cull_set = PersistentDescriptorSet::new(
+            self.descriptor_set_allocator.as_ref(),
+            self.cull_pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, lights.clone()),
+                WriteDescriptorSet::buffer(1, buffer.clone()),
+                WriteDescriptorSet::buffer(2, overflow.clone()),
+            ],
+            [],
+        )?;
+        let draw_set = PersistentDescriptorSet::new(
+            self.descriptor_set_allocator.as_ref(),
+            self.pipeline.layout().set_layouts()[1].clone(),
+            [
+                WriteDescriptorSet::buffer(0, lights.clone()),
+                WriteDescriptorSet::buffer(1, buffer.clone()),
+            ],
+            [],
+        )?;
+        self.slots[slot].tiles = Some(TileLists {
+            capacity: tiles,
+            cull_set,
+            draw_set,
+        });
+        Ok(())
+    }
+}
+
+impl Scene for LightsScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        let slot = frame.frame_in_flight;
+        self.count_overflows(slot)?;
+        let [across, down] = self.config.tiles(frame.extent);
+        self.reserve_tiles(slot, across * down)?;
+
+        let culled = self.lighting == Lighting::Tiled || self.heatmap;
+        self.slots[slot].culled = culled;
+        let mut lights = self.slots[slot].lights.write()?;
+        for (light, orbit) in lights.iter_mut().zip(&self.orbits) {
+            *light = orbit.light(frame.time);
+        }
+        drop(lights);
+        self.uniforms.write(
+            frame,
+            frame_uniform(frame, &self.config, self.lighting, self.heatmap),
+        )
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let slot = &self.slots[frame.frame_in_flight];
+        let Some(lists) = slot.tiles.as_ref().filter(|_| slot.culled) else {
+            return Ok(());
+        };
+        let extent = view_extent(frame);
+        let tiles = self.config.tiles(extent);
+        builder
+            .bind_pipeline_compute(self.cull_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.cull_pipeline.layout().clone(),
+                0,
+                lists.cull_set.clone(),
+            )?
+            .push_constants(
+                self.cull_pipeline.layout().clone(),
+                0,
+                cull_cs::Cull {
+                    inverse_view_projection: (frame.projection * frame.view)
+                        .inverse()
+                        .to_cols_array_2d(),
+                    extent,
+                    tiles,
+                    tile_size: self.config.tile_size,
+                    max_lights_per_tile: self.config.max_per_tile,
+                    light_count: self.config.count,
+                },
+            )?
+            .dispatch([tiles[0], tiles[1], 1])?;
+        Ok(())
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            HEATMAP_KEY => {
+                self.heatmap = !self.heatmap;
+                if self.heatmap {
+                    log::info!(
+                        "Showing how many lights reach each tile; {} tiles have dropped lights \
+                         so far",
+                        self.overflowed_tiles
+                    );
+                } else {
+                    log::info!("Hiding how many lights reach each tile");
+                }
+            }
+            LIGHTING_KEY => {
+                self.lighting = self.lighting.toggled();
+                log::info!("Lighting each pixel with {}", self.lighting.name());
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: Vec3::new(-FLOOR_HALF_SIZE, FLOOR_TOP, -FLOOR_HALF_SIZE),
+            max: Vec3::new(FLOOR_HALF_SIZE, FLOOR_TOP + PILLAR_HEIGHT, FLOOR_HALF_SIZE),
+        })
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let Some(lists) = self.slots[frame.frame_in_flight].tiles.as_ref() else {
+            return Ok(());
+        };
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                vec![self.uniforms.descriptor_set(frame), lists.draw_set.clone()],
+            )?
+            .draw(BOX_VERTICES, 1 + PILLARS_ACROSS * PILLARS_ACROSS, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// `frame`'s extent, or a single pixel where it isn't known, so the tiles can always be worked
+/// out from it.
+fn view_extent(frame: &FrameData) -> [u32; 2] {
+    frame.extent.map(|length| length.max(1))
+}
+
+fn frame_uniform(
+    frame: &FrameData,
+    config: &LightsConfig,
+    lighting: Lighting,
+    heatmap: bool,
+) -> vs::Frame {
+    vs::Frame {
+        view_projection: (frame.projection * frame.view).to_cols_array_2d(),
+        extent: view_extent(frame),
+        tile_size: config.tile_size,
+        max_lights_per_tile: config.max_per_tile,
+        light_count: config.count,
+        lighting: lighting as u32,
+        heatmap: heatmap.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_the_view() {
+        let config = LightsConfig::default();
+        assert_eq!(config.tiles([1920, 1080]), [120, 68]);
+        assert_eq!(config.tiles([16, 17]), [1, 2]);
+        assert_eq!(config.tiles([0, 0]), [1, 1]);
+        let config = LightsConfig {
+            tile_size: 32,
+            max_per_tile: 63,
+            ..config
+        };
+        assert_eq!(config.tiles([1920, 1080]), [60, 34]);
+        assert_eq!(config.tile_stride(), 64);
+    }
+
+    #[test]
+    fn lights_circle_over_the_floor() {
+        let orbits = orbits(DEFAULT_LIGHTS);
+        assert_eq!(orbits.len(), DEFAULT_LIGHTS as usize);
+        assert_eq!(orbits, self::orbits(DEFAULT_LIGHTS));
+        for time in [0.0, 1.5, 60.0] {
+            for orbit in &orbits {
+                let [x, y, z, reach] = orbit.light(time).position_reach;
+                assert!(x.hypot(z) <= ORBIT_RADIUS + 1e-4);
+                assert!(y > FLOOR_TOP && y < FLOOR_TOP + HEIGHT[1] + 0.1 + 1e-4);
+                assert!((REACH[0]..=REACH[1]).contains(&reach));
+            }
+        }
+        // They move, at their own speeds.
+        let moved = orbits
+            .iter()
+            .filter(|orbit| orbit.light(0.0) != orbit.light(1.0))
+            .count();
+        assert_eq!(moved, orbits.len());
+    }
+}
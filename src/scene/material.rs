@@ -0,0 +1,573 @@
+//! How things are drawn, kept apart from what is drawn: a [`Material`] is a pipeline with the
+//! descriptor sets that go with it, and the [`Node`]s a scene draws refer to theirs by
+//! [`MaterialId`].
+//!
+//! Materials drawn with the same shaders can still need different fixed-function state, such as
+//! blending or culling, which their [`PipelineVariant`] says. [`PipelineVariants`] builds one
+//! pipeline for each variant in use, and a [`MaterialRegistry`] makes one material for each
+//! distinct description of one, so draws sorted by pipeline and then material bind as little as
+//! they can.
+
+use std::ops::{AddAssign, Index, Range};
+use std::sync::Arc;
+
+use vulkano::buffer::{BufferContents, IndexBuffer, Subbuffer};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor_set::{DescriptorSetsCollection, PersistentDescriptorSet};
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout};
+use vulkano::render_pass::Subpass;
+use vulkano::shader::EntryPoint;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::scene::{build_pipeline_variant, FrameData, FRAME_SLOTS};
+
+/// How a material's fragments cover what is behind them, as glTF has it. In the order they are
+/// drawn in, so blended materials are drawn over everything else.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments with alpha under a cutoff are discarded, and the rest are opaque.
+    Mask,
+    /// Fragments are blended over what is behind them by their alpha, without writing depth.
+    Blend,
+}
+
+/// The fixed-function state a material needs of its pipeline, beyond what its shaders declare.
+/// Draws sorted by variant draw blended materials last.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PipelineVariant {
+    pub alpha_mode: AlphaMode,
+    /// Draws back faces too, which are culled otherwise.
+    pub double_sided: bool,
+}
+
+/// Which part of the frame a pipeline draws in, for drawing opaque surfaces with a depth
+/// pre-pass: their depth first, and then their shading only where they turned out nearest, so
+/// fragment shaders don't run for what ends up hidden.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DepthPass {
+    /// Without a pre-pass, testing and writing depth as the variant needs.
+    #[default]
+    Single,
+    /// Writes only depth.
+    Prepass,
+    /// Shades what has the depth the pre-pass wrote, without writing any.
+    Shading,
+}
+
+/// One set of shaders' pipelines, one for each [`PipelineVariant`] and [`DepthPass`] asked for,
+/// built the first time it is.
+pub struct PipelineVariants {
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+    /// Names the pipelines, followed by their variant.
+    label: String,
+    pipelines: Vec<((PipelineVariant, DepthPass), Arc<GraphicsPipeline>)>,
+}
+
+impl PipelineVariants {
+    pub fn new(
+        vs: EntryPoint,
+        fs: EntryPoint,
+        vertex_input_state: VertexInputState,
+        subpass: Subpass,
+        label: &str,
+    ) -> Self {
+        Self {
+            vs,
+            fs,
+            vertex_input_state,
+            subpass,
+            label: label.to_owned(),
+            pipelines: Vec::new(),
+        }
+    }
+
+    /// The pipeline for `variant`, building it if this is the first time it's asked for.
+    pub fn get(
+        &mut self,
+        ctx: &VulkanContext,
+        variant: PipelineVariant,
+    ) -> Result<Arc<GraphicsPipeline>, RendererError> {
+        self.get_for(ctx, variant, DepthPass::Single)
+    }
+
+    /// Like [`get`](Self::get), but for drawing in `depth_pass`, which only opaque variants
+    /// take part in.
+    pub fn get_for(
+        &mut self,
+        ctx: &VulkanContext,
+        variant: PipelineVariant,
+        depth_pass: DepthPass,
+    ) -> Result<Arc<GraphicsPipeline>, RendererError> {
+        let key = (variant, depth_pass);
+        if let Some((_, pipeline)) = self.pipelines.iter().find(|(built, _)| *built == key) {
+            return Ok(pipeline.clone());
+        }
+        let pipeline = build_pipeline_variant(
+            ctx.device.clone(),
+            self.vs.clone(),
+            self.fs.clone(),
+            self.vertex_input_state.clone(),
+            self.subpass.clone(),
+            variant,
+            depth_pass,
+        )?;
+        let name = match depth_pass {
+            DepthPass::Single => format!("{} ({variant:?})", self.label),
+            _ => format!("{} ({variant:?}, {depth_pass:?})", self.label),
+        };
+        ctx.name_object(&pipeline, &name);
+        self.pipelines.push((key, pipeline.clone()));
+        Ok(pipeline)
+    }
+
+    /// How many variants have been built.
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+}
+
+/// A descriptor set belonging to a material.
+#[derive(Clone)]
+pub enum MaterialSet {
+    /// The same set every frame, e.g. for textures that never change.
+    Shared(Arc<PersistentDescriptorSet>),
+    /// One set per frame slot, for resources that are rewritten or re-rendered every frame.
+    PerFrame(Vec<Arc<PersistentDescriptorSet>>),
+}
+
+impl MaterialSet {
+    fn get(&self, frame: &FrameData) -> Arc<PersistentDescriptorSet> {
+        match self {
+            Self::Shared(set) => set.clone(),
+            Self::PerFrame(sets) => sets[frame.frame_in_flight].clone(),
+        }
+    }
+}
+
+/// A pipeline and the descriptor sets every draw with it uses.
+///
+/// The material's sets come after the ones each object binds for itself, starting at
+/// `first_set`. Push constants are left to the draws, through the material's layout.
+#[derive(Clone)]
+pub struct Material {
+    pipeline: Arc<GraphicsPipeline>,
+    first_set: u32,
+    sets: Vec<MaterialSet>,
+}
+
+impl Material {
+    /// A material without descriptor sets of its own.
+    pub fn new(pipeline: Arc<GraphicsPipeline>) -> Self {
+        Self {
+            pipeline,
+            first_set: 0,
+            sets: Vec::new(),
+        }
+    }
+
+    /// Binds `sets` to consecutive set numbers from `first_set` on whenever the material is.
+    ///
+    /// # Panics
+    ///
+    /// If a [`MaterialSet::PerFrame`] doesn't have a set for every frame slot.
+    pub fn with_sets(
+        mut self,
+        first_set: u32,
+        sets: impl IntoIterator<Item = MaterialSet>,
+    ) -> Self {
+        self.first_set = first_set;
+        self.sets = sets.into_iter().collect();
+        for set in &self.sets {
+            if let MaterialSet::PerFrame(sets) = set {
+                assert_eq!(sets.len(), FRAME_SLOTS);
+            }
+        }
+        self
+    }
+
+    /// The same material drawn with `pipeline`, whose layout must be compatible with the
+    /// material's, e.g. its pipeline for another [`DepthPass`].
+    pub fn with_pipeline(&self, pipeline: Arc<GraphicsPipeline>) -> Self {
+        Self {
+            pipeline,
+            ..self.clone()
+        }
+    }
+
+    pub fn pipeline(&self) -> &Arc<GraphicsPipeline> {
+        &self.pipeline
+    }
+
+    pub fn layout(&self) -> &Arc<PipelineLayout> {
+        self.pipeline.layout()
+    }
+
+    /// Like [`bind`](Self::bind), but after `previous` was bound, so only binds the pipeline if
+    /// `previous` has another. Returns whether it did, which unbinds any sets of other layouts.
+    pub fn bind_after<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        frame: &FrameData,
+        previous: Option<&Material>,
+    ) -> Result<bool, RendererError> {
+        let new_pipeline =
+            previous.is_none_or(|previous| !Arc::ptr_eq(&previous.pipeline, &self.pipeline));
+        if new_pipeline {
+            builder.bind_pipeline_graphics(self.pipeline.clone())?;
+        }
+        if !self.sets.is_empty() {
+            let sets: Vec<_> = self.sets.iter().map(|set| set.get(frame)).collect();
+            self.bind_sets(builder, self.first_set, sets)?;
+        }
+        Ok(new_pipeline)
+    }
+
+    /// Binds the pipeline and the material's sets for `frame`.
+    pub fn bind<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder.bind_pipeline_graphics(self.pipeline.clone())?;
+        if !self.sets.is_empty() {
+            let sets: Vec<_> = self.sets.iter().map(|set| set.get(frame)).collect();
+            self.bind_sets(builder, self.first_set, sets)?;
+        }
+        Ok(())
+    }
+
+    /// Binds sets that aren't the material's, such as an object's uniforms, to the material's
+    /// layout from `first_set` on.
+    pub fn bind_sets<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        first_set: u32,
+        sets: impl DescriptorSetsCollection,
+    ) -> Result<(), RendererError> {
+        builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            self.layout().clone(),
+            first_set,
+            sets,
+        )?;
+        Ok(())
+    }
+
+    /// Sets the push constants the material's shaders declare, from offset zero.
+    pub fn push_constants<L, Pc: BufferContents>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        push_constants: Pc,
+    ) -> Result<(), RendererError> {
+        builder.push_constants(self.layout().clone(), 0, push_constants)?;
+        Ok(())
+    }
+}
+
+/// Refers to a material in a [`Materials`] list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(usize);
+
+/// The materials a scene draws with, looked up by [`MaterialId`].
+#[derive(Default)]
+pub struct Materials(Vec<Material>);
+
+impl Materials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, material: Material) -> MaterialId {
+        self.0.push(material);
+        MaterialId(self.0.len() - 1)
+    }
+}
+
+/// Hands out one [`MaterialId`] for each distinct description of a material, so identical
+/// materials share their descriptor sets and binding one after the other binds nothing.
+#[derive(Clone, Debug)]
+pub struct MaterialRegistry<K> {
+    /// Each description, with the material made for it.
+    entries: Vec<(K, MaterialId)>,
+}
+
+impl<K> Default for MaterialRegistry<K> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<K: PartialEq> MaterialRegistry<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The material made for `key` before, or the one `add` makes for it now. Compared by
+    /// equality, as descriptions with factors in them can't be hashed.
+    pub fn get_or_add<E>(
+        &mut self,
+        key: K,
+        add: impl FnOnce(&K) -> Result<MaterialId, E>,
+    ) -> Result<MaterialId, E> {
+        if let Some(&(_, id)) = self.entries.iter().find(|(existing, _)| *existing == key) {
+            return Ok(id);
+        }
+        let id = add(&key)?;
+        self.entries.push((key, id));
+        Ok(id)
+    }
+
+    /// How many distinct materials there are.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Index<MaterialId> for Materials {
+    type Output = Material;
+
+    fn index(&self, id: MaterialId) -> &Material {
+        &self.0[id.0]
+    }
+}
+
+/// Something a scene draws: a vertex buffer, optionally indexed, and the material it is drawn
+/// with.
+///
+/// Nodes can share their buffers, drawing different ranges of the same index buffer, so nodes
+/// drawn one after the other with [`draw_after`](Self::draw_after) only bind them once.
+pub struct Node {
+    pub material: MaterialId,
+    /// `None` for nodes whose vertex shader fetches the vertices itself.
+    vertex_buffer: Option<Subbuffer<[u8]>>,
+    vertex_count: u32,
+    index_buffer: Option<IndexBuffer>,
+    /// The indices of `index_buffer` drawn, all of them unless narrowed.
+    indices: Range<u32>,
+}
+
+impl Node {
+    pub fn new<V: BufferContents>(material: MaterialId, vertex_buffer: Subbuffer<[V]>) -> Self {
+        Self {
+            material,
+            vertex_count: vertex_buffer.len() as u32,
+            vertex_buffer: Some(vertex_buffer.into_bytes()),
+            index_buffer: None,
+            indices: 0..0,
+        }
+    }
+
+    /// A node drawing the triangles `index_buffer` picks out of vertices the material's vertex
+    /// shader fetches by index from a buffer of its own, with no vertex buffer bound.
+    pub fn pulled(material: MaterialId, index_buffer: impl Into<IndexBuffer>) -> Self {
+        let index_buffer = index_buffer.into();
+        Self {
+            material,
+            vertex_buffer: None,
+            vertex_count: 0,
+            indices: 0..index_buffer.len() as u32,
+            index_buffer: Some(index_buffer),
+        }
+    }
+
+    /// A node drawing the triangles `index_buffer` picks out of `vertex_buffer`, which can be
+    /// shared with other nodes. The indices can be of any type the device supports.
+    pub fn indexed<V: BufferContents>(
+        material: MaterialId,
+        vertex_buffer: Subbuffer<[V]>,
+        index_buffer: impl Into<IndexBuffer>,
+    ) -> Self {
+        let index_buffer = index_buffer.into();
+        Self {
+            indices: 0..index_buffer.len() as u32,
+            index_buffer: Some(index_buffer),
+            ..Self::new(material, vertex_buffer)
+        }
+    }
+
+    /// Draws only the indices `range` of the node's index buffer, so one index buffer can hold
+    /// the indices of many nodes and be bound once for them all.
+    ///
+    /// # Panics
+    ///
+    /// If the node isn't indexed, or `range` goes past the end of its index buffer.
+    pub fn with_indices(mut self, range: Range<u32>) -> Self {
+        let index_buffer = self.index_buffer.as_ref().expect("the node isn't indexed");
+        assert!(range.start <= range.end && u64::from(range.end) <= index_buffer.len());
+        self.indices = range;
+        self
+    }
+
+    /// Whether the node draws from the same vertex and index buffers as `other`, which drawing
+    /// it after `other` then leaves bound.
+    pub fn shares_buffers_with(&self, other: &Node) -> bool {
+        let index_bytes = |node: &Node| {
+            node.index_buffer
+                .as_ref()
+                .map(|indices| (indices.index_type(), indices.as_bytes().clone()))
+        };
+        self.vertex_buffer == other.vertex_buffer && index_bytes(self) == index_bytes(other)
+    }
+
+    /// Records the node's draw. Its material and per-object sets must already be bound.
+    pub fn draw<L>(&self, builder: &mut AutoCommandBufferBuilder<L>) -> Result<(), RendererError> {
+        self.draw_after(builder, None)?;
+        Ok(())
+    }
+
+    /// Like [`draw`](Self::draw), but after `previous` was drawn, so only binds the buffers if
+    /// `previous` drew from others. Returns whether it did.
+    pub fn draw_after<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        previous: Option<&Node>,
+    ) -> Result<bool, RendererError> {
+        let new_buffers = previous.is_none_or(|previous| !self.shares_buffers_with(previous));
+        if new_buffers {
+            if let Some(vertex_buffer) = &self.vertex_buffer {
+                builder.bind_vertex_buffers(0, vertex_buffer.clone())?;
+            }
+            if let Some(indices) = &self.index_buffer {
+                builder.bind_index_buffer(indices.clone())?;
+            }
+        }
+        match &self.index_buffer {
+            Some(_) => {
+                builder.draw_indexed(self.indices.len() as u32, 1, self.indices.start, 0, 0)?
+            }
+            None => builder.draw(self.vertex_count, 1, 0, 0)?,
+        };
+        Ok(new_buffers)
+    }
+}
+
+/// What a scene's draws bind, and how many draws share what the draw before them bound.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub draws: usize,
+    pub pipeline_binds: usize,
+    /// Materials bound, each binding its sets and, where it differs from the last, its
+    /// pipeline.
+    pub material_binds: usize,
+    /// Vertex and index buffers bound, together.
+    pub buffer_binds: usize,
+}
+
+impl BatchStats {
+    /// Counts drawing `draws` in order, each drawn with the pipeline, material and buffers it
+    /// gives, when each draw only binds what differs from the draw before it.
+    pub fn of<P: PartialEq, M: PartialEq, B: PartialEq>(
+        draws: impl IntoIterator<Item = (P, M, B)>,
+    ) -> Self {
+        let mut stats = Self::default();
+        let mut previous: Option<(P, M, B)> = None;
+        for (pipeline, material, buffers) in draws {
+            stats.draws += 1;
+            let last = previous.as_ref();
+            stats.pipeline_binds += usize::from(last.is_none_or(|(last, _, _)| *last != pipeline));
+            stats.material_binds += usize::from(last.is_none_or(|(_, last, _)| *last != material));
+            stats.buffer_binds += usize::from(last.is_none_or(|(_, _, last)| *last != buffers));
+            previous = Some((pipeline, material, buffers));
+        }
+        stats
+    }
+}
+
+impl AddAssign for BatchStats {
+    fn add_assign(&mut self, other: Self) {
+        self.draws += other.draws;
+        self.pipeline_binds += other.pipeline_binds;
+        self.material_binds += other.material_binds;
+        self.buffer_binds += other.buffer_binds;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_materials_are_made_once() {
+        let mut registry = MaterialRegistry::new();
+        let mut made = 0;
+        let mut get = |registry: &mut MaterialRegistry<(f32, bool)>, key| {
+            registry.get_or_add(key, |_| -> Result<_, ()> {
+                made += 1;
+                Ok(MaterialId(made - 1))
+            })
+        };
+        assert_eq!(get(&mut registry, (0.5, false)), Ok(MaterialId(0)));
+        assert_eq!(get(&mut registry, (0.5, true)), Ok(MaterialId(1)));
+        assert_eq!(get(&mut registry, (0.5, false)), Ok(MaterialId(0)));
+        assert_eq!(registry.len(), 2);
+        // Nothing is registered when making the material fails.
+        assert_eq!(registry.get_or_add((1.0, false), |_| Err("no")), Err("no"));
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn sorted_draws_bind_each_pipeline_and_material_once() {
+        let stats = BatchStats::of([
+            ("opaque", 0, "model"),
+            ("opaque", 0, "model"),
+            ("opaque", 1, "model"),
+            ("blend", 2, "model"),
+            ("blend", 2, "sliders"),
+        ]);
+        assert_eq!(
+            stats,
+            BatchStats {
+                draws: 5,
+                pipeline_binds: 2,
+                material_binds: 3,
+                buffer_binds: 2,
+            }
+        );
+        // Unsorted, the same draws bind far more.
+        let stats = BatchStats::of([(0, 0, 0), (1, 1, 0), (0, 0, 0), (1, 1, 0)]);
+        assert_eq!((stats.pipeline_binds, stats.material_binds), (4, 4));
+        assert_eq!(BatchStats::of::<(), (), ()>([]), BatchStats::default());
+    }
+
+    #[test]
+    fn blended_variants_sort_last() {
+        let variant = |alpha_mode, double_sided| PipelineVariant {
+            alpha_mode,
+            double_sided,
+        };
+        let mut variants = [
+            variant(AlphaMode::Blend, false),
+            variant(AlphaMode::Opaque, true),
+            variant(AlphaMode::Mask, false),
+            variant(AlphaMode::Opaque, false),
+        ];
+        variants.sort();
+        assert_eq!(
+            variants,
+            [
+                variant(AlphaMode::Opaque, false),
+                variant(AlphaMode::Opaque, true),
+                variant(AlphaMode::Mask, false),
+                variant(AlphaMode::Blend, false),
+            ]
+        );
+    }
+}
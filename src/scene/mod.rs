@@ -0,0 +1,964 @@
+//! The things we know how to draw.
+//!
+//! A scene owns its pipeline and GPU resources and records its draw calls into a secondary command
+//! buffer executed inside a render pass, so the same scene can be drawn to the swapchain or to an
+//! offscreen image, and draws that don't change can be recorded once and executed every frame.
+//! Scenes that render to textures of their own do so in a pass beforehand.
+
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::Mat4;
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferInheritanceInfo, CommandBufferUsage,
+    PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::Device;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, SampleCount};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::compute::ComputePipelineCreateInfo;
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, ColorBlendAttachmentState, ColorBlendState, ColorComponents,
+};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::{CullMode, FrontFace, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    ComputePipeline, DynamicState, GraphicsPipeline, Pipeline, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::Subpass;
+use vulkano::shader::{EntryPoint, ShaderModule};
+use winit::event::VirtualKeyCode;
+
+use crate::context::VulkanContext;
+use crate::crash_report;
+use crate::culling::CullStats;
+use crate::environment::Environment;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::model::Model;
+use crate::picking::{Aabb, Ray};
+use crate::shader_dump;
+use crate::std140::{check_std140, std140_layout, Std140Layout};
+
+mod cube;
+mod flag;
+mod life;
+mod lights;
+mod material;
+mod model;
+mod monitor;
+mod nbody;
+mod plasma;
+mod skybox;
+mod sprites;
+mod step_clock;
+mod terrain;
+mod texture_grid;
+mod textured_quad;
+mod trails;
+mod transparency;
+mod triangle;
+
+pub use cube::CubeScene;
+pub use flag::FlagScene;
+pub use life::LifeScene;
+pub use lights::{LightsConfig, LightsScene};
+pub use material::{
+    AlphaMode, BatchStats, DepthPass, Material, MaterialId, MaterialRegistry, MaterialSet,
+    Materials, Node, PipelineVariant, PipelineVariants,
+};
+pub use model::ModelScene;
+pub use monitor::MonitorScene;
+pub use nbody::{benchmark as benchmark_nbody, NBodyReport, NBodyScene, DEFAULT_BODIES};
+pub use plasma::PlasmaScene;
+pub use skybox::WithSkybox;
+pub use sprites::SpritesScene;
+pub use step_clock::{FixedTimestep, DEFAULT_UPDATE_RATE};
+pub use terrain::TerrainScene;
+pub use texture_grid::TextureGridScene;
+pub use textured_quad::TexturedQuadScene;
+pub use trails::TrailsScene;
+pub use transparency::TransparencyScene;
+pub use triangle::TriangleScene;
+
+/// The colour every scene is drawn on top of.
+pub const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// What transparent windows are cleared to instead. Fully transparent black reads the same
+/// whether the compositor expects pre-multiplied colours or not.
+pub const TRANSPARENT_CLEAR_COLOR: [f32; 4] = [0.0; 4];
+
+/// How many frames the CPU may record ahead of the GPU. Anything a scene writes every frame needs
+/// this many copies, so the CPU never writes to memory a frame still in flight is reading.
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// How many windows a scene can be drawn into at once.
+pub const MAX_WINDOWS: usize = 2;
+
+/// How many views of the scene a window can draw: its left and right halves when split, and
+/// the minimap.
+pub const VIEWS_PER_WINDOW: usize = 3;
+
+/// How many views of a scene can be drawn at once. Each is drawn with its own camera, so it needs
+/// its own copies of the per-frame resources.
+pub const MAX_VIEWS: usize = MAX_WINDOWS * VIEWS_PER_WINDOW;
+
+/// Copies of the per-frame resources a scene needs: [`FRAMES_IN_FLIGHT`] for each view.
+pub const FRAME_SLOTS: usize = FRAMES_IN_FLIGHT * MAX_VIEWS;
+
+/// Per-frame values handed to a scene while it records its draws.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameData {
+    pub view: Mat4,
+    pub projection: Mat4,
+    /// Size in pixels of the part of the target the view is drawn into, or zero if unknown.
+    pub extent: [u32; 2],
+    /// Seconds since the app started, for animation.
+    pub time: f32,
+    /// How far this frame is from the last [`Scene::update`] to the next, from 0 to 1, for
+    /// scenes that update at the fixed timestep to draw what is in between.
+    pub update_alpha: f32,
+    /// Which of the [`FRAME_SLOTS`] copies of the per-frame resources this frame uses. The GPU is
+    /// done with everything the previous frame in this slot used.
+    ///
+    /// Each view has [`FRAMES_IN_FLIGHT`] consecutive slots, which its frames take in turn.
+    pub frame_in_flight: usize,
+}
+
+impl FrameData {
+    /// The slot the previous frame of the same view used. Its commands were submitted before
+    /// this frame's, so what it rendered can be read (but not written) this frame.
+    pub fn previous_frame_in_flight(&self) -> usize {
+        let view_start = self.frame_in_flight - self.frame_in_flight % FRAMES_IN_FLIGHT;
+        view_start + (self.frame_in_flight + FRAMES_IN_FLIGHT - 1) % FRAMES_IN_FLIGHT
+    }
+}
+
+impl Default for FrameData {
+    /// Identity matrices, so 3D scenes see clip space directly, at time zero.
+    fn default() -> Self {
+        Self {
+            view: Mat4::IDENTITY,
+            projection: Mat4::IDENTITY,
+            extent: [0, 0],
+            time: 0.0,
+            update_alpha: 0.0,
+            frame_in_flight: 0,
+        }
+    }
+}
+
+/// Model, view and projection matrices and the time, as laid out in the shaders' `Mvp` uniform
+/// block.
+#[derive(BufferContents, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MvpUniform {
+    pub model: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub time: f32,
+}
+
+std140_layout!(MvpUniform {
+    model: [[f32; 4]; 4] => Mat4,
+    view: [[f32; 4]; 4] => Mat4,
+    projection: [[f32; 4]; 4] => Mat4,
+    time: f32 => Scalar,
+});
+
+impl MvpUniform {
+    pub fn new(model: Mat4, frame: &FrameData) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            view: frame.view.to_cols_array_2d(),
+            projection: frame.projection.to_cols_array_2d(),
+            time: frame.time,
+        }
+    }
+}
+
+/// One uniform buffer per frame slot, each with a descriptor set binding it.
+pub struct FrameUniforms<T: BufferContents> {
+    buffers: Vec<Subbuffer<T>>,
+    descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+}
+
+impl<T: BufferContents + Copy + Std140Layout> FrameUniforms<T> {
+    /// Creates the buffers and binds each one at `binding` of set 0 of `pipeline`, after checking
+    /// that `T` is laid out the way the shader reads it.
+    pub fn new(
+        ctx: &VulkanContext,
+        pipeline: &GraphicsPipeline,
+        binding: u32,
+        initial: T,
+    ) -> Result<Self, RendererError> {
+        check_std140::<T>()?;
+        let mut buffers = Vec::with_capacity(FRAME_SLOTS);
+        let mut descriptor_sets = Vec::with_capacity(FRAME_SLOTS);
+        for _ in 0..FRAME_SLOTS {
+            let buffer = Buffer::from_data(
+                ctx.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::UNIFORM_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                initial,
+            )?;
+            ctx.memory_tracker
+                .track_buffer(MemoryCategory::Uniform, buffer.buffer());
+
+            descriptor_sets.push(PersistentDescriptorSet::new(
+                ctx.descriptor_set_allocator.as_ref(),
+                pipeline.layout().set_layouts()[0].clone(),
+                [WriteDescriptorSet::buffer(binding, buffer.clone())],
+                [],
+            )?);
+            buffers.push(buffer);
+        }
+
+        Ok(Self {
+            buffers,
+            descriptor_sets,
+        })
+    }
+
+    /// Overwrites the uniform used by `frame`.
+    pub fn write(&self, frame: &FrameData, value: T) -> Result<(), RendererError> {
+        *self.buffers[frame.frame_in_flight].write()? = value;
+        Ok(())
+    }
+
+    /// The descriptor set binding the uniform used by `frame`.
+    pub fn descriptor_set(&self, frame: &FrameData) -> Arc<PersistentDescriptorSet> {
+        self.descriptor_sets[frame.frame_in_flight].clone()
+    }
+}
+
+/// Scenes are shared between the threads recording their draws, hence `Sync`.
+pub trait Scene: Sync {
+    /// Moves the scene on by `dt` seconds, the fixed timestep. Called before each frame is
+    /// prepared, as many times as are due, which may be none; see [`FixedTimestep`].
+    fn update(&mut self, _dt: f32) {}
+
+    /// Updates per-frame data such as uniforms before any commands are recorded. Only resources
+    /// belonging to `frame.frame_in_flight` may be written.
+    fn prepare(&mut self, _frame: &FrameData) -> Result<(), RendererError> {
+        Ok(())
+    }
+
+    /// Records any rendering into the scene's own targets, before the render pass `draw` is
+    /// recorded in has begun. Images rendered here and sampled in `draw` are transitioned
+    /// between the two by the command buffer.
+    fn draw_offscreen(
+        &self,
+        _builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        _frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        Ok(())
+    }
+
+    /// The object `ray` hits first, if the scene has any that can be selected. What the index
+    /// means is up to the scene.
+    fn pick(&self, _ray: &Ray) -> Option<usize> {
+        None
+    }
+
+    /// Highlights an object [`pick`](Self::pick) returned, or nothing.
+    fn set_selection(&mut self, _selection: Option<usize>) {}
+
+    /// Handles a key press meant for the scene itself. Returns whether the scene used the key,
+    /// in which case it does nothing else.
+    fn key_pressed(&mut self, _key: VirtualKeyCode) -> bool {
+        false
+    }
+
+    /// Paints a mouse stroke from `from` to `to`, in pixels from the top left of a target of
+    /// `target_extent` showing the scene; pressing the button paints a stroke where `from` is
+    /// `to`. Returns whether the scene can be painted on, in which case dragging paints instead
+    /// of moving the camera, and clicking doesn't select.
+    fn paint(&mut self, _from: [f32; 2], _to: [f32; 2], _target_extent: [u32; 2]) -> bool {
+        false
+    }
+
+    /// A box around everything in the scene, for pointing the camera at it.
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// An image holding the whole scene, for scenes that compute it in
+    /// [`draw_offscreen`](Self::draw_offscreen) rather than draw it. Where the target can be
+    /// blitted to, the image is blitted over it, letterboxed, instead of [`draw`](Self::draw)
+    /// being recorded, so the scene needs no graphics pipeline. Up to date once
+    /// [`prepare`](Self::prepare) has run; the image needs `TRANSFER_SRC` usage and a format
+    /// that can be blitted from.
+    fn blit_source(&self) -> Option<Arc<Image>> {
+        None
+    }
+
+    /// Whether [`compute_frame`](Self::compute_frame) can write the whole frame, for scenes
+    /// that compute it. Checked after [`prepare`](Self::prepare), and preferred over
+    /// [`blit_source`](Self::blit_source) where the target is a storage image.
+    fn computes_frame(&self) -> bool {
+        false
+    }
+
+    /// Records compute dispatches writing the whole frame into `target`, a storage view of the
+    /// image being presented, in its own format and in the `General` layout, which the command
+    /// buffer transitions it to and back. Nothing is drawn into the target besides, so pixels
+    /// the scene leaves uncovered are written `clear_color`, already encoded for the target's
+    /// format.
+    fn compute_frame(
+        &self,
+        _builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        _target: Arc<ImageView>,
+        _clear_color: [f32; 4],
+    ) -> Result<(), RendererError> {
+        Ok(())
+    }
+
+    /// Updates per-frame data for drawing both eyes of `frame` at once, looking through the left
+    /// and right eye's `views` rather than `frame.view`. Returns whether the scene can draw them
+    /// that way with [`draw_stereo`](Self::draw_stereo); if not, it is prepared and drawn once
+    /// per eye instead. Only resources belonging to `frame.frame_in_flight` may be written.
+    fn prepare_stereo(
+        &mut self,
+        _frame: &FrameData,
+        _views: [Mat4; 2],
+    ) -> Result<bool, RendererError> {
+        Ok(false)
+    }
+
+    /// Records the scene's draw calls for both eyes, to be executed in a render pass made by
+    /// [`create_multiview_render_pass`](crate::stereo::create_multiview_render_pass). Each
+    /// draw call draws both, with the vertex shader picking its eye's view by `gl_ViewIndex`.
+    /// Only called once [`prepare_stereo`](Self::prepare_stereo) returned `true` for `frame`.
+    fn draw_stereo(
+        &self,
+        _builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        _frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        Ok(())
+    }
+
+    /// How many of the scene's nodes `frame` draws and how many lie outside its view, for
+    /// scenes that cull them. Up to date once [`prepare`](Self::prepare) has run for `frame`.
+    fn cull_stats(&self, _frame: &FrameData) -> Option<CullStats> {
+        None
+    }
+
+    /// How many draws `frame` records and how often they bind a pipeline, a material or
+    /// buffers, for scenes that batch their draws. Up to date once [`prepare`](Self::prepare)
+    /// has run for `frame`.
+    fn batch_stats(&self, _frame: &FrameData) -> Option<BatchStats> {
+        None
+    }
+
+    /// Identifies what [`draw`](Self::draw) records, so that recorded draws can be reused.
+    ///
+    /// `Some` promises that `draw` records the same commands for the same
+    /// `frame.frame_in_flight` for as long as the value stays the same. Anything else that changes
+    /// from frame to frame, like the camera or the time, then has to reach the GPU through
+    /// resources written in [`prepare`](Self::prepare). `None`, the default, has the draws
+    /// recorded anew every frame.
+    fn draw_revision(&self) -> Option<u64> {
+        None
+    }
+
+    /// Records the scene's draw calls, to be executed inside the render pass. The viewport and
+    /// scissor are already set.
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError>;
+
+    /// How many nodes [`draw_nodes`](Self::draw_nodes) can draw independently of each other,
+    /// so that large scenes can be recorded on several threads. Zero, the default, has the
+    /// scene drawn as a whole.
+    fn node_count(&self) -> usize {
+        0
+    }
+
+    /// Records the draws of `nodes`, part of `0..node_count()`, into a command buffer of their
+    /// own like [`draw`](Self::draw) does. Drawing all nodes in order must come out the same as
+    /// `draw`. Only called for scenes with nodes; the default draws the whole scene.
+    fn draw_nodes(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+        _nodes: Range<usize>,
+    ) -> Result<(), RendererError> {
+        self.draw(builder, frame)
+    }
+}
+
+/// Records `scene`'s draws for `frame` into a secondary command buffer, to be executed in
+/// `subpass` of any framebuffer compatible with it. The draws cover the part of the target
+/// `scissor` does. With `nodes`, only those nodes are drawn.
+///
+/// `allocator` must have been created with secondary command buffers.
+#[allow(clippy::too_many_arguments)]
+pub fn record_draws(
+    allocator: &StandardCommandBufferAllocator,
+    queue_family_index: u32,
+    usage: CommandBufferUsage,
+    subpass: Subpass,
+    scene: &dyn Scene,
+    frame: &FrameData,
+    scissor: Scissor,
+    nodes: Option<Range<usize>>,
+) -> Result<Arc<SecondaryAutoCommandBuffer>, RendererError> {
+    let mut builder = AutoCommandBufferBuilder::secondary(
+        allocator,
+        queue_family_index,
+        usage,
+        CommandBufferInheritanceInfo {
+            render_pass: Some(subpass.into()),
+            ..Default::default()
+        },
+    )?;
+    // Dynamic state isn't inherited from the primary command buffer.
+    let viewport = Viewport {
+        offset: [scissor.offset[0] as f32, scissor.offset[1] as f32],
+        extent: [scissor.extent[0] as f32, scissor.extent[1] as f32],
+        depth_range: 0.0..=1.0,
+    };
+    builder
+        .set_viewport(0, [viewport].into_iter().collect())?
+        .set_scissor(0, [scissor].into_iter().collect())?;
+    match nodes {
+        Some(nodes) => scene.draw_nodes(&mut builder, frame, nodes)?,
+        None => scene.draw(&mut builder, frame)?,
+    }
+    Ok(builder.build()?)
+}
+
+/// The built-in scenes, so they can be picked by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneKind {
+    Triangle,
+    TexturedQuad,
+    Cube,
+    Plasma,
+    Monitor,
+    TextureGrid,
+    Life,
+    NBody,
+    Terrain,
+    Sprites,
+    Flag,
+    Trails,
+    Lights,
+    Transparency,
+}
+
+impl SceneKind {
+    pub const ALL: [SceneKind; 14] = [
+        SceneKind::Triangle,
+        SceneKind::TexturedQuad,
+        SceneKind::Cube,
+        SceneKind::Plasma,
+        SceneKind::Monitor,
+        SceneKind::TextureGrid,
+        SceneKind::Life,
+        SceneKind::NBody,
+        SceneKind::Terrain,
+        SceneKind::Sprites,
+        SceneKind::Flag,
+        SceneKind::Trails,
+        SceneKind::Lights,
+        SceneKind::Transparency,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SceneKind::Triangle => "triangle",
+            SceneKind::TexturedQuad => "textured_quad",
+            SceneKind::Cube => "cube",
+            SceneKind::Plasma => "plasma",
+            SceneKind::Monitor => "monitor",
+            SceneKind::TextureGrid => "texture_grid",
+            SceneKind::Life => "life",
+            SceneKind::NBody => "nbody",
+            SceneKind::Terrain => "terrain",
+            SceneKind::Sprites => "sprites",
+            SceneKind::Flag => "flag",
+            SceneKind::Trails => "trails",
+            SceneKind::Lights => "lights",
+            SceneKind::Transparency => "transparency",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Creates the scene's resources and a pipeline compatible with `subpass`. The nbody scene
+    /// simulates [`DEFAULT_BODIES`], and the lights scene bins lights as
+    /// [`LightsConfig::default`] says; [`build_scene`] can change both.
+    pub fn build(
+        self,
+        ctx: &VulkanContext,
+        subpass: Subpass,
+    ) -> Result<Box<dyn Scene>, RendererError> {
+        Ok(match self {
+            SceneKind::Triangle => Box::new(TriangleScene::new(ctx, subpass)?),
+            SceneKind::TexturedQuad => Box::new(TexturedQuadScene::new(ctx, subpass)?),
+            SceneKind::Cube => Box::new(CubeScene::new(ctx, subpass)?),
+            SceneKind::Plasma => Box::new(PlasmaScene::new(ctx, subpass)?),
+            SceneKind::Monitor => Box::new(MonitorScene::new(ctx, subpass)?),
+            SceneKind::TextureGrid => Box::new(TextureGridScene::new(ctx, subpass)?),
+            SceneKind::Life => Box::new(LifeScene::new(ctx, subpass)?),
+            SceneKind::NBody => Box::new(NBodyScene::new(ctx, subpass, DEFAULT_BODIES)?),
+            SceneKind::Terrain => Box::new(TerrainScene::new(ctx, subpass)?),
+            SceneKind::Sprites => Box::new(SpritesScene::new(ctx, subpass)?),
+            SceneKind::Flag => Box::new(FlagScene::new(ctx, subpass)?),
+            SceneKind::Trails => Box::new(TrailsScene::new(ctx, subpass)?),
+            SceneKind::Lights => Box::new(LightsScene::new(ctx, subpass, LightsConfig::default())?),
+            SceneKind::Transparency => Box::new(TransparencyScene::new(ctx, subpass)?),
+        })
+    }
+}
+
+/// How [`build_scene`] and [`build_loaded_model_scene`] build whichever scene they're asked for.
+#[derive(Clone, Copy, Debug)]
+pub struct SceneOptions<'a> {
+    /// The directory of the cubemap drawn behind the scene.
+    pub skybox: Option<&'a Path>,
+    /// The `.hdr` environment map models are lit by, drawn behind the scene where there's no
+    /// skybox.
+    pub environment: Option<&'a Path>,
+    /// Cull models on the GPU where the device can.
+    pub gpu_culling: bool,
+    /// Pull models' vertices from a storage buffer.
+    pub vertex_pulling: bool,
+    /// Start models out drawn with a depth pre-pass.
+    pub depth_prepass: bool,
+    /// How many bodies the nbody scene simulates.
+    pub bodies: u32,
+    /// How the lights scene bins its lights.
+    pub lights: LightsConfig,
+}
+
+/// Builds the scene to draw, as `options` says: the glTF `model` if one is given, otherwise the
+/// built-in `kind`.
+pub fn build_scene(
+    kind: SceneKind,
+    model: Option<&Path>,
+    options: &SceneOptions,
+    ctx: &VulkanContext,
+    subpass: Subpass,
+) -> Result<Box<dyn Scene>, RendererError> {
+    match model {
+        Some(path) => crash_report::set_scene(&format!("model {}", path.display())),
+        None => crash_report::set_scene(kind.name()),
+    }
+    let environment = load_environment(options.environment, ctx)?;
+    let scene: Box<dyn Scene> = match model {
+        Some(path) => Box::new(ModelScene::load(
+            ctx,
+            subpass.clone(),
+            path,
+            options.gpu_culling,
+            options.vertex_pulling,
+            options.depth_prepass,
+            environment.as_ref(),
+        )?),
+        None if kind == SceneKind::NBody => {
+            Box::new(NBodyScene::new(ctx, subpass.clone(), options.bodies)?)
+        }
+        None if kind == SceneKind::Lights => {
+            Box::new(LightsScene::new(ctx, subpass.clone(), options.lights)?)
+        }
+        None => kind.build(ctx, subpass.clone())?,
+    };
+    with_skybox(scene, options.skybox, environment.as_ref(), ctx, subpass)
+}
+
+/// Like [`build_scene`] with a model, but for one already loaded from `path`, e.g. by a
+/// [`Loader`](crate::loader::Loader).
+pub fn build_loaded_model_scene(
+    path: &Path,
+    model: &Model,
+    options: &SceneOptions,
+    ctx: &VulkanContext,
+    subpass: Subpass,
+) -> Result<Box<dyn Scene>, RendererError> {
+    crash_report::set_scene(&format!("model {}", path.display()));
+    let environment = load_environment(options.environment, ctx)?;
+    let scene = Box::new(ModelScene::new(
+        ctx,
+        subpass.clone(),
+        model,
+        options.gpu_culling,
+        options.vertex_pulling,
+        options.depth_prepass,
+        environment.as_ref(),
+    )?);
+    with_skybox(scene, options.skybox, environment.as_ref(), ctx, subpass)
+}
+
+/// The environment map at `path`, if there is one.
+fn load_environment(
+    path: Option<&Path>,
+    ctx: &VulkanContext,
+) -> Result<Option<Environment>, RendererError> {
+    path.map(|path| Environment::load(ctx, path)).transpose()
+}
+
+/// `scene`, drawn in front of the skybox in `skybox` if there is one, or else of `environment`.
+fn with_skybox(
+    scene: Box<dyn Scene>,
+    skybox: Option<&Path>,
+    environment: Option<&Environment>,
+    ctx: &VulkanContext,
+    subpass: Subpass,
+) -> Result<Box<dyn Scene>, RendererError> {
+    match (skybox, environment) {
+        (Some(dir), _) => Ok(Box::new(WithSkybox::load(ctx, subpass, scene, dir)?)),
+        (None, Some(environment)) => Ok(Box::new(WithSkybox::new(
+            ctx,
+            subpass,
+            scene,
+            environment.cube.clone(),
+            environment.sampler.clone(),
+        )?)),
+        (None, None) => Ok(scene),
+    }
+}
+
+/// Builds a compute pipeline running `module`'s `main`, with the layout its shader declares.
+pub(crate) fn build_compute_pipeline(
+    ctx: &VulkanContext,
+    module: Arc<ShaderModule>,
+) -> Result<Arc<ComputePipeline>, RendererError> {
+    let entry_point = module.entry_point("main").unwrap();
+    shader_dump::entry_point(&entry_point);
+    let stage = PipelineShaderStageCreateInfo::new(entry_point);
+    let layout = PipelineLayout::new(
+        ctx.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+            .into_pipeline_layout_create_info(ctx.device.clone())?,
+    )?;
+    Ok(ComputePipeline::new(
+        ctx.device.clone(),
+        None,
+        ComputePipelineCreateInfo::stage_layout(stage, layout),
+    )?)
+}
+
+/// Builds an opaque, depth-tested triangle-list pipeline with a dynamic viewport and scissor, which
+/// is all the simple scenes need.
+fn build_pipeline(
+    device: Arc<Device>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    build_pipeline_with_layout(device, vs, fs, vertex_input_state, subpass, |_| {})
+}
+
+/// Like [`build_pipeline`], but lets `edit_layout` adjust the layout derived from the shaders
+/// first, for bindings the shaders alone can't describe.
+fn build_pipeline_with_layout(
+    device: Arc<Device>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+    edit_layout: impl FnOnce(&mut PipelineDescriptorSetLayoutCreateInfo),
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    build_pipeline_with_depth(
+        device,
+        vs,
+        fs,
+        vertex_input_state,
+        subpass,
+        DepthState::simple(),
+        edit_layout,
+    )
+}
+
+mod depth_only_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            // Leaves the fragment's depth as rasterized, and writes no colour.
+            void main() {}
+        "
+    }
+}
+
+/// Like [`build_pipeline`], but with the culling and blending `variant` needs, and blended
+/// variants testing depth without writing it.
+///
+/// For a depth pre-pass, the [`DepthPass::Prepass`] pipeline writes only depth, running no more
+/// than `vs`, but has the layout `fs` would give it so it binds the same sets. The
+/// [`DepthPass::Shading`] one then shades only the fragments the pre-pass left nearest.
+fn build_pipeline_variant(
+    device: Arc<Device>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+    variant: PipelineVariant,
+    depth_pass: DepthPass,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let blended = variant.alpha_mode == AlphaMode::Blend;
+    // Blended surfaces don't hide what is behind them, so they have no place in a pre-pass.
+    debug_assert!(variant.alpha_mode == AlphaMode::Opaque || depth_pass == DepthPass::Single);
+    let depth = match depth_pass {
+        DepthPass::Single => DepthState {
+            write_enable: !blended,
+            ..DepthState::simple()
+        },
+        DepthPass::Prepass => DepthState::simple(),
+        DepthPass::Shading => DepthState {
+            write_enable: false,
+            compare_op: CompareOp::Equal,
+        },
+    };
+    let (fs, shading_layout, color_write_mask) = if depth_pass == DepthPass::Prepass {
+        let shading_stages = [
+            PipelineShaderStageCreateInfo::new(vs.clone()),
+            PipelineShaderStageCreateInfo::new(fs),
+        ];
+        (
+            depth_only_fs::load(device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            Some(PipelineDescriptorSetLayoutCreateInfo::from_stages(
+                &shading_stages,
+            )),
+            ColorComponents::empty(),
+        )
+    } else {
+        (fs, None, ColorComponents::all())
+    };
+    build_pipeline_with_state(
+        device,
+        vs,
+        fs,
+        vertex_input_state,
+        subpass,
+        InputAssemblyState::default(),
+        depth,
+        RasterizationState {
+            // The projection flips Y, so counter-clockwise triangles still face the camera.
+            cull_mode: if variant.double_sided {
+                CullMode::None
+            } else {
+                CullMode::Back
+            },
+            front_face: FrontFace::CounterClockwise,
+            ..Default::default()
+        },
+        ColorBlendAttachmentState {
+            blend: blended.then(AttachmentBlend::alpha),
+            color_write_mask,
+            ..Default::default()
+        },
+        |layout| {
+            if let Some(shading_layout) = shading_layout {
+                *layout = shading_layout;
+            }
+        },
+    )
+}
+
+/// Like [`build_pipeline_with_layout`], but testing and writing depth as `depth` says, when the
+/// subpass has a depth attachment.
+fn build_pipeline_with_depth(
+    device: Arc<Device>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+    depth: DepthState,
+    edit_layout: impl FnOnce(&mut PipelineDescriptorSetLayoutCreateInfo),
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    build_pipeline_with_state(
+        device,
+        vs,
+        fs,
+        vertex_input_state,
+        subpass,
+        InputAssemblyState::default(),
+        depth,
+        RasterizationState::default(),
+        ColorBlendAttachmentState::default(),
+        edit_layout,
+    )
+}
+
+/// Like [`build_pipeline`], but drawing a line list, as wide as the command buffer's
+/// `set_line_width` last said. Widths other than 1 need the wide lines feature, see
+/// [`DeviceCaps::line_width_range`](crate::caps::DeviceCaps::line_width_range).
+fn build_line_pipeline(
+    device: Arc<Device>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    build_pipeline_with_state(
+        device,
+        vs,
+        fs,
+        vertex_input_state,
+        subpass,
+        InputAssemblyState {
+            topology: PrimitiveTopology::LineList,
+            ..Default::default()
+        },
+        DepthState::simple(),
+        RasterizationState::default(),
+        ColorBlendAttachmentState::default(),
+        |_| {},
+    )
+}
+
+/// The pipeline the others build, assembling primitives as `input_assembly` says and
+/// rasterizing and blending into every colour attachment as `rasterization` and `blend` say.
+#[allow(clippy::too_many_arguments)]
+fn build_pipeline_with_state(
+    device: Arc<Device>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+    input_assembly: InputAssemblyState,
+    depth: DepthState,
+    rasterization: RasterizationState,
+    blend: ColorBlendAttachmentState,
+    edit_layout: impl FnOnce(&mut PipelineDescriptorSetLayoutCreateInfo),
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let blends = vec![blend; subpass.num_color_attachments() as usize];
+    build_pipeline_with_blends(
+        device,
+        vs,
+        fs,
+        vertex_input_state,
+        subpass,
+        input_assembly,
+        depth,
+        rasterization,
+        blends,
+        edit_layout,
+    )
+}
+
+/// Like [`build_pipeline_with_state`], but blending into each colour attachment of the subpass
+/// its own way, as `blends` says in order. Unless they are all the same, the device needs
+/// [`independent_blend`](crate::caps::DeviceCaps::independent_blend).
+#[allow(clippy::too_many_arguments)]
+fn build_pipeline_with_blends(
+    device: Arc<Device>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    vertex_input_state: VertexInputState,
+    subpass: Subpass,
+    input_assembly: InputAssemblyState,
+    depth: DepthState,
+    rasterization: RasterizationState,
+    blends: Vec<ColorBlendAttachmentState>,
+    edit_layout: impl FnOnce(&mut PipelineDescriptorSetLayoutCreateInfo),
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    shader_dump::entry_point(&vs);
+    shader_dump::entry_point(&fs);
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+
+    // The layout (descriptor sets and push constants) is derived from what the shaders declare.
+    let mut layout_info = PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages);
+    edit_layout(&mut layout_info);
+    let layout = PipelineLayout::new(
+        device.clone(),
+        layout_info.into_pipeline_layout_create_info(device.clone())?,
+    )?;
+
+    let has_depth = subpass.subpass_desc().depth_stencil_attachment.is_some();
+    // Pipelines have to rasterize with as many samples as the attachments they draw into have.
+    let rasterization_samples = subpass.num_samples().unwrap_or(SampleCount::Sample1);
+    let lines = matches!(
+        input_assembly.topology,
+        PrimitiveTopology::LineList
+            | PrimitiveTopology::LineStrip
+            | PrimitiveTopology::LineListWithAdjacency
+            | PrimitiveTopology::LineStripWithAdjacency
+    );
+
+    let pipeline = GraphicsPipeline::new(
+        device,
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(input_assembly),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(rasterization),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples,
+                ..Default::default()
+            }),
+            depth_stencil_state: has_depth.then(|| DepthStencilState {
+                depth: Some(depth),
+                ..Default::default()
+            }),
+            color_blend_state: Some(ColorBlendState {
+                attachments: blends,
+                ..Default::default()
+            }),
+            // The viewport and scissor are set when recording, so the pipeline survives window
+            // resizes and can draw into part of the target. So is the width of lines, so it can
+            // change from frame to frame.
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .chain(lines.then_some(DynamicState::LineWidth))
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?;
+
+    Ok(pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn previous_frame_in_flight_stays_within_the_view() {
+        let frame = |frame_in_flight| FrameData {
+            frame_in_flight,
+            ..Default::default()
+        };
+        assert_eq!(frame(0).previous_frame_in_flight(), 1);
+        assert_eq!(frame(1).previous_frame_in_flight(), 0);
+        assert_eq!(frame(6).previous_frame_in_flight(), 7);
+        assert_eq!(frame(7).previous_frame_in_flight(), 6);
+    }
+}
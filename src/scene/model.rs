@@ -0,0 +1,2184 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, IndexBuffer, Subbuffer};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::view::ImageView;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition, VertexInputState};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+use vulkano::DeviceSize;
+use winit::event::VirtualKeyCode;
+
+use crate::animation::{Animation, Pose, Skeleton, Skin};
+use crate::context::VulkanContext;
+use crate::culling::{CullStats, Frustum};
+use crate::environment::Environment;
+use crate::error::RendererError;
+use crate::gpu_culling::{indirect_commands, CullNode, GpuCuller};
+use crate::index_buffer::create_index_buffer;
+use crate::memory_report::MemoryCategory;
+use crate::model::{
+    MeshInstance, Model, ModelMaterial, ModelVertex, MorphTargets, MAX_MORPH_TARGETS,
+};
+use crate::picking::Aabb;
+use crate::sampler::SamplerConfig;
+use crate::scene::{
+    build_pipeline_with_depth, AlphaMode, BatchStats, DepthPass, FrameData, FrameUniforms,
+    Material, MaterialId, MaterialRegistry, MaterialSet, Materials, Node, PipelineVariant,
+    PipelineVariants, Scene, FRAME_SLOTS,
+};
+use crate::std140::std140_layout;
+use crate::texture::Texture;
+use crate::transform::Transform;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+            // From the vertex to the camera, for the highlights.
+            layout(location = 2) out vec3 v_to_camera;
+
+            // Each instance pushes its own model matrix.
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view;
+                mat4 projection;
+                // Which `Shading` the fragment shader lights with.
+                uint shading;
+                // What the fragment shader multiplies the materials' emitted light by.
+                float emissive_strength;
+            } frame;
+
+            layout(push_constant) uniform Instance {
+                mat4 model;
+            } instance;
+
+            void main() {
+                vec4 world = instance.model * vec4(position, 1.0);
+                v_normal = transpose(inverse(mat3(instance.model))) * normal;
+                v_uv = uv;
+                v_to_camera = inverse(frame.view)[3].xyz - world.xyz;
+                gl_Position = frame.projection * frame.view * world;
+            }
+        "
+    }
+}
+
+/// Like `vs`, but fetching each vertex from a storage buffer by its index, with no vertex
+/// buffer bound.
+mod vs_pulled {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+            layout(location = 2) out vec3 v_to_camera;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view;
+                mat4 projection;
+                uint shading;
+                float emissive_strength;
+            } frame;
+
+            // A `ModelVertex`, in arrays of floats rather than vectors, which std430 would align
+            // to 16 bytes where the Rust struct packs them.
+            struct PulledVertex {
+                float position[3];
+                float normal[3];
+                float uv[2];
+                uint joints[4];
+                float weights[4];
+            };
+
+            // The whole arena, which the indices already point into.
+            layout(set = 2, binding = 0) readonly buffer Vertices {
+                PulledVertex vertices[];
+            };
+
+            layout(push_constant) uniform Instance {
+                mat4 model;
+            } instance;
+
+            void main() {
+                PulledVertex vertex = vertices[gl_VertexIndex];
+                vec3 position = vec3(vertex.position[0], vertex.position[1], vertex.position[2]);
+                vec3 normal = vec3(vertex.normal[0], vertex.normal[1], vertex.normal[2]);
+                vec4 world = instance.model * vec4(position, 1.0);
+                v_normal = transpose(inverse(mat3(instance.model))) * normal;
+                v_uv = vec2(vertex.uv[0], vertex.uv[1]);
+                v_to_camera = inverse(frame.view)[3].xyz - world.xyz;
+                gl_Position = frame.projection * frame.view * world;
+            }
+        "
+    }
+}
+
+/// Like `vs`, but for skinned meshes, whose vertices their joints move into the model's space.
+mod vs_skinned {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+            layout(location = 3) in uvec4 joints;
+            layout(location = 4) in vec4 weights;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+            layout(location = 2) out vec3 v_to_camera;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view;
+                mat4 projection;
+                uint shading;
+                float emissive_strength;
+            } frame;
+
+            // Every skin's joints, one after the other, so however many skins there are the
+            // stage uses a single storage buffer, within any device's
+            // maxPerStageDescriptorStorageBuffers.
+            layout(set = 2, binding = 0) readonly buffer Joints {
+                mat4 joint_matrices[];
+            };
+
+            layout(push_constant) uniform Skinned {
+                uint first_joint;
+            } skinned;
+
+            void main() {
+                mat4 skin = weights.x * joint_matrices[skinned.first_joint + joints.x]
+                    + weights.y * joint_matrices[skinned.first_joint + joints.y]
+                    + weights.z * joint_matrices[skinned.first_joint + joints.z]
+                    + weights.w * joint_matrices[skinned.first_joint + joints.w];
+                // Vertices no joint carries stay where they are.
+                if (weights == vec4(0.0)) {
+                    skin = mat4(1.0);
+                }
+                vec4 world = skin * vec4(position, 1.0);
+                v_normal = transpose(inverse(mat3(skin))) * normal;
+                v_uv = uv;
+                v_to_camera = inverse(frame.view)[3].xyz - world.xyz;
+                gl_Position = frame.projection * frame.view * world;
+            }
+        "
+    }
+}
+
+/// Like `vs`, but for meshes with morph targets, whose deltas are blended into each vertex
+/// before the instance's transform moves it.
+mod vs_morphed {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+            layout(location = 2) out vec3 v_to_camera;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view;
+                mat4 projection;
+                uint shading;
+                float emissive_strength;
+            } frame;
+
+            struct Delta {
+                vec4 position;
+                vec4 normal;
+            };
+
+            // Every primitive's targets, one after the other, see `MorphTargets`.
+            layout(set = 2, binding = 0) readonly buffer Deltas {
+                Delta deltas[];
+            };
+
+            // MAX_MORPH_TARGETS (8) for each of MAX_MORPHED_INSTANCES (256), four to a vec4.
+            layout(set = 2, binding = 1) uniform Weights {
+                vec4 weights[512];
+            } morph;
+
+            layout(push_constant) uniform Morphed {
+                mat4 model;
+                uint first_delta;
+                uint first_vertex;
+                uint vertex_count;
+                uint target_count;
+                uint instance;
+            } morphed;
+
+            void main() {
+                // The indices point into the whole arena, so the vertex index does too.
+                uint vertex = gl_VertexIndex - morphed.first_vertex;
+                vec3 morphed_position = position;
+                vec3 morphed_normal = normal;
+                for (uint i = 0; i < morphed.target_count; i++) {
+                    float weight = morph.weights[morphed.instance * 2 + i / 4][i % 4];
+                    Delta delta = deltas[morphed.first_delta + i * morphed.vertex_count + vertex];
+                    morphed_position += weight * delta.position.xyz;
+                    morphed_normal += weight * delta.normal.xyz;
+                }
+                vec4 world = morphed.model * vec4(morphed_position, 1.0);
+                v_normal = transpose(inverse(mat3(morphed.model))) * morphed_normal;
+                v_uv = uv;
+                v_to_camera = inverse(frame.view)[3].xyz - world.xyz;
+                gl_Position = frame.projection * frame.view * world;
+            }
+        "
+    }
+}
+
+/// The sliders setting the morph target weights: a quad for each, generated from the vertex
+/// index, in the bottom left corner of the view.
+mod slider_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec2 v_uv;
+            layout(location = 1) flat out uint v_slider;
+
+            layout(push_constant) uniform Sliders {
+                // The margin, width, height and gap, as in `slider_at`.
+                vec4 spacing;
+                // The view's width over its height.
+                float aspect;
+                uint count;
+            } sliders;
+
+            const vec2 CORNERS[6] = vec2[](
+                vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0),
+                vec2(0.0, 1.0), vec2(1.0, 0.0), vec2(1.0, 1.0)
+            );
+
+            void main() {
+                uint slider = gl_VertexIndex / 6;
+                vec2 corner = CORNERS[gl_VertexIndex % 6];
+                float margin = sliders.spacing.x;
+                vec2 size = sliders.spacing.yz;
+                // In fractions of the view's height up from its bottom left corner, the first
+                // slider at the top.
+                uint row = sliders.count - 1 - slider;
+                vec2 point = vec2(margin, margin + float(row) * (size.y + sliders.spacing.w))
+                    + corner * size;
+                // In front of everything, and writing that depth so nothing drawn later covers
+                // them.
+                gl_Position = vec4(point.x / sliders.aspect * 2.0 - 1.0, 1.0 - point.y * 2.0, 0.0, 1.0);
+                v_uv = corner;
+                v_slider = slider;
+            }
+        "
+    }
+}
+
+mod slider_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 1) flat in uint v_slider;
+
+            layout(location = 0) out vec4 f_color;
+
+            // As in `vs_morphed`. The sliders show the first morphed instance's weights.
+            layout(set = 0, binding = 0) uniform Weights {
+                vec4 weights[512];
+            } morph;
+
+            void main() {
+                float weight = morph.weights[v_slider / 4][v_slider % 4];
+                bool filled = v_uv.x <= clamp(weight, 0.0, 1.0);
+                f_color = vec4(filled ? vec3(0.9, 0.65, 0.2) : vec3(0.15), 1.0);
+            }
+        "
+    }
+}
+
+/// Like `vs`, but each instance is one of the nodes GPU culling found in view, whose transform
+/// it looks up.
+mod vs_indirect {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 normal;
+            layout(location = 2) in vec2 uv;
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+            layout(location = 2) out vec3 v_to_camera;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view;
+                mat4 projection;
+                uint shading;
+                float emissive_strength;
+            } frame;
+
+            layout(set = 2, binding = 0) readonly buffer Transforms {
+                mat4 transforms[];
+            };
+            layout(set = 2, binding = 1) readonly buffer Visible {
+                uint visible[];
+            };
+
+            void main() {
+                mat4 model = transforms[visible[gl_InstanceIndex]];
+                vec4 world = model * vec4(position, 1.0);
+                v_normal = transpose(inverse(mat3(model))) * normal;
+                v_uv = uv;
+                v_to_camera = inverse(frame.view)[3].xyz - world.xyz;
+                gl_Position = frame.projection * frame.view * world;
+            }
+        "
+    }
+}
+
+/// Like `vs_indirect`, but fetching each vertex as `vs_pulled` does.
+mod vs_indirect_pulled {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out vec2 v_uv;
+            layout(location = 2) out vec3 v_to_camera;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view;
+                mat4 projection;
+                uint shading;
+                float emissive_strength;
+            } frame;
+
+            struct PulledVertex {
+                float position[3];
+                float normal[3];
+                float uv[2];
+                uint joints[4];
+                float weights[4];
+            };
+
+            layout(set = 2, binding = 0) readonly buffer Transforms {
+                mat4 transforms[];
+            };
+            layout(set = 2, binding = 1) readonly buffer Visible {
+                uint visible[];
+            };
+            layout(set = 2, binding = 2) readonly buffer Vertices {
+                PulledVertex vertices[];
+            };
+
+            void main() {
+                PulledVertex vertex = vertices[gl_VertexIndex];
+                vec3 position = vec3(vertex.position[0], vertex.position[1], vertex.position[2]);
+                vec3 normal = vec3(vertex.normal[0], vertex.normal[1], vertex.normal[2]);
+                mat4 model = transforms[visible[gl_InstanceIndex]];
+                vec4 world = model * vec4(position, 1.0);
+                v_normal = transpose(inverse(mat3(model))) * normal;
+                v_uv = vec2(vertex.uv[0], vertex.uv[1]);
+                v_to_camera = inverse(frame.view)[3].xyz - world.xyz;
+                gl_Position = frame.projection * frame.view * world;
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in vec2 v_uv;
+            layout(location = 2) in vec3 v_to_camera;
+
+            layout(location = 0) out vec4 f_color;
+
+            // As in `vs`.
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view;
+                mat4 projection;
+                uint shading;
+                float emissive_strength;
+            } frame;
+
+            layout(set = 1, binding = 0) uniform sampler2D base_color_texture;
+            layout(set = 1, binding = 1) uniform MaterialFactors {
+                vec4 base_color;
+                vec3 emissive;
+                float metallic;
+                float roughness;
+                // Zero unless the material is masked, as no alpha is under it.
+                float alpha_cutoff;
+                // Whether the alpha is kept for blending, rather than written as opaque.
+                uint blended;
+                float normal_scale;
+                // Whether `normal_texture` is a normal map, rather than standing in for one.
+                uint normal_mapped;
+            } factors;
+            // Roughness in green and metalness in blue, as glTF packs them.
+            layout(set = 1, binding = 2) uniform sampler2D metallic_roughness_texture;
+            layout(set = 1, binding = 3) uniform sampler2D normal_texture;
+            // The environment's light, as `Environment` describes its images.
+            layout(set = 1, binding = 4) uniform samplerCube irradiance;
+            layout(set = 1, binding = 5) uniform samplerCube prefiltered;
+            layout(set = 1, binding = 6) uniform sampler2D brdf_lut;
+            // Multiplies `factors.emissive`, in sRGB like the base colour.
+            layout(set = 1, binding = 7) uniform sampler2D emissive_texture;
+
+            const float PI = 3.14159265;
+            // As `Shading` numbers them.
+            const uint BLINN_PHONG = 1;
+
+            const vec3 LIGHT_DIRECTION = normalize(vec3(0.4, 1.0, 0.6));
+            // Lights a white Lambertian surface facing it as brightly as the light did before
+            // the BRDF divided the diffuse light by pi.
+            const float LIGHT_RADIANCE = 0.7 * PI;
+
+            // Tilts `normal` as the normal map says, in a tangent frame worked out from how the
+            // position and texture coordinates change between neighbouring pixels, since the
+            // vertices have no tangents.
+            vec3 normal_mapped(vec3 normal) {
+                // The camera doesn't move within the draw, so the position changes as the
+                // direction to it does, the other way.
+                vec3 dp_dx = -dFdx(v_to_camera);
+                vec3 dp_dy = -dFdy(v_to_camera);
+                vec2 duv_dx = dFdx(v_uv);
+                vec2 duv_dy = dFdy(v_uv);
+                vec3 dp_dy_perp = cross(dp_dy, normal);
+                vec3 dp_dx_perp = cross(normal, dp_dx);
+                vec3 tangent = dp_dy_perp * duv_dx.x + dp_dx_perp * duv_dy.x;
+                // glTF's texture coordinates run down the image and its normal maps' green up,
+                // so the bitangent points the way v gets smaller.
+                vec3 bitangent = -(dp_dy_perp * duv_dx.y + dp_dx_perp * duv_dy.y);
+                float scale = inversesqrt(max(max(dot(tangent, tangent), dot(bitangent, bitangent)), 1e-20));
+                vec3 tilt = texture(normal_texture, v_uv).xyz * 2.0 - 1.0;
+                tilt.xy *= factors.normal_scale;
+                return normalize(mat3(tangent * scale, bitangent * scale, normal) * tilt);
+            }
+
+            // Light reflected towards `view` from `light` by the Cook-Torrance BRDF, times the
+            // cosine of the light's angle. Whatever the surface doesn't reflect off it is
+            // scattered as diffuse light, unless it's a metal, which absorbs it.
+            vec3 cook_torrance(vec3 base, float metallic, float roughness, vec3 normal, vec3 view, vec3 light) {
+                vec3 half_vector = normalize(light + view);
+                float n_dot_l = max(dot(normal, light), 0.0);
+                float n_dot_v = max(dot(normal, view), 1e-4);
+                float n_dot_h = max(dot(normal, half_vector), 0.0);
+                float v_dot_h = max(dot(view, half_vector), 0.0);
+                // GGX's distribution of microfacet normals, kept from a point for mirrors.
+                float alpha = max(roughness * roughness, 1e-3);
+                float alpha2 = alpha * alpha;
+                float d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+                float distribution = alpha2 / (PI * d * d);
+                // Smith's shadowing and masking, each by Schlick's fit of GGX's.
+                float k = alpha / 2.0;
+                float geometry = n_dot_v / (n_dot_v * (1.0 - k) + k)
+                    * n_dot_l / (n_dot_l * (1.0 - k) + k);
+                // Schlick's Fresnel, from 4% for dielectrics to the base colour for metals.
+                vec3 f0 = mix(vec3(0.04), base, metallic);
+                vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+                vec3 specular = distribution * geometry * fresnel / (4.0 * n_dot_v * n_dot_l + 1e-4);
+                vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * base / PI;
+                return (diffuse + specular) * n_dot_l;
+            }
+
+            // The same in Blinn-Phong highlights, sharper and brighter the smoother the surface,
+            // so the roughest spread evenly and light as plain diffuse surfaces do. Metals take
+            // their highlights' colour from the base colour and have no diffuse light.
+            vec3 blinn_phong(vec3 base, float metallic, float roughness, vec3 normal, vec3 view, vec3 light) {
+                float r2 = roughness * roughness;
+                float shininess = min(2.0 / max(r2 * r2, 1e-4) - 2.0, 1024.0);
+                vec3 half_vector = normalize(light + view);
+                float highlight = pow(max(dot(normal, half_vector), 0.0), shininess)
+                    * (shininess + 8.0) / 8.0;
+                vec3 specular_color = mix(vec3(0.04), base, metallic);
+                vec3 diffuse_color = base * (1.0 - metallic);
+                return (diffuse_color + specular_color * highlight) / PI
+                    * max(dot(normal, light), 0.0);
+            }
+
+            // The environment's light reflected towards `view`, by the split-sum approximation:
+            // the light prefiltered for the roughness, as the BRDF table scales and biases it, and
+            // the irradiance for the diffuse light.
+            vec3 environment_light(vec3 base, float metallic, float roughness, vec3 normal, vec3 view) {
+                float n_dot_v = max(dot(normal, view), 1e-4);
+                vec3 f0 = mix(vec3(0.04), base, metallic);
+                // Schlick's Fresnel, with the rims of rough surfaces reflecting less.
+                vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+                vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * base * texture(irradiance, normal).rgb;
+                float lod = roughness * float(textureQueryLevels(prefiltered) - 1);
+                vec3 specular = textureLod(prefiltered, reflect(-view, normal), lod).rgb;
+                vec2 scale_bias = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+                return diffuse + specular * (f0 * scale_bias.x + scale_bias.y);
+            }
+
+            void main() {
+                vec4 base_color = texture(base_color_texture, v_uv) * factors.base_color;
+                if (base_color.a < factors.alpha_cutoff) {
+                    discard;
+                }
+                // Back faces are only drawn for double-sided materials, and lit as the front.
+                vec3 normal = normalize(gl_FrontFacing ? v_normal : -v_normal);
+                if (factors.normal_mapped != 0) {
+                    normal = normal_mapped(normal);
+                }
+                vec4 metallic_roughness = texture(metallic_roughness_texture, v_uv);
+                float metallic = clamp(factors.metallic * metallic_roughness.b, 0.0, 1.0);
+                float roughness = clamp(factors.roughness * metallic_roughness.g, 0.0, 1.0);
+                vec3 view = normalize(v_to_camera);
+                vec3 color;
+                if (frame.shading == BLINN_PHONG) {
+                    // Lit by the environment as a plain diffuse surface.
+                    color = base_color.rgb * texture(irradiance, normal).rgb
+                        + blinn_phong(base_color.rgb, metallic, roughness, normal, view, LIGHT_DIRECTION)
+                            * LIGHT_RADIANCE;
+                } else {
+                    color = environment_light(base_color.rgb, metallic, roughness, normal, view)
+                        + cook_torrance(base_color.rgb, metallic, roughness, normal, view, LIGHT_DIRECTION)
+                            * LIGHT_RADIANCE;
+                }
+                // Glowing whether lit or not.
+                color += texture(emissive_texture, v_uv).rgb * factors.emissive * frame.emissive_strength;
+                f_color = vec4(color, factors.blended != 0 ? base_color.a : 1.0);
+            }
+        "
+    }
+}
+
+/// The radiance models are lit with from every direction where there's no `--environment`, as
+/// bright as the flat ambient light they had before image-based lighting.
+const UNIFORM_ENVIRONMENT: f32 = 0.3;
+
+/// Key that switches between [`Shading`]s.
+const SHADING_KEY: VirtualKeyCode = VirtualKeyCode::L;
+
+/// Key that plays the model's next animation, from the start.
+const NEXT_ANIMATION_KEY: VirtualKeyCode = VirtualKeyCode::RBracket;
+
+/// Key that plays the model's previous animation, from the start.
+const PREVIOUS_ANIMATION_KEY: VirtualKeyCode = VirtualKeyCode::LBracket;
+
+/// Keys that halve and double how fast animations play, between [`MIN_SPEED`] and
+/// [`MAX_SPEED`] times their normal speed.
+const SLOWER_KEY: VirtualKeyCode = VirtualKeyCode::Comma;
+const FASTER_KEY: VirtualKeyCode = VirtualKeyCode::Period;
+const MIN_SPEED: f32 = 0.125;
+const MAX_SPEED: f32 = 8.0;
+
+/// Keys that halve and double how brightly emissive materials glow, between
+/// [`MIN_EMISSIVE_STRENGTH`] and [`MAX_EMISSIVE_STRENGTH`] times what their factors say.
+const DIMMER_KEY: VirtualKeyCode = VirtualKeyCode::Semicolon;
+const BRIGHTER_KEY: VirtualKeyCode = VirtualKeyCode::Apostrophe;
+const MIN_EMISSIVE_STRENGTH: f32 = 1.0 / 16.0;
+const MAX_EMISSIVE_STRENGTH: f32 = 16.0;
+
+/// Key that switches the depth pre-pass for opaque surfaces on and off.
+const DEPTH_PREPASS_KEY: VirtualKeyCode = VirtualKeyCode::Z;
+
+/// The most instances whose morph targets are blended, each taking [`MAX_MORPH_TARGETS`] weights
+/// of the weights uniform. Any more are drawn as they are at rest, with a warning.
+const MAX_MORPHED_INSTANCES: usize = 256;
+
+/// Where the morph target sliders are, in fractions of the view's height: how far they are from
+/// its bottom left corner, how big each is and the gap between them.
+const SLIDER_MARGIN: f32 = 0.025;
+const SLIDER_WIDTH: f32 = 0.25;
+const SLIDER_HEIGHT: f32 = 0.025;
+const SLIDER_GAP: f32 = 0.01;
+
+/// How the model's surfaces reflect the light, numbered as the fragment shader reads them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Shading {
+    /// The Cook-Torrance BRDF glTF's metallic-roughness materials are made for.
+    #[default]
+    MetallicRoughness = 0,
+    /// Blinn-Phong highlights from the same factors, to compare against.
+    BlinnPhong = 1,
+}
+
+impl Shading {
+    fn toggled(self) -> Self {
+        match self {
+            Self::MetallicRoughness => Self::BlinnPhong,
+            Self::BlinnPhong => Self::MetallicRoughness,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::MetallicRoughness => "metallic-roughness",
+            Self::BlinnPhong => "Blinn-Phong",
+        }
+    }
+}
+
+std140_layout!(vs::Frame {
+    view: [[f32; 4]; 4] => Mat4,
+    projection: [[f32; 4]; 4] => Mat4,
+    shading: u32 => Scalar,
+    emissive_strength: f32 => Scalar,
+});
+
+fn frame_uniform(frame: &FrameData, shading: Shading, emissive_strength: f32) -> vs::Frame {
+    vs::Frame {
+        view: frame.view.to_cols_array_2d(),
+        projection: frame.projection.to_cols_array_2d(),
+        shading: shading as u32,
+        emissive_strength,
+    }
+}
+
+/// Where a node's vertices are moved to in the model's space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Placement {
+    /// By its instance's transform.
+    Rigid(Transform),
+    /// By its skin's joints, which start at this index into the joint matrices.
+    Skinned(u32),
+    /// By its instance's transform, after blending its morph targets by the weights of the
+    /// `instance`th morphed instance.
+    Morphed {
+        transform: Transform,
+        targets: MorphTargets,
+        instance: u32,
+    },
+}
+
+impl Placement {
+    /// The instance's model matrix. Skinned vertices are already in the model's space.
+    fn matrix(&self) -> Mat4 {
+        match self {
+            Self::Rigid(transform) | Self::Morphed { transform, .. } => transform.matrix(),
+            Self::Skinned(_) => Mat4::IDENTITY,
+        }
+    }
+
+    fn deformation(&self) -> Deformation {
+        match self {
+            Self::Rigid(_) => Deformation::None,
+            Self::Skinned(_) => Deformation::Skinned,
+            Self::Morphed { .. } => Deformation::Morphed,
+        }
+    }
+}
+
+/// What moves a node's vertices before its transform does, and so which shaders draw it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Deformation {
+    None,
+    Skinned,
+    Morphed,
+}
+
+/// Which animation is playing, how fast and how far in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Playback {
+    animation: usize,
+    speed: f32,
+    /// Seconds into the animation, at its own speed.
+    time: f32,
+    /// When the last frame was, to play on by how long ago it was.
+    last_frame: Option<f32>,
+}
+
+impl Playback {
+    fn new() -> Self {
+        Self {
+            animation: 0,
+            speed: 1.0,
+            time: 0.0,
+            last_frame: None,
+        }
+    }
+
+    /// Plays on to a frame at `frame_time` seconds. Changing the speed only changes how fast
+    /// the animation plays from then on, so it doesn't jump.
+    fn advance(&mut self, frame_time: f32) {
+        let elapsed = self
+            .last_frame
+            .map_or(0.0, |last_frame| (frame_time - last_frame).max(0.0));
+        self.time += elapsed * self.speed;
+        self.last_frame = Some(frame_time);
+    }
+
+    /// Changes animation by `step` of `count`, starting it from the beginning.
+    fn select(&mut self, step: isize, count: usize) {
+        self.animation = (self.animation as isize + step).rem_euclid(count as isize) as usize;
+        self.time = 0.0;
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+}
+
+/// Poses a model's nodes every frame by playing one of its animations.
+struct Animator {
+    skeleton: Skeleton,
+    animations: Vec<Animation>,
+    playback: Playback,
+}
+
+impl Animator {
+    fn new(model: &Model) -> Self {
+        let animator = Self {
+            skeleton: model.skeleton.clone(),
+            animations: model.animations.clone(),
+            playback: Playback::new(),
+        };
+        animator.log_playback();
+        animator
+    }
+
+    /// Plays the animation on to `frame`, and poses the nodes as it has them then.
+    fn pose(&mut self, frame: &FrameData) -> Pose {
+        self.playback.advance(frame.time);
+        let mut pose = self.skeleton.rest.clone();
+        if let Some(animation) = self.animations.get(self.playback.animation) {
+            animation.sample(self.playback.time, &mut pose);
+        }
+        pose
+    }
+
+    /// Changes animation or speed for the playback keys.
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            NEXT_ANIMATION_KEY | PREVIOUS_ANIMATION_KEY if self.animations.len() > 1 => {
+                let step = if key == NEXT_ANIMATION_KEY { 1 } else { -1 };
+                self.playback.select(step, self.animations.len());
+            }
+            SLOWER_KEY if !self.animations.is_empty() => {
+                self.playback.set_speed(self.playback.speed / 2.0)
+            }
+            FASTER_KEY if !self.animations.is_empty() => {
+                self.playback.set_speed(self.playback.speed * 2.0)
+            }
+            _ => return false,
+        }
+        self.log_playback();
+        true
+    }
+
+    /// Logs what is playing, which the console overlay shows.
+    fn log_playback(&self) {
+        let Some(animation) = self.animations.get(self.playback.animation) else {
+            return;
+        };
+        log::info!(
+            "Playing animation {} of {}{}, {:.1} s long, at {}x speed",
+            self.playback.animation + 1,
+            self.animations.len(),
+            animation
+                .name
+                .as_ref()
+                .map_or_else(String::new, |name| format!(" ({name})")),
+            animation.duration(),
+            self.playback.speed
+        );
+    }
+}
+
+/// A model's skinned meshes, which their joints move as the [`Animator`] poses them.
+struct Skinning {
+    pipelines: PipelineVariants,
+    skeleton: Skeleton,
+    skins: Vec<Skin>,
+    /// Where each skin's joints start in the joint matrices.
+    first_joints: Vec<u32>,
+    /// Each frame slot's joint matrices, every skin's one after the other.
+    joints: Vec<Subbuffer<[[[f32; 4]; 4]]>>,
+    /// Binds each slot's joint matrices as set 2 of `pipeline`.
+    joint_sets: MaterialSet,
+}
+
+impl Skinning {
+    fn new(ctx: &VulkanContext, subpass: Subpass, model: &Model) -> Result<Self, RendererError> {
+        let first_joints: Vec<u32> = model
+            .skins
+            .iter()
+            .scan(0, |first, skin| {
+                let joints = *first;
+                *first += skin.joints.len() as u32;
+                Some(joints)
+            })
+            .collect();
+        // Buffers can't be empty, even where every skin is.
+        let joint_count = model
+            .skins
+            .iter()
+            .map(|skin| skin.joints.len() as DeviceSize)
+            .sum::<DeviceSize>()
+            .max(1);
+        let size = joint_count * std::mem::size_of::<Mat4>() as DeviceSize;
+        if size > DeviceSize::from(ctx.caps.limits.max_storage_buffer_range) {
+            return Err(RendererError::UnsupportedScene(format!(
+                "the model's {joint_count} joints take {size} bytes, more than the device's \
+                 storage buffers hold"
+            )));
+        }
+
+        let vs = vs_skinned::load(ctx.device.clone())?
+            .entry_point("main")
+            .unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let vertex_input_state =
+            ModelVertex::per_vertex().definition(&vs.info().input_interface)?;
+        let mut pipelines = PipelineVariants::new(
+            vs,
+            fs,
+            vertex_input_state,
+            subpass,
+            "skinned model pipeline",
+        );
+        let pipeline = pipelines.get(ctx, PipelineVariant::default())?;
+
+        let joints = (0..FRAME_SLOTS)
+            .map(|slot| -> Result<_, RendererError> {
+                let joints = Buffer::new_slice::<[[f32; 4]; 4]>(
+                    ctx.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    joint_count,
+                )?;
+                ctx.memory_tracker
+                    .track_buffer(MemoryCategory::Storage, joints.buffer());
+                ctx.name_object(joints.buffer(), &format!("model joints {slot}"));
+                Ok(joints)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let joint_sets = joints
+            .iter()
+            .map(|joints| {
+                PersistentDescriptorSet::new(
+                    ctx.descriptor_set_allocator.as_ref(),
+                    pipeline.layout().set_layouts()[2].clone(),
+                    [WriteDescriptorSet::buffer(0, joints.clone())],
+                    [],
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            pipelines,
+            skeleton: model.skeleton.clone(),
+            skins: model.skins.clone(),
+            first_joints,
+            joints,
+            joint_sets: MaterialSet::PerFrame(joint_sets),
+        })
+    }
+
+    /// Writes the joint matrices `pose` puts the joints at into `frame`'s slot.
+    fn write(&self, frame: &FrameData, pose: &Pose) -> Result<(), RendererError> {
+        let world = self.skeleton.world_matrices(&pose.transforms);
+        let mut joints = self.joints[frame.frame_in_flight].write()?;
+        let matrices = self
+            .skins
+            .iter()
+            .flat_map(|skin| skin.joint_matrices(&world));
+        for (joint, matrix) in joints.iter_mut().zip(matrices) {
+            *joint = matrix.to_cols_array_2d();
+        }
+        Ok(())
+    }
+}
+
+/// A model's meshes with morph targets, blended by the weights the [`Animator`] poses them with
+/// or the sliders set.
+struct Morphing {
+    pipelines: PipelineVariants,
+    /// The node of each morphed instance, whose weights in the pose it takes.
+    nodes: Vec<usize>,
+    /// Each frame slot's weights, [`MAX_MORPH_TARGETS`] for each morphed instance.
+    weights: Vec<Subbuffer<vs_morphed::Weights>>,
+    /// Binds the deltas and each slot's weights as set 2 of `pipeline`.
+    morph_sets: MaterialSet,
+    /// The weights the sliders have set, for every morphed instance, in place of the pose's.
+    overrides: [Option<f32>; MAX_MORPH_TARGETS],
+    slider_pipeline: Arc<GraphicsPipeline>,
+    /// Binds each slot's weights as set 0 of `slider_pipeline`.
+    slider_sets: Vec<Arc<PersistentDescriptorSet>>,
+    /// One for each morph target of the first morphed instance, whose weights they show.
+    slider_count: usize,
+    /// The slider the mouse last pressed, which dragging moves.
+    dragging: Option<usize>,
+}
+
+impl Morphing {
+    /// Blends the morph targets of the instances on `nodes`, at most [`MAX_MORPHED_INSTANCES`]
+    /// of them.
+    fn new(
+        ctx: &VulkanContext,
+        subpass: Subpass,
+        model: &Model,
+        nodes: Vec<usize>,
+    ) -> Result<Self, RendererError> {
+        let size = std::mem::size_of_val(model.morph_deltas.as_slice()) as DeviceSize;
+        if size > DeviceSize::from(ctx.caps.limits.max_storage_buffer_range) {
+            return Err(RendererError::UnsupportedScene(format!(
+                "the model's morph targets take {size} bytes, more than the device's storage \
+                 buffers hold"
+            )));
+        }
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let deltas = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            allocation_info.clone(),
+            model.morph_deltas.iter().copied(),
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Storage, deltas.buffer());
+        ctx.name_object(deltas.buffer(), "model morph deltas");
+
+        let vs = vs_morphed::load(ctx.device.clone())?
+            .entry_point("main")
+            .unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let vertex_input_state =
+            ModelVertex::per_vertex().definition(&vs.info().input_interface)?;
+        let mut pipelines = PipelineVariants::new(
+            vs,
+            fs,
+            vertex_input_state,
+            subpass.clone(),
+            "morphed model pipeline",
+        );
+        let pipeline = pipelines.get(ctx, PipelineVariant::default())?;
+        let slider_pipeline = build_pipeline_with_depth(
+            ctx.device.clone(),
+            slider_vs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            slider_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            subpass,
+            DepthState {
+                write_enable: true,
+                compare_op: CompareOp::Always,
+            },
+            |_| {},
+        )?;
+        ctx.name_object(&slider_pipeline, "morph slider pipeline");
+
+        let weights = (0..FRAME_SLOTS)
+            .map(|slot| -> Result<_, RendererError> {
+                let weights = Buffer::from_data(
+                    ctx.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::UNIFORM_BUFFER,
+                        ..Default::default()
+                    },
+                    allocation_info.clone(),
+                    vs_morphed::Weights {
+                        weights: [[0.0; 4]; MAX_MORPHED_INSTANCES * MAX_MORPH_TARGETS / 4],
+                    },
+                )?;
+                ctx.memory_tracker
+                    .track_buffer(MemoryCategory::Uniform, weights.buffer());
+                ctx.name_object(weights.buffer(), &format!("model morph weights {slot}"));
+                Ok(weights)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let morph_sets = weights
+            .iter()
+            .map(|weights| {
+                PersistentDescriptorSet::new(
+                    ctx.descriptor_set_allocator.as_ref(),
+                    pipeline.layout().set_layouts()[2].clone(),
+                    [
+                        WriteDescriptorSet::buffer(0, deltas.clone()),
+                        WriteDescriptorSet::buffer(1, weights.clone()),
+                    ],
+                    [],
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let slider_sets = weights
+            .iter()
+            .map(|weights| {
+                PersistentDescriptorSet::new(
+                    ctx.descriptor_set_allocator.as_ref(),
+                    slider_pipeline.layout().set_layouts()[0].clone(),
+                    [WriteDescriptorSet::buffer(0, weights.clone())],
+                    [],
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let slider_count = nodes.first().map_or(0, |&node| {
+            model.skeleton.rest.weights[node]
+                .len()
+                .min(MAX_MORPH_TARGETS)
+        });
+        if slider_count > 0 {
+            log::info!("Drag the sliders at the bottom left to set the morph target weights");
+        }
+        Ok(Self {
+            pipelines,
+            nodes,
+            weights,
+            morph_sets: MaterialSet::PerFrame(morph_sets),
+            overrides: [None; MAX_MORPH_TARGETS],
+            slider_pipeline,
+            slider_sets,
+            slider_count,
+            dragging: None,
+        })
+    }
+
+    /// Writes each morphed instance's weights in `pose`, or as the sliders set them, into
+    /// `frame`'s slot.
+    fn write(&self, frame: &FrameData, pose: &Pose) -> Result<(), RendererError> {
+        let mut weights = self.weights[frame.frame_in_flight].write()?;
+        for (instance, &node) in self.nodes.iter().enumerate() {
+            for (target, weight) in blended_weights(&pose.weights[node], &self.overrides)
+                .into_iter()
+                .enumerate()
+            {
+                weights.weights[instance * MAX_MORPH_TARGETS / 4 + target / 4][target % 4] = weight;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts dragging the slider at `point`, if there is one, or drags the one pressed last.
+    /// Returns whether there is one being dragged.
+    fn paint(&mut self, from: [f32; 2], to: [f32; 2], target_extent: [u32; 2]) -> bool {
+        if from == to {
+            self.dragging = slider_at(to, target_extent, self.slider_count);
+        }
+        if let Some(slider) = self.dragging {
+            self.overrides[slider] = Some(slider_weight(to[0], target_extent));
+        }
+        self.dragging.is_some()
+    }
+
+    fn draw_sliders(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let [width, height] = frame.extent;
+        if self.slider_count == 0 || height == 0 {
+            return Ok(());
+        }
+        builder
+            .bind_pipeline_graphics(self.slider_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.slider_pipeline.layout().clone(),
+                0,
+                self.slider_sets[frame.frame_in_flight].clone(),
+            )?
+            .push_constants(
+                self.slider_pipeline.layout().clone(),
+                0,
+                slider_vs::Sliders {
+                    spacing: [SLIDER_MARGIN, SLIDER_WIDTH, SLIDER_HEIGHT, SLIDER_GAP],
+                    aspect: width as f32 / height as f32,
+                    count: self.slider_count as u32,
+                },
+            )?
+            .draw(6 * self.slider_count as u32, 1, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// The weight of each of [`MAX_MORPH_TARGETS`] targets: as `overrides` sets it, or as `posed`,
+/// or none for targets past those posed.
+fn blended_weights(
+    posed: &[f32],
+    overrides: &[Option<f32>; MAX_MORPH_TARGETS],
+) -> [f32; MAX_MORPH_TARGETS] {
+    std::array::from_fn(|target| {
+        overrides[target]
+            .or(posed.get(target).copied())
+            .unwrap_or(0.0)
+    })
+}
+
+/// Which of `count` sliders is at `point`, in pixels from the top left of a view of `extent`.
+/// The first is at the top, and the gaps between them aren't any.
+fn slider_at(point: [f32; 2], extent: [u32; 2], count: usize) -> Option<usize> {
+    let height = extent[1] as f32;
+    if height <= 0.0 {
+        return None;
+    }
+    let x = point[0] / height - SLIDER_MARGIN;
+    let y = (height - point[1]) / height - SLIDER_MARGIN;
+    if !(0.0..=SLIDER_WIDTH).contains(&x) || y < 0.0 {
+        return None;
+    }
+    let pitch = SLIDER_HEIGHT + SLIDER_GAP;
+    let row = (y / pitch) as usize;
+    (row < count && y - row as f32 * pitch <= SLIDER_HEIGHT).then(|| count - 1 - row)
+}
+
+/// The weight a slider is set to by pressing it `x` pixels from the left of a view of `extent`,
+/// from none at its left end to one at its right.
+fn slider_weight(x: f32, extent: [u32; 2]) -> f32 {
+    let height = extent[1].max(1) as f32;
+    ((x / height - SLIDER_MARGIN) / SLIDER_WIDTH).clamp(0.0, 1.0)
+}
+
+/// The draws of a model culled on the GPU: an indirect command for each primitive, drawing the
+/// instances of it in view.
+struct IndirectDraws {
+    culler: GpuCuller,
+    /// The range of the commands drawn with each material.
+    groups: Vec<(MaterialId, Range<u64>)>,
+    /// How many of `groups` come first for being opaque, and so take part in a depth pre-pass.
+    opaque_groups: usize,
+    /// `None` when the vertices are pulled from a storage buffer instead.
+    vertex_buffer: Option<Subbuffer<[ModelVertex]>>,
+    index_buffer: IndexBuffer,
+}
+
+/// Every material of a [`ModelScene`] again, under the same ids, with the pipelines drawing opaque
+/// surfaces with a depth pre-pass. Materials that aren't opaque keep their own pipeline in both.
+struct DepthPrepass {
+    depth: Materials,
+    shading: Materials,
+}
+
+/// `PulledVertex` in `vs_pulled` reads a [`ModelVertex`] as sixteen four-byte fields.
+const _: () = assert!(std::mem::size_of::<ModelVertex>() == 16 * 4);
+
+/// A glTF model, lit from a fixed direction and drawn with each material's base colour,
+/// metallic, roughness and normal textures and factors.
+///
+/// Surfaces are shaded with the Cook-Torrance BRDF glTF's materials are made for, or with
+/// Blinn-Phong highlights from the same factors for comparison, which [`SHADING_KEY`] switches
+/// to and back. Besides the light, they reflect an environment by image-based lighting: its
+/// irradiance as diffuse light, and with the metallic-roughness shading its prefiltered light as
+/// specular.
+///
+/// Each material's alpha mode and sidedness pick the pipeline it's drawn with. Draws are sorted
+/// by pipeline, blended ones last, and then by material, so each is bound once. Blended surfaces
+/// aren't sorted by depth, so ones overlapping each other may blend in the wrong order.
+/// With the depth pre-pass [`DEPTH_PREPASS_KEY`] switches on, opaque surfaces write their depth
+/// first, and are then only shaded where nothing nearer covers them. Masked ones are left out of
+/// the pre-pass, as their depth depends on their textures, and drawn as they are without it.
+///
+/// Nodes out of view are culled on the CPU, or on the GPU where asked for and supported.
+/// Where asked for, models without skins or morph targets bind no vertex buffer and their vertex
+/// shaders pull each vertex from a storage buffer by index instead.
+/// Skinned meshes and morph target weights are posed by playing one of the model's animations,
+/// looping, which [`NEXT_ANIMATION_KEY`] and [`PREVIOUS_ANIMATION_KEY`] choose and
+/// [`SLOWER_KEY`] and [`FASTER_KEY`] speed up and slow down. Nodes that animate without a skin
+/// stay put. Emissive materials glow on top of however they are lit, brighter or dimmer by
+/// [`BRIGHTER_KEY`] and [`DIMMER_KEY`].
+///
+/// Meshes without morph targets are drawn as they were before, and those with them blend up to
+/// [`MAX_MORPH_TARGETS`] in the vertex shader. A slider for each target of the first morphed
+/// mesh sets that target's weight for every mesh, in place of the animation's, until another
+/// animation is chosen. Skinned meshes aren't morphed.
+pub struct ModelScene {
+    materials: Materials,
+    prepass: DepthPrepass,
+    /// Whether opaque nodes are drawn with a depth pre-pass.
+    depth_prepass: bool,
+    /// Each primitive of each instance, with where the instance places it.
+    nodes: Vec<(Placement, Node)>,
+    /// How many of `nodes` come first for being opaque, and so take part in the pre-pass.
+    opaque_nodes: usize,
+    /// The world space bounding sphere of each node.
+    spheres: Vec<(Vec3, f32)>,
+    /// Which nodes are inside the view of each frame slot, as of its latest `prepare`.
+    visible: Vec<Vec<bool>>,
+    /// Changes whenever the visible nodes of any slot do, since the draws skip the others, and
+    /// whenever the depth pre-pass is switched.
+    visibility_revision: u64,
+    /// Set when culling on the GPU, which replaces the CPU culling and the draws of `nodes`.
+    indirect: Option<IndirectDraws>,
+    /// Set for models with skinned or morphed meshes.
+    animator: Option<Animator>,
+    skinning: Option<Skinning>,
+    morphing: Option<Morphing>,
+    uniforms: FrameUniforms<vs::Frame>,
+    shading: Shading,
+    /// What emissive materials' glow is multiplied by.
+    emissive_strength: f32,
+    bounds: Option<Aabb>,
+}
+
+impl ModelScene {
+    /// Loads the `.gltf` or `.glb` file at `path`.
+    pub fn load(
+        ctx: &VulkanContext,
+        subpass: Subpass,
+        path: &Path,
+        gpu_culling: bool,
+        vertex_pulling: bool,
+        depth_prepass: bool,
+        environment: Option<&Environment>,
+    ) -> Result<Self, RendererError> {
+        let model = Model::load(path)?;
+        log::info!(
+            "Loaded {}: {} vertices, {} triangles, {} instances",
+            path.display(),
+            model.vertices.len(),
+            model.indices.len() / 3,
+            model.instances.len()
+        );
+        Self::new(
+            ctx,
+            subpass,
+            &model,
+            gpu_culling,
+            vertex_pulling,
+            depth_prepass,
+            environment,
+        )
+    }
+
+    /// Uploads `model`: the arena into one vertex and one index buffer, and each material's
+    /// factors and textures. With `gpu_culling`, where the device supports it and the model has
+    /// no skins or morph targets, also the nodes' transforms and bounding spheres for culling
+    /// them on the GPU. With `vertex_pulling`, where the model has no skins or morph targets
+    /// either, the vertices go into a storage buffer the vertex shaders index rather than a
+    /// vertex buffer. With `depth_prepass`, opaque surfaces start out drawn with a depth
+    /// pre-pass. The model is lit by `environment`, or by [`UNIFORM_ENVIRONMENT`] from every
+    /// direction without one.
+    pub fn new(
+        ctx: &VulkanContext,
+        subpass: Subpass,
+        model: &Model,
+        gpu_culling: bool,
+        vertex_pulling: bool,
+        depth_prepass: bool,
+        environment: Option<&Environment>,
+    ) -> Result<Self, RendererError> {
+        if gpu_culling && !ctx.caps.multi_draw_indirect {
+            log::warn!("The device can't draw indirectly as GPU culling does, culling on the CPU");
+        }
+        let gpu_culling = gpu_culling && ctx.caps.multi_draw_indirect;
+        let skinned = model
+            .instances
+            .iter()
+            .any(|instance| instance.skin.is_some());
+        let has_targets = |instance: &MeshInstance| {
+            model.meshes[instance.mesh]
+                .iter()
+                .any(|&primitive| model.primitives[primitive].morph_targets.is_some())
+        };
+        if model
+            .instances
+            .iter()
+            .any(|instance| instance.skin.is_some() && has_targets(instance))
+        {
+            log::warn!("Skinned meshes with morph targets aren't morphed");
+        }
+        // The instances whose morph targets are blended, in the order of their weights.
+        let mut morphed: Vec<usize> = (0..model.instances.len())
+            .filter(|&instance| {
+                let instance = &model.instances[instance];
+                instance.skin.is_none() && has_targets(instance)
+            })
+            .collect();
+        if morphed.len() > MAX_MORPHED_INSTANCES {
+            log::warn!(
+                "The model has {} instances with morph targets, only the first \
+                 {MAX_MORPHED_INSTANCES} are morphed",
+                morphed.len()
+            );
+            morphed.truncate(MAX_MORPHED_INSTANCES);
+        }
+        let deformed = skinned || !morphed.is_empty();
+        if gpu_culling && deformed {
+            log::warn!(
+                "Skinned and morphed meshes move away from their bounds, culling on the CPU"
+            );
+        }
+        let gpu_culling = gpu_culling && !deformed;
+        if vertex_pulling && deformed {
+            log::warn!(
+                "Skinned and morphed meshes read their joints and targets with their vertices, \
+                 drawing from a vertex buffer"
+            );
+        }
+        let vertex_pulling = vertex_pulling && !deformed;
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        // Buffers can't be empty, and models without a single primitive are too dull to be
+        // worth a special case.
+        if model.indices.is_empty() {
+            return Err(RendererError::InvalidModel(
+                "the model has no triangles".to_owned(),
+            ));
+        }
+        let vertex_buffer = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: if vertex_pulling {
+                    BufferUsage::STORAGE_BUFFER
+                } else {
+                    BufferUsage::VERTEX_BUFFER
+                },
+                ..Default::default()
+            },
+            allocation_info.clone(),
+            model.vertices.iter().copied(),
+        )?;
+        // The indices already point into the whole arena, so it's the arena that has to fit.
+        let index_buffer = create_index_buffer(ctx, &model.indices, model.vertices.len())?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Vertex, vertex_buffer.buffer());
+        ctx.name_object(vertex_buffer.buffer(), "model vertices");
+        ctx.name_object(index_buffer.as_bytes().buffer(), "model indices");
+
+        let vs = match (gpu_culling, vertex_pulling) {
+            (true, true) => vs_indirect_pulled::load(ctx.device.clone())?,
+            (true, false) => vs_indirect::load(ctx.device.clone())?,
+            (false, true) => vs_pulled::load(ctx.device.clone())?,
+            (false, false) => vs::load(ctx.device.clone())?,
+        };
+        let vs = vs.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let vertex_input_state = if vertex_pulling {
+            VertexInputState::new()
+        } else {
+            ModelVertex::per_vertex().definition(&vs.info().input_interface)?
+        };
+        let mut pipelines = PipelineVariants::new(
+            vs,
+            fs,
+            vertex_input_state,
+            subpass.clone(),
+            "model pipeline",
+        );
+        // For the layout of the sets every variant binds.
+        let pipeline = pipelines.get(ctx, PipelineVariant::default())?;
+        let mut skinning = skinned
+            .then(|| Skinning::new(ctx, subpass.clone(), model))
+            .transpose()?;
+        let mut morphing = (!morphed.is_empty())
+            .then(|| {
+                let nodes = morphed
+                    .iter()
+                    .map(|&instance| model.instances[instance].node)
+                    .collect();
+                Morphing::new(ctx, subpass, model, nodes)
+            })
+            .transpose()?;
+        let animator = deformed.then(|| Animator::new(model));
+        let uniforms = FrameUniforms::new(
+            ctx,
+            &pipeline,
+            0,
+            frame_uniform(&FrameData::default(), Shading::default(), 1.0),
+        )?;
+
+        // Each primitive of each instance, in the order of `nodes`.
+        let node_instances: Vec<(Placement, usize)> = model
+            .instances
+            .iter()
+            .enumerate()
+            .flat_map(|(index, instance)| {
+                let morphed = morphed.iter().position(|&morphed| morphed == index);
+                let skinning = &skinning;
+                model.meshes[instance.mesh].iter().map(move |&primitive| {
+                    let targets = model.primitives[primitive].morph_targets;
+                    let placement = match (instance.skin, skinning, morphed, targets) {
+                        (Some(skin), Some(skinning), _, _) => {
+                            Placement::Skinned(skinning.first_joints[skin])
+                        }
+                        (_, _, Some(morphed), Some(targets)) => Placement::Morphed {
+                            transform: instance.transform,
+                            targets,
+                            instance: morphed as u32,
+                        },
+                        _ => Placement::Rigid(instance.transform),
+                    };
+                    (placement, primitive)
+                })
+            })
+            .collect();
+        // Skinned and morphed nodes go wherever their joints and targets take them, so they are
+        // never culled.
+        let spheres: Vec<_> = node_instances
+            .iter()
+            .map(|&(placement, primitive)| {
+                let (center, radius) = model.primitives[primitive]
+                    .bounds
+                    .transformed(placement.matrix())
+                    .bounding_sphere();
+                match placement {
+                    Placement::Rigid(_) => (center, radius),
+                    Placement::Skinned(_) | Placement::Morphed { .. } => (center, f32::INFINITY),
+                }
+            })
+            .collect();
+        let variant_of = |primitive: usize| {
+            model.primitives[primitive]
+                .material
+                .map_or_else(ModelMaterial::default, |material| {
+                    model.materials[material].clone()
+                })
+                .pipeline_variant()
+        };
+        // The primitives with instances, grouped by pipeline and material so each material's
+        // commands are next to each other.
+        let mut drawn: Vec<usize> = node_instances
+            .iter()
+            .map(|&(_, primitive)| primitive)
+            .collect();
+        drawn.sort_by_key(|&primitive| {
+            (
+                variant_of(primitive),
+                model.primitives[primitive].material,
+                primitive,
+            )
+        });
+        drawn.dedup();
+        let culler = gpu_culling
+            .then(|| {
+                let mut command_of = vec![0; model.primitives.len()];
+                let mut instances = vec![0; model.primitives.len()];
+                for (command, &primitive) in drawn.iter().enumerate() {
+                    command_of[primitive] = command as u32;
+                }
+                for &(_, primitive) in &node_instances {
+                    instances[primitive] += 1;
+                }
+                let commands = indirect_commands(drawn.iter().map(|&primitive| {
+                    (
+                        model.primitives[primitive].indices.clone(),
+                        instances[primitive],
+                    )
+                }));
+                let cull_nodes: Vec<_> = node_instances
+                    .iter()
+                    .zip(&spheres)
+                    .map(|(&(_, primitive), &(center, radius))| {
+                        CullNode::new(center, radius, command_of[primitive])
+                    })
+                    .collect();
+                GpuCuller::new(ctx, &cull_nodes, &commands)
+            })
+            .transpose()?;
+        let culling_sets = culler
+            .as_ref()
+            .map(|culler| -> Result<_, RendererError> {
+                let transforms = Buffer::from_iter(
+                    ctx.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER,
+                        ..Default::default()
+                    },
+                    allocation_info,
+                    node_instances
+                        .iter()
+                        .map(|(placement, _)| placement.matrix().to_cols_array_2d()),
+                )?;
+                ctx.memory_tracker
+                    .track_buffer(MemoryCategory::Storage, transforms.buffer());
+                let sets = culler
+                    .visible_lists()
+                    .iter()
+                    .map(|visible| {
+                        PersistentDescriptorSet::new(
+                            ctx.descriptor_set_allocator.as_ref(),
+                            pipeline.layout().set_layouts()[2].clone(),
+                            [
+                                WriteDescriptorSet::buffer(0, transforms.clone()),
+                                WriteDescriptorSet::buffer(1, visible.clone()),
+                            ]
+                            .into_iter()
+                            .chain(
+                                vertex_pulling
+                                    .then(|| WriteDescriptorSet::buffer(2, vertex_buffer.clone())),
+                            ),
+                            [],
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(MaterialSet::PerFrame(sets))
+            })
+            .transpose()?;
+        let rigid_sets = match culling_sets {
+            None if vertex_pulling => Some(MaterialSet::Shared(PersistentDescriptorSet::new(
+                ctx.descriptor_set_allocator.as_ref(),
+                pipeline.layout().set_layouts()[2].clone(),
+                [WriteDescriptorSet::buffer(0, vertex_buffer.clone())],
+                [],
+            )?)),
+            sets => sets,
+        };
+
+        // Base colour textures are in sRGB and the others linear, so each image is uploaded as
+        // the materials sampling it need, both ways if they disagree. Materials can sample the
+        // same image differently too, so each picks its own sampler rather than using the
+        // textures'.
+        let mut textures: BTreeMap<(usize, bool), Arc<ImageView>> = BTreeMap::new();
+        let mut texture = |image: usize, srgb: bool| -> Result<Arc<ImageView>, RendererError> {
+            if let Some(view) = textures.get(&(image, srgb)) {
+                return Ok(view.clone());
+            }
+            let image_data = &model.images[image];
+            let upload = if srgb {
+                Texture::from_srgba8
+            } else {
+                Texture::from_rgba8
+            };
+            let view = upload(
+                ctx,
+                image_data.width,
+                image_data.height,
+                &image_data.pixels,
+                SamplerConfig::default(),
+            )?
+            .view;
+            textures.insert((image, srgb), view.clone());
+            Ok(view)
+        };
+        // Stands in for the textures of materials without them, leaving the factors as they
+        // are. Materials without a normal map don't sample theirs.
+        let white = Texture::from_rgba8(ctx, 1, 1, &[255; 4], SamplerConfig::default())?.view;
+        let uniform_environment;
+        let environment = match environment {
+            Some(environment) => environment,
+            None => {
+                uniform_environment = Environment::uniform(ctx, UNIFORM_ENVIRONMENT)?;
+                &uniform_environment
+            }
+        };
+        let environment_light = |binding, view: &Arc<ImageView>| {
+            WriteDescriptorSet::image_view_sampler(
+                binding,
+                view.clone(),
+                environment.sampler.clone(),
+            )
+        };
+
+        let (any_skinned, any_morphed) = (skinning.is_some(), morphing.is_some());
+        let mut materials = Materials::new();
+        let mut prepass = DepthPrepass {
+            depth: Materials::new(),
+            shading: Materials::new(),
+        };
+        let mut registry = MaterialRegistry::new();
+        let mut make_material = |(model_material, deformation): &(ModelMaterial, Deformation)|
+         -> Result<MaterialId, RendererError> {
+            let variant = model_material.pipeline_variant();
+            let (variants, per_frame_sets) = match (deformation, &mut skinning, &mut morphing) {
+                (Deformation::Skinned, Some(skinning), _) => {
+                    (&mut skinning.pipelines, Some(skinning.joint_sets.clone()))
+                }
+                (Deformation::Morphed, _, Some(morphing)) => {
+                    (&mut morphing.pipelines, Some(morphing.morph_sets.clone()))
+                }
+                _ => (&mut pipelines, rigid_sets.clone()),
+            };
+            let pipeline = variants.get(ctx, variant)?;
+            let prepass_pipelines = if variant.alpha_mode == AlphaMode::Opaque {
+                Some((
+                    variants.get_for(ctx, variant, DepthPass::Prepass)?,
+                    variants.get_for(ctx, variant, DepthPass::Shading)?,
+                ))
+            } else {
+                None
+            };
+            let factors = Buffer::from_data(
+                ctx.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::UNIFORM_BUFFER,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                fs::MaterialFactors {
+                    base_color: model_material.base_color_factor,
+                    emissive: model_material.emissive_factor,
+                    metallic: model_material.metallic_factor,
+                    roughness: model_material.roughness_factor,
+                    alpha_cutoff: if model_material.alpha_mode == AlphaMode::Mask {
+                        model_material.alpha_cutoff
+                    } else {
+                        0.0
+                    },
+                    blended: (model_material.alpha_mode == AlphaMode::Blend) as u32,
+                    normal_scale: model_material.normal_scale,
+                    normal_mapped: model_material.normal_texture.is_some() as u32,
+                },
+            )?;
+            ctx.memory_tracker
+                .track_buffer(MemoryCategory::Uniform, factors.buffer());
+            let mut image_sampler = |binding, image: Option<usize>, srgb, sampler| {
+                let view = match image {
+                    Some(image) => texture(image, srgb)?,
+                    None => white.clone(),
+                };
+                Ok::<_, RendererError>(WriteDescriptorSet::image_view_sampler(
+                    binding,
+                    view,
+                    ctx.samplers.get(sampler)?,
+                ))
+            };
+            let set = PersistentDescriptorSet::new(
+                ctx.descriptor_set_allocator.as_ref(),
+                pipeline.layout().set_layouts()[1].clone(),
+                [
+                    image_sampler(
+                        0,
+                        model_material.base_color_texture,
+                        true,
+                        model_material.base_color_sampler,
+                    )?,
+                    WriteDescriptorSet::buffer(1, factors),
+                    image_sampler(
+                        2,
+                        model_material.metallic_roughness_texture,
+                        false,
+                        model_material.metallic_roughness_sampler,
+                    )?,
+                    image_sampler(
+                        3,
+                        model_material.normal_texture,
+                        false,
+                        model_material.normal_sampler,
+                    )?,
+                    environment_light(4, &environment.irradiance),
+                    environment_light(5, &environment.prefiltered),
+                    environment_light(6, &environment.brdf_lut),
+                    image_sampler(
+                        7,
+                        model_material.emissive_texture,
+                        true,
+                        model_material.emissive_sampler,
+                    )?,
+                ],
+                [],
+            )?;
+            let sets = [MaterialSet::Shared(set)].into_iter().chain(per_frame_sets);
+            let material = Material::new(pipeline).with_sets(1, sets);
+            let (depth, shading) = match prepass_pipelines {
+                Some((depth, shading)) => (
+                    material.with_pipeline(depth),
+                    material.with_pipeline(shading),
+                ),
+                None => (material.clone(), material.clone()),
+            };
+            prepass.depth.add(depth);
+            prepass.shading.add(shading);
+            Ok(materials.add(material))
+        };
+        // Each model material, and the default one last, for the meshes each way deformed.
+        // Identical ones share a material.
+        let mut model_materials = |deformation: Deformation, used: bool| {
+            if !used {
+                return Ok(Vec::new());
+            }
+            model
+                .materials
+                .iter()
+                .chain([&ModelMaterial::default()])
+                .map(|model_material| {
+                    registry.get_or_add((model_material.clone(), deformation), &mut make_material)
+                })
+                .collect::<Result<Vec<_>, RendererError>>()
+        };
+        let rigid_materials = model_materials(Deformation::None, true)?;
+        let skinned_materials = model_materials(Deformation::Skinned, any_skinned)?;
+        let morphed_materials = model_materials(Deformation::Morphed, any_morphed)?;
+        log::info!(
+            "{} materials in {} pipelines",
+            registry.len(),
+            pipelines.len()
+                + skinning
+                    .as_ref()
+                    .map_or(0, |skinning| skinning.pipelines.len())
+                + morphing
+                    .as_ref()
+                    .map_or(0, |morphing| morphing.pipelines.len())
+        );
+
+        let material_of = |deformation: Deformation, primitive: usize| {
+            let materials = match deformation {
+                Deformation::None => &rigid_materials,
+                Deformation::Skinned => &skinned_materials,
+                Deformation::Morphed => &morphed_materials,
+            };
+            let default_material = materials.len() - 1;
+            materials[model.primitives[primitive]
+                .material
+                .unwrap_or(default_material)]
+        };
+        // Drawn sorted by pipeline, blended ones last, and then by material, so each is bound
+        // once. Every node draws its own range of the one index buffer, so the buffers are
+        // bound once too.
+        let mut nodes: Vec<_> = node_instances
+            .iter()
+            .zip(spheres)
+            .map(|(&(placement, primitive), sphere)| {
+                let material = material_of(placement.deformation(), primitive);
+                let node = if vertex_pulling {
+                    Node::pulled(material, index_buffer.clone())
+                } else {
+                    Node::indexed(material, vertex_buffer.clone(), index_buffer.clone())
+                };
+                let node = node.with_indices(model.primitives[primitive].indices.clone());
+                (variant_of(primitive), placement, node, sphere)
+            })
+            .collect();
+        nodes.sort_by_key(|(variant, placement, node, _)| {
+            (*variant, placement.deformation(), node.material)
+        });
+        let opaque_nodes = nodes
+            .iter()
+            .take_while(|(variant, ..)| variant.alpha_mode == AlphaMode::Opaque)
+            .count();
+        let (nodes, spheres): (Vec<_>, Vec<_>) = nodes
+            .into_iter()
+            .map(|(_, placement, node, sphere)| ((placement, node), sphere))
+            .unzip();
+        let indirect = culler.map(|culler| {
+            let mut groups: Vec<(MaterialId, Range<u64>)> = Vec::new();
+            // Models with skins or morph targets aren't culled on the GPU.
+            for (command, &primitive) in (0..).zip(&drawn) {
+                let material = material_of(Deformation::None, primitive);
+                match groups.last_mut() {
+                    Some((last, commands)) if *last == material => commands.end = command + 1,
+                    _ => groups.push((material, command..command + 1)),
+                }
+            }
+            // Sorted like the nodes, so the opaque groups come first too.
+            let opaque_commands = drawn
+                .iter()
+                .take_while(|&&primitive| variant_of(primitive).alpha_mode == AlphaMode::Opaque)
+                .count() as u64;
+            let opaque_groups = groups
+                .iter()
+                .take_while(|(_, commands)| commands.end <= opaque_commands)
+                .count();
+            IndirectDraws {
+                culler,
+                groups,
+                opaque_groups,
+                vertex_buffer: (!vertex_pulling).then(|| vertex_buffer.clone()),
+                index_buffer: index_buffer.clone(),
+            }
+        });
+
+        Ok(Self {
+            materials,
+            prepass,
+            depth_prepass,
+            visible: vec![vec![true; nodes.len()]; FRAME_SLOTS],
+            nodes,
+            opaque_nodes,
+            spheres,
+            visibility_revision: 0,
+            indirect,
+            animator,
+            skinning,
+            morphing,
+            uniforms,
+            shading: Shading::default(),
+            emissive_strength: 1.0,
+            bounds: model.bounds(),
+        })
+    }
+
+    /// Works out which nodes are in view of `frame`, for culling on the CPU.
+    fn cull(&mut self, frame: &FrameData) {
+        let frustum = Frustum::from_view_projection(frame.projection * frame.view);
+        let visible = &mut self.visible[frame.frame_in_flight];
+        let mut changed = false;
+        for (visible, &(center, radius)) in visible.iter_mut().zip(&self.spheres) {
+            let inside = frustum.intersects_sphere(center, radius);
+            changed |= *visible != inside;
+            *visible = inside;
+        }
+        if changed {
+            self.visibility_revision += 1;
+        }
+    }
+
+    /// The passes drawing `items` of the nodes or indirect groups, the first `opaque` of which
+    /// are opaque, each with the materials it draws with: without a depth pre-pass just the one,
+    /// and with it the depth of the opaque ones before all of them shaded.
+    fn passes(&self, items: Range<usize>, opaque: usize) -> Vec<(&Materials, Range<usize>)> {
+        if !self.depth_prepass {
+            return vec![(&self.materials, items)];
+        }
+        let opaque = items.start..items.end.min(opaque).max(items.start);
+        vec![
+            (&self.prepass.depth, opaque),
+            (&self.prepass.shading, items),
+        ]
+    }
+
+    /// Draws those of `nodes` in view of `frame`, with `materials`.
+    fn draw_pass(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+        materials: &Materials,
+        nodes: Range<usize>,
+    ) -> Result<(), RendererError> {
+        let mut previous: Option<MaterialId> = None;
+        let mut previous_node: Option<&Node> = None;
+        let visible = &self.visible[frame.frame_in_flight][nodes.clone()];
+        for ((placement, node), _) in self.nodes[nodes]
+            .iter()
+            .zip(visible)
+            .filter(|(_, &visible)| visible)
+        {
+            let material = &materials[node.material];
+            // The nodes are sorted, so most share the previous one's pipeline or material.
+            if previous != Some(node.material)
+                && material.bind_after(builder, frame, previous.map(|id| &materials[id]))?
+            {
+                material.bind_sets(builder, 0, self.uniforms.descriptor_set(frame))?;
+            }
+            previous = Some(node.material);
+            match *placement {
+                Placement::Rigid(transform) => material.push_constants(
+                    builder,
+                    vs::Instance {
+                        model: transform.matrix().to_cols_array_2d(),
+                    },
+                )?,
+                Placement::Skinned(first_joint) => {
+                    material.push_constants(builder, vs_skinned::Skinned { first_joint })?
+                }
+                Placement::Morphed {
+                    transform,
+                    targets,
+                    instance,
+                } => material.push_constants(
+                    builder,
+                    vs_morphed::Morphed {
+                        model: transform.matrix().to_cols_array_2d(),
+                        first_delta: targets.first_delta,
+                        first_vertex: targets.first_vertex,
+                        vertex_count: targets.vertex_count,
+                        target_count: targets.count,
+                        instance,
+                    },
+                )?,
+            }
+            // The nodes share their buffers, so they are only bound for the first.
+            node.draw_after(builder, previous_node)?;
+            previous_node = Some(node);
+        }
+        Ok(())
+    }
+}
+
+impl Scene for ModelScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        if self.indirect.is_none() {
+            self.cull(frame);
+        }
+        if let Some(animator) = &mut self.animator {
+            let pose = animator.pose(frame);
+            if let Some(skinning) = &self.skinning {
+                skinning.write(frame, &pose)?;
+            }
+            if let Some(morphing) = &self.morphing {
+                morphing.write(frame, &pose)?;
+            }
+        }
+        self.uniforms.write(
+            frame,
+            frame_uniform(frame, self.shading, self.emissive_strength),
+        )
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        match &self.indirect {
+            Some(indirect) => indirect.culler.record(builder, frame),
+            None => Ok(()),
+        }
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            SHADING_KEY => {
+                self.shading = self.shading.toggled();
+                log::info!("Shading with {}", self.shading.name());
+                return true;
+            }
+            DEPTH_PREPASS_KEY => {
+                self.depth_prepass = !self.depth_prepass;
+                // The draws change, so they have to be recorded again.
+                self.visibility_revision += 1;
+                log::info!(
+                    "Drawing opaque surfaces {} a depth pre-pass",
+                    if self.depth_prepass {
+                        "with"
+                    } else {
+                        "without"
+                    }
+                );
+                return true;
+            }
+            DIMMER_KEY | BRIGHTER_KEY => {
+                let factor = if key == DIMMER_KEY { 0.5 } else { 2.0 };
+                self.emissive_strength = (self.emissive_strength * factor)
+                    .clamp(MIN_EMISSIVE_STRENGTH, MAX_EMISSIVE_STRENGTH);
+                log::info!(
+                    "Emissive materials glow at {}x their strength",
+                    self.emissive_strength
+                );
+                return true;
+            }
+            _ => {}
+        }
+        let Some(animator) = &mut self.animator else {
+            return false;
+        };
+        if !animator.key_pressed(key) {
+            return false;
+        }
+        // Another animation takes the weights back from the sliders.
+        if let (NEXT_ANIMATION_KEY | PREVIOUS_ANIMATION_KEY, Some(morphing)) =
+            (key, &mut self.morphing)
+        {
+            morphing.overrides = [None; MAX_MORPH_TARGETS];
+        }
+        true
+    }
+
+    /// Dragging a morph target's slider sets its weight.
+    fn paint(&mut self, from: [f32; 2], to: [f32; 2], target_extent: [u32; 2]) -> bool {
+        self.morphing
+            .as_mut()
+            .is_some_and(|morphing| morphing.paint(from, to, target_extent))
+    }
+
+    /// As the model is at rest, which animations can take skinned and morphed meshes out of.
+    fn bounds(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn cull_stats(&self, frame: &FrameData) -> Option<CullStats> {
+        if let Some(indirect) = &self.indirect {
+            return indirect.culler.stats(frame);
+        }
+        let visible = &self.visible[frame.frame_in_flight];
+        let drawn = visible.iter().filter(|&&visible| visible).count();
+        Some(CullStats {
+            drawn,
+            culled: visible.len() - drawn,
+        })
+    }
+
+    /// As if all the nodes were recorded together. Recorded in parts, each part binds again
+    /// what its first node needs.
+    fn batch_stats(&self, frame: &FrameData) -> Option<BatchStats> {
+        let mut stats = BatchStats::default();
+        if let Some(indirect) = &self.indirect {
+            // Each group is one indirect draw.
+            let passes = self.passes(0..indirect.groups.len(), indirect.opaque_groups);
+            for (materials, groups) in passes {
+                stats += BatchStats::of(indirect.groups[groups].iter().map(|&(material, _)| {
+                    let pipeline = Arc::as_ptr(materials[material].pipeline());
+                    (pipeline, material, ())
+                }));
+            }
+            // The buffers are bound once, before every pass.
+            stats.buffer_binds = stats.buffer_binds.min(1);
+            return Some(stats);
+        }
+        let visible = &self.visible[frame.frame_in_flight];
+        for (materials, nodes) in self.passes(0..self.nodes.len(), self.opaque_nodes) {
+            let mut previous: Option<&Node> = None;
+            let mut buffers = 0;
+            let draws = self.nodes[nodes.clone()]
+                .iter()
+                .zip(&visible[nodes])
+                .filter(|(_, &visible)| visible)
+                .map(|((_, node), _)| {
+                    if previous.is_none_or(|previous| !node.shares_buffers_with(previous)) {
+                        buffers += 1;
+                    }
+                    previous = Some(node);
+                    let pipeline = Arc::as_ptr(materials[node.material].pipeline());
+                    (pipeline, node.material, buffers)
+                });
+            stats += BatchStats::of(draws);
+        }
+        Some(stats)
+    }
+
+    /// Culling on the CPU changes which nodes are drawn. Culling on the GPU only changes what
+    /// the indirect commands hold, so the draws stay the same.
+    fn draw_revision(&self) -> Option<u64> {
+        Some(self.visibility_revision)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let Some(indirect) = &self.indirect else {
+            return self.draw_nodes(builder, frame, 0..self.nodes.len());
+        };
+        // Every group draws from the same buffers, which stay bound across pipelines.
+        if let Some(vertex_buffer) = &indirect.vertex_buffer {
+            builder.bind_vertex_buffers(0, vertex_buffer.clone())?;
+        }
+        builder.bind_index_buffer(indirect.index_buffer.clone())?;
+        for (materials, groups) in self.passes(0..indirect.groups.len(), indirect.opaque_groups) {
+            let mut previous = None;
+            for (material, commands) in &indirect.groups[groups] {
+                let material = &materials[*material];
+                if material.bind_after(builder, frame, previous)? {
+                    material.bind_sets(builder, 0, self.uniforms.descriptor_set(frame))?;
+                }
+                previous = Some(material);
+                builder.draw_indexed_indirect(
+                    indirect
+                        .culler
+                        .commands(frame)
+                        .clone()
+                        .slice(commands.clone()),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Culled on the GPU, the whole model is a few indirect draws, not worth splitting up.
+    fn node_count(&self) -> usize {
+        if self.indirect.is_some() {
+            0
+        } else {
+            self.nodes.len()
+        }
+    }
+    fn draw_nodes(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+        nodes: Range<usize>,
+    ) -> Result<(), RendererError> {
+        // The sliders go with the last nodes, over everything else.
+        let last = nodes.end == self.nodes.len();
+        for (materials, nodes) in self.passes(nodes, self.opaque_nodes) {
+            self.draw_pass(builder, frame, materials, nodes)?;
+        }
+        match &self.morphing {
+            Some(morphing) if last => morphing.draw_sliders(builder, frame),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playback_follows_the_clock_at_its_speed() {
+        let mut playback = Playback::new();
+        playback.advance(10.0);
+        assert_eq!(playback.time, 0.0);
+        playback.advance(11.0);
+        assert_eq!(playback.time, 1.0);
+        // A new speed applies from the last frame on.
+        playback.set_speed(2.0);
+        playback.advance(11.5);
+        assert_eq!(playback.time, 2.0);
+        playback.set_speed(100.0);
+        assert_eq!(playback.speed, MAX_SPEED);
+
+        playback.select(-1, 3);
+        assert_eq!((playback.animation, playback.time), (2, 0.0));
+        playback.select(1, 3);
+        assert_eq!(playback.animation, 0);
+    }
+
+    #[test]
+    fn sliders_stack_up_from_the_bottom_left() {
+        // 25 pixel margins and sliders, 10 pixel gaps and 250 pixel wide sliders.
+        let extent = [2000, 1000];
+        assert_eq!(slider_at([100.0, 960.0], extent, 2), Some(1));
+        assert_eq!(slider_at([100.0, 930.0], extent, 2), Some(0));
+        // Between the sliders, above them and past their ends are none.
+        assert_eq!(slider_at([100.0, 945.0], extent, 2), None);
+        assert_eq!(slider_at([100.0, 895.0], extent, 2), None);
+        assert_eq!(slider_at([10.0, 960.0], extent, 2), None);
+        assert_eq!(slider_at([300.0, 960.0], extent, 2), None);
+        assert_eq!(slider_at([100.0, 960.0], extent, 0), None);
+
+        assert_eq!(slider_weight(25.0, extent), 0.0);
+        assert_eq!(slider_weight(150.0, extent), 0.5);
+        assert_eq!(slider_weight(900.0, extent), 1.0);
+    }
+
+    #[test]
+    fn sliders_override_the_posed_weights() {
+        let mut overrides = [None; MAX_MORPH_TARGETS];
+        overrides[1] = Some(0.5);
+        overrides[3] = Some(1.0);
+        let weights = blended_weights(&[0.25, 0.75], &overrides);
+        assert_eq!(weights[..4], [0.25, 0.5, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,447 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, ClearColorImageInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+    SecondaryAutoCommandBuffer, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::ClearColorValue;
+use vulkano::image::sampler::SamplerAddressMode;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline};
+use vulkano::render_pass::{Framebuffer, Subpass};
+
+use crate::camera::{Camera, OrbitCamera};
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::picking::Aabb;
+use crate::render_pass::{clear_values, create_framebuffer, SharedAttachments};
+use crate::sampler::SamplerConfig;
+use crate::scene::cube::{cube_vertices, ColoredVertex, NO_HIGHLIGHT};
+use crate::scene::{
+    build_pipeline, FrameData, FrameUniforms, Material, MaterialSet, Materials, MvpUniform, Node,
+    Scene, CLEAR_COLOR, FRAME_SLOTS,
+};
+
+/// Resolution of the image shown on the monitor, independent of the window.
+const MONITOR_EXTENT: [u32; 2] = [512, 384];
+
+/// Where the monitor hangs: behind the cube, facing the default camera.
+const MONITOR_CENTER: Vec3 = Vec3::new(0.0, 0.5, -2.5);
+const MONITOR_HALF_HEIGHT: f32 = 0.9;
+
+#[derive(BufferContents, Vertex, Debug, PartialEq)]
+#[repr(C)]
+pub struct ScreenVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+}
+
+mod screen_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec2 uv;
+
+            layout(location = 0) out vec2 v_uv;
+
+            layout(set = 0, binding = 0) uniform Mvp {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+                float time;
+            } mvp;
+
+            void main() {
+                v_uv = uv;
+                gl_Position = mvp.projection * mvp.view * mvp.model * vec4(position, 1.0);
+            }
+        "
+    }
+}
+
+mod screen_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 1, binding = 0) uniform sampler2D feed;
+
+            void main() {
+                f_color = texture(feed, v_uv);
+            }
+        "
+    }
+}
+
+/// The camera filming what the monitor shows. It pans slowly from side to side, so the picture
+/// visibly lives.
+fn security_camera(time: f32) -> Camera {
+    Camera::Orbit(OrbitCamera {
+        target: Vec3::ZERO,
+        distance: 4.5,
+        yaw: (-30.0_f32).to_radians() + 0.5 * (time * 0.4).sin(),
+        pitch: 20.0_f32.to_radians(),
+    })
+}
+
+/// What the cube and the monitor are drawn with in one of the two passes.
+struct PassUniforms {
+    cube: FrameUniforms<MvpUniform>,
+    screen: FrameUniforms<MvpUniform>,
+}
+
+impl PassUniforms {
+    fn new(
+        ctx: &VulkanContext,
+        cube_pipeline: &GraphicsPipeline,
+        screen_pipeline: &GraphicsPipeline,
+    ) -> Result<Self, RendererError> {
+        let initial = MvpUniform::new(Mat4::IDENTITY, &FrameData::default());
+        Ok(Self {
+            cube: FrameUniforms::new(ctx, cube_pipeline, 0, initial)?,
+            screen: FrameUniforms::new(ctx, screen_pipeline, 0, initial)?,
+        })
+    }
+
+    fn write(&self, frame: &FrameData) -> Result<(), RendererError> {
+        let cube_model = Mat4::from_rotation_y(frame.time * 0.5);
+        let screen_model = Mat4::from_translation(MONITOR_CENTER);
+        self.cube.write(frame, MvpUniform::new(cube_model, frame))?;
+        self.screen
+            .write(frame, MvpUniform::new(screen_model, frame))
+    }
+}
+
+/// A spinning cube and a monitor showing it live from a second camera, rendered into a texture
+/// every frame before the main pass.
+///
+/// The monitor shows itself too when in view. It can't sample the image being rendered, so
+/// there it shows the previous frame's picture instead, which makes for a tunnel of frames
+/// trailing one frame further behind at every step.
+pub struct MonitorScene {
+    materials: Materials,
+    cube: Node,
+    /// The monitor showing the picture rendered this frame.
+    screen: Node,
+    /// The monitor as seen on itself, showing the previous frame's picture.
+    screen_in_monitor: Node,
+    main_uniforms: PassUniforms,
+    monitor_uniforms: PassUniforms,
+    /// One target per frame slot, so each is only reused once the GPU is done with it.
+    monitor_framebuffers: Vec<Arc<Framebuffer>>,
+    bounds: Aabb,
+}
+
+impl MonitorScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let buffer_info = BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        };
+        let allocation_info = AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        let cube_vertices = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            buffer_info.clone(),
+            allocation_info.clone(),
+            cube_vertices(),
+        )?;
+        let half_width = MONITOR_HALF_HEIGHT * MONITOR_EXTENT[0] as f32 / MONITOR_EXTENT[1] as f32;
+        let (w, h) = (half_width, MONITOR_HALF_HEIGHT);
+        // The top of the image is at v = 0.
+        let screen_vertices = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            buffer_info,
+            allocation_info,
+            [
+                ([-w, -h], [0.0, 1.0]),
+                ([w, -h], [1.0, 1.0]),
+                ([w, h], [1.0, 0.0]),
+                ([-w, -h], [0.0, 1.0]),
+                ([w, h], [1.0, 0.0]),
+                ([-w, h], [0.0, 0.0]),
+            ]
+            .map(|([x, y], uv)| ScreenVertex {
+                position: [x, y, 0.0],
+                uv,
+            }),
+        )?;
+        // The cube reaches furthest out when it has turned by 45°.
+        let cube_bounds = Aabb::from_points(
+            super::cube::cube_vertices()
+                .iter()
+                .map(|v| Vec3::from(v.position)),
+        )
+        .unwrap()
+        .transformed(Mat4::from_rotation_y(FRAC_PI_4));
+        let screen_bounds = Aabb {
+            min: MONITOR_CENTER - Vec3::new(w, h, 0.0),
+            max: MONITOR_CENTER + Vec3::new(w, h, 0.0),
+        };
+        let bounds = cube_bounds.union(&screen_bounds);
+
+        for buffer in [cube_vertices.buffer(), screen_vertices.buffer()] {
+            ctx.memory_tracker
+                .track_buffer(MemoryCategory::Vertex, buffer);
+        }
+        ctx.name_object(cube_vertices.buffer(), "monitor cube vertices");
+        ctx.name_object(screen_vertices.buffer(), "monitor screen vertices");
+
+        let vs = super::cube::vs::load(ctx.device.clone())?
+            .entry_point("main")
+            .unwrap();
+        let fs = super::cube::fs::load(ctx.device.clone())?
+            .entry_point("main")
+            .unwrap();
+        let vertex_input_state =
+            ColoredVertex::per_vertex().definition(&vs.info().input_interface)?;
+        let cube_pipeline = build_pipeline(
+            ctx.device.clone(),
+            vs,
+            fs,
+            vertex_input_state,
+            subpass.clone(),
+        )?;
+        ctx.name_object(&cube_pipeline, "monitor cube pipeline");
+
+        let vs = screen_vs::load(ctx.device.clone())?
+            .entry_point("main")
+            .unwrap();
+        let fs = screen_fs::load(ctx.device.clone())?
+            .entry_point("main")
+            .unwrap();
+        let vertex_input_state =
+            ScreenVertex::per_vertex().definition(&vs.info().input_interface)?;
+        let screen_pipeline = build_pipeline(
+            ctx.device.clone(),
+            vs,
+            fs,
+            vertex_input_state,
+            subpass.clone(),
+        )?;
+        ctx.name_object(&screen_pipeline, "monitor screen pipeline");
+
+        // The monitor is rendered with the same render pass (and so the same pipelines) as the
+        // main pass, which means using the same color format.
+        let format = subpass.render_pass().attachments()[0].format;
+        let images = (0..FRAME_SLOTS)
+            .map(|slot| {
+                let image = Image::new(
+                    ctx.memory_allocator.clone(),
+                    ImageCreateInfo {
+                        image_type: ImageType::Dim2d,
+                        format,
+                        extent: [MONITOR_EXTENT[0], MONITOR_EXTENT[1], 1],
+                        usage: ImageUsage::COLOR_ATTACHMENT
+                            | ImageUsage::SAMPLED
+                            | ImageUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo::default(),
+                )?;
+                ctx.memory_tracker
+                    .track_image(MemoryCategory::RenderTarget, &image);
+                ctx.name_object(&image, &format!("monitor feed {slot}"));
+                Ok(image)
+            })
+            .collect::<Result<Vec<_>, RendererError>>()?;
+        // The monitor shows the previous frame's picture of itself, which has to exist from the
+        // first frame on.
+        ctx.submit_and_wait(|builder| {
+            for image in &images {
+                builder.clear_color_image(ClearColorImageInfo {
+                    clear_value: ClearColorValue::Float(CLEAR_COLOR),
+                    ..ClearColorImageInfo::image(image.clone())
+                })?;
+            }
+            Ok(())
+        })?;
+
+        let sampler = ctx
+            .samplers
+            .get(SamplerConfig::default().with_address_mode(SamplerAddressMode::ClampToEdge))?;
+        let mut monitor_framebuffers = Vec::with_capacity(FRAME_SLOTS);
+        let mut feed_descriptor_sets = Vec::with_capacity(FRAME_SLOTS);
+        for (slot, image) in images.into_iter().enumerate() {
+            let view = ImageView::new_default(image)?;
+            let shared = SharedAttachments::new(
+                ctx,
+                subpass.render_pass(),
+                MONITOR_EXTENT,
+                &format!("monitor feed {slot}"),
+            )?;
+            monitor_framebuffers.push(create_framebuffer(
+                subpass.render_pass().clone(),
+                view.clone(),
+                &shared,
+            )?);
+            feed_descriptor_sets.push(PersistentDescriptorSet::new(
+                ctx.descriptor_set_allocator.as_ref(),
+                screen_pipeline.layout().set_layouts()[1].clone(),
+                [WriteDescriptorSet::image_view_sampler(
+                    0,
+                    view,
+                    sampler.clone(),
+                )],
+                [],
+            )?);
+        }
+
+        let main_uniforms = PassUniforms::new(ctx, &cube_pipeline, &screen_pipeline)?;
+        let monitor_uniforms = PassUniforms::new(ctx, &cube_pipeline, &screen_pipeline)?;
+
+        // Set 1 binds the image of the target with the same index, or of the previous frame's
+        // for the monitor's own pass.
+        let previous_feeds = (0..FRAME_SLOTS)
+            .map(|frame_in_flight| {
+                let frame = FrameData {
+                    frame_in_flight,
+                    ..Default::default()
+                };
+                feed_descriptor_sets[frame.previous_frame_in_flight()].clone()
+            })
+            .collect();
+        let mut materials = Materials::new();
+        let cube = materials.add(Material::new(cube_pipeline));
+        let live_feed = materials.add(
+            Material::new(screen_pipeline.clone())
+                .with_sets(1, [MaterialSet::PerFrame(feed_descriptor_sets)]),
+        );
+        let previous_feed = materials.add(
+            Material::new(screen_pipeline).with_sets(1, [MaterialSet::PerFrame(previous_feeds)]),
+        );
+
+        Ok(Self {
+            materials,
+            cube: Node::new(cube, cube_vertices),
+            screen: Node::new(live_feed, screen_vertices.clone()),
+            screen_in_monitor: Node::new(previous_feed, screen_vertices),
+            main_uniforms,
+            monitor_uniforms,
+            monitor_framebuffers,
+            bounds,
+        })
+    }
+
+    /// `frame` with the security camera's matrices.
+    fn monitor_frame(frame: &FrameData) -> FrameData {
+        let camera = security_camera(frame.time);
+        FrameData {
+            view: camera.view_matrix(),
+            projection: camera.projection(MONITOR_EXTENT[0] as f32 / MONITOR_EXTENT[1] as f32),
+            extent: MONITOR_EXTENT,
+            ..*frame
+        }
+    }
+
+    /// Draws the cube and `screen`, one of the two monitor nodes.
+    fn draw_objects<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        frame: &FrameData,
+        uniforms: &PassUniforms,
+        screen: &Node,
+    ) -> Result<(), RendererError> {
+        let material = &self.materials[self.cube.material];
+        material.bind(builder, frame)?;
+        material.bind_sets(builder, 0, uniforms.cube.descriptor_set(frame))?;
+        material.push_constants(builder, NO_HIGHLIGHT)?;
+        self.cube.draw(builder)?;
+
+        let material = &self.materials[screen.material];
+        material.bind(builder, frame)?;
+        material.bind_sets(builder, 0, uniforms.screen.descriptor_set(frame))?;
+        screen.draw(builder)
+    }
+}
+
+impl Scene for MonitorScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        self.main_uniforms.write(frame)?;
+        self.monitor_uniforms.write(&Self::monitor_frame(frame))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: [MONITOR_EXTENT[0] as f32, MONITOR_EXTENT[1] as f32],
+            depth_range: 0.0..=1.0,
+        };
+        let scissor = Scissor {
+            offset: [0, 0],
+            extent: MONITOR_EXTENT,
+        };
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: clear_values(
+                        self.monitor_framebuffers[frame.frame_in_flight].render_pass(),
+                        CLEAR_COLOR,
+                    ),
+                    ..RenderPassBeginInfo::framebuffer(
+                        self.monitor_framebuffers[frame.frame_in_flight].clone(),
+                    )
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(0, [viewport].into_iter().collect())?
+            .set_scissor(0, [scissor].into_iter().collect())?;
+        let monitor_frame = Self::monitor_frame(frame);
+        self.draw_objects(
+            builder,
+            &monitor_frame,
+            &self.monitor_uniforms,
+            &self.screen_in_monitor,
+        )?;
+        builder.end_render_pass(SubpassEndInfo::default())?;
+        Ok(())
+    }
+
+    /// The feed is rendered afresh every frame, but into the slot's own image, so the draws
+    /// sampling it stay the same.
+    fn draw_revision(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        // The command buffer moves the image from being rendered to being sampled in between.
+        self.draw_objects(builder, frame, &self.main_uniforms, &self.screen)
+    }
+}
@@ -0,0 +1,743 @@
+//! Gravity between thousands of bodies, every pair of them, computed in a compute shader and
+//! drawn as glowing points.
+//!
+//! The bodies start out as a cold, slowly rotating disc, which breaks up into clumps that orbit
+//! each other. Each step reads every body from one buffer and writes it, moved on, into the
+//! other. Workgroups go through the bodies a tile at a time, staging each tile's positions in
+//! shared memory, so every position is read from the buffer once per workgroup rather than once
+//! per body. Bodies are drawn as camera-facing quads, one instance each, read straight from the
+//! newest buffer and brighter the faster they go.
+//!
+//! [`PAUSE_KEY`] pauses and resumes, and [`RESET_KEY`] starts again from a new disc.
+//! [`benchmark`] times the steps alone, for `--nbody-bench`.
+
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use glam::{Mat4, Vec3};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CopyBufferInfo, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::SampleCount;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::depth_stencil::DepthStencilState;
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    ComputePipeline, DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::Subpass;
+use vulkano::DeviceSize;
+use winit::event::VirtualKeyCode;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::picking::Aabb;
+use crate::scene::step_clock::StepClock;
+use crate::scene::{build_compute_pipeline, FrameData, FrameUniforms, MvpUniform, Scene};
+use crate::shader_dump;
+
+/// Bodies simulated unless `--bodies` says otherwise.
+pub const DEFAULT_BODIES: u32 = 16 * 1024;
+
+/// Bodies each workgroup moves, and so the bodies in a tile; matches `local_size_x` in the step
+/// shader. Every device runs workgroups of at least this many invocations.
+const WORKGROUP_SIZE: u32 = 128;
+
+/// Steps simulated per second while running.
+const STEPS_PER_SECOND: f32 = 60.0;
+
+/// The most steps one frame takes. After a longer hitch the simulation slows down rather than
+/// catching up.
+const MAX_STEPS_PER_FRAME: u32 = 2;
+
+/// Simulated time each step moves on by. The disc's edge goes round about once every 20 seconds.
+const TIME_STEP: f32 = 0.005;
+
+/// Added to the squared distance between bodies, so close ones don't fling each other away.
+/// Roughly the distance between neighbours in the disc at the default size.
+const SOFTENING: f32 = 0.015;
+
+/// The disc's radius. The bodies' masses add up to one.
+const DISC_RADIUS: f32 = 1.0;
+
+/// Half the disc's thickness.
+const DISC_HALF_THICKNESS: f32 = 0.02;
+
+/// How fast the bodies start orbiting, as a fraction of the speed that keeps them on a circle.
+/// Slower than that, the disc collapses into clumps.
+const ORBIT_FRACTION: f32 = 0.8;
+
+/// Steps taken before timing, so the clocks have ramped up.
+const BENCHMARK_WARMUP_STEPS: u32 = 20;
+
+/// How many times the benchmark times its steps.
+const BENCHMARK_RUNS: usize = 5;
+
+/// Roughly how many interactions each timed run does, so runs take long enough to time
+/// reliably whatever the number of bodies.
+const BENCHMARK_INTERACTIONS_PER_RUN: u64 = 1 << 35;
+
+/// The most steps a timed run takes, for very few bodies.
+const BENCHMARK_MAX_STEPS: u32 = 4096;
+
+/// Key that pauses and resumes the simulation. Not Space, which moves the fly camera up.
+const PAUSE_KEY: VirtualKeyCode = VirtualKeyCode::B;
+
+/// Key that starts again from a new disc.
+const RESET_KEY: VirtualKeyCode = VirtualKeyCode::R;
+
+/// A body as the shaders' `Body` lays it out.
+#[derive(BufferContents, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+struct Body {
+    /// Position, and mass in `w`.
+    position: [f32; 4],
+    /// Velocity, `w` unused.
+    velocity: [f32; 4],
+}
+
+mod step_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 128) in;
+
+            struct Body {
+                vec4 position;
+                vec4 velocity;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Previous {
+                Body previous[];
+            };
+            layout(set = 0, binding = 1) writeonly buffer Next {
+                Body next[];
+            };
+
+            layout(push_constant) uniform Step {
+                uint count;
+                float time_step;
+                float softening_squared;
+            } step;
+
+            shared vec4 tile[gl_WorkGroupSize.x];
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                uint local = gl_LocalInvocationID.x;
+                // Bodies past the end still help load the tiles.
+                Body body = previous[min(index, step.count - 1)];
+
+                vec3 acceleration = vec3(0.0);
+                for (uint start = 0; start < step.count; start += gl_WorkGroupSize.x) {
+                    uint loaded = start + local;
+                    // Massless, the bodies past the end pull on nothing.
+                    tile[local] = loaded < step.count ? previous[loaded].position : vec4(0.0);
+                    barrier();
+                    for (uint i = 0; i < gl_WorkGroupSize.x; i++) {
+                        vec3 offset = tile[i].xyz - body.position.xyz;
+                        float inverse = inversesqrt(dot(offset, offset) + step.softening_squared);
+                        acceleration += tile[i].w * inverse * inverse * inverse * offset;
+                    }
+                    barrier();
+                }
+
+                if (index >= step.count) {
+                    return;
+                }
+                vec3 velocity = body.velocity.xyz + acceleration * step.time_step;
+                vec3 position = body.position.xyz + velocity * step.time_step;
+                next[index] = Body(vec4(position, body.position.w), vec4(velocity, 0.0));
+            }
+        "
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            struct Body {
+                vec4 position;
+                vec4 velocity;
+            };
+
+            layout(set = 0, binding = 0) uniform Mvp {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+                float time;
+            } mvp;
+
+            layout(set = 1, binding = 0) readonly buffer Bodies {
+                Body bodies[];
+            };
+
+            layout(location = 0) out vec2 v_corner;
+            layout(location = 1) out vec3 v_color;
+
+            // Half the width of a body, in the units the bodies move in.
+            const float RADIUS = 0.008;
+            // Bodies this fast or faster are drawn at full brightness.
+            const float BRIGHT_SPEED = 1.5;
+
+            const vec2 CORNERS[6] = vec2[](
+                vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+                vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+            );
+
+            void main() {
+                Body body = bodies[gl_InstanceIndex];
+                v_corner = CORNERS[gl_VertexIndex];
+                // Facing the camera, so the quad is pushed out in view space.
+                vec4 position = mvp.view * vec4(body.position.xyz, 1.0);
+                position.xy += v_corner * RADIUS;
+                gl_Position = mvp.projection * position;
+
+                float brightness = clamp(length(body.velocity.xyz) / BRIGHT_SPEED, 0.1, 1.0);
+                // Slow bodies are a dim orange, fast ones a bright blue-white.
+                v_color = mix(vec3(1.0, 0.45, 0.15), vec3(0.65, 0.8, 1.0), brightness) * brightness;
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_corner;
+            layout(location = 1) in vec3 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                // Round, and fading out towards the edge.
+                float glow = max(1.0 - dot(v_corner, v_corner), 0.0);
+                f_color = vec4(v_color * glow * glow, 1.0);
+            }
+        "
+    }
+}
+
+/// The bodies, twice over, and stepping from one copy into the other.
+struct Simulation {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    pipeline: Arc<ComputePipeline>,
+    bodies: [Subbuffer<[Body]>; 2],
+    /// The set for stepping into `bodies[i]`, which reads the other buffer.
+    step_sets: [Arc<PersistentDescriptorSet>; 2],
+    count: u32,
+}
+
+impl Simulation {
+    fn new(ctx: &VulkanContext, count: u32) -> Result<Self, RendererError> {
+        let size = DeviceSize::from(count) * mem::size_of::<Body>() as DeviceSize;
+        if size > DeviceSize::from(ctx.caps.limits.max_storage_buffer_range) {
+            return Err(RendererError::UnsupportedScene(format!(
+                "{count} bodies take {size} bytes, more than the device's storage buffers hold"
+            )));
+        }
+        let pipeline = build_compute_pipeline(ctx, step_cs::load(ctx.device.clone())?)?;
+        ctx.name_object(&pipeline, "nbody step pipeline");
+
+        let create_bodies = |name: &str| -> Result<_, RendererError> {
+            let bodies = Buffer::new_slice::<Body>(
+                ctx.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                    ..Default::default()
+                },
+                DeviceSize::from(count),
+            )?;
+            ctx.memory_tracker
+                .track_buffer(MemoryCategory::Storage, bodies.buffer());
+            ctx.name_object(bodies.buffer(), name);
+            Ok(bodies)
+        };
+        let bodies = [
+            create_bodies("nbody bodies 0")?,
+            create_bodies("nbody bodies 1")?,
+        ];
+        let step_set = |next: usize| {
+            PersistentDescriptorSet::new(
+                ctx.descriptor_set_allocator.as_ref(),
+                pipeline.layout().set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::buffer(0, bodies[1 - next].clone()),
+                    WriteDescriptorSet::buffer(1, bodies[next].clone()),
+                ],
+                [],
+            )
+        };
+        let step_sets = [step_set(0)?, step_set(1)?];
+        Ok(Self {
+            memory_allocator: ctx.memory_allocator.clone(),
+            pipeline,
+            bodies,
+            step_sets,
+            count,
+        })
+    }
+
+    /// A staging buffer holding a new disc of bodies, to copy in with
+    /// [`record_reset`](Self::record_reset).
+    fn initial_bodies(&self, seed: u32) -> Result<Subbuffer<[Body]>, RendererError> {
+        Ok(Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            disc(self.count, seed),
+        )?)
+    }
+
+    /// Records copying `initial` into `bodies[into]`.
+    fn record_reset(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        initial: Subbuffer<[Body]>,
+        into: usize,
+    ) -> Result<(), RendererError> {
+        builder.copy_buffer(CopyBufferInfo::buffers(initial, self.bodies[into].clone()))?;
+        Ok(())
+    }
+
+    /// Records `steps` steps from `bodies[from]`, which end up in `bodies[(from + steps) % 2]`.
+    fn record_steps(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        from: usize,
+        steps: u32,
+    ) -> Result<(), RendererError> {
+        if steps == 0 {
+            return Ok(());
+        }
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())?
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                step_cs::Step {
+                    count: self.count,
+                    time_step: TIME_STEP,
+                    softening_squared: SOFTENING * SOFTENING,
+                },
+            )?;
+        for step in 1..=steps as usize {
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    self.pipeline.layout().clone(),
+                    0,
+                    self.step_sets[(from + step) % 2].clone(),
+                )?
+                .dispatch([self.count.div_ceil(WORKGROUP_SIZE), 1, 1])?;
+        }
+        Ok(())
+    }
+}
+
+/// What the next frame records before drawing, worked out in `prepare`.
+#[derive(Default)]
+struct Updates {
+    /// The frame slot recording it, that of the first view of the frame.
+    frame_in_flight: usize,
+    /// A new disc to copy into the buffer stepped from.
+    reset: Option<Subbuffer<[Body]>>,
+    /// Which of the buffers the first step reads.
+    stepped_from: usize,
+    steps: u32,
+}
+
+/// Gravity between every pair of a few thousand bodies, stepped at a fixed rate.
+pub struct NBodyScene {
+    simulation: Simulation,
+    pipeline: Arc<GraphicsPipeline>,
+    uniforms: FrameUniforms<MvpUniform>,
+    /// The set for drawing `bodies[i]`.
+    draw_sets: [Arc<PersistentDescriptorSet>; 2],
+    /// Which of the buffers holds the newest bodies.
+    current: usize,
+    clock: StepClock,
+    paused: bool,
+    reset_requested: bool,
+    /// How many discs have been made, to vary the bodies each time.
+    resets: u32,
+    updates: Updates,
+}
+
+impl NBodyScene {
+    /// Simulates `bodies` bodies.
+    pub fn new(ctx: &VulkanContext, subpass: Subpass, bodies: u32) -> Result<Self, RendererError> {
+        let simulation = Simulation::new(ctx, bodies.max(1))?;
+        let pipeline = build_body_pipeline(ctx, subpass)?;
+        ctx.name_object(&pipeline, "nbody pipeline");
+        let uniforms = FrameUniforms::new(
+            ctx,
+            &pipeline,
+            0,
+            MvpUniform::new(Mat4::IDENTITY, &FrameData::default()),
+        )?;
+        let draw_set = |bodies: usize| {
+            PersistentDescriptorSet::new(
+                ctx.descriptor_set_allocator.as_ref(),
+                pipeline.layout().set_layouts()[1].clone(),
+                [WriteDescriptorSet::buffer(
+                    0,
+                    simulation.bodies[bodies].clone(),
+                )],
+                [],
+            )
+        };
+        let draw_sets = [draw_set(0)?, draw_set(1)?];
+        log::info!("Simulating {} bodies", simulation.count);
+        Ok(Self {
+            simulation,
+            pipeline,
+            uniforms,
+            draw_sets,
+            current: 0,
+            clock: StepClock::new(STEPS_PER_SECOND, MAX_STEPS_PER_FRAME),
+            paused: false,
+            reset_requested: true,
+            resets: 0,
+            updates: Updates::default(),
+        })
+    }
+}
+
+impl Scene for NBodyScene {
+    /// Only the first view of a frame moves the bodies on, so every view shows the same step.
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        self.uniforms
+            .write(frame, MvpUniform::new(Mat4::IDENTITY, frame))?;
+        if self.clock.time() == Some(frame.time) {
+            // The same slot at the same time is another frame rather than another view, and
+            // shows the bodies where they are.
+            if frame.frame_in_flight == self.updates.frame_in_flight {
+                self.updates = Updates {
+                    frame_in_flight: frame.frame_in_flight,
+                    stepped_from: self.current,
+                    ..Updates::default()
+                };
+            }
+            return Ok(());
+        }
+        let steps = self.clock.steps(frame.time, !self.paused);
+        let reset = if mem::take(&mut self.reset_requested) {
+            self.resets += 1;
+            Some(self.simulation.initial_bodies(self.resets)?)
+        } else {
+            None
+        };
+
+        let stepped_from = self.current;
+        self.current = (self.current + steps as usize) % 2;
+        self.updates = Updates {
+            frame_in_flight: frame.frame_in_flight,
+            reset,
+            stepped_from,
+            steps,
+        };
+        Ok(())
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let updates = &self.updates;
+        if frame.frame_in_flight != updates.frame_in_flight {
+            return Ok(());
+        }
+        if let Some(reset) = &updates.reset {
+            self.simulation
+                .record_reset(builder, reset.clone(), updates.stepped_from)?;
+        }
+        self.simulation
+            .record_steps(builder, updates.stepped_from, updates.steps)
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        match key {
+            PAUSE_KEY => {
+                self.paused = !self.paused;
+                log::info!("Bodies {}", if self.paused { "paused" } else { "moving" });
+            }
+            RESET_KEY => self.reset_requested = true,
+            _ => return false,
+        }
+        true
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: Vec3::new(-DISC_RADIUS, -DISC_HALF_THICKNESS, -DISC_RADIUS),
+            max: Vec3::new(DISC_RADIUS, DISC_HALF_THICKNESS, DISC_RADIUS),
+        })
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                vec![
+                    self.uniforms.descriptor_set(frame),
+                    self.draw_sets[self.current].clone(),
+                ],
+            )?
+            .draw(6, self.simulation.count, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// How fast the device stepped the bodies, as [`benchmark`] timed it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NBodyReport {
+    pub bodies: u32,
+    pub steps_per_run: u32,
+    /// How long each run took, from submitting its steps to the GPU finishing them.
+    pub runs: Vec<Duration>,
+}
+
+impl NBodyReport {
+    /// Pairs of bodies pulling on each other per second in the median run.
+    pub fn interactions_per_second(&self) -> f64 {
+        let mut rates: Vec<_> = self.runs.iter().map(|&run| self.rate(run)).collect();
+        rates.sort_by(f64::total_cmp);
+        rates.get(rates.len() / 2).copied().unwrap_or(0.0)
+    }
+
+    fn rate(&self, run: Duration) -> f64 {
+        let interactions = f64::from(self.bodies).powi(2) * f64::from(self.steps_per_run);
+        interactions / run.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl fmt::Display for NBodyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let billions = |rate: f64| rate / 1e9;
+        let slowest = self.runs.iter().max().map_or(0.0, |&run| self.rate(run));
+        let fastest = self.runs.iter().min().map_or(0.0, |&run| self.rate(run));
+        writeln!(
+            f,
+            "N-body: {} bodies, {} runs of {} steps",
+            self.bodies,
+            self.runs.len(),
+            self.steps_per_run
+        )?;
+        write!(
+            f,
+            "  {:.2} billion interactions/s (median), {:.2} to {:.2}",
+            billions(self.interactions_per_second()),
+            billions(slowest),
+            billions(fastest)
+        )
+    }
+}
+
+/// Steps `bodies` bodies on `ctx`'s device as fast as it can, without drawing them, and times
+/// it. After a warmup the same number of steps is timed [`BENCHMARK_RUNS`] times, each from a
+/// submission to the GPU finishing it, so the runs can be compared with each other.
+pub fn benchmark(ctx: &VulkanContext, bodies: u32) -> Result<NBodyReport, RendererError> {
+    let simulation = Simulation::new(ctx, bodies.max(1))?;
+    let steps_per_run = steps_per_run(simulation.count);
+    let initial = simulation.initial_bodies(1)?;
+    ctx.submit_and_wait(|builder| {
+        simulation.record_reset(builder, initial, 0)?;
+        simulation.record_steps(builder, 0, BENCHMARK_WARMUP_STEPS)
+    })?;
+
+    let mut current = BENCHMARK_WARMUP_STEPS as usize % 2;
+    let mut runs = Vec::with_capacity(BENCHMARK_RUNS);
+    for _ in 0..BENCHMARK_RUNS {
+        let start = Instant::now();
+        ctx.submit_and_wait(|builder| simulation.record_steps(builder, current, steps_per_run))?;
+        runs.push(start.elapsed());
+        current = (current + steps_per_run as usize) % 2;
+    }
+    Ok(NBodyReport {
+        bodies: simulation.count,
+        steps_per_run,
+        runs,
+    })
+}
+
+/// Steps enough for about [`BENCHMARK_INTERACTIONS_PER_RUN`] interactions between `bodies`
+/// bodies.
+fn steps_per_run(bodies: u32) -> u32 {
+    let per_step = u64::from(bodies).pow(2);
+    (BENCHMARK_INTERACTIONS_PER_RUN / per_step).clamp(1, u64::from(BENCHMARK_MAX_STEPS)) as u32
+}
+
+/// A pipeline like the scenes', but adding up the bodies' glow and ignoring depth, so bodies
+/// on top of each other shine brighter.
+fn build_body_pipeline(
+    ctx: &VulkanContext,
+    subpass: Subpass,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+    let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+    shader_dump::entry_point(&vs);
+    shader_dump::entry_point(&fs);
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        ctx.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(ctx.device.clone())?,
+    )?;
+
+    let has_depth = subpass.subpass_desc().depth_stencil_attachment.is_some();
+    let rasterization_samples = subpass.num_samples().unwrap_or(SampleCount::Sample1);
+    let pipeline = GraphicsPipeline::new(
+        ctx.device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(VertexInputState::new()),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples,
+                ..Default::default()
+            }),
+            depth_stencil_state: has_depth.then(DepthStencilState::default),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::additive()),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?;
+    Ok(pipeline)
+}
+
+/// `count` bodies of equal mass in a thin disc, orbiting its centre a little too slowly to stay
+/// on circles. Always the same for the same `count` and `seed`.
+fn disc(count: u32, seed: u32) -> Vec<Body> {
+    // xorshift32, which is plenty for scattering bodies. Its state must never be zero.
+    let mut state = (0x9e37_79b9_u32 ^ seed.wrapping_mul(0x85eb_ca6b)).max(1);
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    };
+    let mass = 1.0 / count as f32;
+    (0..count)
+        .map(|_| {
+            // Spread evenly over the disc's area.
+            let radius = DISC_RADIUS * random().sqrt();
+            let angle = std::f32::consts::TAU * random();
+            let height = (random() * 2.0 - 1.0) * DISC_HALF_THICKNESS;
+            let (sin, cos) = angle.sin_cos();
+            // Just enough speed for a circle around the mass nearer the centre, which for an
+            // even disc grows with the square of the radius.
+            let inner_mass = (radius / DISC_RADIUS).powi(2);
+            let softened = (radius * radius + SOFTENING * SOFTENING).powf(1.5);
+            let speed = ORBIT_FRACTION * (inner_mass * radius * radius / softened).sqrt();
+            Body {
+                position: [radius * cos, height, radius * sin, mass],
+                velocity: [-speed * sin, 0.0, speed * cos, 0.0],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_disc_is_seeded_and_orbits_its_centre() {
+        let bodies = disc(1000, 1);
+        assert_eq!(bodies, disc(1000, 1));
+        assert_ne!(bodies, disc(1000, 2));
+        let mass: f32 = bodies.iter().map(|body| body.position[3]).sum();
+        assert!((mass - 1.0).abs() < 1e-4);
+        for body in &bodies {
+            let position = Vec3::from_slice(&body.position[..3]);
+            let velocity = Vec3::from_slice(&body.velocity[..3]);
+            assert!(position.length() <= DISC_RADIUS + DISC_HALF_THICKNESS);
+            // Going round the centre, rather than towards or away from it.
+            assert!(position.dot(velocity).abs() < 1e-5);
+        }
+        // Anticlockwise seen from below, as every body turns the same way.
+        assert!(bodies.iter().all(|body| {
+            let [x, _, z, _] = body.position;
+            let [vx, _, vz, _] = body.velocity;
+            x * vz - z * vx >= 0.0
+        }));
+    }
+
+    #[test]
+    fn benchmark_runs_scale_with_the_bodies() {
+        assert_eq!(steps_per_run(DEFAULT_BODIES), 128);
+        assert_eq!(steps_per_run(1 << 20), 1);
+        assert_eq!(steps_per_run(16), BENCHMARK_MAX_STEPS);
+
+        let report = NBodyReport {
+            bodies: 1 << 14,
+            steps_per_run: 128,
+            runs: [1.0, 1.25, 0.5].map(Duration::from_secs_f32).to_vec(),
+        };
+        // The median run took a second.
+        assert_eq!(report.interactions_per_second(), 2f64.powi(35));
+        assert_eq!(
+            report.to_string(),
+            "N-body: 16384 bodies, 3 runs of 128 steps\n  \
+             34.36 billion interactions/s (median), 27.49 to 68.72"
+        );
+    }
+}
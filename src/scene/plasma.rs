@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use vulkano::buffer::BufferContents;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::scene::{build_pipeline, FrameData, FrameUniforms, Scene};
+use crate::std140::std140_layout;
+
+/// Matches the fragment shader's `Frame` uniform block.
+#[derive(BufferContents, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PlasmaUniform {
+    pub time: f32,
+}
+
+std140_layout!(PlasmaUniform {
+    time: f32 => Scalar,
+});
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec2 v_uv;
+
+            // One triangle big enough to cover the whole screen, generated from the vertex index
+            // so no vertex buffer is needed.
+            void main() {
+                v_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(v_uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                float time;
+            } frame;
+
+            void main() {
+                vec2 p = v_uv * 8.0;
+                float t = frame.time;
+                float v = sin(p.x + t) + sin(p.y + t * 0.7) + sin(p.x + p.y + t * 1.3);
+                vec3 color = 0.5 + 0.5 * cos(v + vec3(0.0, 2.0, 4.0));
+                // The whole image pulses brighter and darker about once a second.
+                float pulse = 0.75 + 0.25 * sin(t * 6.2831853);
+                f_color = vec4(color * pulse, 1.0);
+            }
+        "
+    }
+}
+
+/// A full-screen animated plasma, driven only by the elapsed time.
+pub struct PlasmaScene {
+    pipeline: Arc<GraphicsPipeline>,
+    uniforms: FrameUniforms<PlasmaUniform>,
+}
+
+impl PlasmaScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+
+        let pipeline =
+            build_pipeline(ctx.device.clone(), vs, fs, VertexInputState::new(), subpass)?;
+        ctx.name_object(&pipeline, "plasma pipeline");
+        let uniforms = FrameUniforms::new(ctx, &pipeline, 0, PlasmaUniform { time: 0.0 })?;
+
+        Ok(Self { pipeline, uniforms })
+    }
+}
+
+impl Scene for PlasmaScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        self.uniforms
+            .write(frame, PlasmaUniform { time: frame.time })
+    }
+
+    fn draw_revision(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                self.uniforms.descriptor_set(frame),
+            )?
+            .draw(3, 1, 0, 0)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+//! A cubemap drawn behind a scene, for `--skybox`, or the environment map of `--environment`.
+//!
+//! The sky is a triangle covering the target at the far plane, drawn before the scene with depth
+//! writes off, so everything the scene draws covers it and what the scene blends is blended over
+//! it. Each
+//! pixel samples the cubemap in the direction the camera looks through it, ignoring where the
+//! camera is, so the sky stays infinitely far away.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use glam::{Mat3, Mat4};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::sampler::{Filter, Sampler};
+use vulkano::image::view::ImageView;
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState};
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::Pipeline;
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::culling::CullStats;
+use crate::error::RendererError;
+use crate::picking::{Aabb, Ray};
+use crate::sampler::SamplerConfig;
+use crate::scene::{
+    build_pipeline_with_depth, BatchStats, FrameData, FrameUniforms, Material, MaterialSet, Scene,
+};
+use crate::std140::std140_layout;
+use crate::texture::{read_png, Texture};
+
+/// The files a skybox directory holds, one per face, in the order the cubemap's layers are.
+pub const FACE_FILES: [&str; 6] = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec4 v_direction;
+
+            layout(set = 0, binding = 0) uniform Sky {
+                mat4 inverse_view_projection;
+            } sky;
+
+            void main() {
+                // A triangle covering the target, at the far plane.
+                vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+                gl_Position = vec4(position, 1.0, 1.0);
+                // The point on the near plane this corner shows. The camera sits at the origin,
+                // so that is also the direction it is seen in, once divided by w.
+                v_direction = sky.inverse_view_projection * vec4(position, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec4 v_direction;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 1, binding = 0) uniform samplerCube sky;
+
+            void main() {
+                // Dividing isn't linear across the target, so it can't be left to the
+                // interpolation.
+                f_color = vec4(texture(sky, v_direction.xyz / v_direction.w).rgb, 1.0);
+            }
+        "
+    }
+}
+
+/// The cubemap and the pipeline drawing it.
+struct Skybox {
+    material: Material,
+    uniforms: FrameUniforms<vs::Sky>,
+}
+
+impl Skybox {
+    fn load(ctx: &VulkanContext, subpass: Subpass, dir: &Path) -> Result<Self, RendererError> {
+        let (size, faces) = read_faces(dir)?;
+        let cubemap = Texture::cubemap_from_srgba8(
+            ctx,
+            size,
+            &faces,
+            SamplerConfig::nearest_clamped().with_filter(Filter::Linear),
+        )?;
+        ctx.name_object(&cubemap.image, "skybox cubemap");
+        Self::new(ctx, subpass, cubemap.view, cubemap.sampler)
+    }
+
+    fn new(
+        ctx: &VulkanContext,
+        subpass: Subpass,
+        cubemap: Arc<ImageView>,
+        sampler: Arc<Sampler>,
+    ) -> Result<Self, RendererError> {
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let pipeline = build_pipeline_with_depth(
+            ctx.device.clone(),
+            vs,
+            fs,
+            VertexInputState::new(),
+            subpass,
+            // The sky is at the far plane, which the depth buffer is cleared to.
+            DepthState {
+                write_enable: false,
+                compare_op: CompareOp::LessOrEqual,
+            },
+            |_| {},
+        )?;
+        ctx.name_object(&pipeline, "skybox pipeline");
+
+        let cubemap_set = PersistentDescriptorSet::new(
+            ctx.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts()[1].clone(),
+            [WriteDescriptorSet::image_view_sampler(0, cubemap, sampler)],
+            [],
+        )?;
+        let uniforms = FrameUniforms::new(ctx, &pipeline, 0, sky_uniform(&FrameData::default()))?;
+        let material = Material::new(pipeline).with_sets(1, [MaterialSet::Shared(cubemap_set)]);
+        Ok(Self { material, uniforms })
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        self.material.bind(builder, frame)?;
+        self.material
+            .bind_sets(builder, 0, self.uniforms.descriptor_set(frame))?;
+        builder.draw(3, 1, 0, 0)?;
+        Ok(())
+    }
+}
+
+std140_layout!(vs::Sky {
+    inverse_view_projection: [[f32; 4]; 4] => Mat4,
+});
+
+/// Undoes the camera's rotation and projection, but not its position.
+fn sky_uniform(frame: &FrameData) -> vs::Sky {
+    let rotation = Mat4::from_mat3(Mat3::from_mat4(frame.view));
+    vs::Sky {
+        inverse_view_projection: (frame.projection * rotation).inverse().to_cols_array_2d(),
+    }
+}
+
+/// Reads the six faces in `dir`, returning their size and their pixels one after the other.
+fn read_faces(dir: &Path) -> Result<(u32, Vec<u8>), RendererError> {
+    let mut sizes = Vec::with_capacity(FACE_FILES.len());
+    let mut pixels = Vec::new();
+    for file in FACE_FILES {
+        let path = dir.join(file);
+        let (size, face) = read_png(&path).map_err(|err| {
+            RendererError::InvalidTexture(format!("skybox face {}: {err}", path.display()))
+        })?;
+        sizes.push((path, size));
+        pixels.extend(face);
+    }
+    let size = face_size(&sizes).map_err(RendererError::InvalidTexture)?;
+    Ok((size, pixels))
+}
+
+/// The size the faces share. The faces of a cubemap are square and all the same size.
+fn face_size(sizes: &[(PathBuf, [u32; 2])]) -> Result<u32, String> {
+    let (first_path, [width, height]) = &sizes[0];
+    if width != height {
+        return Err(format!(
+            "skybox face {} is {width}x{height}, but faces must be square",
+            first_path.display()
+        ));
+    }
+    match sizes.iter().find(|(_, size)| size != &[*width, *height]) {
+        Some((path, [other_width, other_height])) => Err(format!(
+            "skybox face {} is {other_width}x{other_height}, but {} is {width}x{height}",
+            path.display(),
+            first_path.display()
+        )),
+        None => Ok(*width),
+    }
+}
+
+/// Draws `scene`, then a skybox behind it.
+pub struct WithSkybox {
+    scene: Box<dyn Scene>,
+    skybox: Skybox,
+}
+
+impl WithSkybox {
+    /// Loads the cubemap from the [`FACE_FILES`] in `dir` to draw behind `scene`.
+    pub fn load(
+        ctx: &VulkanContext,
+        subpass: Subpass,
+        scene: Box<dyn Scene>,
+        dir: &Path,
+    ) -> Result<Self, RendererError> {
+        Ok(Self {
+            scene,
+            skybox: Skybox::load(ctx, subpass, dir)?,
+        })
+    }
+
+    /// Draws `cubemap`, sampled with `sampler`, behind `scene`.
+    pub fn new(
+        ctx: &VulkanContext,
+        subpass: Subpass,
+        scene: Box<dyn Scene>,
+        cubemap: Arc<ImageView>,
+        sampler: Arc<Sampler>,
+    ) -> Result<Self, RendererError> {
+        Ok(Self {
+            scene,
+            skybox: Skybox::new(ctx, subpass, cubemap, sampler)?,
+        })
+    }
+}
+
+impl Scene for WithSkybox {
+    fn update(&mut self, dt: f32) {
+        self.scene.update(dt);
+    }
+
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        self.scene.prepare(frame)?;
+        self.skybox.uniforms.write(frame, sky_uniform(frame))
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<vulkano::command_buffer::PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        self.scene.draw_offscreen(builder, frame)
+    }
+
+    fn pick(&self, ray: &Ray) -> Option<usize> {
+        self.scene.pick(ray)
+    }
+
+    fn set_selection(&mut self, selection: Option<usize>) {
+        self.scene.set_selection(selection);
+    }
+
+    fn key_pressed(&mut self, key: winit::event::VirtualKeyCode) -> bool {
+        self.scene.key_pressed(key)
+    }
+
+    fn paint(&mut self, from: [f32; 2], to: [f32; 2], target_extent: [u32; 2]) -> bool {
+        self.scene.paint(from, to, target_extent)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.scene.bounds()
+    }
+
+    fn cull_stats(&self, frame: &FrameData) -> Option<CullStats> {
+        self.scene.cull_stats(frame)
+    }
+
+    fn batch_stats(&self, frame: &FrameData) -> Option<BatchStats> {
+        self.scene.batch_stats(frame)
+    }
+
+    /// The sky's draw never changes; the camera reaches it through its uniforms.
+    fn draw_revision(&self) -> Option<u64> {
+        self.scene.draw_revision()
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        self.skybox.draw(builder, frame)?;
+        self.scene.draw(builder, frame)
+    }
+
+    fn node_count(&self) -> usize {
+        self.scene.node_count()
+    }
+
+    /// The sky goes with the first nodes, so it is still drawn before everything else.
+    fn draw_nodes(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+        nodes: Range<usize>,
+    ) -> Result<(), RendererError> {
+        if nodes.start == 0 {
+            self.skybox.draw(builder, frame)?;
+        }
+        self.scene.draw_nodes(builder, frame, nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(sizes: &[[u32; 2]]) -> Vec<(PathBuf, [u32; 2])> {
+        FACE_FILES
+            .iter()
+            .zip(sizes)
+            .map(|(file, size)| (PathBuf::from(file), *size))
+            .collect()
+    }
+
+    #[test]
+    fn faces_must_be_square_and_the_same_size() {
+        assert_eq!(face_size(&sizes(&[[64, 64]; 6])), Ok(64));
+        assert!(face_size(&sizes(&[[64, 32]; 6])).is_err());
+        let mut mixed = [[64, 64]; 6];
+        mixed[4] = [32, 32];
+        let err = face_size(&sizes(&mixed)).unwrap_err();
+        assert!(err.contains("pz.png is 32x32"), "{err}");
+    }
+
+    #[test]
+    fn the_sky_ignores_where_the_camera_is() {
+        let projection = crate::camera::perspective(1.0, 1.5, 0.1, 100.0);
+        let at = |eye| FrameData {
+            view: Mat4::look_at_rh(eye, eye + glam::Vec3::NEG_Z, glam::Vec3::Y),
+            projection,
+            ..FrameData::default()
+        };
+        let origin = sky_uniform(&at(glam::Vec3::ZERO)).inverse_view_projection;
+        let moved = sky_uniform(&at(glam::Vec3::new(5.0, -2.0, 9.0))).inverse_view_projection;
+        for (a, b) in origin.iter().flatten().zip(moved.iter().flatten()) {
+            assert!((a - b).abs() < 1e-4, "{origin:?} != {moved:?}");
+        }
+    }
+}
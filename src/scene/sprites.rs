@@ -0,0 +1,170 @@
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::scene::{FrameData, Scene};
+use crate::sprite::{AtlasBuilder, Sprite, SpriteBatch, SpriteTexture, UvRect};
+use crate::texture::checkerboard;
+
+/// How many sprites are drawn.
+pub const SPRITES: u32 = 10_000;
+
+/// Pixels across each image in the atlas.
+const IMAGE_SIZE: u32 = 32;
+
+/// What one sprite does, picked at random once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Mover {
+    /// Where it starts, as fractions of the view's width and height.
+    start: [f32; 2],
+    /// Views crossed per second, in each direction.
+    velocity: [f32; 2],
+    /// In pixels.
+    size: f32,
+    /// Radians per second.
+    spin: f32,
+    /// Into the atlas's images.
+    image: usize,
+    tint: [f32; 4],
+}
+
+impl Mover {
+    /// The sprite at `time` seconds, bouncing back and forth across a view of `extent`.
+    fn sprite(&self, time: f32, extent: [u32; 2], rects: &[UvRect]) -> Sprite {
+        let bounce = |start: f32, velocity: f32| {
+            1.0 - ((start + velocity * time).rem_euclid(2.0) - 1.0).abs()
+        };
+        Sprite {
+            position: [0, 1].map(|axis| {
+                bounce(self.start[axis] * 2.0, self.velocity[axis]) * extent[axis] as f32
+            }),
+            size: [self.size; 2],
+            rotation: self.spin * time,
+            uv: rects[self.image],
+            tint: self.tint,
+        }
+    }
+}
+
+/// 10,000 sprites from one atlas bouncing around the window, drawn in a single draw call by a
+/// [`SpriteBatch`].
+pub struct SpritesScene {
+    batch: SpriteBatch,
+    atlas: SpriteTexture,
+    rects: Vec<UvRect>,
+    movers: Vec<Mover>,
+}
+
+impl SpritesScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let mut atlas = AtlasBuilder::new();
+        let shapes: [fn(f32, f32) -> bool; 3] = [
+            |x, y| x * x + y * y <= 1.0,
+            |x, y| (0.5..=1.0).contains(&(x * x + y * y)),
+            |x, y| x.abs() + y.abs() <= 1.0,
+        ];
+        for shape in shapes {
+            atlas.add([IMAGE_SIZE; 2], shape_pixels(shape));
+        }
+        // White and grey, so the tint shows through.
+        atlas.add(
+            [IMAGE_SIZE; 2],
+            checkerboard(IMAGE_SIZE, 4, [255; 4], [128, 128, 128, 255]),
+        );
+        let atlas = atlas.build();
+        let texture = atlas.upload(ctx)?;
+
+        let mut batch = SpriteBatch::new(ctx, subpass)?;
+        let atlas_texture = batch.add_texture(ctx, &texture)?;
+        Ok(Self {
+            batch,
+            atlas: atlas_texture,
+            movers: movers(SPRITES, atlas.rects.len()),
+            rects: atlas.rects,
+        })
+    }
+}
+
+impl Scene for SpritesScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        for mover in &self.movers {
+            let sprite = mover.sprite(frame.time, frame.extent, &self.rects);
+            self.batch.queue(self.atlas, &sprite);
+        }
+        self.batch.upload(frame)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        self.batch.draw(builder, frame)
+    }
+}
+
+/// A white image of `shape`, which says whether a point from -1 to 1 on each axis is inside.
+fn shape_pixels(shape: fn(f32, f32) -> bool) -> Vec<u8> {
+    (0..IMAGE_SIZE * IMAGE_SIZE)
+        .flat_map(|index| {
+            let centre =
+                |coordinate: u32| (coordinate as f32 + 0.5) / IMAGE_SIZE as f32 * 2.0 - 1.0;
+            let inside = shape(centre(index % IMAGE_SIZE), centre(index / IMAGE_SIZE));
+            [255, 255, 255, if inside { 255 } else { 0 }]
+        })
+        .collect()
+}
+
+/// `count` sprites scattered over the view, each showing one of `images`. Always the same for
+/// the same `count`.
+fn movers(count: u32, images: usize) -> Vec<Mover> {
+    // xorshift32, which is plenty for scattering sprites. Its state must never be zero.
+    let mut state = 0x2545_f491_u32;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    };
+    (0..count)
+        .map(|_| {
+            let hue = random();
+            Mover {
+                start: [random(), random()],
+                velocity: [random() * 0.4 - 0.2, random() * 0.4 - 0.2],
+                size: 6.0 + random() * 18.0,
+                spin: random() * 6.0 - 3.0,
+                image: ((random() * images as f32) as usize).min(images - 1),
+                tint: [
+                    0.5 + 0.5 * (std::f32::consts::TAU * hue).cos(),
+                    0.5 + 0.5 * (std::f32::consts::TAU * (hue - 1.0 / 3.0)).cos(),
+                    0.5 + 0.5 * (std::f32::consts::TAU * (hue - 2.0 / 3.0)).cos(),
+                    0.85,
+                ],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_stay_in_the_view_whatever_its_size() {
+        let movers = movers(SPRITES, 4);
+        assert_eq!(movers.len(), SPRITES as usize);
+        let rects = [UvRect::FULL; 4];
+        for extent in [[1280, 720], [300, 900]] {
+            for time in [0.0, 3.7, 1000.0] {
+                for mover in &movers {
+                    let sprite = mover.sprite(time, extent, &rects);
+                    for (length, position) in extent.into_iter().zip(sprite.position) {
+                        assert!((0.0..=length as f32).contains(&position));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Stepping simulations at a fixed rate whatever the frame rate, for the scenes that run one,
+//! and [`FixedTimestep`], which updates every scene at one.
+
+/// How many times a second [`Scene::update`](super::Scene::update) is called, unless
+/// `--update-rate` says otherwise.
+pub const DEFAULT_UPDATE_RATE: f32 = 60.0;
+
+/// The most updates one frame makes, so a long hitch doesn't stall the frames after it.
+const MAX_UPDATES_PER_FRAME: u32 = 8;
+
+/// Turns frame times into whole steps, at a fixed rate.
+#[derive(Debug)]
+pub(super) struct StepClock {
+    steps_per_second: f32,
+    /// The most steps one frame takes. After a longer hitch the simulation skips the rest
+    /// rather than catching up.
+    max_steps_per_frame: u32,
+    /// The time of the frame last stepped for.
+    time: Option<f32>,
+    /// Steps due but not taken yet, less than one.
+    owed: f32,
+}
+
+impl StepClock {
+    pub(super) fn new(steps_per_second: f32, max_steps_per_frame: u32) -> Self {
+        Self {
+            steps_per_second,
+            max_steps_per_frame,
+            time: None,
+            owed: 0.0,
+        }
+    }
+
+    /// The time of the frame last stepped for, if any was.
+    pub(super) fn time(&self) -> Option<f32> {
+        self.time
+    }
+
+    /// How many steps the frame at `time` takes, none unless `running`.
+    pub(super) fn steps(&mut self, time: f32, running: bool) -> u32 {
+        let elapsed = self.time.map_or(0.0, |last| (time - last).max(0.0));
+        self.time = Some(time);
+        if !running {
+            self.owed = 0.0;
+            return 0;
+        }
+        self.owed += elapsed * self.steps_per_second;
+        let steps = self.owed.floor();
+        self.owed -= steps;
+        (steps as u32).min(self.max_steps_per_frame)
+    }
+
+    /// How far the frame last stepped for is from the last step to the next, from 0 to 1.
+    pub(super) fn alpha(&self) -> f32 {
+        self.owed
+    }
+}
+
+/// Updates at a fixed timestep, however long frames take: as many times as are due before each
+/// frame is drawn, possibly none.
+///
+/// A frame is usually drawn between two updates. Scenes that keep the state of the last two can
+/// draw what is between them, by the frame's [`update_alpha`](super::FrameData::update_alpha),
+/// rather than stutter when updates and frames don't line up.
+#[derive(Debug)]
+pub struct FixedTimestep {
+    clock: StepClock,
+    timestep: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(updates_per_second: f32) -> Self {
+        Self {
+            clock: StepClock::new(updates_per_second, MAX_UPDATES_PER_FRAME),
+            timestep: updates_per_second.recip(),
+        }
+    }
+
+    /// Seconds between updates, which every update is passed.
+    pub fn timestep(&self) -> f32 {
+        self.timestep
+    }
+
+    /// Calls `update` with the [`timestep`](Self::timestep) as many times as are due by the
+    /// frame at `time`. Returns how far the frame is from the last update to the next.
+    pub fn advance(&mut self, time: f32, mut update: impl FnMut(f32)) -> f32 {
+        for _ in 0..self.clock.steps(time, true) {
+            update(self.timestep);
+        }
+        self.clock.alpha()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_clock_steps_at_a_fixed_rate() {
+        let mut clock = StepClock::new(15.0, 4);
+        assert_eq!(clock.steps(1.0, true), 0);
+        // 1.875 steps are due, then another 1.875 on top of what was left over.
+        assert_eq!(clock.steps(1.125, true), 1);
+        assert_eq!(clock.steps(1.25, true), 2);
+        assert_eq!(clock.steps(1.265625, true), 0);
+        // Paused, nothing is owed, however long it has been.
+        assert_eq!(clock.steps(5.0, false), 0);
+        assert_eq!(clock.steps(5.0625, true), 0);
+        // A hitch doesn't make the next frame step for as long as the hitch lasted.
+        assert_eq!(clock.steps(15.0, true), 4);
+        assert_eq!(clock.time(), Some(15.0));
+    }
+
+    #[test]
+    fn fixed_timesteps_update_as_often_as_due_and_say_how_far_between() {
+        let mut timestep = FixedTimestep::new(8.0);
+        assert_eq!(timestep.timestep(), 0.125);
+        let mut updates = Vec::new();
+        assert_eq!(timestep.advance(2.0, |dt| updates.push(dt)), 0.0);
+        assert!(updates.is_empty());
+        // Three updates and a half are due, then the other half and one more.
+        assert_eq!(timestep.advance(2.4375, |dt| updates.push(dt)), 0.5);
+        assert_eq!(updates, [0.125; 3]);
+        assert_eq!(timestep.advance(2.625, |dt| updates.push(dt)), 0.0);
+        assert_eq!(updates.len(), 5);
+        // However long the frame took, it only makes so many updates.
+        updates.clear();
+        timestep.advance(100.0, |dt| updates.push(dt));
+        assert_eq!(updates.len() as u32, MAX_UPDATES_PER_FRAME);
+    }
+}
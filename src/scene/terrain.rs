@@ -0,0 +1,570 @@
+//! Rolling terrain generated on the GPU instead of loaded from a heightmap.
+//!
+//! A compute shader sums octaves of simplex noise (fBm) into an `R32_SFLOAT` heightmap, with the
+//! seed and the shape of the noise as push constants. The terrain is a flat grid with a vertex
+//! per texel, which the vertex shader lifts to the heights it reads, lighting it with normals
+//! taken from the differences between neighbouring heights. Generating again only records
+//! another dispatch with other push constants, so the pipelines stay as they are.
+//!
+//! [`REGENERATE_KEY`] generates a new terrain from the next seed. The other keys adjust the
+//! noise, see [`NoiseParams::adjust`], and every change is logged, so the console overlay shows
+//! it.
+
+use std::mem;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use vulkano::buffer::IndexBuffer;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::AllocationCreateInfo;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::{ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+use winit::event::VirtualKeyCode;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::index_buffer::create_index_buffer;
+use crate::memory_report::MemoryCategory;
+use crate::picking::Aabb;
+use crate::sampler::SamplerConfig;
+use crate::scene::{
+    build_compute_pipeline, build_pipeline, FrameData, FrameUniforms, MvpUniform, Scene,
+};
+
+/// Texels of the heightmap along each side, and so vertices of the grid.
+const HEIGHTMAP_SIZE: u32 = 256;
+
+/// The heightmap's format, which the generating shader's `r32f` qualifier matches.
+const HEIGHTMAP_FORMAT: Format = Format::R32_SFLOAT;
+
+/// Texels each workgroup generates along each axis; matches `local_size_x` and `local_size_y`
+/// in the generating shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// How wide and deep the terrain is, centred on the origin; `SIZE` in the vertex shader.
+const TERRAIN_SIZE: f32 = 4.0;
+
+/// How far the terrain reaches above and below zero; `HEIGHT` in the vertex shader.
+const TERRAIN_HEIGHT: f32 = 0.6;
+
+/// Key that generates a new terrain from the next seed.
+const REGENERATE_KEY: VirtualKeyCode = VirtualKeyCode::R;
+
+/// Keys that take away and add an octave.
+const FEWER_OCTAVES_KEY: VirtualKeyCode = VirtualKeyCode::LBracket;
+const MORE_OCTAVES_KEY: VirtualKeyCode = VirtualKeyCode::RBracket;
+
+/// Keys that lower and raise the lacunarity.
+const LACUNARITY_DOWN_KEY: VirtualKeyCode = VirtualKeyCode::Semicolon;
+const LACUNARITY_UP_KEY: VirtualKeyCode = VirtualKeyCode::Apostrophe;
+
+/// Keys that lower and raise the gain.
+const GAIN_DOWN_KEY: VirtualKeyCode = VirtualKeyCode::Comma;
+const GAIN_UP_KEY: VirtualKeyCode = VirtualKeyCode::Period;
+
+mod generate_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, r32f) uniform writeonly image2D heights;
+
+            layout(push_constant) uniform Noise {
+                uint seed;
+                uint octaves;
+                // How much finer each octave is than the one before.
+                float lacunarity;
+                // How much weaker each octave is than the one before.
+                float gain;
+                // Features of the first octave across the heightmap.
+                float frequency;
+            } noise;
+
+            uint hash(uint x) {
+                x ^= x >> 16;
+                x *= 0x7feb352du;
+                x ^= x >> 15;
+                x *= 0x846ca68bu;
+                x ^= x >> 16;
+                return x;
+            }
+
+            vec3 permute(vec3 x) {
+                return mod((x * 34.0 + 1.0) * x, 289.0);
+            }
+
+            // 2D simplex noise, from -1 to 1, after Ian McEwan and Stefan Gustavson's
+            // webgl-noise.
+            float simplex(vec2 v) {
+                const vec4 C = vec4(
+                    0.211324865405187,   // (3 - sqrt(3)) / 6
+                    0.366025403784439,   // (sqrt(3) - 1) / 2
+                    -0.577350269189626,  // -1 + 2 * C.x
+                    0.024390243902439    // 1 / 41
+                );
+                vec2 i = floor(v + dot(v, C.yy));
+                vec2 x0 = v - i + dot(i, C.xx);
+                vec2 i1 = x0.x > x0.y ? vec2(1.0, 0.0) : vec2(0.0, 1.0);
+                vec4 x12 = x0.xyxy + C.xxzz;
+                x12.xy -= i1;
+
+                i = mod(i, 289.0);
+                vec3 p = permute(
+                    permute(i.y + vec3(0.0, i1.y, 1.0)) + i.x + vec3(0.0, i1.x, 1.0)
+                );
+                vec3 m = max(
+                    0.5 - vec3(dot(x0, x0), dot(x12.xy, x12.xy), dot(x12.zw, x12.zw)),
+                    0.0
+                );
+                m = m * m;
+                m = m * m;
+
+                vec3 x = 2.0 * fract(p * C.www) - 1.0;
+                vec3 h = abs(x) - 0.5;
+                vec3 a0 = x - floor(x + 0.5);
+                m *= 1.79284291400159 - 0.85373472095314 * (a0 * a0 + h * h);
+                vec3 g = vec3(
+                    a0.x * x0.x + h.x * x0.y,
+                    a0.yz * x12.xz + h.yz * x12.yw
+                );
+                return 130.0 * dot(m, g);
+            }
+
+            void main() {
+                ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+                ivec2 size = imageSize(heights);
+                if (any(greaterThanEqual(texel, size))) {
+                    return;
+                }
+                // Each seed looks at another part of the noise. The offset isn't scaled with the
+                // octaves, so the finest ones keep their precision.
+                vec2 offset = vec2(hash(noise.seed), hash(noise.seed ^ 0x9e3779b9u));
+                offset = offset / 4294967295.0 * 256.0;
+                vec2 position = vec2(texel) / vec2(size - 1) * noise.frequency;
+
+                float height = 0.0;
+                float amplitude = 1.0;
+                float scale = 1.0;
+                float total = 0.0;
+                for (uint octave = 0; octave < noise.octaves; octave++) {
+                    height += amplitude * simplex(position * scale + offset);
+                    total += amplitude;
+                    scale *= noise.lacunarity;
+                    amplitude *= noise.gain;
+                }
+                imageStore(heights, texel, vec4(height / total));
+            }
+        "
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec3 v_normal;
+            layout(location = 1) out float v_height;
+
+            layout(set = 0, binding = 0) uniform Mvp {
+                mat4 model;
+                mat4 view;
+                mat4 projection;
+                float time;
+            } mvp;
+
+            layout(set = 1, binding = 0) uniform sampler2D heights;
+
+            const float SIZE = 4.0;
+            const float HEIGHT = 0.6;
+
+            float height_at(ivec2 texel) {
+                ivec2 last = textureSize(heights, 0) - 1;
+                return texelFetch(heights, clamp(texel, ivec2(0), last), 0).r * HEIGHT;
+            }
+
+            void main() {
+                int size = textureSize(heights, 0).x;
+                ivec2 texel = ivec2(gl_VertexIndex % size, gl_VertexIndex / size);
+                float cell = SIZE / float(size - 1);
+                float height = height_at(texel);
+                vec2 xz = (vec2(texel) * cell) - SIZE / 2.0;
+
+                // Central differences, one cell either side.
+                float left = height_at(texel - ivec2(1, 0));
+                float right = height_at(texel + ivec2(1, 0));
+                float back = height_at(texel - ivec2(0, 1));
+                float front = height_at(texel + ivec2(0, 1));
+                v_normal = normalize(vec3(left - right, 2.0 * cell, back - front));
+                v_height = height / HEIGHT;
+
+                gl_Position = mvp.projection * mvp.view * mvp.model * vec4(xz.x, height, xz.y, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_normal;
+            layout(location = 1) in float v_height;
+
+            layout(location = 0) out vec4 f_color;
+
+            const vec3 SUN = normalize(vec3(0.4, 0.8, 0.3));
+
+            void main() {
+                vec3 normal = normalize(v_normal);
+                // Grass in the valleys, rock higher up and on steep slopes, snow on the peaks.
+                vec3 grass = vec3(0.22, 0.45, 0.16);
+                vec3 rock = vec3(0.42, 0.37, 0.32);
+                vec3 snow = vec3(0.92, 0.94, 0.96);
+                float steepness = 1.0 - normal.y;
+                vec3 color = mix(grass, rock, smoothstep(0.0, 0.45, v_height + steepness * 2.0));
+                color = mix(color, snow, smoothstep(0.45, 0.6, v_height - steepness));
+
+                float light = 0.25 + 0.75 * max(dot(normal, SUN), 0.0);
+                f_color = vec4(color * light, 1.0);
+            }
+        "
+    }
+}
+
+/// The shape of the noise the terrain is generated from, as the generating shader's push
+/// constants take it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NoiseParams {
+    seed: u32,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            octaves: 6,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl NoiseParams {
+    const OCTAVES: RangeInclusive<u32> = 1..=10;
+    const LACUNARITY: RangeInclusive<f32> = 1.2..=3.0;
+    const GAIN: RangeInclusive<f32> = 0.2..=0.8;
+
+    /// The params after pressing `key`, or `None` if the key doesn't change them. Stops at the
+    /// ends of the ranges above.
+    fn adjust(self, key: VirtualKeyCode) -> Option<Self> {
+        let clamp = |value: f32, range: RangeInclusive<f32>| {
+            // Rounded, so repeated steps don't drift off the tenths and twentieths.
+            let value = (value * 100.0).round() / 100.0;
+            value.clamp(*range.start(), *range.end())
+        };
+        let octaves = |octaves: u32| octaves.clamp(*Self::OCTAVES.start(), *Self::OCTAVES.end());
+        Some(match key {
+            REGENERATE_KEY => Self {
+                seed: self.seed.wrapping_add(1),
+                ..self
+            },
+            FEWER_OCTAVES_KEY => Self {
+                octaves: octaves(self.octaves.saturating_sub(1)),
+                ..self
+            },
+            MORE_OCTAVES_KEY => Self {
+                octaves: octaves(self.octaves + 1),
+                ..self
+            },
+            LACUNARITY_DOWN_KEY => Self {
+                lacunarity: clamp(self.lacunarity - 0.1, Self::LACUNARITY),
+                ..self
+            },
+            LACUNARITY_UP_KEY => Self {
+                lacunarity: clamp(self.lacunarity + 0.1, Self::LACUNARITY),
+                ..self
+            },
+            GAIN_DOWN_KEY => Self {
+                gain: clamp(self.gain - 0.05, Self::GAIN),
+                ..self
+            },
+            GAIN_UP_KEY => Self {
+                gain: clamp(self.gain + 0.05, Self::GAIN),
+                ..self
+            },
+            _ => return None,
+        })
+    }
+
+    fn push_constants(&self) -> generate_cs::Noise {
+        generate_cs::Noise {
+            seed: self.seed,
+            octaves: self.octaves,
+            lacunarity: self.lacunarity,
+            gain: self.gain,
+            frequency: 3.0,
+        }
+    }
+}
+
+/// A terrain of fBm noise, generated on the GPU and regenerated on request.
+pub struct TerrainScene {
+    generate_pipeline: Arc<ComputePipeline>,
+    generate_set: Arc<PersistentDescriptorSet>,
+    pipeline: Arc<GraphicsPipeline>,
+    uniforms: FrameUniforms<MvpUniform>,
+    heights_set: Arc<PersistentDescriptorSet>,
+    indices: IndexBuffer,
+    index_count: u32,
+    params: NoiseParams,
+    regenerate_requested: bool,
+    /// The frame slot that generates the heightmap anew, replaced with `None` once that slot
+    /// comes round again.
+    generating_in: Option<usize>,
+}
+
+impl TerrainScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let needed = FormatFeatures::STORAGE_IMAGE | FormatFeatures::SAMPLED_IMAGE;
+        let missing = needed.difference(ctx.caps.format_features(HEIGHTMAP_FORMAT));
+        if !missing.is_empty() {
+            return Err(RendererError::UnsupportedScene(format!(
+                "terrain needs {HEIGHTMAP_FORMAT:?} images to support {missing:?}"
+            )));
+        }
+
+        let heights = Image::new(
+            ctx.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: HEIGHTMAP_FORMAT,
+                extent: [HEIGHTMAP_SIZE, HEIGHTMAP_SIZE, 1],
+                usage: ImageUsage::STORAGE | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+        ctx.memory_tracker
+            .track_image(MemoryCategory::Texture, &heights);
+        ctx.name_object(&*heights, "terrain heightmap");
+        let heights = ImageView::new_default(heights)?;
+
+        let generate_pipeline =
+            build_compute_pipeline(ctx, generate_cs::load(ctx.device.clone())?)?;
+        ctx.name_object(&generate_pipeline, "terrain generate pipeline");
+        let generate_set = PersistentDescriptorSet::new(
+            ctx.descriptor_set_allocator.as_ref(),
+            generate_pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view(0, heights.clone())],
+            [],
+        )?;
+
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let pipeline =
+            build_pipeline(ctx.device.clone(), vs, fs, VertexInputState::new(), subpass)?;
+        ctx.name_object(&pipeline, "terrain pipeline");
+        let uniforms = FrameUniforms::new(
+            ctx,
+            &pipeline,
+            0,
+            MvpUniform::new(Mat4::IDENTITY, &FrameData::default()),
+        )?;
+        // Heights are fetched a texel at a time, never filtered.
+        let heights_set = PersistentDescriptorSet::new(
+            ctx.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts()[1].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                heights,
+                ctx.samplers.get(SamplerConfig::nearest_clamped())?,
+            )],
+            [],
+        )?;
+
+        let grid = grid_indices(HEIGHTMAP_SIZE);
+        let vertex_count = (HEIGHTMAP_SIZE * HEIGHTMAP_SIZE) as usize;
+        let indices = create_index_buffer(ctx, &grid, vertex_count)?;
+
+        Ok(Self {
+            generate_pipeline,
+            generate_set,
+            pipeline,
+            uniforms,
+            heights_set,
+            indices,
+            index_count: grid.len() as u32,
+            params: NoiseParams::default(),
+            regenerate_requested: true,
+            generating_in: None,
+        })
+    }
+}
+
+impl Scene for TerrainScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        if mem::take(&mut self.regenerate_requested) {
+            let NoiseParams {
+                seed,
+                octaves,
+                lacunarity,
+                gain,
+            } = self.params;
+            log::info!(
+                "Terrain: seed {seed}, {octaves} octaves, lacunarity {lacunarity:.1}, \
+                 gain {gain:.2}"
+            );
+            self.generating_in = Some(frame.frame_in_flight);
+        } else if self.generating_in == Some(frame.frame_in_flight) {
+            self.generating_in = None;
+        }
+        self.uniforms
+            .write(frame, MvpUniform::new(Mat4::IDENTITY, frame))
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        if self.generating_in != Some(frame.frame_in_flight) {
+            return Ok(());
+        }
+        builder
+            .bind_pipeline_compute(self.generate_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                self.generate_pipeline.layout().clone(),
+                0,
+                self.generate_set.clone(),
+            )?
+            .push_constants(
+                self.generate_pipeline.layout().clone(),
+                0,
+                self.params.push_constants(),
+            )?
+            .dispatch([
+                HEIGHTMAP_SIZE.div_ceil(WORKGROUP_SIZE),
+                HEIGHTMAP_SIZE.div_ceil(WORKGROUP_SIZE),
+                1,
+            ])?;
+        Ok(())
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        match self.params.adjust(key) {
+            Some(params) => {
+                self.params = params;
+                self.regenerate_requested = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let half = TERRAIN_SIZE / 2.0;
+        Some(Aabb {
+            min: Vec3::new(-half, -TERRAIN_HEIGHT, -half),
+            max: Vec3::new(half, TERRAIN_HEIGHT, half),
+        })
+    }
+
+    fn draw_revision(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                vec![
+                    self.uniforms.descriptor_set(frame),
+                    self.heights_set.clone(),
+                ],
+            )?
+            .bind_index_buffer(self.indices.clone())?
+            .draw_indexed(self.index_count, 1, 0, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// Two triangles for every cell of a grid of `size` by `size` vertices, numbered row by row.
+pub(super) fn grid_indices(size: u32) -> Vec<u32> {
+    let cells = size.saturating_sub(1);
+    (0..cells)
+        .flat_map(|row| (0..cells).map(move |column| row * size + column))
+        .flat_map(|corner| {
+            let below = corner + size;
+            [corner, below, corner + 1, corner + 1, below, below + 1]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_grid_covers_every_cell() {
+        assert_eq!(
+            grid_indices(3),
+            [
+                0, 3, 1, 1, 3, 4, //
+                1, 4, 2, 2, 4, 5, //
+                3, 6, 4, 4, 6, 7, //
+                4, 7, 5, 5, 7, 8,
+            ]
+        );
+        let indices = grid_indices(HEIGHTMAP_SIZE);
+        assert_eq!(indices.len() as u32, (HEIGHTMAP_SIZE - 1).pow(2) * 6);
+        assert_eq!(indices.iter().max(), Some(&(HEIGHTMAP_SIZE.pow(2) - 1)));
+    }
+
+    #[test]
+    fn keys_adjust_the_noise_within_limits() {
+        let params = NoiseParams::default();
+        assert_eq!(params.adjust(VirtualKeyCode::W), None);
+        assert_eq!(params.adjust(REGENERATE_KEY).unwrap().seed, 2);
+        assert_eq!(params.adjust(MORE_OCTAVES_KEY).unwrap().octaves, 7);
+        assert_eq!(params.adjust(LACUNARITY_DOWN_KEY).unwrap().lacunarity, 1.9);
+        assert_eq!(params.adjust(GAIN_UP_KEY).unwrap().gain, 0.55);
+
+        let mut params = params;
+        for _ in 0..20 {
+            params = params
+                .adjust(FEWER_OCTAVES_KEY)
+                .and_then(|params| params.adjust(LACUNARITY_UP_KEY))
+                .and_then(|params| params.adjust(GAIN_DOWN_KEY))
+                .unwrap();
+        }
+        assert_eq!(params.octaves, 1);
+        assert_eq!(params.lacunarity, 3.0);
+        assert_eq!(params.gain, 0.2);
+    }
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+
+use crate::bindless::{bindless_capacity, make_bindless, TextureTable};
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::sampler::SamplerConfig;
+use crate::scene::textured_quad::TexturedVertex;
+use crate::scene::{build_pipeline_with_layout, FrameData, Scene};
+use crate::texture::{checkerboard, Texture};
+
+/// Matches the shaders' `Quad` push constant block.
+#[derive(BufferContents, Clone, Copy, Debug)]
+#[repr(C)]
+struct QuadPushConstants {
+    offset: [f32; 2],
+    texture_id: u32,
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 position;
+            layout(location = 1) in vec2 uv;
+
+            layout(location = 0) out vec2 v_uv;
+
+            layout(push_constant) uniform Quad {
+                vec2 offset;
+                uint texture_id;
+            } quad;
+
+            void main() {
+                v_uv = uv;
+                gl_Position = vec4(position + quad.offset, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod bindless_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+            #extension GL_EXT_nonuniform_qualifier : require
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(push_constant) uniform Quad {
+                vec2 offset;
+                uint texture_id;
+            } quad;
+
+            layout(set = 0, binding = 0) uniform sampler2D textures[];
+
+            void main() {
+                f_color = texture(textures[nonuniformEXT(quad.texture_id)], v_uv);
+            }
+        "
+    }
+}
+
+mod fallback_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D tex;
+
+            void main() {
+                f_color = texture(tex, v_uv);
+            }
+        "
+    }
+}
+
+/// The checkerboards' colours, one texture per quad.
+const PALETTES: [([u8; 4], [u8; 4]); 4] = [
+    ([255, 200, 0, 255], [0, 80, 255, 255]),
+    ([255, 60, 60, 255], [255, 255, 255, 255]),
+    ([40, 200, 90, 255], [20, 20, 20, 255]),
+    ([200, 90, 255, 255], [255, 255, 120, 255]),
+];
+
+/// A 2x2 grid of quads, each sampling its own texture out of a [`TextureTable`]: by ID from a
+/// bindless array where descriptor indexing is supported, or from a set bound per quad.
+pub struct TextureGridScene {
+    pipeline: Arc<GraphicsPipeline>,
+    vertex_buffer: Subbuffer<[TexturedVertex]>,
+    textures: TextureTable,
+    /// Each quad's offset from the centre and texture ID.
+    quads: Vec<QuadPushConstants>,
+}
+
+impl TextureGridScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        // A quad a little under a quarter of the target, centred on the origin.
+        let vertices = [
+            ([-0.4, -0.4], [0.0, 0.0]),
+            ([0.4, -0.4], [1.0, 0.0]),
+            ([0.4, 0.4], [1.0, 1.0]),
+            ([-0.4, -0.4], [0.0, 0.0]),
+            ([0.4, 0.4], [1.0, 1.0]),
+            ([-0.4, 0.4], [0.0, 1.0]),
+        ]
+        .map(|(position, uv)| TexturedVertex { position, uv });
+
+        let vertex_buffer = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vertices,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Vertex, vertex_buffer.buffer());
+        ctx.name_object(vertex_buffer.buffer(), "texture grid vertices");
+
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let capacity = bindless_capacity(&ctx.caps.limits);
+        let bindless = ctx.caps.descriptor_indexing && capacity >= PALETTES.len() as u32;
+        let fs = if bindless {
+            bindless_fs::load(ctx.device.clone())?
+        } else {
+            log::info!("Descriptor indexing is unavailable, binding a descriptor set per texture");
+            fallback_fs::load(ctx.device.clone())?
+        }
+        .entry_point("main")
+        .unwrap();
+        let vertex_input_state =
+            TexturedVertex::per_vertex().definition(&vs.info().input_interface)?;
+
+        let pipeline = build_pipeline_with_layout(
+            ctx.device.clone(),
+            vs,
+            fs,
+            vertex_input_state,
+            subpass,
+            |layout| {
+                if bindless {
+                    make_bindless(layout, 0, 0, capacity);
+                }
+            },
+        )?;
+        ctx.name_object(&pipeline, "texture grid pipeline");
+
+        let mut textures = TextureTable::new(ctx, pipeline.layout().set_layouts()[0].clone());
+
+        let mut quads = Vec::with_capacity(PALETTES.len());
+        for (index, (a, b)) in PALETTES.into_iter().enumerate() {
+            let pixels = checkerboard(64, 2 << index, a, b);
+            let texture =
+                Texture::from_rgba8(ctx, 64, 64, &pixels, SamplerConfig::nearest_clamped())?;
+            let texture_id = textures.register_texture(&texture)?;
+            let column = (index % 2) as f32;
+            let row = (index / 2) as f32;
+            quads.push(QuadPushConstants {
+                offset: [column - 0.5, row - 0.5],
+                texture_id,
+            });
+        }
+
+        Ok(Self {
+            pipeline,
+            vertex_buffer,
+            textures,
+            quads,
+        })
+    }
+}
+
+impl Scene for TextureGridScene {
+    fn draw_revision(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        _frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_vertex_buffers(0, self.vertex_buffer.clone())?;
+        for (index, quad) in self.quads.iter().enumerate() {
+            // Bindless, every quad uses the same set, so it only needs binding once.
+            if index == 0 || !self.textures.is_bindless() {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipeline.layout().clone(),
+                    0,
+                    self.textures.descriptor_set(quad.texture_id).unwrap(),
+                )?;
+            }
+            builder
+                .push_constants(self.pipeline.layout().clone(), 0, *quad)?
+                .draw(self.vertex_buffer.len() as u32, 1, 0, 0)?;
+        }
+        Ok(())
+    }
+}
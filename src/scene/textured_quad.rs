@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::sampler::SamplerConfig;
+use crate::scene::{build_pipeline, FrameData, Scene};
+use crate::texture::{checkerboard, Texture};
+
+#[derive(BufferContents, Vertex, Debug, PartialEq)]
+#[repr(C)]
+pub struct TexturedVertex {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 position;
+            layout(location = 1) in vec2 uv;
+
+            layout(location = 0) out vec2 v_uv;
+
+            void main() {
+                v_uv = uv;
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D tex;
+
+            void main() {
+                f_color = texture(tex, v_uv);
+            }
+        "
+    }
+}
+
+/// A screen-centred quad sampling a procedurally generated checkerboard texture.
+pub struct TexturedQuadScene {
+    pipeline: Arc<GraphicsPipeline>,
+    vertex_buffer: Subbuffer<[TexturedVertex]>,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+}
+
+impl TexturedQuadScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        // Two triangles making up a quad covering the middle half of the target.
+        let vertices = [
+            ([-0.5, -0.5], [0.0, 0.0]),
+            ([0.5, -0.5], [1.0, 0.0]),
+            ([0.5, 0.5], [1.0, 1.0]),
+            ([-0.5, -0.5], [0.0, 0.0]),
+            ([0.5, 0.5], [1.0, 1.0]),
+            ([-0.5, 0.5], [0.0, 1.0]),
+        ]
+        .map(|(position, uv)| TexturedVertex { position, uv });
+
+        let vertex_buffer = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vertices,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Vertex, vertex_buffer.buffer());
+        ctx.name_object(vertex_buffer.buffer(), "textured quad vertices");
+
+        let pixels = checkerboard(64, 4, [255, 200, 0, 255], [0, 80, 255, 255]);
+        // Nearest filtering keeps the checker edges crisp (and the output deterministic).
+        let texture = Texture::from_rgba8(ctx, 64, 64, &pixels, SamplerConfig::nearest_clamped())?;
+
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let vertex_input_state =
+            TexturedVertex::per_vertex().definition(&vs.info().input_interface)?;
+
+        let pipeline = build_pipeline(ctx.device.clone(), vs, fs, vertex_input_state, subpass)?;
+        ctx.name_object(&pipeline, "textured quad pipeline");
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            ctx.descriptor_set_allocator.as_ref(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                texture.view.clone(),
+                texture.sampler.clone(),
+            )],
+            [],
+        )?;
+
+        Ok(Self {
+            pipeline,
+            vertex_buffer,
+            descriptor_set,
+        })
+    }
+}
+
+impl Scene for TexturedQuadScene {
+    fn draw_revision(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        _frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )?
+            .bind_vertex_buffers(0, self.vertex_buffer.clone())?
+            .draw(self.vertex_buffer.len() as u32, 1, 0, 0)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Glowing dots leaving fading trails behind them, built up in a [`FeedbackTarget`].
+//!
+//! Each frame, a full-screen pass into the target's current image samples the previous one,
+//! dims it a little and adds the dots where they are now, so everywhere they have been fades
+//! out gradually. The view then shows the current image stretched across it.
+
+use std::sync::Arc;
+
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer, SubpassEndInfo,
+};
+use vulkano::descriptor_set::PersistentDescriptorSet;
+use vulkano::image::sampler::SamplerAddressMode;
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::feedback::FeedbackTarget;
+use crate::sampler::SamplerConfig;
+use crate::scene::{build_pipeline, FrameData, Scene, FRAME_SLOTS};
+
+/// Resolution the trails are drawn at, independent of the window.
+const TRAILS_EXTENT: [u32; 2] = [1024, 576];
+
+/// Seconds for a trail to fade to half its brightness.
+const HALF_LIFE: f32 = 0.25;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec2 v_uv;
+
+            // One triangle big enough to cover the whole screen, generated from the vertex index
+            // so no vertex buffer is needed.
+            void main() {
+                v_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(v_uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod accumulate_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D previous;
+
+            layout(push_constant) uniform Step {
+                float time;
+                // What the previous frame's image is multiplied by.
+                float fade;
+                // Width over height, so the dots stay round.
+                float aspect;
+            } step;
+
+            const int DOTS = 6;
+
+            void main() {
+                vec3 color = texture(previous, v_uv).rgb * step.fade;
+                vec2 p = vec2(v_uv.x * step.aspect, v_uv.y);
+                for (int i = 0; i < DOTS; i++) {
+                    float k = float(i);
+                    // Lissajous curves, each dot on its own.
+                    vec2 center = vec2(
+                        (0.5 + 0.42 * sin(step.time * (0.9 + 0.23 * k) + k)) * step.aspect,
+                        0.5 + 0.42 * sin(step.time * (1.3 + 0.17 * k) + 2.0 * k)
+                    );
+                    vec2 d = p - center;
+                    float glow = exp(-dot(d, d) / 0.00012);
+                    color += glow * (0.5 + 0.5 * cos(k + vec3(0.0, 2.0, 4.0)));
+                }
+                f_color = vec4(color, 1.0);
+            }
+        "
+    }
+}
+
+mod show_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D trails;
+
+            void main() {
+                f_color = vec4(min(texture(trails, v_uv).rgb, 1.0), 1.0);
+            }
+        "
+    }
+}
+
+/// Dots on Lissajous curves, trailing light that fades over a quarter of a second whatever the
+/// frame rate.
+pub struct TrailsScene {
+    target: FeedbackTarget,
+    accumulate_pipeline: Arc<GraphicsPipeline>,
+    /// Indexed by frame slot, each binding the previous frame's image.
+    previous_sets: Vec<Arc<PersistentDescriptorSet>>,
+    show_pipeline: Arc<GraphicsPipeline>,
+    /// Indexed by frame slot, each binding the slot's own image.
+    current_sets: Vec<Arc<PersistentDescriptorSet>>,
+    /// When each slot's last frame was, to fade by how long ago the previous frame was.
+    times: Vec<Option<f32>>,
+}
+
+impl TrailsScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let target = FeedbackTarget::new(ctx, TRAILS_EXTENT, "trails")?;
+        let sampler = ctx
+            .samplers
+            .get(SamplerConfig::default().with_address_mode(SamplerAddressMode::ClampToEdge))?;
+        let vs = vs::load(ctx.device.clone())?;
+
+        let accumulate_pipeline = build_pipeline(
+            ctx.device.clone(),
+            vs.entry_point("main").unwrap(),
+            accumulate_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            target.subpass(),
+        )?;
+        ctx.name_object(&accumulate_pipeline, "trails accumulate pipeline");
+        let previous_sets = target.previous_sets(
+            ctx,
+            &accumulate_pipeline.layout().set_layouts()[0],
+            &sampler,
+        )?;
+
+        let show_pipeline = build_pipeline(
+            ctx.device.clone(),
+            vs.entry_point("main").unwrap(),
+            show_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            subpass,
+        )?;
+        ctx.name_object(&show_pipeline, "trails show pipeline");
+        let current_sets =
+            target.current_sets(ctx, &show_pipeline.layout().set_layouts()[0], &sampler)?;
+
+        Ok(Self {
+            target,
+            accumulate_pipeline,
+            previous_sets,
+            show_pipeline,
+            current_sets,
+            times: vec![None; FRAME_SLOTS],
+        })
+    }
+}
+
+impl Scene for TrailsScene {
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        self.times[frame.frame_in_flight] = Some(frame.time);
+        Ok(())
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let elapsed = self.times[frame.previous_frame_in_flight()]
+            .map_or(0.0, |previous| frame.time - previous);
+        let [width, height] = self.target.extent();
+        self.target.begin(builder, frame)?;
+        builder
+            .bind_pipeline_graphics(self.accumulate_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.accumulate_pipeline.layout().clone(),
+                0,
+                self.previous_sets[frame.frame_in_flight].clone(),
+            )?
+            .push_constants(
+                self.accumulate_pipeline.layout().clone(),
+                0,
+                accumulate_fs::Step {
+                    time: frame.time,
+                    fade: fade(elapsed),
+                    aspect: width as f32 / height as f32,
+                },
+            )?
+            .draw(3, 1, 0, 0)?
+            .end_render_pass(SubpassEndInfo::default())?;
+        Ok(())
+    }
+
+    /// The trails are drawn afresh every frame, but into the slot's own image, so the draw
+    /// showing them stays the same.
+    fn draw_revision(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder
+            .bind_pipeline_graphics(self.show_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.show_pipeline.layout().clone(),
+                0,
+                self.current_sets[frame.frame_in_flight].clone(),
+            )?
+            .draw(3, 1, 0, 0)?;
+        Ok(())
+    }
+}
+
+/// How much of the previous frame is kept after `elapsed` seconds, halving every
+/// [`HALF_LIFE`]. Frames that seem to go back in time, such as the first, keep it all.
+fn fade(elapsed: f32) -> f32 {
+    0.5_f32.powf(elapsed.max(0.0) / HALF_LIFE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trails_fade_by_time_not_frames() {
+        assert_eq!(fade(0.0), 1.0);
+        assert_eq!(fade(-1.0), 1.0);
+        assert!((fade(HALF_LIFE) - 0.5).abs() < 1e-6);
+        // Two frames of 1/120 s fade as much as one of 1/60 s.
+        assert!((fade(1.0 / 120.0).powi(2) - fade(1.0 / 60.0)).abs() < 1e-6);
+    }
+}
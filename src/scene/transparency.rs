@@ -0,0 +1,870 @@
+//! Two translucent spheres sunk into each other over a floor, drawn with weighted blended
+//! order-independent transparency or sorted back to front, for comparing the two.
+//!
+//! Sorting draws whole spheres from the furthest to the nearest, which can't be right where they
+//! intersect: there each is partly in front of the other, so part of the overlap always blends
+//! in the wrong order, and which part flips the moment their centres pass each other.
+//!
+//! Weighted blended transparency (McGuire and Bavoil, 2013) doesn't need an order. Before the
+//! scene is drawn, a pass of its own draws the floor's depth and then the spheres, testing depth
+//! without writing it, into two attachments: every fragment adds its premultiplied colour and
+//! opacity, weighted by how near it is, into an `RGBA16F` accumulation, and multiplies an `R8`
+//! revealage, how much of what is behind still shows, by one minus its opacity. Both blends
+//! commute, so the order fragments arrive in doesn't matter. The scene's own pass then draws the
+//! floor, and a full-screen pass lays the weighted average colour over it, as opaque as the
+//! revealage says it should be. The accumulation is kept in floating point, so colours brighter
+//! than white reach HDR targets as they are.
+//!
+//! The two attachments blend differently, which needs
+//! [`independent_blend`](crate::caps::DeviceCaps::independent_blend); without it, only the sorted
+//! path is drawn. [`TRANSPARENCY_KEY`] switches between them.
+//!
+//! The spheres circle each other, moved on at the fixed timestep by [`Scene::update`] and drawn
+//! between the last two updates by the frame's
+//! [`update_alpha`](crate::scene::FrameData::update_alpha).
+
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+    SecondaryAutoCommandBuffer, SubpassBeginInfo, SubpassContents, SubpassEndInfo,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::format::Format;
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageLayout, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState,
+};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState};
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::rasterization::{CullMode, FrontFace, RasterizationState};
+use vulkano::pipeline::graphics::vertex_input::VertexInputState;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+use vulkano::shader::EntryPoint;
+use winit::event::VirtualKeyCode;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::{MemoryCategory, MemoryTracker};
+use crate::picking::Aabb;
+use crate::render_pass::DEPTH_FORMAT;
+use crate::sampler::SamplerConfig;
+use crate::scene::{
+    build_pipeline, build_pipeline_variant, build_pipeline_with_blends, build_pipeline_with_state,
+    AlphaMode, DepthPass, FrameData, FrameUniforms, PipelineVariant, Scene, FRAME_SLOTS,
+};
+use crate::std140::std140_layout;
+
+/// Format of the premultiplied colours and opacities added up, weighted, for each pixel.
+pub const ACCUMULATION_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Format of how much of what is behind the transparent surfaces shows through each pixel.
+pub const REVEALAGE_FORMAT: Format = Format::R8_UNORM;
+
+/// Key that switches between [`Transparency`] paths.
+const TRANSPARENCY_KEY: VirtualKeyCode = VirtualKeyCode::O;
+
+/// Half the width of the square floor, centred on the origin. Matches the vertex shader.
+const FLOOR_HALF_SIZE: f32 = 3.0;
+
+/// Height of the top of the floor. Matches the vertex shader.
+const FLOOR_TOP: f32 = -0.8;
+
+/// How many spheres there are, the vertex shader's first instances.
+const SPHERES: u32 = 2;
+
+/// The instance the vertex shader makes the floor of, after the spheres.
+const FLOOR_INSTANCE: u32 = SPHERES;
+
+/// Vertices of one box, six faces of two triangles each.
+const BOX_VERTICES: u32 = 36;
+
+/// Quads around and from pole to pole of each sphere. Match the vertex shader.
+const SPHERE_SLICES: u32 = 32;
+const SPHERE_STACKS: u32 = 16;
+
+/// Vertices of one sphere, two triangles to each quad.
+const SPHERE_VERTICES: u32 = SPHERE_SLICES * SPHERE_STACKS * 6;
+
+const SPHERE_RADIUS: f32 = 0.7;
+
+/// Height of the spheres' centres.
+const SPHERE_HEIGHT: f32 = 0.2;
+
+/// How far each sphere's centre is from the middle. Well within their radius, so they always
+/// intersect.
+const ORBIT_RADIUS: f32 = 0.4;
+
+/// Radians per second the spheres circle each other.
+const ORBIT_SPEED: f32 = 0.6;
+
+/// Each sphere's colour, and its opacity in `w`.
+const COLORS: [[f32; 4]; 2] = [[1.0, 0.3, 0.2, 0.5], [0.2, 0.5, 1.0, 0.5]];
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec3 v_position;
+            layout(location = 1) out vec3 v_normal;
+            layout(location = 2) out vec4 v_color;
+            // How far in front of the camera, which weighted blending weighs nearer surfaces by.
+            layout(location = 3) out float v_view_depth;
+
+            layout(set = 0, binding = 0) uniform Frame {
+                mat4 view_projection;
+                mat4 view;
+                // Each sphere's centre, and its radius in `w`.
+                vec4 spheres[2];
+                // Each sphere's colour, and its opacity in `a`.
+                vec4 colors[2];
+            } frame;
+
+            const uint FLOOR = 2;
+            const float FLOOR_HALF_SIZE = 3.0;
+            const float FLOOR_TOP = -0.8;
+            const float FLOOR_THICKNESS = 0.1;
+            const uint SLICES = 32;
+            const uint STACKS = 16;
+            const float PI = 3.14159265;
+
+            const vec3 FACES[6] = vec3[](
+                vec3(1.0, 0.0, 0.0), vec3(-1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0), vec3(0.0, -1.0, 0.0),
+                vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0)
+            );
+            const vec2 CORNERS[6] = vec2[](
+                vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+                vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+            );
+            // A quad of a sphere's grid as two triangles, counter-clockwise from outside.
+            const vec2 QUAD[6] = vec2[](
+                vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+                vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+            );
+
+            // Instances 0 and 1 are the spheres, each a grid of quads from pole to pole, and
+            // instance FLOOR the floor, a box. Both are made from the vertex index so no vertex
+            // buffer is needed.
+            void main() {
+                if (uint(gl_InstanceIndex) == FLOOR) {
+                    vec3 normal = FACES[gl_VertexIndex / 6];
+                    vec2 corner = CORNERS[gl_VertexIndex % 6];
+                    vec3 unit = normal + corner.x * normal.zxy + corner.y * normal.yzx;
+                    vec3 center = vec3(0.0, FLOOR_TOP - FLOOR_THICKNESS * 0.5, 0.0);
+                    vec3 half_size =
+                        vec3(FLOOR_HALF_SIZE, FLOOR_THICKNESS * 0.5, FLOOR_HALF_SIZE);
+                    v_position = center + unit * half_size;
+                    v_normal = normal;
+                    v_color = vec4(0.7, 0.7, 0.7, 1.0);
+                } else {
+                    uint quad = gl_VertexIndex / 6;
+                    vec2 grid = vec2(quad % SLICES, quad / SLICES) + QUAD[gl_VertexIndex % 6];
+                    float around = 2.0 * PI * grid.x / float(SLICES);
+                    float down = PI * grid.y / float(STACKS);
+                    vec3 normal =
+                        vec3(sin(down) * cos(around), cos(down), sin(down) * sin(around));
+                    vec4 sphere = frame.spheres[gl_InstanceIndex];
+                    v_position = sphere.xyz + normal * sphere.w;
+                    v_normal = normal;
+                    v_color = frame.colors[gl_InstanceIndex];
+                }
+                v_view_depth = -(frame.view * vec4(v_position, 1.0)).z;
+                gl_Position = frame.view_projection * vec4(v_position, 1.0);
+            }
+        "
+    }
+}
+
+std140_layout!(vs::Frame {
+    view_projection: [[f32; 4]; 4] => Mat4,
+    view: [[f32; 4]; 4] => Mat4,
+    spheres: [[f32; 4]; 2] => Array(&Vec4, 2),
+    colors: [[f32; 4]; 2] => Array(&Vec4, 2),
+});
+
+mod floor_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_position;
+            layout(location = 1) in vec3 v_normal;
+            layout(location = 2) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            const vec3 TO_LIGHT = normalize(vec3(0.4, 1.0, 0.3));
+
+            void main() {
+                // Checkers half a unit across, to see through the spheres.
+                vec2 cell = floor(v_position.xz * 2.0);
+                float checker = mod(cell.x + cell.y, 2.0);
+                vec3 albedo = v_color.rgb * (0.45 + 0.55 * checker);
+                float light = 0.3 + 0.7 * max(dot(normalize(v_normal), TO_LIGHT), 0.0);
+                f_color = vec4(albedo * light, 1.0);
+            }
+        "
+    }
+}
+
+mod sorted_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_position;
+            layout(location = 1) in vec3 v_normal;
+            layout(location = 2) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            const vec3 TO_LIGHT = normalize(vec3(0.4, 1.0, 0.3));
+
+            void main() {
+                float light = 0.3 + 0.7 * max(dot(normalize(v_normal), TO_LIGHT), 0.0);
+                f_color = vec4(v_color.rgb * light, v_color.a);
+            }
+        "
+    }
+}
+
+mod accumulate_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_position;
+            layout(location = 1) in vec3 v_normal;
+            layout(location = 2) in vec4 v_color;
+            layout(location = 3) in float v_view_depth;
+
+            // Added up: premultiplied colour and opacity, weighted.
+            layout(location = 0) out vec4 f_accumulation;
+            // Multiplied in as one minus it: the opacity.
+            layout(location = 1) out float f_revealage;
+
+            const vec3 TO_LIGHT = normalize(vec3(0.4, 1.0, 0.3));
+
+            void main() {
+                float light = 0.3 + 0.7 * max(dot(normalize(v_normal), TO_LIGHT), 0.0);
+                vec4 color = vec4(v_color.rgb * light, v_color.a);
+                // Nearer surfaces count for more, so what is in front still mostly shows in
+                // front. The paper's equation 7, for scenes a few units across.
+                float z = v_view_depth;
+                float weight =
+                    clamp(10.0 / (1e-5 + pow(z / 5.0, 2.0) + pow(z / 200.0, 6.0)), 1e-2, 3e3);
+                f_accumulation = vec4(color.rgb * color.a, color.a) * weight;
+                f_revealage = color.a;
+            }
+        "
+    }
+}
+
+mod composite_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec2 v_uv;
+
+            // One triangle big enough to cover the whole view, generated from the vertex index
+            // so no vertex buffer is needed.
+            void main() {
+                v_uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(v_uv * 2.0 - 1.0, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod composite_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D accumulation;
+            layout(set = 0, binding = 1) uniform sampler2D revealage;
+
+            void main() {
+                // The attachments are as large as the view, so each pixel reads its own texel.
+                ivec2 texel = ivec2(v_uv * vec2(textureSize(revealage, 0)));
+                float revealed = texelFetch(revealage, texel, 0).r;
+                // Nothing transparent covers this pixel.
+                if (revealed >= 1.0) {
+                    discard;
+                }
+                vec4 accumulated = texelFetch(accumulation, texel, 0);
+                vec3 average = accumulated.rgb / max(accumulated.a, 1e-5);
+                f_color = vec4(average, 1.0 - revealed);
+            }
+        "
+    }
+}
+
+/// How the spheres are blended over what is behind them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Transparency {
+    /// Each sphere whole, the furthest first.
+    Sorted,
+    /// Every fragment at once, weighted by depth, in any order.
+    WeightedBlended,
+}
+
+impl Transparency {
+    fn toggled(self) -> Self {
+        match self {
+            Self::Sorted => Self::WeightedBlended,
+            Self::WeightedBlended => Self::Sorted,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sorted => "sorted back to front",
+            Self::WeightedBlended => "with weighted blended transparency",
+        }
+    }
+}
+
+/// The spheres' centres, `angle` radians round. Always on opposite sides of the middle.
+fn sphere_centers(angle: f32) -> [Vec3; 2] {
+    let (sin, cos) = angle.sin_cos();
+    let offset = Vec3::new(cos, 0.0, sin) * ORBIT_RADIUS;
+    let middle = Vec3::new(0.0, SPHERE_HEIGHT, 0.0);
+    [middle + offset, middle - offset]
+}
+
+/// The spheres in the order sorted transparency draws them: the one whose centre is furthest
+/// from the camera with `view` first.
+fn back_to_front(view: Mat4, centers: &[Vec3; 2]) -> [usize; 2] {
+    let mut order = [0, 1];
+    // The camera looks down -Z, so the furthest is the most negative.
+    order.sort_by(|&a, &b| {
+        let depth = |sphere: usize| view.transform_point3(centers[sphere]).z;
+        depth(a).total_cmp(&depth(b))
+    });
+    order
+}
+
+/// A slot's attachments for the weighted blended pass, and the set the composite samples them
+/// with.
+struct OitTarget {
+    extent: [u32; 2],
+    framebuffer: Arc<Framebuffer>,
+    composite_set: Arc<PersistentDescriptorSet>,
+}
+
+/// What weighted blended transparency draws with, on devices that can.
+struct WeightedBlended {
+    render_pass: Arc<RenderPass>,
+    /// Draws the floor's depth, so spheres behind it are hidden.
+    depth_pipeline: Arc<GraphicsPipeline>,
+    accumulate_pipeline: Arc<GraphicsPipeline>,
+    composite_pipeline: Arc<GraphicsPipeline>,
+    sampler: Arc<Sampler>,
+    /// Indexed by frame slot. Made in `prepare`, once the view's size is known, and remade when
+    /// it changes.
+    targets: Vec<Option<OitTarget>>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    memory_tracker: Arc<MemoryTracker>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+}
+
+impl WeightedBlended {
+    fn new(ctx: &VulkanContext, vs: &EntryPoint, subpass: Subpass) -> Result<Self, RendererError> {
+        let render_pass = vulkano::single_pass_renderpass!(
+            ctx.device.clone(),
+            attachments: {
+                accumulation: {
+                    format: ACCUMULATION_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                    initial_layout: ImageLayout::Undefined,
+                    final_layout: ImageLayout::ShaderReadOnlyOptimal,
+                },
+                revealage: {
+                    format: REVEALAGE_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: Store,
+                    initial_layout: ImageLayout::Undefined,
+                    final_layout: ImageLayout::ShaderReadOnlyOptimal,
+                },
+                depth: {
+                    format: DEPTH_FORMAT,
+                    samples: 1,
+                    load_op: Clear,
+                    store_op: DontCare,
+                },
+            },
+            pass: {
+                color: [accumulation, revealage],
+                depth_stencil: {depth},
+            },
+        )?;
+        let oit_subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+        let depth_pipeline = build_pipeline_variant(
+            ctx.device.clone(),
+            vs.clone(),
+            floor_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            oit_subpass.clone(),
+            PipelineVariant {
+                alpha_mode: AlphaMode::Opaque,
+                double_sided: true,
+            },
+            DepthPass::Prepass,
+        )?;
+        ctx.name_object(&depth_pipeline, "transparency depth pipeline");
+
+        let accumulate = AttachmentBlend {
+            src_color_blend_factor: BlendFactor::One,
+            dst_color_blend_factor: BlendFactor::One,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: BlendFactor::One,
+            dst_alpha_blend_factor: BlendFactor::One,
+            alpha_blend_op: BlendOp::Add,
+        };
+        let reveal = AttachmentBlend {
+            src_color_blend_factor: BlendFactor::Zero,
+            dst_color_blend_factor: BlendFactor::OneMinusSrcColor,
+            color_blend_op: BlendOp::Add,
+            src_alpha_blend_factor: BlendFactor::Zero,
+            dst_alpha_blend_factor: BlendFactor::OneMinusSrcAlpha,
+            alpha_blend_op: BlendOp::Add,
+        };
+        let accumulate_pipeline = build_pipeline_with_blends(
+            ctx.device.clone(),
+            vs.clone(),
+            accumulate_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            oit_subpass,
+            InputAssemblyState::default(),
+            DepthState {
+                write_enable: false,
+                ..DepthState::simple()
+            },
+            sphere_rasterization(),
+            [accumulate, reveal]
+                .map(|blend| ColorBlendAttachmentState {
+                    blend: Some(blend),
+                    ..Default::default()
+                })
+                .into(),
+            |_| {},
+        )?;
+        ctx.name_object(&accumulate_pipeline, "transparency accumulate pipeline");
+
+        let composite_pipeline = build_pipeline_with_state(
+            ctx.device.clone(),
+            composite_vs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            composite_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            subpass,
+            InputAssemblyState::default(),
+            // The accumulate pass already hid what the floor covers.
+            DepthState {
+                write_enable: false,
+                compare_op: CompareOp::Always,
+            },
+            RasterizationState::default(),
+            ColorBlendAttachmentState {
+                blend: Some(AttachmentBlend::alpha()),
+                ..Default::default()
+            },
+            |_| {},
+        )?;
+        ctx.name_object(&composite_pipeline, "transparency composite pipeline");
+
+        Ok(Self {
+            render_pass,
+            depth_pipeline,
+            accumulate_pipeline,
+            composite_pipeline,
+            sampler: ctx.samplers.get(SamplerConfig::nearest_clamped())?,
+            targets: (0..FRAME_SLOTS).map(|_| None).collect(),
+            memory_allocator: ctx.memory_allocator.clone(),
+            memory_tracker: ctx.memory_tracker.clone(),
+            descriptor_set_allocator: ctx.descriptor_set_allocator.clone(),
+        })
+    }
+
+    /// Makes sure `slot` has attachments of `extent`.
+    fn reserve_target(&mut self, slot: usize, extent: [u32; 2]) -> Result<(), RendererError> {
+        if self.targets[slot]
+            .as_ref()
+            .is_some_and(|target| target.extent == extent)
+        {
+            return Ok(());
+        }
+        let image = |format, usage| {
+            let image = Image::new(
+                self.memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent: [extent[0], extent[1], 1],
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )?;
+            self.memory_tracker
+                .track_image(MemoryCategory::RenderTarget, &image);
+            Ok::<_, RendererError>(ImageView::new_default(image)?)
+        };
+        let sampled = ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED;
+        let accumulation = image(ACCUMULATION_FORMAT, sampled)?;
+        let revealage = image(REVEALAGE_FORMAT, sampled)?;
+        let depth = image(
+            DEPTH_FORMAT,
+            ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+        )?;
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![accumulation.clone(), revealage.clone(), depth],
+                ..Default::default()
+            },
+        )?;
+        let composite_set = PersistentDescriptorSet::new(
+            self.descriptor_set_allocator.as_ref(),
+            self.composite_pipeline.layout().set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, accumulation, self.sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(1, revealage, self.sampler.clone()),
+            ],
+            [],
+        )?;
+        self.targets[slot] = Some(OitTarget {
+            extent,
+            framebuffer,
+            composite_set,
+        });
+        Ok(())
+    }
+}
+
+/// Only the spheres' outsides are drawn, so each is one layer wherever it is seen.
+fn sphere_rasterization() -> RasterizationState {
+    RasterizationState {
+        // The projection flips Y, so counter-clockwise triangles still face the camera.
+        cull_mode: CullMode::Back,
+        front_face: FrontFace::CounterClockwise,
+        ..Default::default()
+    }
+}
+
+/// Two intersecting translucent spheres circling each other over a checkered floor.
+pub struct TransparencyScene {
+    floor_pipeline: Arc<GraphicsPipeline>,
+    sorted_pipeline: Arc<GraphicsPipeline>,
+    /// `None` where the device can't blend its attachments independently.
+    weighted_blended: Option<WeightedBlended>,
+    uniforms: FrameUniforms<vs::Frame>,
+    /// How far round the spheres were at the last update and the one before.
+    angles: [f32; 2],
+    /// Where the spheres are this frame, which sorting orders them by.
+    centers: [Vec3; 2],
+    transparency: Transparency,
+}
+
+impl TransparencyScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let floor_pipeline = build_pipeline(
+            ctx.device.clone(),
+            vs.clone(),
+            floor_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            subpass.clone(),
+        )?;
+        ctx.name_object(&floor_pipeline, "transparency floor pipeline");
+        let sorted_pipeline = build_pipeline_with_state(
+            ctx.device.clone(),
+            vs.clone(),
+            sorted_fs::load(ctx.device.clone())?
+                .entry_point("main")
+                .unwrap(),
+            VertexInputState::new(),
+            subpass.clone(),
+            InputAssemblyState::default(),
+            DepthState {
+                write_enable: false,
+                ..DepthState::simple()
+            },
+            sphere_rasterization(),
+            ColorBlendAttachmentState {
+                blend: Some(AttachmentBlend::alpha()),
+                ..Default::default()
+            },
+            |_| {},
+        )?;
+        ctx.name_object(&sorted_pipeline, "transparency sorted pipeline");
+
+        let weighted_blended = if ctx.caps.independent_blend {
+            Some(WeightedBlended::new(ctx, &vs, subpass)?)
+        } else {
+            log::warn!(
+                "The device can't blend attachments independently, so transparency is only \
+                 sorted"
+            );
+            None
+        };
+        let transparency = if weighted_blended.is_some() {
+            Transparency::WeightedBlended
+        } else {
+            Transparency::Sorted
+        };
+        let centers = sphere_centers(0.0);
+        let uniforms = FrameUniforms::new(
+            ctx,
+            &floor_pipeline,
+            0,
+            frame_uniform(&FrameData::default(), &centers),
+        )?;
+        log::info!("Drawing the spheres {}", transparency.name());
+
+        Ok(Self {
+            floor_pipeline,
+            sorted_pipeline,
+            weighted_blended,
+            uniforms,
+            angles: [0.0; 2],
+            centers,
+            transparency,
+        })
+    }
+}
+
+impl Scene for TransparencyScene {
+    fn update(&mut self, dt: f32) {
+        let [_, latest] = self.angles;
+        self.angles = [latest, latest + ORBIT_SPEED * dt];
+        // Both come back round together, so drawing between them never goes the long way.
+        if self.angles[0] >= TAU {
+            self.angles = self.angles.map(|angle| angle - TAU);
+        }
+    }
+
+    fn prepare(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        let [previous, latest] = self.angles;
+        self.centers = sphere_centers(previous + (latest - previous) * frame.update_alpha);
+        if let (Some(weighted_blended), Transparency::WeightedBlended) =
+            (&mut self.weighted_blended, self.transparency)
+        {
+            let extent = frame.extent.map(|length| length.max(1));
+            weighted_blended.reserve_target(frame.frame_in_flight, extent)?;
+        }
+        self.uniforms
+            .write(frame, frame_uniform(frame, &self.centers))
+    }
+
+    fn draw_offscreen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let Some(weighted_blended) = self
+            .weighted_blended
+            .as_ref()
+            .filter(|_| self.transparency == Transparency::WeightedBlended)
+        else {
+            return Ok(());
+        };
+        let Some(target) = &weighted_blended.targets[frame.frame_in_flight] else {
+            return Ok(());
+        };
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: target.extent.map(|length| length as f32),
+            depth_range: 0.0..=1.0,
+        };
+        let scissor = Scissor {
+            offset: [0, 0],
+            extent: target.extent,
+        };
+        let uniforms = self.uniforms.descriptor_set(frame);
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    // Nothing added up yet, and everything behind showing.
+                    clear_values: vec![
+                        Some([0.0; 4].into()),
+                        Some([1.0, 0.0, 0.0, 0.0].into()),
+                        Some(1.0.into()),
+                    ],
+                    ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )?
+            .set_viewport(0, [viewport].into_iter().collect())?
+            .set_scissor(0, [scissor].into_iter().collect())?
+            .bind_pipeline_graphics(weighted_blended.depth_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                weighted_blended.depth_pipeline.layout().clone(),
+                0,
+                uniforms.clone(),
+            )?
+            .draw(BOX_VERTICES, 1, 0, FLOOR_INSTANCE)?
+            .bind_pipeline_graphics(weighted_blended.accumulate_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                weighted_blended.accumulate_pipeline.layout().clone(),
+                0,
+                uniforms,
+            )?
+            .draw(SPHERE_VERTICES, SPHERES, 0, 0)?
+            .end_render_pass(SubpassEndInfo::default())?;
+        Ok(())
+    }
+
+    fn key_pressed(&mut self, key: VirtualKeyCode) -> bool {
+        if key != TRANSPARENCY_KEY {
+            return false;
+        }
+        if self.weighted_blended.is_none() {
+            log::warn!("Weighted blended transparency needs independent blending");
+            return true;
+        }
+        self.transparency = self.transparency.toggled();
+        log::info!("Drawing the spheres {}", self.transparency.name());
+        true
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: Vec3::new(-FLOOR_HALF_SIZE, FLOOR_TOP, -FLOOR_HALF_SIZE),
+            max: Vec3::new(
+                FLOOR_HALF_SIZE,
+                SPHERE_HEIGHT + SPHERE_RADIUS,
+                FLOOR_HALF_SIZE,
+            ),
+        })
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let uniforms = self.uniforms.descriptor_set(frame);
+        builder
+            .bind_pipeline_graphics(self.floor_pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.floor_pipeline.layout().clone(),
+                0,
+                uniforms.clone(),
+            )?
+            .draw(BOX_VERTICES, 1, 0, FLOOR_INSTANCE)?;
+
+        match (&self.weighted_blended, self.transparency) {
+            (Some(weighted_blended), Transparency::WeightedBlended) => {
+                let Some(target) = &weighted_blended.targets[frame.frame_in_flight] else {
+                    return Ok(());
+                };
+                let pipeline = &weighted_blended.composite_pipeline;
+                builder
+                    .bind_pipeline_graphics(pipeline.clone())?
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        pipeline.layout().clone(),
+                        0,
+                        target.composite_set.clone(),
+                    )?
+                    .draw(3, 1, 0, 0)?;
+            }
+            _ => {
+                builder
+                    .bind_pipeline_graphics(self.sorted_pipeline.clone())?
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        self.sorted_pipeline.layout().clone(),
+                        0,
+                        uniforms,
+                    )?;
+                for sphere in back_to_front(frame.view, &self.centers) {
+                    builder.draw(SPHERE_VERTICES, 1, 0, sphere as u32)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn frame_uniform(frame: &FrameData, centers: &[Vec3; 2]) -> vs::Frame {
+    vs::Frame {
+        view_projection: (frame.projection * frame.view).to_cols_array_2d(),
+        view: frame.view.to_cols_array_2d(),
+        spheres: centers.map(|center| center.extend(SPHERE_RADIUS).to_array()),
+        colors: COLORS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_spheres_always_intersect_above_the_floor() {
+        for step in 0..64 {
+            let [a, b] = sphere_centers(step as f32 * TAU / 64.0);
+            let apart = a.distance(b);
+            assert!(apart > 0.0 && apart < 2.0 * SPHERE_RADIUS);
+            for center in [a, b] {
+                assert!(center.y - SPHERE_RADIUS > FLOOR_TOP);
+                assert!(center.x.abs().max(center.z.abs()) + SPHERE_RADIUS < FLOOR_HALF_SIZE);
+            }
+        }
+        assert_eq!(SPHERE_VERTICES % 3, 0);
+    }
+
+    #[test]
+    fn sorting_draws_the_further_sphere_first() {
+        // A quarter of the way round, sphere 0 is nearer +Z and sphere 1 nearer -Z.
+        let centers = sphere_centers(TAU / 4.0);
+        assert!(centers[0].z > centers[1].z);
+        let from_front = Mat4::look_at_rh(Vec3::new(0.0, 1.0, 5.0), Vec3::ZERO, Vec3::Y);
+        assert_eq!(back_to_front(from_front, &centers), [1, 0]);
+        let from_back = Mat4::look_at_rh(Vec3::new(0.0, 1.0, -5.0), Vec3::ZERO, Vec3::Y);
+        assert_eq!(back_to_front(from_back, &centers), [0, 1]);
+    }
+}
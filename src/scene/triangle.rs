@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::scene::{build_pipeline, FrameData, Scene};
+
+// Any struct deriving from AnyBitPattern from bytemuck library
+// can be put in a buffer. Vulkano provides its own BufferContents macro
+// that does this.
+#[derive(BufferContents, Vertex, Debug, PartialEq)]
+// Any data sent through an FFI boundary should use repr(C).
+// Makes order, size and allignment of values match that of C/C++.
+#[repr(C)]
+pub struct TriangleVertex {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 position;
+
+            void main() {
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = vec4(1.0, 0.0, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+/// A single flat red triangle.
+pub struct TriangleScene {
+    pipeline: Arc<GraphicsPipeline>,
+    vertex_buffer: Subbuffer<[TriangleVertex]>,
+}
+
+impl TriangleScene {
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let vertices = [
+            TriangleVertex {
+                position: [-0.5, -0.25],
+            },
+            TriangleVertex {
+                position: [0.0, 0.5],
+            },
+            TriangleVertex {
+                position: [0.25, -0.1],
+            },
+        ];
+        let vertex_buffer = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    // We are using Buffer::from_iter to upload data to the buffer so require
+                    // that the host can accesss the buffer to upload it. Else we will need
+                    // to use a proxy buffer that the data is copied from.
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            vertices,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Vertex, vertex_buffer.buffer());
+        ctx.name_object(vertex_buffer.buffer(), "triangle vertices");
+
+        let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+        let vertex_input_state =
+            TriangleVertex::per_vertex().definition(&vs.info().input_interface)?;
+
+        let pipeline = build_pipeline(ctx.device.clone(), vs, fs, vertex_input_state, subpass)?;
+        ctx.name_object(&pipeline, "triangle pipeline");
+
+        Ok(Self {
+            pipeline,
+            vertex_buffer,
+        })
+    }
+}
+
+impl Scene for TriangleScene {
+    fn draw_revision(&self) -> Option<u64> {
+        Some(0)
+    }
+
+    fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        _frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_vertex_buffers(0, self.vertex_buffer.clone())?
+            .draw(self.vertex_buffer.len() as u32, 1, 0, 0)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Saving what a window shows to a PNG file.
+//!
+//! The swapchain image is copied into a host-visible buffer at the end of the frame's command
+//! buffer, after everything else has drawn into it, and written out once the frame's fence has
+//! signalled.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CopyImageToBufferInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::format::Format;
+use vulkano::image::Image;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+
+/// A file name in the working directory that no earlier screenshot has taken, going by the time.
+pub fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    PathBuf::from(format!("screenshot-{millis}.png"))
+}
+
+/// A copy of an image on its way back from the GPU.
+pub struct PendingScreenshot {
+    path: PathBuf,
+    extent: [u32; 2],
+    format: Format,
+    buffer: Subbuffer<[u8]>,
+}
+
+impl PendingScreenshot {
+    /// Records copying `image` into a buffer of its own, to be saved to `path` once the command
+    /// buffer has run.
+    pub fn record(
+        ctx: &VulkanContext,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image: Arc<Image>,
+        path: PathBuf,
+    ) -> Result<Self, RendererError> {
+        let format = image.format();
+        if channel_order(format).is_none() {
+            return Err(RendererError::Screenshot(format!(
+                "{format:?} images can't be saved"
+            )));
+        }
+        let extent = [image.extent()[0], image.extent()[1]];
+        let buffer = Buffer::new_slice::<u8>(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            extent[0] as u64 * extent[1] as u64 * 4,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Staging, buffer.buffer());
+        builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))?;
+        Ok(Self {
+            path,
+            extent,
+            format,
+            buffer,
+        })
+    }
+
+    /// Writes the image out. The command buffer the copy was recorded into must have finished.
+    pub fn save(self) -> Result<PathBuf, RendererError> {
+        let mut pixels = self.buffer.read()?.to_vec();
+        if channel_order(self.format) == Some(ChannelOrder::Bgra) {
+            bgra_to_rgba(&mut pixels);
+        }
+        write_png(&self.path, self.extent, &pixels)
+            .map_err(|err| RendererError::Screenshot(format!("{}: {err}", self.path.display())))?;
+        Ok(self.path)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+
+/// How the bytes of a pixel of `format` are laid out, for the 8-bit formats swapchains use. The
+/// sRGB formats hold the same encoded bytes a PNG does, so they need no conversion either.
+fn channel_order(format: Format) -> Option<ChannelOrder> {
+    match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => Some(ChannelOrder::Rgba),
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => Some(ChannelOrder::Bgra),
+        _ => None,
+    }
+}
+
+fn bgra_to_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+}
+
+fn write_png(path: &Path, extent: [u32; 2], pixels: &[u8]) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), extent[0], extent[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bgra_pixels_are_swizzled() {
+        let mut pixels = [1, 2, 3, 4, 5, 6, 7, 8];
+        bgra_to_rgba(&mut pixels);
+        assert_eq!(pixels, [3, 2, 1, 4, 7, 6, 5, 8]);
+        assert_eq!(
+            channel_order(Format::B8G8R8A8_SRGB),
+            Some(ChannelOrder::Bgra)
+        );
+        assert_eq!(channel_order(Format::A2B10G10R10_UNORM_PACK32), None);
+    }
+}
@@ -0,0 +1,110 @@
+//! Loading shaders compiled ahead of time, e.g. with `glslangValidator -V`, rather than the ones
+//! `vulkano_shaders` builds into the crate.
+
+use std::sync::Arc;
+
+use vulkano::device::Device;
+use vulkano::shader::{ShaderModule, ShaderModuleCreateInfo, ShaderStage};
+use vulkano::Validated;
+
+use crate::error::RendererError;
+use crate::shader_dump;
+
+/// The first word of every SPIR-V module, which also tells its byte order.
+pub const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Words in a SPIR-V module's header: the magic number, version, generator, bound and schema.
+const HEADER_WORDS: usize = 5;
+
+/// Builds a shader module from precompiled SPIR-V `bytes`, which must have an entry point
+/// called `main` for `stage`.
+pub fn load_spirv(
+    device: Arc<Device>,
+    bytes: &[u8],
+    stage: ShaderStage,
+) -> Result<Arc<ShaderModule>, RendererError> {
+    let words = spirv_words(bytes).map_err(RendererError::InvalidShader)?;
+    // SAFETY: vulkano parses the module and checks the capabilities and extensions it declares
+    // against the device, but doesn't validate the code itself. That is left to whatever
+    // compiled it, and to the validation layers.
+    let module = unsafe { ShaderModule::new(device, ShaderModuleCreateInfo::new(&words)) }
+        .map_err(|err| match err {
+            Validated::ValidationError(err) => RendererError::InvalidShader(err.to_string()),
+            Validated::Error(err) => RendererError::from(err),
+        })?;
+    shader_dump::spirv(&module, bytes);
+    let entry_point = module.entry_point("main").ok_or_else(|| {
+        RendererError::InvalidShader("there is no entry point called `main`".to_owned())
+    })?;
+    let found = ShaderStage::from(entry_point.info().execution_model);
+    if found != stage {
+        return Err(RendererError::InvalidShader(format!(
+            "`main` is a {found:?} shader rather than a {stage:?} one"
+        )));
+    }
+    Ok(module)
+}
+
+/// Splits SPIR-V into words in the machine's byte order, whichever order the module is in.
+fn spirv_words(bytes: &[u8]) -> Result<Vec<u32>, String> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(format!(
+            "{} bytes isn't a whole number of 4-byte SPIR-V words",
+            bytes.len()
+        ));
+    }
+    if bytes.len() < HEADER_WORDS * 4 {
+        return Err(format!(
+            "{} bytes is too short for a SPIR-V header",
+            bytes.len()
+        ));
+    }
+    let words = bytes.chunks_exact(4).map(|word| word.try_into().unwrap());
+    let magic = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    if magic == SPIRV_MAGIC {
+        Ok(words.map(u32::from_le_bytes).collect())
+    } else if magic == SPIRV_MAGIC.swap_bytes() {
+        Ok(words.map(u32::from_be_bytes).collect())
+    } else {
+        Err(format!(
+            "starts with {magic:#010x} rather than the SPIR-V magic number {SPIRV_MAGIC:#010x}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A header for SPIR-V 1.0 with a bound of 1, in either byte order.
+    fn header(to_bytes: fn(u32) -> [u8; 4]) -> Vec<u8> {
+        [SPIRV_MAGIC, 0x0001_0000, 0, 1, 0]
+            .into_iter()
+            .flat_map(to_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn either_byte_order_is_read() {
+        let expected = vec![SPIRV_MAGIC, 0x0001_0000, 0, 1, 0];
+        assert_eq!(spirv_words(&header(u32::to_le_bytes)), Ok(expected.clone()));
+        assert_eq!(spirv_words(&header(u32::to_be_bytes)), Ok(expected));
+    }
+
+    #[test]
+    fn malformed_code_is_rejected() {
+        let mut unaligned = header(u32::to_le_bytes);
+        unaligned.pop();
+        assert!(spirv_words(&unaligned)
+            .unwrap_err()
+            .contains("whole number of 4-byte"));
+        assert!(spirv_words(&header(u32::to_le_bytes)[..8])
+            .unwrap_err()
+            .contains("too short"));
+        let mut wrong_magic = header(u32::to_le_bytes);
+        wrong_magic[0] = 0;
+        assert!(spirv_words(&wrong_magic)
+            .unwrap_err()
+            .contains("magic number 0x07230203"));
+    }
+}
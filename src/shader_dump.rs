@@ -0,0 +1,252 @@
+//! Writing out what each shader declares as pipelines are built with it, for `--dump-shaders`,
+//! to track down pipelines that shaders don't fit.
+//!
+//! Once [`enable`]d, every entry point a pipeline is built with is logged along with its inputs
+//! and outputs, descriptor bindings and push constants, as vulkano reflects them, and written to
+//! `shader-<N>-<entry point>.txt` in the dump directory, `N` numbering the modules in the order
+//! first seen. That happens before the pipeline is built, so it is there to compare with the
+//! error when building fails.
+//!
+//! vulkano keeps the SPIR-V of the modules it creates to itself, so `shader-<N>.spv` is only
+//! written for modules [`load_spirv`](crate::shader::load_spirv) builds from precompiled code.
+//! The SPIR-V of the shaders `vulkano_shaders` compiles into the crate is the macro's.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+use vulkano::format::{Format, NumericType};
+use vulkano::shader::{
+    EntryPoint, EntryPointInfo, ShaderInterface, ShaderInterfaceEntryType, ShaderModule,
+    ShaderStage,
+};
+use vulkano::{Handle, VulkanObject};
+
+/// Where shaders are dumped, and the modules dumped so far.
+struct Dump {
+    dir: PathBuf,
+    /// The handles of the modules seen, numbered by their index.
+    modules: Vec<u64>,
+    /// The module numbers and names of the entry points written.
+    entry_points: Vec<(usize, String)>,
+}
+
+impl Dump {
+    /// The number of `module`'s files, numbering it if it's new.
+    fn number(&mut self, module: &ShaderModule) -> usize {
+        let handle = module.handle().as_raw();
+        match self.modules.iter().position(|&seen| seen == handle) {
+            Some(number) => number,
+            None => {
+                self.modules.push(handle);
+                self.modules.len() - 1
+            }
+        }
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) {
+        let path = self.dir.join(name);
+        if let Err(err) = fs::create_dir_all(&self.dir).and_then(|()| fs::write(&path, contents)) {
+            log::warn!("couldn't dump a shader to {}: {err}", path.display());
+        }
+    }
+}
+
+static DUMP: Mutex<Option<Dump>> = Mutex::new(None);
+
+fn with_dump(dump: impl FnOnce(&mut Dump)) {
+    if let Some(state) = DUMP.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+        dump(state);
+    }
+}
+
+/// Dumps every shader from now on into `dir`, which is created if need be.
+pub fn enable(dir: PathBuf) {
+    log::info!("dumping shaders to {}", dir.display());
+    *DUMP.lock().unwrap_or_else(PoisonError::into_inner) = Some(Dump {
+        dir,
+        modules: Vec::new(),
+        entry_points: Vec::new(),
+    });
+}
+
+/// Logs and writes what `entry_point` declares, the first time a pipeline is built with it.
+pub fn entry_point(entry_point: &EntryPoint) {
+    with_dump(|dump| {
+        let info = entry_point.info();
+        let number = dump.number(entry_point.module().base_module());
+        let key = (number, info.name.clone());
+        if dump.entry_points.contains(&key) {
+            return;
+        }
+        let description = describe(info);
+        log::info!("shader {number}: {description}");
+        dump.write(
+            &format!("shader-{number}-{}.txt", info.name),
+            description.as_bytes(),
+        );
+        dump.entry_points.push(key);
+    });
+}
+
+/// Writes the SPIR-V `bytes` `module` was built from.
+pub fn spirv(module: &ShaderModule, bytes: &[u8]) {
+    with_dump(|dump| {
+        let number = dump.number(module);
+        dump.write(&format!("shader-{number}.spv"), bytes);
+    });
+}
+
+/// What an entry point declares, one item to a line.
+fn describe(info: &EntryPointInfo) -> String {
+    let mut description = format!(
+        "`{}`, a {:?} shader",
+        info.name,
+        ShaderStage::from(info.execution_model)
+    );
+    describe_interface(&mut description, "input", &info.input_interface);
+    describe_interface(&mut description, "output", &info.output_interface);
+
+    let mut bindings: Vec<_> = info.descriptor_binding_requirements.iter().collect();
+    bindings.sort_by_key(|&(&set_binding, _)| set_binding);
+    for ((set, binding), requirements) in bindings {
+        let count = requirements
+            .descriptor_count
+            .map_or("runtime-sized".to_owned(), |count| count.to_string());
+        let _ = write!(
+            description,
+            "\n  set {set} binding {binding}: {count} of {:?}, in {:?}",
+            requirements.descriptor_types, requirements.stages
+        );
+    }
+    if let Some(range) = &info.push_constant_requirements {
+        let _ = write!(
+            description,
+            "\n  push constants: {} bytes from offset {}, in {:?}",
+            range.size, range.offset, range.stages
+        );
+    }
+    description
+}
+
+fn describe_interface(description: &mut String, direction: &str, interface: &ShaderInterface) {
+    let mut elements: Vec<_> = interface.elements().iter().collect();
+    elements.sort_by_key(|element| (element.location, element.component));
+    for element in elements {
+        let format = format_of(&element.ty).map_or_else(
+            || format!("{:?}", element.ty),
+            |format| format!("{format:?}"),
+        );
+        let _ = write!(
+            description,
+            "\n  {direction} location {}: {format}",
+            element.location
+        );
+        if element.ty.num_elements > 1 {
+            let _ = write!(description, " x {}", element.ty.num_elements);
+        }
+        if let Some(name) = &element.name {
+            let _ = write!(description, " `{name}`");
+        }
+    }
+}
+
+/// The vertex attribute format holding one value of `ty`, or one column or array element of it.
+fn format_of(ty: &ShaderInterfaceEntryType) -> Option<Format> {
+    use Format::*;
+    let formats = match (ty.base_type, ty.is_64bit) {
+        (NumericType::Float, false) => [
+            R32_SFLOAT,
+            R32G32_SFLOAT,
+            R32G32B32_SFLOAT,
+            R32G32B32A32_SFLOAT,
+        ],
+        (NumericType::Int, false) => [R32_SINT, R32G32_SINT, R32G32B32_SINT, R32G32B32A32_SINT],
+        (NumericType::Uint, false) => [R32_UINT, R32G32_UINT, R32G32B32_UINT, R32G32B32A32_UINT],
+        (NumericType::Float, true) => [
+            R64_SFLOAT,
+            R64G64_SFLOAT,
+            R64G64B64_SFLOAT,
+            R64G64B64A64_SFLOAT,
+        ],
+        (NumericType::Int, true) => [R64_SINT, R64G64_SINT, R64G64B64_SINT, R64G64B64A64_SINT],
+        (NumericType::Uint, true) => [R64_UINT, R64G64_UINT, R64G64B64_UINT, R64G64B64A64_UINT],
+    };
+    formats
+        .get((ty.num_components as usize).checked_sub(1)?)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::descriptor_set::layout::DescriptorType;
+    use vulkano::pipeline::layout::PushConstantRange;
+    use vulkano::shader::spirv::ExecutionModel;
+    use vulkano::shader::{DescriptorBindingRequirements, ShaderStages};
+
+    use super::*;
+
+    fn ty(base_type: NumericType, num_components: u32, is_64bit: bool) -> ShaderInterfaceEntryType {
+        ShaderInterfaceEntryType {
+            base_type,
+            num_components,
+            num_elements: 1,
+            is_64bit,
+        }
+    }
+
+    #[test]
+    fn interface_types_have_the_formats_of_their_attributes() {
+        assert_eq!(
+            format_of(&ty(NumericType::Float, 3, false)),
+            Some(Format::R32G32B32_SFLOAT)
+        );
+        assert_eq!(
+            format_of(&ty(NumericType::Uint, 4, false)),
+            Some(Format::R32G32B32A32_UINT)
+        );
+        assert_eq!(
+            format_of(&ty(NumericType::Float, 2, true)),
+            Some(Format::R64G64_SFLOAT)
+        );
+        assert_eq!(format_of(&ty(NumericType::Int, 0, false)), None);
+    }
+
+    #[test]
+    fn descriptions_list_bindings_in_order_and_push_constants() {
+        let binding = |descriptor_type, descriptor_count| DescriptorBindingRequirements {
+            descriptor_types: vec![descriptor_type],
+            descriptor_count,
+            stages: ShaderStages::FRAGMENT,
+            ..Default::default()
+        };
+        let info = EntryPointInfo {
+            name: "main".to_owned(),
+            execution_model: ExecutionModel::Fragment,
+            descriptor_binding_requirements: [
+                ((1, 0), binding(DescriptorType::StorageBuffer, None)),
+                (
+                    (0, 2),
+                    binding(DescriptorType::CombinedImageSampler, Some(4)),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            push_constant_requirements: Some(PushConstantRange {
+                stages: ShaderStages::FRAGMENT,
+                offset: 0,
+                size: 16,
+            }),
+            input_interface: ShaderInterface::empty(),
+            output_interface: ShaderInterface::empty(),
+        };
+        assert_eq!(
+            describe(&info),
+            "`main`, a Fragment shader\n  \
+             set 0 binding 2: 4 of [CombinedImageSampler], in FRAGMENT\n  \
+             set 1 binding 0: runtime-sized of [StorageBuffer], in FRAGMENT\n  \
+             push constants: 16 bytes from offset 0, in FRAGMENT"
+        );
+    }
+}
@@ -0,0 +1,615 @@
+//! Drawing many textured 2D quads at once, over or instead of a 3D scene.
+//!
+//! A [`SpriteBatch`] collects a frame's [`Sprite`]s, each sampling a rectangle of one of the
+//! batch's textures, and writes them as instances into a host-visible buffer per frame slot,
+//! grown whenever a frame has more sprites than it holds. The instances are sorted by texture
+//! first, so each texture is bound and drawn once, and a frame of sprites from a single texture
+//! is a single draw call however many there are. Positions are in pixels from the top left
+//! corner of the view, turned into clip space by an orthographic projection, and the quads are
+//! blended with premultiplied alpha and never depth tested.
+//!
+//! Many small images are best packed into one texture with an [`AtlasBuilder`], which hands back
+//! where each ended up.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use glam::Mat4;
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, SecondaryAutoCommandBuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::sampler::SamplerAddressMode;
+use vulkano::image::SampleCount;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::depth_stencil::DepthStencilState;
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::ViewportState;
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::Subpass;
+use vulkano::DeviceSize;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::{MemoryCategory, MemoryTracker};
+use crate::sampler::SamplerConfig;
+use crate::scene::{FrameData, FRAME_SLOTS};
+use crate::shader_dump;
+use crate::texture::Texture;
+
+/// Transparent pixels left between the images of an atlas, so filtering at the edge of one
+/// doesn't pick up its neighbours.
+const ATLAS_PADDING: u32 = 1;
+
+/// Room for this many sprites in a slot's buffer to begin with.
+const INITIAL_CAPACITY: DeviceSize = 256;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 center;
+            layout(location = 1) in vec2 size;
+            layout(location = 2) in float rotation;
+            layout(location = 3) in vec2 uv_min;
+            layout(location = 4) in vec2 uv_max;
+            layout(location = 5) in vec4 tint;
+
+            layout(location = 0) out vec2 v_uv;
+            layout(location = 1) out vec4 v_tint;
+
+            layout(push_constant) uniform Projection {
+                mat4 projection;
+            };
+
+            // Two triangles per sprite, with the sprite's centre at the origin.
+            const vec2 CORNERS[6] = vec2[](
+                vec2(-0.5, -0.5), vec2(0.5, -0.5), vec2(-0.5, 0.5),
+                vec2(-0.5, 0.5), vec2(0.5, -0.5), vec2(0.5, 0.5)
+            );
+
+            void main() {
+                vec2 corner = CORNERS[gl_VertexIndex];
+                float c = cos(rotation);
+                float s = sin(rotation);
+                vec2 offset = mat2(c, s, -s, c) * (corner * size);
+                gl_Position = projection * vec4(center + offset, 0.0, 1.0);
+                v_uv = mix(uv_min, uv_max, corner + 0.5);
+                v_tint = vec4(tint.rgb * tint.a, tint.a);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 1) in vec4 v_tint;
+
+            layout(location = 0) out vec4 f_color;
+
+            // Premultiplied by alpha, like the tint.
+            layout(set = 0, binding = 0) uniform sampler2D tex;
+
+            void main() {
+                f_color = texture(tex, v_uv) * v_tint;
+            }
+        "
+    }
+}
+
+/// A rectangle of a texture, in texture coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl UvRect {
+    /// The whole texture.
+    pub const FULL: UvRect = UvRect {
+        min: [0.0, 0.0],
+        max: [1.0, 1.0],
+    };
+}
+
+/// A textured quad to draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    /// Where the sprite's centre goes, in pixels from the top left corner of the view.
+    pub position: [f32; 2],
+    /// In pixels.
+    pub size: [f32; 2],
+    /// Clockwise on screen, in radians.
+    pub rotation: f32,
+    pub uv: UvRect,
+    /// Multiplies the texture's colour, not premultiplied by alpha.
+    pub tint: [f32; 4],
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 0.0],
+            size: [1.0, 1.0],
+            rotation: 0.0,
+            uv: UvRect::FULL,
+            tint: [1.0; 4],
+        }
+    }
+}
+
+/// One of the textures a [`SpriteBatch`] can draw sprites from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpriteTexture(usize);
+
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct SpriteInstance {
+    #[format(R32G32_SFLOAT)]
+    pub center: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    pub size: [f32; 2],
+    #[format(R32_SFLOAT)]
+    pub rotation: f32,
+    #[format(R32G32_SFLOAT)]
+    pub uv_min: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    pub uv_max: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub tint: [f32; 4],
+}
+
+impl From<&Sprite> for SpriteInstance {
+    fn from(sprite: &Sprite) -> Self {
+        Self {
+            center: sprite.position,
+            size: sprite.size,
+            rotation: sprite.rotation,
+            uv_min: sprite.uv.min,
+            uv_max: sprite.uv.max,
+            tint: sprite.tint,
+        }
+    }
+}
+
+/// What one frame slot draws: its instances, sorted by texture, and the range of them each
+/// texture covers.
+struct SlotSprites {
+    instances: Subbuffer<[SpriteInstance]>,
+    runs: Vec<(SpriteTexture, Range<u32>)>,
+    projection: Mat4,
+}
+
+/// Collects sprites each frame and draws them with as few draw calls as their textures allow.
+pub struct SpriteBatch {
+    pipeline: Arc<GraphicsPipeline>,
+    /// One per texture added, binding it.
+    descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    queued: Vec<(SpriteTexture, SpriteInstance)>,
+    /// What each frame slot last uploaded. The buffers are kept to be written again.
+    slots: Vec<Option<SlotSprites>>,
+    /// For growing the slots' buffers.
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    memory_tracker: Arc<MemoryTracker>,
+}
+
+impl SpriteBatch {
+    /// Creates a batch drawing in `subpass`, without any textures yet.
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let pipeline = build_sprite_pipeline(ctx, subpass)?;
+        ctx.name_object(&pipeline, "sprite pipeline");
+        Ok(Self {
+            pipeline,
+            descriptor_sets: Vec::new(),
+            queued: Vec::new(),
+            slots: (0..FRAME_SLOTS).map(|_| None).collect(),
+            memory_allocator: ctx.memory_allocator.clone(),
+            memory_tracker: ctx.memory_tracker.clone(),
+        })
+    }
+
+    /// Lets sprites be drawn from `texture`, whose colours must be premultiplied by alpha.
+    pub fn add_texture(
+        &mut self,
+        ctx: &VulkanContext,
+        texture: &Texture,
+    ) -> Result<SpriteTexture, RendererError> {
+        self.descriptor_sets.push(PersistentDescriptorSet::new(
+            ctx.descriptor_set_allocator.as_ref(),
+            self.pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                texture.view.clone(),
+                texture.sampler.clone(),
+            )],
+            [],
+        )?);
+        Ok(SpriteTexture(self.descriptor_sets.len() - 1))
+    }
+
+    /// Queues `sprite` to be drawn from `texture` in the next [`upload`](Self::upload).
+    /// Sprites are drawn in the order queued, except that all of one texture's are drawn before
+    /// the next texture's.
+    pub fn queue(&mut self, texture: SpriteTexture, sprite: &Sprite) {
+        self.queued.push((texture, sprite.into()));
+    }
+
+    /// Writes everything queued since the last call into `frame`'s slot, to be drawn into a
+    /// view of `frame.extent` pixels, and empties the queue. Only call this from
+    /// [`Scene::prepare`](crate::scene::Scene::prepare) or wherever else the slot's last frame
+    /// is known to have finished.
+    pub fn upload(&mut self, frame: &FrameData) -> Result<(), RendererError> {
+        // Stable, so each texture's sprites keep their order.
+        self.queued.sort_by_key(|&(texture, _)| texture);
+        let runs = texture_runs(self.queued.iter().map(|&(texture, _)| texture));
+        let count = self.queued.len() as DeviceSize;
+
+        let slot = &mut self.slots[frame.frame_in_flight];
+        let instances = match slot.take() {
+            Some(previous) if previous.instances.len() >= count => previous.instances,
+            _ => {
+                let capacity = count.next_power_of_two().max(INITIAL_CAPACITY);
+                let instances = Buffer::new_slice::<SpriteInstance>(
+                    self.memory_allocator.clone(),
+                    BufferCreateInfo {
+                        usage: BufferUsage::VERTEX_BUFFER,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                            | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                        ..Default::default()
+                    },
+                    capacity,
+                )?;
+                self.memory_tracker
+                    .track_buffer(MemoryCategory::Vertex, instances.buffer());
+                instances
+            }
+        };
+        {
+            let mut written = instances.write()?;
+            for (to, (_, instance)) in written.iter_mut().zip(self.queued.drain(..)) {
+                *to = instance;
+            }
+        }
+        *slot = Some(SlotSprites {
+            instances,
+            runs,
+            projection: sprite_projection(frame.extent),
+        });
+        Ok(())
+    }
+
+    /// Records drawing what was last uploaded for `frame`, one draw call per texture.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<SecondaryAutoCommandBuffer>,
+        frame: &FrameData,
+    ) -> Result<(), RendererError> {
+        let Some(slot) = &self.slots[frame.frame_in_flight] else {
+            return Ok(());
+        };
+        if slot.runs.is_empty() {
+            return Ok(());
+        }
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vs::Projection {
+                    projection: slot.projection.to_cols_array_2d(),
+                },
+            )?
+            .bind_vertex_buffers(0, slot.instances.clone())?;
+        for (texture, instances) in &slot.runs {
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipeline.layout().clone(),
+                    0,
+                    self.descriptor_sets[texture.0].clone(),
+                )?
+                .draw(6, instances.len() as u32, 0, instances.start)?;
+        }
+        Ok(())
+    }
+
+    /// How many draw calls [`draw`](Self::draw) records for `frame`.
+    pub fn draw_calls(&self, frame: &FrameData) -> usize {
+        self.slots[frame.frame_in_flight]
+            .as_ref()
+            .map_or(0, |slot| slot.runs.len())
+    }
+}
+
+/// The orthographic projection turning pixels from the top left corner of a view of `extent`
+/// into clip space, with Y down like Vulkan's.
+pub fn sprite_projection(extent: [u32; 2]) -> Mat4 {
+    let [width, height] = extent.map(|length| length.max(1) as f32);
+    Mat4::orthographic_rh(0.0, width, 0.0, height, -1.0, 1.0)
+}
+
+/// The range of each run of the same texture in `textures`, in order.
+fn texture_runs(
+    textures: impl IntoIterator<Item = SpriteTexture>,
+) -> Vec<(SpriteTexture, Range<u32>)> {
+    let mut runs: Vec<(SpriteTexture, Range<u32>)> = Vec::new();
+    for (index, texture) in (0..).zip(textures) {
+        match runs.last_mut() {
+            Some((last, range)) if *last == texture => range.end = index + 1,
+            _ => runs.push((texture, index..index + 1)),
+        }
+    }
+    runs
+}
+
+/// Packs small RGBA8 images into one, to draw sprites of any of them from a single texture.
+#[derive(Default)]
+pub struct AtlasBuilder {
+    images: Vec<([u32; 2], Vec<u8>)>,
+}
+
+/// Images packed by an [`AtlasBuilder`], with their colours premultiplied by alpha.
+pub struct Atlas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    /// Where each image added ended up, in the order they were added.
+    pub rects: Vec<UvRect>,
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an image of `size` with tightly packed, straight-alpha RGBA8 `pixels`, returning
+    /// its index in [`Atlas::rects`].
+    pub fn add(&mut self, size: [u32; 2], pixels: Vec<u8>) -> usize {
+        assert_eq!(
+            pixels.len(),
+            (size[0] * size[1] * 4) as usize,
+            "pixel data does not match a {}x{} RGBA8 image",
+            size[0],
+            size[1],
+        );
+        self.images.push((size, pixels));
+        self.images.len() - 1
+    }
+
+    /// Packs the images in rows, tallest first, into an atlas a power of two wide.
+    pub fn build(self) -> Atlas {
+        let padded = |size: [u32; 2]| size.map(|length| length + ATLAS_PADDING);
+        let area: u32 = self
+            .images
+            .iter()
+            .map(|(size, _)| padded(*size)[0] * padded(*size)[1])
+            .sum();
+        let widest = self
+            .images
+            .iter()
+            .map(|(size, _)| padded(*size)[0])
+            .max()
+            .unwrap_or(1);
+        let width = (area as f64).sqrt().ceil().max(widest as f64) as u32;
+        let width = width.next_power_of_two();
+
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|&index| std::cmp::Reverse(self.images[index].0[1]));
+        let mut origins = vec![[0, 0]; self.images.len()];
+        let [mut x, mut y, mut row_height] = [0, 0, 0];
+        for index in order {
+            let [image_width, image_height] = padded(self.images[index].0);
+            if x + image_width > width {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+            origins[index] = [x, y];
+            x += image_width;
+            row_height = row_height.max(image_height);
+        }
+        let height = (y + row_height).max(1);
+
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        for ((size, image), origin) in self.images.iter().zip(&origins) {
+            for row in 0..size[1] {
+                for column in 0..size[0] {
+                    let from = ((row * size[0] + column) * 4) as usize;
+                    let to = (((origin[1] + row) * width + origin[0] + column) * 4) as usize;
+                    let alpha = image[from + 3];
+                    for channel in 0..3 {
+                        pixels[to + channel] = premultiply(image[from + channel], alpha);
+                    }
+                    pixels[to + 3] = alpha;
+                }
+            }
+        }
+        let rects = self
+            .images
+            .iter()
+            .zip(&origins)
+            .map(|((size, _), origin)| UvRect {
+                min: [
+                    origin[0] as f32 / width as f32,
+                    origin[1] as f32 / height as f32,
+                ],
+                max: [
+                    (origin[0] + size[0]) as f32 / width as f32,
+                    (origin[1] + size[1]) as f32 / height as f32,
+                ],
+            })
+            .collect();
+        Atlas {
+            width,
+            height,
+            pixels,
+            rects,
+        }
+    }
+}
+
+impl Atlas {
+    /// Uploads the atlas into a texture, clamped to its edges and filtered linearly.
+    pub fn upload(&self, ctx: &VulkanContext) -> Result<Texture, RendererError> {
+        let texture = Texture::from_rgba8(
+            ctx,
+            self.width,
+            self.height,
+            &self.pixels,
+            SamplerConfig::default().with_address_mode(SamplerAddressMode::ClampToEdge),
+        )?;
+        ctx.name_object(&texture.image, "sprite atlas");
+        Ok(texture)
+    }
+}
+
+/// `channel` scaled by `alpha`, rounded to the nearest.
+fn premultiply(channel: u8, alpha: u8) -> u8 {
+    ((channel as u32 * alpha as u32 + 127) / 255) as u8
+}
+
+/// A pipeline drawing instanced quads with premultiplied alpha, ignoring depth.
+fn build_sprite_pipeline(
+    ctx: &VulkanContext,
+    subpass: Subpass,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+    let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+    let vertex_input_state =
+        SpriteInstance::per_instance().definition(&vs.info().input_interface)?;
+    shader_dump::entry_point(&vs);
+    shader_dump::entry_point(&fs);
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        ctx.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(ctx.device.clone())?,
+    )?;
+
+    let has_depth = subpass.subpass_desc().depth_stencil_attachment.is_some();
+    let rasterization_samples = subpass.num_samples().unwrap_or(SampleCount::Sample1);
+    let premultiplied = AttachmentBlend {
+        src_color_blend_factor: BlendFactor::One,
+        dst_color_blend_factor: BlendFactor::OneMinusSrcAlpha,
+        color_blend_op: BlendOp::Add,
+        src_alpha_blend_factor: BlendFactor::One,
+        dst_alpha_blend_factor: BlendFactor::OneMinusSrcAlpha,
+        alpha_blend_op: BlendOp::Add,
+    };
+    Ok(GraphicsPipeline::new(
+        ctx.device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples,
+                ..Default::default()
+            }),
+            // Like text, drawn over whatever is there, whatever its depth.
+            depth_stencil_state: has_depth.then(DepthStencilState::default),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(premultiplied),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    #[test]
+    fn sprites_of_one_texture_are_one_run() {
+        let [a, b] = [SpriteTexture(0), SpriteTexture(1)];
+        assert_eq!(texture_runs([a; 10_000]), [(a, 0..10_000)]);
+        assert_eq!(texture_runs([a, a, b]), [(a, 0..2), (b, 2..3)]);
+        assert!(texture_runs([]).is_empty());
+    }
+
+    #[test]
+    fn the_projection_maps_pixels_to_clip_space() {
+        let projection = sprite_projection([800, 600]);
+        let clip = |x, y| projection.transform_point3(Vec3::new(x, y, 0.0));
+        assert!(clip(0.0, 0.0).abs_diff_eq(Vec3::new(-1.0, -1.0, 0.5), 1e-6));
+        assert!(clip(800.0, 600.0).abs_diff_eq(Vec3::new(1.0, 1.0, 0.5), 1e-6));
+    }
+
+    #[test]
+    fn atlas_images_dont_overlap_and_are_premultiplied() {
+        let mut builder = AtlasBuilder::new();
+        let sizes = [[16, 16], [8, 24], [30, 4], [1, 1]];
+        for (index, size) in sizes.into_iter().enumerate() {
+            let pixel = [200, 100, index as u8 * 50, 128];
+            builder.add(size, pixel.repeat((size[0] * size[1]) as usize));
+        }
+        let atlas = builder.build();
+        assert!(atlas.width.is_power_of_two());
+        assert_eq!(
+            atlas.pixels.len(),
+            (atlas.width * atlas.height * 4) as usize
+        );
+
+        let texels = |rect: &UvRect| {
+            let [x0, y0] = [
+                rect.min[0] * atlas.width as f32,
+                rect.min[1] * atlas.height as f32,
+            ];
+            let [x1, y1] = [
+                rect.max[0] * atlas.width as f32,
+                rect.max[1] * atlas.height as f32,
+            ];
+            [x0, y0, x1, y1].map(|coordinate| coordinate.round() as u32)
+        };
+        for (index, (rect, size)) in atlas.rects.iter().zip(sizes).enumerate() {
+            let [x0, y0, x1, y1] = texels(rect);
+            assert_eq!([x1 - x0, y1 - y0], size);
+            for other in &atlas.rects[index + 1..] {
+                let [ox0, oy0, ox1, oy1] = texels(other);
+                assert!(x1 <= ox0 || ox1 <= x0 || y1 <= oy0 || oy1 <= y0);
+            }
+            let first = ((y0 * atlas.width + x0) * 4) as usize;
+            assert_eq!(
+                atlas.pixels[first..first + 4],
+                [100, 50, premultiply(index as u8 * 50, 128), 128]
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! A ring of staging memory for data uploaded every frame, like animated vertices or instance
+//! transforms.
+//!
+//! A fresh staging buffer per upload, as [`Texture`](crate::texture::Texture) uses for one-off
+//! uploads, churns through allocations when it happens every frame. A [`StagingRing`] instead
+//! writes each upload just past the previous one in a single host-visible buffer and wraps around
+//! at the end. Every region remembers the fence of the submission copying out of it, and isn't
+//! written over until that fence has signalled.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::BufferCopy;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::sync::future::FenceSignalFuture;
+use vulkano::sync::GpuFuture;
+use vulkano::DeviceSize;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+
+/// The fence of a submission, as `then_signal_fence_and_flush` returns it for a boxed future.
+pub type SubmitFence = FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>;
+
+/// Uploads are at least this aligned, which covers every texel block size, so regions can also
+/// be copied into images.
+const MIN_ALIGNMENT: DeviceSize = 16;
+
+/// Streams uploads through one fixed host-visible buffer.
+///
+/// Record copies out of what [`upload`](Self::upload) returns, then hand the fence of the
+/// submission containing them to [`submitted`](Self::submitted).
+pub struct StagingRing {
+    buffer: Subbuffer<[u8]>,
+    /// Uploads start on multiples of this, so that flushing one never touches its neighbours.
+    alignment: DeviceSize,
+    regions: Regions<Arc<SubmitFence>>,
+}
+
+impl StagingRing {
+    /// Allocates a ring of `capacity` bytes.
+    pub fn new(ctx: &VulkanContext, capacity: DeviceSize) -> Result<Self, RendererError> {
+        let buffer = Buffer::new_slice::<u8>(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            capacity,
+        )?;
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Staging, buffer.buffer());
+        ctx.name_object(buffer.buffer(), "staging ring");
+        Ok(Self {
+            buffer,
+            alignment: ctx.caps.limits.non_coherent_atom_size.max(MIN_ALIGNMENT),
+            regions: Regions::new(capacity),
+        })
+    }
+
+    /// The whole ring, which the regions [`upload`](Self::upload) returns are relative to.
+    pub fn buffer(&self) -> &Subbuffer<[u8]> {
+        &self.buffer
+    }
+
+    /// Writes `data` into the ring and returns where it went: as a subbuffer to copy from, and
+    /// as a region of [`buffer`](Self::buffer) for batching several uploads into one copy. The
+    /// region's destination offset is left at zero.
+    ///
+    /// Blocks until the GPU is done with the space if an earlier submission still reads from it.
+    /// Everything uploaded since the last [`submitted`](Self::submitted) has to fit into the
+    /// ring at once.
+    ///
+    /// # Panics
+    ///
+    /// If `data` is empty.
+    pub fn upload<T: BufferContents + Copy>(
+        &mut self,
+        data: &[T],
+    ) -> Result<(Subbuffer<[T]>, BufferCopy), RendererError> {
+        assert!(!data.is_empty(), "can't upload nothing");
+        let size = std::mem::size_of_val(data) as DeviceSize;
+        let alignment = self.alignment.max(std::mem::align_of::<T>() as DeviceSize);
+        let range = self.regions.allocate(size, alignment, |fence| {
+            fence.wait(None)?;
+            Ok(())
+        })?;
+
+        let subbuffer = self
+            .buffer
+            .clone()
+            .slice(range.clone())
+            .reinterpret::<[T]>();
+        subbuffer.write()?.copy_from_slice(data);
+        let region = BufferCopy {
+            src_offset: range.start,
+            size,
+            ..Default::default()
+        };
+        Ok((subbuffer, region))
+    }
+
+    /// Records that everything uploaded since the last call is read by the submission `fence`
+    /// belongs to, so it can be written over once that has finished.
+    pub fn submitted(&mut self, fence: Arc<SubmitFence>) {
+        self.regions.submitted(fence);
+    }
+}
+
+/// Where in the ring the uploads went, oldest first, with the fences guarding them.
+struct Regions<F> {
+    capacity: DeviceSize,
+    /// Where the newest region ends.
+    head: DeviceSize,
+    /// The fence is `None` until the region's submission is known.
+    in_flight: VecDeque<(Range<DeviceSize>, Option<F>)>,
+}
+
+impl<F: Clone> Regions<F> {
+    fn new(capacity: DeviceSize) -> Self {
+        Self {
+            capacity,
+            head: 0,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Finds `size` bytes starting on a multiple of `alignment`, right after the newest region
+    /// or back at the start if they don't fit before the end. Regions in the way are forgotten
+    /// once their fences have been passed to `wait`, oldest first.
+    fn allocate(
+        &mut self,
+        size: DeviceSize,
+        alignment: DeviceSize,
+        mut wait: impl FnMut(&F) -> Result<(), RendererError>,
+    ) -> Result<Range<DeviceSize>, RendererError> {
+        if size > self.capacity {
+            return Err(RendererError::StagingRingFull(format!(
+                "{size} bytes don't fit into {} bytes",
+                self.capacity
+            )));
+        }
+        let mut start = self.head.next_multiple_of(alignment);
+        if start + size > self.capacity {
+            start = 0;
+        }
+        let range = start..start + size;
+
+        while self
+            .in_flight
+            .iter()
+            .any(|(region, _)| region.start < range.end && range.start < region.end)
+        {
+            let Some(fence) = &self.in_flight[0].1 else {
+                return Err(RendererError::StagingRingFull(
+                    "the uploads since the last submission take up the whole ring".to_owned(),
+                ));
+            };
+            wait(fence)?;
+            self.in_flight.pop_front();
+        }
+
+        self.head = range.end;
+        self.in_flight.push_back((range.clone(), None));
+        Ok(range)
+    }
+
+    /// Guards the regions allocated since the last call with `fence`.
+    fn submitted(&mut self, fence: F) {
+        for (_, region_fence) in self.in_flight.iter_mut().rev() {
+            if region_fence.is_some() {
+                break;
+            }
+            *region_fence = Some(fence.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Allocates without any fence having to be waited for.
+    fn allocate(regions: &mut Regions<u32>, size: DeviceSize) -> Range<DeviceSize> {
+        regions
+            .allocate(size, 16, |fence| panic!("waited for fence {fence}"))
+            .unwrap()
+    }
+
+    #[test]
+    fn uploads_follow_each_other_aligned() {
+        let mut regions = Regions::new(256);
+        assert_eq!(allocate(&mut regions, 40), 0..40);
+        assert_eq!(allocate(&mut regions, 8), 48..56);
+        assert_eq!(allocate(&mut regions, 16), 64..80);
+    }
+
+    #[test]
+    fn wrapping_waits_for_the_regions_in_the_way() {
+        let mut regions = Regions::new(256);
+        allocate(&mut regions, 96);
+        regions.submitted(1);
+        allocate(&mut regions, 96);
+        regions.submitted(2);
+
+        let mut waited = Vec::new();
+        let range = regions
+            .allocate(96, 16, |&fence| {
+                waited.push(fence);
+                Ok(())
+            })
+            .unwrap();
+        // Doesn't fit after the second region, and the first one is in the way at the start.
+        assert_eq!(range, 0..96);
+        assert_eq!(waited, [1]);
+    }
+
+    #[test]
+    fn unsubmitted_uploads_are_never_overwritten() {
+        let mut regions = Regions::new(256);
+        allocate(&mut regions, 200);
+        assert!(matches!(
+            regions.allocate(100, 16, |_| Ok(())),
+            Err(RendererError::StagingRingFull(_))
+        ));
+        assert!(matches!(
+            regions.allocate(300, 16, |_| Ok(())),
+            Err(RendererError::StagingRingFull(_))
+        ));
+    }
+
+    #[test]
+    fn submitting_only_guards_the_newest_uploads() {
+        let mut regions = Regions::new(256);
+        allocate(&mut regions, 16);
+        regions.submitted(1);
+        allocate(&mut regions, 16);
+        allocate(&mut regions, 16);
+        regions.submitted(2);
+        let fences: Vec<_> = regions.in_flight.iter().map(|(_, fence)| *fence).collect();
+        assert_eq!(fences, [Some(1), Some(2), Some(2)]);
+    }
+}
@@ -0,0 +1,218 @@
+//! Checking that uniform structs written on the CPU line up with the `std140` blocks shaders
+//! read them as.
+//!
+//! `#[repr(C)]` packs fields as tightly as Rust's own alignment allows, while `std140` aligns
+//! vectors and matrices to 8 or 16 bytes, so a struct that looks right can put a field where the
+//! shader reads padding. Nothing fails then, the shader just reads garbage. A
+//! [`Std140Layout`] lists a struct's fields with the GLSL types they stand for, usually through
+//! [`std140_layout!`], and [`check_std140`] compares their offsets and sizes with where `std140`
+//! puts them. [`FrameUniforms`](crate::scene::FrameUniforms) checks before creating its buffers.
+
+use std::any;
+use std::fmt;
+
+use crate::error::RendererError;
+
+/// The GLSL type a field stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Std140Type {
+    /// `float`, `int`, `uint` or `bool`.
+    Scalar,
+    Vec2,
+    Vec3,
+    Vec4,
+    /// A matrix of three columns of three, each column padded to a `vec4`.
+    Mat3,
+    Mat4,
+    /// An array of `len` elements, each padded to a multiple of 16 bytes.
+    Array(&'static Std140Type, usize),
+}
+
+impl Std140Type {
+    /// What `std140` aligns the start of the field to, in bytes.
+    pub const fn alignment(self) -> usize {
+        match self {
+            Self::Scalar => 4,
+            Self::Vec2 => 8,
+            Self::Vec3 | Self::Vec4 | Self::Mat3 | Self::Mat4 | Self::Array(..) => 16,
+        }
+    }
+
+    /// How many bytes `std140` takes for the field, without padding after it.
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Scalar => 4,
+            Self::Vec2 => 8,
+            Self::Vec3 => 12,
+            Self::Vec4 => 16,
+            Self::Mat3 => 48,
+            Self::Mat4 => 64,
+            Self::Array(element, len) => element.size().next_multiple_of(16) * len,
+        }
+    }
+}
+
+impl fmt::Display for Std140Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Scalar => write!(f, "scalar"),
+            Self::Vec2 => write!(f, "vec2"),
+            Self::Vec3 => write!(f, "vec3"),
+            Self::Vec4 => write!(f, "vec4"),
+            Self::Mat3 => write!(f, "mat3"),
+            Self::Mat4 => write!(f, "mat4"),
+            Self::Array(element, len) => write!(f, "{element}[{len}]"),
+        }
+    }
+}
+
+/// A field of a uniform struct, as Rust lays it out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Std140Field {
+    pub name: &'static str,
+    /// Bytes from the start of the struct.
+    pub offset: usize,
+    /// Bytes the Rust type takes.
+    pub size: usize,
+    /// The GLSL type the shader reads the field as.
+    pub ty: Std140Type,
+}
+
+/// A struct uploaded as a `std140` uniform block.
+pub trait Std140Layout {
+    /// The struct's fields, in order.
+    const FIELDS: &'static [Std140Field];
+}
+
+/// Implements [`Std140Layout`] for a struct, from its fields' Rust types and the GLSL types they
+/// stand for, so the offsets and sizes come from the compiler. Naming a field or type the struct
+/// doesn't have fails to compile.
+///
+/// ```ignore
+/// std140_layout!(MvpUniform {
+///     model: [[f32; 4]; 4] => Mat4,
+///     time: f32 => Scalar,
+/// });
+/// ```
+macro_rules! std140_layout {
+    ($ty:ty { $($field:ident: $field_ty:ty => $std140:expr),* $(,)? }) => {
+        impl $crate::std140::Std140Layout for $ty {
+            const FIELDS: &'static [$crate::std140::Std140Field] = {
+                #[allow(unused_imports)]
+                use $crate::std140::Std140Type::*;
+                &[$(
+                    $crate::std140::Std140Field {
+                        name: stringify!($field),
+                        offset: ::std::mem::offset_of!($ty, $field),
+                        size: {
+                            // Only compiles if the field has the type given.
+                            const _: fn(&$ty) -> &$field_ty = |value| &value.$field;
+                            ::std::mem::size_of::<$field_ty>()
+                        },
+                        ty: $std140,
+                    }
+                ),*]
+            };
+        }
+    };
+}
+pub(crate) use std140_layout;
+
+/// Checks that every field of `T` starts where `std140` puts it, after the fields before it, and
+/// takes as many bytes as its GLSL type.
+pub fn check_std140<T: Std140Layout>() -> Result<(), RendererError> {
+    let name = any::type_name::<T>()
+        .rsplit("::")
+        .next()
+        .unwrap_or_default();
+    let mut end = 0usize;
+    for field in T::FIELDS {
+        let expected = end.next_multiple_of(field.ty.alignment());
+        if field.offset != expected {
+            return Err(RendererError::UniformLayout(format!(
+                "`{name}::{}` starts at byte {}, but std140 puts a {} there at byte {expected}",
+                field.name, field.offset, field.ty
+            )));
+        }
+        if field.size != field.ty.size() {
+            return Err(RendererError::UniformLayout(format!(
+                "`{name}::{}` takes {} bytes, but a std140 {} takes {}",
+                field.name,
+                field.size,
+                field.ty,
+                field.ty.size()
+            )));
+        }
+        end = expected + field.size;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Light {
+        position: [f32; 3],
+        intensity: f32,
+        color: [f32; 4],
+        /// Each `float` of the array takes 16 bytes.
+        weights: [[f32; 4]; 2],
+        uv_scale: [f32; 2],
+    }
+
+    std140_layout!(Light {
+        position: [f32; 3] => Vec3,
+        intensity: f32 => Scalar,
+        color: [f32; 4] => Vec4,
+        weights: [[f32; 4]; 2] => Array(&Scalar, 2),
+        uv_scale: [f32; 2] => Vec2,
+    });
+
+    /// A `vec3` straight after a `float` starts 16 bytes in, not 4.
+    #[repr(C)]
+    struct Unpadded {
+        time: f32,
+        direction: [f32; 3],
+    }
+
+    std140_layout!(Unpadded {
+        time: f32 => Scalar,
+        direction: [f32; 3] => Vec3,
+    });
+
+    /// A `mat3`'s columns are padded to `vec4`s.
+    #[repr(C)]
+    struct TightMatrix {
+        normal: [[f32; 3]; 3],
+    }
+
+    std140_layout!(TightMatrix {
+        normal: [[f32; 3]; 3] => Mat3,
+    });
+
+    #[test]
+    fn padded_structs_pass() {
+        assert!(check_std140::<Light>().is_ok());
+        assert_eq!(Std140Type::Array(&Std140Type::Vec3, 4).size(), 64);
+    }
+
+    #[test]
+    fn misaligned_fields_are_reported() {
+        let Err(RendererError::UniformLayout(msg)) = check_std140::<Unpadded>() else {
+            panic!("a misaligned vec3 passed");
+        };
+        assert_eq!(
+            msg,
+            "`Unpadded::direction` starts at byte 4, but std140 puts a vec3 there at byte 16"
+        );
+        let Err(RendererError::UniformLayout(msg)) = check_std140::<TightMatrix>() else {
+            panic!("an unpadded mat3 passed");
+        };
+        assert_eq!(
+            msg,
+            "`TightMatrix::normal` takes 36 bytes, but a std140 mat3 takes 48"
+        );
+    }
+}
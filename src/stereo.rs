@@ -0,0 +1,285 @@
+//! Drawing the scene once per eye, side by side, for `--stereo`.
+//!
+//! Where the device has multiview and the scene can use it, both eyes are drawn at once: the
+//! render pass from [`create_multiview_render_pass`] draws into both layers of a
+//! [`StereoTarget`] with a view mask of `0b11`, so every draw call runs for both eyes and the
+//! vertex shader picks its eye's view matrix with `gl_ViewIndex`. [`record_composite`] then
+//! blits the layers into the left and right halves of the swapchain image. Otherwise the window
+//! draws the scene twice, once into each half with its own viewport, like a split view.
+
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BlitImageInfo, CommandBufferInheritanceInfo, CommandBufferUsage,
+    PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageCreateInfo, ImageLayout, ImageType, ImageUsage, SampleCount};
+use vulkano::memory::allocator::AllocationCreateInfo;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::render_pass::{
+    AttachmentDescription, AttachmentLoadOp, AttachmentReference, AttachmentStoreOp, Framebuffer,
+    FramebufferCreateInfo, RenderPass, RenderPassCreateInfo, Subpass, SubpassDescription,
+};
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::render_pass::DEPTH_FORMAT;
+use crate::scene::{FrameData, Scene};
+
+/// How far apart the eyes are, in world units. About a person's, with the scenes' objects a
+/// unit or so across.
+pub const EYE_SEPARATION: f32 = 0.065;
+
+/// Both eyes, drawn into layers 0 and 1.
+const VIEW_MASK: u32 = 0b11;
+
+/// The left and right eyes' view matrices, for a head seeing through `view`. The eyes look the
+/// same way, `separation` apart, so something straight ahead appears right of centre to the left
+/// eye and left of it to the right one.
+pub fn eye_views(view: Mat4, separation: f32) -> [Mat4; 2] {
+    // Moving an eye left moves what it sees right.
+    [0.5, -0.5].map(|shift| Mat4::from_translation(Vec3::X * shift * separation) * view)
+}
+
+/// Where each eye goes in a target of `extent`, as `(offset, extent)`: the left eye on the left
+/// half, the right eye on the rest.
+pub fn eye_areas(extent: [u32; 2]) -> [([u32; 2], [u32; 2]); 2] {
+    let left_width = extent[0] / 2;
+    [
+        ([0, 0], [left_width, extent[1]]),
+        ([left_width, 0], [extent[0] - left_width, extent[1]]),
+    ]
+}
+
+/// Creates a render pass like [`create_render_pass`](crate::render_pass::create_render_pass)
+/// with a single sample, but drawing every draw call into both layers of its attachments, one
+/// view each.
+///
+/// Pipelines only need a render pass compatible with the one they draw in, so scenes create one
+/// of these themselves for their multiview pipelines.
+pub fn create_multiview_render_pass(
+    device: Arc<Device>,
+    color_format: Format,
+) -> Result<Arc<RenderPass>, RendererError> {
+    let attachment = |format, load_op, store_op, layout| AttachmentDescription {
+        format,
+        samples: SampleCount::Sample1,
+        load_op,
+        store_op,
+        initial_layout: layout,
+        final_layout: layout,
+        ..Default::default()
+    };
+    Ok(RenderPass::new(
+        device,
+        RenderPassCreateInfo {
+            attachments: vec![
+                attachment(
+                    color_format,
+                    AttachmentLoadOp::Clear,
+                    AttachmentStoreOp::Store,
+                    ImageLayout::ColorAttachmentOptimal,
+                ),
+                // Nothing reads depth after the pass.
+                attachment(
+                    DEPTH_FORMAT,
+                    AttachmentLoadOp::Clear,
+                    AttachmentStoreOp::DontCare,
+                    ImageLayout::DepthStencilAttachmentOptimal,
+                ),
+            ],
+            subpasses: vec![SubpassDescription {
+                view_mask: VIEW_MASK,
+                color_attachments: vec![Some(AttachmentReference {
+                    attachment: 0,
+                    layout: ImageLayout::ColorAttachmentOptimal,
+                    ..Default::default()
+                })],
+                depth_stencil_attachment: Some(AttachmentReference {
+                    attachment: 1,
+                    layout: ImageLayout::DepthStencilAttachmentOptimal,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+            // The eyes see nearly the same thing, which lets the driver share work between them.
+            correlated_view_masks: vec![VIEW_MASK],
+            ..Default::default()
+        },
+    )?)
+}
+
+/// The two-layer colour and depth images a multiview render pass draws both eyes into, one eye
+/// per layer, and a framebuffer for them.
+pub struct StereoTarget {
+    /// Of each eye.
+    extent: [u32; 2],
+    color: Arc<Image>,
+    framebuffer: Arc<Framebuffer>,
+}
+
+impl StereoTarget {
+    /// Creates a target for `render_pass`, made by [`create_multiview_render_pass`], with each
+    /// eye's layer `extent` in size.
+    pub fn new(
+        ctx: &VulkanContext,
+        render_pass: &Arc<RenderPass>,
+        extent: [u32; 2],
+    ) -> Result<Self, RendererError> {
+        let layered_image = |format, usage, label| {
+            let image = Image::new(
+                ctx.memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format,
+                    extent: [extent[0], extent[1], 1],
+                    array_layers: VIEW_MASK.count_ones(),
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )?;
+            ctx.memory_tracker
+                .track_image(MemoryCategory::RenderTarget, &image);
+            ctx.name_object(&image, label);
+            Ok::<_, RendererError>(image)
+        };
+        let color = layered_image(
+            render_pass.attachments()[0].format,
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            "stereo colour",
+        )?;
+        let depth = layered_image(
+            DEPTH_FORMAT,
+            ImageUsage::DEPTH_STENCIL_ATTACHMENT | ImageUsage::TRANSIENT_ATTACHMENT,
+            "stereo depth buffer",
+        )?;
+        let framebuffer = Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![
+                    ImageView::new_default(color.clone())?,
+                    ImageView::new_default(depth)?,
+                ],
+                ..Default::default()
+            },
+        )?;
+        Ok(Self {
+            extent,
+            color,
+            framebuffer,
+        })
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// The image both eyes are drawn into, the left eye in layer 0.
+    pub fn color(&self) -> &Arc<Image> {
+        &self.color
+    }
+
+    pub fn framebuffer(&self) -> &Arc<Framebuffer> {
+        &self.framebuffer
+    }
+}
+
+/// Records `scene`'s draws for both eyes of `frame` into a secondary command buffer, to be
+/// executed in `subpass` of a multiview render pass. The draws cover each eye's whole layer.
+///
+/// `allocator` must have been created with secondary command buffers.
+pub fn record_stereo_draws(
+    allocator: &StandardCommandBufferAllocator,
+    queue_family_index: u32,
+    subpass: Subpass,
+    scene: &dyn Scene,
+    frame: &FrameData,
+) -> Result<Arc<SecondaryAutoCommandBuffer>, RendererError> {
+    let mut builder = AutoCommandBufferBuilder::secondary(
+        allocator,
+        queue_family_index,
+        CommandBufferUsage::OneTimeSubmit,
+        CommandBufferInheritanceInfo {
+            render_pass: Some(subpass.into()),
+            ..Default::default()
+        },
+    )?;
+    // One viewport for both views: each eye has a layer of its own.
+    let viewport = Viewport {
+        offset: [0.0, 0.0],
+        extent: [frame.extent[0] as f32, frame.extent[1] as f32],
+        depth_range: 0.0..=1.0,
+    };
+    let scissor = Scissor {
+        offset: [0, 0],
+        extent: frame.extent,
+    };
+    builder
+        .set_viewport(0, [viewport].into_iter().collect())?
+        .set_scissor(0, [scissor].into_iter().collect())?;
+    scene.draw_stereo(&mut builder, frame)?;
+    Ok(builder.build()?)
+}
+
+/// Records blitting the eyes drawn into `source`, a [`StereoTarget`]'s colour image, side by
+/// side onto `target`, which needs `TRANSFER_DST` usage.
+pub fn record_composite(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    source: Arc<Image>,
+    target: Arc<Image>,
+) -> Result<(), RendererError> {
+    let source_extent = source.extent();
+    let mut blit = BlitImageInfo::images(source, target.clone());
+    let whole_layer = blit.regions[0].clone();
+    blit.regions = eye_areas([target.extent()[0], target.extent()[1]])
+        .into_iter()
+        .zip(0..)
+        .map(|((offset, extent), layer)| {
+            let mut region = whole_layer.clone();
+            region.src_subresource.array_layers = layer..layer + 1;
+            region.src_offsets = [[0; 3], source_extent];
+            region.dst_offsets = [
+                [offset[0], offset[1], 0],
+                [offset[0] + extent[0], offset[1] + extent[1], 1],
+            ];
+            region
+        })
+        .collect();
+    builder.blit_image(blit)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_eye_sees_the_scene_shifted_the_other_way() {
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 0.0, 3.0), Vec3::ZERO, Vec3::Y);
+        let [left, right] = eye_views(view, EYE_SEPARATION);
+        let origin_left = left.transform_point3(Vec3::ZERO);
+        let origin_right = right.transform_point3(Vec3::ZERO);
+        assert!(origin_left.x > 0.0 && origin_right.x < 0.0);
+        assert!((origin_left.x - origin_right.x - EYE_SEPARATION).abs() < 1e-6);
+        // Only sideways: depth and height stay as the head sees them.
+        let centre = view.transform_point3(Vec3::ZERO);
+        for origin in [origin_left, origin_right] {
+            assert_eq!([origin.y, origin.z], [centre.y, centre.z]);
+        }
+    }
+
+    #[test]
+    fn eyes_split_the_target_in_two() {
+        assert_eq!(
+            eye_areas([801, 600]),
+            [([0, 0], [400, 600]), ([400, 0], [401, 600])]
+        );
+    }
+}
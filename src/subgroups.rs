@@ -0,0 +1,338 @@
+//! Adding up a buffer in compute shaders, with subgroup operations and without, to see how the
+//! device's subgroups behave.
+//!
+//! Each workgroup of [`WORKGROUP_SIZE`] invocations reads one value per invocation and adds them
+//! up, and its first invocation adds the workgroup's sum onto a single total with an atomic. The
+//! subgroup path adds across each subgroup with `subgroupAdd`, stores one sum per subgroup in
+//! shared memory, and has the first subgroup add those up the same way. The shared memory path
+//! halves a shared array until one sum is left, with a barrier each time, and works on any
+//! device. [`reduce`] runs whichever the device supports, checking each total against the CPU's.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+use vulkano::pipeline::{ComputePipeline, Pipeline, PipelineBindPoint};
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::scene::build_compute_pipeline;
+
+/// How many values [`reduce`] adds up.
+pub const VALUES: u32 = 1 << 22;
+
+/// Values each workgroup adds up; matches `local_size_x` in the shaders.
+const WORKGROUP_SIZE: u32 = 256;
+
+mod subgroup_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        // Subgroup operations need SPIR-V 1.3.
+        vulkan_version: "1.1",
+        src: r"
+            #version 450
+            #extension GL_KHR_shader_subgroup_basic : require
+            #extension GL_KHR_shader_subgroup_arithmetic : require
+
+            layout(local_size_x = 256) in;
+
+            layout(set = 0, binding = 0) readonly buffer Values {
+                uint values[];
+            };
+            layout(set = 0, binding = 1) buffer Total {
+                uint total;
+            };
+
+            // A sum per subgroup, as many as there could be with subgroups of one.
+            shared uint subgroup_sums[256];
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                uint sum = subgroupAdd(index < values.length() ? values[index] : 0);
+                if (subgroupElect()) {
+                    subgroup_sums[gl_SubgroupID] = sum;
+                }
+                memoryBarrierShared();
+                barrier();
+
+                // The first subgroup adds up everyone's sums, as many at a time as it has
+                // invocations.
+                if (gl_SubgroupID == 0) {
+                    uint workgroup_sum = 0;
+                    for (uint i = gl_SubgroupInvocationID; i < gl_NumSubgroups; i += gl_SubgroupSize) {
+                        workgroup_sum += subgroup_sums[i];
+                    }
+                    workgroup_sum = subgroupAdd(workgroup_sum);
+                    if (subgroupElect()) {
+                        atomicAdd(total, workgroup_sum);
+                    }
+                }
+            }
+        "
+    }
+}
+
+mod shared_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r"
+            #version 450
+
+            layout(local_size_x = 256) in;
+
+            layout(set = 0, binding = 0) readonly buffer Values {
+                uint values[];
+            };
+            layout(set = 0, binding = 1) buffer Total {
+                uint total;
+            };
+
+            shared uint sums[256];
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                uint local = gl_LocalInvocationID.x;
+                sums[local] = index < values.length() ? values[index] : 0;
+                // Each round adds the upper half of what's left onto the lower half.
+                for (uint half_size = 128; half_size > 0; half_size /= 2) {
+                    memoryBarrierShared();
+                    barrier();
+                    if (local < half_size) {
+                        sums[local] += sums[local + half_size];
+                    }
+                }
+                if (local == 0) {
+                    atomicAdd(total, sums[0]);
+                }
+            }
+        "
+    }
+}
+
+/// How a workgroup adds up its values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReductionPath {
+    /// With `subgroupAdd`, then across the workgroup's subgroups through shared memory.
+    Subgroup,
+    /// In shared memory alone, halving it each round.
+    SharedMemory,
+}
+
+impl fmt::Display for ReductionPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReductionPath::Subgroup => "subgroupAdd",
+            ReductionPath::SharedMemory => "shared memory",
+        })
+    }
+}
+
+/// The total one path came to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reduction {
+    pub path: ReductionPath,
+    pub gpu_sum: u32,
+    /// From submitting the dispatch to the GPU finishing it.
+    pub elapsed: Duration,
+}
+
+/// What [`reduce`] found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReductionReport {
+    pub values: u32,
+    /// Zero on devices without subgroups.
+    pub subgroup_size: u32,
+    /// The values added up on the CPU, wrapping like the GPU's atomics.
+    pub cpu_sum: u32,
+    /// One per path run, the subgroup path first where the device supports it.
+    pub reductions: Vec<Reduction>,
+}
+
+impl ReductionReport {
+    /// Whether every path came to the CPU's sum.
+    pub fn all_match(&self) -> bool {
+        self.reductions
+            .iter()
+            .all(|reduction| reduction.gpu_sum == self.cpu_sum)
+    }
+}
+
+impl fmt::Display for ReductionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Reduction: {} values", self.values)?;
+        match self.subgroup_size {
+            0 => write!(f, ", no subgroups")?,
+            size => write!(f, ", subgroups of {size}")?,
+        }
+        write!(f, "\n  CPU sum {}", self.cpu_sum)?;
+        for reduction in &self.reductions {
+            write!(
+                f,
+                "\n  {:<14} GPU sum {} in {:.2} ms, {}",
+                format!("{}:", reduction.path),
+                reduction.gpu_sum,
+                reduction.elapsed.as_secs_f64() * 1e3,
+                if reduction.gpu_sum == self.cpu_sum {
+                    "matches"
+                } else {
+                    "MISMATCH"
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds up [`VALUES`] values on the GPU along each path the device supports, and on the CPU.
+pub fn reduce(ctx: &VulkanContext) -> Result<ReductionReport, RendererError> {
+    let values: Vec<u32> = (0..VALUES).map(value).collect();
+    let cpu_sum = values
+        .iter()
+        .fold(0u32, |sum, &value| sum.wrapping_add(value));
+    let input = Buffer::from_iter(
+        ctx.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        values,
+    )?;
+    ctx.memory_tracker
+        .track_buffer(MemoryCategory::Storage, input.buffer());
+    ctx.name_object(input.buffer(), "reduction values");
+
+    let subgroup_size = ctx.caps.limits.subgroup_size;
+    let mut paths = Vec::new();
+    if ctx.caps.subgroup_arithmetic() {
+        log::info!("Reducing with subgroupAdd, {subgroup_size} invocations per subgroup");
+        paths.push(ReductionPath::Subgroup);
+    } else {
+        log::info!(
+            "Compute shaders can't add across subgroups here, so only reducing in shared memory"
+        );
+    }
+    paths.push(ReductionPath::SharedMemory);
+
+    let reductions = paths
+        .into_iter()
+        .map(|path| {
+            let module = match path {
+                ReductionPath::Subgroup => subgroup_cs::load(ctx.device.clone())?,
+                ReductionPath::SharedMemory => shared_cs::load(ctx.device.clone())?,
+            };
+            let pipeline = build_compute_pipeline(ctx, module)?;
+            let (gpu_sum, elapsed) = run(ctx, &pipeline, &input)?;
+            log::info!("The {path} reduction came to {gpu_sum}");
+            Ok(Reduction {
+                path,
+                gpu_sum,
+                elapsed,
+            })
+        })
+        .collect::<Result<_, RendererError>>()?;
+    Ok(ReductionReport {
+        values: VALUES,
+        subgroup_size,
+        cpu_sum,
+        reductions,
+    })
+}
+
+/// Runs `pipeline` over `input` once, returning its total and how long that took.
+fn run(
+    ctx: &VulkanContext,
+    pipeline: &Arc<ComputePipeline>,
+    input: &Subbuffer<[u32]>,
+) -> Result<(u32, Duration), RendererError> {
+    let total = Buffer::from_data(
+        ctx.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        0u32,
+    )?;
+    let set = PersistentDescriptorSet::new(
+        ctx.descriptor_set_allocator.as_ref(),
+        pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::buffer(0, input.clone()),
+            WriteDescriptorSet::buffer(1, total.clone()),
+        ],
+        [],
+    )?;
+
+    let start = Instant::now();
+    ctx.submit_and_wait(|builder| {
+        builder
+            .bind_pipeline_compute(pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                set,
+            )?
+            .dispatch([(input.len() as u32).div_ceil(WORKGROUP_SIZE), 1, 1])?;
+        Ok(())
+    })?;
+    let elapsed = start.elapsed();
+    let sum = *total.read()?;
+    Ok((sum, elapsed))
+}
+
+/// The value at `index`, scattered over 0 to 1023 so the sums are easy to get wrong.
+fn value(index: u32) -> u32 {
+    index.wrapping_mul(2_654_435_761) >> 22
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_say_whether_each_path_matched() {
+        let reduction = |path, gpu_sum| Reduction {
+            path,
+            gpu_sum,
+            elapsed: Duration::from_micros(1500),
+        };
+        let mut report = ReductionReport {
+            values: 8,
+            subgroup_size: 32,
+            cpu_sum: 100,
+            reductions: vec![
+                reduction(ReductionPath::Subgroup, 100),
+                reduction(ReductionPath::SharedMemory, 100),
+            ],
+        };
+        assert!(report.all_match());
+        assert_eq!(
+            report.to_string(),
+            "Reduction: 8 values, subgroups of 32\n  \
+             CPU sum 100\n  \
+             subgroupAdd:   GPU sum 100 in 1.50 ms, matches\n  \
+             shared memory: GPU sum 100 in 1.50 ms, matches"
+        );
+
+        report.reductions[1].gpu_sum = 99;
+        assert!(!report.all_match());
+        assert!(report
+            .to_string()
+            .ends_with("GPU sum 99 in 1.50 ms, MISMATCH"));
+        assert!((0..VALUES).step_by(997).all(|index| value(index) < 1024));
+    }
+}
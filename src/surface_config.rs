@@ -0,0 +1,463 @@
+//! Everything about a surface that matters when creating its swapchain, queried in one place.
+
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::format::{Format, FormatFeatures};
+use vulkano::image::ImageUsage;
+use vulkano::swapchain::{
+    ColorSpace, CompositeAlpha, CompositeAlphas, PresentMode, Surface, SurfaceInfo,
+    SurfaceTransform, SwapchainCreateInfo,
+};
+
+use crate::error::RendererError;
+
+/// Formats we'd like the swapchain to use, best first. With an sRGB format the hardware does the
+/// linear-to-sRGB conversion when the shaders write their output.
+const PREFERRED_FORMATS: [Format; 2] = [Format::B8G8R8A8_SRGB, Format::R8G8B8A8_SRGB];
+
+/// What we present HDR in. Extended linear sRGB takes the linear values the shaders write as
+/// they are, with 1.0 as the display's SDR white, so scenes look the same as in SDR and only
+/// brighter values would go beyond it. HDR10 would need the output PQ encoded in a pass of
+/// its own, which we don't have.
+const HDR_FORMATS: [(Format, ColorSpace); 1] =
+    [(Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear)];
+
+/// The surface's capabilities, formats and present modes.
+///
+/// Only the parts of vulkano's `SurfaceCapabilities` we use are copied out, into a plain struct
+/// that tests can fill in with recorded data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceConfig {
+    pub min_image_count: u32,
+    /// `None` means there's no limit.
+    pub max_image_count: Option<u32>,
+    /// `None` means the surface takes whatever size the swapchain picks, within the limits below.
+    pub current_extent: Option<[u32; 2]>,
+    pub min_image_extent: [u32; 2],
+    pub max_image_extent: [u32; 2],
+    pub current_transform: SurfaceTransform,
+    pub supported_composite_alpha: CompositeAlphas,
+    pub supported_usage_flags: ImageUsage,
+    pub formats: Vec<(Format, ColorSpace)>,
+    pub present_modes: Vec<PresentMode>,
+}
+
+impl SurfaceConfig {
+    pub fn query(
+        physical_device: &PhysicalDevice,
+        surface: &Surface,
+    ) -> Result<Self, RendererError> {
+        let capabilities = physical_device.surface_capabilities(surface, SurfaceInfo::default())?;
+        let formats = physical_device.surface_formats(surface, SurfaceInfo::default())?;
+        let present_modes = physical_device
+            .surface_present_modes(surface, SurfaceInfo::default())?
+            .collect();
+
+        Ok(Self {
+            min_image_count: capabilities.min_image_count,
+            max_image_count: capabilities.max_image_count,
+            current_extent: capabilities.current_extent,
+            min_image_extent: capabilities.min_image_extent,
+            max_image_extent: capabilities.max_image_extent,
+            current_transform: capabilities.current_transform,
+            supported_composite_alpha: capabilities.supported_composite_alpha,
+            supported_usage_flags: capabilities.supported_usage_flags,
+            formats,
+            present_modes,
+        })
+    }
+
+    /// Picks an 8-bit sRGB format if there is one, otherwise whatever the surface lists first.
+    pub fn choose_format(&self) -> (Format, ColorSpace) {
+        PREFERRED_FORMATS
+            .iter()
+            .find_map(|&preferred| {
+                self.formats.iter().copied().find(|&(format, color_space)| {
+                    format == preferred && color_space == ColorSpace::SrgbNonLinear
+                })
+            })
+            .unwrap_or(self.formats[0])
+    }
+
+    /// The HDR format and colour space to present in, if the surface offers one. Surfaces only
+    /// list HDR colour spaces when `VK_EXT_swapchain_colorspace` is enabled.
+    pub fn choose_hdr_format(&self) -> Option<(Format, ColorSpace)> {
+        HDR_FORMATS
+            .into_iter()
+            .find(|hdr_format| self.formats.contains(hdr_format))
+    }
+
+    /// Looks for `format`, preferring the colour space
+    /// [`choose_hdr_format`](Self::choose_hdr_format) pairs it with if any, then sRGB, e.g. to
+    /// give a second window the same format as the first.
+    pub fn format_with(&self, format: Format) -> Option<(Format, ColorSpace)> {
+        let matching = || {
+            self.formats
+                .iter()
+                .copied()
+                .filter(move |&(f, _)| f == format)
+        };
+        let hdr_color_space = HDR_FORMATS
+            .into_iter()
+            .find_map(|(hdr_format, color_space)| (hdr_format == format).then_some(color_space));
+        hdr_color_space
+            .and_then(|hdr| matching().find(|&(_, color_space)| color_space == hdr))
+            .or_else(|| {
+                matching().find(|&(_, color_space)| color_space == ColorSpace::SrgbNonLinear)
+            })
+            .or_else(|| matching().next())
+    }
+
+    /// Uses `preferred` if the surface supports it. Otherwise falls back to `Fifo`, the one mode
+    /// every surface has to support.
+    pub fn choose_present_mode(&self, preferred: PresentMode) -> PresentMode {
+        if self.present_modes.contains(&preferred) {
+            preferred
+        } else {
+            PresentMode::Fifo
+        }
+    }
+
+    /// The swapchain size to use when the window is `requested` pixels big.
+    ///
+    /// Most platforms dictate the size through `current_extent`. Where they don't, the window size
+    /// is used, clamped to what the surface allows.
+    pub fn clamp_extent(&self, requested: [u32; 2]) -> [u32; 2] {
+        self.current_extent.unwrap_or_else(|| {
+            [
+                requested[0].clamp(self.min_image_extent[0], self.max_image_extent[0]),
+                requested[1].clamp(self.min_image_extent[1], self.max_image_extent[1]),
+            ]
+        })
+    }
+
+    /// How many images to ask for: `requested` if given, within the surface's limits.
+    pub fn image_count(&self, requested: Option<u32>) -> u32 {
+        // Some drivers report an `min_image_count` of 1, but fullscreen mode requires at least 2.
+        // Therefore we must ensure the count is at least 2 by default, otherwise the program
+        // would crash when entering fullscreen mode on those drivers.
+        let count = requested.unwrap_or(2).max(self.min_image_count);
+        // vulkano already turned the `max_image_count` of 0 meaning unbounded into `None`.
+        match self.max_image_count {
+            Some(max) => count.min(max),
+            None => count,
+        }
+    }
+
+    /// How the window's contents are blended with what's behind it. A `transparent` window gets
+    /// pre- or post-multiplied alpha, whichever the surface supports, and is opaque if it
+    /// supports neither. Otherwise it is opaque if at all possible, then left to the platform,
+    /// and only blended with the desktop if the surface allows nothing else.
+    ///
+    /// The order is spelled out rather than taking the first supported mode, which is
+    /// `Inherit` on some drivers that then composite the window semi-transparently.
+    pub fn choose_composite_alpha(&self, transparent: bool) -> CompositeAlpha {
+        let preference = if transparent {
+            [
+                CompositeAlpha::PreMultiplied,
+                CompositeAlpha::PostMultiplied,
+                CompositeAlpha::Opaque,
+                CompositeAlpha::Inherit,
+            ]
+        } else {
+            [
+                CompositeAlpha::Opaque,
+                CompositeAlpha::Inherit,
+                CompositeAlpha::PreMultiplied,
+                CompositeAlpha::PostMultiplied,
+            ]
+        };
+        preference
+            .into_iter()
+            .find(|&mode| self.supported_composite_alpha.contains_enum(mode))
+            // Surfaces have to support at least one mode, so this is only for broken drivers.
+            .unwrap_or(CompositeAlpha::Opaque)
+    }
+
+    /// Swapchain images are always rendered to. They're also made blit targets when the surface
+    /// allows it, so a scene rendered at a lower resolution can be scaled onto them, and copy
+    /// sources for screenshots.
+    pub fn image_usage(&self) -> ImageUsage {
+        ImageUsage::COLOR_ATTACHMENT
+            | (self.supported_usage_flags & (ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC))
+    }
+
+    /// `STORAGE` if the surface allows it on its images and their format, whose
+    /// `format_features` are given, can be a storage image, so compute shaders can write them.
+    /// Otherwise nothing.
+    pub fn storage_usage(&self, format_features: FormatFeatures) -> ImageUsage {
+        if self.supported_usage_flags.intersects(ImageUsage::STORAGE)
+            && format_features.intersects(FormatFeatures::STORAGE_IMAGE)
+        {
+            ImageUsage::STORAGE
+        } else {
+            ImageUsage::empty()
+        }
+    }
+
+    /// Fills in a swapchain create info for a window of `window_size` pixels, which the desktop
+    /// should show through where nothing is drawn if `transparent`.
+    pub fn swapchain_create_info(
+        &self,
+        window_size: [u32; 2],
+        present_mode: PresentMode,
+        image_count: Option<u32>,
+        transparent: bool,
+    ) -> SwapchainCreateInfo {
+        let (image_format, image_color_space) = self.choose_format();
+        SwapchainCreateInfo {
+            min_image_count: self.image_count(image_count),
+            image_format,
+            image_color_space,
+            image_extent: self.clamp_extent(window_size),
+            image_usage: self.image_usage(),
+            pre_transform: self.current_transform,
+            composite_alpha: self.choose_composite_alpha(transparent),
+            present_mode: self.choose_present_mode(present_mode),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Roughly what a Windows desktop driver reports: the surface dictates its extent.
+    fn windows_surface() -> SurfaceConfig {
+        SurfaceConfig {
+            min_image_count: 2,
+            max_image_count: Some(8),
+            current_extent: Some([1280, 720]),
+            min_image_extent: [1280, 720],
+            max_image_extent: [1280, 720],
+            current_transform: SurfaceTransform::Identity,
+            supported_composite_alpha: CompositeAlphas::OPAQUE,
+            supported_usage_flags: ImageUsage::COLOR_ATTACHMENT
+                | ImageUsage::TRANSFER_SRC
+                | ImageUsage::TRANSFER_DST
+                | ImageUsage::SAMPLED
+                | ImageUsage::STORAGE,
+            formats: vec![
+                (Format::B8G8R8A8_UNORM, ColorSpace::SrgbNonLinear),
+                (Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear),
+            ],
+            present_modes: vec![
+                PresentMode::Fifo,
+                PresentMode::FifoRelaxed,
+                PresentMode::Mailbox,
+                PresentMode::Immediate,
+            ],
+        }
+    }
+
+    /// Roughly what Mesa reports on Wayland: no fixed extent and only FIFO and mailbox.
+    fn wayland_surface() -> SurfaceConfig {
+        SurfaceConfig {
+            min_image_count: 4,
+            max_image_count: None,
+            current_extent: None,
+            min_image_extent: [1, 1],
+            max_image_extent: [16384, 16384],
+            current_transform: SurfaceTransform::Identity,
+            supported_composite_alpha: CompositeAlphas::OPAQUE | CompositeAlphas::PRE_MULTIPLIED,
+            supported_usage_flags: ImageUsage::COLOR_ATTACHMENT
+                | ImageUsage::TRANSFER_SRC
+                | ImageUsage::TRANSFER_DST
+                | ImageUsage::SAMPLED,
+            formats: vec![
+                (Format::A2R10G10B10_UNORM_PACK32, ColorSpace::SrgbNonLinear),
+                (Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear),
+            ],
+            present_modes: vec![PresentMode::Mailbox, PresentMode::Fifo],
+        }
+    }
+
+    #[test]
+    fn srgb_formats_are_preferred() {
+        assert_eq!(
+            windows_surface().choose_format(),
+            (Format::B8G8R8A8_SRGB, ColorSpace::SrgbNonLinear)
+        );
+        assert_eq!(
+            wayland_surface().choose_format(),
+            (Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear)
+        );
+    }
+
+    #[test]
+    fn first_format_when_nothing_preferred() {
+        let mut config = windows_surface();
+        config.formats = vec![(Format::B8G8R8A8_UNORM, ColorSpace::SrgbNonLinear)];
+        assert_eq!(config.choose_format().0, Format::B8G8R8A8_UNORM);
+    }
+
+    #[test]
+    fn format_with_finds_a_specific_format() {
+        assert_eq!(
+            wayland_surface().format_with(Format::R8G8B8A8_SRGB),
+            Some((Format::R8G8B8A8_SRGB, ColorSpace::SrgbNonLinear))
+        );
+        assert_eq!(wayland_surface().format_with(Format::B8G8R8A8_SRGB), None);
+    }
+
+    #[test]
+    fn hdr_needs_extended_linear_srgb() {
+        let mut config = windows_surface();
+        assert_eq!(config.choose_hdr_format(), None);
+        config.formats.extend([
+            (Format::A2B10G10R10_UNORM_PACK32, ColorSpace::Hdr10St2084),
+            (Format::R16G16B16A16_SFLOAT, ColorSpace::SrgbNonLinear),
+        ]);
+        assert_eq!(config.choose_hdr_format(), None);
+        config
+            .formats
+            .push((Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear));
+        let hdr_format = (Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear);
+        assert_eq!(config.choose_hdr_format(), Some(hdr_format));
+        // Windows opened later match the first one's colour space too.
+        assert_eq!(
+            config.format_with(Format::R16G16B16A16_SFLOAT),
+            Some(hdr_format)
+        );
+        // SDR is still the default.
+        assert_eq!(config.choose_format().1, ColorSpace::SrgbNonLinear);
+    }
+
+    #[test]
+    fn unsupported_present_mode_falls_back_to_fifo() {
+        assert_eq!(
+            wayland_surface().choose_present_mode(PresentMode::Immediate),
+            PresentMode::Fifo
+        );
+        assert_eq!(
+            wayland_surface().choose_present_mode(PresentMode::Mailbox),
+            PresentMode::Mailbox
+        );
+    }
+
+    #[test]
+    fn extent_follows_current_extent_or_clamps() {
+        assert_eq!(windows_surface().clamp_extent([800, 600]), [1280, 720]);
+        assert_eq!(wayland_surface().clamp_extent([800, 600]), [800, 600]);
+        assert_eq!(wayland_surface().clamp_extent([0, 99999]), [1, 16384]);
+    }
+
+    #[test]
+    fn image_usage_adds_transfers_when_supported() {
+        let usage = windows_surface().image_usage();
+        assert_eq!(
+            usage,
+            ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC
+        );
+
+        let mut config = windows_surface();
+        config.supported_usage_flags = ImageUsage::COLOR_ATTACHMENT;
+        assert_eq!(config.image_usage(), ImageUsage::COLOR_ATTACHMENT);
+    }
+
+    #[test]
+    fn storage_usage_needs_the_surface_and_the_format() {
+        let storable = FormatFeatures::STORAGE_IMAGE | FormatFeatures::BLIT_DST;
+        assert_eq!(
+            windows_surface().storage_usage(storable),
+            ImageUsage::STORAGE
+        );
+        // sRGB formats usually can't be storage images.
+        assert_eq!(
+            windows_surface().storage_usage(FormatFeatures::BLIT_DST),
+            ImageUsage::empty()
+        );
+        assert_eq!(
+            wayland_surface().storage_usage(storable),
+            ImageUsage::empty()
+        );
+    }
+
+    #[test]
+    fn image_count_respects_limits() {
+        assert_eq!(windows_surface().image_count(None), 2);
+        assert_eq!(wayland_surface().image_count(None), 4);
+
+        let mut config = windows_surface();
+        config.min_image_count = 1;
+        config.max_image_count = Some(1);
+        assert_eq!(config.image_count(None), 1);
+    }
+
+    #[test]
+    fn requested_image_count_is_clamped() {
+        assert_eq!(windows_surface().image_count(Some(3)), 3);
+        assert_eq!(windows_surface().image_count(Some(10)), 8);
+        assert_eq!(wayland_surface().image_count(Some(3)), 4);
+        assert_eq!(wayland_surface().image_count(Some(10)), 10);
+
+        let mut config = windows_surface();
+        config.min_image_count = 1;
+        assert_eq!(config.image_count(Some(1)), 1);
+    }
+
+    #[test]
+    fn transparency_needs_a_blended_composite_alpha() {
+        assert_eq!(
+            wayland_surface().choose_composite_alpha(true),
+            CompositeAlpha::PreMultiplied
+        );
+        assert_eq!(
+            wayland_surface().choose_composite_alpha(false),
+            CompositeAlpha::Opaque
+        );
+        assert_eq!(
+            windows_surface().choose_composite_alpha(true),
+            CompositeAlpha::Opaque
+        );
+
+        let mut config = windows_surface();
+        config.supported_composite_alpha =
+            CompositeAlphas::INHERIT | CompositeAlphas::POST_MULTIPLIED;
+        assert_eq!(
+            config.choose_composite_alpha(true),
+            CompositeAlpha::PostMultiplied
+        );
+        assert_eq!(
+            config.choose_composite_alpha(false),
+            CompositeAlpha::Inherit
+        );
+    }
+
+    #[test]
+    fn composite_alpha_prefers_opaque_then_inherit() {
+        let all = CompositeAlphas::OPAQUE
+            | CompositeAlphas::PRE_MULTIPLIED
+            | CompositeAlphas::POST_MULTIPLIED
+            | CompositeAlphas::INHERIT;
+        let choose = |supported, transparent| {
+            let mut config = windows_surface();
+            config.supported_composite_alpha = supported;
+            config.choose_composite_alpha(transparent)
+        };
+        assert_eq!(choose(all, false), CompositeAlpha::Opaque);
+        assert_eq!(
+            choose(
+                CompositeAlphas::INHERIT | CompositeAlphas::PRE_MULTIPLIED,
+                false
+            ),
+            CompositeAlpha::Inherit
+        );
+        assert_eq!(
+            choose(
+                CompositeAlphas::PRE_MULTIPLIED | CompositeAlphas::POST_MULTIPLIED,
+                false
+            ),
+            CompositeAlpha::PreMultiplied
+        );
+        assert_eq!(
+            choose(CompositeAlphas::POST_MULTIPLIED, false),
+            CompositeAlpha::PostMultiplied
+        );
+        assert_eq!(choose(all, true), CompositeAlpha::PreMultiplied);
+        assert_eq!(
+            choose(CompositeAlphas::empty(), false),
+            CompositeAlpha::Opaque
+        );
+    }
+}
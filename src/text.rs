@@ -0,0 +1,493 @@
+//! Drawing text and flat rectangles over whatever has been drawn into a target, for HUDs.
+//!
+//! Text is set in a monospace font bundled with the crate, rasterized into a [`GlyphAtlas`] of
+//! the printable ASCII characters at the size it's drawn at. Each frame's glyphs and rectangles are
+//! queued as quads, then recorded into a secondary command buffer that draws them all at once
+//! with alpha blending and no depth test, so they end up on top of the scene.
+
+use std::sync::Arc;
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{BufferContents, BufferUsage};
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferInheritanceInfo, CommandBufferUsage,
+    SecondaryAutoCommandBuffer,
+};
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::image::SampleCount;
+use vulkano::memory::allocator::MemoryTypeFilter;
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, ColorBlendAttachmentState, ColorBlendState,
+};
+use vulkano::pipeline::graphics::depth_stencil::DepthStencilState;
+use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::pipeline::graphics::multisample::MultisampleState;
+use vulkano::pipeline::graphics::rasterization::RasterizationState;
+use vulkano::pipeline::graphics::vertex_input::{Vertex, VertexDefinition};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport, ViewportState};
+use vulkano::pipeline::graphics::GraphicsPipelineCreateInfo;
+use vulkano::pipeline::layout::PipelineDescriptorSetLayoutCreateInfo;
+use vulkano::pipeline::{
+    DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+    PipelineShaderStageCreateInfo,
+};
+use vulkano::render_pass::Subpass;
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::sampler::SamplerConfig;
+use crate::shader_dump;
+use crate::texture::Texture;
+
+/// DejaVu Sans Mono, under the licence in `assets/fonts/LICENSE`.
+const FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSansMono.ttf");
+
+/// The characters the atlas has glyphs for. Anything else is drawn as [`REPLACEMENT`].
+const FIRST_CHAR: char = ' ';
+const LAST_CHAR: char = '~';
+const REPLACEMENT: char = '?';
+
+/// How many cells wide the atlas is.
+const ATLAS_COLUMNS: u32 = 16;
+
+/// The height text is set at, in logical pixels.
+pub const FONT_SIZE: f32 = 16.0;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 position;
+            layout(location = 1) in vec2 uv;
+            layout(location = 2) in vec4 color;
+
+            layout(location = 0) out vec2 v_uv;
+            layout(location = 1) out vec4 v_color;
+
+            // Positions are in pixels from the target's top left corner.
+            layout(push_constant) uniform Target {
+                vec2 extent;
+            } target;
+
+            void main() {
+                v_uv = uv;
+                v_color = color;
+                gl_Position = vec4(position / target.extent * 2.0 - 1.0, 0.0, 1.0);
+            }
+        "
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 v_uv;
+            layout(location = 1) in vec4 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D atlas;
+
+            void main() {
+                f_color = vec4(v_color.rgb, v_color.a * texture(atlas, v_uv).a);
+            }
+        "
+    }
+}
+
+#[derive(BufferContents, Vertex, Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct TextVertex {
+    /// In pixels from the top left corner of the target.
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+/// The printable ASCII characters rasterized into a grid of equally sized cells, white with the
+/// glyph's coverage in alpha, followed by one fully opaque cell for drawing rectangles.
+pub struct GlyphAtlas {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed RGBA8.
+    pub pixels: Vec<u8>,
+    /// The size of one character, and how far the next one starts along a line.
+    pub cell: [u32; 2],
+}
+
+impl GlyphAtlas {
+    /// Rasterizes the bundled font at `size` pixels.
+    pub fn new(size: f32) -> Self {
+        let font = FontRef::try_from_slice(FONT).expect("the bundled font is valid");
+        let scale = PxScale::from(size);
+        let scaled = font.as_scaled(scale);
+        // Monospace, so any glyph's advance will do.
+        let cell = [
+            scaled.h_advance(font.glyph_id('M')).ceil() as u32,
+            scaled.height().ceil() as u32,
+        ];
+        let cells = SOLID_CELL + 1;
+        let rows = cells.div_ceil(ATLAS_COLUMNS);
+        let (width, height) = (ATLAS_COLUMNS * cell[0], rows * cell[1]);
+        let mut pixels: Vec<u8> = [255, 255, 255, 0].repeat((width * height) as usize);
+
+        for c in FIRST_CHAR..=LAST_CHAR {
+            let [x, y] = cell_origin(cell, cell_index(c));
+            let glyph = font
+                .glyph_id(c)
+                .with_scale_and_position(scale, point(x as f32, y as f32 + scaled.ascent()));
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|glyph_x, glyph_y, coverage| {
+                let px = bounds.min.x as i64 + i64::from(glyph_x);
+                let py = bounds.min.y as i64 + i64::from(glyph_y);
+                // Keep anything overhanging its cell out of the neighbours'.
+                let inside_cell = (i64::from(x)..i64::from(x + cell[0])).contains(&px)
+                    && (i64::from(y)..i64::from(y + cell[1])).contains(&py);
+                if inside_cell {
+                    let alpha = &mut pixels[(py as usize * width as usize + px as usize) * 4 + 3];
+                    *alpha = (*alpha).max((coverage.clamp(0.0, 1.0) * 255.0).round() as u8);
+                }
+            });
+        }
+        let [x, y] = cell_origin(cell, SOLID_CELL);
+        for row in y..y + cell[1] {
+            for column in x..x + cell[0] {
+                pixels[(row * width + column) as usize * 4 + 3] = 255;
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+            cell,
+        }
+    }
+
+    /// The texture coordinates of the top left and bottom right corners of cell `index`.
+    fn cell_uvs(&self, index: u32) -> ([f32; 2], [f32; 2]) {
+        let [x, y] = cell_origin(self.cell, index);
+        let size = [self.width as f32, self.height as f32];
+        (
+            [x as f32 / size[0], y as f32 / size[1]],
+            [
+                (x + self.cell[0]) as f32 / size[0],
+                (y + self.cell[1]) as f32 / size[1],
+            ],
+        )
+    }
+}
+
+/// Which atlas cell holds the glyph for `c`.
+fn cell_index(c: char) -> u32 {
+    let c = if (FIRST_CHAR..=LAST_CHAR).contains(&c) {
+        c
+    } else {
+        REPLACEMENT
+    };
+    c as u32 - FIRST_CHAR as u32
+}
+
+/// The atlas cell that is opaque all over, right after the last character's.
+const SOLID_CELL: u32 = LAST_CHAR as u32 - FIRST_CHAR as u32 + 1;
+
+/// The top left corner of cell `index` in an atlas of `cell`-sized cells, in pixels.
+fn cell_origin(cell: [u32; 2], index: u32) -> [u32; 2] {
+    [
+        index % ATLAS_COLUMNS * cell[0],
+        index / ATLAS_COLUMNS * cell[1],
+    ]
+}
+
+/// Draws queued text and rectangles, in the order they were queued.
+pub struct TextRenderer {
+    atlas: GlyphAtlas,
+    /// The size `atlas` was rasterized at, in pixels.
+    font_size: f32,
+    pipeline: Arc<GraphicsPipeline>,
+    descriptor_set: Arc<PersistentDescriptorSet>,
+    vertex_allocator: SubbufferAllocator,
+    vertices: Vec<TextVertex>,
+}
+
+impl TextRenderer {
+    /// Creates a renderer for `subpass` with text at [`FONT_SIZE`] pixels.
+    pub fn new(ctx: &VulkanContext, subpass: Subpass) -> Result<Self, RendererError> {
+        let atlas = GlyphAtlas::new(FONT_SIZE);
+        let pipeline = build_text_pipeline(ctx, subpass)?;
+        ctx.name_object(&pipeline, "text pipeline");
+        let descriptor_set = atlas_descriptor_set(ctx, &pipeline, &atlas)?;
+        // Rewritten every frame, so keep it where the CPU can write it directly.
+        let vertex_allocator = SubbufferAllocator::new(
+            ctx.memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::VERTEX_BUFFER,
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+        );
+        Ok(Self {
+            atlas,
+            font_size: FONT_SIZE,
+            pipeline,
+            descriptor_set,
+            vertex_allocator,
+            vertices: Vec::new(),
+        })
+    }
+
+    /// Sets text at `size` pixels from now on, rasterizing the atlas again if that's a new size.
+    pub fn set_font_size(&mut self, ctx: &VulkanContext, size: f32) -> Result<(), RendererError> {
+        if size == self.font_size {
+            return Ok(());
+        }
+        let atlas = GlyphAtlas::new(size);
+        self.descriptor_set = atlas_descriptor_set(ctx, &self.pipeline, &atlas)?;
+        self.atlas = atlas;
+        self.font_size = size;
+        Ok(())
+    }
+
+    /// The size of one character, in pixels.
+    pub fn cell(&self) -> [u32; 2] {
+        self.atlas.cell
+    }
+
+    /// Queues `text` as a single line with its top left corner at `position`. Characters
+    /// outside printable ASCII are drawn as `?`.
+    pub fn queue_text(&mut self, position: [f32; 2], text: &str, color: [f32; 4]) {
+        let [cell_width, cell_height] = self.atlas.cell.map(|size| size as f32);
+        for (column, c) in text.chars().enumerate() {
+            if c == ' ' {
+                continue;
+            }
+            let top_left = [position[0] + column as f32 * cell_width, position[1]];
+            let bottom_right = [top_left[0] + cell_width, top_left[1] + cell_height];
+            let uvs = self.atlas.cell_uvs(cell_index(c));
+            self.queue_quad(top_left, bottom_right, uvs, color);
+        }
+    }
+
+    /// Queues a filled rectangle from `top_left` to `bottom_right`.
+    pub fn queue_rect(&mut self, top_left: [f32; 2], bottom_right: [f32; 2], color: [f32; 4]) {
+        let (uv_min, uv_max) = self.atlas.cell_uvs(SOLID_CELL);
+        // Sample the middle of the cell, well clear of its neighbours.
+        let uv = [(uv_min[0] + uv_max[0]) / 2.0, (uv_min[1] + uv_max[1]) / 2.0];
+        self.queue_quad(top_left, bottom_right, (uv, uv), color);
+    }
+
+    fn queue_quad(
+        &mut self,
+        top_left: [f32; 2],
+        bottom_right: [f32; 2],
+        (uv_min, uv_max): ([f32; 2], [f32; 2]),
+        color: [f32; 4],
+    ) {
+        let vertex = |x: usize, y: usize| TextVertex {
+            position: [
+                [top_left[0], bottom_right[0]][x],
+                [top_left[1], bottom_right[1]][y],
+            ],
+            uv: [[uv_min[0], uv_max[0]][x], [uv_min[1], uv_max[1]][y]],
+            color,
+        };
+        self.vertices.extend([
+            vertex(0, 0),
+            vertex(1, 0),
+            vertex(0, 1),
+            vertex(0, 1),
+            vertex(1, 0),
+            vertex(1, 1),
+        ]);
+    }
+
+    /// Records drawing everything queued since the last call into a target of `extent` pixels
+    /// with a command buffer from `allocator`, and empties the queue. Returns `None` if nothing
+    /// was queued.
+    pub fn record(
+        &mut self,
+        ctx: &VulkanContext,
+        allocator: &StandardCommandBufferAllocator,
+        subpass: Subpass,
+        extent: [u32; 2],
+    ) -> Result<Option<Arc<SecondaryAutoCommandBuffer>>, RendererError> {
+        if self.vertices.is_empty() {
+            return Ok(None);
+        }
+        let vertex_buffer = self
+            .vertex_allocator
+            .allocate_slice::<TextVertex>(self.vertices.len() as u64)?;
+        vertex_buffer.write()?.copy_from_slice(&self.vertices);
+        ctx.memory_tracker
+            .track_buffer(MemoryCategory::Vertex, vertex_buffer.buffer());
+        let vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+
+        let mut builder = AutoCommandBufferBuilder::secondary(
+            allocator,
+            ctx.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+            CommandBufferInheritanceInfo {
+                render_pass: Some(subpass.into()),
+                ..Default::default()
+            },
+        )?;
+        let viewport = Viewport {
+            offset: [0.0, 0.0],
+            extent: extent.map(|size| size as f32),
+            depth_range: 0.0..=1.0,
+        };
+        builder
+            .set_viewport(0, [viewport].into_iter().collect())?
+            .set_scissor(
+                0,
+                [Scissor {
+                    offset: [0, 0],
+                    extent,
+                }]
+                .into_iter()
+                .collect(),
+            )?
+            .bind_pipeline_graphics(self.pipeline.clone())?
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                self.descriptor_set.clone(),
+            )?
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                vs::Target {
+                    extent: extent.map(|size| size as f32),
+                },
+            )?
+            .bind_vertex_buffers(0, vertex_buffer)?
+            .draw(vertex_count, 1, 0, 0)?;
+        Ok(Some(builder.build()?))
+    }
+}
+
+/// Uploads `atlas` and binds it for `pipeline`.
+fn atlas_descriptor_set(
+    ctx: &VulkanContext,
+    pipeline: &GraphicsPipeline,
+    atlas: &GlyphAtlas,
+) -> Result<Arc<PersistentDescriptorSet>, RendererError> {
+    // Glyphs are drawn at their rasterized size, so there's nothing to filter.
+    let texture = Texture::from_rgba8(
+        ctx,
+        atlas.width,
+        atlas.height,
+        &atlas.pixels,
+        SamplerConfig::nearest_clamped(),
+    )?;
+    ctx.name_object(&texture.image, "glyph atlas");
+    Ok(PersistentDescriptorSet::new(
+        ctx.descriptor_set_allocator.as_ref(),
+        pipeline.layout().set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view_sampler(
+            0,
+            texture.view,
+            texture.sampler,
+        )],
+        [],
+    )?)
+}
+
+/// A pipeline like the scenes', but blending by alpha and ignoring depth.
+fn build_text_pipeline(
+    ctx: &VulkanContext,
+    subpass: Subpass,
+) -> Result<Arc<GraphicsPipeline>, RendererError> {
+    let vs = vs::load(ctx.device.clone())?.entry_point("main").unwrap();
+    let fs = fs::load(ctx.device.clone())?.entry_point("main").unwrap();
+    let vertex_input_state = TextVertex::per_vertex().definition(&vs.info().input_interface)?;
+    shader_dump::entry_point(&vs);
+    shader_dump::entry_point(&fs);
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        ctx.device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(ctx.device.clone())?,
+    )?;
+
+    let has_depth = subpass.subpass_desc().depth_stencil_attachment.is_some();
+    let rasterization_samples = subpass.num_samples().unwrap_or(SampleCount::Sample1);
+    let pipeline = GraphicsPipeline::new(
+        ctx.device.clone(),
+        None,
+        GraphicsPipelineCreateInfo {
+            stages: stages.into_iter().collect(),
+            vertex_input_state: Some(vertex_input_state),
+            input_assembly_state: Some(InputAssemblyState::default()),
+            viewport_state: Some(ViewportState::default()),
+            rasterization_state: Some(RasterizationState::default()),
+            multisample_state: Some(MultisampleState {
+                rasterization_samples,
+                ..Default::default()
+            }),
+            // Neither tested against nor written to depth, so it covers the scene.
+            depth_stencil_state: has_depth.then(DepthStencilState::default),
+            color_blend_state: Some(ColorBlendState::with_attachment_states(
+                subpass.num_color_attachments(),
+                ColorBlendAttachmentState {
+                    blend: Some(AttachmentBlend::alpha()),
+                    ..Default::default()
+                },
+            )),
+            dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                .into_iter()
+                .collect(),
+            subpass: Some(subpass.into()),
+            ..GraphicsPipelineCreateInfo::layout(layout)
+        },
+    )?;
+    Ok(pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell_alpha(atlas: &GlyphAtlas, index: u32) -> Vec<u8> {
+        let [x, y] = cell_origin(atlas.cell, index);
+        (y..y + atlas.cell[1])
+            .flat_map(|row| {
+                (x..x + atlas.cell[0])
+                    .map(move |column| atlas.pixels[(row * atlas.width + column) as usize * 4 + 3])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn atlas_has_a_cell_per_character() {
+        let atlas = GlyphAtlas::new(FONT_SIZE);
+        assert_eq!(
+            atlas.pixels.len(),
+            (atlas.width * atlas.height * 4) as usize
+        );
+        assert!(atlas.cell[0] > 0 && atlas.cell[1] >= FONT_SIZE as u32);
+        assert!(cell_alpha(&atlas, cell_index(' ')).iter().all(|&a| a == 0));
+        assert!(cell_alpha(&atlas, cell_index('M')).contains(&255));
+        assert!(cell_alpha(&atlas, SOLID_CELL).iter().all(|&a| a == 255));
+        assert_eq!(cell_index('é'), cell_index(REPLACEMENT));
+    }
+}
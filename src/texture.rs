@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage};
+use vulkano::command_buffer::CopyBufferToImageInfo;
+use vulkano::format::Format;
+use vulkano::image::sampler::Sampler;
+use vulkano::image::view::{ImageView, ImageViewCreateInfo, ImageViewType};
+use vulkano::image::{Image, ImageCreateFlags, ImageCreateInfo, ImageType, ImageUsage};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter};
+
+use crate::compressed_texture::CompressedImage;
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::memory_report::MemoryCategory;
+use crate::sampler::SamplerConfig;
+
+/// A sampled 2D image or cubemap living in device memory, with the sampler it is meant to be
+/// sampled with.
+pub struct Texture {
+    pub image: Arc<Image>,
+    pub view: Arc<ImageView>,
+    /// From the context's [`SamplerCache`](crate::sampler::SamplerCache), so shared with every
+    /// other texture with the same config.
+    pub sampler: Arc<Sampler>,
+}
+
+impl Texture {
+    /// Uploads tightly packed RGBA8 pixels into a new device-local texture.
+    pub fn from_rgba8(
+        ctx: &VulkanContext,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        sampler: SamplerConfig,
+    ) -> Result<Self, RendererError> {
+        assert_eq!(
+            pixels.len(),
+            (width * height * 4) as usize,
+            "pixel data does not match a {width}x{height} RGBA8 image",
+        );
+        Self::upload(
+            ctx,
+            Format::R8G8B8A8_UNORM,
+            [width, height],
+            false,
+            pixels,
+            sampler,
+        )
+    }
+
+    /// Like [`from_rgba8`](Self::from_rgba8), but for colours stored in sRGB, which are
+    /// converted to linear when sampled.
+    pub fn from_srgba8(
+        ctx: &VulkanContext,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        sampler: SamplerConfig,
+    ) -> Result<Self, RendererError> {
+        assert_eq!(
+            pixels.len(),
+            (width * height * 4) as usize,
+            "pixel data does not match a {width}x{height} RGBA8 image",
+        );
+        Self::upload(
+            ctx,
+            Format::R8G8B8A8_SRGB,
+            [width, height],
+            false,
+            pixels,
+            sampler,
+        )
+    }
+
+    /// Uploads the six `size`x`size` faces of a cubemap, tightly packed sRGB RGBA8 pixels one
+    /// face after the other in the order +X, -X, +Y, -Y, +Z, -Z, into a new device-local
+    /// cubemap.
+    pub fn cubemap_from_srgba8(
+        ctx: &VulkanContext,
+        size: u32,
+        faces: &[u8],
+        sampler: SamplerConfig,
+    ) -> Result<Self, RendererError> {
+        assert_eq!(
+            faces.len(),
+            (size * size * 4 * 6) as usize,
+            "pixel data does not match six {size}x{size} RGBA8 faces",
+        );
+        Self::upload(
+            ctx,
+            Format::R8G8B8A8_SRGB,
+            [size, size],
+            true,
+            faces,
+            sampler,
+        )
+    }
+
+    /// Uploads a block-compressed texture. The blocks are copied as they are if the device can
+    /// sample the format; otherwise they're decoded to RGBA8 first.
+    pub fn from_compressed(
+        ctx: &VulkanContext,
+        image: &CompressedImage,
+        sampler: SamplerConfig,
+    ) -> Result<Self, RendererError> {
+        let extent = [image.width, image.height];
+        if image.format.is_supported(&ctx.caps, image.srgb) {
+            return Self::upload(
+                ctx,
+                image.vulkan_format(),
+                extent,
+                false,
+                &image.data,
+                sampler,
+            );
+        }
+
+        log::warn!(
+            "{:?} textures aren't supported by the device, decoding on the CPU",
+            image.vulkan_format()
+        );
+        let format = if image.srgb {
+            Format::R8G8B8A8_SRGB
+        } else {
+            Format::R8G8B8A8_UNORM
+        };
+        Self::upload(ctx, format, extent, false, &image.decode_rgba8(), sampler)
+    }
+
+    /// Copies `data`, already in the layout of `format`, into a new device-local texture. With
+    /// `cube`, `data` holds six faces, and the texture is sampled as a cubemap.
+    fn upload(
+        ctx: &VulkanContext,
+        format: Format,
+        extent: [u32; 2],
+        cube: bool,
+        data: &[u8],
+        sampler: SamplerConfig,
+    ) -> Result<Self, RendererError> {
+        // The GPU prefers textures in device-local memory that the host usually can't write to,
+        // so the pixels go through a host-visible staging buffer and get copied over.
+        let staging_buffer = Buffer::from_iter(
+            ctx.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            data.iter().copied(),
+        )?;
+
+        let image = Image::new(
+            ctx.memory_allocator.clone(),
+            ImageCreateInfo {
+                flags: if cube {
+                    ImageCreateFlags::CUBE_COMPATIBLE
+                } else {
+                    ImageCreateFlags::empty()
+                },
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [extent[0], extent[1], 1],
+                array_layers: if cube { 6 } else { 1 },
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )?;
+
+        // The copy covers every layer, so the faces land in the layers in the order they come in.
+        ctx.submit_and_wait(|builder| {
+            builder.copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                staging_buffer,
+                image.clone(),
+            ))?;
+            Ok(())
+        })?;
+
+        ctx.memory_tracker
+            .track_image(MemoryCategory::Texture, &image);
+        let view = if cube {
+            ImageView::new(
+                image.clone(),
+                ImageViewCreateInfo {
+                    view_type: ImageViewType::Cube,
+                    ..ImageViewCreateInfo::from_image(&image)
+                },
+            )?
+        } else {
+            ImageView::new_default(image.clone())?
+        };
+        let sampler = ctx.samplers.get(sampler)?;
+
+        Ok(Self {
+            image,
+            view,
+            sampler,
+        })
+    }
+}
+
+/// Generates RGBA8 pixels for a `size`x`size` checkerboard with `cells` squares per side.
+pub fn checkerboard(size: u32, cells: u32, a: [u8; 4], b: [u8; 4]) -> Vec<u8> {
+    let cell_size = (size / cells).max(1);
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let color = if (x / cell_size + y / cell_size).is_multiple_of(2) {
+                a
+            } else {
+                b
+            };
+            pixels.extend_from_slice(&color);
+        }
+    }
+    pixels
+}
+
+/// Decodes a PNG file into RGBA8 pixels, whatever its colour type.
+pub fn read_png(path: &Path) -> Result<([u32; 2], Vec<u8>), String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|err| err.to_string())?;
+    let pixels = &buffer[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => unreachable!("palettes are expanded when decoding"),
+    };
+    Ok(([info.width, info.height], rgba))
+}
@@ -0,0 +1,115 @@
+//! Placing objects by translation, rotation and scale instead of by matrix.
+
+use std::ops::Mul;
+
+use glam::{Mat4, Quat, Vec3};
+
+/// Scales, then rotates, then translates, the way glTF nodes are placed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    pub rotation: Quat,
+    pub scale: [f32; 3],
+}
+
+impl Transform {
+    /// Leaves everything where it is.
+    pub const IDENTITY: Self = Self {
+        translation: [0.0; 3],
+        rotation: Quat::IDENTITY,
+        scale: [1.0; 3],
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation: translation.to_array(),
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Self {
+            rotation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self {
+            scale: scale.to_array(),
+            ..Self::IDENTITY
+        }
+    }
+
+    /// The model matrix doing the same.
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            self.scale.into(),
+            self.rotation,
+            self.translation.into(),
+        )
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.rotation * (Vec3::from(self.scale) * point) + Vec3::from(self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Transform {
+    type Output = Self;
+
+    /// `child` placed by `self`, i.e. `child` first, like multiplying their matrices would.
+    ///
+    /// A scale that differs between axes can't be carried through a rotation, so where `self`'s
+    /// does and `child` is rotated the result is only approximate.
+    fn mul(self, child: Self) -> Self {
+        Self {
+            translation: self.transform_point(child.translation.into()).to_array(),
+            rotation: self.rotation * child.rotation,
+            scale: (Vec3::from(self.scale) * Vec3::from(child.scale)).to_array(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent_and_child() -> (Transform, Transform) {
+        let parent = Transform {
+            translation: [1.0, 2.0, 3.0],
+            rotation: Quat::from_rotation_y(0.5),
+            scale: [2.0; 3],
+        };
+        let child = Transform {
+            translation: [0.0, -1.0, 4.0],
+            rotation: Quat::from_rotation_x(1.2),
+            scale: [0.5, 1.0, 3.0],
+        };
+        (parent, child)
+    }
+
+    #[test]
+    fn transforming_a_point_matches_the_matrix() {
+        let (parent, _) = parent_and_child();
+        let point = Vec3::new(0.3, -0.7, 2.0);
+        assert!(parent
+            .transform_point(point)
+            .abs_diff_eq(parent.matrix().transform_point3(point), 1e-5));
+    }
+
+    #[test]
+    fn composing_matches_multiplying_the_matrices() {
+        let (parent, child) = parent_and_child();
+        assert!((parent * child)
+            .matrix()
+            .abs_diff_eq(parent.matrix() * child.matrix(), 1e-5));
+        assert_eq!(Transform::IDENTITY * child, child);
+    }
+}
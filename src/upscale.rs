@@ -0,0 +1,247 @@
+//! Rendering at a logical resolution different from the window's and scaling the result up.
+//!
+//! The scene is drawn into a transient image of the logical size, with the rest of a
+//! [`ScaledTarget`], which is then blitted onto the swapchain image by [`record_upscale`],
+//! letterboxed if the aspect ratios differ.
+
+use std::sync::Arc;
+
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, BlitImageInfo, ClearColorImageInfo, PrimaryAutoCommandBuffer,
+};
+use vulkano::format::{ClearColorValue, Format};
+use vulkano::image::sampler::Filter;
+use vulkano::image::view::ImageView;
+use vulkano::image::Image;
+use vulkano::render_pass::{Framebuffer, RenderPass};
+
+use crate::context::VulkanContext;
+use crate::error::RendererError;
+use crate::frame_graph::TransientDesc;
+use crate::render_pass::{create_framebuffer, SharedAttachments};
+
+/// Smallest and largest factor `--render-scale` and the runtime keys allow.
+pub const MIN_RENDER_SCALE: f32 = 0.125;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+
+/// How big the image the scene is drawn into should be.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderScale {
+    /// A factor of the window size. `1.0` draws straight into the swapchain.
+    Relative(f32),
+    /// A fixed size, letterboxed to keep its aspect ratio whatever the window does.
+    Fixed([u32; 2]),
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        RenderScale::Relative(1.0)
+    }
+}
+
+impl RenderScale {
+    /// The extent to render the scene at for a window of `window_extent` pixels.
+    pub fn logical_extent(self, window_extent: [u32; 2]) -> [u32; 2] {
+        match self {
+            RenderScale::Relative(scale) => {
+                window_extent.map(|size| ((size as f32 * scale).round() as u32).max(1))
+            }
+            RenderScale::Fixed(extent) => extent,
+        }
+    }
+
+    /// Multiplies a relative scale by `factor`, within the allowed range. Fixed sizes stay fixed.
+    pub fn scaled_by(self, factor: f32) -> Self {
+        match self {
+            RenderScale::Relative(scale) => {
+                RenderScale::Relative((scale * factor).clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE))
+            }
+            fixed => fixed,
+        }
+    }
+}
+
+/// The filter used when scaling the logical image to the window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Blocky, for pixel art.
+    #[default]
+    Nearest,
+    /// Smooth.
+    Linear,
+}
+
+impl UpscaleFilter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(UpscaleFilter::Nearest),
+            "linear" => Some(UpscaleFilter::Linear),
+            _ => None,
+        }
+    }
+
+    pub fn filter(self) -> Filter {
+        match self {
+            UpscaleFilter::Nearest => Filter::Nearest,
+            UpscaleFilter::Linear => Filter::Linear,
+        }
+    }
+}
+
+/// The largest rectangle with the aspect ratio of `source` that fits centred in `target`, as
+/// `(offset, extent)`.
+pub fn letterbox(source: [u32; 2], target: [u32; 2]) -> ([u32; 2], [u32; 2]) {
+    // Compare the aspect ratios without dividing: source is wider if sw / sh > tw / th.
+    let source_wider =
+        u64::from(source[0]) * u64::from(target[1]) > u64::from(target[0]) * u64::from(source[1]);
+    let extent = if source_wider {
+        let height = u64::from(target[0]) * u64::from(source[1]) / u64::from(source[0]);
+        [target[0], (height as u32).max(1)]
+    } else {
+        let width = u64::from(target[1]) * u64::from(source[0]) / u64::from(source[1]);
+        [(width as u32).max(1), target[1]]
+    };
+    let offset = [(target[0] - extent[0]) / 2, (target[1] - extent[1]) / 2];
+    (offset, extent)
+}
+
+/// What the scene needs to be drawn at the logical resolution besides its colour image, which
+/// comes from the frame graph: the depth buffer and multisampled colour, and a framebuffer for
+/// the colour image it was last drawn into.
+pub struct ScaledTarget {
+    extent: [u32; 2],
+    format: Format,
+    shared: SharedAttachments,
+    framebuffer: Option<Arc<Framebuffer>>,
+}
+
+impl ScaledTarget {
+    /// Creates a target for `render_pass`, whose colour attachment has the swapchain's format so
+    /// the scene's pipeline works with either.
+    pub fn new(
+        ctx: &VulkanContext,
+        render_pass: &Arc<RenderPass>,
+        extent: [u32; 2],
+    ) -> Result<Self, RendererError> {
+        Ok(Self {
+            extent,
+            format: render_pass.attachments()[0].format,
+            shared: SharedAttachments::new(ctx, render_pass, extent, "scene")?,
+            framebuffer: None,
+        })
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// The colour image to draw the scene into.
+    pub fn color_desc(&self) -> TransientDesc {
+        TransientDesc {
+            format: self.format,
+            extent: self.extent,
+        }
+    }
+
+    /// A framebuffer for `render_pass` that draws into `color`. The frame graph usually hands
+    /// out the same image frame after frame, so the last one is kept.
+    pub fn framebuffer(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        color: &Arc<Image>,
+    ) -> Result<Arc<Framebuffer>, RendererError> {
+        if let Some(framebuffer) = &self.framebuffer {
+            if Arc::ptr_eq(framebuffer.attachments()[0].image(), color) {
+                return Ok(framebuffer.clone());
+            }
+        }
+        let framebuffer = create_framebuffer(
+            render_pass.clone(),
+            ImageView::new_default(color.clone())?,
+            &self.shared,
+        )?;
+        self.framebuffer = Some(framebuffer.clone());
+        Ok(framebuffer)
+    }
+}
+
+/// Records scaling the rendered `source` onto `target`, which need `TRANSFER_SRC` and
+/// `TRANSFER_DST` usage. Whatever the image doesn't cover is cleared to `clear_color`.
+pub fn record_upscale(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    source: Arc<Image>,
+    target: Arc<Image>,
+    filter: UpscaleFilter,
+    clear_color: [f32; 4],
+) -> Result<(), RendererError> {
+    let source_extent = [source.extent()[0], source.extent()[1]];
+    let target_extent = [target.extent()[0], target.extent()[1]];
+    let (offset, extent) = letterbox(source_extent, target_extent);
+    if extent != target_extent {
+        builder.clear_color_image(ClearColorImageInfo {
+            clear_value: ClearColorValue::Float(clear_color),
+            ..ClearColorImageInfo::image(target.clone())
+        })?;
+    }
+
+    let mut blit = BlitImageInfo {
+        filter: filter.filter(),
+        ..BlitImageInfo::images(source, target)
+    };
+    blit.regions[0].dst_offsets = [
+        [offset[0], offset[1], 0],
+        [offset[0] + extent[0], offset[1] + extent[1], 1],
+    ];
+    builder.blit_image(blit)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_scale_rounds_and_never_hits_zero() {
+        let scale = RenderScale::Relative(0.5);
+        assert_eq!(scale.logical_extent([1280, 721]), [640, 361]);
+        assert_eq!(RenderScale::Relative(0.1).logical_extent([1, 1]), [1, 1]);
+    }
+
+    #[test]
+    fn fixed_scale_ignores_window() {
+        let scale = RenderScale::Fixed([640, 360]);
+        assert_eq!(scale.logical_extent([1920, 1200]), [640, 360]);
+    }
+
+    #[test]
+    fn scaling_is_clamped() {
+        assert_eq!(
+            RenderScale::Relative(1.5).scaled_by(2.0),
+            RenderScale::Relative(MAX_RENDER_SCALE)
+        );
+        assert_eq!(
+            RenderScale::Relative(0.25).scaled_by(0.25),
+            RenderScale::Relative(MIN_RENDER_SCALE)
+        );
+        assert_eq!(
+            RenderScale::Fixed([640, 360]).scaled_by(2.0),
+            RenderScale::Fixed([640, 360])
+        );
+    }
+
+    #[test]
+    fn letterbox_same_aspect_fills_target() {
+        assert_eq!(letterbox([640, 360], [1920, 1080]), ([0, 0], [1920, 1080]));
+    }
+
+    #[test]
+    fn letterbox_adds_bars() {
+        // 16:9 into 16:10 leaves bars above and below.
+        assert_eq!(letterbox([640, 360], [1920, 1200]), ([0, 60], [1920, 1080]));
+        // 4:3 into 16:9 leaves bars left and right.
+        assert_eq!(
+            letterbox([320, 240], [1920, 1080]),
+            ([240, 0], [1440, 1080])
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Running under the validation layer with one of the features that instrument shaders, for
+//! `--shader-printf` and `--gpu-validation`.
+//!
+//! With [`LayerFeature::ShaderPrintf`], a shader opts in with
+//! `#extension GL_EXT_debug_printf : enable`, after which something like
+//! `debugPrintfEXT("uv=%v2f", v_uv)` prints from every invocation that runs it, so guard it to
+//! the ones of interest, e.g. `if (ivec2(gl_FragCoord.xy) == ivec2(640, 360))` for the center
+//! pixel of a 1280x720 window. The source must then be an `r#"…"#` string, for the quotes.
+//! The layer only says which stage printed a message when `VK_LAYER_PRINTF_VERBOSE=1` is set.
+//!
+//! With [`LayerFeature::GpuAssisted`], the layer checks what shaders access on the GPU, e.g.
+//! descriptor indices and storage buffer offsets, which the CPU-side checks can't see.
+//!
+//! Either way the layer reports through the debug messenger created here, which logs it.
+
+use std::sync::Arc;
+
+use vulkano::device::physical::PhysicalDevice;
+use vulkano::instance::debug::{
+    DebugUtilsMessageSeverity, DebugUtilsMessageType, DebugUtilsMessenger,
+    DebugUtilsMessengerCallback, DebugUtilsMessengerCreateInfo, ValidationFeatureEnable,
+};
+use vulkano::instance::{Instance, InstanceCreateInfo};
+use vulkano::VulkanLibrary;
+
+use crate::error::RendererError;
+
+/// The layer implementing both features.
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Where the layer reads the validation features to enable on top of those asked for.
+const LAYER_ENABLES_VAR: &str = "VK_LAYER_ENABLES";
+
+/// What the validation layer is run for. The layer can only do one of them at a time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerFeature {
+    /// Logging what shaders print with `debugPrintfEXT`.
+    ShaderPrintf,
+    /// Validating what shaders access, on the GPU.
+    GpuAssisted,
+}
+
+impl LayerFeature {
+    fn name(self) -> &'static str {
+        match self {
+            Self::ShaderPrintf => "shader printf",
+            Self::GpuAssisted => "GPU-assisted validation",
+        }
+    }
+
+    fn validation_features(self) -> Vec<ValidationFeatureEnable> {
+        match self {
+            Self::ShaderPrintf => vec![ValidationFeatureEnable::DebugPrintf],
+            // The layer binds its own descriptor set, in the last slot unless one is reserved.
+            Self::GpuAssisted => vec![
+                ValidationFeatureEnable::GpuAssisted,
+                ValidationFeatureEnable::GpuAssistedReserveBindingSlot,
+            ],
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Self::ShaderPrintf => Self::GpuAssisted,
+            Self::GpuAssisted => Self::ShaderPrintf,
+        }
+    }
+
+    /// How `VK_LAYER_ENABLES` names the feature.
+    fn layer_enables_name(self) -> &'static str {
+        match self {
+            Self::ShaderPrintf => "DEBUG_PRINTF",
+            Self::GpuAssisted => "GPU_ASSISTED",
+        }
+    }
+}
+
+/// Sets `create_info` up for the validation layer to run `feature`, if the layer is installed.
+/// Returns whether it is.
+///
+/// The feature stays off when `VK_LAYER_ENABLES` turns the other one on, since the layer would
+/// refuse to create the instance.
+pub fn enable(
+    library: &VulkanLibrary,
+    create_info: &mut InstanceCreateInfo,
+    feature: LayerFeature,
+) -> bool {
+    let name = feature.name();
+    let installed = library
+        .layer_properties()
+        .is_ok_and(|mut layers| layers.any(|layer| layer.name() == VALIDATION_LAYER));
+    let layer_extensions = library.supported_layer_extensions(VALIDATION_LAYER);
+    let Some(layer_extensions) = layer_extensions.ok().filter(|_| installed) else {
+        log::warn!("The {name} needs {VALIDATION_LAYER}, which isn't installed");
+        return false;
+    };
+    if !layer_extensions.ext_validation_features {
+        log::warn!("{VALIDATION_LAYER} is too old for {name}");
+        return false;
+    }
+    let other = feature.other();
+    let layer_enables = std::env::var(LAYER_ENABLES_VAR).unwrap_or_default();
+    if layer_enables.contains(other.layer_enables_name()) {
+        log::warn!(
+            "{LAYER_ENABLES_VAR} turns on {}, which can't be combined with {name}, so {name} \
+             stays off",
+            other.name()
+        );
+        return false;
+    }
+
+    create_info.enabled_layers.push(VALIDATION_LAYER.to_owned());
+    create_info.enabled_extensions.ext_validation_features = true;
+    create_info.enabled_extensions.ext_debug_utils = true;
+    create_info
+        .enabled_validation_features
+        .extend(feature.validation_features());
+    log::warn!(
+        "The validation layer instruments every shader for {name}, which makes frames several \
+         times slower"
+    );
+    true
+}
+
+/// Whether [`enable`] set `instance` up for the validation layer.
+pub fn is_enabled(instance: &Instance) -> bool {
+    instance
+        .enabled_layers()
+        .iter()
+        .any(|layer| layer == VALIDATION_LAYER)
+}
+
+/// Warns if `physical_device` lacks the features the layer needs to instrument shaders for
+/// GPU-assisted validation, which it then skips. The layer enables them itself.
+pub fn check_gpu_assisted_features(physical_device: &PhysicalDevice) {
+    let features = physical_device.supported_features();
+    let missing: Vec<_> = [
+        (
+            features.fragment_stores_and_atomics,
+            "fragmentStoresAndAtomics",
+        ),
+        (
+            features.vertex_pipeline_stores_and_atomics,
+            "vertexPipelineStoresAndAtomics",
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(supported, name)| (!supported).then_some(name))
+    .collect();
+    if !missing.is_empty() {
+        log::warn!(
+            "GPU-assisted validation needs {}, which the device lacks, so shaders won't be \
+             validated",
+            missing.join(" and ")
+        );
+    }
+}
+
+/// Logs what shaders print and whatever the validation layer reports, for as long as the
+/// messenger lives.
+pub fn create_messenger(instance: &Arc<Instance>) -> Result<DebugUtilsMessenger, RendererError> {
+    // SAFETY: the callback only logs, without calling into Vulkan.
+    let callback = unsafe {
+        DebugUtilsMessengerCallback::new(|severity, _, data| {
+            let printed = data
+                .message_id_name
+                .is_some_and(|name| name.contains("DEBUG-PRINTF"));
+            if printed {
+                let (stage, text) = printed_message(data.message);
+                log::info!("[{} shader] {text}", stage.unwrap_or("unknown"));
+            } else if severity.intersects(DebugUtilsMessageSeverity::ERROR) {
+                log::error!("{}", data.message);
+            } else if severity.intersects(DebugUtilsMessageSeverity::WARNING) {
+                log::warn!("{}", data.message);
+            } else {
+                log::debug!("{}", data.message);
+            }
+        })
+    };
+    Ok(DebugUtilsMessenger::new(
+        instance.clone(),
+        DebugUtilsMessengerCreateInfo {
+            // The layer reports printed messages as information.
+            message_severity: DebugUtilsMessageSeverity::ERROR
+                | DebugUtilsMessageSeverity::WARNING
+                | DebugUtilsMessageSeverity::INFO,
+            message_type: DebugUtilsMessageType::GENERAL
+                | DebugUtilsMessageType::VALIDATION
+                | DebugUtilsMessageType::PERFORMANCE,
+            ..DebugUtilsMessengerCreateInfo::user_callback(callback)
+        },
+    )?)
+}
+
+/// The stage a debug printf message came from, if the layer said, and what the shader printed,
+/// which is always the message's last line.
+fn printed_message(message: &str) -> (Option<&str>, &str) {
+    let stage = message.split_once("Stage = ").and_then(|(_, rest)| {
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        Some(&rest[..end]).filter(|stage| !stage.is_empty())
+    });
+    let text = message
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default();
+    (stage, text.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printed_messages_are_split_from_the_stage() {
+        assert_eq!(printed_message("uv=0.50, 0.50"), (None, "uv=0.50, 0.50"));
+        let verbose = "Command buffer (0x1). Draw Index 0. Pipeline (0x2). Shader Module (0x3). \
+                       Shader Instruction Index = 92. Stage = Fragment.  Fragment coord (x,y) = \
+                       (640.5, 360.5).\n\nuv=0.50, 0.50\n";
+        assert_eq!(
+            printed_message(verbose),
+            (Some("Fragment"), "uv=0.50, 0.50")
+        );
+    }
+}
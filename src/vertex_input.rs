@@ -0,0 +1,183 @@
+//! Vertex data that doesn't come from a Rust vertex type.
+//!
+//! Scenes normally derive `Vertex` on a struct and let vulkano work out the vertex input from it.
+//! Meshes loaded from precompiled blobs are just bytes, so here the buffer is created straight
+//! from a byte slice and the layout is described by hand.
+
+use std::sync::Arc;
+
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::format::Format;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator};
+use vulkano::pipeline::graphics::vertex_input::{
+    VertexInputAttributeDescription, VertexInputBindingDescription, VertexInputRate,
+    VertexInputState,
+};
+
+use crate::error::RendererError;
+
+/// Uploads raw, interleaved vertex data into a vertex buffer.
+///
+/// `bytes` must hold a whole number of vertices of `stride` bytes each. Typed data can be turned
+/// into bytes with `bytemuck::cast_slice`.
+pub fn vertex_buffer_from_bytes(
+    allocator: Arc<StandardMemoryAllocator>,
+    bytes: &[u8],
+    stride: usize,
+) -> Result<Subbuffer<[u8]>, RendererError> {
+    vertex_count(bytes.len(), stride)?;
+
+    let buffer = Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        bytes.iter().copied(),
+    )?;
+
+    Ok(buffer)
+}
+
+/// Number of `stride`-sized vertices in `len` bytes, or an error if they don't divide evenly.
+pub fn vertex_count(len: usize, stride: usize) -> Result<u32, RendererError> {
+    if stride == 0 || !len.is_multiple_of(stride) {
+        return Err(RendererError::InvalidVertexData(format!(
+            "{len} bytes is not a whole number of {stride}-byte vertices"
+        )));
+    }
+    Ok((len / stride) as u32)
+}
+
+/// One shader input read from each vertex.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VertexAttribute {
+    /// The `layout(location = ...)` of the shader input.
+    pub location: u32,
+    pub format: Format,
+    /// Byte offset of the attribute from the start of the vertex.
+    pub offset: u32,
+}
+
+/// A hand-written description of interleaved vertex data in a single per-vertex binding.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexLayout {
+    /// Size of one vertex in bytes.
+    pub stride: u32,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new(stride: u32) -> Self {
+        Self {
+            stride,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Adds an attribute, builder style.
+    pub fn attribute(mut self, location: u32, format: Format, offset: u32) -> Self {
+        self.attributes.push(VertexAttribute {
+            location,
+            format,
+            offset,
+        });
+        self
+    }
+
+    /// Builds the pipeline's vertex input state, reading everything from binding 0.
+    ///
+    /// Checks that every attribute fits inside a vertex and that no location is used twice,
+    /// since getting either wrong reads garbage rather than failing loudly.
+    pub fn vertex_input_state(&self) -> Result<VertexInputState, RendererError> {
+        for (i, attribute) in self.attributes.iter().enumerate() {
+            let end = u64::from(attribute.offset) + attribute.format.block_size();
+            if end > u64::from(self.stride) {
+                return Err(RendererError::InvalidVertexData(format!(
+                    "attribute at location {} ({:?} at offset {}) ends past the {}-byte stride",
+                    attribute.location, attribute.format, attribute.offset, self.stride,
+                )));
+            }
+            if self.attributes[..i]
+                .iter()
+                .any(|other| other.location == attribute.location)
+            {
+                return Err(RendererError::InvalidVertexData(format!(
+                    "location {} is used by more than one attribute",
+                    attribute.location,
+                )));
+            }
+        }
+
+        Ok(VertexInputState::new()
+            .binding(
+                0,
+                VertexInputBindingDescription {
+                    stride: self.stride,
+                    input_rate: VertexInputRate::Vertex,
+                },
+            )
+            .attributes(self.attributes.iter().map(|attribute| {
+                (
+                    attribute.location,
+                    VertexInputAttributeDescription {
+                        binding: 0,
+                        format: attribute.format,
+                        offset: attribute.offset,
+                    },
+                )
+            })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Position and colour, as a mesh blob might store them.
+    fn position_color_layout() -> VertexLayout {
+        VertexLayout::new(24)
+            .attribute(0, Format::R32G32B32_SFLOAT, 0)
+            .attribute(1, Format::R32G32B32_SFLOAT, 12)
+    }
+
+    #[test]
+    fn vertex_count_of_cast_slice() {
+        let vertices: [[f32; 6]; 3] = [[0.0; 6]; 3];
+        let bytes: &[u8] = bytemuck::cast_slice(&vertices);
+        assert_eq!(vertex_count(bytes.len(), 24).unwrap(), 3);
+    }
+
+    #[test]
+    fn vertex_count_rejects_partial_vertices() {
+        assert!(vertex_count(25, 24).is_err());
+        assert!(vertex_count(24, 0).is_err());
+    }
+
+    #[test]
+    fn layout_describes_binding_and_attributes() {
+        let state = position_color_layout().vertex_input_state().unwrap();
+        assert_eq!(state.bindings[&0].stride, 24);
+        assert_eq!(state.attributes[&1].offset, 12);
+        assert_eq!(state.attributes[&1].format, Format::R32G32B32_SFLOAT);
+    }
+
+    #[test]
+    fn layout_rejects_attribute_past_stride() {
+        let layout = position_color_layout().attribute(2, Format::R32G32_SFLOAT, 20);
+        assert!(layout.vertex_input_state().is_err());
+    }
+
+    #[test]
+    fn layout_rejects_duplicate_locations() {
+        let layout = VertexLayout::new(16)
+            .attribute(0, Format::R32G32_SFLOAT, 0)
+            .attribute(0, Format::R32G32_SFLOAT, 8);
+        assert!(layout.vertex_input_state().is_err());
+    }
+}
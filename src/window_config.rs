@@ -0,0 +1,133 @@
+//! How the demo's windows look apart from what is drawn in them: their transparency, icon and
+//! cursor.
+
+use std::path::Path;
+
+use winit::error::OsError;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::{CursorIcon, Icon, Window, WindowBuilder};
+
+use crate::texture::read_png;
+
+/// The cursor shown over the windows while it isn't captured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CursorStyle {
+    /// Whatever the platform shows.
+    #[default]
+    Default,
+    Hidden,
+    /// One of the platform's own cursors. winit can't show cursors from images.
+    Icon(CursorIcon),
+}
+
+impl CursorStyle {
+    /// The names `--cursor` takes, in the order the help lists them.
+    pub const NAMES: [(&'static str, CursorStyle); 7] = [
+        ("default", CursorStyle::Default),
+        ("hidden", CursorStyle::Hidden),
+        ("crosshair", CursorStyle::Icon(CursorIcon::Crosshair)),
+        ("hand", CursorStyle::Icon(CursorIcon::Hand)),
+        ("arrow", CursorStyle::Icon(CursorIcon::Arrow)),
+        ("move", CursorStyle::Icon(CursorIcon::Move)),
+        ("text", CursorStyle::Icon(CursorIcon::Text)),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .into_iter()
+            .find_map(|(cursor_name, cursor)| (cursor_name == name).then_some(cursor))
+    }
+
+    pub fn is_visible(self) -> bool {
+        self != CursorStyle::Hidden
+    }
+
+    /// Shows the cursor over `window`.
+    pub fn apply(self, window: &Window) {
+        window.set_cursor_icon(match self {
+            CursorStyle::Icon(icon) => icon,
+            CursorStyle::Default | CursorStyle::Hidden => CursorIcon::Default,
+        });
+        window.set_cursor_visible(self.is_visible());
+    }
+}
+
+/// What every window of the demo is created with. Each gets its own title.
+#[derive(Clone, Debug, Default)]
+pub struct WindowConfig {
+    transparent: bool,
+    icon: Option<Icon>,
+    cursor: CursorStyle,
+}
+
+impl WindowConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets the desktop show through wherever nothing is drawn, where the compositor supports it.
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Uses the PNG at `path` as the windows' icon, where the platform shows one. If it can't be
+    /// loaded, the windows keep the default icon and the reason is logged.
+    pub fn icon(mut self, path: &Path) -> Self {
+        match load_icon(path) {
+            Ok(icon) => self.icon = Some(icon),
+            Err(err) => log::warn!(
+                "Could not load the window icon {}, using the default: {err}",
+                path.display()
+            ),
+        }
+        self
+    }
+
+    pub fn cursor(mut self, cursor: CursorStyle) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    /// Opens a window titled `title`.
+    pub fn build<T>(
+        &self,
+        title: &str,
+        event_loop: &EventLoopWindowTarget<T>,
+    ) -> Result<Window, OsError> {
+        let window = WindowBuilder::new()
+            .with_title(title)
+            .with_transparent(self.transparent)
+            .with_window_icon(self.icon.clone())
+            .build(event_loop)?;
+        self.cursor.apply(&window);
+        Ok(window)
+    }
+}
+
+fn load_icon(path: &Path) -> Result<Icon, String> {
+    let ([width, height], rgba) = read_png(path)?;
+    Icon::from_rgba(rgba, width, height).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursors_by_name() {
+        assert_eq!(
+            CursorStyle::from_name("crosshair"),
+            Some(CursorStyle::Icon(CursorIcon::Crosshair))
+        );
+        assert_eq!(CursorStyle::from_name("hidden"), Some(CursorStyle::Hidden));
+        assert!(!CursorStyle::Hidden.is_visible());
+        assert_eq!(CursorStyle::from_name("pointer"), None);
+    }
+
+    #[test]
+    fn unloadable_icons_fall_back_to_the_default() {
+        let config = WindowConfig::new().icon(Path::new("no/such/icon.png"));
+        assert!(config.icon.is_none());
+    }
+}
@@ -0,0 +1,1152 @@
+//! Everything that belongs to one window: its surface and swapchain, the targets the scene is
+//! drawn into for it, its frames in flight and the cameras it is viewed with.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use glam::Mat4;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo, SubpassBeginInfo,
+    SubpassContents, SubpassEndInfo,
+};
+use vulkano::device::Queue;
+use vulkano::format::FormatFeatures;
+use vulkano::image::view::ImageView;
+use vulkano::image::{Image, ImageUsage};
+use vulkano::pipeline::graphics::viewport::Scissor;
+use vulkano::render_pass::{Framebuffer, RenderPass, Subpass};
+use vulkano::swapchain::{
+    self, CompositeAlpha, PresentMode, Surface, Swapchain, SwapchainCreateInfo,
+    SwapchainPresentInfo,
+};
+use vulkano::sync::{self, GpuFuture};
+use vulkano::{Validated, VulkanError};
+use winit::dpi::PhysicalPosition;
+use winit::window::{Fullscreen, Window};
+
+use crate::camera::Camera;
+use crate::clear_color::ClearColor;
+use crate::console::ConsoleOverlay;
+use crate::context::VulkanContext;
+use crate::crash_report;
+use crate::culling::CullStats;
+use crate::draw_cache::DrawCache;
+use crate::error::RendererError;
+#[cfg(windows)]
+use crate::exclusive_fullscreen::ExclusiveFullscreen;
+use crate::frame_commands::FrameCommandPools;
+use crate::frame_graph::{FrameGraph, ImageAccess, Pass, TransientPool};
+use crate::frame_pacing::FramePacer;
+use crate::minimap::{minimap_camera, Minimap};
+use crate::options::Options;
+use crate::picking::Ray;
+use crate::render_pass::{clear_values, create_framebuffer, SharedAttachments};
+use crate::scene::{
+    BatchStats, FrameData, Scene, FRAMES_IN_FLIGHT, TRANSPARENT_CLEAR_COLOR, VIEWS_PER_WINDOW,
+};
+use crate::screenshot::PendingScreenshot;
+use crate::staging::SubmitFence;
+use crate::stereo::{
+    create_multiview_render_pass, eye_areas, eye_views, record_composite, StereoTarget,
+    EYE_SEPARATION,
+};
+use crate::surface_config::SurfaceConfig;
+use crate::upscale::{letterbox, record_upscale, RenderScale, ScaledTarget, UpscaleFilter};
+
+/// How long to wait for the compositor to hand back a swapchain image before skipping the frame.
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// After this many acquires in a row have timed out, the swapchain is recreated in case it is
+/// what's stuck.
+const ACQUIRE_TIMEOUTS_BEFORE_RECREATE: u32 = 3;
+
+/// The view the minimap is drawn as, after the one or two of the window itself.
+const MINIMAP_VIEW: usize = VIEWS_PER_WINDOW - 1;
+
+/// One window the scene is drawn into.
+///
+/// The same drop-order rules as for [`Renderer`](crate::renderer::Renderer) apply: per-frame state
+/// first, then the targets, then the swapchain and window. [`Drop`] waits for the queue first,
+/// so closing one window never frees anything another window's frames are still using.
+pub struct WindowContext {
+    /// Signalled when the GPU finishes the last frame submitted in each frame-in-flight slot.
+    frame_fences: Vec<Option<Arc<SubmitFence>>>,
+    /// The command buffers of each slot's frames, reset along with the slot.
+    command_pools: FrameCommandPools,
+    /// The scene's draws as recorded for each view and slot, reused while they still apply.
+    draw_cache: DrawCache,
+    /// How long recording the last frame's command buffer took on the CPU.
+    record_time: Duration,
+    /// How many of the scene's nodes the last frame drew and skipped, over all its views.
+    cull_stats: Option<CullStats>,
+    /// What the last frame's draws bound, over all its views.
+    batch_stats: Option<BatchStats>,
+    /// Number of frames submitted so far.
+    frame_count: usize,
+    /// Where to save the next frame, if a screenshot was asked for.
+    screenshot: Option<PathBuf>,
+    pacer: FramePacer,
+    recreate_swapchain: bool,
+    recreate_targets: bool,
+    /// How many acquires in a row have timed out.
+    acquire_timeouts: u32,
+    /// Where the scene is drawn when it renders at a different resolution from the window.
+    scaled_target: Option<ScaledTarget>,
+    /// Where both eyes are drawn at once, while `stereo_render_pass` is set and the scene is
+    /// drawn at the window's resolution.
+    stereo_target: Option<StereoTarget>,
+    /// Draws both eyes in one pass, with `--stereo` where the device has multiview and the
+    /// swapchain images can be blitted to.
+    stereo_render_pass: Option<Arc<RenderPass>>,
+    /// The images the frame graph hands out for each frame's transients.
+    transient_images: TransientPool,
+    /// One per swapchain image when the scene is drawn straight into the swapchain, otherwise
+    /// empty.
+    framebuffers: Vec<Arc<Framebuffer>>,
+    /// One per swapchain image when they are storage images, for scenes that compute the whole
+    /// frame to write into them, otherwise empty.
+    storage_views: Vec<Arc<ImageView>>,
+    images: Vec<Arc<Image>>,
+    swapchain: Arc<Swapchain>,
+    window: Arc<Window>,
+    /// The camera this window shows the scene through, or the left half of it when split.
+    pub camera: Camera,
+    /// When set, the window is split in two, with the scene seen through this camera on the
+    /// right.
+    pub split_camera: Option<Camera>,
+    /// A top-down view of the scene around `camera` in a corner, while shown.
+    pub minimap: Minimap,
+    /// Set with `--stereo`: the scene is seen through `camera` once per eye, side by side,
+    /// instead of through the split camera.
+    stereo: bool,
+    /// Which sets of the scene's per-frame resources this window's views use. Each view gets its
+    /// own, because the scene is drawn with a different camera in each.
+    window_index: usize,
+    render_scale: RenderScale,
+    upscale_filter: UpscaleFilter,
+    /// Whether the swapchain images can be blitted to, e.g. from what a compute scene computed.
+    blit_supported: bool,
+    /// Whether the swapchain images can be blitted from and to. Without it the scene always
+    /// renders at the window's resolution.
+    upscale_supported: bool,
+    /// Transparent where nothing is drawn, for windows the desktop shows through.
+    clear_color: ClearColor,
+    /// Set with `--exclusive-fullscreen` where the device supports it.
+    #[cfg(windows)]
+    exclusive_fullscreen: Option<ExclusiveFullscreen>,
+    queue: Arc<Queue>,
+}
+
+impl WindowContext {
+    /// Creates a swapchain for `window` whose images match `render_pass`, so the scene's
+    /// pipelines can draw into any window. The present mode and render scale come from
+    /// `options`.
+    pub(crate) fn new(
+        ctx: &VulkanContext,
+        render_pass: &Arc<RenderPass>,
+        window: Arc<Window>,
+        surface: Arc<Surface>,
+        camera: Camera,
+        window_index: usize,
+        options: &Options,
+    ) -> Result<Self, RendererError> {
+        let physical_device = ctx.device.physical_device();
+        if !physical_device.surface_support(ctx.queue.queue_family_index(), &surface)? {
+            return Err(RendererError::IncompatibleWindow(
+                "the device can't present to it".to_owned(),
+            ));
+        }
+
+        let surface_config = SurfaceConfig::query(physical_device, &surface)?;
+        let format = render_pass.attachments()[0].format;
+        let (image_format, image_color_space) =
+            surface_config.format_with(format).ok_or_else(|| {
+                RendererError::IncompatibleWindow(format!(
+                    "its surface doesn't support the {format:?} format the scene renders in"
+                ))
+            })?;
+        let mut create_info = SwapchainCreateInfo {
+            image_format,
+            image_color_space,
+            ..surface_config.swapchain_create_info(
+                window.inner_size().into(),
+                options.present_mode,
+                options.swapchain_images,
+                options.transparent,
+            )
+        };
+        if options.compute_present {
+            let storage = surface_config.storage_usage(ctx.caps.format_features(image_format));
+            if storage.is_empty() || !ctx.caps.storage_write_without_format {
+                log::warn!(
+                    "The swapchain images can't be written from compute shaders, so compute \
+                     scenes are blitted to them"
+                );
+            } else {
+                create_info.image_usage |= storage;
+            }
+        }
+        #[cfg(windows)]
+        let exclusive_fullscreen = ExclusiveFullscreen::new(ctx, options);
+        #[cfg(windows)]
+        if let Some(exclusive) = &exclusive_fullscreen {
+            exclusive.configure(&mut create_info, &window);
+        }
+        let (swapchain, images) = Swapchain::new(ctx.device.clone(), surface, create_info)?;
+        let storage_views = create_storage_views(&swapchain, &images)?;
+        if let Some(requested) = options.swapchain_images {
+            let allowed = surface_config.image_count(Some(requested));
+            if allowed != requested {
+                log::warn!(
+                    "The surface doesn't allow {requested} swapchain images, asking for {allowed}"
+                );
+            }
+        }
+        log::info!(
+            "Swapchain: {} images, {:?}",
+            swapchain.image_count(),
+            swapchain.present_mode()
+        );
+
+        name_swapchain(ctx, window_index, &swapchain, &images);
+        crash_report::set_swapchain(window_index, &swapchain);
+        log::info!("Composite alpha: {:?}", swapchain.composite_alpha());
+        // Every scene writes opaque colours, which are the same pre-multiplied or not, so only
+        // the clear colour depends on the composite alpha.
+        let transparent = matches!(
+            swapchain.composite_alpha(),
+            CompositeAlpha::PreMultiplied | CompositeAlpha::PostMultiplied
+        );
+        if options.transparent && !transparent {
+            log::warn!(
+                "The surface doesn't support blending with the desktop, so the window is opaque"
+            );
+        }
+        let clear_color = if transparent {
+            ClearColor::fixed(TRANSPARENT_CLEAR_COLOR)
+        } else {
+            options.clear_color
+        };
+
+        let format_features = ctx.caps.format_features(swapchain.image_format());
+        let blit_supported = swapchain.image_usage().intersects(ImageUsage::TRANSFER_DST)
+            && format_features.intersects(FormatFeatures::BLIT_DST);
+        let upscale_supported =
+            blit_supported && format_features.intersects(FormatFeatures::BLIT_SRC);
+        if options.render_scale != RenderScale::default() && !upscale_supported {
+            log::warn!("The swapchain can't be blitted to, so ignoring the render scale");
+        }
+        let mut upscale_filter = options.upscale_filter;
+        if upscale_filter == UpscaleFilter::Linear
+            && !format_features.intersects(FormatFeatures::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            log::warn!("Linear filtering isn't supported for the swapchain format, using nearest");
+            upscale_filter = UpscaleFilter::Nearest;
+        }
+
+        let stereo_render_pass = if !options.stereo {
+            None
+        } else if ctx.caps.multiview && upscale_supported {
+            log::info!("Stereo: drawing both eyes in one pass with multiview");
+            Some(create_multiview_render_pass(
+                ctx.device.clone(),
+                swapchain.image_format(),
+            )?)
+        } else {
+            log::warn!(
+                "Multiview isn't supported or the swapchain can't be blitted, so stereo draws \
+                 the scene once per eye"
+            );
+            None
+        };
+
+        if options.frame_latency.is_some() && !ctx.caps.present_wait {
+            log::warn!("Present waits aren't supported, so ignoring the frame latency");
+        }
+
+        let mut context = Self {
+            frame_fences: (0..FRAMES_IN_FLIGHT).map(|_| None).collect(),
+            command_pools: FrameCommandPools::new(ctx),
+            draw_cache: DrawCache::new(ctx, options),
+            record_time: Duration::ZERO,
+            cull_stats: None,
+            batch_stats: None,
+            frame_count: 0,
+            screenshot: None,
+            pacer: FramePacer::new(ctx.caps.present_wait, options.frame_latency),
+            recreate_swapchain: false,
+            recreate_targets: false,
+            acquire_timeouts: 0,
+            scaled_target: None,
+            stereo_target: None,
+            stereo_render_pass,
+            transient_images: TransientPool::new(),
+            framebuffers: Vec::new(),
+            storage_views,
+            images,
+            swapchain,
+            window,
+            camera,
+            split_camera: None,
+            minimap: Minimap::new(options.minimap),
+            stereo: options.stereo,
+            window_index,
+            render_scale: options.render_scale,
+            upscale_filter,
+            blit_supported,
+            upscale_supported,
+            clear_color,
+            #[cfg(windows)]
+            exclusive_fullscreen,
+            queue: ctx.queue.clone(),
+        };
+        context.create_targets(ctx, render_pass)?;
+        Ok(context)
+    }
+
+    pub fn window(&self) -> &Arc<Window> {
+        &self.window
+    }
+
+    pub(crate) fn surface(&self) -> &Arc<Surface> {
+        self.swapchain.surface()
+    }
+
+    pub(crate) fn window_index(&self) -> usize {
+        self.window_index
+    }
+
+    /// The size of the images the scene is drawn into: the logical resolution when rendering at
+    /// a different scale, otherwise the size of the images being presented.
+    pub fn extent(&self) -> [u32; 2] {
+        match &self.scaled_target {
+            Some(target) => target.extent(),
+            None => self.swapchain.image_extent(),
+        }
+    }
+
+    /// Physical pixels per logical pixel on the monitor the window is on, for drawing UI at the
+    /// monitor's pixel density. Everything else works in physical pixels throughout.
+    pub fn scale_factor(&self) -> f64 {
+        self.window.scale_factor()
+    }
+
+    /// Marks the swapchain as stale, e.g. after the window was resized. It is recreated at the
+    /// start of the next frame.
+    pub fn resize(&mut self) {
+        self.recreate_swapchain = true;
+    }
+
+    /// Switches the window between borderless fullscreen on its current monitor and windowed.
+    /// With `--exclusive-fullscreen` on Windows, the swapchain also holds exclusive fullscreen
+    /// while the window is fullscreen.
+    pub fn toggle_fullscreen(&mut self) {
+        let fullscreen = self.window.fullscreen().is_none();
+        #[cfg(windows)]
+        if let Some(exclusive) = &mut self.exclusive_fullscreen {
+            if exclusive.set_fullscreen(&self.swapchain, fullscreen) {
+                self.recreate_swapchain = true;
+            }
+        }
+        self.window
+            .set_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
+    }
+
+    /// Saves the next frame this window presents to `path`, as a PNG.
+    pub fn request_screenshot(&mut self, path: PathBuf) {
+        if self
+            .swapchain
+            .image_usage()
+            .intersects(ImageUsage::TRANSFER_SRC)
+        {
+            self.screenshot = Some(path);
+        } else {
+            log::warn!("The swapchain images can't be copied from, so no screenshot");
+        }
+    }
+
+    /// How many images the driver gave the swapchain, which may be more than were asked for.
+    pub fn swapchain_image_count(&self) -> u32 {
+        self.swapchain.image_count()
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.swapchain.present_mode()
+    }
+
+    /// How many frames may be queued at once: [`FRAMES_IN_FLIGHT`], or fewer when there aren't
+    /// enough swapchain images for that many to be drawn while one is on screen.
+    fn frames_in_flight(&self) -> usize {
+        (self.images.len() - 1).clamp(1, FRAMES_IN_FLIGHT)
+    }
+
+    /// How long frames recently took from being queued to reaching the screen, if the device can
+    /// tell.
+    pub fn present_latency(&self) -> Option<Duration> {
+        self.pacer.latency()
+    }
+
+    /// How long the CPU took to record the last frame's commands, scene draws included.
+    pub fn record_time(&self) -> Duration {
+        self.record_time
+    }
+
+    /// The nodes the last frame drew and culled, if the scene culls them.
+    pub fn cull_stats(&self) -> Option<CullStats> {
+        self.cull_stats
+    }
+
+    /// What the last frame's draws bound, if the scene batches them.
+    pub fn batch_stats(&self) -> Option<BatchStats> {
+        self.batch_stats
+    }
+
+    /// The latencies measured since the last call, for collecting statistics over a run. Empty
+    /// without present waits.
+    pub fn take_present_latencies(&mut self) -> Vec<Duration> {
+        self.pacer.take_latencies()
+    }
+
+    /// The ray through the scene under `cursor`, in physical pixels like winit reports it, so
+    /// it matches the swapchain whatever the window's DPI scale factor. `None` if the cursor is
+    /// on a letterbox bar.
+    pub fn pick_ray(&self, cursor: PhysicalPosition<f64>) -> Option<Ray> {
+        // The swapchain lags behind resizes and scale factor changes by a frame, and is
+        // stretched over the window until then.
+        let window_size: [u32; 2] = self.window.inner_size().into();
+        let swapchain_extent = self.swapchain.image_extent();
+        let cursor = [
+            cursor.x * swapchain_extent[0] as f64 / window_size[0].max(1) as f64,
+            cursor.y * swapchain_extent[1] as f64 / window_size[1].max(1) as f64,
+        ];
+
+        // Undo the blit from the logical resolution to the window, if there is one.
+        let (offset, extent) = letterbox(self.extent(), swapchain_extent);
+        let to_target = |axis: usize| {
+            (cursor[axis] - offset[axis] as f64) * self.extent()[axis] as f64 / extent[axis] as f64
+        };
+        let pixel = [to_target(0).floor(), to_target(1).floor()];
+
+        let (view_frame, scissor) =
+            self.views(&FrameData::default(), 0)
+                .into_iter()
+                .find(|(_, scissor)| {
+                    (0..2).all(|axis| {
+                        let start = scissor.offset[axis] as f64;
+                        (start..start + scissor.extent[axis] as f64).contains(&pixel[axis])
+                    })
+                })?;
+        Some(Ray::through_pixel(
+            [
+                (pixel[0] - scissor.offset[0] as f64) as f32,
+                (pixel[1] - scissor.offset[1] as f64) as f32,
+            ],
+            scissor.extent,
+            view_frame.view,
+            view_frame.projection,
+        ))
+    }
+
+    pub fn render_scale(&self) -> RenderScale {
+        self.render_scale
+    }
+
+    /// Changes the logical resolution. The render targets are recreated at the start of the next
+    /// frame.
+    pub fn set_render_scale(&mut self, render_scale: RenderScale) {
+        self.render_scale = render_scale;
+        self.recreate_targets = true;
+    }
+
+    /// Acquires a swapchain image, draws `scene` into it once per view, then the minimap and
+    /// `console` over it while shown, and queues it for presentation.
+    ///
+    /// Up to [`FRAMES_IN_FLIGHT`] frames may be queued at once, or one less than there are
+    /// swapchain images; this blocks until the oldest one has finished before starting another.
+    /// The camera matrices and `frame_in_flight` of `frame` are filled in here for each view.
+    ///
+    /// Returns `false` if no frame was drawn, e.g. because the window is minimized, the
+    /// swapchain had to be recreated first or no image was free within [`ACQUIRE_TIMEOUT`].
+    pub(crate) fn render(
+        &mut self,
+        ctx: &VulkanContext,
+        render_pass: &Arc<RenderPass>,
+        scene: &mut dyn Scene,
+        console: &mut ConsoleOverlay,
+        frame: &FrameData,
+    ) -> Result<bool, RendererError> {
+        // Don't draw while minimized: the swapchain can't be zero-sized.
+        let window_size = self.window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 {
+            return Ok(false);
+        }
+
+        let slot = self.frame_count % FRAMES_IN_FLIGHT;
+
+        // Wait for the frame that last used this slot, so the scene can overwrite its uniforms.
+        // With fewer frames in flight than slots, that's a more recent frame than the slot's
+        // last, and frames finish in order, so the slot's is done too. Waiting on the slot's own
+        // fence as well cleans its frame up, which drops the command buffer so the slot's pool
+        // can be reset.
+        let oldest_slot = (slot + FRAMES_IN_FLIGHT - self.frames_in_flight()) % FRAMES_IN_FLIGHT;
+        for fence in [oldest_slot, slot]
+            .into_iter()
+            .filter_map(|slot| self.frame_fences[slot].as_ref())
+        {
+            fence.wait(None)?;
+        }
+        self.command_pools.reset(ctx, slot);
+
+        if self.recreate_swapchain {
+            // The allowed extents change along with the window, so ask the surface again. The
+            // rest of the create info, composite alpha included, stays as first chosen.
+            let surface_config =
+                SurfaceConfig::query(ctx.device.physical_device(), self.swapchain.surface())?;
+            // The surface has the final say where it reports a `current_extent`, which needn't
+            // match the window's size, e.g. under fractional scaling.
+            let image_extent = surface_config.clamp_extent(window_size.into());
+            if image_extent != <[u32; 2]>::from(window_size) {
+                log::debug!(
+                    "The surface is {}x{} for a {}x{} window",
+                    image_extent[0],
+                    image_extent[1],
+                    window_size.width,
+                    window_size.height
+                );
+            }
+            // Some platforms report a zero extent while minimizing, whatever the window's size.
+            // Try again next frame.
+            if image_extent.contains(&0) {
+                return Ok(false);
+            }
+            #[allow(unused_mut)]
+            let mut create_info = SwapchainCreateInfo {
+                image_extent,
+                ..self.swapchain.create_info()
+            };
+            // The window may have moved to another monitor, or lost exclusivity.
+            #[cfg(windows)]
+            if let Some(exclusive) = &self.exclusive_fullscreen {
+                exclusive.configure(&mut create_info, &self.window);
+            }
+            let (new_swapchain, new_images) = self.swapchain.recreate(create_info)?;
+            name_swapchain(ctx, self.window_index, &new_swapchain, &new_images);
+            crash_report::set_swapchain(self.window_index, &new_swapchain);
+            if new_swapchain.image_count() != self.swapchain.image_count() {
+                log::info!("Swapchain now has {} images", new_swapchain.image_count());
+            }
+            self.storage_views = create_storage_views(&new_swapchain, &new_images)?;
+            self.swapchain = new_swapchain;
+            self.images = new_images;
+            self.framebuffers.clear();
+            self.pacer.swapchain_recreated();
+            self.recreate_swapchain = false;
+            self.recreate_targets = true;
+        }
+        if self.recreate_targets {
+            self.create_targets(ctx, render_pass)?;
+            self.recreate_targets = false;
+        }
+
+        #[cfg(windows)]
+        if let Some(exclusive) = &mut self.exclusive_fullscreen {
+            if !exclusive.update(&self.swapchain) {
+                self.recreate_swapchain = true;
+                return Ok(false);
+            }
+        }
+
+        self.pacer.wait(&self.swapchain)?;
+
+        let (image_index, suboptimal, acquire_future) =
+            match swapchain::acquire_next_image(self.swapchain.clone(), Some(ACQUIRE_TIMEOUT))
+                .map_err(Validated::unwrap)
+            {
+                Ok(r) => r,
+                Err(VulkanError::OutOfDate) => {
+                    self.recreate_swapchain = true;
+                    return Ok(false);
+                }
+                // Rather than freezing along with a stalled compositor, skip the frame and keep
+                // handling input.
+                Err(VulkanError::Timeout | VulkanError::NotReady) => {
+                    self.acquire_timeouts += 1;
+                    log::warn!(
+                        "No swapchain image after {ACQUIRE_TIMEOUT:?}, skipping the frame ({} in \
+                         a row)",
+                        self.acquire_timeouts
+                    );
+                    if self.acquire_timeouts >= ACQUIRE_TIMEOUTS_BEFORE_RECREATE {
+                        log::warn!("Recreating the swapchain after repeated acquire timeouts");
+                        self.recreate_swapchain = true;
+                        self.acquire_timeouts = 0;
+                    }
+                    return Ok(false);
+                }
+                #[cfg(windows)]
+                Err(VulkanError::FullScreenExclusiveModeLost) => {
+                    self.exclusive_fullscreen_lost();
+                    return Ok(false);
+                }
+                Err(e) => return Err(Validated::Error(e).into()),
+            };
+
+        self.acquire_timeouts = 0;
+
+        // The image is still usable but no longer matches the surface exactly, so recreate the
+        // swapchain next frame.
+        if suboptimal {
+            self.recreate_swapchain = true;
+        }
+
+        // Both eyes at once where the scene can draw them that way. Anything else is drawn once
+        // per view, each eye a view of its own when stereo.
+        let mut stereo_frame = None;
+        if let Some(target) = self
+            .stereo_target
+            .as_ref()
+            .filter(|_| !console.is_visible())
+        {
+            let (eyes_frame, eye_views) = self.stereo_frame(frame, slot, target.extent());
+            if scene.prepare_stereo(&eyes_frame, eye_views)? {
+                stereo_frame = Some(eyes_frame);
+            }
+        }
+        let views = match stereo_frame {
+            Some(_) => Vec::new(),
+            None => self.views(frame, slot),
+        };
+        let minimap_view = self.minimap_view(frame, slot);
+        for (view_frame, _) in views.iter().chain(&minimap_view) {
+            scene.prepare(view_frame)?;
+        }
+        // Only what the window itself shows; the minimap sees most of the scene anyway.
+        self.cull_stats = views
+            .iter()
+            .filter_map(|(view_frame, _)| scene.cull_stats(view_frame))
+            .reduce(|mut total, view| {
+                total += view;
+                total
+            });
+        self.batch_stats = views
+            .iter()
+            .filter_map(|(view_frame, _)| scene.batch_stats(view_frame))
+            .reduce(|mut total, view| {
+                total += view;
+                total
+            });
+        let scene: &dyn Scene = scene;
+
+        let record_start = Instant::now();
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_pools.allocator(ctx, slot),
+            ctx.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+        // A scene computing the whole frame writes it straight into the swapchain image where
+        // that is a storage image, or else has an image of itself blitted over the target where
+        // that works, and then nothing is drawn in the render pass. The console is drawn there,
+        // so it takes the scene's draws.
+        let compute_target = self
+            .storage_views
+            .get(image_index as usize)
+            .filter(|_| scene.computes_frame() && !console.is_visible())
+            .cloned();
+        let blit_source = scene
+            .blit_source()
+            .filter(|_| compute_target.is_none() && self.blit_supported && !console.is_visible());
+        let drawn = compute_target.is_none() && blit_source.is_none() && stereo_frame.is_none();
+        let draws = if drawn {
+            views
+                .iter()
+                .map(|(view_frame, scissor)| {
+                    self.draw_cache
+                        .draws(subpass.clone(), scene, view_frame, *scissor)
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            Vec::new()
+        };
+        let minimap_draws = match &minimap_view {
+            Some((view_frame, area)) if drawn => {
+                let extent = self.extent();
+                let pixels_per_point = self.pixels_per_point();
+                let mut draws = vec![self.minimap.record_clear(
+                    ctx,
+                    self.command_pools.secondary_allocator(ctx, slot),
+                    subpass.clone(),
+                    *area,
+                )?];
+                draws.extend(
+                    self.draw_cache
+                        .draws(subpass.clone(), scene, view_frame, *area)?,
+                );
+                draws.extend(self.minimap.record_overlay(
+                    ctx,
+                    self.command_pools.secondary_allocator(ctx, slot),
+                    subpass.clone(),
+                    extent,
+                    *area,
+                    view_frame,
+                    self.camera.position(),
+                    self.camera.forward(),
+                    pixels_per_point,
+                )?);
+                draws
+            }
+            _ => Vec::new(),
+        };
+        let stereo_draws = match (&stereo_frame, &self.stereo_render_pass) {
+            (Some(eyes_frame), Some(stereo_render_pass)) => Some(self.draw_cache.stereo_draws(
+                Subpass::from(stereo_render_pass.clone(), 0).unwrap(),
+                scene,
+                eyes_frame,
+            )?),
+            _ => None,
+        };
+        // Drawn into the scene's target, so at the logical resolution when it is scaled.
+        let extent = self.extent();
+        let pixels_per_point = self.pixels_per_point();
+        // Only asking for an allocator when something is recorded keeps the pool stats exact.
+        let overlay = if console.is_visible() {
+            console.record(
+                ctx,
+                self.command_pools.secondary_allocator(ctx, slot),
+                subpass,
+                extent,
+                pixels_per_point,
+            )?
+        } else {
+            None
+        };
+
+        // Filled in by the screenshot pass, if it runs.
+        let mut screenshot = None;
+        let mut graph = FrameGraph::new();
+        let presented = graph.import("swapchain image", self.images[image_index as usize].clone());
+        let scene_target = match &self.scaled_target {
+            Some(target) => graph.transient("scene colour", target.color_desc()),
+            None => presented,
+        };
+        graph.add_pass(Pass::new("scene offscreen", |builder, _| {
+            for view_frame in views
+                .iter()
+                .chain(&minimap_view)
+                .map(|(view_frame, _)| view_frame)
+                .chain(&stereo_frame)
+            {
+                scene.draw_offscreen(builder, view_frame)?;
+            }
+            Ok(())
+        }));
+        let scaled_target = &mut self.scaled_target;
+        let swapchain_framebuffers = &self.framebuffers;
+        // The scaled target has the swapchain's format too.
+        let clear_color = self
+            .clear_color
+            .value(frame.time, self.swapchain.image_format());
+        graph.add_pass(
+            Pass::new("scene", move |builder, images| {
+                let framebuffer = match scaled_target {
+                    Some(target) => target.framebuffer(render_pass, images.image(scene_target))?,
+                    None => swapchain_framebuffers[image_index as usize].clone(),
+                };
+                builder.begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: clear_values(render_pass, clear_color),
+                        ..RenderPassBeginInfo::framebuffer(framebuffer)
+                    },
+                    SubpassBeginInfo {
+                        contents: SubpassContents::SecondaryCommandBuffers,
+                        ..Default::default()
+                    },
+                )?;
+                for draws in draws
+                    .into_iter()
+                    .flatten()
+                    .chain(minimap_draws)
+                    .chain(overlay)
+                {
+                    builder.execute_commands(draws)?;
+                }
+                builder.end_render_pass(SubpassEndInfo::default())?;
+                Ok(())
+            })
+            .writes(scene_target, ImageAccess::ColorAttachment)
+            .enabled(drawn),
+        );
+        let upscale_filter = self.upscale_filter;
+        graph.add_pass(
+            Pass::new("upscale", move |builder, images| {
+                record_upscale(
+                    builder,
+                    images.image(scene_target).clone(),
+                    images.image(presented).clone(),
+                    upscale_filter,
+                    clear_color,
+                )
+            })
+            .reads(scene_target, ImageAccess::TransferSrc)
+            .writes(presented, ImageAccess::TransferDst)
+            .enabled(scene_target != presented && drawn),
+        );
+        if let (Some(draws), Some(target)) = (stereo_draws, &self.stereo_target) {
+            let eyes = graph.import("stereo colour", target.color().clone());
+            let framebuffer = target.framebuffer().clone();
+            graph.add_pass(
+                Pass::new("stereo", move |builder, _| {
+                    builder.begin_render_pass(
+                        RenderPassBeginInfo {
+                            clear_values: clear_values(framebuffer.render_pass(), clear_color),
+                            ..RenderPassBeginInfo::framebuffer(framebuffer)
+                        },
+                        SubpassBeginInfo {
+                            contents: SubpassContents::SecondaryCommandBuffers,
+                            ..Default::default()
+                        },
+                    )?;
+                    builder.execute_commands(draws)?;
+                    builder.end_render_pass(SubpassEndInfo::default())?;
+                    Ok(())
+                })
+                .writes(eyes, ImageAccess::ColorAttachment),
+            );
+            graph.add_pass(
+                Pass::new("stereo composite", move |builder, images| {
+                    record_composite(
+                        builder,
+                        images.image(eyes).clone(),
+                        images.image(presented).clone(),
+                    )
+                })
+                .reads(eyes, ImageAccess::TransferSrc)
+                .writes(presented, ImageAccess::TransferDst),
+            );
+        }
+        if let Some(target) = compute_target {
+            graph.add_pass(
+                Pass::new("scene compute", move |builder, _| {
+                    scene.compute_frame(builder, target, clear_color)
+                })
+                .writes(presented, ImageAccess::Storage),
+            );
+        }
+        if let Some(source) = blit_source {
+            let source = graph.import("scene blit source", source);
+            graph.add_pass(
+                Pass::new("scene blit", move |builder, images| {
+                    record_upscale(
+                        builder,
+                        images.image(source).clone(),
+                        images.image(presented).clone(),
+                        UpscaleFilter::Nearest,
+                        clear_color,
+                    )
+                })
+                .reads(source, ImageAccess::TransferSrc)
+                .writes(presented, ImageAccess::TransferDst),
+            );
+        }
+        // Reads the swapchain image, so it comes after everything that draws into it and shows
+        // exactly what is presented.
+        let screenshot_path = self.screenshot.take();
+        let take_screenshot = screenshot_path.is_some();
+        graph.add_pass(
+            Pass::new("screenshot", |builder, images| {
+                screenshot = screenshot_path.and_then(|path| {
+                    PendingScreenshot::record(ctx, builder, images.image(presented).clone(), path)
+                        .inspect_err(|err| log::error!("{err}"))
+                        .ok()
+                });
+                Ok(())
+            })
+            .reads(presented, ImageAccess::TransferSrc)
+            .enabled(take_screenshot),
+        );
+        graph.execute(ctx, &mut self.transient_images, &mut builder)?;
+        let command_buffer = builder.build()?;
+        self.record_time = record_start.elapsed();
+
+        // Frames are submitted in order, so waiting on the previous one keeps the GPU from
+        // overlapping their use of the shared depth buffer.
+        let previous_slot = (slot + FRAMES_IN_FLIGHT - 1) % FRAMES_IN_FLIGHT;
+        let previous_frame_end = match self.frame_fences[previous_slot].clone() {
+            Some(fence) => fence.boxed_send_sync(),
+            None => sync::now(ctx.device.clone()).boxed_send_sync(),
+        };
+
+        // None of this waits on the CPU. Joining futures only gathers what the submission waits
+        // for: vulkano turns the acquire into a semaphore the render submission waits on, and
+        // signals another one from it for the present to wait on. The previous frame already
+        // went to the same queue, so it needs no semaphore at all. The only CPU waits are on the
+        // fences at the start of the frame, for frames as far back as the frames in flight go.
+        let future = previous_frame_end
+            .join(acquire_future)
+            .then_execute(ctx.queue.clone(), command_buffer)?
+            .then_swapchain_present(
+                ctx.queue.clone(),
+                SwapchainPresentInfo {
+                    present_id: self.pacer.next_present_id(),
+                    ..SwapchainPresentInfo::swapchain_image_index(
+                        self.swapchain.clone(),
+                        image_index,
+                    )
+                },
+            )
+            .boxed_send_sync()
+            .then_signal_fence_and_flush();
+
+        self.frame_count += 1;
+        match future.map_err(Validated::unwrap) {
+            Ok(future) => {
+                let fence = Arc::new(future);
+                self.frame_fences[slot] = Some(fence.clone());
+                self.pacer.presented();
+                // Screenshots are rare enough that stalling for this one frame is fine.
+                if let Some(screenshot) = screenshot {
+                    fence.wait(None)?;
+                    match screenshot.save() {
+                        Ok(path) => log::info!("Saved a screenshot to {}", path.display()),
+                        Err(err) => log::error!("{err}"),
+                    }
+                }
+            }
+            Err(VulkanError::OutOfDate) => {
+                self.recreate_swapchain = true;
+                self.frame_fences[slot] = None;
+            }
+            #[cfg(windows)]
+            Err(VulkanError::FullScreenExclusiveModeLost) => {
+                self.exclusive_fullscreen_lost();
+                self.frame_fences[slot] = None;
+            }
+            Err(e) => {
+                self.frame_fences[slot] = None;
+                return Err(Validated::Error(e).into());
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Lets go of the frames in flight without waiting for them, after the device was lost.
+    /// Dropping a fence waits on it, which fails and panics on a lost device, so they are leaked
+    /// instead; the memory goes with the device.
+    pub fn abandon_frames(&mut self) {
+        for fence in self.frame_fences.iter_mut().filter_map(Option::take) {
+            std::mem::forget(fence);
+        }
+    }
+
+    /// Forgets the draws recorded for the old scene, after the renderer swapped in another.
+    pub fn scene_replaced(&mut self) {
+        self.draw_cache.clear();
+    }
+
+    /// Falls back to borderless fullscreen after Windows took exclusivity away.
+    #[cfg(windows)]
+    fn exclusive_fullscreen_lost(&mut self) {
+        if let Some(exclusive) = &mut self.exclusive_fullscreen {
+            exclusive.mode_lost();
+        }
+        self.recreate_swapchain = true;
+    }
+
+    /// The views to draw this frame, with their frame data and the part of the target they cover:
+    /// the whole target, or its left and right halves when split or stereo. Each view gets the
+    /// aspect ratio of its own part.
+    fn views(&self, frame: &FrameData, slot: usize) -> Vec<(FrameData, Scissor)> {
+        let extent = self.extent();
+        let [left, right] = eye_areas(extent);
+        let areas = match &self.split_camera {
+            _ if self.stereo => {
+                let [left_view, right_view] = eye_views(self.camera.view_matrix(), EYE_SEPARATION);
+                vec![
+                    (left_view, &self.camera, left),
+                    (right_view, &self.camera, right),
+                ]
+            }
+            None => vec![(self.camera.view_matrix(), &self.camera, ([0, 0], extent))],
+            Some(split_camera) => vec![
+                (self.camera.view_matrix(), &self.camera, left),
+                (split_camera.view_matrix(), split_camera, right),
+            ],
+        };
+
+        areas
+            .into_iter()
+            .enumerate()
+            .map(|(view, (view_matrix, camera, (offset, extent)))| {
+                let view_frame = FrameData {
+                    view: view_matrix,
+                    projection: camera.projection(extent[0] as f32 / extent[1].max(1) as f32),
+                    extent,
+                    frame_in_flight: self.frame_in_flight(view, slot),
+                    ..*frame
+                };
+                (view_frame, Scissor { offset, extent })
+            })
+            .collect()
+    }
+
+    /// The minimap's view this frame and the part of the target it covers, while it is shown
+    /// and fits. Never drawn over stereo views, where it would only cover one eye.
+    fn minimap_view(&self, frame: &FrameData, slot: usize) -> Option<(FrameData, Scissor)> {
+        if self.stereo {
+            return None;
+        }
+        let area = self.minimap.area(self.extent(), self.pixels_per_point())?;
+        let camera = minimap_camera(self.camera.position());
+        let view_frame = FrameData {
+            view: camera.view_matrix(),
+            projection: camera.projection(area.extent[0] as f32 / area.extent[1] as f32),
+            extent: area.extent,
+            frame_in_flight: self.frame_in_flight(MINIMAP_VIEW, slot),
+            ..*frame
+        };
+        Some((view_frame, area))
+    }
+
+    /// Pixels of the scene's target per logical pixel: the monitor's scale factor, scaled along
+    /// with the scene when it is rendered at another resolution.
+    fn pixels_per_point(&self) -> f64 {
+        let window_height = self.swapchain.image_extent()[1].max(1);
+        self.scale_factor() * self.extent()[1] as f64 / window_height as f64
+    }
+
+    /// The frame data for drawing both eyes at once into layers of `extent`, in the first view's
+    /// slots, and the eyes' view matrices. `view` is the left eye's.
+    fn stereo_frame(
+        &self,
+        frame: &FrameData,
+        slot: usize,
+        extent: [u32; 2],
+    ) -> (FrameData, [Mat4; 2]) {
+        let views = eye_views(self.camera.view_matrix(), EYE_SEPARATION);
+        let eyes_frame = FrameData {
+            view: views[0],
+            projection: self
+                .camera
+                .projection(extent[0] as f32 / extent[1].max(1) as f32),
+            extent,
+            frame_in_flight: self.frame_in_flight(0, slot),
+            ..*frame
+        };
+        (eyes_frame, views)
+    }
+
+    /// Which of the per-frame resources the window's `view` uses in frame slot `slot`.
+    fn frame_in_flight(&self, view: usize, slot: usize) -> usize {
+        let view_slot = self.window_index * VIEWS_PER_WINDOW + view;
+        view_slot * FRAMES_IN_FLIGHT + slot
+    }
+
+    /// Makes sure the scene has something to draw into for the current swapchain and render
+    /// scale: the swapchain's own framebuffers, or a [`ScaledTarget`] at the logical resolution.
+    /// Both eyes are only drawn at once at the window's resolution, into a [`StereoTarget`].
+    fn create_targets(
+        &mut self,
+        ctx: &VulkanContext,
+        render_pass: &Arc<RenderPass>,
+    ) -> Result<(), RendererError> {
+        let window_extent = self.swapchain.image_extent();
+        let logical_extent = self.render_scale.logical_extent(window_extent);
+        // New targets mean new views, so nothing recorded for the old ones is of use.
+        self.draw_cache.clear();
+
+        if logical_extent == window_extent || !self.upscale_supported {
+            self.scaled_target = None;
+            if self.framebuffers.is_empty() {
+                self.framebuffers = create_framebuffers(ctx, render_pass, &self.images)?;
+            }
+            if let Some(stereo_render_pass) = &self.stereo_render_pass {
+                // The blit stretches the left eye by a pixel on odd widths.
+                let eye_extent = eye_areas(window_extent)[1].1;
+                if self
+                    .stereo_target
+                    .as_ref()
+                    .is_none_or(|target| target.extent() != eye_extent)
+                {
+                    self.stereo_target =
+                        Some(StereoTarget::new(ctx, stereo_render_pass, eye_extent)?);
+                }
+            }
+        } else {
+            self.framebuffers.clear();
+            self.stereo_target = None;
+            if self
+                .scaled_target
+                .as_ref()
+                .is_none_or(|target| target.extent() != logical_extent)
+            {
+                self.scaled_target = Some(ScaledTarget::new(ctx, render_pass, logical_extent)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WindowContext {
+    fn drop(&mut self) {
+        // The frame fences only cover rendering, not presentation, so wait for the whole queue.
+        // There's nothing useful to do about an error this late.
+        if let Err(err) = self.queue.with(|mut queue| queue.wait_idle()) {
+            log::error!("Failed to wait for the GPU before closing a window: {err}");
+        }
+        crash_report::clear_swapchain(self.window_index);
+    }
+}
+
+fn create_framebuffers(
+    ctx: &VulkanContext,
+    render_pass: &Arc<RenderPass>,
+    images: &[Arc<Image>],
+) -> Result<Vec<Arc<Framebuffer>>, RendererError> {
+    let extent = images[0].extent();
+    let shared = SharedAttachments::new(ctx, render_pass, [extent[0], extent[1]], "swapchain")?;
+    images
+        .iter()
+        .map(|image| {
+            create_framebuffer(
+                render_pass.clone(),
+                ImageView::new_default(image.clone())?,
+                &shared,
+            )
+        })
+        .collect()
+}
+
+/// A storage view of each of `images` if `swapchain` made them storage images, otherwise none.
+fn create_storage_views(
+    swapchain: &Swapchain,
+    images: &[Arc<Image>],
+) -> Result<Vec<Arc<ImageView>>, RendererError> {
+    if !swapchain.image_usage().intersects(ImageUsage::STORAGE) {
+        return Ok(Vec::new());
+    }
+    images
+        .iter()
+        .map(|image| Ok(ImageView::new_default(image.clone())?))
+        .collect()
+}
+
+/// Names a window's swapchain and its images, for telling windows apart in captures.
+fn name_swapchain(
+    ctx: &VulkanContext,
+    window_index: usize,
+    swapchain: &Arc<Swapchain>,
+    images: &[Arc<Image>],
+) {
+    if !ctx.debug_names_enabled() {
+        return;
+    }
+    ctx.name_object(swapchain, &format!("window {window_index} swapchain"));
+    for (index, image) in images.iter().enumerate() {
+        ctx.name_object(
+            image,
+            &format!("window {window_index} swapchain image {index}"),
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Renders the built-in scenes offscreen and compares them against reference images.
+//!
+//! The references live in `tests/golden/`. Set `UPDATE_GOLDEN=1` to overwrite them with what the
+//! current machine renders. On a mismatch the rendered image and a diff image are written to
+//! `target/golden-failures/` so the failure can be inspected.
+//!
+//! When no Vulkan implementation or device is available the tests print a message and pass, so
+//! `cargo test` stays usable on machines without a GPU.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use hi_vulkanos::context::VulkanContext;
+use hi_vulkanos::device_selection::DeviceSelection;
+use hi_vulkanos::offscreen::OffscreenTarget;
+use hi_vulkanos::scene::{FrameData, SceneKind};
+
+const EXTENT: [u32; 2] = [256, 256];
+
+/// Maximum difference allowed per colour channel before a pixel counts as different.
+const CHANNEL_TOLERANCE: u8 = 3;
+
+/// Number of pixels allowed to differ, to absorb rasterization differences along edges between
+/// drivers.
+const MAX_DIFFERING_PIXELS: usize = 256;
+
+fn context() -> Option<VulkanContext> {
+    match VulkanContext::headless(&DeviceSelection::default(), None) {
+        Ok(ctx) => Some(ctx),
+        Err(err) if err.is_unavailable() => {
+            eprintln!("skipping golden image test: {err}");
+            None
+        }
+        Err(err) => panic!("failed to create a headless Vulkan context: {err}"),
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"))
+}
+
+fn failure_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden-failures")
+}
+
+fn read_png(path: &Path) -> ([u32; 2], Vec<u8>) {
+    let decoder = png::Decoder::new(File::open(path).unwrap_or_else(|err| {
+        panic!(
+            "missing reference image {}: {err} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display(),
+        )
+    }));
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "reference images must be 8-bit RGBA",
+    );
+    pixels.truncate(info.buffer_size());
+    ([info.width, info.height], pixels)
+}
+
+fn write_png(path: &Path, extent: [u32; 2], pixels: &[u8]) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path).unwrap()),
+        extent[0],
+        extent[1],
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(pixels)
+        .unwrap();
+}
+
+/// Compares two RGBA8 images. Returns the number of pixels outside the tolerance along with a
+/// diff image where differing pixels are magenta and matching ones are a dimmed copy of the
+/// expected image.
+fn compare(expected: &[u8], actual: &[u8]) -> (usize, Vec<u8>) {
+    let mut differing = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+    for (e, a) in expected.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        let max_delta = e.iter().zip(a).map(|(e, a)| e.abs_diff(*a)).max().unwrap();
+        if max_delta > CHANNEL_TOLERANCE {
+            differing += 1;
+            diff.extend_from_slice(&[255, 0, 255, 255]);
+        } else {
+            diff.extend_from_slice(&[e[0] / 4, e[1] / 4, e[2] / 4, 255]);
+        }
+    }
+    (differing, diff)
+}
+
+fn check_scene(kind: SceneKind) {
+    let Some(ctx) = context() else {
+        return;
+    };
+
+    let target = OffscreenTarget::new(&ctx, EXTENT).unwrap();
+    let mut scene = kind.build(&ctx, target.subpass()).unwrap();
+    let actual = target
+        .render(&ctx, scene.as_mut(), &FrameData::default())
+        .unwrap();
+
+    let name = kind.name();
+    let reference = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&reference, EXTENT, &actual);
+        eprintln!("updated {}", reference.display());
+        return;
+    }
+
+    let (extent, expected) = read_png(&reference);
+    assert_eq!(extent, EXTENT, "reference {name} has the wrong size");
+
+    let (differing, diff) = compare(&expected, &actual);
+    if differing > MAX_DIFFERING_PIXELS {
+        let actual_path = failure_dir().join(format!("{name}.actual.png"));
+        let diff_path = failure_dir().join(format!("{name}.diff.png"));
+        write_png(&actual_path, EXTENT, &actual);
+        write_png(&diff_path, EXTENT, &diff);
+        panic!(
+            "{name}: {differing} pixels differ from the reference by more than \
+             {CHANNEL_TOLERANCE} (budget {MAX_DIFFERING_PIXELS}); rendered image written to {} \
+             and diff to {}",
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+#[test]
+fn triangle_matches_reference() {
+    check_scene(SceneKind::Triangle);
+}
+
+#[test]
+fn textured_quad_matches_reference() {
+    check_scene(SceneKind::TexturedQuad);
+}